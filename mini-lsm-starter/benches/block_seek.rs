@@ -0,0 +1,99 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark of `BlockIterator::seek_to_key`, which binary-searches over the restart points of a
+//! block and then decodes the entries after the restart point, against a linear walk from the
+//! first entry. Prints the average time of a seek for each block size and restart interval: as the
+//! block grows 4 times, the binary search takes about two more steps while the linear walk takes 4
+//! times as long.
+//!
+//! Run with `cargo bench -p mini-lsm-starter --bench block_seek`.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use mini_lsm_starter::block::{Block, BlockBuilder, BlockIterator};
+use mini_lsm_starter::key::{KeySlice, TS_DEFAULT};
+
+/// The number of seeks of each measurement.
+const NUM_SEEKS: usize = 200000;
+const VALUE_SIZE: usize = 16;
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:010}", idx).into_bytes()
+}
+
+/// Build a block of `block_size` bytes, returning it with its number of entries.
+fn build_block(block_size: usize, restart_interval: usize) -> (Block, usize) {
+    let mut builder = BlockBuilder::new(block_size).with_restart_interval(restart_interval);
+    let value = vec![b'v'; VALUE_SIZE];
+    let mut num_entries = 0;
+    while builder.add(
+        KeySlice::from_slice(&key_of(num_entries), TS_DEFAULT),
+        &value,
+    ) {
+        num_entries += 1;
+    }
+    (builder.build(), num_entries)
+}
+
+fn seek_linear(block: Arc<Block>, key: KeySlice) -> BlockIterator {
+    let mut iter = BlockIterator::create_and_seek_to_first(block);
+    while iter.is_valid() && iter.key() < key {
+        iter.next();
+    }
+    iter
+}
+
+fn measure(
+    block: &Arc<Block>,
+    num_entries: usize,
+    seek: impl Fn(Arc<Block>, KeySlice) -> BlockIterator,
+) -> Duration {
+    let mut rng = StdRng::seed_from_u64(0);
+    let keys = (0..NUM_SEEKS)
+        .map(|_| key_of(rng.gen_range(0..num_entries)))
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    for key in &keys {
+        let iter = seek(block.clone(), KeySlice::from_slice(key, TS_DEFAULT));
+        assert_eq!(black_box(iter.key().key_ref()), key);
+    }
+    start.elapsed() / NUM_SEEKS as u32
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>16} {:>8} {:>12} {:>12}",
+        "block_size", "restart_interval", "entries", "binary", "linear"
+    );
+    for block_size in [1 << 10, 4 << 10, 16 << 10, 64 << 10] {
+        // The maximum block size, as the offsets in a block are 16-bit
+        let block_size = block_size.min(u16::MAX as usize);
+        for restart_interval in [1, 16] {
+            let (block, num_entries) = build_block(block_size, restart_interval);
+            let block = Arc::new(block);
+            let binary = measure(&block, num_entries, BlockIterator::create_and_seek_to_key);
+            let linear = measure(&block, num_entries, seek_linear);
+            println!(
+                "{:>10} {:>16} {:>8} {:>12?} {:>12?}",
+                block_size, restart_interval, num_entries, binary, linear
+            );
+        }
+    }
+}
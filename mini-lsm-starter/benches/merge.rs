@@ -0,0 +1,116 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark of `MergeIterator::next` over 2, 8 and 64 sorted runs with interleaved keys, some of
+//! which occur in several runs. Prints the average time of a `next` for each number of runs: the
+//! loser tree replays one match per level of the tree, so the time grows with `log2` of the
+//! number of runs.
+//!
+//! Run with `cargo bench -p mini-lsm-starter --bench merge`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use mini_lsm_starter::iterators::StorageIterator;
+use mini_lsm_starter::iterators::merge_iterator::MergeIterator;
+use mini_lsm_starter::key::{KeySlice, TS_DEFAULT};
+
+/// The number of keys merged by each measurement.
+const NUM_KEYS: usize = 1 << 20;
+const NUM_ROUNDS: usize = 5;
+
+/// A sorted run held in memory, so that the benchmark measures the merge and not the reads.
+struct VecIterator {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    index: usize,
+}
+
+impl StorageIterator for VecIterator {
+    type KeyType<'a> = KeySlice<'a>;
+
+    fn key(&self) -> KeySlice<'_> {
+        KeySlice::from_slice(&self.entries[self.index].0, TS_DEFAULT)
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.entries[self.index].1
+    }
+
+    fn is_valid(&self) -> bool {
+        self.index < self.entries.len()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+}
+
+/// Spread `NUM_KEYS` keys over `num_runs` runs, putting one in 16 keys into two runs.
+fn build_runs(num_runs: usize, rng: &mut StdRng) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut runs = vec![Vec::new(); num_runs];
+    for idx in 0..NUM_KEYS {
+        let key = format!("key_{:010}", idx).into_bytes();
+        let run = rng.gen_range(0..num_runs);
+        if rng.gen_range(0..16) == 0 {
+            let other = rng.gen_range(0..num_runs);
+            if other != run {
+                runs[other].push((key.clone(), b"old".to_vec()));
+            }
+        }
+        runs[run].push((key, b"value".to_vec()));
+    }
+    runs
+}
+
+fn measure(runs: &[Vec<(Vec<u8>, Vec<u8>)>]) -> (Duration, usize) {
+    let iters = runs
+        .iter()
+        .map(|entries| {
+            Box::new(VecIterator {
+                entries: entries.clone(),
+                index: 0,
+            })
+        })
+        .collect();
+    let start = Instant::now();
+    let mut iter = MergeIterator::create(iters);
+    let mut count = 0;
+    while iter.is_valid() {
+        black_box(iter.key());
+        count += 1;
+        iter.next().unwrap();
+    }
+    (start.elapsed(), count)
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    println!("{:>6} {:>10} {:>12}", "runs", "keys", "per next");
+    for num_runs in [2, 8, 64] {
+        let runs = build_runs(num_runs, &mut rng);
+        let (elapsed, count) = (0..NUM_ROUNDS).map(|_| measure(&runs)).min().unwrap();
+        assert_eq!(count, NUM_KEYS);
+        println!(
+            "{:>6} {:>10} {:>12?}",
+            num_runs,
+            count,
+            elapsed / count as u32
+        );
+    }
+}
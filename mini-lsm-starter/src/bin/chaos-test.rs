@@ -0,0 +1,258 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Crash-recovery chaos test. Each iteration runs a seeded random workload in a child process,
+//! which aborts at a random point or exits without closing the storage. The parent then reopens
+//! the storage and checks every key against a model of the acknowledged writes: a key must have
+//! the value it had at the last completed flush, or a value written after it. Flushes are the
+//! durability points, as a flushed SST is synced together with its manifest record.
+//!
+//! A failure prints the seed and iteration, and `--seed` reruns the same workloads.
+
+mod wrapper;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wrapper::mini_lsm_wrapper;
+
+use mini_lsm_wrapper::compact::CompactionOptions;
+use mini_lsm_wrapper::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "chaos.db")]
+    path: PathBuf,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[arg(long, default_value_t = 20)]
+    iterations: u64,
+    /// The maximum number of operations of each iteration.
+    #[arg(long, default_value_t = 2000)]
+    ops: usize,
+    #[arg(long, default_value_t = 200)]
+    keys: usize,
+    #[arg(long)]
+    enable_wal: bool,
+    /// Run the workload of the given iteration in this process, see `run_child`.
+    #[arg(long, hide = true)]
+    child: Option<u64>,
+}
+
+fn options(args: &Args) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = args.enable_wal;
+    // Freeze and flush memtables in the background too
+    options.target_sst_size = 16 << 10;
+    options.num_memtable_limit = 2;
+    options
+}
+
+fn key_of(idx: usize) -> String {
+    format!("key_{:05}", idx)
+}
+
+fn log_path(args: &Args) -> PathBuf {
+    args.path.with_extension("log")
+}
+
+/// The log of a child, written without buffering so that it survives an abort. Each write is
+/// logged as `issue <key> <value>`, with `-` for a delete, before it is sent to the storage, and
+/// followed by `ack` once the storage returns. `flushed` is logged after a completed flush.
+struct OpLog(File);
+
+impl OpLog {
+    fn record(&mut self, line: &str) -> Result<()> {
+        self.0.write_all(format!("{}\n", line).as_bytes())?;
+        Ok(())
+    }
+}
+
+fn run_child(args: &Args, iteration: u64) -> Result<()> {
+    let mut rng = StdRng::seed_from_u64(args.seed ^ iteration.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let mut log = OpLog(File::create(log_path(args))?);
+    let storage = MiniLsm::open(&args.path, options(args))?;
+    let num_ops = rng.gen_range(1..=args.ops);
+    // Abort in the middle of an operation, or drop the storage without closing it at the end
+    let abort_at = rng.gen_bool(0.75).then(|| rng.gen_range(0..num_ops));
+    for op in 0..num_ops {
+        let key = key_of(rng.gen_range(0..args.keys));
+        let action = rng.gen_range(0..100);
+        let aborting = abort_at == Some(op);
+        if action < 5 {
+            if aborting {
+                // The flush thread may be running, so this aborts at a random point of it
+                std::process::abort();
+            }
+            storage.force_flush_all()?;
+            log.record("flushed")?;
+            continue;
+        }
+        let value = (action >= 20).then(|| format!("value_{}_{}", iteration, op));
+        log.record(&format!(
+            "issue {} {}",
+            key,
+            value.as_deref().unwrap_or("-")
+        ))?;
+        if aborting {
+            std::process::abort();
+        }
+        match &value {
+            Some(value) => storage.put(key.as_bytes(), value.as_bytes())?,
+            None => storage.delete(key.as_bytes())?,
+        }
+        log.record("ack")?;
+    }
+    drop(storage);
+    Ok(())
+}
+
+/// The values a key may have after recovery.
+#[derive(Default)]
+struct Allowed {
+    durable: Option<String>,
+    later: Vec<Option<String>>,
+}
+
+/// Replay the log of an iteration on top of the state recovered before it.
+fn allowed_values(
+    log_path: &Path,
+    recovered: &BTreeMap<String, String>,
+) -> Result<HashMap<String, Allowed>> {
+    let mut acked = recovered.clone();
+    let mut allowed = HashMap::<String, Allowed>::new();
+    for (key, value) in recovered {
+        allowed.entry(key.clone()).or_default().durable = Some(value.clone());
+    }
+    let log = std::fs::read_to_string(log_path)?;
+    let mut issued: Option<(String, Option<String>)> = None;
+    for line in log.lines() {
+        let parts = line.split(' ').collect::<Vec<_>>();
+        match parts.as_slice() {
+            ["issue", key, value] => {
+                let value = (*value != "-").then(|| value.to_string());
+                allowed
+                    .entry(key.to_string())
+                    .or_default()
+                    .later
+                    .push(value.clone());
+                issued = Some((key.to_string(), value));
+            }
+            ["ack"] => {
+                let Some((key, value)) = issued.take() else {
+                    bail!("ack without a write in the log");
+                };
+                match value {
+                    Some(value) => acked.insert(key, value),
+                    None => acked.remove(&key),
+                };
+            }
+            ["flushed"] => {
+                for (key, allowed) in allowed.iter_mut() {
+                    allowed.durable = acked.get(key).cloned();
+                    allowed.later.clear();
+                }
+                for (key, value) in &acked {
+                    allowed.entry(key.clone()).or_default().durable = Some(value.clone());
+                }
+            }
+            _ => bail!("invalid log line {:?}", line),
+        }
+    }
+    Ok(allowed)
+}
+
+fn run(args: &Args) -> Result<()> {
+    if args.path.exists() {
+        std::fs::remove_dir_all(&args.path)?;
+    }
+    let exe = std::env::current_exe()?;
+    let mut recovered = BTreeMap::new();
+    for iteration in 0..args.iterations {
+        let status = Command::new(&exe)
+            .args(std::env::args_os().skip(1))
+            .arg("--child")
+            .arg(iteration.to_string())
+            .status()?;
+        // An abort is expected, any other failure is not
+        #[cfg(unix)]
+        let aborted = std::os::unix::process::ExitStatusExt::signal(&status) == Some(libc::SIGABRT);
+        #[cfg(not(unix))]
+        let aborted = false;
+        if !status.success() && !aborted {
+            bail!("iteration {} failed: {}", iteration, status);
+        }
+
+        let allowed = allowed_values(&log_path(args), &recovered)?;
+        let storage = MiniLsm::open(&args.path, options(args))?;
+        recovered.clear();
+        for idx in 0..args.keys {
+            let key = key_of(idx);
+            let value = storage
+                .get(key.as_bytes())?
+                .map(|value| String::from_utf8_lossy(&value).into_owned());
+            let allowed = allowed.get(&key);
+            let ok = match allowed {
+                Some(allowed) => allowed.durable == value || allowed.later.contains(&value),
+                None => value.is_none(),
+            };
+            if !ok {
+                let (durable, later) = allowed
+                    .map(|allowed| (allowed.durable.clone(), allowed.later.clone()))
+                    .unwrap_or_default();
+                bail!(
+                    "seed {} iteration {}: {} recovered as {:?}, but it was {:?} at the last flush \
+                     and written with {:?} after it",
+                    args.seed,
+                    iteration,
+                    key,
+                    value,
+                    durable,
+                    later
+                );
+            }
+            if let Some(value) = value {
+                recovered.insert(key, value);
+            }
+        }
+        drop(storage);
+        println!(
+            "iteration {}: {} keys recovered",
+            iteration,
+            recovered.len()
+        );
+    }
+    println!(
+        "{} iterations passed with seed {}",
+        args.iterations, args.seed
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    match args.child {
+        Some(iteration) => run_child(&args, iteration)
+            .with_context(|| format!("iteration {} with seed {}", iteration, args.seed)),
+        None => run(&args),
+    }
+}
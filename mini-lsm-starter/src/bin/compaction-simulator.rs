@@ -28,6 +28,8 @@ use mini_lsm_wrapper::key::KeyBytes;
 use mini_lsm_wrapper::lsm_storage::LsmStorageState;
 use mini_lsm_wrapper::mem_table::MemTable;
 use mini_lsm_wrapper::table::SsTable;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -102,6 +104,11 @@ enum Args {
         iterations: usize,
         #[clap(long, default_value = "32")]
         sst_size_mb: usize,
+        /// The seed of the random key ranges of the flushed SSTs. The compaction controllers have
+        /// no randomness of their own, so a run with the same seed and options produces the same
+        /// level layouts. A random seed is used and printed if not set.
+        #[clap(long)]
+        seed: Option<u64>,
     },
 }
 
@@ -236,9 +243,7 @@ impl MockStorage {
     }
 }
 
-fn generate_random_key_range() -> (KeyBytes, KeyBytes) {
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
+fn generate_random_key_range(rng: &mut StdRng) -> (KeyBytes, KeyBytes) {
     let begin: usize = rng.gen_range(0..(1 << 31));
     let end: usize = begin + rng.gen_range((1 << 10)..(1 << 31));
     let mut begin_bytes = BytesMut::new();
@@ -499,7 +504,11 @@ fn main() {
             base_level_size_mb,
             iterations,
             sst_size_mb,
+            seed,
         } => {
+            let seed = seed.unwrap_or_else(|| rand::thread_rng().r#gen());
+            println!("Seed: {seed}");
+            let mut rng = StdRng::seed_from_u64(seed);
             let controller = LeveledCompactionController::new(LeveledCompactionOptions {
                 level0_file_num_compaction_trigger,
                 level_size_multiplier,
@@ -515,7 +524,7 @@ fn main() {
             for i in 0..iterations {
                 println!("=== Iteration {i} ===");
                 let id = storage.flush_sst_to_l0();
-                let (first_key, last_key) = generate_random_key_range();
+                let (first_key, last_key) = generate_random_key_range(&mut rng);
                 storage.snapshot.sstables.insert(
                     id,
                     Arc::new(SsTable::create_meta_only(
@@ -13,70 +13,475 @@
 // limitations under the License.
 
 mod builder;
+mod compression;
 mod iterator;
 
-use crate::key::KeyVec;
-pub use builder::BlockBuilder;
+use std::fmt;
+
+use crate::key::{KeySlice, KeyVec, TS_DEFAULT};
+use anyhow::Result;
+pub use builder::{BlockBuilder, DEFAULT_RESTART_INTERVAL};
 use bytes::{Buf, BufMut, Bytes};
+pub use compression::CompressionType;
 pub use iterator::BlockIterator;
 
+/// Set in the 16-bit overlap length of an entry in a block with fixed-size lengths if the value
+/// is followed by a user metadata byte.
+pub(crate) const HAS_META_FLAG: u16 = 1 << 15;
+
+/// Set in the 16-bit key length of an entry in a block with fixed-size lengths whose value is
+/// empty but which is not a delete. Entries with an empty value and without this flag are deletes.
+pub(crate) const EMPTY_VALUE_FLAG: u16 = 1 << 15;
+
+/// Set in the number of restarts of a block whose lengths are varints, which blocks of SSTs
+/// before format version 5 lack.
+const VARINT_LENGTHS_FLAG: u16 = 1 << 15;
+
+/// Set in the number of restarts of a block whose keys are followed by their timestamps, which
+/// blocks of SSTs before format version 9 lack.
+const TIMESTAMPS_FLAG: u16 = 1 << 14;
+
+/// Append `value` as a LEB128 varint.
+pub(crate) fn put_varint(buf: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buf.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+/// Read a LEB128 varint.
+pub(crate) fn get_varint(buf: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf.get_u8();
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// The size of `value` as a varint.
+pub(crate) fn varint_len(value: usize) -> usize {
+    (usize::BITS - (value | 1).leading_zeros()).div_ceil(7) as usize
+}
+
+/// The layout of the entries of a block, which changed with the format versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EntryLayout {
+    /// Whether the lengths of the entries are 16-bit integers rather than varints, as in the
+    /// blocks of SSTs before format version 5.
+    pub(crate) fixed_lengths: bool,
+    /// Whether each key is followed by its timestamp, which the blocks of SSTs before format
+    /// version 9 lack. Their keys have timestamp `TS_DEFAULT`.
+    pub(crate) timestamps: bool,
+}
+
+impl EntryLayout {
+    /// The layout of the blocks built by this version.
+    pub(crate) const CURRENT: Self = Self {
+        fixed_lengths: false,
+        timestamps: true,
+    };
+}
+
+/// Append the encoding of a block with the given data and restart offsets, the latter as encoded
+/// 16-bit integers, see `Block::encode`.
+fn encode_into(data: &[u8], offsets: &[u8], layout: EntryLayout, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(data);
+    out.extend_from_slice(offsets);
+    let mut flags = 0;
+    if !layout.fixed_lengths {
+        flags |= VARINT_LENGTHS_FLAG;
+    }
+    if layout.timestamps {
+        flags |= TIMESTAMPS_FLAG;
+    }
+    out.put_u16((offsets.len() / 2) as u16 | flags);
+    let checksum = crc32fast::hash(&out[start..]);
+    out.put_u32(checksum);
+}
+
+/// Append the encoding of a block followed by its codec flag, see `Block::encode_compressed`. An
+/// uncompressed block is encoded in place, without a temporary buffer.
+pub(crate) fn encode_compressed_into(
+    data: &[u8],
+    offsets: &[u8],
+    layout: EntryLayout,
+    compression: CompressionType,
+    out: &mut Vec<u8>,
+) {
+    let start = out.len();
+    encode_into(data, offsets, layout, out);
+    let flag = match compression.compress(&out[start..]) {
+        Some(compressed) => {
+            out.truncate(start);
+            out.extend_from_slice(&compressed);
+            compression.flag()
+        }
+        None => CompressionType::None.flag(),
+    };
+    out.put_u8(flag);
+}
+
+/// An entry as stored in a block, with the suffix of its key after the overlap.
+pub(crate) struct RawEntry<'a> {
+    pub(crate) overlap: usize,
+    pub(crate) key_suffix: &'a [u8],
+    pub(crate) ts: u64,
+    /// The range of the value in the block data.
+    pub(crate) value_range: (usize, usize),
+    pub(crate) is_deleted: bool,
+    pub(crate) meta: u8,
+    /// The offset of the next entry in the block data.
+    pub(crate) next_offset: usize,
+}
+
+/// The size of the CRC32 checksum at the end of an encoded block.
+pub(crate) const CHECKSUM_SIZE: usize = 4;
+
+/// How `Block::try_decode` treats the checksum at the end of an encoded block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Fail if the checksum does not match the block.
+    Verify,
+    /// Strip the checksum without computing it.
+    Skip,
+    /// The block has no checksum, as its SST predates them (format version 1).
+    Absent,
+}
+
+/// The error returned when the checksum stored with a block does not match its content, e.g.,
+/// as the file was corrupted on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumError {
+    pub stored: u32,
+    pub computed: u32,
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block checksum mismatch: stored {:#010x}, computed {:#010x}",
+            self.stored, self.computed
+        )
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// Returns true if the error is caused by a block whose checksum does not match.
+pub fn is_checksum_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ChecksumError>().is_some()
+}
+
+/// The error returned when an encoded block is malformed, e.g., as it was corrupted on disk
+/// without a checksum to catch it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptionError {
+    pub reason: String,
+}
+
+impl CorruptionError {
+    fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "corrupted block: {}", self.reason)
+    }
+}
+
+impl std::error::Error for CorruptionError {}
+
+/// Returns true if the error is caused by a malformed block.
+pub fn is_corruption_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<CorruptionError>().is_some()
+}
+
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted key-value pairs.
+///
+/// The data and the offsets are views into the buffer the block was decoded from, or built in.
 pub struct Block {
-    pub(crate) data: Vec<u8>,
-    pub(crate) offsets: Vec<u16>,
+    pub(crate) data: Bytes,
+    /// The offsets of the restart points, as big-endian 16-bit integers.
+    pub(crate) offsets: Bytes,
+    pub(crate) layout: EntryLayout,
 }
 
 impl Block {
     /// Encode the internal data to the data layout illustrated in the course
     /// Note: You may want to recheck if any of the expected field is missing from your output
     /*
-    ----------------------------------------------------------------------------------------------------
-    |             Data Section             |              Offset Section             |      Extra      |
-    ----------------------------------------------------------------------------------------------------
-    | Entry #1 | Entry #2 | ... | Entry #N | Offset #1 | Offset #2 | ... | Offset #N | num_of_elements |
-    ----------------------------------------------------------------------------------------------------
-
-    -----------------------------------------------------------------------
-    |                           Entry #1                            | ... |
-    -----------------------------------------------------------------------
-    | key_len (2B) | key (keylen) | value_len (2B) | value (varlen) | ... |
-    -----------------------------------------------------------------------
+    -----------------------------------------------------------------------------------------------------------------
+    |             Data Section             |              Offset Section             |            Extra             |
+    -----------------------------------------------------------------------------------------------------------------
+    | Entry #1 | Entry #2 | ... | Entry #N | Restart #1 | Restart #2 | ... | Restart #R | num_of_restarts | crc32 (4B) |
+    -----------------------------------------------------------------------------------------------------------------
+
+    ----------------------------------------------------------------------------------------------------------------------
+    |                                   Entry #1                                                                   | ... |
+    ----------------------------------------------------------------------------------------------------------------------
+    | overlap (varint) | key_len (varint) | key (keylen) | ts (8B) | value_len (varint) | value (varlen) | meta (1B) | ... |
+    ----------------------------------------------------------------------------------------------------------------------
+
+    Every entry stores its key as the suffix after its overlap with the previous key, except the
+    entries at the restart points, which store the full key and whose offsets are listed after the
+    data. Blocks of SSTs before format version 3 list every entry as a restart point, and the
+    overlap of those entries is with the first key of the block. The overlap and the key length
+    only cover the user key, its timestamp follows it in full.
+
+    The meta byte is only present if it is not 0, which is flagged by the lowest bit of the overlap
+    length before the key. The lowest bit of the key length tells an empty value from a delete,
+    both lengths being shifted left by one to make room for these flags. The CRC32 checksum covers
+    everything before it.
+
+    Blocks of SSTs before format version 5 store the lengths as 16-bit integers instead, with the
+    flags in their top bits, and the top bit of `num_of_restarts` is set for the blocks with
+    varint lengths. Blocks of SSTs before format version 9 have no timestamps, and the second
+    highest bit of `num_of_restarts` is set for the blocks with timestamps.
 
     -------------------------------
-    |offset|offset|num_of_elements|
+    |offset|offset|num_of_restarts|
     -------------------------------
     |   0  |  12  |       2       |
     -------------------------------
     */
     pub fn encode(&self) -> Bytes {
-        let mut encoded_data = self.data.clone();
-        let num_of_elements = self.offsets.len();
-        for offset in &self.offsets {
-            encoded_data.put_u16(*offset);
-        }
-        encoded_data.put_u16(num_of_elements as u16);
+        let mut encoded_data = Vec::with_capacity(self.data.len() + self.offsets.len() + 6);
+        encode_into(&self.data, &self.offsets, self.layout, &mut encoded_data);
         Bytes::from(encoded_data)
     }
 
-    /// Decode from the data layout, transform the input `data` to a single `Block`
+    /// Encode the block followed by a one-byte codec flag, which is the layout of the blocks in
+    /// SSTs since format version 4. The encoding is compressed with `compression`, unless that
+    /// does not make it smaller.
+    pub fn encode_compressed(&self, compression: CompressionType) -> Bytes {
+        let mut encoded = Vec::new();
+        encode_compressed_into(
+            &self.data,
+            &self.offsets,
+            self.layout,
+            compression,
+            &mut encoded,
+        );
+        Bytes::from(encoded)
+    }
+
+    /// Decode a block encoded with `encode_compressed`, checking its checksum according to `mode`.
+    /// An uncompressed block keeps referencing `data`.
+    pub fn decode_compressed(data: Bytes, mode: ChecksumMode) -> Result<Self> {
+        let Some(&flag) = data.last() else {
+            return Err(CorruptionError::new("block is empty").into());
+        };
+        let data = data.slice(..data.len() - 1);
+        if flag == CompressionType::None.flag() {
+            return Self::try_decode(data, mode);
+        }
+        let data = CompressionType::decompress(flag, &data)
+            .map_err(|e| CorruptionError::new(format!("{:#}", e)))?;
+        Self::try_decode(Bytes::from(data), mode)
+    }
+
+    /// Decode from the data layout, transform the input `data` to a single `Block`. Panics if the
+    /// checksum does not match or the block is malformed, use `try_decode` for data read from the
+    /// disk, which does not copy it.
     pub fn decode(data: &[u8]) -> Self {
-        let num_of_elements = (&data[data.len() - 2..]).get_u16() as usize;
-        let data_end = data.len() - 2 - num_of_elements * 2;
+        Self::try_decode(Bytes::copy_from_slice(data), ChecksumMode::Verify).unwrap()
+    }
 
-        Self {
-            data: data[..data_end].to_vec(),
-            offsets: data[data_end..data.len() - 2]
-                .chunks_exact(2)
-                .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
-                .collect::<Vec<u16>>(),
+    /// Decode a block, checking its checksum according to `mode`. The block keeps referencing
+    /// `data` instead of copying it. Fails with a `ChecksumError` if the checksum does not match,
+    /// or with a `CorruptionError` if the restart points do not fit the block.
+    pub fn try_decode(data: Bytes, mode: ChecksumMode) -> Result<Self> {
+        let data = match mode {
+            ChecksumMode::Absent => data,
+            _ if data.len() < CHECKSUM_SIZE => {
+                return Err(ChecksumError {
+                    stored: 0,
+                    computed: crc32fast::hash(&data),
+                }
+                .into());
+            }
+            ChecksumMode::Skip => data.slice(..data.len() - CHECKSUM_SIZE),
+            ChecksumMode::Verify => {
+                let checksum_offset = data.len() - CHECKSUM_SIZE;
+                let stored = (&data[checksum_offset..]).get_u32();
+                let computed = crc32fast::hash(&data[..checksum_offset]);
+                if stored != computed {
+                    return Err(ChecksumError { stored, computed }.into());
+                }
+                data.slice(..checksum_offset)
+            }
+        };
+        Ok(Self::decode_layout(data)?)
+    }
+
+    /// Split a block without its checksum into its data and restart offsets, checking that the
+    /// offsets fit the block and start its entries in order.
+    fn decode_layout(data: Bytes) -> Result<Self, CorruptionError> {
+        if data.len() < 2 {
+            return Err(CorruptionError::new(format!(
+                "{} bytes are too short for a block",
+                data.len()
+            )));
+        }
+        let num_of_restarts_with_flags = (&data[data.len() - 2..]).get_u16();
+        let num_of_restarts =
+            (num_of_restarts_with_flags & !(VARINT_LENGTHS_FLAG | TIMESTAMPS_FLAG)) as usize;
+        let Some(data_end) = (data.len() - 2).checked_sub(num_of_restarts * 2) else {
+            return Err(CorruptionError::new(format!(
+                "{} restart points do not fit in {} bytes",
+                num_of_restarts,
+                data.len()
+            )));
+        };
+        let block = Self {
+            offsets: data.slice(data_end..data.len() - 2),
+            data: data.slice(..data_end),
+            layout: EntryLayout {
+                fixed_lengths: num_of_restarts_with_flags & VARINT_LENGTHS_FLAG == 0,
+                timestamps: num_of_restarts_with_flags & TIMESTAMPS_FLAG != 0,
+            },
+        };
+        // The first entry is a restart point, and so is none past the last entry
+        if (num_of_restarts == 0) != block.data.is_empty() {
+            return Err(CorruptionError::new(format!(
+                "{} restart points for {} bytes of entries",
+                num_of_restarts, data_end
+            )));
+        }
+        let mut prev_offset = None;
+        for restart_idx in 0..num_of_restarts {
+            let offset = block.restart_offset(restart_idx).unwrap();
+            let valid = match prev_offset {
+                None => offset == 0,
+                Some(prev_offset) => prev_offset < offset && offset < data_end,
+            };
+            if !valid {
+                return Err(CorruptionError::new(format!(
+                    "restart point {} at offset {} is out of order or past the {} bytes of entries",
+                    restart_idx, offset, data_end
+                )));
+            }
+            prev_offset = Some(offset);
+        }
+        Ok(block)
+    }
+
+    /// The number of restart points of the block.
+    pub(crate) fn num_restarts(&self) -> usize {
+        self.offsets.len() / 2
+    }
+
+    /// The offset in the block data of the `restart_idx`-th restart point, if any.
+    pub(crate) fn restart_offset(&self, restart_idx: usize) -> Option<usize> {
+        let offset = self.offsets.get(restart_idx * 2..restart_idx * 2 + 2)?;
+        Some(u16::from_be_bytes([offset[0], offset[1]]) as usize)
+    }
+
+    /// Decode the entry at `offset` in the block data.
+    pub(crate) fn entry_at(&self, offset: usize) -> RawEntry<'_> {
+        let mut buf = &self.data[offset..];
+        let (overlap, has_meta, key_len, has_empty_value) = if self.layout.fixed_lengths {
+            let overlap_with_flags = buf.get_u16();
+            let key_len_with_flags = buf.get_u16();
+            (
+                (overlap_with_flags & !HAS_META_FLAG) as usize,
+                overlap_with_flags & HAS_META_FLAG != 0,
+                (key_len_with_flags & !EMPTY_VALUE_FLAG) as usize,
+                key_len_with_flags & EMPTY_VALUE_FLAG != 0,
+            )
+        } else {
+            let overlap_with_flags = get_varint(&mut buf);
+            let key_len_with_flags = get_varint(&mut buf);
+            (
+                overlap_with_flags >> 1,
+                overlap_with_flags & 1 != 0,
+                key_len_with_flags >> 1,
+                key_len_with_flags & 1 != 0,
+            )
+        };
+        let key_suffix = &buf[..key_len];
+        buf.advance(key_len);
+        let ts = if self.layout.timestamps {
+            buf.get_u64()
+        } else {
+            TS_DEFAULT
+        };
+        let value_len = if self.layout.fixed_lengths {
+            buf.get_u16() as usize
+        } else {
+            get_varint(&mut buf)
+        };
+        let value_start = self.data.len() - buf.remaining();
+        let value_end = value_start + value_len;
+        let meta = if has_meta { self.data[value_end] } else { 0 };
+        RawEntry {
+            overlap,
+            key_suffix,
+            ts,
+            value_range: (value_start, value_end),
+            is_deleted: value_len == 0 && !has_empty_value,
+            meta,
+            next_offset: value_end + has_meta as usize,
+        }
+    }
+
+    /// Check if the block contains any deletion, i.e., an entry with an empty value that is not
+    /// flagged as an empty value.
+    pub fn has_deletes(&self) -> bool {
+        let mut offset = 0;
+        while offset < self.data.len() {
+            let entry = self.entry_at(offset);
+            if entry.is_deleted {
+                return true;
+            }
+            offset = entry.next_offset;
+        }
+        false
+    }
+
+    /// Check if the block holds more than one version of a key, which only blocks with timestamps
+    /// do.
+    pub fn has_multiple_versions(&self) -> bool {
+        if !self.layout.timestamps {
+            return false;
+        }
+        let mut prev_key = Vec::new();
+        let mut offset = 0;
+        while offset < self.data.len() {
+            let entry = self.entry_at(offset);
+            // Only the restart points, which have no overlap, can start with a different key
+            if entry.overlap + entry.key_suffix.len() == prev_key.len()
+                && prev_key[entry.overlap..] == *entry.key_suffix
+            {
+                return true;
+            }
+            prev_key.truncate(entry.overlap);
+            prev_key.extend_from_slice(entry.key_suffix);
+            offset = entry.next_offset;
         }
+        false
     }
 
     pub fn get_first_key(&self) -> KeyVec {
-        let mut buf = &self.data[..];
-        buf.get_u16(); // Skip the overlap length
-        let key_len = buf.get_u16();
-        let key = &buf[..key_len as usize];
-        KeyVec::from_vec(key.to_vec())
+        self.first_key().to_key_vec()
+    }
+
+    /// The first key of the block, borrowed from the block data.
+    pub fn first_key(&self) -> KeySlice<'_> {
+        // The first entry has no overlap
+        let entry = self.entry_at(0);
+        KeySlice::from_slice(entry.key_suffix, entry.ts)
     }
 }
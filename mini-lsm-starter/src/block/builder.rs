@@ -16,39 +16,59 @@ use bytes::BufMut;
 
 use crate::key::{KeySlice, KeyVec};
 
-use super::Block;
+use super::{Block, CompressionType, EntryLayout, encode_compressed_into, put_varint, varint_len};
+
+/// The number of entries between two restart points of a block, unless configured with
+/// `BlockBuilder::with_restart_interval`.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
 
 /// Builds a block.
 pub struct BlockBuilder {
-    /// Offsets of each key-value entries.
-    offsets: Vec<u16>,
+    /// Offsets of the restart points, i.e., of every `restart_interval`-th entry, encoded as
+    /// big-endian 16-bit integers.
+    offsets: Vec<u8>,
     /// All serialized key-value pairs in the block.
     data: Vec<u8>,
     /// The expected block size.
     block_size: usize,
-    /// The first key in the block
-    first_key: KeyVec,
+    /// The number of entries between two restart points.
+    restart_interval: usize,
+    /// The number of entries added so far.
+    num_entries: usize,
+    /// The last key in the block, which the next key is delta-encoded against.
+    last_key: KeyVec,
 }
 
 impl BlockBuilder {
-    /// Creates a new block builder.
+    /// Creates a new block builder, with room for `block_size` bytes of entries.
     pub fn new(block_size: usize) -> Self {
         Self {
             offsets: Vec::new(),
-            data: Vec::new(),
+            data: Vec::with_capacity(block_size),
             block_size,
-            first_key: KeyVec::new(),
+            restart_interval: DEFAULT_RESTART_INTERVAL,
+            num_entries: 0,
+            last_key: KeyVec::new(),
         }
     }
 
+    /// Store the full key of every `restart_interval`-th entry, and the other keys as the suffix
+    /// after their overlap with the previous key. A larger interval compresses more, while a seek
+    /// decodes up to `restart_interval` entries after its binary search over the restart points.
+    pub fn with_restart_interval(mut self, restart_interval: usize) -> Self {
+        assert!(restart_interval > 0, "restart interval must be positive");
+        self.restart_interval = restart_interval;
+        self
+    }
+
     fn compute_key_overlap(&self, key: &[u8]) -> usize {
         let mut overlap = 0;
-        let first_key = self.first_key.raw_ref();
+        let last_key = self.last_key.key_ref();
         loop {
-            if overlap >= key.len() || overlap >= first_key.len() {
+            if overlap >= key.len() || overlap >= last_key.len() {
                 break;
             }
-            if first_key[overlap] != key[overlap] {
+            if last_key[overlap] != key[overlap] {
                 break;
             }
             overlap += 1;
@@ -60,38 +80,108 @@ impl BlockBuilder {
     /// You may find the `bytes::BufMut` trait useful for manipulating binary data.
     #[must_use]
     pub fn add(&mut self, key: KeySlice, value: &[u8]) -> bool {
-        if !self.is_empty() {
-            let curr_block_size = self.data.len() + self.offsets.len() * 2 + 2; // 2 bytes for each offset and 2 bytes for num_of_elements
-            if curr_block_size + key.len() + value.len() + 3 * 2 > self.block_size {
-                return false;
-            }
+        self.add_with_meta(key, value, 0)
+    }
+
+    /// Adds a key-value pair with a user metadata byte. Returns false when the block is full.
+    #[must_use]
+    pub fn add_with_meta(&mut self, key: KeySlice, value: &[u8], meta: u8) -> bool {
+        self.add_entry(key, value, meta, value.is_empty())
+    }
+
+    /// Adds a put or a delete. Unlike `add`, an empty value is only a delete if `is_delete` is
+    /// set. Returns false when the block is full.
+    #[must_use]
+    pub fn add_entry(&mut self, key: KeySlice, value: &[u8], meta: u8, is_delete: bool) -> bool {
+        if !self.is_empty() && !self.fits(Self::entry_size(key, value, meta)) {
+            return false;
         }
+        self.add_unchecked(key, value, meta, is_delete);
+        true
+    }
 
-        self.offsets.push(self.data.len() as u16); // Store the offset of the current key-value pair
-        let overlap = self.compute_key_overlap(key.raw_ref());
-        self.data.put_u16(overlap as u16); // Overlap length
-        self.data.put_u16((key.len() - overlap) as u16); // Key length
-        self.data.put(&key.raw_ref()[overlap..]); // Key data
-        self.data.put_u16(value.len() as u16); // Value length
-        self.data.put(value); // Value data
+    /// The upper bound of the bytes taken by a key-value pair in the block.
+    pub fn entry_size(key: KeySlice, value: &[u8], meta: u8) -> usize {
+        let meta_len = if meta != 0 { 1 } else { 0 };
+        // The overlap length is at most the key length, and both are shifted left by a flag bit
+        let key_len_size = varint_len(key.key_len() << 1 | 1);
+        // overlap length, key length, timestamp, value length and the offset if it is a restart
+        // point
+        key.raw_len() + value.len() + key_len_size * 2 + varint_len(value.len()) + 2 + meta_len
+    }
+
+    /// Check if an entry of `entry_size` bytes can be added without exceeding the block size.
+    pub fn fits(&self, entry_size: usize) -> bool {
+        self.estimated_size() + entry_size <= self.block_size
+    }
+
+    /// The encoded size of the block built so far.
+    pub fn estimated_size(&self) -> usize {
+        self.data.len() + self.offsets.len() + 2 // 2 bytes for each offset and 2 bytes for num_of_restarts
+    }
 
-        if self.first_key.is_empty() {
-            self.first_key = key.to_key_vec();
+    /// Adds a key-value pair to the block without checking whether the block is full.
+    pub fn add_unchecked(&mut self, key: KeySlice, value: &[u8], meta: u8, is_delete: bool) {
+        let overlap = if self.num_entries.is_multiple_of(self.restart_interval) {
+            debug_assert!(self.data.len() <= u16::MAX as usize);
+            self.offsets.put_u16(self.data.len() as u16); // Store the offset of the restart point
+            0
+        } else {
+            self.compute_key_overlap(key.key_ref())
+        };
+        let has_meta = (meta != 0) as usize;
+        put_varint(&mut self.data, overlap << 1 | has_meta); // Overlap length
+        debug_assert!(!is_delete || value.is_empty());
+        let has_empty_value = (value.is_empty() && !is_delete) as usize;
+        put_varint(
+            &mut self.data,
+            (key.key_len() - overlap) << 1 | has_empty_value,
+        ); // Key length
+        self.data.put(&key.key_ref()[overlap..]); // Key data
+        self.data.put_u64(key.ts()); // Timestamp
+        put_varint(&mut self.data, value.len()); // Value length
+        self.data.put(value); // Value data
+        if meta != 0 {
+            self.data.put_u8(meta); // User metadata
         }
 
-        true
+        self.last_key.set_from_slice(key);
+        self.num_entries += 1;
     }
 
     /// Check if there is no key-value pair in the block.
     pub fn is_empty(&self) -> bool {
-        self.offsets.is_empty()
+        self.num_entries == 0
+    }
+
+    /// Empty the builder for the next block of `block_size`, keeping its allocations.
+    pub fn reset(&mut self, block_size: usize) {
+        self.offsets.clear();
+        self.data.clear();
+        self.data.reserve(block_size);
+        self.block_size = block_size;
+        self.num_entries = 0;
+        self.last_key.clear();
+    }
+
+    /// Append the block built so far to `out` as `Block::encode_compressed` would, without
+    /// consuming the builder, which can then be `reset` for the next block.
+    pub fn encode_compressed_into(&self, compression: CompressionType, out: &mut Vec<u8>) {
+        encode_compressed_into(
+            &self.data,
+            &self.offsets,
+            EntryLayout::CURRENT,
+            compression,
+            out,
+        );
     }
 
-    /// Finalize the block.
+    /// Finalize the block, handing its buffers over without copying them.
     pub fn build(self) -> Block {
         Block {
-            data: self.data,
-            offsets: self.offsets,
+            data: self.data.into(),
+            offsets: self.offsets.into(),
+            layout: EntryLayout::CURRENT,
         }
     }
 }
@@ -0,0 +1,58 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result, bail};
+
+/// The codec of a block in an SST, see `LsmStorageOptions::block_compression`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionType {
+    #[default]
+    None,
+    Lz4,
+    /// Zstd with the given compression level, from 1 to 22.
+    Zstd(i32),
+}
+
+impl CompressionType {
+    /// The flag persisted after each block. The level of zstd is only needed to compress.
+    pub(crate) fn flag(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Lz4 => 1,
+            CompressionType::Zstd(_) => 2,
+        }
+    }
+
+    /// Compress `data`, or return `None` if it does not get smaller.
+    pub(crate) fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        let compressed = match self {
+            CompressionType::None => return None,
+            CompressionType::Lz4 => lz4_flex::compress_prepend_size(data),
+            CompressionType::Zstd(level) => zstd::bulk::compress(data, level).ok()?,
+        };
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+
+    /// Decompress data compressed with the codec of `flag`.
+    pub(crate) fn decompress(flag: u8, data: &[u8]) -> Result<Vec<u8>> {
+        match flag {
+            0 => Ok(data.to_vec()),
+            1 => {
+                lz4_flex::decompress_size_prepended(data).context("failed to decompress lz4 block")
+            }
+            2 => zstd::stream::decode_all(data).context("failed to decompress zstd block"),
+            _ => bail!("unknown block compression flag {}", flag),
+        }
+    }
+}
@@ -15,10 +15,10 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
+use std::cmp::Ordering;
 use std::sync::Arc;
 
 use crate::key::{KeySlice, KeyVec};
-use bytes::Buf;
 
 use super::Block;
 
@@ -30,8 +30,16 @@ pub struct BlockIterator {
     key: KeyVec,
     /// the current value range in the block.data, corresponds to the current key
     value_range: (usize, usize),
-    /// Current index of the key-value pair, should be in range of [0, num_of_elements)
-    idx: usize,
+    /// The user metadata byte of the current value
+    value_meta: u8,
+    /// Whether the current entry is a delete
+    is_deleted: bool,
+    /// Index of the last restart point at or before the current entry
+    restart_idx: usize,
+    /// Offset of the current entry in block.data
+    offset: usize,
+    /// Offset of the entry after the current one in block.data
+    next_offset: usize,
     /// The first key in the block
     first_key: KeyVec,
 }
@@ -43,10 +51,22 @@ impl BlockIterator {
             block,
             key: KeyVec::new(),
             value_range: (0, 0),
-            idx: 0,
+            value_meta: 0,
+            is_deleted: false,
+            restart_idx: 0,
+            offset: 0,
+            next_offset: 0,
         }
     }
 
+    /// Switch to another block and seek to its first key, reusing the key buffers of this
+    /// iterator instead of allocating new ones.
+    pub fn seek_to_first_of_block(&mut self, block: Arc<Block>) {
+        self.first_key.set_from_slice(block.first_key());
+        self.block = block;
+        self.seek_to_first();
+    }
+
     /// Creates a block iterator and seek to the first entry.
     pub fn create_and_seek_to_first(block: Arc<Block>) -> Self {
         let mut iter = Self::new(block);
@@ -54,6 +74,20 @@ impl BlockIterator {
         iter
     }
 
+    /// Creates a block iterator and seek to the last entry.
+    pub fn create_and_seek_to_last(block: Arc<Block>) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to_last();
+        iter
+    }
+
+    /// Switch to another block and seek to its last key, see `seek_to_first_of_block`.
+    pub fn seek_to_last_of_block(&mut self, block: Arc<Block>) {
+        self.first_key.set_from_slice(block.first_key());
+        self.block = block;
+        self.seek_to_last();
+    }
+
     /// Creates a block iterator and seek to the first key that >= `key`.
     pub fn create_and_seek_to_key(block: Arc<Block>, key: KeySlice) -> Self {
         let mut iter = Self::new(block);
@@ -62,7 +96,7 @@ impl BlockIterator {
     }
 
     /// Returns the key of the current entry.
-    pub fn key(&self) -> KeySlice {
+    pub fn key(&self) -> KeySlice<'_> {
         self.key.as_key_slice()
     }
 
@@ -72,6 +106,16 @@ impl BlockIterator {
         &self.block.data[start..end]
     }
 
+    /// Returns the user metadata byte of the current entry.
+    pub fn value_meta(&self) -> u8 {
+        self.value_meta
+    }
+
+    /// Returns true if the current entry is a delete rather than an empty value.
+    pub fn is_deleted(&self) -> bool {
+        self.is_deleted
+    }
+
     /// Returns true if the iterator is valid.
     /// Note: You may want to make use of `key`
     pub fn is_valid(&self) -> bool {
@@ -80,58 +124,128 @@ impl BlockIterator {
 
     /// Seeks to the first key in the block.
     pub fn seek_to_first(&mut self) {
-        self.seek_to_idx(0);
+        self.seek_to_restart(0);
+    }
+
+    /// Seeks to the last key in the block.
+    pub fn seek_to_last(&mut self) {
+        self.seek_to_restart(self.block.num_restarts().saturating_sub(1));
+        while self.next_offset < self.block.data.len() {
+            self.next();
+        }
     }
 
     /// Move to the next key in the block.
     pub fn next(&mut self) {
-        self.idx += 1;
-        self.seek_to_idx(self.idx);
+        self.decode_at(self.next_offset);
+    }
+
+    /// Move to the previous key in the block, invalidating the iterator before the first key.
+    /// As the keys are delta-encoded, the entries are decoded again from the last restart point
+    /// before the previous key.
+    pub fn prev(&mut self) {
+        if !self.is_valid() {
+            return;
+        }
+        let offset = self.offset;
+        let restart_idx = if self.block.restart_offset(self.restart_idx) == Some(offset) {
+            let Some(restart_idx) = self.restart_idx.checked_sub(1) else {
+                self.invalidate();
+                return;
+            };
+            restart_idx
+        } else {
+            self.restart_idx
+        };
+        self.seek_to_restart(restart_idx);
+        while self.next_offset < offset {
+            self.next();
+        }
     }
 
     /// Seek to the first key that >= `key`.
     /// Note: You should assume the key-value pairs in the block are sorted when being added by
     /// callers.
     pub fn seek_to_key(&mut self, key: KeySlice) {
+        // Binary search for the last restart point not greater than the key, comparing the keys
+        // in place, then decode the entries after it
         let mut lo = 0;
-        let mut hi = self.block.offsets.len();
+        let mut hi = self.block.num_restarts();
         while lo < hi {
             let mid = (lo + hi) / 2;
-            self.seek_to_idx(mid);
-
-            match self.key.cmp(&key.to_key_vec()) {
-                std::cmp::Ordering::Less => lo = mid + 1,
-                std::cmp::Ordering::Greater => hi = mid,
-                std::cmp::Ordering::Equal => return, // Found the key
+            match self.cmp_restart_key(mid, key) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => {
+                    self.seek_to_restart(mid);
+                    return;
+                }
             }
         }
-        self.seek_to_idx(lo);
+        self.seek_to_restart(lo.saturating_sub(1));
+        while self.is_valid() && self.key.as_key_slice() < key {
+            self.next();
+        }
     }
 
-    pub fn seek_to_idx(&mut self, idx: usize) {
-        if idx >= self.block.offsets.len() {
-            self.key.clear();
-            self.value_range = (0, 0);
-            return;
-        }
+    /// Compare the key at the `restart_idx`-th restart point with `key`, without copying it out of
+    /// the block.
+    fn cmp_restart_key(&self, restart_idx: usize, key: KeySlice) -> Ordering {
+        let entry = self
+            .block
+            .entry_at(self.block.restart_offset(restart_idx).unwrap());
+        // Empty, except in blocks written before restart points were introduced
+        let prefix = &self.first_key.key_ref()[..entry.overlap];
+        let (key_prefix, key_suffix) = key.key_ref().split_at(prefix.len().min(key.key_len()));
+        prefix
+            .cmp(key_prefix)
+            .then_with(|| entry.key_suffix.cmp(key_suffix))
+            .then_with(|| key.ts().cmp(&entry.ts))
+    }
 
-        let offset = self.block.offsets[idx] as usize;
-        let mut entry = &self.block.data[offset..];
+    /// Seek to the entry at the `restart_idx`-th restart point.
+    pub fn seek_to_restart(&mut self, restart_idx: usize) {
+        let Some(offset) = self.block.restart_offset(restart_idx) else {
+            self.invalidate();
+            return;
+        };
+        self.restart_idx = restart_idx;
+        self.decode_at(offset);
+    }
 
-        let overlap_len = entry.get_u16() as usize;
-        let key_len = entry.get_u16() as usize;
-        let key = &entry[..key_len];
-        entry.advance(key_len);
+    fn invalidate(&mut self) {
         self.key.clear();
-        self.key.append(&self.first_key.raw_ref()[..overlap_len]);
-        self.key.append(key);
-
-        let value_len = entry.get_u16() as usize;
-        let value_start = offset + 2 + 2 + key_len + 2;
-        let value_end = value_start + value_len;
-        self.value_range = (value_start, value_end);
-        entry.advance(value_len);
+        self.value_range = (0, 0);
+        self.value_meta = 0;
+        self.is_deleted = false;
+        self.next_offset = self.block.data.len();
+    }
 
-        self.idx = idx;
+    /// Decode the entry at `offset`, which is either a restart point or follows the current entry.
+    fn decode_at(&mut self, offset: usize) {
+        if offset >= self.block.data.len() {
+            self.invalidate();
+            return;
+        }
+        if self.block.restart_offset(self.restart_idx + 1) == Some(offset) {
+            self.restart_idx += 1;
+        }
+        let entry = self.block.entry_at(offset);
+        if self.block.restart_offset(self.restart_idx) == Some(offset) {
+            // Restart points have no overlap, except in blocks written before restart points were
+            // introduced: every entry is a restart point there, delta-encoded against the first key
+            self.key.clear();
+            self.key.append(&self.first_key.key_ref()[..entry.overlap]);
+        } else {
+            self.key.truncate(entry.overlap);
+        }
+        self.key.append(entry.key_suffix);
+        self.key.set_ts(entry.ts);
+
+        self.offset = offset;
+        self.value_range = entry.value_range;
+        self.is_deleted = entry.is_deleted;
+        self.value_meta = entry.meta;
+        self.next_offset = entry.next_offset;
     }
 }
@@ -0,0 +1,600 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Result, anyhow, bail};
+use moka::sync::ConcurrentCacheExt;
+use parking_lot::{Mutex, RwLock};
+
+use crate::block::Block;
+use crate::table::TableMetadata;
+
+type BlockKey = (usize, usize);
+
+type MokaCache = moka::sync::Cache<BlockKey, Arc<Block>>;
+
+type MetadataPool = moka::sync::Cache<usize, Arc<TableMetadata>>;
+
+/// How a `BlockCache` picks the blocks to keep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockCachePolicy {
+    /// Only admit a block over the ones it would evict if it is read more often, so that a scan
+    /// reading many blocks once does not flush the cache.
+    #[default]
+    TinyLfu,
+    /// Always admit a block, evicting the least recently read ones.
+    Lru,
+}
+
+/// The blocks in a least-recently-used order, bounded in weight.
+struct LruBlocks {
+    capacity: u64,
+    weigh_blocks: bool,
+    size: u64,
+    /// The blocks with the tick of their last read.
+    blocks: HashMap<BlockKey, (Arc<Block>, u64)>,
+    /// The keys by the tick of their last read, from the least recent.
+    order: BTreeMap<u64, BlockKey>,
+    next_tick: u64,
+}
+
+impl LruBlocks {
+    fn weight(&self, block: &Block) -> u64 {
+        if self.weigh_blocks {
+            block_size(block) as u64
+        } else {
+            1
+        }
+    }
+
+    fn touch(&mut self, key: &BlockKey) -> Option<Arc<Block>> {
+        let (block, tick) = self.blocks.get_mut(key)?;
+        self.order.remove(tick);
+        *tick = self.next_tick;
+        self.order.insert(self.next_tick, *key);
+        self.next_tick += 1;
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, key: BlockKey, block: Arc<Block>) {
+        self.remove(&key);
+        self.size += self.weight(&block);
+        self.blocks.insert(key, (block, self.next_tick));
+        self.order.insert(self.next_tick, key);
+        self.next_tick += 1;
+        while self.size > self.capacity && self.evict_one().is_some() {}
+    }
+
+    fn remove(&mut self, key: &BlockKey) -> Option<u64> {
+        let (block, tick) = self.blocks.remove(key)?;
+        self.order.remove(&tick);
+        let weight = self.weight(&block);
+        self.size -= weight;
+        Some(weight)
+    }
+
+    /// Evict the least recently read block, returning its weight.
+    fn evict_one(&mut self) -> Option<u64> {
+        let (_, key) = self.order.pop_first()?;
+        let (block, _) = self.blocks.remove(&key).unwrap();
+        let weight = self.weight(&block);
+        self.size -= weight;
+        Some(weight)
+    }
+}
+
+/// A pool of cached blocks.
+enum Cache {
+    TinyLfu(MokaCache),
+    /// Concurrent misses of a block all read it, unlike with `TinyLfu`.
+    Lru(Mutex<LruBlocks>),
+}
+
+impl Cache {
+    fn new(capacity: u64, weigh_blocks: bool, policy: BlockCachePolicy) -> Self {
+        match policy {
+            BlockCachePolicy::TinyLfu => {
+                let builder = MokaCache::builder().max_capacity(capacity);
+                Self::TinyLfu(if weigh_blocks {
+                    builder
+                        .weigher(|_, block: &Arc<Block>| block_size(block))
+                        .build()
+                } else {
+                    builder.build()
+                })
+            }
+            BlockCachePolicy::Lru => Self::Lru(Mutex::new(LruBlocks {
+                capacity,
+                weigh_blocks,
+                size: 0,
+                blocks: HashMap::new(),
+                order: BTreeMap::new(),
+                next_tick: 0,
+            })),
+        }
+    }
+
+    fn get(&self, key: &BlockKey) -> Option<Arc<Block>> {
+        match self {
+            Self::TinyLfu(cache) => cache.get(key),
+            Self::Lru(blocks) => blocks.lock().touch(key),
+        }
+    }
+
+    /// Get a block, loading it with `init` on a miss. Returns whether it was a hit.
+    fn try_get_with(
+        &self,
+        key: BlockKey,
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<(Arc<Block>, bool)> {
+        let mut hit = true;
+        let block = match self {
+            Self::TinyLfu(cache) => {
+                // Keep the error of our own `init` as is, so that callers can downcast it, e.g.,
+                // to a `ChecksumError`. The cache only shares the message with concurrent readers
+                // of the block.
+                let mut init_error = None;
+                cache
+                    .try_get_with(key, || {
+                        hit = false;
+                        init().map_err(|e| {
+                            let message = format!("{:#}", e);
+                            init_error = Some(e);
+                            message
+                        })
+                    })
+                    .map_err(|e| init_error.take().unwrap_or_else(|| anyhow!("{}", e)))?
+            }
+            Self::Lru(blocks) => {
+                if let Some(block) = blocks.lock().touch(&key) {
+                    return Ok((block, true));
+                }
+                hit = false;
+                let block = init()?;
+                blocks.lock().insert(key, block.clone());
+                block
+            }
+        };
+        Ok((block, hit))
+    }
+
+    /// The weight of the cached blocks.
+    fn weighted_size(&self) -> u64 {
+        match self {
+            Self::TinyLfu(cache) => {
+                cache.sync();
+                cache.weighted_size()
+            }
+            Self::Lru(blocks) => blocks.lock().size,
+        }
+    }
+
+    /// The bytes of the cached blocks, whatever their weight.
+    fn block_bytes(&self) -> u64 {
+        match self {
+            Self::TinyLfu(cache) => {
+                cache.sync();
+                cache
+                    .iter()
+                    .map(|(_, block)| block_size(&block) as u64)
+                    .sum()
+            }
+            Self::Lru(blocks) => blocks
+                .lock()
+                .blocks
+                .values()
+                .map(|(block, _)| block_size(block) as u64)
+                .sum(),
+        }
+    }
+
+    /// Evict blocks weighing at least `excess`, or all of them. Returns the weight left to evict.
+    fn evict(&self, mut excess: u64) -> u64 {
+        match self {
+            Self::TinyLfu(cache) => {
+                for (key, block) in cache.iter() {
+                    if excess == 0 {
+                        break;
+                    }
+                    cache.invalidate(&*key);
+                    excess = excess.saturating_sub(block_size(&block) as u64);
+                }
+            }
+            Self::Lru(blocks) => {
+                let mut blocks = blocks.lock();
+                while excess > 0
+                    && let Some(weight) = blocks.evict_one()
+                {
+                    excess = excess.saturating_sub(weight);
+                }
+            }
+        }
+        excess
+    }
+}
+
+/// Block cache hits and misses of the SSTs in one level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl LevelCacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+#[derive(Default)]
+struct LevelCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Caches the blocks of the SSTs, keyed by `(sst_id, block_idx)`. The levels are numbered from 0
+/// for L0, and the blocks of the high priority levels are kept in a separate pool, so that a scan
+/// over the other levels cannot evict them.
+///
+/// A cache created with `with_byte_capacity` is bounded in bytes, and the block indexes and bloom
+/// filters of the open SSTs, as well as the blocks pinned by the iterators, are charged to it,
+/// evicting blocks to make room for them. With `with_metadata_capacity`, the block indexes and
+/// bloom filters are kept in a pool of their own instead, keyed by SST id.
+pub struct BlockCache {
+    cache: Cache,
+    high_priority_cache: Option<Cache>,
+    high_priority_levels: Vec<usize>,
+    level_counters: RwLock<Vec<LevelCacheCounters>>,
+    /// The capacity in bytes, if the blocks are weighed by their size.
+    byte_capacity: Option<u64>,
+    /// Fail to charge metadata beyond `byte_capacity` instead of going over it.
+    strict_capacity_limit: bool,
+    metadata_size: AtomicU64,
+    pinned_size: AtomicU64,
+    pinned_blocks: AtomicU64,
+    metadata_pool: Option<MetadataPool>,
+}
+
+/// SST metadata charged to a `BlockCache`, released on drop.
+pub struct MetadataCharge {
+    cache: Arc<BlockCache>,
+    size: u64,
+}
+
+impl Drop for MetadataCharge {
+    fn drop(&mut self) {
+        self.cache
+            .metadata_size
+            .fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+/// A block held by an iterator, which pins it in memory and charges it to the cache until the
+/// handle is dropped. The charge comes on top of the cached blocks, as evicting a pinned block
+/// from the cache does not free it, and only counts against the capacity of a cache bounded in
+/// bytes.
+pub struct BlockHandle {
+    block: Arc<Block>,
+    cache: Option<Arc<BlockCache>>,
+    charge: u64,
+}
+
+impl BlockHandle {
+    /// A handle to a block read without a cache, which is not charged anywhere.
+    pub fn uncached(block: Arc<Block>) -> Self {
+        Self {
+            block,
+            cache: None,
+            charge: 0,
+        }
+    }
+
+    pub fn block(&self) -> &Arc<Block> {
+        &self.block
+    }
+}
+
+impl Drop for BlockHandle {
+    fn drop(&mut self) {
+        if let Some(cache) = &self.cache {
+            cache.pinned_size.fetch_sub(self.charge, Ordering::Relaxed);
+            cache.pinned_blocks.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn block_size(block: &Block) -> u32 {
+    (block.data.len() + block.offsets.len()) as u32
+}
+
+impl BlockCache {
+    /// Create a cache holding up to `capacity` blocks.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_high_priority_levels(capacity, Vec::new(), BlockCachePolicy::default())
+    }
+
+    /// Create a cache holding up to `capacity` blocks, half of which are reserved for the blocks
+    /// of `high_priority_levels`.
+    pub fn with_high_priority_levels(
+        capacity: u64,
+        high_priority_levels: Vec<usize>,
+        policy: BlockCachePolicy,
+    ) -> Self {
+        Self::build(capacity, high_priority_levels, false, false, policy)
+    }
+
+    /// Create a cache holding up to `capacity` bytes of blocks and SST metadata, half of which are
+    /// reserved for the blocks of `high_priority_levels`. With `strict_capacity_limit`, charging
+    /// metadata that does not fit fails instead of going over the capacity.
+    pub fn with_byte_capacity(
+        capacity: u64,
+        high_priority_levels: Vec<usize>,
+        strict_capacity_limit: bool,
+        policy: BlockCachePolicy,
+    ) -> Self {
+        Self::build(
+            capacity,
+            high_priority_levels,
+            true,
+            strict_capacity_limit,
+            policy,
+        )
+    }
+
+    fn build(
+        capacity: u64,
+        high_priority_levels: Vec<usize>,
+        weigh_blocks: bool,
+        strict_capacity_limit: bool,
+        policy: BlockCachePolicy,
+    ) -> Self {
+        let new_cache = |capacity| Cache::new(capacity, weigh_blocks, policy);
+        let (cache, high_priority_cache) = if high_priority_levels.is_empty() {
+            (new_cache(capacity), None)
+        } else {
+            let high_priority_capacity = capacity / 2;
+            (
+                new_cache(capacity - high_priority_capacity),
+                Some(new_cache(high_priority_capacity)),
+            )
+        };
+        Self {
+            cache,
+            high_priority_cache,
+            high_priority_levels,
+            level_counters: RwLock::new(Vec::new()),
+            byte_capacity: weigh_blocks.then_some(capacity),
+            strict_capacity_limit,
+            metadata_size: AtomicU64::new(0),
+            pinned_size: AtomicU64::new(0),
+            pinned_blocks: AtomicU64::new(0),
+            metadata_pool: None,
+        }
+    }
+
+    /// Keep the block indexes and bloom filters of the SSTs opened with this cache in a pool
+    /// bounded to `capacity` bytes, apart from the blocks, rather than in memory for as long as
+    /// the SSTs are open. The SSTs read them again from the disk once evicted.
+    pub fn with_metadata_capacity(mut self, capacity: u64) -> Self {
+        self.metadata_pool = Some(
+            MetadataPool::builder()
+                .max_capacity(capacity)
+                .weigher(|_, metadata: &Arc<TableMetadata>| {
+                    metadata.size().try_into().unwrap_or(u32::MAX)
+                })
+                .build(),
+        );
+        self
+    }
+
+    pub(crate) fn has_metadata_pool(&self) -> bool {
+        self.metadata_pool.is_some()
+    }
+
+    /// Get the metadata of an SST from the metadata pool, loading it with `init` on a miss.
+    pub(crate) fn try_get_metadata(
+        &self,
+        sst_id: usize,
+        init: impl FnOnce() -> Result<TableMetadata>,
+    ) -> Result<Arc<TableMetadata>> {
+        let pool = self.metadata_pool.as_ref().expect("no metadata pool");
+        pool.try_get_with(sst_id, || init().map(Arc::new))
+            .map_err(|e| anyhow!("{:#}", e))
+    }
+
+    pub(crate) fn insert_metadata(&self, sst_id: usize, metadata: Arc<TableMetadata>) {
+        if let Some(pool) = &self.metadata_pool {
+            pool.insert(sst_id, metadata);
+        }
+    }
+
+    /// Drop the metadata of an SST that was deleted.
+    pub(crate) fn remove_metadata(&self, sst_id: usize) {
+        if let Some(pool) = &self.metadata_pool {
+            pool.invalidate(&sst_id);
+        }
+    }
+
+    /// The bytes of the block indexes and of the bloom filters in the metadata pool.
+    pub fn pooled_metadata_size(&self) -> (u64, u64) {
+        let Some(pool) = &self.metadata_pool else {
+            return (0, 0);
+        };
+        pool.sync();
+        pool.iter()
+            .map(|(_, metadata)| (metadata.block_index_size(), metadata.bloom_size()))
+            .fold((0, 0), |(index, bloom), (i, b)| (index + i, bloom + b))
+    }
+
+    fn pools(&self) -> impl Iterator<Item = &Cache> {
+        std::iter::once(&self.cache).chain(self.high_priority_cache.as_ref())
+    }
+
+    fn cache_of(&self, level: usize) -> &Cache {
+        match &self.high_priority_cache {
+            Some(cache) if self.high_priority_levels.contains(&level) => cache,
+            _ => &self.cache,
+        }
+    }
+
+    fn record(&self, level: usize, hit: bool) {
+        let counters = self.level_counters.read();
+        if let Some(counters) = counters.get(level) {
+            let counter = if hit {
+                &counters.hits
+            } else {
+                &counters.misses
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(counters);
+        let mut counters = self.level_counters.write();
+        if counters.len() <= level {
+            counters.resize_with(level + 1, Default::default);
+        }
+        drop(counters);
+        self.record(level, hit);
+    }
+
+    /// Get a block of an SST in `level`, without loading it on a miss.
+    pub fn get(&self, level: usize, key: &BlockKey) -> Option<Arc<Block>> {
+        let block = self.cache_of(level).get(key);
+        self.record(level, block.is_some());
+        block
+    }
+
+    /// Get a block of an SST in `level`, loading it with `init` on a miss.
+    pub fn try_get_with(
+        &self,
+        level: usize,
+        key: BlockKey,
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        let (block, hit) = self.cache_of(level).try_get_with(key, init)?;
+        self.record(level, hit);
+        if !hit {
+            self.evict_over_capacity();
+        }
+        Ok(block)
+    }
+
+    /// Charge `size` bytes of SST metadata to a cache bounded in bytes, evicting blocks to make
+    /// room for it. The charge is released when the returned guard is dropped.
+    pub fn charge_metadata(self: &Arc<Self>, size: u64) -> Result<MetadataCharge> {
+        let Some(capacity) = self.byte_capacity else {
+            return Ok(MetadataCharge {
+                cache: self.clone(),
+                size: 0,
+            });
+        };
+        let metadata_size = self.metadata_size.fetch_add(size, Ordering::Relaxed) + size;
+        if self.strict_capacity_limit && metadata_size > capacity {
+            self.metadata_size.fetch_sub(size, Ordering::Relaxed);
+            bail!(
+                "SST metadata of {} bytes does not fit into the block cache of {} bytes",
+                size,
+                capacity
+            );
+        }
+        self.evict_over_capacity();
+        Ok(MetadataCharge {
+            cache: self.clone(),
+            size,
+        })
+    }
+
+    /// The bytes of SST metadata charged to the cache.
+    pub fn metadata_size(&self) -> u64 {
+        self.metadata_size.load(Ordering::Relaxed)
+    }
+
+    /// Pin a block for an iterator, see `BlockHandle`. Charging a cache bounded in bytes evicts
+    /// blocks to make room for the pinned one.
+    pub fn pin(self: &Arc<Self>, block: Arc<Block>) -> BlockHandle {
+        let charge = block_size(&block) as u64;
+        self.pinned_size.fetch_add(charge, Ordering::Relaxed);
+        self.pinned_blocks.fetch_add(1, Ordering::Relaxed);
+        self.evict_over_capacity();
+        BlockHandle {
+            block,
+            cache: Some(self.clone()),
+            charge,
+        }
+    }
+
+    /// The bytes of the blocks pinned by the iterators.
+    pub fn pinned_size(&self) -> u64 {
+        self.pinned_size.load(Ordering::Relaxed)
+    }
+
+    /// The number of blocks pinned by the iterators.
+    pub fn pinned_blocks(&self) -> u64 {
+        self.pinned_blocks.load(Ordering::Relaxed)
+    }
+
+    /// The bytes of the cached blocks, the charged metadata and the pinned blocks, or the number
+    /// of cached blocks if the cache is not bounded in bytes.
+    pub fn usage(&self) -> u64 {
+        let blocks = self.pools().map(Cache::weighted_size).sum::<u64>();
+        match self.byte_capacity {
+            Some(_) => blocks + self.metadata_size() + self.pinned_size(),
+            None => blocks,
+        }
+    }
+
+    /// The bytes of the cached blocks, which are summed up over the blocks if the cache is not
+    /// bounded in bytes.
+    pub fn cached_size(&self) -> u64 {
+        match self.byte_capacity {
+            Some(_) => self.pools().map(Cache::weighted_size).sum(),
+            None => self.pools().map(Cache::block_bytes).sum(),
+        }
+    }
+
+    /// Evict blocks until they fit into the capacity left by the metadata and the pinned blocks.
+    /// The pools only bound the blocks by themselves, so this is only needed when either is
+    /// charged.
+    fn evict_over_capacity(&self) {
+        let Some(capacity) = self.byte_capacity else {
+            return;
+        };
+        if self.metadata_size() == 0 && self.pinned_size() == 0 {
+            return;
+        }
+        let mut excess = self.usage().saturating_sub(capacity);
+        for cache in self.pools() {
+            excess = cache.evict(excess);
+        }
+    }
+
+    /// The hits and misses of each level, indexed by level.
+    pub fn level_stats(&self) -> Vec<LevelCacheStats> {
+        self.level_counters
+            .read()
+            .iter()
+            .map(|counters| LevelCacheStats {
+                hits: counters.hits.load(Ordering::Relaxed),
+                misses: counters.misses.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loading sorted CSV or Parquet files, e.g., written by `crate::export`, into a storage without
+//! going through the memtables. Workers build the SSTs of the files in parallel, which are then
+//! added on top of L0 together, where the compactions eventually merge them into the levels.
+//!
+//! The keys must be strictly increasing within a file and across the files in the given order,
+//! and the storage must not have any key in the range of the import. A CSV file has a header and
+//! the key and the value in its first two columns, and a Parquet file has them in its first two
+//! columns, either UTF-8 or binary. Parsers turn the cells into keys and values, and default to
+//! their bytes as they are.
+
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use anyhow::{Context, Result, bail, ensure};
+use parking_lot::Mutex;
+
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::LsmStorageInner;
+use crate::table::SsTable;
+use crate::ttl;
+
+/// Turns a cell into a key or a value.
+pub type Parser = Box<dyn Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync>;
+
+/// Called with the progress of an import.
+pub type ProgressCallback = Box<dyn Fn(&ImportProgress) + Send + Sync>;
+
+/// Parse a lowercase or uppercase hex cell, as written by `crate::export::decode_hex`.
+pub fn parse_hex(cell: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        cell.len().is_multiple_of(2),
+        "odd length hex {:?}",
+        String::from_utf8_lossy(cell)
+    );
+    cell.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair)?;
+            u8::from_str_radix(pair, 16).with_context(|| format!("invalid hex {:?}", pair))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    #[cfg(feature = "csv")]
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub files: usize,
+    pub files_done: usize,
+    pub rows: u64,
+    pub ssts: usize,
+}
+
+pub struct BulkImport {
+    format: ImportFormat,
+    key_parser: Parser,
+    value_parser: Parser,
+    workers: usize,
+    progress: Option<ProgressCallback>,
+}
+
+impl BulkImport {
+    /// An import of files in `format` with one worker per CPU.
+    pub fn new(format: ImportFormat) -> Self {
+        Self {
+            format,
+            key_parser: Box::new(|cell| Ok(cell.to_vec())),
+            value_parser: Box::new(|cell| Ok(cell.to_vec())),
+            workers: std::thread::available_parallelism().map_or(1, |workers| workers.get()),
+            progress: None,
+        }
+    }
+
+    pub fn with_key_parser(
+        mut self,
+        parser: impl Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.key_parser = Box::new(parser);
+        self
+    }
+
+    pub fn with_value_parser(
+        mut self,
+        parser: impl Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.value_parser = Box::new(parser);
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        assert!(workers > 0, "an import needs at least one worker");
+        self.workers = workers;
+        self
+    }
+
+    /// Call `progress` every time a worker finishes an SST or a file.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(&ImportProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Call `f` with the key and the value cells of each row of a file.
+    fn for_each_row(
+        &self,
+        file: &Path,
+        mut f: impl FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        match self.format {
+            #[cfg(feature = "csv")]
+            ImportFormat::Csv => {
+                let mut reader = csv::Reader::from_path(file)?;
+                let mut record = csv::ByteRecord::new();
+                while reader.read_byte_record(&mut record)? {
+                    let (Some(key), Some(value)) = (record.get(0), record.get(1)) else {
+                        bail!(
+                            "row {} has fewer than 2 columns",
+                            record.position().map_or(0, |pos| pos.line())
+                        );
+                    };
+                    f(key, value)?;
+                }
+            }
+            #[cfg(feature = "parquet")]
+            ImportFormat::Parquet => {
+                use parquet::file::reader::{FileReader, SerializedFileReader};
+                use parquet::record::Field;
+
+                fn cell(field: &Field) -> Result<&[u8]> {
+                    match field {
+                        Field::Str(text) => Ok(text.as_bytes()),
+                        Field::Bytes(bytes) => Ok(bytes.data()),
+                        field => bail!("expected a string or binary column, got {}", field),
+                    }
+                }
+
+                let reader = SerializedFileReader::new(std::fs::File::open(file)?)?;
+                for row in reader.get_row_iter(None)? {
+                    let row = row?;
+                    let mut columns = row.get_column_iter();
+                    let (Some((_, key)), Some((_, value))) = (columns.next(), columns.next())
+                    else {
+                        bail!("a row has fewer than 2 columns");
+                    };
+                    f(cell(key)?, cell(value)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn report(&self, progress: &Mutex<ImportProgress>, update: impl FnOnce(&mut ImportProgress)) {
+        let mut progress_guard = progress.lock();
+        update(&mut progress_guard);
+        if let Some(report) = &self.progress {
+            report(&progress_guard);
+        }
+    }
+}
+
+impl LsmStorageInner {
+    /// Build the SSTs of sorted files with the workers of `import` and add them on top of L0.
+    /// Nothing is added if a file fails to parse or the keys are out of order. Returns the ids of
+    /// the imported SSTs. The rows are written at one timestamp, taken before they are read.
+    pub fn bulk_import(&self, files: &[PathBuf], import: &BulkImport) -> Result<Vec<usize>> {
+        let ts = self.reserve_ts();
+        let progress = Mutex::new(ImportProgress {
+            files: files.len(),
+            ..Default::default()
+        });
+        let next_file = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let mut results = std::thread::scope(|scope| {
+            let workers = (0..import.workers.min(files.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        while !failed.load(Ordering::Relaxed) {
+                            let idx = next_file.fetch_add(1, Ordering::Relaxed);
+                            let Some(file) = files.get(idx) else {
+                                break;
+                            };
+                            let mut ssts = Vec::new();
+                            let result =
+                                self.build_import_file(file, import, ts, &progress, &mut ssts);
+                            if result.is_err() {
+                                failed.store(true, Ordering::Relaxed);
+                            }
+                            results.push((idx, result, ssts));
+                        }
+                        results
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        results.sort_by_key(|(idx, _, _)| *idx);
+
+        let mut ssts: Vec<Arc<SsTable>> = Vec::new();
+        let mut error = None;
+        for (idx, result, file_ssts) in results {
+            if let Err(err) = result {
+                error.get_or_insert_with(|| {
+                    err.context(format!("failed to import {:?}", files[idx]))
+                });
+            }
+            if let (Some(last), Some(first)) = (ssts.last(), file_ssts.first())
+                && error.is_none()
+                && first.first_key().key_ref() <= last.last_key().key_ref()
+            {
+                error = Some(anyhow::anyhow!(
+                    "{:?} starts at key {:?}, not after the keys of the files before it",
+                    files[idx],
+                    bytes::Bytes::copy_from_slice(first.first_key().key_ref())
+                ));
+            }
+            ssts.extend(file_ssts);
+        }
+        if error.is_none()
+            && let (Some(first), Some(last)) = (ssts.first(), ssts.last())
+            && self
+                .scan(
+                    Bound::Included(first.first_key().key_ref()),
+                    Bound::Included(last.last_key().key_ref()),
+                )?
+                .is_valid()
+        {
+            error = Some(anyhow::anyhow!(
+                "cannot import range {:?}..={:?} over existing keys",
+                bytes::Bytes::copy_from_slice(first.first_key().key_ref()),
+                bytes::Bytes::copy_from_slice(last.last_key().key_ref())
+            ));
+        }
+        if let Some(err) = error {
+            for sst in ssts {
+                self.remove_sst_file(sst.sst_id())?;
+            }
+            return Err(err);
+        }
+        self.sync_new_ssts(&ssts)?;
+        self.sync_dir()?;
+        self.install_l0_ssts(ssts)
+    }
+
+    /// Build the SSTs of one file into `ssts`, which holds those built so far if it fails.
+    fn build_import_file(
+        &self,
+        file: &Path,
+        import: &BulkImport,
+        ts: u64,
+        progress: &Mutex<ImportProgress>,
+        ssts: &mut Vec<Arc<SsTable>>,
+    ) -> Result<()> {
+        let mut builder = self.new_sst_builder();
+        // The keys are not empty, so only the first row has no key before it
+        let mut last_key = Vec::new();
+        let mut row = 0;
+        // The rows since the last progress report
+        let mut rows = 0;
+        import.for_each_row(file, |key, value| {
+            row += 1;
+            let key = (import.key_parser)(key)?;
+            let mut value = (import.value_parser)(value)?;
+            ensure!(!key.is_empty(), "row {} has an empty key", row);
+            ensure!(
+                key > last_key,
+                "row {} has key {:?}, not after the key {:?} before it",
+                row,
+                bytes::Bytes::from(key),
+                bytes::Bytes::copy_from_slice(&last_key)
+            );
+            self.check_entry_size(&key, &value)?;
+            if let Some(options) = &self.options.ttl {
+                let expiry = options.now_millis() + options.ttl.as_millis() as u64;
+                value = ttl::append_expiry(&value, expiry);
+            }
+            builder.add_entry(KeySlice::from_slice(&key, ts), &value, 0, false);
+            last_key = key;
+            rows += 1;
+            if builder.estimated_size() >= self.options.target_sst_size {
+                let full = std::mem::replace(&mut builder, self.new_sst_builder());
+                ssts.push(self.build_sst(full, self.next_sst_id(), 0)?);
+                import.report(progress, |progress| {
+                    progress.rows += rows;
+                    progress.ssts += 1;
+                });
+                rows = 0;
+            }
+            Ok(())
+        })?;
+        if !builder.is_empty() {
+            ssts.push(self.build_sst(builder, self.next_sst_id(), 0)?);
+        }
+        import.report(progress, |progress| {
+            progress.rows += rows;
+            progress.ssts += usize::from(rows > 0);
+            progress.files_done += 1;
+        });
+        Ok(())
+    }
+}
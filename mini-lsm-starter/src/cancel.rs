@@ -0,0 +1,103 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cooperative cancellation of long-running reads and compactions, which check a
+//! `CancellationToken` as they go and stop with a `Cancelled` error once it is cancelled or past
+//! its deadline.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+/// The error returned by an operation stopped through its `CancellationToken`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled {
+    /// Whether the deadline of the token passed, rather than the token being cancelled.
+    pub deadline_exceeded: bool,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.deadline_exceeded {
+            true => write!(f, "operation cancelled: deadline exceeded"),
+            false => write!(f, "operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Returns true if the error is caused by a cancelled operation.
+pub fn is_cancelled_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Cancelled>().is_some()
+}
+
+/// A handle to cancel an operation, e.g., from the thread serving the request that started it.
+/// The clones of a token share its state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is cancelled once `timeout` has passed, or earlier through `cancel`.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// A token that is cancelled at `deadline`, or earlier through `cancel`.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Cancel the operations checking this token or any of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// Fail with `Cancelled` if the token is cancelled or past its deadline.
+    pub fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Acquire) {
+            return Err(Cancelled {
+                deadline_exceeded: false,
+            }
+            .into());
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Cancelled {
+                deadline_exceeded: true,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Column families: named keyspaces of one storage. The keys of a column family are stored with
+//! a prefix of `CF_KEY_PREFIX` and the 4-byte ID of the column family, so that all column families
+//! share the memtables, SSTs, WAL, manifest and options of the storage, and a `WriteBatch` over
+//! several of them is atomic like any other batch. They need `LsmStorageOptions::internal_keyspace`,
+//! which reserves the keys from `CF_KEY_PREFIX` on. The keys before it form the default column
+//! family, which the plain `MiniLsm` methods read and write.
+//!
+//! The names are mapped to their IDs by the registry, stored as keys under the ID 0. Dropping a
+//! column family deletes its keys with a range delete, and its ID is never used again. The last
+//! ID holds the windows of the idempotent writes, see `crate::idempotence`.
+//!
+//! The column families do not have memtables, SST levels or options of their own yet: a column
+//! family that needs a different compaction or block size still has to be a separate storage.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use anyhow::{Result, bail};
+use bytes::{BufMut, Bytes};
+use parking_lot::{Mutex, RwLock};
+
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+use crate::lsm_storage::LsmStorageInner;
+
+/// The prefix of the keys of the column families. The default column family cannot write the keys
+/// from it on, and its scans stop before it.
+pub const CF_KEY_PREFIX: &[u8] = b"\xff\xffcf";
+
+const CF_ID_LEN: usize = 4;
+const CF_PREFIX_LEN: usize = CF_KEY_PREFIX.len() + CF_ID_LEN;
+/// The ID of the registry, the column families starting from 1.
+const REGISTRY_ID: u32 = 0;
+/// The ID of the windows of the applied operations of the idempotent writes.
+pub(crate) const OPERATIONS_ID: u32 = u32::MAX;
+
+/// A handle to a column family, which fails the reads and writes once it is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnFamily {
+    name: String,
+    id: u32,
+}
+
+impl ColumnFamily {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The key of `key` in this column family.
+    pub(crate) fn key(&self, key: &[u8]) -> Vec<u8> {
+        prefixed_key(self.id, key)
+    }
+
+    /// The range of all the keys of this column family, the end excluded.
+    pub(crate) fn key_range(&self) -> (Vec<u8>, Vec<u8>) {
+        (self.key(&[]), prefixed_key(self.id + 1, &[]))
+    }
+
+    /// The bounds of a range of this column family.
+    pub(crate) fn bounds(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let lower = match lower {
+            Bound::Included(key) => Bound::Included(self.key(key)),
+            Bound::Excluded(key) => Bound::Excluded(self.key(key)),
+            Bound::Unbounded => Bound::Included(self.key(&[])),
+        };
+        let upper = match upper {
+            Bound::Included(key) => Bound::Included(self.key(key)),
+            Bound::Excluded(key) => Bound::Excluded(self.key(key)),
+            Bound::Unbounded => Bound::Excluded(self.key_range().1),
+        };
+        (lower, upper)
+    }
+}
+
+pub(crate) fn prefixed_key(id: u32, key: &[u8]) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(CF_PREFIX_LEN + key.len());
+    prefixed.put_slice(CF_KEY_PREFIX);
+    prefixed.put_u32(id);
+    prefixed.put_slice(key);
+    prefixed
+}
+
+/// The key of the registry holding the ID of the column family `name`. The empty name holds the
+/// next ID to assign.
+pub(crate) fn registry_key(name: &str) -> Vec<u8> {
+    prefixed_key(REGISTRY_ID, name.as_bytes())
+}
+
+/// Whether `key` is kept by the storage for itself, in the registry or the windows of the
+/// idempotent writes, rather than written by a client.
+pub(crate) fn is_internal_key(key: &[u8]) -> bool {
+    [REGISTRY_ID, OPERATIONS_ID]
+        .iter()
+        .any(|&id| key.starts_with(&prefixed_key(id, &[])))
+}
+
+/// Fail the write of `key` to the default column family if it is reserved for the others.
+pub(crate) fn check_default_key(key: &[u8]) -> Result<()> {
+    if key >= CF_KEY_PREFIX {
+        bail!(
+            "keys from {:?} are reserved for the column families, cannot write {:?}",
+            Bytes::from_static(CF_KEY_PREFIX),
+            Bytes::copy_from_slice(key)
+        );
+    }
+    Ok(())
+}
+
+/// Limit the range delete of the keys from `start` to `end`, exclusive, to the default column
+/// family.
+pub(crate) fn default_delete_range<'a>(start: &'a [u8], end: &'a [u8]) -> Result<&'a [u8]> {
+    check_default_key(start)?;
+    Ok(end.min(CF_KEY_PREFIX))
+}
+
+/// Fail if some keys from `CF_KEY_PREFIX` on were written before they were reserved.
+pub(crate) fn check_reserved_keys_unused(storage: &LsmStorageInner) -> Result<()> {
+    let iter = storage.scan_internal(Bound::Included(CF_KEY_PREFIX), Bound::Unbounded)?;
+    if iter.is_valid() {
+        bail!(
+            "cannot turn on internal_keyspace, as the keys from {:?} on are reserved and {:?} \
+             exists; move such keys first",
+            Bytes::from_static(CF_KEY_PREFIX),
+            Bytes::copy_from_slice(iter.key())
+        );
+    }
+    Ok(())
+}
+
+/// Whether the keys starting with `prefix` include some keys from `CF_KEY_PREFIX` on.
+pub(crate) fn prefix_overlaps_reserved(prefix: &[u8]) -> bool {
+    prefix >= CF_KEY_PREFIX || CF_KEY_PREFIX.starts_with(prefix)
+}
+
+/// Limit the bounds of a scan of the default column family to the keys before `CF_KEY_PREFIX`.
+pub(crate) fn default_bounds<'a>(
+    lower: Bound<&'a [u8]>,
+    upper: Bound<&'a [u8]>,
+) -> (Bound<&'a [u8]>, Bound<&'a [u8]>) {
+    let reserved = Bound::Excluded(CF_KEY_PREFIX);
+    let lower = match lower {
+        Bound::Included(key) | Bound::Excluded(key) if key >= CF_KEY_PREFIX => {
+            Bound::Included(CF_KEY_PREFIX)
+        }
+        lower => lower,
+    };
+    let upper = match upper {
+        Bound::Included(key) | Bound::Excluded(key) if key < CF_KEY_PREFIX => upper,
+        _ => reserved,
+    };
+    (lower, upper)
+}
+
+/// The column families of a storage by name, loaded from the registry when it is opened.
+#[derive(Default)]
+pub(crate) struct ColumnFamilies {
+    ids: RwLock<BTreeMap<String, u32>>,
+    next_id: Mutex<u32>,
+}
+
+impl ColumnFamilies {
+    /// Load the registry of a storage.
+    pub(crate) fn load(storage: &LsmStorageInner) -> Result<Self> {
+        let (lower, upper) = (
+            prefixed_key(REGISTRY_ID, &[]),
+            prefixed_key(REGISTRY_ID + 1, &[]),
+        );
+        let mut iter = storage.scan_internal(Bound::Included(&lower), Bound::Excluded(&upper))?;
+        let mut ids = BTreeMap::new();
+        let mut next_id = REGISTRY_ID + 1;
+        while iter.is_valid() {
+            let name = String::from_utf8(iter.key()[CF_PREFIX_LEN..].to_vec())?;
+            let id = u32::from_be_bytes(iter.value().try_into()?);
+            if name.is_empty() {
+                next_id = id;
+            } else {
+                ids.insert(name, id);
+            }
+            iter.next()?;
+        }
+        Ok(Self {
+            ids: RwLock::new(ids),
+            next_id: Mutex::new(next_id),
+        })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<ColumnFamily> {
+        self.ids.read().get(name).map(|&id| ColumnFamily {
+            name: name.to_string(),
+            id,
+        })
+    }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.ids.read().keys().cloned().collect()
+    }
+
+    /// Fail if `cf` was dropped.
+    pub(crate) fn check(&self, cf: &ColumnFamily) -> Result<()> {
+        if self.ids.read().get(&cf.name) != Some(&cf.id) {
+            bail!("column family {:?} was dropped", cf.name);
+        }
+        Ok(())
+    }
+
+    /// Fail the write of `key` by a batch unless it is a key of the default column family or of a
+    /// column family that still exists, so that a batch built with a handle that was dropped since
+    /// fails like `MiniLsm::put_cf` does.
+    pub(crate) fn check_batch_key(&self, key: &[u8]) -> Result<()> {
+        let Some(id) = key
+            .strip_prefix(CF_KEY_PREFIX)
+            .and_then(|key| key.get(..CF_ID_LEN))
+            .map(|id| u32::from_be_bytes(id.try_into().unwrap()))
+            .filter(|&id| id != REGISTRY_ID && id != OPERATIONS_ID)
+        else {
+            return check_default_key(key);
+        };
+        if !self.ids.read().values().any(|&live| live == id) {
+            bail!("column family {} of the batch was dropped", id);
+        }
+        Ok(())
+    }
+
+    /// Assign an ID to a new column family, persisted by `write` with the registry records to
+    /// write, as the key and value of each.
+    pub(crate) fn create(
+        &self,
+        name: &str,
+        write: impl FnOnce(&[(Vec<u8>, Vec<u8>)]) -> Result<()>,
+    ) -> Result<ColumnFamily> {
+        if name.is_empty() {
+            bail!("the name of a column family cannot be empty");
+        }
+        let mut next_id = self.next_id.lock();
+        if self.ids.read().contains_key(name) {
+            bail!("column family {:?} already exists", name);
+        }
+        let id = *next_id;
+        write(&[
+            (registry_key(name), id.to_be_bytes().to_vec()),
+            (registry_key(""), (id + 1).to_be_bytes().to_vec()),
+        ])?;
+        *next_id = id + 1;
+        self.ids.write().insert(name.to_string(), id);
+        Ok(ColumnFamily {
+            name: name.to_string(),
+            id,
+        })
+    }
+
+    /// Remove the column family `name`, whose keys and registry record are deleted by `delete`.
+    pub(crate) fn drop(
+        &self,
+        name: &str,
+        delete: impl FnOnce(&ColumnFamily) -> Result<()>,
+    ) -> Result<()> {
+        let _next_id = self.next_id.lock();
+        let Some(cf) = self.get(name) else {
+            bail!("column family {:?} does not exist", name);
+        };
+        delete(&cf)?;
+        self.ids.write().remove(name);
+        Ok(())
+    }
+}
+
+/// An iterator over the keys of a column family, without their prefix.
+pub struct ColumnFamilyIterator {
+    iter: FusedIterator<LsmIterator>,
+}
+
+impl ColumnFamilyIterator {
+    pub(crate) fn new(iter: FusedIterator<LsmIterator>) -> Self {
+        Self { iter }
+    }
+}
+
+impl StorageIterator for ColumnFamilyIterator {
+    type KeyType<'a> = &'a [u8];
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.iter.key()[CF_PREFIX_LEN..]
+    }
+
+    fn value(&self) -> &[u8] {
+        self.iter.value()
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.iter.value_meta()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()
+    }
+}
@@ -15,33 +15,68 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
+mod fifo;
+mod lazy_leveled;
 mod leveled;
+mod schedule;
 mod simple_leveled;
 mod tiered;
+mod version_gc;
 
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-pub use leveled::{LeveledCompactionController, LeveledCompactionOptions, LeveledCompactionTask};
+use anyhow::{Result, anyhow, bail, ensure};
+use bytes::Bytes;
+pub use fifo::{FifoCompactionController, FifoCompactionOptions, FifoCompactionTask};
+pub use lazy_leveled::{
+    LazyLeveledCompactionController, LazyLeveledCompactionOptions, LazyLeveledCompactionTask,
+};
+pub use leveled::{
+    EntryCountCompactionOptions, LeveledCompactionController, LeveledCompactionOptions,
+    LeveledCompactionTask,
+};
+use parking_lot::{Mutex, MutexGuard};
+pub(crate) use schedule::{CompactionScheduler, time_of_day_utc};
+pub use schedule::{CompactionWindow, UtilizationProbe};
 use serde::{Deserialize, Serialize};
 pub use simple_leveled::{
     SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, SimpleLeveledCompactionTask,
 };
 pub use tiered::{TieredCompactionController, TieredCompactionOptions, TieredCompactionTask};
+pub(crate) use version_gc::VersionGcIterator;
 
-use crate::lsm_storage::{LsmStorageInner, LsmStorageState};
-use crate::table::SsTable;
+use crate::block::BlockIterator;
+use crate::cancel::is_cancelled_error;
+use crate::compaction_filter::CompactionFilterIterator;
+use crate::iterators::StorageIterator;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::key::{KeyBytes, KeyVec};
+use crate::lsm_iterator::LsmIteratorStats;
+use crate::lsm_storage::{
+    BackgroundTask, LsmEvent, LsmStorageInner, LsmStorageOptions, LsmStorageState,
+};
+use crate::manifest::ManifestRecord;
+use crate::range_tombstone::{RangeDeleteIterator, RangeTombstones};
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::ttl::{ExpiryFilterIterator, TtlOptions};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CompactionTask {
     Leveled(LeveledCompactionTask),
     Tiered(TieredCompactionTask),
     Simple(SimpleLeveledCompactionTask),
+    LazyLeveled(LazyLeveledCompactionTask),
+    Fifo(FifoCompactionTask),
     ForceFullCompaction {
         l0_sstables: Vec<usize>,
         l1_sstables: Vec<usize>,
     },
+    /// Compacts some SSTs of a level into the overlapping SSTs of the next level, independently
+    /// of the compaction controller. Used by the compactions triggered by reads.
+    Partial(LeveledCompactionTask),
 }
 
 impl CompactionTask {
@@ -51,18 +86,127 @@ impl CompactionTask {
             CompactionTask::Leveled(task) => task.is_lower_level_bottom_level,
             CompactionTask::Simple(task) => task.is_lower_level_bottom_level,
             CompactionTask::Tiered(task) => task.bottom_tier_included,
+            CompactionTask::LazyLeveled(task) => task.bottom_run_included,
+            CompactionTask::Fifo(_) => false,
+            CompactionTask::Partial(task) => task.is_lower_level_bottom_level,
+        }
+    }
+
+    /// The input SSTs of the task, from the newest to the oldest.
+    pub(crate) fn input_sst_ids(&self) -> Vec<usize> {
+        match self {
+            CompactionTask::ForceFullCompaction {
+                l0_sstables,
+                l1_sstables,
+            } => [l0_sstables.as_slice(), l1_sstables.as_slice()].concat(),
+            CompactionTask::Simple(SimpleLeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            })
+            | CompactionTask::Leveled(LeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            })
+            | CompactionTask::Partial(LeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            }) => [
+                upper_level_sst_ids.as_slice(),
+                lower_level_sst_ids.as_slice(),
+            ]
+            .concat(),
+            CompactionTask::Tiered(TieredCompactionTask { tiers, .. }) => tiers
+                .iter()
+                .flat_map(|(_, tier)| tier.iter().copied())
+                .collect(),
+            CompactionTask::LazyLeveled(LazyLeveledCompactionTask {
+                l0_sstables, runs, ..
+            }) => l0_sstables
+                .iter()
+                .copied()
+                .chain(runs.iter().flat_map(|(_, run)| run.iter().copied()))
+                .collect(),
+            CompactionTask::Fifo(FifoCompactionTask { sst_ids }) => sst_ids.clone(),
         }
     }
+
+    /// The SSTs the task could move to the lower level as they are if they overlap with nothing
+    /// else: the upper SSTs of a leveled task without SSTs from the lower level.
+    fn movable_sst_ids(&self) -> Option<&[usize]> {
+        match self {
+            CompactionTask::Leveled(task) | CompactionTask::Partial(task)
+                if task.lower_level_sst_ids.is_empty()
+                    && task.upper_level != Some(task.lower_level) =>
+            {
+                Some(&task.upper_level_sst_ids)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the task drops its input SSTs without writing any output.
+    pub(crate) fn drops_input(&self) -> bool {
+        matches!(self, CompactionTask::Fifo(_))
+    }
 }
 
 pub(crate) enum CompactionController {
     Leveled(LeveledCompactionController),
     Tiered(TieredCompactionController),
     Simple(SimpleLeveledCompactionController),
+    LazyLeveled(LazyLeveledCompactionController),
+    Fifo(FifoCompactionController),
     NoCompaction,
 }
 
 impl CompactionController {
+    pub fn new(options: &CompactionOptions) -> Self {
+        match options {
+            CompactionOptions::Leveled(options) => {
+                Self::Leveled(LeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Tiered(options) => {
+                Self::Tiered(TieredCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Simple(options) => {
+                Self::Simple(SimpleLeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::LazyLeveled(options) => {
+                Self::LazyLeveled(LazyLeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Fifo(options) => {
+                Self::Fifo(FifoCompactionController::new(options.clone()))
+            }
+            CompactionOptions::NoCompaction => Self::NoCompaction,
+        }
+    }
+
+    /// Size the levels of leveled compaction by their number of entries, see
+    /// `EntryCountCompactionOptions`.
+    pub(crate) fn with_entry_counts(
+        self,
+        entry_counts: Option<&EntryCountCompactionOptions>,
+    ) -> Self {
+        match self {
+            Self::Leveled(ctrl) => Self::Leveled(ctrl.with_entry_counts(entry_counts.cloned())),
+            ctrl => ctrl,
+        }
+    }
+
+    pub fn options(&self) -> CompactionOptions {
+        match self {
+            Self::Leveled(ctrl) => CompactionOptions::Leveled(ctrl.options().clone()),
+            Self::Tiered(ctrl) => CompactionOptions::Tiered(ctrl.options().clone()),
+            Self::Simple(ctrl) => CompactionOptions::Simple(ctrl.options().clone()),
+            Self::LazyLeveled(ctrl) => CompactionOptions::LazyLeveled(ctrl.options().clone()),
+            Self::Fifo(ctrl) => CompactionOptions::Fifo(ctrl.options().clone()),
+            Self::NoCompaction => CompactionOptions::NoCompaction,
+        }
+    }
+
     pub fn generate_compaction_task(&self, snapshot: &LsmStorageState) -> Option<CompactionTask> {
         match self {
             CompactionController::Leveled(ctrl) => ctrl
@@ -74,10 +218,41 @@ impl CompactionController {
             CompactionController::Tiered(ctrl) => ctrl
                 .generate_compaction_task(snapshot)
                 .map(CompactionTask::Tiered),
+            CompactionController::LazyLeveled(ctrl) => ctrl
+                .generate_compaction_task(snapshot)
+                .map(CompactionTask::LazyLeveled),
+            CompactionController::Fifo(ctrl) => ctrl
+                .generate_compaction_task(snapshot)
+                .map(CompactionTask::Fifo),
             CompactionController::NoCompaction => unreachable!(),
         }
     }
 
+    /// Generate up to `max_tasks` tasks whose inputs are disjoint from each other and from the
+    /// SSTs already being compacted. Only leveled compaction splits its work into several tasks,
+    /// the other controllers generate at most the task of `generate_compaction_task`.
+    pub fn generate_compaction_tasks(
+        &self,
+        snapshot: &LsmStorageState,
+        max_tasks: usize,
+    ) -> Vec<CompactionTask> {
+        if let CompactionController::Leveled(ctrl) = self {
+            return ctrl
+                .generate_compaction_tasks(snapshot, max_tasks)
+                .into_iter()
+                .map(CompactionTask::Leveled)
+                .collect();
+        }
+        self.generate_compaction_task(snapshot)
+            .filter(|task| {
+                task.input_sst_ids()
+                    .iter()
+                    .all(|id| !snapshot.sstables[id].is_being_compacted())
+            })
+            .into_iter()
+            .collect()
+    }
+
     pub fn apply_compaction_result(
         &self,
         snapshot: &LsmStorageState,
@@ -86,6 +261,9 @@ impl CompactionController {
         in_recovery: bool,
     ) -> (LsmStorageState, Vec<usize>) {
         match (self, task) {
+            (_, CompactionTask::Partial(task)) => {
+                apply_partial_compaction_result(snapshot, task, output, in_recovery)
+            }
             (CompactionController::Leveled(ctrl), CompactionTask::Leveled(task)) => {
                 ctrl.apply_compaction_result(snapshot, task, output, in_recovery)
             }
@@ -95,21 +273,37 @@ impl CompactionController {
             (CompactionController::Tiered(ctrl), CompactionTask::Tiered(task)) => {
                 ctrl.apply_compaction_result(snapshot, task, output)
             }
+            (CompactionController::LazyLeveled(ctrl), CompactionTask::LazyLeveled(task)) => {
+                ctrl.apply_compaction_result(snapshot, task, output)
+            }
+            (CompactionController::Fifo(ctrl), CompactionTask::Fifo(task)) => {
+                ctrl.apply_compaction_result(snapshot, task)
+            }
             _ => unreachable!(),
         }
     }
 }
 
 impl CompactionController {
+    /// The level below L0 that L0 is compacted into, `None` without sorted levels.
+    pub(crate) fn l0_target_level(&self, snapshot: &LsmStorageState) -> Option<usize> {
+        match self {
+            Self::Leveled(ctrl) => Some(ctrl.base_level(snapshot)),
+            Self::Simple(_) | Self::NoCompaction => Some(1),
+            Self::Tiered(_) | Self::LazyLeveled(_) | Self::Fifo(_) => None,
+        }
+    }
+
+    /// Whether the flushes go on top of L0, rather than into a new tier.
     pub fn flush_to_l0(&self) -> bool {
-        matches!(
-            self,
-            Self::Leveled(_) | Self::Simple(_) | Self::NoCompaction
-        )
+        !matches!(self, Self::Tiered(_))
     }
 }
 
-#[derive(Debug, Clone)]
+/// The controllers have no randomness: ties between levels or SSTs are broken by level and SST
+/// ID, so the same sequence of flushes always produces the same level layouts. Tests and the
+/// compaction simulator get reproducible layouts by seeding the workload they generate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompactionOptions {
     /// Leveled compaction with partial compaction + dynamic level support (= RocksDB's Leveled
     /// Compaction)
@@ -118,56 +312,1536 @@ pub enum CompactionOptions {
     Tiered(TieredCompactionOptions),
     /// Simple leveled compaction
     Simple(SimpleLeveledCompactionOptions),
+    /// Tiered levels on top of a leveled last level (= Dostoevsky's lazy leveling)
+    LazyLeveled(LazyLeveledCompactionOptions),
+    /// Drop the oldest SSTs once they exceed a size budget (= RocksDB's FIFO compaction)
+    Fifo(FifoCompactionOptions),
     /// In no compaction mode (week 1), always flush to L0
     NoCompaction,
 }
 
+impl CompactionOptions {
+    pub fn style(&self) -> &'static str {
+        match self {
+            Self::Leveled(_) => "leveled",
+            Self::Tiered(_) => "tiered",
+            Self::Simple(_) => "simple leveled",
+            Self::LazyLeveled(_) => "lazy leveled",
+            Self::Fifo(_) => "fifo",
+            Self::NoCompaction => "no compaction",
+        }
+    }
+
+    /// Whether the SSTs below L0 form sorted levels, as opposed to overlapping tiers.
+    pub fn has_sorted_levels(&self) -> bool {
+        !matches!(self, Self::Tiered(_) | Self::LazyLeveled(_) | Self::Fifo(_))
+    }
+
+    /// The number of levels below L0, `None` for tiered and lazy leveled compaction, where the
+    /// number of runs changes over time, and for FIFO compaction, which keeps the SSTs in L0.
+    pub fn num_levels(&self) -> Option<usize> {
+        match self {
+            Self::Leveled(LeveledCompactionOptions { max_levels, .. })
+            | Self::Simple(SimpleLeveledCompactionOptions { max_levels, .. }) => Some(*max_levels),
+            Self::Tiered(_) | Self::LazyLeveled(_) | Self::Fifo(_) => None,
+            Self::NoCompaction => Some(1),
+        }
+    }
+
+    /// The most sorted runs of SSTs the controller leaves before a flush makes it compact them,
+    /// `None` without compaction.
+    pub fn sorted_runs_before_compaction(&self) -> Option<usize> {
+        match self {
+            Self::Leveled(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger,
+                max_levels,
+                ..
+            })
+            | Self::Simple(SimpleLeveledCompactionOptions {
+                level0_file_num_compaction_trigger,
+                max_levels,
+                ..
+            }) => Some(level0_file_num_compaction_trigger + max_levels),
+            Self::Tiered(TieredCompactionOptions { num_tiers, .. }) => Some(*num_tiers),
+            // Up to `size_ratio` L0 SSTs, `size_ratio - 1` runs in each level above the last
+            // one, and the last level
+            Self::LazyLeveled(LazyLeveledCompactionOptions {
+                size_ratio,
+                max_levels,
+            }) => Some(size_ratio + (max_levels - 1) * (size_ratio - 1) + 1),
+            // Only the size of the SSTs is bounded
+            Self::Fifo(_) | Self::NoCompaction => None,
+        }
+    }
+
+    /// Describe the change from this compaction layout to the one of `new`, if the SSTs laid out
+    /// for this one have to be compacted into one sorted run first.
+    pub fn layout_change(&self, new: &CompactionOptions) -> Option<String> {
+        if self.style() != new.style() {
+            return Some(format!(
+                "compaction style changed from {} to {}",
+                self.style(),
+                new.style()
+            ));
+        }
+        match (self.num_levels(), new.num_levels()) {
+            (Some(old_levels), Some(new_levels)) if old_levels != new_levels => Some(format!(
+                "number of levels changed from {} to {}",
+                old_levels, new_levels
+            )),
+            _ => None,
+        }
+    }
+
+    /// Check for values the compaction controllers cannot work with.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Leveled(options) => {
+                ensure!(
+                    options.max_levels >= 1,
+                    "leveled compaction needs at least 1 level, got max_levels = 0"
+                );
+                ensure!(
+                    options.level_size_multiplier >= 2,
+                    "leveled compaction needs a level_size_multiplier of at least 2, got {}",
+                    options.level_size_multiplier
+                );
+                ensure!(
+                    options.level0_file_num_compaction_trigger >= 1,
+                    "level0_file_num_compaction_trigger must be at least 1"
+                );
+                ensure!(
+                    options.base_level_size_mb >= 1,
+                    "base_level_size_mb must be at least 1"
+                );
+            }
+            Self::Simple(options) => {
+                ensure!(
+                    options.max_levels >= 1,
+                    "simple leveled compaction needs at least 1 level, got max_levels = 0"
+                );
+                ensure!(
+                    options.level0_file_num_compaction_trigger >= 1,
+                    "level0_file_num_compaction_trigger must be at least 1"
+                );
+            }
+            Self::Tiered(options) => {
+                ensure!(
+                    options.num_tiers >= 2,
+                    "tiered compaction needs num_tiers of at least 2, got {}",
+                    options.num_tiers
+                );
+                ensure!(
+                    options.min_merge_width >= 2,
+                    "tiered compaction needs a min_merge_width of at least 2, got {}",
+                    options.min_merge_width
+                );
+                if let Some(max_merge_width) = options.max_merge_width {
+                    ensure!(
+                        max_merge_width >= options.min_merge_width,
+                        "max_merge_width ({}) is less than min_merge_width ({})",
+                        max_merge_width,
+                        options.min_merge_width
+                    );
+                }
+            }
+            Self::LazyLeveled(options) => {
+                ensure!(
+                    options.max_levels >= 2,
+                    "lazy leveled compaction needs at least 2 levels, got max_levels = {}",
+                    options.max_levels
+                );
+                ensure!(
+                    options.size_ratio >= 2,
+                    "lazy leveled compaction needs a size_ratio of at least 2, got {}",
+                    options.size_ratio
+                );
+            }
+            Self::Fifo(options) => {
+                ensure!(
+                    options.max_table_files_size >= 1,
+                    "max_table_files_size must be at least 1"
+                );
+            }
+            Self::NoCompaction => {}
+        }
+        Ok(())
+    }
+}
+
+/// Remove the input SSTs of a task that compacts some SSTs of a level into the overlapping SSTs
+/// of the level below, and add the output to the lower level.
+fn apply_partial_compaction_result(
+    snapshot: &LsmStorageState,
+    task: &LeveledCompactionTask,
+    output: &[usize],
+    in_recovery: bool,
+) -> (LsmStorageState, Vec<usize>) {
+    let mut snapshot = snapshot.clone();
+    let upper_level_sst_ids = task
+        .upper_level_sst_ids
+        .iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    let lower_level_sst_ids = task
+        .lower_level_sst_ids
+        .iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    match task.upper_level {
+        Some(upper_level) => snapshot.levels[upper_level - 1]
+            .1
+            .retain(|id| !upper_level_sst_ids.contains(id)),
+        None => snapshot
+            .l0_sstables
+            .retain(|id| !upper_level_sst_ids.contains(id)),
+    }
+    let sstables = &snapshot.sstables;
+    let lower_level = &mut snapshot.levels[task.lower_level - 1].1;
+    lower_level.retain(|id| !lower_level_sst_ids.contains(id));
+    lower_level.extend_from_slice(output);
+    // The SSTs are not loaded yet during recovery, they are sorted after recovery instead
+    if !in_recovery {
+        lower_level.sort_by(|a, b| sstables[a].first_key().cmp(sstables[b].first_key()));
+    }
+    let files_to_remove = [
+        task.upper_level_sst_ids.as_slice(),
+        task.lower_level_sst_ids.as_slice(),
+    ]
+    .concat();
+    (snapshot, files_to_remove)
+}
+
+/// The input SSTs a compaction task claimed, released when it is dropped. Two tasks scheduled at
+/// the same time can never claim the same SST, so that they can run in parallel without
+/// compacting an SST twice.
+pub(crate) struct CompactionClaim {
+    ssts: Vec<Arc<SsTable>>,
+}
+
+impl CompactionClaim {
+    /// Claim all of `sst_ids`, or none of them if another task claimed any.
+    pub(crate) fn try_claim(snapshot: &LsmStorageState, sst_ids: &[usize]) -> Option<Self> {
+        let mut claim = Self {
+            ssts: Vec::with_capacity(sst_ids.len()),
+        };
+        for id in sst_ids {
+            let sst = &snapshot.sstables[id];
+            if !sst.try_claim_for_compaction() {
+                // Dropping the partial claim releases the SSTs claimed so far
+                return None;
+            }
+            claim.ssts.push(sst.clone());
+        }
+        Some(claim)
+    }
+
+    /// Claim `sst_ids` for a task that cannot be retried later.
+    fn claim(snapshot: &LsmStorageState, sst_ids: &[usize]) -> Result<Self> {
+        Self::try_claim(snapshot, sst_ids)
+            .ok_or_else(|| anyhow!("some of the SSTs are being compacted by another task"))
+    }
+}
+
+impl Drop for CompactionClaim {
+    fn drop(&mut self) {
+        for sst in &self.ssts {
+            sst.release_compaction_claim();
+        }
+    }
+}
+
+/// Options for compacting key ranges that scans found to be mostly tombstones.
+#[derive(Debug, Clone)]
+pub struct DeletionCompactionOptions {
+    /// The minimum number of tombstones a scan must skip to report its range.
+    pub min_tombstones: usize,
+    /// The minimum percentage of tombstones among the entries visited by the scan.
+    pub tombstone_ratio_percent: usize,
+}
+
+/// Bounds the memory used by ranges waiting for the compaction thread.
+const MAX_PENDING_DELETION_RANGES: usize = 64;
+
+/// Collects the key ranges that `LsmIterator`s found to be tombstone-heavy, so that the
+/// compaction thread can compact them before the tombstones reach the bottom level on their own.
+pub(crate) struct DeletionCollector {
+    options: Option<DeletionCompactionOptions>,
+    /// The first key and the last key (`None` if unbounded) of each reported range.
+    ranges: Mutex<Vec<(Bytes, Option<Bytes>)>>,
+}
+
+impl DeletionCollector {
+    pub(crate) fn new(options: Option<DeletionCompactionOptions>) -> Self {
+        Self {
+            options,
+            ranges: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        first_key: Bytes,
+        last_key: Option<Bytes>,
+        stats: LsmIteratorStats,
+    ) {
+        let Some(options) = &self.options else {
+            return;
+        };
+        let visited = stats.tombstones_skipped + stats.entries_returned;
+        if stats.tombstones_skipped < options.min_tombstones
+            || stats.tombstones_skipped * 100 < visited * options.tombstone_ratio_percent
+        {
+            return;
+        }
+        let mut ranges = self.ranges.lock();
+        if ranges.len() < MAX_PENDING_DELETION_RANGES {
+            ranges.push((first_key, last_key));
+        }
+    }
+
+    fn pop(&self) -> Option<(Bytes, Option<Bytes>)> {
+        self.ranges.lock().pop()
+    }
+
+    /// Return a range taken with `pop` whose compaction was deferred, so that it is popped next.
+    fn push_back(&self, first_key: Bytes, last_key: Option<Bytes>) {
+        self.ranges.lock().push((first_key, last_key));
+    }
+
+    /// The pending ranges, in the order `pop` returns them.
+    fn pending(&self) -> Vec<(Bytes, Option<Bytes>)> {
+        self.ranges.lock().iter().rev().cloned().collect()
+    }
+}
+
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A handle to a background flush or compaction. It resolves once the work finishes, so that
+/// callers don't need to poll the LSM structure to know when the work is done.
+pub struct TaskHandle {
+    rx: crossbeam_channel::Receiver<Result<()>>,
+}
+
+impl TaskHandle {
+    fn new() -> (crossbeam_channel::Sender<Result<()>>, Self) {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        (tx, Self { rx })
+    }
+
+    /// Run `f` on a new thread and return a handle that resolves to its result.
+    pub(crate) fn spawn(f: impl FnOnce() -> Result<()> + Send + 'static) -> Self {
+        let (tx, handle) = Self::new();
+        std::thread::spawn(move || {
+            tx.send(f()).ok();
+        });
+        handle
+    }
+
+    /// Block until the task finishes and return its result.
+    pub fn wait(self) -> Result<()> {
+        self.rx
+            .recv()
+            .map_err(|_| anyhow!("the task was dropped before it finished"))?
+    }
+
+    /// Block until the task finishes or the timeout elapses. Returns `None` on timeout, in which
+    /// case the handle can be waited on again.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Result<()>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => Some(result),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => None,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                Some(Err(anyhow!("the task was dropped before it finished")))
+            }
+        }
+    }
+}
+
+/// Resolves the `TaskHandle`s of everyone waiting for the next run of a background task.
+#[derive(Default)]
+pub(crate) struct TaskNotifier {
+    waiters: Mutex<Vec<crossbeam_channel::Sender<Result<()>>>>,
+}
+
+impl TaskNotifier {
+    pub(crate) fn subscribe(&self) -> TaskHandle {
+        let (tx, handle) = TaskHandle::new();
+        self.waiters.lock().push(tx);
+        handle
+    }
+
+    pub(crate) fn notify(&self, result: &Result<()>) {
+        for waiter in self.waiters.lock().drain(..) {
+            let result = match result {
+                Ok(()) => Ok(()),
+                Err(e) => Err(anyhow!("{:#}", e)),
+            };
+            waiter.send(result).ok();
+        }
+    }
+}
+
+/// Why a compaction was picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionReason {
+    /// The compaction controller picked it based on the shape of the levels.
+    Controller,
+    /// Scans found a key range with many tombstones, see `DeletionCompactionOptions`.
+    DeletionRange,
+    /// An SST was read in vain too often, see `seek_compaction_threshold`.
+    SeekMisses,
+    /// Most entries of an SST have expired, see `TtlOptions`.
+    Expired,
+}
+
+/// The size of a level, or of a tier with tiered compaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelSize {
+    /// 0 for L0, otherwise the id of the level or tier.
+    pub level: usize,
+    pub num_ssts: usize,
+    pub size_bytes: u64,
+    pub num_entries: u64,
+}
+
+/// The compaction the compaction thread would run next, see `LsmStorageInner::explain_compaction`.
+#[derive(Debug)]
+pub struct CompactionPlan {
+    pub task: CompactionTask,
+    pub reason: CompactionReason,
+    pub input_sst_ids: Vec<usize>,
+    pub input_size: u64,
+    /// An upper bound of the output size, as compactions only drop entries.
+    pub estimated_output_size: u64,
+    /// The sizes of the levels the task was picked from.
+    pub levels: Vec<LevelSize>,
+}
+
+impl CompactionPlan {
+    pub(crate) fn new(
+        snapshot: &LsmStorageState,
+        task: CompactionTask,
+        reason: CompactionReason,
+    ) -> Self {
+        let level_size = |level, sst_ids: &[usize]| LevelSize {
+            level,
+            num_ssts: sst_ids.len(),
+            size_bytes: sst_ids
+                .iter()
+                .map(|id| snapshot.sstables[id].table_size())
+                .sum(),
+            num_entries: sst_ids
+                .iter()
+                .map(|id| snapshot.sstables[id].properties().num_entries)
+                .sum(),
+        };
+        let mut levels = vec![level_size(0, &snapshot.l0_sstables)];
+        levels.extend(
+            snapshot
+                .levels
+                .iter()
+                .map(|(level, sst_ids)| level_size(*level, sst_ids)),
+        );
+        Self {
+            input_sst_ids: task.input_sst_ids(),
+            input_size: LsmStorageInner::compaction_input_size(snapshot, &task),
+            estimated_output_size: LsmStorageInner::estimated_output_size(snapshot, &task),
+            reason,
+            task,
+            levels,
+        }
+    }
+}
+
 impl LsmStorageInner {
-    fn compact(&self, _task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
-        unimplemented!()
+    /// Explain the compaction the compaction thread would run next, without running it. The
+    /// controller is asked first, then the compactions triggered by reads and TTLs are checked in
+    /// the order the compaction thread checks them.
+    pub fn explain_compaction(&self) -> Option<CompactionPlan> {
+        let compaction_controller = self.compaction_controller();
+        if let CompactionController::NoCompaction = *compaction_controller {
+            return None;
+        }
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let (task, reason) = match compaction_controller.generate_compaction_task(&snapshot) {
+            Some(task) => (task, CompactionReason::Controller),
+            None => self.triggered_compaction_task(&snapshot)?,
+        };
+        Some(CompactionPlan::new(&snapshot, task, reason))
+    }
+
+    /// The compaction triggered by reads or TTLs that would run next, without consuming the
+    /// triggers. The TTL compaction is reported even if its `compaction_interval` has not
+    /// elapsed yet.
+    pub(crate) fn triggered_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<(CompactionTask, CompactionReason)> {
+        let deletion_task =
+            self.deletion_collector
+                .pending()
+                .into_iter()
+                .find_map(|(first_key, last_key)| {
+                    Self::deletion_compaction_task(snapshot, &first_key, last_key.as_deref())
+                });
+        if let Some(task) = deletion_task {
+            return Some((task, CompactionReason::DeletionRange));
+        }
+        let seek_compaction_sst = *self.seek_compaction_sst.lock();
+        if let Some(task) =
+            seek_compaction_sst.and_then(|sst_id| Self::seek_compaction_task(snapshot, sst_id))
+        {
+            return Some((task, CompactionReason::SeekMisses));
+        }
+        self.ttl_compaction_task(snapshot)
+            .map(|task| (task, CompactionReason::Expired))
+    }
+
+    fn build_compaction_output(
+        &self,
+        builder: SsTableBuilder,
+        data_path: usize,
+    ) -> Result<Arc<SsTable>> {
+        let sst_id = self.next_sst_id();
+        self.build_sst(builder, sst_id, data_path)
+    }
+
+    /// The size of the input SSTs of a task, which bounds the size of its output.
+    fn compaction_input_size(snapshot: &LsmStorageState, task: &CompactionTask) -> u64 {
+        task.input_sst_ids()
+            .iter()
+            .map(|id| snapshot.sstables[id].table_size())
+            .sum()
+    }
+
+    /// The output is at most as large as the input, and empty if the input is dropped.
+    fn estimated_output_size(snapshot: &LsmStorageState, task: &CompactionTask) -> u64 {
+        if task.drops_input() {
+            0
+        } else {
+            Self::compaction_input_size(snapshot, task)
+        }
+    }
+
+    /// Record the data path of the compaction output outside of the first data path.
+    fn record_data_paths(&self, state_lock: &MutexGuard<()>, output: &[usize]) -> Result<()> {
+        if let Some(manifest) = &self.manifest {
+            for &sst_id in output {
+                let data_path = self.data_path_of_sst(sst_id);
+                if data_path != 0 {
+                    manifest.add_record(state_lock, ManifestRecord::DataPath(sst_id, data_path))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Split the input SSTs into groups whose key ranges overlap, ordered by key range. The SSTs
+    /// in each group keep their order in `sst_ids`, from the newest to the oldest.
+    fn group_overlapping_ssts(
+        snapshot: &LsmStorageState,
+        sst_ids: &[usize],
+    ) -> Vec<Vec<Arc<SsTable>>> {
+        let mut sorted = sst_ids
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| (idx, snapshot.sstables[id].clone()))
+            .collect::<Vec<_>>();
+        sorted.sort_by(|(_, a), (_, b)| a.first_key().cmp(b.first_key()));
+
+        // The versions of a key overlap whatever their timestamps are
+        let mut groups: Vec<Vec<(usize, Arc<SsTable>)>> = Vec::new();
+        let mut group_last_key: Option<KeyBytes> = None;
+        for (idx, sst) in sorted {
+            match &group_last_key {
+                Some(last_key) if sst.first_key().key_ref() <= last_key.key_ref() => {
+                    if sst.last_key().key_ref() > last_key.key_ref() {
+                        group_last_key = Some(sst.last_key().clone());
+                    }
+                    groups.last_mut().unwrap().push((idx, sst));
+                }
+                _ => {
+                    group_last_key = Some(sst.last_key().clone());
+                    groups.push(vec![(idx, sst)]);
+                }
+            }
+        }
+        groups
+            .into_iter()
+            .map(|mut group| {
+                group.sort_by_key(|(idx, _)| *idx);
+                group.into_iter().map(|(_, sst)| sst).collect()
+            })
+            .collect()
+    }
+
+    /// Compact the SSTs of `task` into new SSTs, recording the CPU time it takes.
+    fn compact(&self, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
+        (self.cpu_usage).measure(BackgroundTask::Compaction, || self.compact_ssts(task))
+    }
+
+    fn compact_ssts(&self, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
+        if task.drops_input() {
+            return Ok(Vec::new());
+        }
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        if let Some(ssts) = self.trivial_move(&snapshot, task)? {
+            return Ok(ssts);
+        }
+
+        // SSTs are listed from the newest to the oldest, so that the merge iterator prefers the
+        // latest version of a key.
+        let sst_ids = task.input_sst_ids();
+
+        let compact_to_bottom_level = task.compact_to_bottom_level();
+        let watermark = self.mvcc().watermark();
+        let expiry_now = self.options.ttl.as_ref().map(TtlOptions::now_millis);
+        let target_size = self.options.target_sst_size;
+        let data_path = self.data_path_for(&snapshot, Self::compaction_input_size(&snapshot, task));
+        let mut builder = self.new_sst_builder();
+        let mut new_ssts = Vec::new();
+        // Checked for each block or output SST, dropping the outputs so far once cancelled
+        let cancellation = self.compaction_cancellation.lock().clone();
+        let check_cancelled = |new_ssts: &[Arc<SsTable>]| {
+            let result = cancellation.check();
+            if result.is_err() {
+                for sst in new_ssts {
+                    self.remove_sst_file(sst.sst_id()).ok();
+                }
+            }
+            result
+        };
+        for group in Self::group_overlapping_ssts(&snapshot, &sst_ids) {
+            if let [sst] = group.as_slice()
+                && self.options.compaction_filter.is_none()
+                && sst.range_tombstones().is_empty()
+                && !expiry_now.is_some_and(|now| sst.properties().expiry_histogram.has_expired(now))
+            {
+                // Nothing else overlaps with this SST, so its blocks can be copied as-is, except
+                // those with versions older than the latest one at or below the watermark, which
+                // may continue from the previous block. In the bottom level, the deletes at or
+                // below the watermark are dropped as well.
+                let mut gc_key = Vec::new();
+                let metadata = sst.metadata()?;
+                let block_meta = metadata.block_meta();
+                for block_idx in 0..sst.num_of_blocks() {
+                    check_cancelled(&new_ssts)?;
+                    let (block, encoded) = sst.read_block_for_copy(block_idx)?;
+                    let continues_gc_key = block.first_key().key_ref() == gc_key;
+                    if continues_gc_key
+                        || block.has_multiple_versions()
+                        || (compact_to_bottom_level && block.has_deletes())
+                    {
+                        let mut iter = BlockIterator::create_and_seek_to_first(block);
+                        while iter.is_valid() {
+                            let key = iter.key();
+                            if key.key_ref() != gc_key {
+                                if key.ts() <= watermark {
+                                    gc_key.clear();
+                                    gc_key.extend_from_slice(key.key_ref());
+                                }
+                                let drop_delete = compact_to_bottom_level
+                                    && iter.is_deleted()
+                                    && key.ts() <= watermark;
+                                if !drop_delete {
+                                    builder.add_entry(
+                                        key,
+                                        iter.value(),
+                                        iter.value_meta(),
+                                        iter.is_deleted(),
+                                    );
+                                }
+                            }
+                            iter.next();
+                        }
+                    } else {
+                        builder.add_encoded_block(block, &encoded);
+                        let last_key = &block_meta[block_idx].last_key;
+                        gc_key.clear();
+                        if last_key.ts() <= watermark {
+                            gc_key.extend_from_slice(last_key.key_ref());
+                        }
+                    }
+                    // The versions of a key stay in one SST
+                    let next_block_continues_key =
+                        block_meta.get(block_idx + 1).is_some_and(|meta| {
+                            meta.first_key.key_ref() == block_meta[block_idx].last_key.key_ref()
+                        });
+                    if builder.estimated_size() >= target_size && !next_block_continues_key {
+                        let builder = std::mem::replace(&mut builder, self.new_sst_builder());
+                        new_ssts.push(self.build_compaction_output(builder, data_path)?);
+                    }
+                }
+                continue;
+            }
+            // The range tombstones of an SST drop the keys it covers in the older SSTs, and are
+            // kept for the levels below unless this is the bottom level
+            let mut iters = Vec::with_capacity(group.len());
+            let mut tombstones = RangeTombstones::new();
+            for sst in group {
+                let newer_tombstones = Arc::new(tombstones.clone());
+                tombstones.extend(sst.range_tombstones());
+                let iter = SsTableIterator::create_and_seek_to_first(sst)?;
+                // The tombstones hide the older versions from the reads at or after their
+                // timestamps, which are all the reads once they are below the watermark
+                iters.push(Box::new(RangeDeleteIterator::for_compaction(
+                    iter,
+                    newer_tombstones,
+                    watermark,
+                )?));
+            }
+            if compact_to_bottom_level {
+                tombstones = RangeTombstones::new();
+            }
+            let iter = CompactionFilterIterator::new(
+                ExpiryFilterIterator::new(MergeIterator::create(iters), expiry_now),
+                self.options.compaction_filter.clone(),
+                self.options.ttl.is_some(),
+            );
+            let mut iter = VersionGcIterator::new(iter, watermark, compact_to_bottom_level)?;
+            while iter.is_valid() {
+                check_cancelled(&new_ssts)?;
+                builder.add_sorted_entries(&mut iter, target_size, false)?;
+                // Each output SST keeps the part of the tombstones before its next one
+                if iter.is_valid() {
+                    builder.add_range_tombstones(&tombstones.split_before(iter.key().key_ref()));
+                } else {
+                    builder.add_range_tombstones(&std::mem::take(&mut tombstones));
+                }
+                if builder.estimated_size() >= target_size {
+                    let builder = std::mem::replace(&mut builder, self.new_sst_builder());
+                    new_ssts.push(self.build_compaction_output(builder, data_path)?);
+                }
+            }
+            builder.add_range_tombstones(&tombstones);
+        }
+        if !builder.is_empty() {
+            new_ssts.push(self.build_compaction_output(builder, data_path)?);
+        }
+        if compact_to_bottom_level
+            && self.options.verify_bottom_level_compaction
+            && let Err(e) = Self::verify_bottom_level_output(&new_ssts, watermark)
+        {
+            for sst in &new_ssts {
+                self.remove_sst_file(sst.sst_id()).ok();
+            }
+            return Err(e);
+        }
+        // Synced together once all outputs are written, right before the callers commit them
+        self.sync_new_ssts(&new_ssts)?;
+        Ok(new_ssts)
+    }
+
+    /// The input SSTs of `task` in key order if they can be moved to the lower level as they are,
+    /// see `LsmStorageOptions::sequential_writes`: they overlap with nothing else, none has
+    /// expired entries or tombstones to drop in the bottom level, and no compaction filter is set.
+    fn trivial_move(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+    ) -> Result<Option<Vec<Arc<SsTable>>>> {
+        if !self.options.sequential_writes || self.options.compaction_filter.is_some() {
+            return Ok(None);
+        }
+        let Some(sst_ids) = task.movable_sst_ids() else {
+            return Ok(None);
+        };
+        let groups = Self::group_overlapping_ssts(snapshot, sst_ids);
+        if groups.iter().any(|group| group.len() > 1) {
+            return Ok(None);
+        }
+        let ssts = groups.into_iter().flatten().collect::<Vec<_>>();
+        let expiry_now = self.options.ttl.as_ref().map(TtlOptions::now_millis);
+        for sst in &ssts {
+            if expiry_now.is_some_and(|now| sst.properties().expiry_histogram.has_expired(now)) {
+                return Ok(None);
+            }
+            if task.compact_to_bottom_level() {
+                if !sst.range_tombstones().is_empty() {
+                    return Ok(None);
+                }
+                // Nor old versions to drop
+                let versions_span_blocks = sst
+                    .metadata()?
+                    .block_meta()
+                    .windows(2)
+                    .any(|metas| metas[0].last_key.key_ref() == metas[1].first_key.key_ref());
+                if versions_span_blocks {
+                    return Ok(None);
+                }
+                for block_idx in 0..sst.num_of_blocks() {
+                    let block = sst.read_block_cached(block_idx)?;
+                    if block.has_deletes() || block.has_multiple_versions() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        Ok(Some(ssts))
+    }
+
+    /// Check that the output of a compaction to the bottom level, given in key order, has one
+    /// version of each key at or below `watermark` and no tombstones there, as there is nothing
+    /// below for them to shadow.
+    pub(crate) fn verify_bottom_level_output(ssts: &[Arc<SsTable>], watermark: u64) -> Result<()> {
+        let mut prev_key = KeyVec::new();
+        for sst in ssts {
+            let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone())?;
+            while iter.is_valid() {
+                let key = iter.key();
+                if iter.is_deleted() && key.ts() <= watermark {
+                    bail!(
+                        "bottom level compaction output SST {} has a tombstone for key {:?}",
+                        sst.sst_id(),
+                        key
+                    );
+                }
+                let older_version =
+                    prev_key.key_ref() == key.key_ref() && prev_key.ts() <= watermark;
+                if !prev_key.is_empty() && (key <= prev_key.as_key_slice() || older_version) {
+                    bail!(
+                        "bottom level compaction output has key {:?} more than once or out of \
+                         order, in SST {}",
+                        key,
+                        sst.sst_id()
+                    );
+                }
+                prev_key.set_from_slice(iter.key());
+                iter.next()?;
+            }
+        }
+        Ok(())
     }
 
     pub fn force_full_compaction(&self) -> Result<()> {
-        unimplemented!()
+        let _compaction_lock = self.compaction_lock.lock();
+        let CompactionController::NoCompaction = *self.compaction_controller() else {
+            bail!("full compaction can only be called when compaction is not enabled");
+        };
+
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+
+        let l0_sstables = snapshot.l0_sstables.clone();
+        let l1_sstables = snapshot.levels[0].1.clone();
+        let compaction_task = CompactionTask::ForceFullCompaction {
+            l0_sstables: l0_sstables.clone(),
+            l1_sstables: l1_sstables.clone(),
+        };
+        let _claim = CompactionClaim::claim(&snapshot, &compaction_task.input_sst_ids())?;
+        let sstables = self.compact(&compaction_task)?;
+        let ids = sstables.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+
+        {
+            let state_lock = self.state_lock.lock();
+            let mut state = self.state.read().as_ref().clone();
+            for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
+                let result = state.sstables.remove(sst);
+                assert!(result.is_some());
+            }
+            for new_sst in sstables {
+                let result = state.sstables.insert(new_sst.sst_id(), new_sst);
+                assert!(result.is_none());
+            }
+            assert_eq!(l1_sstables, state.levels[0].1);
+            state.levels[0].1.clone_from(&ids);
+            // New SSTs may have been flushed to L0 while we were compacting
+            let mut l0_sstables_map = l0_sstables.iter().copied().collect::<HashSet<_>>();
+            state.l0_sstables.retain(|id| !l0_sstables_map.remove(id));
+            assert!(l0_sstables_map.is_empty());
+            state.update_sst_levels();
+            self.quotas.refresh(&state);
+            self.key_distribution.refresh(&state);
+            *self.state.write() = Arc::new(state);
+            self.sync_dir()?;
+            if let Some(manifest) = &self.manifest {
+                manifest.add_record(
+                    &state_lock,
+                    ManifestRecord::Compaction(compaction_task, ids.clone()),
+                )?;
+                self.record_data_paths(&state_lock, &ids)?;
+            }
+        }
+        for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
+            self.remove_sst_file(*sst)?;
+        }
+        self.sync_dir()?;
+
+        Ok(())
+    }
+
+    /// Compact the keys from `first_key` to `last_key`, both included, down to the bottom level,
+    /// e.g. to reclaim the space of a deleted range right away. The memtables are flushed first,
+    /// then the L0 SSTs are compacted into L1 if any of them overlaps with the range, and the
+    /// SSTs overlapping with it are pushed down one level at a time. The bottom level SSTs are
+    /// rewritten in place if nothing was pushed into them, which drops their tombstones.
+    ///
+    /// Holds the compaction lock throughout, so that the compaction thread does not pick any of
+    /// the SSTs meanwhile. Only supported with sorted levels.
+    pub fn compact_range(&self, first_key: &[u8], last_key: &[u8]) -> Result<()> {
+        ensure!(
+            first_key <= last_key,
+            "the first key of the range must not be after its last key"
+        );
+        self.force_flush_all()?;
+        let _compaction_lock = self.compaction_lock.lock();
+        let compaction_options = self.compaction_controller().options();
+        if !compaction_options.has_sorted_levels() {
+            bail!(
+                "compact_range is not supported with {} compaction",
+                compaction_options.style()
+            );
+        }
+        let snapshot = self.state.read().clone();
+        if snapshot
+            .l0_sstables
+            .iter()
+            .any(|id| snapshot.sstables[id].overlaps_range(first_key, Some(last_key)))
+        {
+            // All L0 SSTs go down together, as the older ones would shadow the newer ones
+            // otherwise
+            let (l0_first_key, l0_last_key) = snapshot.key_range(&snapshot.l0_sstables).unwrap();
+            let task = CompactionTask::Partial(LeveledCompactionTask {
+                upper_level: None,
+                upper_level_sst_ids: snapshot.l0_sstables.clone(),
+                lower_level: snapshot.levels[0].0,
+                lower_level_sst_ids: Self::overlapping_ssts(
+                    &snapshot,
+                    0,
+                    l0_first_key.key_ref(),
+                    Some(l0_last_key.key_ref()),
+                ),
+                is_lower_level_bottom_level: snapshot.levels.len() == 1,
+            });
+            self.run_manual_compaction_task(&snapshot, task)?;
+        }
+        let bottom_level_idx = self.state.read().levels.len() - 1;
+        let mut bottom_level_rewritten = false;
+        for level_idx in 0..=bottom_level_idx {
+            let snapshot = self.state.read().clone();
+            let sst_ids = Self::overlapping_ssts(&snapshot, level_idx, first_key, Some(last_key));
+            if sst_ids.is_empty() || (level_idx == bottom_level_idx && bottom_level_rewritten) {
+                continue;
+            }
+            let task = Self::generate_partial_compaction_task(&snapshot, level_idx, sst_ids);
+            bottom_level_rewritten |= task.compact_to_bottom_level();
+            self.run_manual_compaction_task(&snapshot, task)?;
+        }
+        Ok(())
+    }
+
+    /// Stop the running compactions, which fail with a `Cancelled` error at their next block or
+    /// output SST and leave their input SSTs as they were. The compactions started afterwards
+    /// run as usual, and a cancelled background compaction is retried by the compaction thread.
+    pub fn cancel_compactions(&self) {
+        std::mem::take(&mut *self.compaction_cancellation.lock()).cancel();
+    }
+
+    /// Run a task requested by the user, which fails instead of being deferred. The compaction
+    /// windows do not apply.
+    fn run_manual_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        task: CompactionTask,
+    ) -> Result<()> {
+        let estimated_size = Self::estimated_output_size(snapshot, &task);
+        let data_path = self.data_path_for(snapshot, estimated_size);
+        if !self.has_disk_space_for(BackgroundTask::Compaction, estimated_size, data_path)? {
+            bail!("not enough disk space to compact {} bytes", estimated_size);
+        }
+        let _claim = CompactionClaim::claim(snapshot, &task.input_sst_ids())?;
+        self.compact_and_install(task)
+    }
+
+    /// Compact all SSTs into one sorted run and lay it out for `compaction_options`: in the
+    /// bottom level, or as the only tier with tiered compaction. Any compaction strategy can
+    /// continue from there, which makes this the migration path between compaction layouts. The
+    /// controller for `compaction_options` is installed along with the new layout.
+    pub(crate) fn compact_into_layout(
+        &self,
+        _compaction_lock: &MutexGuard<'_, ()>,
+        compaction_options: &CompactionOptions,
+    ) -> Result<()> {
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let l0_sstables = snapshot.l0_sstables.clone();
+        let level_sstables = snapshot
+            .levels
+            .iter()
+            .flat_map(|(_, level_sst_ids)| level_sst_ids.iter().copied())
+            .collect::<Vec<_>>();
+        let compaction_task = CompactionTask::ForceFullCompaction {
+            l0_sstables: l0_sstables.clone(),
+            l1_sstables: level_sstables.clone(),
+        };
+        let has_input = !l0_sstables.is_empty() || !level_sstables.is_empty();
+        let _claim = CompactionClaim::claim(&snapshot, &compaction_task.input_sst_ids())?;
+        let sstables = match has_input {
+            true => self.compact(&compaction_task)?,
+            false => Vec::new(),
+        };
+        let ids = sstables.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+
+        {
+            let state_lock = self.state_lock.lock();
+            let mut state = self.state.read().as_ref().clone();
+            for sst in l0_sstables.iter().chain(level_sstables.iter()) {
+                let result = state.sstables.remove(sst);
+                assert!(result.is_some());
+            }
+            for new_sst in sstables {
+                let result = state.sstables.insert(new_sst.sst_id(), new_sst);
+                assert!(result.is_none());
+            }
+            // New SSTs may have been flushed to L0 while we were compacting
+            let mut l0_sstables_map = l0_sstables.iter().copied().collect::<HashSet<_>>();
+            state.l0_sstables.retain(|id| !l0_sstables_map.remove(id));
+            assert!(l0_sstables_map.is_empty());
+            state.levels = LsmStorageState::empty_levels(compaction_options);
+            if compaction_options.has_sorted_levels() {
+                state.levels.last_mut().unwrap().1.clone_from(&ids);
+            } else if let Some(&tier_id) = ids.first() {
+                state.levels.push((tier_id, ids.clone()));
+            }
+            state.update_sst_levels();
+            self.quotas.refresh(&state);
+            self.key_distribution.refresh(&state);
+            *self.state.write() = Arc::new(state);
+            self.sync_dir()?;
+            if let Some(manifest) = &self.manifest
+                && has_input
+            {
+                manifest.add_record(
+                    &state_lock,
+                    ManifestRecord::Compaction(compaction_task, ids.clone()),
+                )?;
+                self.record_data_paths(&state_lock, &ids)?;
+            }
+            self.install_compaction_controller(&state_lock, compaction_options)?;
+        }
+        for sst in l0_sstables.iter().chain(level_sstables.iter()) {
+            self.remove_sst_file(*sst)?;
+        }
+        self.sync_dir()?;
+        Ok(())
+    }
+
+    /// Replace the compaction controller and record the new strategy in the manifest.
+    fn install_compaction_controller(
+        &self,
+        state_lock: &MutexGuard<'_, ()>,
+        compaction_options: &CompactionOptions,
+    ) -> Result<()> {
+        *self.compaction_controller.write() = Arc::new(
+            CompactionController::new(compaction_options)
+                .with_entry_counts(self.options.entry_count_compaction.as_ref()),
+        );
+        if let Some(manifest) = &self.manifest {
+            manifest.add_record(
+                state_lock,
+                ManifestRecord::CompactionStrategy(compaction_options.clone()),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn compaction_controller(&self) -> Arc<CompactionController> {
+        self.compaction_controller.read().clone()
+    }
+
+    /// Switch to another compaction strategy while running, after the compaction in progress
+    /// finishes. If the new strategy lays out the levels differently, e.g., from tiered to
+    /// leveled, all SSTs are compacted into one sorted run in the new layout first.
+    pub fn change_compaction_strategy(&self, compaction_options: CompactionOptions) -> Result<()> {
+        let options = LsmStorageOptions {
+            compaction_options,
+            ..self.options.as_ref().clone()
+        };
+        options.validate()?;
+        let compaction_lock = self.compaction_lock.lock();
+        let current = self.compaction_controller().options();
+        if current.layout_change(&options.compaction_options).is_some() {
+            self.compact_into_layout(&compaction_lock, &options.compaction_options)?;
+        } else {
+            let state_lock = self.state_lock.lock();
+            self.install_compaction_controller(&state_lock, &options.compaction_options)?;
+        }
+        self.write_options_file(&options)
+    }
+
+    /// Run one compaction task generated by the controller, or a compaction triggered by reads if
+    /// the controller has nothing to do. Returns whether a task was run. Without a controller,
+    /// only the expired entries are compacted, as they are configured apart from it.
+    pub(crate) fn trigger_compaction(&self) -> Result<bool> {
+        let _compaction_lock = self.compaction_lock.lock();
+        let compaction_controller = self.compaction_controller();
+        if let CompactionController::NoCompaction = *compaction_controller {
+            return self.trigger_ttl_compaction();
+        }
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        if self.run_compaction_tasks(
+            &snapshot,
+            compaction_controller
+                .generate_compaction_tasks(&snapshot, self.options.max_concurrent_compactions),
+        )? {
+            return Ok(true);
+        }
+        // The task of the controller may have been deferred, which should not hold back the
+        // compactions triggered by reads
+        Ok(self.trigger_deletion_compaction()?
+            || self.trigger_seek_compaction()?
+            || self.trigger_ttl_compaction()?)
+    }
+
+    fn overlapping_ssts(
+        snapshot: &LsmStorageState,
+        level_idx: usize,
+        first_key: &[u8],
+        last_key: Option<&[u8]>,
+    ) -> Vec<usize> {
+        snapshot.levels[level_idx]
+            .1
+            .iter()
+            .copied()
+            .filter(|id| snapshot.sstables[id].overlaps_range(first_key, last_key))
+            .collect()
+    }
+
+    /// Compact `upper_level_sst_ids` from `snapshot.levels[level_idx]` with the overlapping SSTs
+    /// of the next level. SSTs in the bottom level are rewritten in place, which drops tombstones.
+    fn generate_partial_compaction_task(
+        snapshot: &LsmStorageState,
+        level_idx: usize,
+        upper_level_sst_ids: Vec<usize>,
+    ) -> CompactionTask {
+        let bottom_level_idx = snapshot.levels.len() - 1;
+        let upper_level = snapshot.levels[level_idx].0;
+        if level_idx == bottom_level_idx {
+            return CompactionTask::Partial(LeveledCompactionTask {
+                upper_level: Some(upper_level),
+                upper_level_sst_ids,
+                lower_level: upper_level,
+                lower_level_sst_ids: Vec::new(),
+                is_lower_level_bottom_level: true,
+            });
+        }
+        let (first_key, last_key) = snapshot.key_range(&upper_level_sst_ids).unwrap();
+        let lower_level_sst_ids = Self::overlapping_ssts(
+            snapshot,
+            level_idx + 1,
+            first_key.key_ref(),
+            Some(last_key.key_ref()),
+        );
+        CompactionTask::Partial(LeveledCompactionTask {
+            upper_level: Some(upper_level),
+            upper_level_sst_ids,
+            lower_level: snapshot.levels[level_idx + 1].0,
+            lower_level_sst_ids,
+            is_lower_level_bottom_level: level_idx + 1 == bottom_level_idx,
+        })
+    }
+
+    /// Compact the SSTs of the next tombstone-heavy range reported by the scans, from the
+    /// top-most level below L0 that has any. All L0 SSTs would need to be compacted together, so
+    /// they are left to the compaction controller. Returns whether a task was run.
+    pub(crate) fn trigger_deletion_compaction(&self) -> Result<bool> {
+        while let Some((first_key, last_key)) = self.deletion_collector.pop() {
+            let snapshot = {
+                let state = self.state.read();
+                Arc::clone(&state)
+            };
+            if let Some(task) =
+                Self::deletion_compaction_task(&snapshot, &first_key, last_key.as_deref())
+            {
+                if self.run_compaction_task(&snapshot, task)? {
+                    return Ok(true);
+                }
+                self.deletion_collector.push_back(first_key, last_key);
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Compact the SSTs of the first level overlapping with a tombstone-heavy range.
+    fn deletion_compaction_task(
+        snapshot: &LsmStorageState,
+        first_key: &[u8],
+        last_key: Option<&[u8]>,
+    ) -> Option<CompactionTask> {
+        (0..snapshot.levels.len()).find_map(|level_idx| {
+            let sst_ids = Self::overlapping_ssts(snapshot, level_idx, first_key, last_key);
+            (!sst_ids.is_empty())
+                .then(|| Self::generate_partial_compaction_task(snapshot, level_idx, sst_ids))
+        })
+    }
+
+    /// Compact the SST that exceeded `seek_compaction_threshold` into the next level, so that
+    /// reads stop probing it in vain. SSTs in L0 and the bottom level are skipped. Returns whether
+    /// a task was run.
+    pub(crate) fn trigger_seek_compaction(&self) -> Result<bool> {
+        let Some(sst_id) = self.seek_compaction_sst.lock().take() else {
+            return Ok(false);
+        };
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let Some(task) = Self::seek_compaction_task(&snapshot, sst_id) else {
+            return Ok(false);
+        };
+        if self.run_compaction_task(&snapshot, task)? {
+            return Ok(true);
+        }
+        // Retry on the next tick, unless another SST has been reported meanwhile
+        self.seek_compaction_sst.lock().get_or_insert(sst_id);
+        Ok(false)
+    }
+
+    /// Compact an SST read in vain too often into the next level.
+    fn seek_compaction_task(snapshot: &LsmStorageState, sst_id: usize) -> Option<CompactionTask> {
+        let level_idx = snapshot
+            .levels
+            .iter()
+            .position(|(_, level_sst_ids)| level_sst_ids.contains(&sst_id));
+        match level_idx {
+            Some(level_idx) if level_idx + 1 < snapshot.levels.len() => Some(
+                Self::generate_partial_compaction_task(snapshot, level_idx, vec![sst_id]),
+            ),
+            // The SST was compacted away or moved to the bottom level
+            _ => None,
+        }
+    }
+
+    /// Every `compaction_interval` of the TTL options, compact the SST below L0 with the most
+    /// expired entries if more than half of its entries have expired, instead of waiting for
+    /// reads to skip them. Returns whether a task was run.
+    pub(crate) fn trigger_ttl_compaction(&self) -> Result<bool> {
+        let Some(options) = &self.options.ttl else {
+            return Ok(false);
+        };
+        {
+            let mut last_ttl_check = self.last_ttl_check.lock();
+            if last_ttl_check.elapsed() < options.compaction_interval {
+                return Ok(false);
+            }
+            *last_ttl_check = Instant::now();
+        }
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        match self.ttl_compaction_task(&snapshot) {
+            Some(task) => self.run_compaction_task(&snapshot, task),
+            None => Ok(false),
+        }
+    }
+
+    /// Compact the SST with the most expired entries if they are the majority of its entries.
+    fn ttl_compaction_task(&self, snapshot: &LsmStorageState) -> Option<CompactionTask> {
+        let now = self.options.ttl.as_ref()?.now_millis();
+        let mut most_expired = None;
+        for (level_idx, (_, level_sst_ids)) in snapshot.levels.iter().enumerate() {
+            for sst_id in level_sst_ids {
+                let properties = snapshot.sstables[sst_id].properties();
+                let expired = properties.expiry_histogram.expired_entries(now);
+                if expired * 2 > properties.num_entries
+                    && most_expired.is_none_or(|(_, _, most)| expired > most)
+                {
+                    most_expired = Some((level_idx, *sst_id, expired));
+                }
+            }
+        }
+        let (level_idx, sst_id, _) = most_expired?;
+        Some(Self::generate_partial_compaction_task(
+            snapshot,
+            level_idx,
+            vec![sst_id],
+        ))
+    }
+
+    /// Run `tasks` concurrently, one thread per task. Each task installs its output on its own,
+    /// in the order they finish, as their inputs are disjoint. Returns whether any task was run.
+    fn run_compaction_tasks(
+        &self,
+        snapshot: &LsmStorageState,
+        mut tasks: Vec<CompactionTask>,
+    ) -> Result<bool> {
+        if tasks.len() <= 1 {
+            return match tasks.pop() {
+                Some(task) => self.run_compaction_task(snapshot, task),
+                None => Ok(false),
+            };
+        }
+        let results = std::thread::scope(|scope| {
+            let handles = tasks
+                .into_iter()
+                .map(|task| scope.spawn(move || self.run_compaction_task(snapshot, task)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("a compaction task panicked")))
+                })
+                .collect::<Vec<_>>()
+        });
+        let mut any_run = false;
+        for result in results {
+            any_run |= result?;
+        }
+        Ok(any_run)
+    }
+
+    /// Compact the SSTs of `task` and install the output. Returns whether the task was run, as
+    /// it is deferred if it goes to the bottom level outside of the `compaction_windows` or if the
+    /// disk is short of space.
+    fn run_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        task: CompactionTask,
+    ) -> Result<bool> {
+        if task.compact_to_bottom_level()
+            && !self
+                .compaction_scheduler
+                .allows_heavy_compaction(time_of_day_utc())
+        {
+            self.emit_event(LsmEvent::HeavyCompactionDeferred {
+                input_sst_ids: task.input_sst_ids(),
+            });
+            return Ok(false);
+        }
+        let estimated_size = Self::estimated_output_size(snapshot, &task);
+        let data_path = self.data_path_for(snapshot, estimated_size);
+        if !self.has_disk_space_for(BackgroundTask::Compaction, estimated_size, data_path)? {
+            return Ok(false);
+        }
+        let Some(_claim) = CompactionClaim::try_claim(snapshot, &task.input_sst_ids()) else {
+            return Ok(false);
+        };
+        self.compact_and_install(task)?;
+        Ok(true)
+    }
+
+    /// Compact the SSTs of `task`, claimed by the caller, install the output and remove the
+    /// input SSTs.
+    fn compact_and_install(&self, task: CompactionTask) -> Result<()> {
+        let sstables = self.compact(&task)?;
+        let output = sstables.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+        let ssts_to_remove = {
+            let state_lock = self.state_lock.lock();
+            let mut snapshot = self.state.read().as_ref().clone();
+            for new_sst in sstables {
+                let sst_id = new_sst.sst_id();
+                let result = snapshot.sstables.insert(sst_id, new_sst);
+                // Unless the SST was moved as it is
+                assert!(result.is_none() || task.input_sst_ids().contains(&sst_id));
+            }
+            let (mut snapshot, mut files_to_remove) = self
+                .compaction_controller()
+                .apply_compaction_result(&snapshot, &task, &output, false);
+            files_to_remove.retain(|id| !output.contains(id));
+            let mut ssts_to_remove = Vec::with_capacity(files_to_remove.len());
+            for file_to_remove in &files_to_remove {
+                let result = snapshot.sstables.remove(file_to_remove);
+                assert!(result.is_some(), "cannot remove {}.sst", file_to_remove);
+                ssts_to_remove.push(result.unwrap());
+            }
+            snapshot.update_sst_levels();
+            self.quotas.refresh(&snapshot);
+            self.key_distribution.refresh(&snapshot);
+            *self.state.write() = Arc::new(snapshot);
+            self.sync_dir()?;
+            if let Some(manifest) = &self.manifest {
+                manifest.add_record(
+                    &state_lock,
+                    ManifestRecord::Compaction(task, output.clone()),
+                )?;
+                self.record_data_paths(&state_lock, &output)?;
+            }
+            ssts_to_remove
+        };
+        for sst in ssts_to_remove {
+            self.remove_sst_file(sst.sst_id())?;
+        }
+        self.sync_dir()
+    }
+
+    /// Block until the compaction controller does not generate any task, waking up whenever the
+    /// compaction thread finishes a task.
+    pub fn wait_for_compactions(&self, timeout: Duration) -> Result<()> {
+        let compaction_controller = self.compaction_controller();
+        if let CompactionController::NoCompaction = *compaction_controller {
+            return Ok(());
+        }
+        let deadline = Instant::now() + timeout;
+        let mut next_compaction = self.compaction_listeners.subscribe();
+        loop {
+            let snapshot = {
+                let state = self.state.read();
+                Arc::clone(&state)
+            };
+            if compaction_controller
+                .generate_compaction_task(&snapshot)
+                .is_none()
+            {
+                return Ok(());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                bail!("compactions did not finish within {:?}", timeout);
+            }
+            // Also poll periodically, as the compaction may finish before we subscribe to it
+            if let Some(result) = next_compaction.wait_timeout(remaining.min(POLL_INTERVAL)) {
+                result?;
+                next_compaction = self.compaction_listeners.subscribe();
+            }
+        }
     }
 
-    fn trigger_compaction(&self) -> Result<()> {
-        unimplemented!()
+    /// Run one tick of a background thread, unless a previous background error paused it. A
+    /// failure or a panic of the task is recorded as the background error and reported to the
+    /// event listeners.
+    fn run_background_task(
+        &self,
+        name: &'static str,
+        listeners: &TaskNotifier,
+        task: impl FnOnce() -> Result<bool>,
+    ) {
+        if self.has_background_error() {
+            return;
+        }
+        // A panic would otherwise end the thread silently, and the writes would go on piling up
+        // memtables
+        let result = panic::catch_unwind(AssertUnwindSafe(task)).unwrap_or_else(|payload| {
+            let message = (payload.downcast_ref::<&str>().copied())
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(anyhow!("{} panicked: {}", name, message))
+        });
+        match result {
+            Ok(false) => {}
+            Ok(true) => listeners.notify(&Ok(())),
+            // Not a failure of the storage, so background work goes on
+            Err(e) if is_cancelled_error(&e) => listeners.notify(&Err(e)),
+            Err(e) => {
+                self.record_background_error(&e);
+                self.emit_event(LsmEvent::BackgroundError {
+                    task: name,
+                    error: format!("{:#}", e),
+                });
+                listeners.notify(&Err(e));
+            }
+        }
     }
 
     pub(crate) fn spawn_compaction_thread(
         self: &Arc<Self>,
         rx: crossbeam_channel::Receiver<()>,
     ) -> Result<Option<std::thread::JoinHandle<()>>> {
-        if let CompactionOptions::Leveled(_)
-        | CompactionOptions::Simple(_)
-        | CompactionOptions::Tiered(_) = self.options.compaction_options
-        {
-            let this = self.clone();
-            let handle = std::thread::spawn(move || {
-                let ticker = crossbeam_channel::tick(Duration::from_millis(50));
-                loop {
-                    crossbeam_channel::select! {
-                        recv(ticker) -> _ => if let Err(e) = this.trigger_compaction() {
-                            eprintln!("compaction failed: {}", e);
-                        },
-                        recv(rx) -> _ => return
-                    }
+        // Also started without compaction, which may be enabled by `change_compaction_strategy`
+        let this = self.clone();
+        let handle = std::thread::spawn(move || {
+            let ticker = crossbeam_channel::tick(Duration::from_millis(50));
+            loop {
+                crossbeam_channel::select! {
+                    recv(ticker) -> _ => {
+                        this.run_background_task(
+                            "compaction",
+                            &this.compaction_listeners,
+                            || this.trigger_compaction(),
+                        );
+                        // Not a compaction of SSTs that waiters should hear about
+                        this.run_background_task(
+                            "manifest compaction",
+                            &this.compaction_listeners,
+                            || this.trigger_manifest_compaction().map(|_| false),
+                        );
+                    },
+                    recv(rx) -> _ => return
                 }
-            });
-            return Ok(Some(handle));
+            }
+        });
+        Ok(Some(handle))
+    }
+
+    /// Delete the SSTs in trash at `sst_delete_rate`, see `SstFileManager`.
+    pub(crate) fn spawn_trash_thread(
+        self: &Arc<Self>,
+        rx: crossbeam_channel::Receiver<()>,
+    ) -> Result<Option<std::thread::JoinHandle<()>>> {
+        if self.sst_file_manager.delete_rate().is_none() {
+            return Ok(None);
         }
-        Ok(None)
+        let this = self.clone();
+        let handle = std::thread::spawn(move || {
+            let ticker = crossbeam_channel::tick(Duration::from_millis(50));
+            loop {
+                crossbeam_channel::select! {
+                    recv(ticker) -> _ => {
+                        // Emptying the trash may take long, so check for a stop in between
+                        while rx.is_empty() {
+                            match this.sst_file_manager.delete_trash_chunk() {
+                                Ok(true) => {}
+                                Ok(false) => break,
+                                // The trash only holds deleted SSTs, so the writes go on and the
+                                // deletion is retried on the next tick
+                                Err(e) => {
+                                    this.emit_event(LsmEvent::BackgroundError {
+                                        task: "trash deletion",
+                                        error: format!("{:#}", e),
+                                    });
+                                    break;
+                                }
+                            }
+                        }
+                    },
+                    recv(rx) -> _ => return
+                }
+            }
+        });
+        Ok(Some(handle))
     }
 
-    fn trigger_flush(&self) -> Result<()> {
-        if {
+    /// Flush the earliest immutable memtable if there are too many. Returns whether a memtable
+    /// was flushed.
+    pub(crate) fn trigger_flush(&self) -> Result<bool> {
+        let flush_memtable = {
             let state = self.state.read();
-            state.imm_memtables.len() + 1 > self.options.num_memtable_limit
-        } {
-            self.force_flush_next_imm_memtable()?;
+            if state.imm_memtables.len() < self.options.num_memtable_limit {
+                return Ok(false);
+            }
+            state.imm_memtables.last().cloned()
+        };
+        let Some(flush_memtable) = flush_memtable else {
+            return Ok(false);
+        };
+        if let Some(limiter) = &self.sorted_run_limiter
+            && limiter.at_bound(&self.state.read())
+        {
+            return Ok(false);
         }
-        Ok(())
+        let estimated_size = flush_memtable.approximate_size() as u64;
+        if !self.has_disk_space_for(BackgroundTask::Flush, estimated_size, 0)? {
+            return Ok(false);
+        }
+        self.force_flush_next_imm_memtable()?;
+        Ok(true)
     }
 
     pub(crate) fn spawn_flush_thread(
@@ -179,9 +1853,11 @@ impl LsmStorageInner {
             let ticker = crossbeam_channel::tick(Duration::from_millis(50));
             loop {
                 crossbeam_channel::select! {
-                    recv(ticker) -> _ => if let Err(e) = this.trigger_flush() {
-                        eprintln!("flush failed: {}", e);
-                    },
+                    recv(ticker) -> _ => this.run_background_task(
+                        "flush",
+                        &this.flush_listeners,
+                        || this.trigger_flush(),
+                    ),
                     recv(rx) -> _ => return
                 }
             }
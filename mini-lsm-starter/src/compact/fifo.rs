@@ -0,0 +1,95 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lsm_storage::LsmStorageState;
+
+/// FIFO compaction never rewrites data: the SSTs stay in L0 as they are flushed, and the oldest
+/// ones are dropped once the SSTs take more than `max_table_files_size` bytes. For logs,
+/// time-series and caches, where old data can be discarded as a whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FifoCompactionOptions {
+    pub max_table_files_size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FifoCompactionTask {
+    /// The SSTs to drop, from the oldest.
+    pub sst_ids: Vec<usize>,
+}
+
+pub struct FifoCompactionController {
+    options: FifoCompactionOptions,
+}
+
+impl FifoCompactionController {
+    pub fn new(options: FifoCompactionOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &FifoCompactionOptions {
+        &self.options
+    }
+
+    pub fn generate_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<FifoCompactionTask> {
+        let mut total_size = snapshot
+            .sstables
+            .values()
+            .map(|sst| sst.table_size())
+            .sum::<u64>();
+        if total_size <= self.options.max_table_files_size {
+            return None;
+        }
+        // The SSTs below L0 were there before switching to FIFO compaction, so they are older
+        // than L0
+        let oldest_first = snapshot
+            .levels
+            .iter()
+            .rev()
+            .flat_map(|(_, sst_ids)| sst_ids.iter())
+            .chain(snapshot.l0_sstables.iter().rev());
+        let mut sst_ids = Vec::new();
+        for &sst_id in oldest_first {
+            if total_size <= self.options.max_table_files_size {
+                break;
+            }
+            total_size -= snapshot.sstables[&sst_id].table_size();
+            sst_ids.push(sst_id);
+        }
+        Some(FifoCompactionTask { sst_ids })
+    }
+
+    pub fn apply_compaction_result(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &FifoCompactionTask,
+    ) -> (LsmStorageState, Vec<usize>) {
+        let mut new_snapshot = snapshot.clone();
+        let dropped = task.sst_ids.iter().copied().collect::<HashSet<_>>();
+        new_snapshot.l0_sstables.retain(|id| !dropped.contains(id));
+        for (_, sst_ids) in &mut new_snapshot.levels {
+            sst_ids.retain(|id| !dropped.contains(id));
+        }
+        new_snapshot
+            .levels
+            .retain(|(_, sst_ids)| !sst_ids.is_empty());
+        (new_snapshot, task.sst_ids.clone())
+    }
+}
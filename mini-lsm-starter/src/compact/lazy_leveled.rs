@@ -0,0 +1,167 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+use crate::lsm_storage::LsmStorageState;
+
+/// Lazy leveling (as in Dostoevsky): the levels above the last one are tiered and hold up to
+/// `size_ratio - 1` sorted runs each, so that an entry is rewritten once per level like with
+/// tiered compaction, while the last level is a single sorted run like with leveled compaction,
+/// which holds most of the data and bounds the space amplification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LazyLeveledCompactionOptions {
+    /// The number of runs that are merged into a run of the next level, and the ratio between
+    /// the sizes of the runs of adjacent levels. L0 SSTs are merged once there are this many.
+    pub size_ratio: usize,
+    /// The number of levels below L0, including the last level.
+    pub max_levels: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LazyLeveledCompactionTask {
+    /// The L0 SSTs merged into a new run on top of the levels, empty when merging runs below L0.
+    pub l0_sstables: Vec<usize>,
+    /// Adjacent runs below L0, from the newest, merged into one run in their place.
+    pub runs: Vec<(usize, Vec<usize>)>,
+    pub bottom_run_included: bool,
+}
+
+pub struct LazyLeveledCompactionController {
+    options: LazyLeveledCompactionOptions,
+}
+
+impl LazyLeveledCompactionController {
+    pub fn new(options: LazyLeveledCompactionOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &LazyLeveledCompactionOptions {
+        &self.options
+    }
+
+    /// The level of a run above the last level. The runs of level `max_levels - n` are about
+    /// `size_ratio^n` times smaller than the last level, so that the level sizes follow the size
+    /// of the last level as it grows.
+    fn level_of_run(&self, run_size: u64, bottom_size: u64) -> usize {
+        let size_ratio = self.options.size_ratio as u64;
+        let mut distance = 1;
+        let mut scaled_size = run_size.saturating_mul(size_ratio);
+        while distance < self.options.max_levels - 1 && scaled_size < bottom_size {
+            distance += 1;
+            scaled_size = scaled_size.saturating_mul(size_ratio);
+        }
+        self.options.max_levels - distance
+    }
+
+    pub fn generate_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<LazyLeveledCompactionTask> {
+        let size_ratio = self.options.size_ratio;
+        if snapshot.l0_sstables.len() >= size_ratio {
+            return Some(LazyLeveledCompactionTask {
+                l0_sstables: snapshot.l0_sstables.clone(),
+                runs: Vec::new(),
+                // The new run is the only one if there are no levels yet
+                bottom_run_included: snapshot.levels.is_empty(),
+            });
+        }
+        let (bottom_run, upper_runs) = snapshot.levels.split_last()?;
+        let run_size = |sst_ids: &[usize]| -> u64 {
+            sst_ids
+                .iter()
+                .map(|id| snapshot.sstables[id].table_size())
+                .sum()
+        };
+        let bottom_size = run_size(&bottom_run.1);
+        let levels = upper_runs
+            .iter()
+            .map(|(_, sst_ids)| self.level_of_run(run_size(sst_ids), bottom_size))
+            .collect::<Vec<_>>();
+
+        // Merge the adjacent runs of a full level, the deepest first. A full level above the last
+        // one goes into the last level.
+        let mut group_end = upper_runs.len();
+        while group_end > 0 {
+            let level = levels[group_end - 1];
+            let group_start = levels[..group_end]
+                .iter()
+                .rposition(|&other| other != level)
+                .map_or(0, |idx| idx + 1);
+            if group_end - group_start >= size_ratio {
+                let bottom_run_included =
+                    level == self.options.max_levels - 1 && group_end == upper_runs.len();
+                let runs_end = group_end + usize::from(bottom_run_included);
+                return Some(LazyLeveledCompactionTask {
+                    l0_sstables: Vec::new(),
+                    runs: snapshot.levels[group_start..runs_end].to_vec(),
+                    bottom_run_included,
+                });
+            }
+            group_end = group_start;
+        }
+
+        // Runs whose sizes are out of order do not group into full levels, so bound the number
+        // of runs by merging the newest ones
+        if upper_runs.len() > (self.options.max_levels - 1) * (size_ratio - 1) {
+            return Some(LazyLeveledCompactionTask {
+                l0_sstables: Vec::new(),
+                runs: upper_runs[..size_ratio].to_vec(),
+                bottom_run_included: false,
+            });
+        }
+        None
+    }
+
+    pub fn apply_compaction_result(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &LazyLeveledCompactionTask,
+        output: &[usize],
+    ) -> (LsmStorageState, Vec<usize>) {
+        let mut new_snapshot = snapshot.clone();
+        let new_run = output.first().map(|&run_id| (run_id, output.to_vec()));
+        let mut files_to_remove = task.l0_sstables.clone();
+        if task.runs.is_empty() {
+            // New SSTs may have been flushed to L0 while compacting, which are kept
+            new_snapshot
+                .l0_sstables
+                .retain(|id| !task.l0_sstables.contains(id));
+            new_snapshot.levels.splice(0..0, new_run);
+        } else {
+            let start = new_snapshot
+                .levels
+                .iter()
+                .position(|(run_id, _)| *run_id == task.runs[0].0)
+                .expect("the compacted runs are in the levels");
+            let removed = new_snapshot
+                .levels
+                .splice(start..start + task.runs.len(), new_run)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                removed.iter().map(|(run_id, _)| run_id).collect::<Vec<_>>(),
+                task.runs
+                    .iter()
+                    .map(|(run_id, _)| run_id)
+                    .collect::<Vec<_>>(),
+                "the compacted runs changed"
+            );
+            for (_, sst_ids) in &task.runs {
+                files_to_remove.extend_from_slice(sst_ids);
+            }
+        }
+        (new_snapshot, files_to_remove)
+    }
+}
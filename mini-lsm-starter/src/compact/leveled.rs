@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 
 use crate::lsm_storage::LsmStorageState;
@@ -26,7 +28,7 @@ pub struct LeveledCompactionTask {
     pub is_lower_level_bottom_level: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LeveledCompactionOptions {
     pub level_size_multiplier: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -34,38 +36,275 @@ pub struct LeveledCompactionOptions {
     pub base_level_size_mb: usize,
 }
 
+/// Size the levels of leveled compaction by their number of entries instead of their bytes. With
+/// small entries, a byte size large enough for a level to be worth compacting takes a huge number
+/// of files to reach.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryCountCompactionOptions {
+    /// Replaces `base_level_size_mb`.
+    pub base_level_entries: u64,
+    /// Also compact L0 into the base level once it holds this many entries, before it has
+    /// `level0_file_num_compaction_trigger` SSTs.
+    pub level0_entry_compaction_trigger: Option<u64>,
+}
+
 pub struct LeveledCompactionController {
     options: LeveledCompactionOptions,
+    entry_counts: Option<EntryCountCompactionOptions>,
 }
 
 impl LeveledCompactionController {
     pub fn new(options: LeveledCompactionOptions) -> Self {
-        Self { options }
+        Self {
+            options,
+            entry_counts: None,
+        }
+    }
+
+    pub(crate) fn with_entry_counts(
+        self,
+        entry_counts: Option<EntryCountCompactionOptions>,
+    ) -> Self {
+        Self {
+            entry_counts,
+            ..self
+        }
+    }
+
+    pub fn options(&self) -> &LeveledCompactionOptions {
+        &self.options
+    }
+
+    /// The size of some SSTs in the unit of the targets: entries with `EntryCountCompactionOptions`,
+    /// bytes otherwise.
+    fn size_of(&self, snapshot: &LsmStorageState, sst_ids: &[usize]) -> u64 {
+        sst_ids
+            .iter()
+            .map(|id| {
+                let sst = &snapshot.sstables[id];
+                match self.entry_counts {
+                    Some(_) => sst.properties().num_entries,
+                    None => sst.table_size(),
+                }
+            })
+            .sum()
     }
 
     fn find_overlapping_ssts(
         &self,
-        _snapshot: &LsmStorageState,
-        _sst_ids: &[usize],
-        _in_level: usize,
+        snapshot: &LsmStorageState,
+        sst_ids: &[usize],
+        in_level: usize,
     ) -> Vec<usize> {
-        unimplemented!()
+        let Some((first_key, last_key)) = snapshot.key_range(sst_ids) else {
+            return Vec::new();
+        };
+        snapshot.levels[in_level - 1]
+            .1
+            .iter()
+            .copied()
+            .filter(|id| {
+                snapshot.sstables[id].overlaps_range(first_key.key_ref(), Some(last_key.key_ref()))
+            })
+            .collect()
+    }
+
+    /// The real and target size of each level below L0, and the level L0 is compacted into.
+    fn level_sizes(&self, snapshot: &LsmStorageState) -> (Vec<u64>, Vec<u64>, usize) {
+        let max_levels = self.options.max_levels;
+        let base_level_size = match &self.entry_counts {
+            Some(entry_counts) => entry_counts.base_level_entries,
+            None => self.options.base_level_size_mb as u64 * 1024 * 1024,
+        };
+        let real_level_size = snapshot
+            .levels
+            .iter()
+            .map(|(_, sst_ids)| self.size_of(snapshot, sst_ids))
+            .collect::<Vec<_>>();
+
+        // The bottom level is at least as large as the base level, and each level above it is
+        // `level_size_multiplier` times smaller until one would be smaller than the base level
+        let mut target_level_size = vec![0; max_levels];
+        target_level_size[max_levels - 1] = real_level_size[max_levels - 1].max(base_level_size);
+        let mut base_level = max_levels;
+        for level in (0..max_levels - 1).rev() {
+            let next_level_size = target_level_size[level + 1];
+            if next_level_size > base_level_size {
+                target_level_size[level] =
+                    next_level_size / self.options.level_size_multiplier as u64;
+            }
+            if target_level_size[level] > 0 {
+                base_level = level + 1;
+            }
+        }
+        (real_level_size, target_level_size, base_level)
+    }
+
+    /// The level L0 is compacted into.
+    pub(crate) fn base_level(&self, snapshot: &LsmStorageState) -> usize {
+        self.level_sizes(snapshot).2
+    }
+
+    fn l0_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        base_level: usize,
+    ) -> Option<LeveledCompactionTask> {
+        let l0_entries_exceeded = self
+            .entry_counts
+            .as_ref()
+            .and_then(|entry_counts| entry_counts.level0_entry_compaction_trigger)
+            .is_some_and(|trigger| {
+                let l0_entries = snapshot
+                    .l0_sstables
+                    .iter()
+                    .map(|id| snapshot.sstables[id].properties().num_entries)
+                    .sum::<u64>();
+                l0_entries >= trigger
+            });
+        if snapshot.l0_sstables.is_empty()
+            || (snapshot.l0_sstables.len() < self.options.level0_file_num_compaction_trigger
+                && !l0_entries_exceeded)
+        {
+            return None;
+        }
+        Some(LeveledCompactionTask {
+            upper_level: None,
+            upper_level_sst_ids: snapshot.l0_sstables.clone(),
+            lower_level: base_level,
+            lower_level_sst_ids: self.find_overlapping_ssts(
+                snapshot,
+                &snapshot.l0_sstables,
+                base_level,
+            ),
+            is_lower_level_bottom_level: base_level == self.options.max_levels,
+        })
     }
 
     pub fn generate_compaction_task(
         &self,
-        _snapshot: &LsmStorageState,
+        snapshot: &LsmStorageState,
     ) -> Option<LeveledCompactionTask> {
-        unimplemented!()
+        self.generate_disjoint_tasks(snapshot, HashSet::new(), 1)
+            .into_iter()
+            .next()
+    }
+
+    /// Generate up to `max_tasks` tasks whose inputs are disjoint from each other and from the
+    /// SSTs already being compacted, so that they can run concurrently. The first task is the
+    /// one `generate_compaction_task` would generate if no SST was being compacted.
+    pub fn generate_compaction_tasks(
+        &self,
+        snapshot: &LsmStorageState,
+        max_tasks: usize,
+    ) -> Vec<LeveledCompactionTask> {
+        let busy = snapshot
+            .sstables
+            .values()
+            .filter(|sst| sst.is_being_compacted())
+            .map(|sst| sst.sst_id())
+            .collect();
+        self.generate_disjoint_tasks(snapshot, busy, max_tasks)
+    }
+
+    fn generate_disjoint_tasks(
+        &self,
+        snapshot: &LsmStorageState,
+        mut busy: HashSet<usize>,
+        max_tasks: usize,
+    ) -> Vec<LeveledCompactionTask> {
+        let max_levels = self.options.max_levels;
+        let (real_level_size, target_level_size, base_level) = self.level_sizes(snapshot);
+        let mut tasks = Vec::new();
+        let mut add_task = |task: LeveledCompactionTask| {
+            let inputs = || {
+                task.upper_level_sst_ids
+                    .iter()
+                    .chain(task.lower_level_sst_ids.iter())
+            };
+            if tasks.len() >= max_tasks || inputs().any(|id| busy.contains(id)) {
+                return false;
+            }
+            busy.extend(inputs());
+            tasks.push(task);
+            true
+        };
+
+        if let Some(task) = self.l0_compaction_task(snapshot, base_level) {
+            add_task(task);
+        }
+
+        // Compact the oldest SSTs of the levels the most over their target into the next level,
+        // until the levels would be back to their target. On ties, the lower level goes first.
+        let ratio = |level: usize| real_level_size[level] as f64 / target_level_size[level] as f64;
+        let mut levels = (0..max_levels - 1)
+            .rev()
+            .filter(|&level| real_level_size[level] > target_level_size[level])
+            .collect::<Vec<_>>();
+        levels.sort_by(|&a, &b| ratio(b).total_cmp(&ratio(a)));
+        for level in levels {
+            let mut excess = real_level_size[level] - target_level_size[level];
+            let mut sst_ids = snapshot.levels[level].1.clone();
+            sst_ids.sort_unstable();
+            for sst_id in sst_ids {
+                if excess == 0 {
+                    break;
+                }
+                let task = LeveledCompactionTask {
+                    upper_level: Some(level + 1),
+                    upper_level_sst_ids: vec![sst_id],
+                    lower_level: level + 2,
+                    lower_level_sst_ids: self.find_overlapping_ssts(snapshot, &[sst_id], level + 2),
+                    is_lower_level_bottom_level: level + 2 == max_levels,
+                };
+                if add_task(task) {
+                    excess = excess.saturating_sub(self.size_of(snapshot, &[sst_id]));
+                }
+            }
+        }
+        tasks
     }
 
     pub fn apply_compaction_result(
         &self,
-        _snapshot: &LsmStorageState,
-        _task: &LeveledCompactionTask,
-        _output: &[usize],
-        _in_recovery: bool,
+        snapshot: &LsmStorageState,
+        task: &LeveledCompactionTask,
+        output: &[usize],
+        in_recovery: bool,
     ) -> (LsmStorageState, Vec<usize>) {
-        unimplemented!()
+        let mut new_snapshot = snapshot.clone();
+        let upper_level_sst_ids = task
+            .upper_level_sst_ids
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        let lower_level_sst_ids = task
+            .lower_level_sst_ids
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        // New SSTs may have been flushed to L0 while compacting, which are kept
+        match task.upper_level {
+            Some(upper_level) => new_snapshot.levels[upper_level - 1]
+                .1
+                .retain(|id| !upper_level_sst_ids.contains(id)),
+            None => new_snapshot
+                .l0_sstables
+                .retain(|id| !upper_level_sst_ids.contains(id)),
+        }
+        let sstables = &new_snapshot.sstables;
+        let lower_level = &mut new_snapshot.levels[task.lower_level - 1].1;
+        lower_level.retain(|id| !lower_level_sst_ids.contains(id));
+        lower_level.extend_from_slice(output);
+        // The SSTs are not loaded yet during recovery, they are sorted after recovery instead
+        if !in_recovery {
+            lower_level.sort_by(|a, b| sstables[a].first_key().cmp(sstables[b].first_key()));
+        }
+        let files_to_remove = [
+            task.upper_level_sst_ids.as_slice(),
+            task.lower_level_sst_ids.as_slice(),
+        ]
+        .concat();
+        (new_snapshot, files_to_remove)
     }
 }
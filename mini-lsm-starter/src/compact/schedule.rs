@@ -0,0 +1,100 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! When compactions to the bottom level are allowed to run, see
+//! `LsmStorageOptions::compaction_windows`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::RwLock;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A daily time window, as offsets from midnight UTC. The window wraps around midnight if `end`
+/// is before `start`, e.g., from 22:00 to 06:00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionWindow {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl CompactionWindow {
+    pub fn new(start: Duration, end: Duration) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, time_of_day: Duration) -> bool {
+        if self.start <= self.end {
+            self.start <= time_of_day && time_of_day < self.end
+        } else {
+            self.start <= time_of_day || time_of_day < self.end
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        self.start < DAY && self.end < DAY
+    }
+}
+
+/// Reports the current CPU or I/O utilization of the machine, from 0.0 (idle) to 1.0 (busy).
+pub type UtilizationProbe = Box<dyn Fn() -> f64 + Send + Sync>;
+
+/// The time elapsed since midnight UTC.
+pub(crate) fn time_of_day_utc() -> Duration {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_nanos((since_epoch.as_nanos() % DAY.as_nanos()) as u64)
+}
+
+/// Decides whether a compaction to the bottom level, which rewrites the largest level, can run
+/// now. Flushes and the other compactions are never deferred.
+pub(crate) struct CompactionScheduler {
+    windows: Vec<CompactionWindow>,
+    /// The probe set with `MiniLsm::set_utilization_probe` and the utilization below which heavy
+    /// compactions run outside of the windows.
+    utilization: RwLock<Option<(UtilizationProbe, f64)>>,
+}
+
+impl CompactionScheduler {
+    pub(crate) fn new(windows: Vec<CompactionWindow>) -> Self {
+        Self {
+            windows,
+            utilization: RwLock::new(None),
+        }
+    }
+
+    pub(crate) fn set_utilization_probe(&self, probe: Option<(UtilizationProbe, f64)>) {
+        *self.utilization.write() = probe;
+    }
+
+    /// Heavy compactions run anytime if neither windows nor a probe are configured, and
+    /// otherwise inside a window or while the utilization is below the threshold.
+    pub(crate) fn allows_heavy_compaction(&self, time_of_day: Duration) -> bool {
+        let utilization = self.utilization.read();
+        if self.windows.is_empty() && utilization.is_none() {
+            return true;
+        }
+        if self
+            .windows
+            .iter()
+            .any(|window| window.contains(time_of_day))
+        {
+            return true;
+        }
+        utilization
+            .as_ref()
+            .is_some_and(|(probe, max_utilization)| probe() < *max_utilization)
+    }
+}
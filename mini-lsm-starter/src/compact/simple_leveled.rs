@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 
 use crate::lsm_storage::LsmStorageState;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleLeveledCompactionOptions {
     pub size_ratio_percent: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -42,14 +44,48 @@ impl SimpleLeveledCompactionController {
         Self { options }
     }
 
+    pub fn options(&self) -> &SimpleLeveledCompactionOptions {
+        &self.options
+    }
+
     /// Generates a compaction task.
     ///
     /// Returns `None` if no compaction needs to be scheduled. The order of SSTs in the compaction task id vector matters.
     pub fn generate_compaction_task(
         &self,
-        _snapshot: &LsmStorageState,
+        snapshot: &LsmStorageState,
     ) -> Option<SimpleLeveledCompactionTask> {
-        unimplemented!()
+        let max_levels = self.options.max_levels;
+        if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
+            return Some(SimpleLeveledCompactionTask {
+                upper_level: None,
+                upper_level_sst_ids: snapshot.l0_sstables.clone(),
+                lower_level: 1,
+                lower_level_sst_ids: snapshot.levels[0].1.clone(),
+                is_lower_level_bottom_level: max_levels == 1,
+            });
+        }
+
+        // Compact a level with its next level once the next level holds too few SSTs in
+        // proportion, counting the SSTs as the size of the levels
+        for upper_level in 1..max_levels {
+            let lower_level = upper_level + 1;
+            let upper_level_ssts = snapshot.levels[upper_level - 1].1.len();
+            let lower_level_ssts = snapshot.levels[lower_level - 1].1.len();
+            if upper_level_ssts == 0 {
+                continue;
+            }
+            if lower_level_ssts * 100 < upper_level_ssts * self.options.size_ratio_percent {
+                return Some(SimpleLeveledCompactionTask {
+                    upper_level: Some(upper_level),
+                    upper_level_sst_ids: snapshot.levels[upper_level - 1].1.clone(),
+                    lower_level,
+                    lower_level_sst_ids: snapshot.levels[lower_level - 1].1.clone(),
+                    is_lower_level_bottom_level: lower_level == max_levels,
+                });
+            }
+        }
+        None
     }
 
     /// Apply the compaction result.
@@ -61,10 +97,32 @@ impl SimpleLeveledCompactionController {
     /// in your implementation.
     pub fn apply_compaction_result(
         &self,
-        _snapshot: &LsmStorageState,
-        _task: &SimpleLeveledCompactionTask,
-        _output: &[usize],
+        snapshot: &LsmStorageState,
+        task: &SimpleLeveledCompactionTask,
+        output: &[usize],
     ) -> (LsmStorageState, Vec<usize>) {
-        unimplemented!()
+        let mut new_snapshot = snapshot.clone();
+        let upper_level_sst_ids = task
+            .upper_level_sst_ids
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        // New SSTs may have been flushed to L0 while compacting, which are kept
+        match task.upper_level {
+            Some(upper_level) => new_snapshot.levels[upper_level - 1]
+                .1
+                .retain(|id| !upper_level_sst_ids.contains(id)),
+            None => new_snapshot
+                .l0_sstables
+                .retain(|id| !upper_level_sst_ids.contains(id)),
+        }
+        // The whole lower level is compacted, so the output replaces it
+        new_snapshot.levels[task.lower_level - 1].1 = output.to_vec();
+        let files_to_remove = [
+            task.upper_level_sst_ids.as_slice(),
+            task.lower_level_sst_ids.as_slice(),
+        ]
+        .concat();
+        (new_snapshot, files_to_remove)
     }
 }
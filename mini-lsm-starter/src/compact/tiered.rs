@@ -18,16 +18,24 @@ use crate::lsm_storage::LsmStorageState;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TieredCompactionTask {
+    /// Adjacent tiers, from the newest, merged into one tier in their place.
     pub tiers: Vec<(usize, Vec<usize>)>,
     pub bottom_tier_included: bool,
 }
 
-#[derive(Debug, Clone)]
+/// Tiered compaction (as RocksDB's universal compaction): each flush adds a tier on top of the
+/// others, and adjacent tiers are merged into one, counting the SSTs of the tiers as their sizes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TieredCompactionOptions {
+    /// The number of tiers from which on tiers are merged.
     pub num_tiers: usize,
+    /// Merge all tiers once the tiers above the last one are this large in percent of it.
     pub max_size_amplification_percent: usize,
+    /// Merge the tiers above a tier that is more than `100 + size_ratio` percent of their size.
     pub size_ratio: usize,
+    /// The fewest tiers merged for the size ratio.
     pub min_merge_width: usize,
+    /// The most tiers merged when merging the newest tiers to reduce the number of tiers.
     pub max_merge_width: Option<usize>,
 }
 
@@ -40,19 +48,81 @@ impl TieredCompactionController {
         Self { options }
     }
 
+    pub fn options(&self) -> &TieredCompactionOptions {
+        &self.options
+    }
+
     pub fn generate_compaction_task(
         &self,
-        _snapshot: &LsmStorageState,
+        snapshot: &LsmStorageState,
     ) -> Option<TieredCompactionTask> {
-        unimplemented!()
+        let tiers = &snapshot.levels;
+        if tiers.len() < self.options.num_tiers {
+            return None;
+        }
+        let tier_size = |idx: usize| tiers[idx].1.len();
+
+        // The tiers above the last one are the space amplification, as the last tier holds most
+        // of the keys they overwrite
+        let upper_size = (0..tiers.len() - 1).map(tier_size).sum::<usize>();
+        if upper_size * 100
+            >= tier_size(tiers.len() - 1) * self.options.max_size_amplification_percent
+        {
+            return Some(TieredCompactionTask {
+                tiers: tiers.clone(),
+                bottom_tier_included: true,
+            });
+        }
+
+        // Merge the newest tiers into the next one once it is much larger than all of them
+        let mut size = 0;
+        for idx in 0..tiers.len() - 1 {
+            size += tier_size(idx);
+            let num_tiers = idx + 1;
+            if tier_size(idx + 1) * 100 > size * (100 + self.options.size_ratio)
+                && num_tiers >= self.options.min_merge_width
+            {
+                return Some(TieredCompactionTask {
+                    tiers: tiers[..num_tiers].to_vec(),
+                    bottom_tier_included: false,
+                });
+            }
+        }
+
+        // Otherwise reduce the number of tiers by merging the newest ones
+        let num_tiers = tiers
+            .len()
+            .min(self.options.max_merge_width.unwrap_or(usize::MAX));
+        Some(TieredCompactionTask {
+            tiers: tiers[..num_tiers].to_vec(),
+            bottom_tier_included: num_tiers == tiers.len(),
+        })
     }
 
     pub fn apply_compaction_result(
         &self,
-        _snapshot: &LsmStorageState,
-        _task: &TieredCompactionTask,
-        _output: &[usize],
+        snapshot: &LsmStorageState,
+        task: &TieredCompactionTask,
+        output: &[usize],
     ) -> (LsmStorageState, Vec<usize>) {
-        unimplemented!()
+        let mut new_snapshot = snapshot.clone();
+        // New tiers may have been flushed on top while compacting, which are kept
+        let start = new_snapshot
+            .levels
+            .iter()
+            .position(|(tier_id, _)| *tier_id == task.tiers[0].0)
+            .expect("the compacted tiers are in the levels");
+        let new_tier = output.first().map(|&tier_id| (tier_id, output.to_vec()));
+        let removed = new_snapshot
+            .levels
+            .splice(start..start + task.tiers.len(), new_tier)
+            .collect::<Vec<_>>();
+        assert_eq!(removed, task.tiers, "the compacted tiers changed");
+        let files_to_remove = task
+            .tiers
+            .iter()
+            .flat_map(|(_, sst_ids)| sst_ids.iter().copied())
+            .collect();
+        (new_snapshot, files_to_remove)
     }
 }
@@ -0,0 +1,105 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+
+/// Drops the versions of the keys of a compaction that no read can see anymore: those older than
+/// the latest version at or below the watermark, which all the reads see instead. That version
+/// is dropped as well if it is a delete and `drop_deletes` is set, i.e., in the bottom level,
+/// where no older version is left for it to hide.
+pub(crate) struct VersionGcIterator<I> {
+    iter: I,
+    watermark: u64,
+    drop_deletes: bool,
+    /// The key whose version at or below the watermark was reached, empty if none.
+    gc_key: Vec<u8>,
+}
+
+impl<I> VersionGcIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    pub(crate) fn new(iter: I, watermark: u64, drop_deletes: bool) -> Result<Self> {
+        let mut iter = Self {
+            iter,
+            watermark,
+            drop_deletes,
+            gc_key: Vec::new(),
+        };
+        iter.skip_invisible()?;
+        Ok(iter)
+    }
+
+    fn skip_invisible(&mut self) -> Result<()> {
+        while self.iter.is_valid() {
+            let key = self.iter.key();
+            if key.key_ref() == self.gc_key {
+                self.iter.next()?;
+                continue;
+            }
+            if key.ts() > self.watermark {
+                return Ok(());
+            }
+            self.gc_key.clear();
+            self.gc_key.extend_from_slice(key.key_ref());
+            if !(self.drop_deletes && self.iter.is_deleted()) {
+                return Ok(());
+            }
+            self.iter.next()?;
+        }
+        Ok(())
+    }
+}
+
+impl<I> StorageIterator for VersionGcIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    type KeyType<'a>
+        = I::KeyType<'a>
+    where
+        Self: 'a;
+
+    fn key(&self) -> Self::KeyType<'_> {
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.iter.value()
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.iter.value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.iter.is_deleted()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()?;
+        self.skip_invisible()
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.iter.num_active_iterators()
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compaction filters, which drop or rewrite entries as they are compacted, e.g. to purge expired
+//! sessions or soft-deleted rows without deleting them explicitly. See
+//! `LsmStorageOptions::compaction_filter`.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::ttl;
+
+/// What to do with an entry being compacted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompactionDecision {
+    Keep,
+    /// Delete the key. The entry is replaced by a tombstone, so that an older version of the key
+    /// in a lower level does not become visible again, and dropped once it reaches the bottom
+    /// level.
+    Remove,
+    /// Write this value instead.
+    ChangeValue(Bytes),
+}
+
+pub trait CompactionFilter: Send + Sync + Debug {
+    /// Decide the fate of the latest version of `key` among the compacted SSTs. Deletes are not
+    /// passed to the filter. An entry kept or changed is filtered again by the next compactions,
+    /// so filters should give the same decision for the value they wrote.
+    fn filter(&self, key: &[u8], value: &[u8]) -> CompactionDecision;
+}
+
+/// Runs a compaction filter on the entries of a compaction. The expiry time of a TTL is hidden
+/// from the filter and kept when it changes the value.
+pub(crate) struct CompactionFilterIterator<I> {
+    iter: I,
+    filter: Option<Arc<dyn CompactionFilter>>,
+    has_expiry: bool,
+    decision: CompactionDecision,
+}
+
+impl<I> CompactionFilterIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    pub(crate) fn new(
+        iter: I,
+        filter: Option<Arc<dyn CompactionFilter>>,
+        has_expiry: bool,
+    ) -> Self {
+        let mut iter = Self {
+            iter,
+            filter,
+            has_expiry,
+            decision: CompactionDecision::Keep,
+        };
+        iter.filter_current();
+        iter
+    }
+
+    fn filter_current(&mut self) {
+        self.decision = CompactionDecision::Keep;
+        let Some(filter) = &self.filter else {
+            return;
+        };
+        if !self.iter.is_valid() || self.iter.is_deleted() {
+            return;
+        }
+        let value = self.iter.value();
+        let (value, expiry) = match self.has_expiry {
+            true => {
+                let (value, expiry) = ttl::split_expiry(value);
+                (value, Some(expiry))
+            }
+            false => (value, None),
+        };
+        self.decision = match (filter.filter(self.iter.key().key_ref(), value), expiry) {
+            (CompactionDecision::ChangeValue(value), Some(expiry)) => {
+                CompactionDecision::ChangeValue(ttl::append_expiry(&value, expiry).into())
+            }
+            (decision, _) => decision,
+        };
+    }
+}
+
+impl<I> StorageIterator for CompactionFilterIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    type KeyType<'a>
+        = I::KeyType<'a>
+    where
+        Self: 'a;
+
+    fn key(&self) -> Self::KeyType<'_> {
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        match &self.decision {
+            CompactionDecision::Keep => self.iter.value(),
+            CompactionDecision::Remove => &[],
+            CompactionDecision::ChangeValue(value) => value,
+        }
+    }
+
+    fn value_meta(&self) -> u8 {
+        match self.decision {
+            CompactionDecision::Remove => 0,
+            _ => self.iter.value_meta(),
+        }
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.iter.is_deleted() || self.decision == CompactionDecision::Remove
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()?;
+        self.filter_current();
+        Ok(())
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.iter.num_active_iterators()
+    }
+}
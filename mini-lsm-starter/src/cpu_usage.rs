@@ -0,0 +1,93 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The CPU time spent in the flushes and compactions, so that the CPU of the machine can be
+//! attributed to the background work and the foreground reads and writes.
+//!
+//! Each task is measured with the CPU clock of the thread running it, which only counts the time
+//! the thread was on a CPU, not the time it waited for the disk or for locks. A flush or
+//! compaction run by `force_flush` or `force_full_compaction` counts as background work too.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::lsm_storage::BackgroundTask;
+
+/// The flushes and compactions run so far, and the CPU time they took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackgroundCpuStats {
+    pub flushes: u64,
+    pub flush_cpu_time: Duration,
+    pub compactions: u64,
+    pub compaction_cpu_time: Duration,
+}
+
+#[derive(Default)]
+struct TaskCounters {
+    tasks: AtomicU64,
+    cpu_nanos: AtomicU64,
+}
+
+#[derive(Default)]
+pub(crate) struct BackgroundCpuUsage {
+    flushes: TaskCounters,
+    compactions: TaskCounters,
+}
+
+/// The CPU time consumed by the current thread.
+fn thread_cpu_time() -> Duration {
+    let mut time = std::mem::MaybeUninit::<libc::timespec>::uninit();
+    // SAFETY: `time` is only read after `clock_gettime` fills it
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, time.as_mut_ptr()) } != 0 {
+        return Duration::ZERO;
+    }
+    let time = unsafe { time.assume_init() };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+impl BackgroundCpuUsage {
+    /// Run a flush or compaction task on the current thread and record the CPU time it took,
+    /// whether it succeeds or not.
+    pub(crate) fn measure<T>(&self, task: BackgroundTask, run: impl FnOnce() -> T) -> T {
+        let start = thread_cpu_time();
+        let result = run();
+        let cpu_time = thread_cpu_time().saturating_sub(start);
+        let counters = match task {
+            BackgroundTask::Flush => &self.flushes,
+            BackgroundTask::Compaction => &self.compactions,
+        };
+        counters.tasks.fetch_add(1, Ordering::Relaxed);
+        counters
+            .cpu_nanos
+            .fetch_add(cpu_time.as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    pub(crate) fn stats(&self) -> BackgroundCpuStats {
+        let load = |counters: &TaskCounters| {
+            (
+                counters.tasks.load(Ordering::Relaxed),
+                Duration::from_nanos(counters.cpu_nanos.load(Ordering::Relaxed)),
+            )
+        };
+        let (flushes, flush_cpu_time) = load(&self.flushes);
+        let (compactions, compaction_cpu_time) = load(&self.compactions);
+        BackgroundCpuStats {
+            flushes,
+            flush_cpu_time,
+            compactions,
+            compaction_cpu_time,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exports scans into CSV or Parquet files, so that they can be queried with tools like DuckDB
+//! without writing a dumper for each storage. Keys and values are turned into text columns
+//! `key` and `value` by decoders, which default to lossy UTF-8.
+//!
+//! Export a snapshot, or a range of it, with the iterator of `Snapshot::scan`:
+//!
+//! ```ignore
+//! let snapshot = storage.snapshot()?;
+//! Exporter::new()
+//!     .with_value_decoder(decode_hex)
+//!     .write_csv(snapshot.scan(Bound::Unbounded, Bound::Unbounded)?, File::create(path)?)?;
+//! ```
+//!
+//! `write_csv` needs the `csv` feature and `write_parquet` the `parquet` feature.
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+
+/// Turns a key or a value into the text of its column.
+pub type Decoder = Box<dyn Fn(&[u8]) -> Result<String> + Send + Sync>;
+
+/// Decode the bytes as UTF-8, replacing the invalid sequences with U+FFFD.
+pub fn decode_utf8_lossy(data: &[u8]) -> Result<String> {
+    Ok(String::from_utf8_lossy(data).into_owned())
+}
+
+/// Decode the bytes as lowercase hex.
+pub fn decode_hex(data: &[u8]) -> Result<String> {
+    let mut text = String::with_capacity(data.len() * 2);
+    for byte in data {
+        write!(text, "{byte:02x}")?;
+    }
+    Ok(text)
+}
+
+/// The default number of rows in a Parquet row group, which bounds the rows kept in memory.
+#[cfg(feature = "parquet")]
+pub const DEFAULT_ROWS_PER_GROUP: usize = 64 * 1024;
+
+pub struct Exporter {
+    key_decoder: Decoder,
+    value_decoder: Decoder,
+    #[cfg(feature = "parquet")]
+    rows_per_group: usize,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Exporter {
+    pub fn new() -> Self {
+        Self {
+            key_decoder: Box::new(decode_utf8_lossy),
+            value_decoder: Box::new(decode_utf8_lossy),
+            #[cfg(feature = "parquet")]
+            rows_per_group: DEFAULT_ROWS_PER_GROUP,
+        }
+    }
+
+    pub fn with_key_decoder(
+        mut self,
+        decoder: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.key_decoder = Box::new(decoder);
+        self
+    }
+
+    pub fn with_value_decoder(
+        mut self,
+        decoder: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.value_decoder = Box::new(decoder);
+        self
+    }
+
+    #[cfg(feature = "parquet")]
+    pub fn with_rows_per_group(mut self, rows_per_group: usize) -> Self {
+        assert!(rows_per_group > 0, "a row group needs at least one row");
+        self.rows_per_group = rows_per_group;
+        self
+    }
+
+    fn decode(&self, iter: &FusedIterator<LsmIterator>) -> Result<(String, String)> {
+        Ok((
+            (self.key_decoder)(iter.key())?,
+            (self.value_decoder)(iter.value())?,
+        ))
+    }
+
+    /// Write the entries of `iter` as CSV rows with a `key,value` header. Returns the number of
+    /// rows written.
+    #[cfg(feature = "csv")]
+    pub fn write_csv(
+        &self,
+        mut iter: FusedIterator<LsmIterator>,
+        writer: impl Write,
+    ) -> Result<u64> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["key", "value"])?;
+        let mut rows = 0;
+        while iter.is_valid() {
+            let (key, value) = self.decode(&iter)?;
+            writer.write_record([key, value])?;
+            rows += 1;
+            iter.next()?;
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+
+    /// Write the entries of `iter` as a Parquet file with the UTF-8 columns `key` and `value`,
+    /// buffering at most one row group at a time. Returns the number of rows written.
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet(
+        &self,
+        mut iter: FusedIterator<LsmIterator>,
+        writer: impl Write + Send,
+    ) -> Result<u64> {
+        use std::sync::Arc;
+
+        use parquet::data_type::{ByteArray, ByteArrayType};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+
+        let schema = parse_message_type(
+            "message entries {
+                REQUIRED BYTE_ARRAY key (UTF8);
+                REQUIRED BYTE_ARRAY value (UTF8);
+            }",
+        )?;
+        let mut writer = SerializedFileWriter::new(
+            writer,
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )?;
+        let mut rows = 0;
+        while iter.is_valid() {
+            let mut keys = Vec::new();
+            let mut values = Vec::new();
+            while iter.is_valid() && keys.len() < self.rows_per_group {
+                let (key, value) = self.decode(&iter)?;
+                keys.push(ByteArray::from(key.into_bytes()));
+                values.push(ByteArray::from(value.into_bytes()));
+                iter.next()?;
+            }
+            let mut row_group = writer.next_row_group()?;
+            for column in [&keys, &values] {
+                let mut column_writer = row_group.next_column()?.unwrap();
+                column_writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(column, None, None)?;
+                column_writer.close()?;
+            }
+            row_group.close()?;
+            rows += keys.len() as u64;
+        }
+        writer.close()?;
+        Ok(rows)
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline migration of a storage directory to the current format version, see `FORMAT_VERSION`.
+//!
+//! `migrate_format` rewrites each live SST with the current SST encoding, then the manifest with
+//! the current record encoding, and finally records the current version in the `OPTIONS` file.
+//! The SSTs rewritten so far are listed in the `MIGRATION` file, so that a migration that was
+//! interrupted resumes where it stopped when it is run again. Only upgrades are possible, as
+//! this build writes the current format only.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::manifest::{Manifest, ManifestRecord, live_ssts};
+use crate::options_file::{FORMAT_VERSION, OPTIONS_FILE, StoredOptions};
+use crate::table::{SsTable, SsTableBuilder};
+
+pub const MIGRATION_FILE: &str = "MIGRATION";
+
+/// The progress of a migration, reported after each SST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationProgress {
+    /// The format version of the directory before the migration.
+    pub from_version: u32,
+    /// The number of SSTs rewritten so far, including those rewritten before a resume.
+    pub migrated_ssts: usize,
+    /// The number of live SSTs.
+    pub total_ssts: usize,
+}
+
+/// Rewrite the storage directory at `path`, opened with `options` before, in the current format
+/// version. The storage must not be open. Directories already in the current version are
+/// rewritten as well.
+pub fn migrate_format(
+    path: impl AsRef<Path>,
+    options: &LsmStorageOptions,
+    mut progress: impl FnMut(MigrationProgress),
+) -> Result<()> {
+    let path = path.as_ref();
+    let Some(stored) = StoredOptions::read(path)? else {
+        bail!("{} has no {} file", path.display(), OPTIONS_FILE);
+    };
+    stored.check_format_version()?;
+    let manifest_path = path.join("MANIFEST");
+    let entries = Manifest::read(&manifest_path)?;
+    let data_paths = entries
+        .iter()
+        .flat_map(|entry| match &entry.record {
+            ManifestRecord::DataPath(sst_id, data_path) => vec![(*sst_id, *data_path)],
+            ManifestRecord::Snapshot(snapshot) => snapshot.data_paths.clone(),
+            _ => vec![],
+        })
+        .collect::<HashMap<_, _>>();
+    let mut ssts = live_ssts(&entries).into_iter().collect::<Vec<_>>();
+    ssts.sort();
+
+    let migration_path = path.join(MIGRATION_FILE);
+    let mut migrated = read_migrated_ssts(&migration_path)?;
+    let mut migration_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&migration_path)?;
+    let mut report = MigrationProgress {
+        from_version: stored.format_version,
+        migrated_ssts: ssts.iter().filter(|(id, _)| migrated.contains(id)).count(),
+        total_ssts: ssts.len(),
+    };
+    progress(report);
+    for (sst_id, epoch) in ssts {
+        if migrated.contains(&sst_id) {
+            continue;
+        }
+        let data_path = data_paths.get(&sst_id).copied().unwrap_or(0);
+        let dir = match options.data_paths.get(data_path) {
+            Some(data_path) => data_path.path.as_path(),
+            None => path,
+        };
+        let sst_path = LsmStorageInner::path_of_sst_static(dir, epoch, sst_id);
+        rewrite_sst(&sst_path, options)
+            .with_context(|| format!("failed to migrate {}", sst_path.display()))?;
+        writeln!(migration_file, "{}", sst_id)?;
+        migration_file.sync_all()?;
+        migrated.insert(sst_id);
+        report.migrated_ssts += 1;
+        progress(report);
+    }
+
+    Manifest::rewrite(&manifest_path, &entries)?;
+    StoredOptions {
+        format_version: FORMAT_VERSION,
+        ..stored
+    }
+    .write(path)?;
+    std::fs::remove_file(&migration_path)?;
+    std::fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// The SSTs listed in the `MIGRATION` file of an interrupted migration.
+fn read_migrated_ssts(path: &Path) -> Result<HashSet<usize>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let data = std::fs::read_to_string(path)?;
+    // A line cut short by a crash is skipped, and its SST is rewritten again
+    data.split_inclusive('\n')
+        .filter_map(|line| line.strip_suffix('\n'))
+        .map(|line| {
+            line.parse::<usize>()
+                .with_context(|| format!("invalid line {:?} in {}", line, path.display()))
+        })
+        .collect()
+}
+
+/// Rewrite an SST in place, keeping its range tombstones and its table properties that depend
+/// on the builder options.
+fn rewrite_sst(path: &Path, options: &LsmStorageOptions) -> Result<()> {
+    let sst = Arc::new(SsTable::open_path(path)?);
+    let properties = sst.properties();
+    let mut builder = SsTableBuilder::new(options.block_size);
+    if let Some(max_block_size) = options.max_block_size {
+        builder = builder.with_max_block_size(max_block_size);
+    }
+    if !properties.prefix_sizes.is_empty() {
+        let prefixes = properties
+            .prefix_sizes
+            .iter()
+            .map(|(prefix, _)| prefix.clone())
+            .collect::<Vec<_>>();
+        builder = builder.with_tenant_prefixes(&prefixes);
+    }
+    if options.ttl.is_some() || properties.expiry_histogram != Default::default() {
+        builder = builder.with_expiry_tracking();
+    }
+    if let Some(extractor) = &options.prefix_extractor {
+        builder = builder.with_prefix_extractor(extractor.clone());
+    }
+    builder.add_range_tombstones(sst.range_tombstones());
+    builder.add_sorted_entries(&mut sst.iter()?, usize::MAX, false)?;
+    let tmp_path = PathBuf::from(format!("{}.migrating", path.display()));
+    builder.build(0, None, &tmp_path)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
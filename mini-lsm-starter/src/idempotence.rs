@@ -0,0 +1,115 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Idempotent writes: a write carrying an `OperationId` is applied once, however many times a
+//! producer retries it. The storage keeps a window of the latest operation IDs applied for each
+//! client, written in the same batch as each write and stored under the keys reserved for the
+//! column families, so that it survives a restart with the writes it covers.
+
+use std::fmt;
+
+use anyhow::{Result, bail};
+use bytes::{Buf, BufMut};
+
+use crate::column_family;
+
+/// The default of `LsmStorageOptions::idempotent_write_window`.
+pub const DEFAULT_IDEMPOTENT_WRITE_WINDOW: usize = 64;
+
+/// Identifies a write by the client sending it and an ID unique within that client. The IDs
+/// of a client should increase, as only the latest ones are remembered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationId {
+    pub client_id: u64,
+    pub op_id: u64,
+}
+
+/// The error returned for an operation older than all the ones remembered for its client, which
+/// cannot be told apart from one already applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleOperation {
+    pub operation: OperationId,
+    /// The oldest operation ID remembered for the client.
+    pub oldest_op_id: u64,
+}
+
+impl fmt::Display for StaleOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "operation {} of client {} is older than the window of its applied operations, which \
+             starts at {}",
+            self.operation.op_id, self.operation.client_id, self.oldest_op_id
+        )
+    }
+}
+
+impl std::error::Error for StaleOperation {}
+
+/// Returns true if the error is caused by an operation too old to be deduplicated.
+pub fn is_stale_operation_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<StaleOperation>().is_some()
+}
+
+/// The key of the window of a client.
+pub(crate) fn window_key(client_id: u64) -> Vec<u8> {
+    column_family::prefixed_key(column_family::OPERATIONS_ID, &client_id.to_be_bytes())
+}
+
+/// The latest operation IDs applied for a client, in ascending order.
+#[derive(Debug, Default)]
+pub(crate) struct OperationWindow {
+    op_ids: Vec<u64>,
+}
+
+impl OperationWindow {
+    pub(crate) fn decode(mut value: &[u8]) -> Result<Self> {
+        if !value.len().is_multiple_of(8) {
+            bail!("malformed operation window of {} bytes", value.len());
+        }
+        let mut op_ids = Vec::with_capacity(value.len() / 8);
+        while value.has_remaining() {
+            op_ids.push(value.get_u64());
+        }
+        Ok(Self { op_ids })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(self.op_ids.len() * 8);
+        for op_id in &self.op_ids {
+            value.put_u64(*op_id);
+        }
+        value
+    }
+
+    /// Record the operation as applied, keeping the latest `capacity` IDs. Returns false if it
+    /// was applied already, and fails if it is older than a full window.
+    pub(crate) fn insert(&mut self, operation: OperationId, capacity: usize) -> Result<bool> {
+        let Err(idx) = self.op_ids.binary_search(&operation.op_id) else {
+            return Ok(false);
+        };
+        if idx == 0 && self.op_ids.len() >= capacity {
+            return Err(StaleOperation {
+                operation,
+                oldest_op_id: self.op_ids.first().copied().unwrap_or(operation.op_id),
+            }
+            .into());
+        }
+        self.op_ids.insert(idx, operation.op_id);
+        if self.op_ids.len() > capacity {
+            self.op_ids.remove(0);
+        }
+        Ok(true)
+    }
+}
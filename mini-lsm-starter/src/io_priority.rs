@@ -0,0 +1,61 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The I/O priority of reads, see `ReadOptions::priority`. Low-priority reads, e.g., background
+//! analytical scans, wait for the `low_priority_read_rate_limit` before reading a block from the
+//! disk, which leaves the disk to the interactive reads. Blocks found in the block cache are not
+//! throttled. Like `ionice`, the priority applies to the thread issuing the read, while it reads.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use crate::rate_limiter::RateLimiter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IoPriority {
+    /// Interactive reads, never throttled.
+    #[default]
+    High,
+    /// Background reads, throttled by the `low_priority_read_rate_limit`.
+    Low,
+}
+
+thread_local! {
+    static READ_THROTTLE: RefCell<Option<Arc<RateLimiter>>> = const { RefCell::new(None) };
+}
+
+/// Throttles the disk reads of the current thread until dropped.
+pub(crate) struct ReadThrottleGuard {
+    previous: Option<Arc<RateLimiter>>,
+}
+
+/// Throttle the disk reads of the current thread with `limiter` until the guard is dropped.
+pub(crate) fn throttle_reads(limiter: Arc<RateLimiter>) -> ReadThrottleGuard {
+    let previous = READ_THROTTLE.with(|throttle| throttle.borrow_mut().replace(limiter));
+    ReadThrottleGuard { previous }
+}
+
+impl Drop for ReadThrottleGuard {
+    fn drop(&mut self) {
+        READ_THROTTLE.with(|throttle| *throttle.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Wait until the current thread may read `bytes` from the disk.
+pub(crate) fn before_disk_read(bytes: usize) {
+    let limiter = READ_THROTTLE.with(|throttle| throttle.borrow().clone());
+    if let Some(limiter) = limiter {
+        limiter.request(bytes);
+    }
+}
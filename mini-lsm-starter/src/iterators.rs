@@ -17,7 +17,7 @@ pub mod merge_iterator;
 pub mod two_merge_iterator;
 
 pub trait StorageIterator {
-    type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord
+    type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord + Default + Copy
     where
         Self: 'a;
 
@@ -33,6 +33,23 @@ pub trait StorageIterator {
     /// Move to the next position.
     fn next(&mut self) -> anyhow::Result<()>;
 
+    /// Move to the first entry at or after `key`, which may be before the current position.
+    /// Fails for the iterators that cannot reposition themselves.
+    fn seek(&mut self, _key: Self::KeyType<'_>) -> anyhow::Result<()> {
+        anyhow::bail!("seek is not supported by this iterator")
+    }
+
+    /// Get the user metadata byte of the current value, 0 if none was set.
+    fn value_meta(&self) -> u8 {
+        0
+    }
+
+    /// Check if the current entry is a delete. Only iterators that can return empty values need
+    /// to override this.
+    fn is_deleted(&self) -> bool {
+        self.value().is_empty()
+    }
+
     /// Number of underlying active iterators for this iterator.
     fn num_active_iterators(&self) -> usize {
         1
@@ -15,6 +15,7 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
+use std::ops::Bound;
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -22,44 +23,182 @@ use anyhow::Result;
 use super::StorageIterator;
 use crate::{
     key::KeySlice,
+    prefetch::ScanPrefetch,
+    scan_memory::ScanMemory,
     table::{SsTable, SsTableIterator},
 };
 
 /// Concat multiple iterators ordered in key order and their key ranges do not overlap. We do not want to create the
 /// iterators when initializing this iterator to reduce the overhead of seeking.
+///
+/// A reverse iterator, see `create_and_seek_to_bound_rev`, moves from the last key to the first
+/// one on `next`.
 pub struct SstConcatIterator {
     current: Option<SsTableIterator>,
+    /// The next SST to open, or if `reverse`, the one after it.
     next_sst_idx: usize,
     sstables: Vec<Arc<SsTable>>,
+    prefetch: Option<Arc<ScanPrefetch>>,
+    memory: Option<Arc<ScanMemory>>,
+    reverse: bool,
 }
 
 impl SstConcatIterator {
     pub fn create_and_seek_to_first(sstables: Vec<Arc<SsTable>>) -> Result<Self> {
-        unimplemented!()
+        Self::create_and_seek_to_bound(sstables, Bound::Unbounded, None, None)
     }
 
     pub fn create_and_seek_to_key(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
-        unimplemented!()
+        let mut iter =
+            Self::create_and_seek_to_bound(sstables, Bound::Included(key.key_ref()), None, None)?;
+        // Skip the versions of the key newer than `key`
+        while iter.is_valid() && iter.key() < key {
+            iter.next()?;
+        }
+        Ok(iter)
+    }
+
+    /// Create an iterator seeking to the first key within `lower`, whose SST iterators use the
+    /// given prefetches and memory accounting of a scan.
+    pub(crate) fn create_and_seek_to_bound(
+        sstables: Vec<Arc<SsTable>>,
+        lower: Bound<&[u8]>,
+        prefetch: Option<Arc<ScanPrefetch>>,
+        memory: Option<Arc<ScanMemory>>,
+    ) -> Result<Self> {
+        let next_sst_idx = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                sstables.partition_point(|table| table.last_key().key_ref() < key)
+            }
+            Bound::Unbounded => 0,
+        };
+        let mut iter = Self {
+            current: None,
+            next_sst_idx,
+            sstables,
+            prefetch,
+            memory,
+            reverse: false,
+        };
+        iter.open_next(lower)?;
+        Ok(iter)
+    }
+
+    /// Create a reverse iterator seeking to the last key within `upper`, whose SST iterators use
+    /// the given memory accounting of a scan.
+    pub(crate) fn create_and_seek_to_bound_rev(
+        sstables: Vec<Arc<SsTable>>,
+        upper: Bound<&[u8]>,
+        memory: Option<Arc<ScanMemory>>,
+    ) -> Result<Self> {
+        let next_sst_idx = match upper {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                sstables.partition_point(|table| table.first_key().key_ref() <= key)
+            }
+            Bound::Unbounded => sstables.len(),
+        };
+        let mut iter = Self {
+            current: None,
+            next_sst_idx,
+            sstables,
+            prefetch: None,
+            memory,
+            reverse: true,
+        };
+        iter.open_prev(upper)?;
+        Ok(iter)
+    }
+
+    /// Open the SSTs before `next_sst_idx` from the last one until one has a key within `upper`.
+    fn open_prev(&mut self, upper: Bound<&[u8]>) -> Result<()> {
+        self.current = None;
+        while self.next_sst_idx > 0 {
+            self.next_sst_idx -= 1;
+            let table = self.sstables[self.next_sst_idx].clone();
+            let mut iter = SsTableIterator::create_and_seek_to_bound_rev(table, upper)?;
+            if let Some(memory) = &self.memory {
+                iter = iter.with_memory(memory.clone())?;
+            }
+            if iter.is_valid() {
+                self.current = Some(iter);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the SSTs from `next_sst_idx` until one has a key within `lower`.
+    fn open_next(&mut self, lower: Bound<&[u8]>) -> Result<()> {
+        // Release the block of the exhausted SST before reading the next one
+        self.current = None;
+        while let Some(table) = self.sstables.get(self.next_sst_idx) {
+            self.next_sst_idx += 1;
+            let mut iter = SsTableIterator::create_and_seek_to_bound(table.clone(), lower)?;
+            if let Some(prefetch) = &self.prefetch {
+                iter = iter.with_prefetch(prefetch.clone());
+            }
+            if let Some(memory) = &self.memory {
+                iter = iter.with_memory(memory.clone())?;
+            }
+            if iter.is_valid() {
+                self.current = Some(iter);
+                break;
+            }
+        }
+        Ok(())
     }
 }
 
 impl StorageIterator for SstConcatIterator {
     type KeyType<'a> = KeySlice<'a>;
 
-    fn key(&self) -> KeySlice {
-        unimplemented!()
+    fn key(&self) -> KeySlice<'_> {
+        self.current.as_ref().unwrap().key()
     }
 
     fn value(&self) -> &[u8] {
-        unimplemented!()
+        self.current.as_ref().unwrap().value()
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.current.as_ref().unwrap().value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.current.as_ref().unwrap().is_deleted()
     }
 
     fn is_valid(&self) -> bool {
-        unimplemented!()
+        self.current.as_ref().is_some_and(|iter| iter.is_valid())
     }
 
     fn next(&mut self) -> Result<()> {
-        unimplemented!()
+        let current = self.current.as_mut().unwrap();
+        if self.reverse {
+            current.prev()?;
+            if !current.is_valid() {
+                self.open_prev(Bound::Unbounded)?;
+            }
+            return Ok(());
+        }
+        current.next()?;
+        if !current.is_valid() {
+            self.open_next(Bound::Unbounded)?;
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        anyhow::ensure!(!self.reverse, "seek is not supported by reverse iterators");
+        self.next_sst_idx = self
+            .sstables
+            .partition_point(|table| table.last_key().as_key_slice() < key);
+        self.open_next(Bound::Included(key.key_ref()))?;
+        // Skip the versions of the key newer than `key`
+        while self.is_valid() && self.key() < key {
+            self.next()?;
+        }
+        Ok(())
     }
 
     fn num_active_iterators(&self) -> usize {
@@ -12,79 +12,113 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cmp::{self};
-use std::collections::BinaryHeap;
-use std::collections::binary_heap::PeekMut;
+use std::cmp;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 
-use crate::key::KeySlice;
+use crate::key::{KeySlice, KeyVec};
 
 use super::StorageIterator;
 
-struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
-
-impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(other) == cmp::Ordering::Equal
-    }
-}
-
-impl<I: StorageIterator> Eq for HeapWrapper<I> {}
-
-impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl<I: StorageIterator> Ord for HeapWrapper<I> {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.1
-            .key()
-            .cmp(&other.1.key())
-            .then(self.0.cmp(&other.0))
-            .reverse()
-    }
-}
-
 /// Merge multiple iterators of the same type. If the same key occurs multiple times in some
 /// iterators, prefer the one with smaller index.
+///
+/// The iterators are the leaves of a loser tree (tournament tree). Each inner node keeps the
+/// iterator that lost the match played there, and the overall winner is kept at the root, so
+/// moving the winner forward only replays the matches on the path from its leaf to the root:
+/// about `log2(n)` comparisons per `next`, where a binary heap needs up to twice as many to
+/// sift the winner down. Invalid iterators lose every match, and are kept so that a `seek`
+/// may make them valid again.
 pub struct MergeIterator<I: StorageIterator> {
-    iters: BinaryHeap<HeapWrapper<I>>,
-    current: Option<HeapWrapper<I>>,
+    iters: Vec<Box<I>>,
+    /// `tree[0]` is the index of the winner, and `tree[node]` the index of the loser at `node`
+    /// for the inner nodes `1..n`. The leaf of iterator `i` is node `n + i`, and the parent of
+    /// a node is `node / 2`.
+    tree: Vec<usize>,
+    /// The key of the winner before it moved forward, to skip that key in the other iterators.
+    prev_key: KeyVec,
+    reverse: bool,
 }
 
 impl<I: StorageIterator> MergeIterator<I> {
     pub fn create(iters: Vec<Box<I>>) -> Self {
-        if iters.is_empty() {
-            return Self {
-                iters: BinaryHeap::new(),
-                current: None,
-            };
-        }
+        Self::create_with_order(iters, false)
+    }
 
-        let mut heap = BinaryHeap::new();
+    /// Merge iterators that each move from their last key to their first one, yielding the keys
+    /// in descending order.
+    pub fn create_rev(iters: Vec<Box<I>>) -> Self {
+        Self::create_with_order(iters, true)
+    }
+
+    fn create_with_order(iters: Vec<Box<I>>, reverse: bool) -> Self {
+        let mut iter = Self {
+            tree: vec![0; iters.len()],
+            iters,
+            prev_key: KeyVec::new(),
+            reverse,
+        };
+        iter.rebuild();
+        iter
+    }
 
-        // If all iterators are invalid
-        if iters.iter().all(|iter| !iter.is_valid()) {
-            return Self {
-                iters: heap,
-                current: None,
+    /// Whether iterator `a` comes before iterator `b` in the merge.
+    fn beats(&self, a: usize, b: usize) -> bool {
+        let (iter_a, iter_b) = (&self.iters[a], &self.iters[b]);
+        match (iter_a.is_valid(), iter_b.is_valid()) {
+            (true, true) => {
+                let key_order = iter_a.key().cmp(&iter_b.key());
+                let key_order = if self.reverse {
+                    key_order.reverse()
+                } else {
+                    key_order
+                };
+                key_order.then(a.cmp(&b)) == cmp::Ordering::Less
+            }
+            (valid_a, valid_b) => valid_a && !valid_b || valid_a == valid_b && a < b,
+        }
+    }
+
+    /// Play all the matches of the tree from the leaves up.
+    fn rebuild(&mut self) {
+        let n = self.iters.len();
+        if n == 0 {
+            return;
+        }
+        // The winner of the matches below each node, the leaves being `n..2n`
+        let mut winners = vec![0; 2 * n];
+        for (i, winner) in winners[n..].iter_mut().enumerate() {
+            *winner = i;
+        }
+        for node in (1..n).rev() {
+            let (left, right) = (winners[2 * node], winners[2 * node + 1]);
+            let (winner, loser) = if self.beats(right, left) {
+                (right, left)
+            } else {
+                (left, right)
             };
+            winners[node] = winner;
+            self.tree[node] = loser;
         }
+        self.tree[0] = winners[1.min(2 * n - 1)];
+    }
 
-        for (index, iter) in iters.into_iter().enumerate() {
-            if iter.is_valid() {
-                heap.push(HeapWrapper(index, iter));
+    /// Replay the matches on the path from the leaf of iterator `i` to the root, after `i`
+    /// moved forward.
+    fn replay(&mut self, i: usize) {
+        let mut winner = i;
+        let mut node = (self.iters.len() + i) / 2;
+        while node > 0 {
+            if self.beats(self.tree[node], winner) {
+                std::mem::swap(&mut self.tree[node], &mut winner);
             }
+            node /= 2;
         }
+        self.tree[0] = winner;
+    }
 
-        let current = heap.pop().unwrap();
-        Self {
-            iters: heap,
-            current: Some(current),
-        }
+    fn current(&self) -> &I {
+        &self.iters[self.tree[0]]
     }
 }
 
@@ -93,69 +127,66 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
 {
     type KeyType<'a> = KeySlice<'a>;
 
-    fn key(&self) -> KeySlice {
-        self.current.as_ref().unwrap().1.key()
+    fn key(&self) -> KeySlice<'_> {
+        self.current().key()
     }
 
     fn value(&self) -> &[u8] {
-        self.current.as_ref().unwrap().1.value()
+        self.current().value()
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.current().value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.current().is_deleted()
     }
 
     fn is_valid(&self) -> bool {
-        self.current
-            .as_ref()
-            .map(|c| c.1.is_valid())
-            .unwrap_or(false)
+        !self.iters.is_empty() && self.current().is_valid()
     }
 
     fn next(&mut self) -> Result<()> {
-        let current = self.current.as_mut().unwrap();
-        while let Some(mut inner_iter) = self.iters.peek_mut() {
-            if inner_iter.1.key() == current.1.key() {
-                if let Err(err) = inner_iter.1.next() {
-                    PeekMut::pop(inner_iter);
-                    return Err(err);
+        let current = self.tree[0];
+        self.prev_key.set_from_slice(self.iters[current].key());
+        self.iters[current].next()?;
+        self.replay(current);
+
+        // Skip the same key in the other iterators, which now win in turn
+        while self.is_valid() {
+            let key_order = self.key().cmp(&self.prev_key.as_key_slice());
+            match key_order {
+                cmp::Ordering::Equal => {
+                    let winner = self.tree[0];
+                    self.iters[winner].next()?;
+                    self.replay(winner);
                 }
-
-                if !inner_iter.1.is_valid() {
-                    PeekMut::pop(inner_iter);
+                cmp::Ordering::Less if !self.reverse => {
+                    bail!("MergeIterator should always return keys in sorted order")
                 }
-            } else if inner_iter.1.key() < current.1.key() {
-                return Err(anyhow::anyhow!(
-                    "MergeIterator should always return keys in sorted order"
-                ));
-            } else {
-                break;
-            }
-        }
-
-        current.1.next()?;
-
-        if !current.1.is_valid() {
-            if let Some(next) = self.iters.pop() {
-                *current = next;
-            }
-            return Ok(());
-        }
-
-        if let Some(mut inner_iter) = self.iters.peek_mut() {
-            if *current < *inner_iter {
-                std::mem::swap(current, &mut *inner_iter);
+                cmp::Ordering::Greater if self.reverse => {
+                    bail!("MergeIterator should always return keys in sorted order")
+                }
+                _ => break,
             }
         }
-
         Ok(())
     }
 
+    /// Seek all the iterators, including the exhausted ones, and merge them again.
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        anyhow::ensure!(!self.reverse, "seek is not supported by reverse iterators");
+        let result = self.iters.iter_mut().try_for_each(|iter| iter.seek(key));
+        self.rebuild();
+        result
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.iters
             .iter()
-            .map(|x| x.1.num_active_iterators())
-            .sum::<usize>()
-            + self
-                .current
-                .as_ref()
-                .map(|x| x.1.num_active_iterators())
-                .unwrap_or(0)
+            .filter(|iter| iter.is_valid())
+            .map(|iter| iter.num_active_iterators())
+            .sum()
     }
 }
@@ -25,6 +25,8 @@ pub struct TwoMergeIterator<A: StorageIterator, B: StorageIterator> {
     a: A,
     b: B,
     flag: bool, // true if a is chosen, false if b is chosen
+    /// Whether the iterators yield their keys in descending order.
+    reverse: bool,
 }
 
 impl<
@@ -33,27 +35,39 @@ impl<
 > TwoMergeIterator<A, B>
 {
     pub fn create(a: A, b: B) -> Result<Self> {
-        let mut iter = Self { a, b, flag: false };
+        Self::create_with_order(a, b, false)
+    }
+
+    /// Merge two iterators yielding their keys in descending order.
+    pub fn create_rev(a: A, b: B) -> Result<Self> {
+        Self::create_with_order(a, b, true)
+    }
+
+    fn create_with_order(a: A, b: B, reverse: bool) -> Result<Self> {
+        let mut iter = Self {
+            a,
+            b,
+            flag: false,
+            reverse,
+        };
         iter.skip_b()?;
-        iter.flag = Self::choose_a(&iter.a, &iter.b);
+        iter.flag = iter.choose_a();
         Ok(iter)
     }
 
-    fn choose_a(a: &A, b: &B) -> bool {
-        if !a.is_valid() {
+    fn choose_a(&self) -> bool {
+        if !self.a.is_valid() {
             return false;
         }
-        if !b.is_valid() {
+        if !self.b.is_valid() {
             return true;
         }
-        a.key() < b.key()
+        (self.a.key() < self.b.key()) != self.reverse
     }
 
     fn skip_b(&mut self) -> Result<()> {
-        if self.a.is_valid() {
-            if self.b.is_valid() && self.a.key() == self.b.key() {
-                self.b.next()?;
-            }
+        if self.a.is_valid() && self.b.is_valid() && self.a.key() == self.b.key() {
+            self.b.next()?;
         }
         Ok(())
     }
@@ -82,6 +96,22 @@ impl<
         }
     }
 
+    fn value_meta(&self) -> u8 {
+        if self.flag {
+            self.a.value_meta()
+        } else {
+            self.b.value_meta()
+        }
+    }
+
+    fn is_deleted(&self) -> bool {
+        if self.flag {
+            self.a.is_deleted()
+        } else {
+            self.b.is_deleted()
+        }
+    }
+
     fn is_valid(&self) -> bool {
         if self.flag {
             self.a.is_valid()
@@ -97,7 +127,16 @@ impl<
             self.b.next()?;
         }
         self.skip_b()?;
-        self.flag = Self::choose_a(&self.a, &self.b);
+        self.flag = self.choose_a();
+        Ok(())
+    }
+
+    fn seek(&mut self, key: Self::KeyType<'_>) -> Result<()> {
+        anyhow::ensure!(!self.reverse, "seek is not supported by reverse iterators");
+        self.a.seek(key)?;
+        self.b.seek(key)?;
+        self.skip_b()?;
+        self.flag = self.choose_a();
         Ok(())
     }
 
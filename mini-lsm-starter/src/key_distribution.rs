@@ -0,0 +1,156 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An approximate histogram of the keys in the SSTs. The bucket boundaries are chosen from a
+//! sample of the keys, see `crate::key_sample`, and the entries and bytes of each bucket are
+//! estimated from the block indexes and the table properties without reading any block. This is
+//! cheap enough to refresh the counts whenever the SSTs change, e.g., after each compaction.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::{Result, ensure};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::column_family::CF_KEY_PREFIX;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageState};
+
+/// The number of keys sampled for each bucket to choose the boundaries.
+const SAMPLES_PER_BUCKET: usize = 16;
+
+/// A range of keys and the approximate number of entries and bytes of the SSTs in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBucket {
+    /// The first key of the bucket, empty for the first bucket.
+    pub lower: Bytes,
+    /// The key after the bucket, `None` for the last bucket, or `CF_KEY_PREFIX` with
+    /// `LsmStorageOptions::internal_keyspace`, whose reserved keys are left out.
+    pub upper: Option<Bytes>,
+    /// The number of entries, including deletes and overwritten versions.
+    pub entries: u64,
+    /// The total length of the keys and values.
+    pub bytes: u64,
+}
+
+/// Keeps the buckets last returned by `key_distribution`, to refresh their counts when the SSTs
+/// change without sampling the boundaries again.
+#[derive(Default)]
+pub(crate) struct KeyDistributionTracker {
+    /// The number of buckets requested and the buckets.
+    buckets: Mutex<Option<(usize, Vec<KeyBucket>)>>,
+}
+
+impl KeyDistributionTracker {
+    /// Recompute the counts of the buckets after the set of SSTs changed.
+    pub(crate) fn refresh(&self, snapshot: &LsmStorageState) {
+        if let Some((_, buckets)) = self.buckets.lock().as_mut() {
+            count_buckets(buckets, snapshot);
+        }
+    }
+}
+
+/// Spread the entries and the bytes of each SST evenly over its blocks, and add each block to the
+/// bucket of its first key, skipping the blocks after the last bucket. An SST whose block index
+/// cannot be read back from the disk is counted as one block.
+fn count_buckets(buckets: &mut [KeyBucket], snapshot: &LsmStorageState) {
+    for bucket in buckets.iter_mut() {
+        bucket.entries = 0;
+        bucket.bytes = 0;
+    }
+    for sst in snapshot.sstables.values() {
+        let metadata = sst.metadata();
+        let first_keys = match &metadata {
+            Ok(metadata) => metadata
+                .block_meta()
+                .iter()
+                .map(|meta| meta.first_key.key_ref())
+                .collect(),
+            Err(_) => vec![sst.first_key().key_ref()],
+        };
+        let num_blocks = first_keys.len() as u64;
+        let properties = sst.properties();
+        let bytes = properties.raw_key_size + properties.raw_value_size;
+        for (idx, first_key) in first_keys.into_iter().enumerate() {
+            let idx = idx as u64;
+            let bucket = buckets.partition_point(|bucket| {
+                bucket
+                    .upper
+                    .as_ref()
+                    .is_some_and(|upper| upper.as_ref() <= first_key)
+            });
+            let Some(bucket) = buckets.get_mut(bucket) else {
+                continue;
+            };
+            bucket.entries += properties.num_entries * (idx + 1) / num_blocks
+                - properties.num_entries * idx / num_blocks;
+            bucket.bytes += bytes * (idx + 1) / num_blocks - bytes * idx / num_blocks;
+        }
+    }
+}
+
+impl LsmStorageInner {
+    /// The approximate distribution of the keys of the SSTs over `buckets` ranges holding about
+    /// as many blocks each, fewer if the SSTs have too few blocks to tell them apart. The
+    /// boundaries are sampled on the first call and kept for the following calls with the same
+    /// number of buckets, while the counts are refreshed as the SSTs change. Like
+    /// `sample_keys`, the memtables are left out.
+    pub fn key_distribution(&self, buckets: usize) -> Result<Vec<KeyBucket>> {
+        ensure!(
+            buckets > 0,
+            "the key distribution needs at least one bucket"
+        );
+        if let Some((requested, cached)) = self.key_distribution.buckets.lock().as_ref()
+            && *requested == buckets
+        {
+            return Ok(cached.clone());
+        }
+
+        let end = match self.options.internal_keyspace {
+            true => Some(Bytes::from_static(CF_KEY_PREFIX)),
+            false => None,
+        };
+        let samples = self.sample_keys(
+            buckets * SAMPLES_PER_BUCKET,
+            Bound::Unbounded,
+            end.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+        )?;
+        let mut boundaries = (1..buckets)
+            .filter_map(|idx| samples.get(idx * samples.len() / buckets).cloned())
+            .collect::<Vec<_>>();
+        boundaries.dedup();
+        let mut lower = Bytes::new();
+        let mut key_buckets = Vec::with_capacity(boundaries.len() + 1);
+        for upper in boundaries.into_iter().map(Some).chain([end]) {
+            let next_lower = upper.clone().unwrap_or_default();
+            key_buckets.push(KeyBucket {
+                lower,
+                upper,
+                entries: 0,
+                bytes: 0,
+            });
+            lower = next_lower;
+        }
+
+        // Counted on the latest SSTs, which a compaction may have changed since the sampling
+        let mut cached = self.key_distribution.buckets.lock();
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        count_buckets(&mut key_buckets, &snapshot);
+        *cached = Some((buckets, key_buckets.clone()));
+        Ok(key_buckets)
+    }
+}
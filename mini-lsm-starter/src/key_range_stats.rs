@@ -0,0 +1,135 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable key hashes for placing keys on shards with consistent hashing, and read and write
+//! counters per key range, so that a routing layer above the crate can detect hot shards.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Result, ensure};
+use bytes::Bytes;
+
+/// A hash of `key` that is stable across processes, platforms and versions of the crate, unlike
+/// the hashers of the standard library.
+pub fn key_hash(key: &[u8]) -> u64 {
+    farmhash::fingerprint64(key)
+}
+
+/// The bucket in `0..num_buckets` of a key hash, with the jump consistent hash of Lamping and
+/// Veach: growing from `n` to `n + 1` buckets only moves `1 / (n + 1)` of the keys, all to the
+/// new bucket.
+pub fn jump_consistent_hash(mut hash: u64, num_buckets: u32) -> u32 {
+    assert!(num_buckets > 0, "no buckets to place the key in");
+    let mut bucket = -1i64;
+    let mut next = 0i64;
+    while next < num_buckets as i64 {
+        bucket = next;
+        hash = hash.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((hash >> 33) + 1) as f64)) as i64;
+    }
+    bucket as u32
+}
+
+/// The reads and writes of the keys in `[lower, upper)`, where `upper` is `None` for the last
+/// range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRangeStats {
+    pub lower: Bytes,
+    pub upper: Option<Bytes>,
+    /// The gets of keys in the range, and the scans starting in it.
+    pub reads: u64,
+    /// The puts and deletes of keys in the range.
+    pub writes: u64,
+    /// The size of the keys and values written.
+    pub bytes_written: u64,
+}
+
+#[derive(Default)]
+struct RangeCounters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// Counts the reads and writes of the ranges split at `key_range_stats_boundaries`. Recording
+/// is a binary search over the boundaries and a relaxed atomic add, and nothing is recorded if
+/// no boundaries are configured.
+pub(crate) struct KeyRangeCounters {
+    boundaries: Vec<Bytes>,
+    counters: Vec<RangeCounters>,
+}
+
+impl KeyRangeCounters {
+    pub(crate) fn new(boundaries: Vec<Bytes>) -> Self {
+        let num_ranges = if boundaries.is_empty() {
+            0
+        } else {
+            boundaries.len() + 1
+        };
+        Self {
+            boundaries,
+            counters: (0..num_ranges).map(|_| RangeCounters::default()).collect(),
+        }
+    }
+
+    pub(crate) fn validate(boundaries: &[Bytes]) -> Result<()> {
+        ensure!(
+            boundaries.windows(2).all(|pair| pair[0] < pair[1]),
+            "key_range_stats_boundaries must be sorted and distinct"
+        );
+        Ok(())
+    }
+
+    fn counters_of(&self, key: &[u8]) -> Option<&RangeCounters> {
+        if self.counters.is_empty() {
+            return None;
+        }
+        let idx = self
+            .boundaries
+            .partition_point(|boundary| boundary.as_ref() <= key);
+        Some(&self.counters[idx])
+    }
+
+    pub(crate) fn record_read(&self, key: &[u8]) {
+        if let Some(counters) = self.counters_of(key) {
+            counters.reads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_write(&self, key: &[u8], num_bytes: usize) {
+        if let Some(counters) = self.counters_of(key) {
+            counters.writes.fetch_add(1, Ordering::Relaxed);
+            counters
+                .bytes_written
+                .fetch_add(num_bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Vec<KeyRangeStats> {
+        self.counters
+            .iter()
+            .enumerate()
+            .map(|(idx, counters)| KeyRangeStats {
+                lower: match idx {
+                    0 => Bytes::new(),
+                    _ => self.boundaries[idx - 1].clone(),
+                },
+                upper: self.boundaries.get(idx).cloned(),
+                reads: counters.reads.load(Ordering::Relaxed),
+                writes: counters.writes.load(Ordering::Relaxed),
+                bytes_written: counters.bytes_written.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
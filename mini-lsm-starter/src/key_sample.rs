@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sampling the keys of a range without scanning it, e.g., to build a histogram of the key space
+//! or to choose the split points of a shard. The blocks of the SSTs overlapping the range are
+//! sampled from the block indexes, and then an entry from each sampled block, so only the
+//! sampled blocks are read.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use rand::Rng;
+
+use crate::block::BlockIterator;
+use crate::lsm_storage::LsmStorageInner;
+use crate::table::SsTable;
+
+fn in_range(key: &[u8], lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+    let above_lower = match lower {
+        Bound::Included(lower) => key >= lower,
+        Bound::Excluded(lower) => key > lower,
+        Bound::Unbounded => true,
+    };
+    let below_upper = match upper {
+        Bound::Included(upper) => key <= upper,
+        Bound::Excluded(upper) => key < upper,
+        Bound::Unbounded => true,
+    };
+    above_lower && below_upper
+}
+
+impl LsmStorageInner {
+    /// Sample about `n` keys of the SSTs in a range, sorted and without duplicates. See
+    /// `sample_keys_with_rng`.
+    pub fn sample_keys(
+        &self,
+        n: usize,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<Vec<Bytes>> {
+        self.sample_keys_with_rng(n, lower, upper, &mut rand::thread_rng())
+    }
+
+    /// Sample `n` of the blocks overlapping the range with reservoir sampling, and one key in the
+    /// range from each of them. The keys are about uniform as long as the blocks hold about the
+    /// same number of entries, and there are fewer than `n` of them if the range spans fewer
+    /// blocks. Only the keys of the SSTs are sampled, not those of the memtables, and a key may
+    /// have been deleted by a newer SST.
+    pub(crate) fn sample_keys_with_rng(
+        &self,
+        n: usize,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<Bytes>> {
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let sst_ids = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flat_map(|(_, level)| level.iter()));
+        let mut blocks: Vec<(Arc<SsTable>, usize)> = Vec::with_capacity(n);
+        let mut num_blocks = 0;
+        for sst_id in sst_ids {
+            let sst = &snapshot.sstables[sst_id];
+            if !Self::range_overlap(
+                lower,
+                upper,
+                sst.first_key().key_ref(),
+                sst.last_key().key_ref(),
+            ) {
+                continue;
+            }
+            for (block_idx, meta) in sst.metadata()?.block_meta().iter().enumerate() {
+                if !Self::range_overlap(
+                    lower,
+                    upper,
+                    meta.first_key.key_ref(),
+                    meta.last_key.key_ref(),
+                ) {
+                    continue;
+                }
+                num_blocks += 1;
+                if blocks.len() < n {
+                    blocks.push((sst.clone(), block_idx));
+                } else {
+                    let replaced = rng.gen_range(0..num_blocks);
+                    if replaced < n {
+                        blocks[replaced] = (sst.clone(), block_idx);
+                    }
+                }
+            }
+        }
+
+        let mut keys = Vec::with_capacity(blocks.len());
+        for (sst, block_idx) in blocks {
+            let mut iter =
+                BlockIterator::create_and_seek_to_first(sst.read_block_cached(block_idx)?);
+            let mut sampled = None;
+            let mut num_entries = 0;
+            while iter.is_valid() {
+                let key = iter.key().key_ref();
+                if !iter.is_deleted() && in_range(key, lower, upper) {
+                    num_entries += 1;
+                    if rng.gen_range(0..num_entries) == 0 {
+                        sampled = Some(Bytes::copy_from_slice(key));
+                    }
+                }
+                iter.next();
+            }
+            keys.extend(sampled);
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
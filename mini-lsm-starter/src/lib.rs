@@ -13,17 +13,54 @@
 // limitations under the License.
 
 pub mod block;
+pub mod block_cache;
+#[cfg(any(feature = "csv", feature = "parquet"))]
+pub mod bulk_import;
+pub mod cancel;
+pub mod column_family;
 pub mod compact;
+pub mod compaction_filter;
+pub mod cpu_usage;
 pub mod debug;
+#[cfg(any(feature = "csv", feature = "parquet"))]
+pub mod export;
+pub mod format_migration;
+pub mod idempotence;
+pub mod io_priority;
 pub mod iterators;
 pub mod key;
+pub mod key_distribution;
+pub mod key_range_stats;
+pub mod key_sample;
 pub mod lsm_iterator;
 pub mod lsm_storage;
 pub mod manifest;
 pub mod mem_table;
 pub mod mvcc;
+pub mod negative_cache;
+pub mod options_file;
+pub mod prefetch;
+pub mod prefix_extractor;
+pub mod quota;
+pub mod range_export;
+pub mod range_tombstone;
+pub mod rate_limiter;
+pub mod scan_chunks;
+pub mod scan_memory;
+pub mod session;
+pub mod snapshot;
+pub mod sorted_runs;
+pub mod sst_file_manager;
 pub mod table;
+pub mod ttl;
 pub mod wal;
+pub mod write_batch;
+pub mod write_observer;
+
+// Reading SST files without a storage engine, see `SsTable::open_path`
+pub use block::BlockIterator;
+pub use iterators::StorageIterator;
+pub use table::{SsTable, SsTableIterator};
 
 #[cfg(test)]
 mod tests;
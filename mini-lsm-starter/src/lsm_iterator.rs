@@ -12,83 +12,384 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "tokio")]
+mod stream;
+
 use core::panic;
+use std::fmt;
 use std::ops::Bound;
+use std::sync::{Arc, OnceLock};
 
 use anyhow::{Ok, Result};
 use bytes::Bytes;
 
 use crate::{
+    cancel::CancellationToken,
+    compact::DeletionCollector,
+    io_priority,
     iterators::{
-        StorageIterator, merge_iterator::MergeIterator, two_merge_iterator::TwoMergeIterator,
+        StorageIterator, concat_iterator::SstConcatIterator, merge_iterator::MergeIterator,
+        two_merge_iterator::TwoMergeIterator,
     },
+    key::{KeySlice, TS_RANGE_BEGIN},
     mem_table::MemTableIterator,
-    table::SsTableIterator,
+    prefetch::ScanPrefetch,
+    range_tombstone::RangeDeleteIterator,
+    rate_limiter::RateLimiter,
+    scan_memory::ScanMemory,
+    ttl,
 };
 
+#[cfg(feature = "tokio")]
+pub use stream::{DEFAULT_STREAM_BATCH_SIZE, LsmStream};
+
 /// Represents the internal type for an LSM iterator. This type will be changed across the course for multiple times.
-type LsmIteratorInner =
-    TwoMergeIterator<MergeIterator<MemTableIterator>, MergeIterator<SsTableIterator>>;
+///
+/// Each L0 SST is read by its own concat iterator, while each sorted level is read by a single
+/// concat iterator that opens its SSTs one after another, so the heap only merges one iterator
+/// per L0 SST and level.
+type LsmIteratorInner = TwoMergeIterator<
+    MergeIterator<RangeDeleteIterator<MemTableIterator>>,
+    MergeIterator<RangeDeleteIterator<SstConcatIterator>>,
+>;
+
+/// The work done by an `LsmIterator` so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LsmIteratorStats {
+    /// The number of deleted keys skipped.
+    pub tombstones_skipped: usize,
+    /// The number of keys returned, including the current one.
+    pub entries_returned: usize,
+}
 
 pub struct LsmIterator {
     inner: LsmIteratorInner,
+    /// The bound the scan starts from, which `seek` does not move before. Unused in reverse.
+    start_bound: Bound<Bytes>,
+    /// The bound the scan stops at, the lower one when in reverse.
     end_bound: Bound<Bytes>,
     is_valid: bool,
+    /// Whether the keys are visited in descending order, see `new_rev`.
+    reverse: bool,
+    /// In reverse, the key, value and metadata of the current entry. The versions of a key come
+    /// oldest first, so the visible one is only known once the inner iterator moved past them.
+    current: Option<(Bytes, Bytes, u8)>,
+    /// The timestamp of the snapshot to read, the newer versions of the keys are skipped.
+    read_ts: u64,
+    /// The key of the last version the iterator stopped at, whose older versions are skipped.
+    prev_key: Vec<u8>,
+    stats: LsmIteratorStats,
+    /// The first key the iterator visited, used to report the scanned range on drop.
+    first_key: Option<Bytes>,
+    deletion_collector: Option<Arc<DeletionCollector>>,
+    /// If set, the values end with their expiry time, and the entries expired at this time are
+    /// skipped like deletes.
+    expiry_now: Option<u64>,
+    /// The block prefetches of the scan, cancelled once the iterator reaches the end bound.
+    prefetch: Option<Arc<ScanPrefetch>>,
+    /// The blocks held by the SST iterators, checked against the scan memory budget.
+    memory: Option<Arc<ScanMemory>>,
+    /// Checked before each step of the inner iterator, see `with_cancellation`.
+    cancellation: Option<CancellationToken>,
+    /// Throttles the disk reads of a low-priority scan, see `io_priority`.
+    read_throttle: Option<Arc<RateLimiter>>,
 }
 
 impl LsmIterator {
-    pub(crate) fn new(iter: LsmIteratorInner, end_bound: Bound<Bytes>) -> Result<Self> {
+    pub(crate) fn new(
+        iter: LsmIteratorInner,
+        start_bound: Bound<Bytes>,
+        end_bound: Bound<Bytes>,
+        read_ts: u64,
+        expiry_now: Option<u64>,
+    ) -> Result<Self> {
+        let first_key = iter
+            .is_valid()
+            .then(|| Bytes::copy_from_slice(iter.key().key_ref()));
         let mut iter = Self {
+            start_bound,
             end_bound,
             is_valid: iter.is_valid(),
+            reverse: false,
+            current: None,
+            read_ts,
+            prev_key: Vec::new(),
             inner: iter,
+            stats: LsmIteratorStats::default(),
+            first_key,
+            deletion_collector: None,
+            expiry_now,
+            prefetch: None,
+            memory: None,
+            cancellation: None,
+            read_throttle: None,
         };
+        iter.is_valid = iter.is_valid && iter.in_bound();
         iter.skip_deleted()?;
         Ok(iter)
     }
 
+    /// Iterate an inner iterator yielding its keys in descending order, stopping at
+    /// `lower_bound`.
+    pub(crate) fn new_rev(
+        iter: LsmIteratorInner,
+        lower_bound: Bound<Bytes>,
+        read_ts: u64,
+        expiry_now: Option<u64>,
+    ) -> Result<Self> {
+        let mut iter = Self {
+            start_bound: Bound::Unbounded,
+            end_bound: lower_bound,
+            is_valid: iter.is_valid(),
+            reverse: true,
+            current: None,
+            read_ts,
+            prev_key: Vec::new(),
+            inner: iter,
+            stats: LsmIteratorStats::default(),
+            first_key: None,
+            deletion_collector: None,
+            expiry_now,
+            prefetch: None,
+            memory: None,
+            cancellation: None,
+            read_throttle: None,
+        };
+        iter.is_valid = iter.is_valid && iter.in_bound();
+        iter.skip_deleted_rev()?;
+        Ok(iter)
+    }
+
+    /// Report the tombstones skipped by this iterator to `collector` when it is dropped.
+    pub(crate) fn with_deletion_collector(mut self, collector: Arc<DeletionCollector>) -> Self {
+        self.deletion_collector = Some(collector);
+        self
+    }
+
+    /// Cancel the block prefetches of the scan once the iterator is exhausted.
+    pub(crate) fn with_prefetch(mut self, prefetch: Arc<ScanPrefetch>) -> Self {
+        if !self.is_valid {
+            prefetch.cancel();
+        }
+        self.prefetch = Some(prefetch);
+        self
+    }
+
+    /// Check the blocks held by the SST iterators against the scan memory budget as the iterator
+    /// moves.
+    pub(crate) fn with_memory(mut self, memory: Arc<ScanMemory>) -> Result<Self> {
+        memory.check()?;
+        self.memory = Some(memory);
+        Ok(self)
+    }
+
+    /// Fail with a `Cancelled` error instead of reading further once `token` is cancelled,
+    /// including in the middle of skipping deleted keys.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Throttle the disk reads of the iterator as it moves with `limiter`.
+    pub(crate) fn with_read_throttle(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.read_throttle = Some(limiter);
+        self
+    }
+
+    pub fn stats(&self) -> LsmIteratorStats {
+        self.stats
+    }
+
+    /// Turn the iterator into an async stream of its entries, see `LsmStream`.
+    #[cfg(feature = "tokio")]
+    pub fn into_stream(self) -> LsmStream<Self> {
+        LsmStream::new(self)
+    }
+
+    /// The memory accounting of the blocks held by the scan, if `scan_memory_budget` is set.
+    pub fn memory(&self) -> Option<&ScanMemory> {
+        self.memory.as_deref()
+    }
+
+    fn cancel_prefetch(&self) {
+        if let Some(prefetch) = &self.prefetch {
+            prefetch.cancel();
+        }
+    }
+
+    /// Move to the latest version at or before `read_ts` of the next key that is not deleted,
+    /// skipping the newer and older versions of the keys.
     fn skip_deleted(&mut self) -> Result<()> {
-        while self.is_valid() && self.inner.value().is_empty() {
+        while self.is_valid() {
+            let key = self.inner.key();
+            if key.ts() > self.read_ts || key.key_ref() == self.prev_key {
+                self.next_inner()?;
+                continue;
+            }
+            self.prev_key.clear();
+            self.prev_key.extend_from_slice(key.key_ref());
+            if !self.is_inner_deleted() {
+                self.stats.entries_returned += 1;
+                break;
+            }
+            self.stats.tombstones_skipped += 1;
             self.next_inner()?;
         }
         Ok(())
     }
 
+    /// Move to the latest version at or before `read_ts` of the previous key that is not
+    /// deleted, reading all the versions of each key.
+    fn skip_deleted_rev(&mut self) -> Result<()> {
+        self.current = None;
+        while self.is_valid {
+            let key = Bytes::copy_from_slice(self.inner.key().key_ref());
+            let mut visible = None;
+            while self.is_valid && self.inner.key().key_ref() == key {
+                if self.inner.key().ts() <= self.read_ts {
+                    visible = Some((
+                        Bytes::copy_from_slice(self.inner.value()),
+                        self.inner.value_meta(),
+                        self.is_inner_deleted(),
+                    ));
+                }
+                self.next_inner()?;
+            }
+            match visible {
+                None => {}
+                Some((_, _, true)) => self.stats.tombstones_skipped += 1,
+                Some((value, meta, false)) => {
+                    self.stats.entries_returned += 1;
+                    self.current = Some((key, value, meta));
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_inner_deleted(&self) -> bool {
+        self.inner.is_deleted()
+            || self
+                .expiry_now
+                .is_some_and(|now| ttl::is_expired(self.inner.value(), now))
+    }
+
     fn next_inner(&mut self) -> Result<()> {
+        if let Some(token) = &self.cancellation {
+            token.check()?;
+        }
         self.inner.next()?;
 
         if !self.inner.is_valid() {
             self.is_valid = false;
+            self.cancel_prefetch();
             return Ok(());
         }
 
-        match self.end_bound.as_ref() {
-            Bound::Unbounded => {}
-            Bound::Included(key) => self.is_valid = self.inner.key().raw_ref() <= key,
-            Bound::Excluded(key) => self.is_valid = self.inner.key().raw_ref() < key,
+        self.is_valid = self.in_bound();
+        if !self.is_valid {
+            self.cancel_prefetch();
         }
         Ok(())
     }
+
+    /// Whether the key of the inner iterator is within the end bound.
+    fn in_bound(&self) -> bool {
+        let key = self.inner.key().key_ref();
+        match (self.end_bound.as_ref(), self.reverse) {
+            (Bound::Unbounded, _) => true,
+            (Bound::Included(bound), false) => key <= bound,
+            (Bound::Excluded(bound), false) => key < bound,
+            (Bound::Included(bound), true) => key >= bound,
+            (Bound::Excluded(bound), true) => key > bound,
+        }
+    }
+
+    /// The stored value of the current entry, still ending with its expiry time if any.
+    fn raw_value(&self) -> &[u8] {
+        match &self.current {
+            Some((_, value, _)) => value,
+            None => self.inner.value(),
+        }
+    }
 }
 
 impl StorageIterator for LsmIterator {
     type KeyType<'a> = &'a [u8];
 
     fn is_valid(&self) -> bool {
-        self.is_valid
+        match self.reverse {
+            true => self.current.is_some(),
+            false => self.is_valid,
+        }
     }
 
     fn key(&self) -> &[u8] {
-        self.inner.key().raw_ref()
+        match &self.current {
+            Some((key, _, _)) => key,
+            None => self.inner.key().key_ref(),
+        }
     }
 
     fn value(&self) -> &[u8] {
-        self.inner.value()
+        match self.expiry_now {
+            Some(_) => ttl::split_expiry(self.raw_value()).0,
+            None => self.raw_value(),
+        }
     }
 
-    fn next(&mut self) -> Result<()> {
-        self.next_inner()?;
+    fn value_meta(&self) -> u8 {
+        match &self.current {
+            Some((_, _, meta)) => *meta,
+            None => self.inner.value_meta(),
+        }
+    }
+
+    fn is_deleted(&self) -> bool {
+        false
+    }
+
+    /// Move to the first key at or after `key` that is not deleted, within the range of the
+    /// scan. Moving back before the current key is allowed, but not before the start of the
+    /// range.
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        anyhow::ensure!(!self.reverse, "seek is not supported by reverse scans");
+        let _throttle = self.read_throttle.clone().map(io_priority::throttle_reads);
+        if let Some(token) = &self.cancellation {
+            token.check()?;
+        }
+        let start_bound = self.start_bound.clone();
+        let (key, skip_key) = match start_bound.as_ref() {
+            Bound::Included(start) if key < start => (&start[..], false),
+            Bound::Excluded(start) if key <= start => (&start[..], true),
+            _ => (key, false),
+        };
+        self.inner.seek(KeySlice::from_slice(key, TS_RANGE_BEGIN))?;
+        // An excluded start key is skipped like the older versions of the previous key
+        self.prev_key.clear();
+        if skip_key {
+            self.prev_key.extend_from_slice(key);
+        }
+        self.is_valid = self.inner.is_valid() && self.in_bound();
         self.skip_deleted()?;
+        if let Some(memory) = &self.memory {
+            memory.check()?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        let _throttle = self.read_throttle.clone().map(io_priority::throttle_reads);
+        if self.reverse {
+            self.skip_deleted_rev()?;
+        } else {
+            self.next_inner()?;
+            self.skip_deleted()?;
+        }
+        if let Some(memory) = &self.memory {
+            memory.check()?;
+        }
         Ok(())
     }
 
@@ -97,12 +398,55 @@ impl StorageIterator for LsmIterator {
     }
 }
 
+impl Drop for LsmIterator {
+    fn drop(&mut self) {
+        self.cancel_prefetch();
+        let (Some(collector), Some(first_key)) = (&self.deletion_collector, self.first_key.take())
+        else {
+            return;
+        };
+        let last_key = if self.inner.is_valid() {
+            Some(Bytes::copy_from_slice(self.inner.key().key_ref()))
+        } else {
+            match &self.end_bound {
+                Bound::Included(key) | Bound::Excluded(key) => Some(key.clone()),
+                Bound::Unbounded => None,
+            }
+        };
+        collector.record(first_key, last_key, self.stats);
+    }
+}
+
+/// The error returned when reading the current entry of an invalid iterator, by the `try_`
+/// methods of `FusedIterator`, or by its `next` after such a read with `with_misuse_errors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IteratorMisuseError {
+    /// The method called on the invalid iterator, e.g., `key`.
+    pub operation: &'static str,
+}
+
+impl fmt::Display for IteratorMisuseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot call {} on an invalid iterator", self.operation)
+    }
+}
+
+impl std::error::Error for IteratorMisuseError {}
+
+/// Returns true if the error is caused by reading the current entry of an invalid iterator.
+pub fn is_iterator_misuse_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<IteratorMisuseError>().is_some()
+}
+
 /// A wrapper around existing iterator, will prevent users from calling `next` when the iterator is
 /// invalid. If an iterator is already invalid, `next` does not do anything. If `next` returns an error,
 /// `is_valid` should return false, and `next` should always return an error.
 pub struct FusedIterator<I: StorageIterator> {
     iter: I,
     has_errored: bool,
+    misuse_errors: bool,
+    /// The first read of an invalid iterator with `misuse_errors`, which `next` fails with.
+    misuse: OnceLock<&'static str>,
 }
 
 impl<I: StorageIterator> FusedIterator<I> {
@@ -110,7 +454,86 @@ impl<I: StorageIterator> FusedIterator<I> {
         Self {
             iter,
             has_errored: false,
+            misuse_errors: false,
+            misuse: OnceLock::new(),
+        }
+    }
+
+    /// If `enabled`, reading the current entry of an invalid iterator returns an empty key or
+    /// value instead of panicking, and the following `next` fails with an `IteratorMisuseError`.
+    pub fn with_misuse_errors(mut self, enabled: bool) -> Self {
+        self.misuse_errors = enabled;
+        self
+    }
+
+    /// Whether the iterator is valid to read `operation`. Panics if it is not, unless
+    /// `misuse_errors` is set, where the misuse is recorded for `next` instead.
+    fn check_valid(&self, operation: &'static str) -> bool {
+        if self.is_valid() {
+            return true;
         }
+        if !self.misuse_errors {
+            panic!("Cannot call {} on an invalid iterator", operation);
+        }
+        let _ = self.misuse.set(operation);
+        false
+    }
+
+    fn misuse_error(&self, operation: &'static str) -> Result<()> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        Err(IteratorMisuseError { operation }.into())
+    }
+
+    /// The current key, or an `IteratorMisuseError` if the iterator is invalid.
+    pub fn try_key(&self) -> Result<I::KeyType<'_>> {
+        self.misuse_error("key")?;
+        Ok(self.iter.key())
+    }
+
+    /// The current value, or an `IteratorMisuseError` if the iterator is invalid.
+    pub fn try_value(&self) -> Result<&[u8]> {
+        self.misuse_error("value")?;
+        Ok(self.iter.value())
+    }
+
+    /// The user metadata byte of the current value, or an `IteratorMisuseError` if the iterator
+    /// is invalid.
+    pub fn try_value_meta(&self) -> Result<u8> {
+        self.misuse_error("value_meta")?;
+        Ok(self.iter.value_meta())
+    }
+
+    /// Whether the current entry is a delete, or an `IteratorMisuseError` if the iterator is
+    /// invalid.
+    pub fn try_is_deleted(&self) -> Result<bool> {
+        self.misuse_error("is_deleted")?;
+        Ok(self.iter.is_deleted())
+    }
+}
+
+impl FusedIterator<LsmIterator> {
+    pub fn stats(&self) -> LsmIteratorStats {
+        self.iter.stats()
+    }
+
+    pub fn memory(&self) -> Option<&ScanMemory> {
+        self.iter.memory()
+    }
+
+    /// Stop the scan with a `Cancelled` error once `token` is cancelled, see
+    /// `LsmIterator::with_cancellation`.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.iter = self.iter.with_cancellation(token);
+        self
+    }
+
+    /// Turn the iterator returned by a scan into an async stream of its entries, see
+    /// `LsmStream`.
+    #[cfg(feature = "tokio")]
+    pub fn into_stream(self) -> LsmStream<Self> {
+        LsmStream::new(self)
     }
 }
 
@@ -125,36 +548,72 @@ impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
     }
 
     fn key(&self) -> Self::KeyType<'_> {
-        if !self.is_valid() {
-            panic!("Cannot call key on an invalid iterator");
+        if !self.check_valid("key") {
+            return Default::default();
         }
         self.iter.key()
     }
 
     fn value(&self) -> &[u8] {
-        if !self.is_valid() {
-            panic!("Cannot call value on an invalid iterator");
+        if !self.check_valid("value") {
+            return &[];
         }
         self.iter.value()
     }
 
+    fn value_meta(&self) -> u8 {
+        if !self.check_valid("value_meta") {
+            return 0;
+        }
+        self.iter.value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        if !self.check_valid("is_deleted") {
+            return false;
+        }
+        self.iter.is_deleted()
+    }
+
     fn next(&mut self) -> Result<()> {
+        if let Some(&operation) = self.misuse.get() {
+            self.has_errored = true;
+            return Err(IteratorMisuseError { operation }.into());
+        }
         if self.has_errored {
             return Err(anyhow::anyhow!(
                 "Cannot call next on an iterator that has errored"
             ));
         }
 
-        if self.iter.is_valid() {
-            if let Err(err) = self.iter.next() {
-                self.has_errored = true;
-                return Err(err);
-            }
+        if self.iter.is_valid()
+            && let Err(err) = self.iter.next()
+        {
+            self.has_errored = true;
+            return Err(err);
         }
 
         Ok(())
     }
 
+    /// Seeks the inner iterator, which may be invalid, unless it has errored.
+    fn seek(&mut self, key: Self::KeyType<'_>) -> Result<()> {
+        if let Some(&operation) = self.misuse.get() {
+            self.has_errored = true;
+            return Err(IteratorMisuseError { operation }.into());
+        }
+        if self.has_errored {
+            return Err(anyhow::anyhow!(
+                "Cannot call seek on an iterator that has errored"
+            ));
+        }
+        if let Err(err) = self.iter.seek(key) {
+            self.has_errored = true;
+            return Err(err);
+        }
+        Ok(())
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.iter.num_active_iterators()
     }
@@ -0,0 +1,122 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An async `Stream` over the entries of a scan, for async callers that must not block their
+//! runtime on the block reads of the iterator.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio::task::JoinHandle;
+
+use crate::iterators::StorageIterator;
+
+/// The number of entries read by each task on the blocking pool, unless configured with
+/// `LsmStream::with_batch_size`.
+pub const DEFAULT_STREAM_BATCH_SIZE: usize = 128;
+
+type Batch<I> = (I, Result<Vec<(Bytes, Bytes)>>);
+
+/// Yields the entries of an iterator, which is moved to the blocking pool of tokio to read the
+/// next batch of entries whenever the entries read before are consumed. It must be polled from
+/// within a tokio runtime. The stream ends after the first error.
+pub struct LsmStream<I> {
+    /// `None` while a batch is being read, or once the stream ended.
+    iter: Option<I>,
+    buffered: VecDeque<(Bytes, Bytes)>,
+    pending: Option<JoinHandle<Batch<I>>>,
+    batch_size: usize,
+}
+
+impl<I> LsmStream<I>
+where
+    I: for<'a> StorageIterator<KeyType<'a> = &'a [u8]> + Send + 'static,
+{
+    pub fn new(iter: I) -> Self {
+        Self {
+            iter: Some(iter),
+            buffered: VecDeque::new(),
+            pending: None,
+            batch_size: DEFAULT_STREAM_BATCH_SIZE,
+        }
+    }
+
+    /// Read `batch_size` entries on the blocking pool at a time. Larger batches amortize the
+    /// hand-offs to the pool, and buffer more entries in memory.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    fn read_batch(iter: &mut I, batch_size: usize) -> Result<Vec<(Bytes, Bytes)>> {
+        let mut batch = Vec::with_capacity(batch_size);
+        while iter.is_valid() && batch.len() < batch_size {
+            batch.push((
+                Bytes::copy_from_slice(iter.key()),
+                Bytes::copy_from_slice(iter.value()),
+            ));
+            iter.next()?;
+        }
+        Ok(batch)
+    }
+}
+
+impl<I> Stream for LsmStream<I>
+where
+    I: for<'a> StorageIterator<KeyType<'a> = &'a [u8]> + Send + Unpin + 'static,
+{
+    type Item = Result<(Bytes, Bytes)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(entry) = this.buffered.pop_front() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+            if let Some(pending) = &mut this.pending {
+                let result = ready!(Pin::new(pending).poll(cx));
+                this.pending = None;
+                let (iter, batch) = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        return Poll::Ready(Some(Err(anyhow!("scan task failed: {}", err))));
+                    }
+                };
+                match batch {
+                    Ok(batch) => this.buffered.extend(batch),
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                }
+                this.iter = Some(iter);
+                continue;
+            }
+            let Some(mut iter) = this.iter.take() else {
+                return Poll::Ready(None);
+            };
+            if !iter.is_valid() {
+                return Poll::Ready(None);
+            }
+            let batch_size = this.batch_size;
+            this.pending = Some(tokio::task::spawn_blocking(move || {
+                let batch = Self::read_batch(&mut iter, batch_size);
+                (iter, batch)
+            }));
+        }
+    }
+}
@@ -12,36 +12,66 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
-
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem::replace;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
 
-use anyhow::{Ok, Result};
+use anyhow::{Context, Ok, Result, anyhow, bail, ensure};
 use bytes::Bytes;
 use parking_lot::{Mutex, MutexGuard, RwLock};
 
-use crate::block::Block;
+use crate::block::{Block, CompressionType, DEFAULT_RESTART_INTERVAL, EMPTY_VALUE_FLAG};
+use crate::cancel::{CancellationToken, is_cancelled_error};
+use crate::column_family::{self, ColumnFamilies, ColumnFamily, ColumnFamilyIterator};
 use crate::compact::{
-    CompactionController, CompactionOptions, LeveledCompactionController, LeveledCompactionOptions,
-    SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, TieredCompactionController,
+    CompactionController, CompactionOptions, CompactionPlan, CompactionScheduler, CompactionTask,
+    CompactionWindow, DeletionCollector, DeletionCompactionOptions, EntryCountCompactionOptions,
+    LeveledCompactionOptions, POLL_INTERVAL, SimpleLeveledCompactionOptions, TaskHandle,
+    TaskNotifier, UtilizationProbe,
 };
+use crate::compaction_filter::CompactionFilter;
+use crate::cpu_usage::{BackgroundCpuStats, BackgroundCpuUsage};
+use crate::idempotence::{self, DEFAULT_IDEMPOTENT_WRITE_WINDOW, OperationId, OperationWindow};
+use crate::io_priority::{self, IoPriority};
 use crate::iterators::{
-    StorageIterator, merge_iterator::MergeIterator, two_merge_iterator::TwoMergeIterator,
+    concat_iterator::SstConcatIterator, merge_iterator::MergeIterator,
+    two_merge_iterator::TwoMergeIterator,
 };
-use crate::key::Key;
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT};
+use crate::key_distribution::{KeyBucket, KeyDistributionTracker};
+use crate::key_range_stats::{KeyRangeCounters, KeyRangeStats};
 use crate::lsm_iterator::{FusedIterator, LsmIterator};
-use crate::manifest::Manifest;
+use crate::manifest::{
+    DEFAULT_MAX_MANIFEST_SIZE, Manifest, ManifestEntry, ManifestRecord, ManifestSnapshot, live_ssts,
+};
 use crate::mem_table::{MemTable, map_bound};
 use crate::mvcc::LsmMvccInner;
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
-
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+use crate::mvcc::txn::Transaction;
+use crate::negative_cache::{NegativeCache, NegativeCacheStats};
+use crate::options_file::StoredOptions;
+use crate::prefetch::{PrefetchStats, Prefetcher, ScanPrefetch};
+use crate::prefix_extractor::PrefixExtractor;
+use crate::quota::{QuotaTracker, TenantQuota, TenantUsage};
+use crate::range_export::RangeExport;
+use crate::range_tombstone::{RangeDeleteIterator, RangeTombstones};
+use crate::rate_limiter::RateLimiter;
+use crate::scan_chunks::ScanChunks;
+use crate::scan_memory::{ScanMemory, ScanMemoryBudget};
+use crate::session::{SequenceTracker, SessionToken};
+use crate::snapshot::{DiffIterator, Snapshot};
+use crate::sorted_runs::{SortedRunLimiter, SortedRunStats};
+use crate::sst_file_manager::SstFileManager;
+use crate::table::{FileObject, SharedMetadata, SsTable, SsTableBuilder};
+use crate::ttl::{self, TtlOptions};
+use crate::write_batch::{Precondition, WriteBatch, WriteBatchBuilder};
+use crate::write_observer::{WriteObserver, WriteObservers};
+
+pub use crate::block_cache::BlockCache;
+use crate::block_cache::{BlockCachePolicy, LevelCacheStats};
 
 /// Represents the state of the storage engine.
 #[derive(Clone)]
@@ -66,21 +96,164 @@ pub enum WriteBatchRecord<T: AsRef<[u8]>> {
 
 impl LsmStorageState {
     fn create(options: &LsmStorageOptions) -> Self {
-        let levels = match &options.compaction_options {
+        Self {
+            memtable: Arc::new(MemTable::create(0)),
+            imm_memtables: Vec::new(),
+            l0_sstables: Vec::new(),
+            levels: Self::empty_levels(&options.compaction_options),
+            sstables: Default::default(),
+        }
+    }
+
+    /// The levels below L0 of an empty tree with the given compaction options.
+    pub(crate) fn empty_levels(compaction_options: &CompactionOptions) -> Vec<(usize, Vec<usize>)> {
+        match compaction_options {
             CompactionOptions::Leveled(LeveledCompactionOptions { max_levels, .. })
             | CompactionOptions::Simple(SimpleLeveledCompactionOptions { max_levels, .. }) => (1
                 ..=*max_levels)
                 .map(|level| (level, Vec::new()))
                 .collect::<Vec<_>>(),
-            CompactionOptions::Tiered(_) => Vec::new(),
+            CompactionOptions::Tiered(_)
+            | CompactionOptions::LazyLeveled(_)
+            | CompactionOptions::Fifo(_) => Vec::new(),
             CompactionOptions::NoCompaction => vec![(1, Vec::new())],
-        };
+        }
+    }
+
+    /// The smallest first key and the largest last key of some SSTs, `None` if there are none.
+    pub(crate) fn key_range(&self, sst_ids: &[usize]) -> Option<(&KeyBytes, &KeyBytes)> {
+        let first_key = sst_ids
+            .iter()
+            .map(|id| self.sstables[id].first_key())
+            .min()?;
+        let last_key = sst_ids
+            .iter()
+            .map(|id| self.sstables[id].last_key())
+            .max()?;
+        Some((first_key, last_key))
+    }
+
+    /// Tell the SSTs below L0 which level they are in, numbering the levels (or tiers) from 1.
+    pub(crate) fn update_sst_levels(&self) {
+        for (idx, (_, level_sst_ids)) in self.levels.iter().enumerate() {
+            for sst_id in level_sst_ids {
+                self.sstables[sst_id].set_level(idx + 1);
+            }
+        }
+    }
+
+    /// Put the ids of all SSTs below L0 into one sorted run laid out for `compaction_options`:
+    /// in the bottom level, or as the only tier with tiered compaction.
+    fn relayout(&mut self, compaction_options: &CompactionOptions) {
+        let sst_ids = self
+            .levels
+            .iter()
+            .flat_map(|(_, level_sst_ids)| level_sst_ids.iter().copied())
+            .collect::<Vec<_>>();
+        self.levels = Self::empty_levels(compaction_options);
+        if compaction_options.has_sorted_levels() {
+            self.levels.last_mut().unwrap().1 = sst_ids;
+        } else if let Some(&tier_id) = sst_ids.first() {
+            self.levels.push((tier_id, sst_ids));
+        }
+    }
+
+    /// Add a flushed SST on top of L0, or as a new tier if the compaction controller does not
+    /// flush to L0.
+    pub(crate) fn add_flushed_sst(&mut self, sst_id: usize, flush_to_l0: bool) {
+        match flush_to_l0 {
+            true => self.l0_sstables.insert(0, sst_id),
+            false => self.levels.insert(0, (sst_id, vec![sst_id])),
+        }
+    }
+}
+
+/// The state of a directory rebuilt from its manifest, before the SSTs are opened.
+struct RecoveredManifest {
+    /// The ids of the SSTs in L0 and in the levels, without the SST objects.
+    state: LsmStorageState,
+    /// The SSTs that are not in the first data path, see `ManifestRecord::DataPath`.
+    sst_paths: HashMap<usize, usize>,
+    /// The memtables that were not flushed, with the epoch of the process that wrote their WAL.
+    memtables: BTreeMap<usize, u64>,
+}
+
+impl RecoveredManifest {
+    /// Replay the records of the manifest on the levels of `layout`, the compaction options the
+    /// directory was last opened with.
+    fn replay(
+        entries: &[ManifestEntry],
+        options: &LsmStorageOptions,
+        layout: &CompactionOptions,
+    ) -> Self {
+        let mut state = LsmStorageState::create(options);
+        state.levels = LsmStorageState::empty_levels(layout);
+        let mut controller = CompactionController::new(layout);
+        let mut sst_paths = HashMap::new();
+        let mut memtables = BTreeMap::new();
+        for entry in entries {
+            match &entry.record {
+                ManifestRecord::Flush(sst_id) => {
+                    memtables.remove(sst_id);
+                    state.add_flushed_sst(*sst_id, controller.flush_to_l0());
+                }
+                ManifestRecord::FlushToLevel(sst_id, level) => {
+                    memtables.remove(sst_id);
+                    state.levels[level - 1].1.push(*sst_id);
+                }
+                ManifestRecord::NewMemtable(memtable_id) => {
+                    memtables.insert(*memtable_id, entry.epoch);
+                }
+                ManifestRecord::Compaction(
+                    task @ CompactionTask::ForceFullCompaction { .. },
+                    output,
+                ) => {
+                    // Written by `force_full_compaction`, or by `compact_into_layout` followed
+                    // by the new compaction strategy
+                    let input = task.input_sst_ids().into_iter().collect::<HashSet<_>>();
+                    state.l0_sstables.retain(|id| !input.contains(id));
+                    for (_, level_sst_ids) in &mut state.levels {
+                        level_sst_ids.retain(|id| !input.contains(id));
+                    }
+                    if controller.options().has_sorted_levels() {
+                        state.levels.last_mut().unwrap().1.extend_from_slice(output);
+                    } else {
+                        state.levels.retain(|(_, tier)| !tier.is_empty());
+                        if let Some(&tier_id) = output.first() {
+                            state.levels.push((tier_id, output.clone()));
+                        }
+                    }
+                }
+                ManifestRecord::Compaction(task, output) => {
+                    (state, _) = controller.apply_compaction_result(&state, task, output, true);
+                }
+                ManifestRecord::DataPath(sst_id, data_path) => {
+                    sst_paths.insert(*sst_id, *data_path);
+                }
+                ManifestRecord::CompactionStrategy(compaction_options) => {
+                    if controller
+                        .options()
+                        .layout_change(compaction_options)
+                        .is_some()
+                    {
+                        state.relayout(compaction_options);
+                    }
+                    controller = CompactionController::new(compaction_options);
+                }
+                ManifestRecord::Snapshot(snapshot) => {
+                    state.l0_sstables.clone_from(&snapshot.l0_sstables);
+                    state.levels.clone_from(&snapshot.levels);
+                    controller = CompactionController::new(&snapshot.compaction_options);
+                    sst_paths = snapshot.data_paths.iter().copied().collect();
+                    memtables = snapshot.memtables.iter().copied().collect();
+                }
+                ManifestRecord::NewEpoch(_) => {}
+            }
+        }
         Self {
-            memtable: Arc::new(MemTable::create(0)),
-            imm_memtables: Vec::new(),
-            l0_sstables: Vec::new(),
-            levels,
-            sstables: Default::default(),
+            state,
+            sst_paths,
+            memtables,
         }
     }
 }
@@ -95,7 +268,155 @@ pub struct LsmStorageOptions {
     pub num_memtable_limit: usize,
     pub compaction_options: CompactionOptions,
     pub enable_wal: bool,
+    // Make the transactions serializable by also validating the key ranges they scan, so that a
+    // write into a scanned range aborts them. Otherwise they only validate the keys they read and
+    // write, and a scan does not see the keys committed into its range in the meantime
     pub serializable: bool,
+    // Free disk space in bytes to keep in addition to the estimated output of a flush or
+    // compaction, the task is deferred otherwise
+    pub disk_space_reserve: u64,
+    // Compact the key ranges that scans found to be mostly tombstones
+    pub deletion_compaction: Option<DeletionCompactionOptions>,
+    // Compact an SST below L0 once this many point lookups read it without finding the key
+    pub seek_compaction_threshold: Option<usize>,
+    // Levels whose blocks are cached in a separate pool, e.g., `vec![1]` to keep L1 cached
+    pub high_priority_cache_levels: Vec<usize>,
+    // Choose the block size of each SST between `block_size` and this based on the entry sizes
+    pub max_block_size: Option<usize>,
+    // Key prefixes whose written and stored bytes are tracked, optionally with a limit
+    pub tenant_quotas: Vec<TenantQuota>,
+    // Bytes per second of the writes with `WriteOptions::rate_limited`, can be changed with
+    // `MiniLsm::set_write_rate_limit`
+    pub write_rate_limit: Option<u64>,
+    // Bytes per second read from the disk by the reads with `IoPriority::Low`, can be changed
+    // with `MiniLsm::set_low_priority_read_rate_limit`
+    pub low_priority_read_rate_limit: Option<u64>,
+    // Expire the entries this long after they are written
+    pub ttl: Option<TtlOptions>,
+    // Reject the writes of larger keys and values, at most `MAX_KEY_SIZE` and `MAX_VALUE_SIZE`
+    pub max_key_size: usize,
+    pub max_value_size: usize,
+    // Publish the bloom filters and block indexes of the SSTs in shared memory under this
+    // namespace, for reader processes opening them with `SsTable::open_with_shared_metadata`
+    pub shared_metadata_namespace: Option<String>,
+    // Directories for the SSTs, fastest first. Flushes go to the first one and compactions to
+    // the first one with room for their output. The SSTs are stored in the main directory if empty
+    pub data_paths: Vec<DataPath>,
+    // Move obsolete SSTs to a trash directory and delete them in the background at this many
+    // bytes per second, instead of deleting them right away
+    pub sst_delete_rate: Option<u64>,
+    // Read this many blocks ahead in each SST of a scan in the background, 0 to disable
+    pub scan_readahead: usize,
+    // Bound the bytes of the blocks held by a scan
+    pub scan_memory_budget: Option<ScanMemoryBudget>,
+    // Bound the blocks a scan pins in the block cache at once, one for each SST it merges. A scan
+    // over more SSTs reads the SSTs of each level one after another, and fails if it still pins
+    // too many, e.g., with many L0 SSTs
+    pub max_pinned_blocks_per_iterator: Option<usize>,
+    // Make reading the key or value of an exhausted or failed scan iterator return an empty one
+    // instead of panicking, and fail the next `next` of the iterator with an
+    // `IteratorMisuseError`
+    pub iterator_misuse_errors: bool,
+    // Bound the block cache to this many bytes instead of 1024 blocks, charging the block indexes
+    // and bloom filters of the open SSTs to it
+    pub block_cache_capacity: Option<u64>,
+    // Keep the block indexes and bloom filters of the SSTs in a pool of the block cache bounded
+    // to this many bytes, instead of in memory for every open SST, reading them from the SSTs
+    // again once evicted
+    pub metadata_cache_capacity: Option<u64>,
+    // How the block cache picks the blocks to keep within its capacity
+    pub block_cache_policy: BlockCachePolicy,
+    // Fail to open or build an SST whose metadata does not fit into `block_cache_capacity`
+    // instead of going over it
+    pub strict_capacity_limit: bool,
+    // Compact all SSTs into one sorted run when opening with a compaction layout different from
+    // the one in the OPTIONS file, instead of failing to open
+    pub migrate_compaction_layout: bool,
+    // Check that the keys written to each SST are strictly increasing in release builds too, failing
+    // the flush or compaction instead of writing a corrupt SST. Debug builds always check
+    pub verify_key_order: bool,
+    // Read back the output of each compaction to the bottom level and fail the compaction if it has
+    // a tombstone or more than one version of a key
+    pub verify_bottom_level_compaction: bool,
+    // The daily windows in which compactions to the bottom level run. Outside of them, such
+    // compactions only run while the probe set with `MiniLsm::set_utilization_probe` reports a low
+    // utilization. Flushes and the other compactions run anytime. Empty to not restrict them
+    pub compaction_windows: Vec<CompactionWindow>,
+    // Split the keys into ranges at these sorted keys or key prefixes and count the reads and
+    // writes of each range, see `MiniLsm::metrics`. Empty to not count them
+    pub key_range_stats_boundaries: Vec<Bytes>,
+    // Remember up to this many keys that gets found absent, and answer the next gets of them
+    // without reading the memtables and SSTs until they are written
+    pub negative_cache_capacity: Option<u64>,
+    // Check the CRC32 checksum of each block read from an SST, failing the read with a
+    // `ChecksumError` if the block was corrupted. Skipping the check saves a pass over each block
+    pub verify_block_checksums: bool,
+    // Write the SSTs of flushes and compactions in chunks of this many bytes, starting the
+    // write-back of each chunk as it is written, and sync them right before they are recorded in
+    // the manifest. This bounds the data each sync waits for, so that the syncs of large
+    // compactions do not stall the foreground writes. `None` to write and sync each SST at once
+    pub sst_sync_chunk_size: Option<usize>,
+    // Store the full key of every this many entries of a block, and the other keys as a suffix
+    // after their overlap with the previous key. Seeks binary-search over the full keys and decode
+    // up to this many entries, so a larger interval trades seek time for smaller blocks
+    pub block_restart_interval: usize,
+    // Compress each block of the SSTs written from now on with this codec. The blocks that do not
+    // get smaller are stored uncompressed, and each block records its codec, so that changing
+    // this does not affect reading the SSTs written before
+    pub block_compression: CompressionType,
+    // The bits of the bloom filter of the SSTs for each key. Gets skip the SSTs whose bloom filter
+    // rules out their key, and more bits rule out more absent keys at the cost of memory. `None`
+    // for about 1% false positives
+    pub bloom_bits_per_key: Option<usize>,
+    // Build a second bloom filter in each SST over the prefixes of the keys this extracts, which
+    // `scan_prefix` checks to skip the SSTs without the prefix
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    // Run this filter on the entries of each compaction, which may drop or rewrite them, see
+    // `CompactionFilter`. The blocks of the SSTs that overlap with no other input are rewritten
+    // entry by entry instead of copied as-is when it is set
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    // Compact the manifest into a snapshot of the state once it grows past this many bytes, or
+    // past twice its size after the last compaction if that is larger
+    pub max_manifest_size: u64,
+    // Size the levels of leveled compaction by their number of entries instead of their bytes,
+    // for workloads of small entries. Ignored by the other compaction strategies
+    pub entry_count_compaction: Option<EntryCountCompactionOptions>,
+    // Keep the SSTs to at most this many sorted runs, the L0 SSTs and the non-empty levels or
+    // tiers, for a hard bound on the SSTs each read goes through. Flushes wait for a compaction
+    // once the bound is reached, and the writes stall once `num_memtable_limit` immutable
+    // memtables wait. SSTs imported as a whole are not held back
+    pub max_sorted_runs: Option<usize>,
+    // Run up to this many compaction tasks with disjoint inputs at once, each on its own thread.
+    // Only leveled compaction generates several tasks, for different levels or key ranges
+    pub max_concurrent_compactions: usize,
+    // Flush a memtable straight into the level L0 is compacted into when L0 is empty and no SST
+    // down to that level overlaps with the keys of the memtable, which saves a compaction for
+    // time-ordered writes. Falls back to L0 while a compaction runs, or for the compaction
+    // strategies without sorted levels
+    pub flush_to_base_level: bool,
+    // Optimize for keys written in increasing order, e.g. logs keyed by time: the memtables
+    // append the writes to a vector instead of inserting them into the skipmap, and compactions
+    // move the SSTs that overlap with nothing in the next level down as they are instead of
+    // rewriting them. Writes out of order are still correct, from the first one on the current
+    // memtable falls back to the skipmap
+    pub sequential_writes: bool,
+    // Remember the IDs of this many latest operations of each client for the writes with
+    // `WriteOptions::operation`, which are applied once even if retried within the window
+    pub idempotent_write_window: usize,
+    // Reserve the keys from `column_family::CF_KEY_PREFIX` on for the column families and the
+    // windows of the idempotent writes, which the plain writes and scans cannot reach then. Only
+    // turned on for a directory without keys in that range, and not turned off once it was used,
+    // see `StoredOptions::internal_keyspace`
+    pub internal_keyspace: bool,
+}
+
+/// A directory for SSTs, see `LsmStorageOptions::data_paths`.
+#[derive(Debug, Clone)]
+pub struct DataPath {
+    pub path: PathBuf,
+    /// The size of the SSTs this directory should hold. Compactions skip to the next directory
+    /// if their output would exceed it, except for the last directory, which takes the rest.
+    pub target_size: u64,
 }
 
 impl LsmStorageOptions {
@@ -107,6 +428,49 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 50,
             serializable: false,
+            disk_space_reserve: 0,
+            deletion_compaction: None,
+            seek_compaction_threshold: None,
+            high_priority_cache_levels: Vec::new(),
+            max_block_size: None,
+            tenant_quotas: Vec::new(),
+            write_rate_limit: None,
+            low_priority_read_rate_limit: None,
+            ttl: None,
+            max_key_size: MAX_KEY_SIZE,
+            max_value_size: MAX_VALUE_SIZE,
+            shared_metadata_namespace: None,
+            data_paths: Vec::new(),
+            sst_delete_rate: None,
+            scan_readahead: 0,
+            scan_memory_budget: None,
+            max_pinned_blocks_per_iterator: None,
+            iterator_misuse_errors: false,
+            metadata_cache_capacity: None,
+            block_cache_capacity: None,
+            block_cache_policy: BlockCachePolicy::default(),
+            strict_capacity_limit: false,
+            migrate_compaction_layout: false,
+            verify_key_order: false,
+            verify_bottom_level_compaction: false,
+            compaction_windows: Vec::new(),
+            key_range_stats_boundaries: Vec::new(),
+            negative_cache_capacity: None,
+            verify_block_checksums: true,
+            sst_sync_chunk_size: None,
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            block_compression: CompressionType::None,
+            bloom_bits_per_key: None,
+            prefix_extractor: None,
+            compaction_filter: None,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            entry_count_compaction: None,
+            max_sorted_runs: None,
+            max_concurrent_compactions: 1,
+            flush_to_base_level: false,
+            sequential_writes: false,
+            idempotent_write_window: DEFAULT_IDEMPOTENT_WRITE_WINDOW,
+            internal_keyspace: false,
         }
     }
 
@@ -118,6 +482,49 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            disk_space_reserve: 0,
+            deletion_compaction: None,
+            seek_compaction_threshold: None,
+            high_priority_cache_levels: Vec::new(),
+            max_block_size: None,
+            tenant_quotas: Vec::new(),
+            write_rate_limit: None,
+            low_priority_read_rate_limit: None,
+            ttl: None,
+            max_key_size: MAX_KEY_SIZE,
+            max_value_size: MAX_VALUE_SIZE,
+            shared_metadata_namespace: None,
+            data_paths: Vec::new(),
+            sst_delete_rate: None,
+            scan_readahead: 0,
+            scan_memory_budget: None,
+            max_pinned_blocks_per_iterator: None,
+            iterator_misuse_errors: false,
+            metadata_cache_capacity: None,
+            block_cache_capacity: None,
+            block_cache_policy: BlockCachePolicy::default(),
+            strict_capacity_limit: false,
+            migrate_compaction_layout: false,
+            verify_key_order: false,
+            verify_bottom_level_compaction: false,
+            compaction_windows: Vec::new(),
+            key_range_stats_boundaries: Vec::new(),
+            negative_cache_capacity: None,
+            verify_block_checksums: true,
+            sst_sync_chunk_size: None,
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            block_compression: CompressionType::None,
+            bloom_bits_per_key: None,
+            prefix_extractor: None,
+            compaction_filter: None,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            entry_count_compaction: None,
+            max_sorted_runs: None,
+            max_concurrent_compactions: 1,
+            flush_to_base_level: false,
+            sequential_writes: false,
+            idempotent_write_window: DEFAULT_IDEMPOTENT_WRITE_WINDOW,
+            internal_keyspace: false,
         }
     }
 
@@ -129,13 +536,365 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            disk_space_reserve: 0,
+            deletion_compaction: None,
+            seek_compaction_threshold: None,
+            high_priority_cache_levels: Vec::new(),
+            max_block_size: None,
+            tenant_quotas: Vec::new(),
+            write_rate_limit: None,
+            low_priority_read_rate_limit: None,
+            ttl: None,
+            max_key_size: MAX_KEY_SIZE,
+            max_value_size: MAX_VALUE_SIZE,
+            shared_metadata_namespace: None,
+            data_paths: Vec::new(),
+            sst_delete_rate: None,
+            scan_readahead: 0,
+            scan_memory_budget: None,
+            max_pinned_blocks_per_iterator: None,
+            iterator_misuse_errors: false,
+            metadata_cache_capacity: None,
+            block_cache_capacity: None,
+            block_cache_policy: BlockCachePolicy::default(),
+            strict_capacity_limit: false,
+            migrate_compaction_layout: false,
+            verify_key_order: false,
+            verify_bottom_level_compaction: false,
+            compaction_windows: Vec::new(),
+            key_range_stats_boundaries: Vec::new(),
+            negative_cache_capacity: None,
+            verify_block_checksums: true,
+            sst_sync_chunk_size: None,
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            block_compression: CompressionType::None,
+            bloom_bits_per_key: None,
+            prefix_extractor: None,
+            compaction_filter: None,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            entry_count_compaction: None,
+            max_sorted_runs: None,
+            max_concurrent_compactions: 1,
+            flush_to_base_level: false,
+            sequential_writes: false,
+            idempotent_write_window: DEFAULT_IDEMPOTENT_WRITE_WINDOW,
+            internal_keyspace: false,
+        }
+    }
+
+    /// Check the options for values and combinations the engine cannot work with.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.block_size > 0 && self.block_size <= u16::MAX as usize,
+            "block_size must be between 1 and {} bytes, got {}",
+            u16::MAX,
+            self.block_size
+        );
+        ensure!(
+            self.num_memtable_limit >= 1,
+            "num_memtable_limit must be at least 1"
+        );
+        if self.max_key_size > MAX_KEY_SIZE || self.max_value_size > MAX_VALUE_SIZE {
+            bail!(
+                "max_key_size and max_value_size cannot exceed {} and {} bytes",
+                MAX_KEY_SIZE,
+                MAX_VALUE_SIZE
+            );
+        }
+        if let Some(max_block_size) = self.max_block_size {
+            ensure!(
+                max_block_size >= self.block_size,
+                "max_block_size ({}) is smaller than block_size ({})",
+                max_block_size,
+                self.block_size
+            );
+        }
+        self.compaction_options.validate()?;
+        ensure!(
+            self.max_concurrent_compactions >= 1,
+            "max_concurrent_compactions must be at least 1"
+        );
+        ensure!(
+            self.idempotent_write_window >= 1,
+            "idempotent_write_window must be at least 1"
+        );
+        if let Some(max_sorted_runs) = self.max_sorted_runs {
+            // The bound must leave room for the flushes that make the controller compact
+            let Some(min_sorted_runs) = self.compaction_options.sorted_runs_before_compaction()
+            else {
+                bail!(
+                    "max_sorted_runs is not supported with {} compaction",
+                    self.compaction_options.style()
+                );
+            };
+            ensure!(
+                max_sorted_runs >= min_sorted_runs,
+                "max_sorted_runs must be at least {} with these {} compaction options, got {}",
+                min_sorted_runs,
+                self.compaction_options.style(),
+                max_sorted_runs
+            );
         }
+        if let Some(entry_counts) = &self.entry_count_compaction {
+            ensure!(
+                entry_counts.base_level_entries >= 1,
+                "base_level_entries must be at least 1"
+            );
+            ensure!(
+                entry_counts.level0_entry_compaction_trigger != Some(0),
+                "level0_entry_compaction_trigger must be at least 1"
+            );
+        }
+        // These compact an SST into the overlapping SSTs of the next level, which needs sorted
+        // levels
+        if !self.compaction_options.has_sorted_levels() {
+            ensure!(
+                self.deletion_compaction.is_none(),
+                "deletion_compaction is not supported with {} compaction",
+                self.compaction_options.style()
+            );
+            ensure!(
+                self.seek_compaction_threshold.is_none(),
+                "seek_compaction_threshold is not supported with {} compaction",
+                self.compaction_options.style()
+            );
+        }
+        ensure!(
+            !self.strict_capacity_limit || self.block_cache_capacity.is_some(),
+            "strict_capacity_limit requires block_cache_capacity to be set"
+        );
+        ensure!(
+            self.sst_sync_chunk_size != Some(0),
+            "sst_sync_chunk_size must be positive"
+        );
+        ensure!(
+            self.max_pinned_blocks_per_iterator != Some(0),
+            "max_pinned_blocks_per_iterator must be positive"
+        );
+        ensure!(
+            self.metadata_cache_capacity != Some(0),
+            "metadata_cache_capacity must be positive"
+        );
+        ensure!(
+            self.block_restart_interval > 0,
+            "block_restart_interval must be positive"
+        );
+        ensure!(
+            self.bloom_bits_per_key != Some(0),
+            "bloom_bits_per_key must be positive"
+        );
+        if let CompressionType::Zstd(level) = self.block_compression {
+            ensure!(
+                zstd::compression_level_range().contains(&level),
+                "zstd compression level {} is out of range",
+                level
+            );
+        }
+        ensure!(
+            self.sst_delete_rate != Some(0),
+            "sst_delete_rate must be positive, obsolete SSTs would never be deleted"
+        );
+        KeyRangeCounters::validate(&self.key_range_stats_boundaries)?;
+        for window in &self.compaction_windows {
+            ensure!(
+                window.is_valid(),
+                "compaction window {:?} does not start and end within a day",
+                window
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The largest key the WAL can hold, as the top bit of its 16-bit key lengths is a flag.
+pub const MAX_KEY_SIZE: usize = (EMPTY_VALUE_FLAG - 1) as usize;
+
+/// The largest value, leaving room for the expiry time of a TTL. A block holding a larger value
+/// holds only that value, so much larger values would be better stored outside of the LSM tree.
+pub const MAX_VALUE_SIZE: usize = (16 << 20) - ttl::EXPIRY_LEN;
+
+/// The error returned when a write exceeds `max_key_size` or `max_value_size`. It is an
+/// `std::io::Error` of kind `InvalidInput`.
+fn entry_too_large(what: &str, len: usize, limit: usize) -> anyhow::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "{} of {} bytes exceeds the limit of {} bytes",
+            what, len, limit
+        ),
+    )
+    .into()
+}
+
+/// The smallest key greater than every key starting with `prefix`, or `None` if there is none.
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let len = prefix.iter().rposition(|byte| *byte != u8::MAX)? + 1;
+    let mut upper = prefix[..len].to_vec();
+    upper[len - 1] += 1;
+    Some(upper)
+}
+
+/// Which tiers of the storage a read is allowed to touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadTier {
+    /// Read from memtables, the block cache and the disk.
+    #[default]
+    All,
+    /// Read from memtables and blocks already in the block cache, never issue disk reads.
+    BlockCacheOnly,
+    /// Only read from the memtables.
+    MemtableOnly,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub read_tier: ReadTier,
+    /// Wait up to `session_timeout` until the writes of this session have been applied, which
+    /// gives read-your-writes on a replica. Fails with a `TimedOut` I/O error otherwise.
+    pub session: Option<SessionToken>,
+    pub session_timeout: Duration,
+    /// Read the snapshot of the storage at this timestamp instead of the latest one, see
+    /// `LsmStorageInner::get_with_ts`.
+    pub read_ts: Option<u64>,
+    /// Throttle the disk reads of a background read, see `io_priority`.
+    pub priority: IoPriority,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Wait for the write rate limiter before writing, e.g., for a bulk import that should not
+    /// starve the other writers. Writes without this flag are never throttled.
+    pub rate_limited: bool,
+    /// Apply the write only if this operation was not applied yet, see `crate::idempotence`. Needs
+    /// `LsmStorageOptions::internal_keyspace`.
+    pub operation: Option<OperationId>,
+}
+
+/// The error returned when a read cannot be answered within the requested `ReadTier`. It is an
+/// `std::io::Error` of kind `WouldBlock`, so that callers can fall back to a full read elsewhere.
+fn would_block() -> anyhow::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::WouldBlock,
+        "read cannot be served from the requested tier",
+    )
+    .into()
+}
+
+/// Returns true if the error is caused by a read that would need to go below its `ReadTier`.
+pub fn is_would_block(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+/// What to do with an entry of a memtable being flushed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlushDecision {
+    Keep,
+    /// Drop the entry, so that it never reaches L0. This is only safe for keys that are never
+    /// flushed otherwise, as an older version of the key in an SST would become visible again.
+    Remove,
+    /// Write this value instead.
+    ChangeValue(Bytes),
+}
+
+/// Decides the fate of the entries of a memtable when it is flushed, e.g., to drop short-lived
+/// keys before they reach L0. `value` is `None` for a delete.
+pub trait FlushFilter: Send + Sync {
+    fn filter(&self, key: &[u8], value: Option<&[u8]>) -> FlushDecision;
+}
+
+/// The kind of a background task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundTask {
+    Flush,
+    Compaction,
+}
+
+/// Events reported to the listeners added with `MiniLsm::add_event_listener`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LsmEvent {
+    /// A background task was deferred as the disk does not have `estimated_size` bytes plus
+    /// the configured reserve available. It will be retried on the next tick.
+    InsufficientDiskSpace {
+        task: BackgroundTask,
+        estimated_size: u64,
+        available: u64,
+    },
+    /// A compaction to the bottom level was deferred as it is outside of the
+    /// `compaction_windows` and the utilization is not low enough. It will be retried on the
+    /// next tick.
+    HeavyCompactionDeferred { input_sst_ids: Vec<usize> },
+    /// A background task failed, or panicked. A failed flush or compaction stops the writes
+    /// until `MiniLsm::resume`, while the deletion of the trash is retried on the next tick.
+    BackgroundError { task: &'static str, error: String },
+}
+
+pub type EventListener = Box<dyn Fn(&LsmEvent) + Send + Sync>;
+
+/// The counters returned by `MiniLsm::metrics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsmMetrics {
+    /// The block cache hits and misses of each level, see `MiniLsm::block_cache_stats`.
+    pub block_cache: Vec<LevelCacheStats>,
+    /// `None` if `scan_readahead` is not set.
+    pub prefetch: Option<PrefetchStats>,
+    /// The usage of the tenants configured in `tenant_quotas`.
+    pub tenants: Vec<(Bytes, TenantUsage)>,
+    /// The reads and writes of the ranges split at `key_range_stats_boundaries`, empty if none
+    /// are configured.
+    pub key_ranges: Vec<KeyRangeStats>,
+    /// `None` if `negative_cache_capacity` is not set.
+    pub negative_cache: Option<NegativeCacheStats>,
+    /// The write stalls forced by `max_sorted_runs`, `None` if it is not set.
+    pub sorted_runs: Option<SortedRunStats>,
+    /// The CPU time of the flushes and compactions.
+    pub background_cpu: BackgroundCpuStats,
+}
+
+/// The bytes held by the structures of the storage, returned by `MiniLsm::memory_usage`. The
+/// sizes are approximate, leaving out the overhead of the allocator and the containers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub active_memtable: u64,
+    pub immutable_memtables: u64,
+    /// The blocks in the block cache, not counting the ones pinned by the iterators.
+    pub block_cache: u64,
+    /// The block indexes of the open SSTs, including those in the metadata pool of the block
+    /// cache, see `metadata_cache_capacity`.
+    pub table_metadata: u64,
+    /// The bloom filters of the open SSTs, over their keys and prefixes, including those in the
+    /// metadata pool.
+    pub bloom_filters: u64,
+    /// The blocks pinned by the iterators reading through the block cache.
+    pub pinned_blocks: u64,
+    /// The writes buffered by the transactions, and the committed writes kept to validate them.
+    pub txn_write_sets: u64,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> u64 {
+        self.active_memtable
+            + self.immutable_memtables
+            + self.block_cache
+            + self.table_metadata
+            + self.bloom_filters
+            + self.pinned_blocks
+            + self.txn_write_sets
     }
 }
 
-#[derive(Clone, Debug)]
-pub enum CompactionFilter {
-    Prefix(Bytes),
+/// The space available to unprivileged users on the file system of `path`.
+fn available_disk_space(path: &Path) -> Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after `statvfs` fills it
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // the field types differ between platforms
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
 }
 
 /// The storage interface of the LSM tree.
@@ -146,10 +905,62 @@ pub(crate) struct LsmStorageInner {
     pub(crate) block_cache: Arc<BlockCache>,
     next_sst_id: AtomicUsize,
     pub(crate) options: Arc<LsmStorageOptions>,
-    pub(crate) compaction_controller: CompactionController,
+    /// The controller of the current compaction strategy, which replaces the one in `options`
+    /// once changed with `change_compaction_strategy`.
+    pub(crate) compaction_controller: RwLock<Arc<CompactionController>>,
+    /// Held while a compaction runs, so that the compaction strategy is changed between them.
+    pub(crate) compaction_lock: Mutex<()>,
+    /// Checked by the running compactions, and replaced once cancelled so that the later ones
+    /// run again, see `cancel_compactions`.
+    pub(crate) compaction_cancellation: Mutex<CancellationToken>,
     pub(crate) manifest: Option<Manifest>,
     pub(crate) mvcc: Option<LsmMvccInner>,
-    pub(crate) compaction_filters: Arc<Mutex<Vec<CompactionFilter>>>,
+    flush_filters: RwLock<Vec<Box<dyn FlushFilter>>>,
+    /// Waiters for the next compaction run by the compaction thread.
+    pub(crate) compaction_listeners: TaskNotifier,
+    /// Waiters for the next flush run by the flush thread.
+    pub(crate) flush_listeners: TaskNotifier,
+    /// The error that stopped a background flush or compaction. Writes are rejected and
+    /// background work is paused until `resume` succeeds.
+    background_error: Mutex<Option<anyhow::Error>>,
+    event_listeners: RwLock<Vec<EventListener>>,
+    /// Shared with the manifest and the SST builders.
+    pub(crate) write_observers: Arc<WriteObservers>,
+    /// Collects the key ranges that scans found to be tombstone-heavy.
+    pub(crate) deletion_collector: Arc<DeletionCollector>,
+    /// The SST that exceeded `seek_compaction_threshold`, waiting for the compaction thread.
+    pub(crate) seek_compaction_sst: Mutex<Option<usize>>,
+    pub(crate) compaction_scheduler: CompactionScheduler,
+    pub(crate) quotas: QuotaTracker,
+    pub(crate) key_distribution: KeyDistributionTracker,
+    pub(crate) key_range_counters: KeyRangeCounters,
+    /// The keys found absent, if `negative_cache_capacity` is set.
+    negative_cache: Option<NegativeCache>,
+    write_rate_limiter: RateLimiter,
+    /// Throttles the disk reads of the reads with `IoPriority::Low`.
+    low_priority_read_limiter: Arc<RateLimiter>,
+    /// The sequence of the last write applied to this instance.
+    sequence: SequenceTracker,
+    /// Serializes the writes, so that the preconditions of a conditional batch still hold when
+    /// it is applied, and the timestamps are committed in order.
+    pub(crate) write_lock: Mutex<()>,
+    /// When the compaction thread last looked for expired SSTs.
+    pub(crate) last_ttl_check: Mutex<Instant>,
+    pub(crate) shared_metadata: Option<SharedMetadata>,
+    /// The index in `data_paths` of the SSTs that are not in the first data path.
+    sst_paths: RwLock<HashMap<usize, usize>>,
+    /// The epoch in the file name of the SSTs written by earlier processes. The others have the
+    /// epoch of this process.
+    sst_epochs: RwLock<HashMap<usize, u64>>,
+    pub(crate) sst_file_manager: SstFileManager,
+    pub(crate) cpu_usage: BackgroundCpuUsage,
+    /// The epoch of this process in the manifest, which is part of the names of the files it
+    /// creates.
+    epoch: u64,
+    /// Prefetches the blocks of scans if `scan_readahead` is set.
+    prefetcher: Option<Prefetcher>,
+    /// Holds back the flushes and writes if `max_sorted_runs` is set.
+    pub(crate) sorted_run_limiter: Option<SortedRunLimiter>,
 }
 
 /// A thin wrapper for `LsmStorageInner` and the user interface for MiniLSM.
@@ -158,324 +969,2077 @@ pub struct MiniLsm {
     /// Notifies the L0 flush thread to stop working. (In week 1 day 6)
     flush_notifier: crossbeam_channel::Sender<()>,
     /// The handle for the flush thread. (In week 1 day 6)
-    flush_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    pub(crate) flush_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
     /// Notifies the compaction thread to stop working. (In week 2)
     compaction_notifier: crossbeam_channel::Sender<()>,
     /// The handle for the compaction thread. (In week 2)
-    compaction_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    pub(crate) compaction_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    /// Notifies the thread deleting the SSTs in trash to stop working.
+    trash_notifier: crossbeam_channel::Sender<()>,
+    /// The handle for the thread deleting the SSTs in trash.
+    trash_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    /// The column families by name, see `crate::column_family`.
+    column_families: ColumnFamilies,
 }
 
 impl Drop for MiniLsm {
     fn drop(&mut self) {
         self.compaction_notifier.send(()).ok();
         self.flush_notifier.send(()).ok();
+        self.trash_notifier.send(()).ok();
     }
 }
 
+/// How long `MiniLsm::close` waits for the compactions to finish.
+const CLOSE_COMPACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
 impl MiniLsm {
+    /// Flush all memtables, wait for the compactions to finish, stop the background threads and
+    /// sync the manifest. The threads are stopped even if the flush or the compactions fail.
     pub fn close(&self) -> Result<()> {
-        unimplemented!()
+        let flushed = self
+            .inner
+            .force_flush_all()
+            .and_then(|_| self.inner.wait_for_compactions(CLOSE_COMPACTION_TIMEOUT));
+        self.compaction_notifier.send(()).ok();
+        self.flush_notifier.send(()).ok();
+        self.trash_notifier.send(()).ok();
+        for thread in [
+            &self.compaction_thread,
+            &self.flush_thread,
+            &self.trash_thread,
+        ] {
+            if let Some(thread) = thread.lock().take() {
+                thread
+                    .join()
+                    .map_err(|_| anyhow!("a background thread panicked"))?;
+            }
+        }
+        flushed?;
+        if let Some(manifest) = &self.inner.manifest {
+            manifest.sync()?;
+        }
+        self.inner.sync_dir()
     }
 
     /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
     /// not exist.
     pub fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Arc<Self>> {
         let inner = Arc::new(LsmStorageInner::open(path, options)?);
+        let column_families = match inner.options.internal_keyspace {
+            true => ColumnFamilies::load(&inner)?,
+            false => ColumnFamilies::default(),
+        };
         let (tx1, rx) = crossbeam_channel::unbounded();
         let compaction_thread = inner.spawn_compaction_thread(rx)?;
         let (tx2, rx) = crossbeam_channel::unbounded();
         let flush_thread = inner.spawn_flush_thread(rx)?;
+        let (tx3, rx) = crossbeam_channel::unbounded();
+        let trash_thread = inner.spawn_trash_thread(rx)?;
         Ok(Arc::new(Self {
             inner,
             flush_notifier: tx2,
             flush_thread: Mutex::new(flush_thread),
             compaction_notifier: tx1,
             compaction_thread: Mutex::new(compaction_thread),
+            trash_notifier: tx3,
+            trash_thread: Mutex::new(trash_thread),
+            column_families,
         }))
     }
 
-    pub fn new_txn(&self) -> Result<()> {
+    /// Start a transaction, see `Transaction`.
+    pub fn new_txn(&self) -> Result<Arc<Transaction>> {
         self.inner.new_txn()
     }
 
-    pub fn write_batch<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
-        self.inner.write_batch(batch)
+    /// Take a snapshot of the current state, freezing the current memtable if it has data.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.inner.snapshot()
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
-        self.inner.add_compaction_filter(compaction_filter)
+    /// Iterate over the keys in the range whose value changed from snapshot `old` to `new`.
+    pub fn diff_scan(
+        &self,
+        old: &Snapshot,
+        new: &Snapshot,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<DiffIterator> {
+        DiffIterator::create(old.scan(lower, upper)?, new.scan(lower, upper)?)
     }
 
-    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
-        self.inner.get(key)
+    /// Write the puts and deletes of a batch, a `WriteBatch` or a slice of records, atomically:
+    /// they are logged as one WAL record, take one sequence, and land in the same memtable.
+    /// The preconditions of a `WriteBatch` are only checked by `write_conditional`.
+    pub fn write_batch<T: AsRef<[u8]>, B: AsRef<[WriteBatchRecord<T>]> + ?Sized>(
+        &self,
+        batch: &B,
+    ) -> Result<()> {
+        self.check_batch_writable(batch.as_ref())?;
+        self.inner.write_batch(batch.as_ref())
     }
 
-    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        self.inner.put(key, value)
+    /// Write a batch atomically with the given write options, e.g., only once for retries of the
+    /// same `WriteOptions::operation`.
+    pub fn write_batch_with_options<T: AsRef<[u8]>, B: AsRef<[WriteBatchRecord<T>]> + ?Sized>(
+        &self,
+        batch: &B,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        self.check_batch_writable(batch.as_ref())?;
+        self.inner.write_batch_with_options(batch.as_ref(), options)
     }
 
-    pub fn delete(&self, key: &[u8]) -> Result<()> {
-        self.inner.delete(key)
+    /// Apply the batch atomically if all of its preconditions hold. Returns the indices of the
+    /// failed preconditions, in which case nothing is written.
+    pub fn write_conditional(&self, batch: &WriteBatch) -> Result<Vec<usize>> {
+        self.check_batch_writable(batch.records())?;
+        self.inner.write_conditional(batch)
     }
 
-    pub fn sync(&self) -> Result<()> {
-        self.inner.sync()
+    /// A builder of batches of at most `target_sst_size` bytes of WAL each, the size the
+    /// memtable is frozen at.
+    pub fn write_batch_builder(&self) -> WriteBatchBuilder {
+        WriteBatchBuilder::new().with_max_batch_size(self.inner.options.target_sst_size)
     }
 
-    pub fn scan(
-        &self,
-        lower: Bound<&[u8]>,
-        upper: Bound<&[u8]>,
-    ) -> Result<FusedIterator<LsmIterator>> {
-        self.inner.scan(lower, upper)
+    /// Register a filter run on each entry of the memtables flushed from now on. Filters run in
+    /// the order they were added, each seeing the value left by the previous ones.
+    pub fn add_flush_filter(&self, flush_filter: Box<dyn FlushFilter>) {
+        self.inner.add_flush_filter(flush_filter)
     }
 
-    /// Only call this in test cases due to race conditions
-    pub fn force_flush(&self) -> Result<()> {
-        if !self.inner.state.read().memtable.is_empty() {
-            self.inner
-                .force_freeze_memtable(&self.inner.state_lock.lock())?;
-        }
-        if !self.inner.state.read().imm_memtables.is_empty() {
-            self.inner.force_flush_next_imm_memtable()?;
-        }
-        Ok(())
+    /// Register a listener for `LsmEvent`s. It is called from the background threads, so it
+    /// should return quickly.
+    pub fn add_event_listener(&self, listener: EventListener) {
+        self.inner.add_event_listener(listener)
     }
 
-    pub fn force_full_compaction(&self) -> Result<()> {
-        self.inner.force_full_compaction()
+    /// Register an observer of the bytes written to the SSTs and the manifest from now on.
+    pub fn add_write_observer(&self, observer: Box<dyn WriteObserver>) {
+        self.inner.add_write_observer(observer)
     }
-}
 
-impl LsmStorageInner {
-    pub(crate) fn next_sst_id(&self) -> usize {
-        self.next_sst_id
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.inner.get(key)
     }
 
-    pub(crate) fn mvcc(&self) -> &LsmMvccInner {
-        self.mvcc.as_ref().unwrap()
+    /// Get a key with the given read options. Returns a `WouldBlock` error (see `is_would_block`)
+    /// if the key cannot be resolved without going below `options.read_tier`.
+    pub fn get_with_options(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Bytes>> {
+        self.inner.get_with_options(key, options)
     }
 
-    /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
-    /// not exist.
-    pub(crate) fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
-        let path = path.as_ref();
-        if !path.exists() {
-            std::fs::create_dir(path)?;
-        }
-        let state = LsmStorageState::create(&options);
-
-        let compaction_controller = match &options.compaction_options {
-            CompactionOptions::Leveled(options) => {
-                CompactionController::Leveled(LeveledCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Tiered(options) => {
-                CompactionController::Tiered(TieredCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Simple(options) => CompactionController::Simple(
-                SimpleLeveledCompactionController::new(options.clone()),
-            ),
-            CompactionOptions::NoCompaction => CompactionController::NoCompaction,
-        };
-
-        let storage = Self {
-            state: Arc::new(RwLock::new(Arc::new(state))),
-            state_lock: Mutex::new(()),
-            path: path.to_path_buf(),
-            block_cache: Arc::new(BlockCache::new(1024)),
-            next_sst_id: AtomicUsize::new(1),
-            compaction_controller,
-            manifest: None,
-            options: options.into(),
-            mvcc: None,
-            compaction_filters: Arc::new(Mutex::new(Vec::new())),
-        };
+    /// Get a key as of the snapshot at `read_ts`, see `LsmStorageInner::get_with_ts`.
+    pub fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
+        self.inner.get_with_ts(key, read_ts)
+    }
 
-        Ok(storage)
+    /// Get many keys at once, returning their values in the order of `keys`. All the keys are
+    /// read from one snapshot, and the keys are sorted so that each SST is probed once in key
+    /// order and the keys falling into the same block share one read of it, which is much
+    /// faster than a `get` for each key.
+    pub fn multi_get<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Bytes>>> {
+        self.inner.multi_get(keys)
     }
 
-    pub fn sync(&self) -> Result<()> {
-        unimplemented!()
+    /// Get many keys at once with the given read options, see `multi_get`.
+    pub fn multi_get_with_options<K: AsRef<[u8]>>(
+        &self,
+        keys: &[K],
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Bytes>>> {
+        self.inner.multi_get_with_options(keys, options)
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
-        let mut compaction_filters = self.compaction_filters.lock();
-        compaction_filters.push(compaction_filter);
+    /// The timestamp of the latest write, to read a snapshot with `get_with_ts` and
+    /// `scan_with_ts`.
+    pub fn latest_commit_ts(&self) -> u64 {
+        self.inner.mvcc().latest_commit_ts()
     }
 
-    fn value_opt(value: &[u8]) -> Option<Bytes> {
-        if value.is_empty() {
-            None
-        } else {
-            Some(Bytes::copy_from_slice(value))
+    /// Fail the write of `key` if it is reserved, see `LsmStorageOptions::internal_keyspace`.
+    fn check_writable(&self, key: &[u8]) -> Result<()> {
+        match self.inner.options.internal_keyspace {
+            true => column_family::check_default_key(key),
+            false => Ok(()),
         }
     }
 
-    fn range_overlap(
-        user_begin: Bound<&[u8]>,
-        user_end: Bound<&[u8]>,
-        table_begin: &[u8],
-        table_end: &[u8],
-    ) -> bool {
-        match user_end {
-            Bound::Excluded(key) if key <= table_begin => {
-                return false;
-            }
-            Bound::Included(key) if key < table_begin => {
-                return false;
-            }
-            _ => {}
+    /// Fail the batch if it writes a reserved key other than the keys of the column families put
+    /// by `WriteBatch::put_cf`, or if one of these column families was dropped, see
+    /// `LsmStorageOptions::internal_keyspace`.
+    fn check_batch_writable<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
+        if !self.inner.options.internal_keyspace {
+            return Ok(());
         }
-        match user_begin {
-            Bound::Excluded(key) if key >= table_end => {
-                return false;
-            }
-            Bound::Included(key) if key > table_end => {
-                return false;
-            }
-            _ => {}
+        for record in batch {
+            let (WriteBatchRecord::Put(key, _) | WriteBatchRecord::Del(key)) = record;
+            self.column_families.check_batch_key(key.as_ref())?;
         }
-        true
+        Ok(())
     }
 
-    fn key_within(user_key: &[u8], table_begin: &[u8], table_end: &[u8]) -> bool {
-        table_begin <= user_key && user_key <= table_end
+    /// Limit the bounds of a scan to the keys that are not reserved, see
+    /// `LsmStorageOptions::internal_keyspace`.
+    fn readable_bounds<'a>(
+        &self,
+        lower: Bound<&'a [u8]>,
+        upper: Bound<&'a [u8]>,
+    ) -> (Bound<&'a [u8]>, Bound<&'a [u8]>) {
+        match self.inner.options.internal_keyspace {
+            true => column_family::default_bounds(lower, upper),
+            false => (lower, upper),
+        }
     }
 
-    /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
-    pub fn get(&self, _key: &[u8]) -> Result<Option<Bytes>> {
-        let snapshot = {
-            let state = self.state.read();
-            Arc::clone(&state)
-        };
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_writable(key)?;
+        self.inner.put(key, value)
+    }
 
-        if let Some(value) = snapshot.memtable.get(_key) {
-            return Ok(Self::value_opt(&value));
-        }
+    /// Put a key-value pair with a user metadata byte, which scans return with
+    /// `LsmIterator::value_meta`.
+    pub fn put_with_meta(&self, key: &[u8], value: &[u8], meta: u8) -> Result<()> {
+        self.check_writable(key)?;
+        self.inner.put_with_meta(key, value, meta)
+    }
 
-        for memtable in snapshot.imm_memtables.iter() {
-            if let Some(value) = memtable.get(_key) {
-                return Ok(Self::value_opt(&value));
-            }
-        }
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.check_writable(key)?;
+        self.inner.delete(key)
+    }
 
-        let key = Key::from_slice(_key);
-        let mut iters = Vec::with_capacity(snapshot.l0_sstables.len());
-        for sst_id in snapshot.l0_sstables.iter() {
-            let table = snapshot.sstables[sst_id].clone();
-            if Self::key_within(
-                _key,
-                table.first_key().raw_ref(),
-                table.last_key().raw_ref(),
-            ) {
-                if let Some(bloom) = &table.bloom {
-                    if bloom.may_contain(farmhash::fingerprint32(_key)) {
-                        iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
-                            table, key,
-                        )?));
-                    }
-                } else {
-                    iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
-                        table, key,
-                    )?));
-                }
-            }
-        }
-        let iter = MergeIterator::create(iters);
-        if iter.is_valid() && iter.key() == key {
-            return Ok(Self::value_opt(iter.value()));
-        }
-        Ok(None)
+    /// Delete the keys from `start` to `end`, exclusive. The reserved keys are left out, see
+    /// `LsmStorageOptions::internal_keyspace`.
+    pub fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<()> {
+        let end = match self.inner.options.internal_keyspace {
+            true => column_family::default_delete_range(start, end)?,
+            false => end,
+        };
+        self.inner.delete_range(start, end)
     }
 
-    /// Write a batch of data into the storage. Implement in week 2 day 7.
-    pub fn write_batch<T: AsRef<[u8]>>(&self, _batch: &[WriteBatchRecord<T>]) -> Result<()> {
-        unimplemented!()
+    pub fn put_with_options(&self, key: &[u8], value: &[u8], options: &WriteOptions) -> Result<()> {
+        self.check_writable(key)?;
+        self.inner.put_with_options(key, value, options)
     }
 
-    /// Put a key-value pair into the storage by writing into the current memtable.
-    pub fn put(&self, _key: &[u8], _value: &[u8]) -> Result<()> {
-        let num_bytes;
-        {
-            let state = self.state.read();
-            state.memtable.put(_key, _value)?;
-            num_bytes = state.memtable.approximate_size();
-        }
+    pub fn delete_with_options(&self, key: &[u8], options: &WriteOptions) -> Result<()> {
+        self.check_writable(key)?;
+        self.inner.delete_with_options(key, options)
+    }
 
-        self.freeze_memtable_if_needed(num_bytes)?;
+    /// Create the column family `name`, see `crate::column_family`. Needs `internal_keyspace`.
+    pub fn create_cf(&self, name: &str) -> Result<ColumnFamily> {
+        ensure!(
+            self.inner.options.internal_keyspace,
+            "column families need internal_keyspace"
+        );
+        self.column_families.create(name, |records| {
+            let batch = records
+                .iter()
+                .map(|(key, value)| WriteBatchRecord::Put(key, value))
+                .collect::<Vec<_>>();
+            self.inner.write_batch(&batch)
+        })
+    }
 
-        Ok(())
+    /// Drop the column family `name` and delete all of its keys. The handles to it fail from now
+    /// on.
+    pub fn drop_cf(&self, name: &str) -> Result<()> {
+        self.column_families.drop(name, |cf| {
+            let (start, end) = cf.key_range();
+            self.inner.delete_range(&start, &end)?;
+            self.inner.delete(&column_family::registry_key(name))
+        })
     }
 
-    /// Remove a key from the storage by writing an empty value.
-    pub fn delete(&self, _key: &[u8]) -> Result<()> {
-        let num_bytes;
-        {
-            let state = self.state.read();
-            state.memtable.put(_key, &[])?;
-            num_bytes = state.memtable.approximate_size();
-        }
+    /// The column family `name`, if it exists.
+    pub fn cf(&self, name: &str) -> Option<ColumnFamily> {
+        self.column_families.get(name)
+    }
 
-        self.freeze_memtable_if_needed(num_bytes)?;
+    /// The names of the column families in order, without the default one.
+    pub fn list_cfs(&self) -> Vec<String> {
+        self.column_families.names()
+    }
 
-        Ok(())
+    pub fn get_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<Option<Bytes>> {
+        self.column_families.check(cf)?;
+        self.inner.get(&cf.key(key))
     }
 
-    fn freeze_memtable_if_needed(&self, approximate_size: usize) -> Result<()> {
-        if approximate_size >= self.options.target_sst_size {
-            // Acquire state mutex to prevent concurrent freezers
-            let state_lock = self.state_lock.lock();
+    pub fn put_cf(&self, cf: &ColumnFamily, key: &[u8], value: &[u8]) -> Result<()> {
+        self.column_families.check(cf)?;
+        self.inner.put(&cf.key(key), value)
+    }
 
-            // Acquire read lock on the state
-            let state = self.state.read();
+    pub fn delete_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<()> {
+        self.column_families.check(cf)?;
+        self.inner.delete(&cf.key(key))
+    }
 
-            // Recheck if the memtable is needed to be frozen
-            if state.memtable.approximate_size() >= self.options.target_sst_size {
-                drop(state);
-                self.force_freeze_memtable(&state_lock)?;
-            }
-        }
-        Ok(())
+    /// Scan a range of the keys of a column family, which the iterator returns without the
+    /// prefix of the column family.
+    pub fn scan_cf(
+        &self,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<ColumnFamilyIterator> {
+        self.column_families.check(cf)?;
+        let (lower, upper) = cf.bounds(lower, upper);
+        let iter = self.inner.scan(
+            lower.as_ref().map(Vec::as_slice),
+            upper.as_ref().map(Vec::as_slice),
+        )?;
+        Ok(ColumnFamilyIterator::new(iter))
     }
 
-    pub(crate) fn path_of_sst_static(path: impl AsRef<Path>, id: usize) -> PathBuf {
-        path.as_ref().join(format!("{:05}.sst", id))
+    /// Change the bytes per second of the rate-limited writes, `None` to stop throttling them.
+    pub fn set_write_rate_limit(&self, bytes_per_sec: Option<u64>) {
+        self.inner
+            .write_rate_limiter
+            .set_bytes_per_sec(bytes_per_sec);
     }
 
-    pub(crate) fn path_of_sst(&self, id: usize) -> PathBuf {
-        Self::path_of_sst_static(&self.path, id)
+    pub fn write_rate_limit(&self) -> Option<u64> {
+        self.inner.write_rate_limiter.bytes_per_sec()
     }
 
-    pub(crate) fn path_of_wal_static(path: impl AsRef<Path>, id: usize) -> PathBuf {
-        path.as_ref().join(format!("{:05}.wal", id))
+    /// Change the bytes per second read from the disk by low-priority reads, `None` to stop
+    /// throttling them.
+    pub fn set_low_priority_read_rate_limit(&self, bytes_per_sec: Option<u64>) {
+        self.inner
+            .low_priority_read_limiter
+            .set_bytes_per_sec(bytes_per_sec);
     }
 
-    pub(crate) fn path_of_wal(&self, id: usize) -> PathBuf {
-        Self::path_of_wal_static(&self.path, id)
+    pub fn low_priority_read_rate_limit(&self) -> Option<u64> {
+        self.inner.low_priority_read_limiter.bytes_per_sec()
     }
 
-    pub(super) fn sync_dir(&self) -> Result<()> {
-        unimplemented!()
+    /// Let compactions to the bottom level run outside of the `compaction_windows` while `probe`
+    /// reports a utilization below `max_utilization`. The probe is called from the compaction
+    /// thread before each such compaction.
+    pub fn set_utilization_probe(&self, probe: UtilizationProbe, max_utilization: f64) {
+        self.inner
+            .compaction_scheduler
+            .set_utilization_probe(Some((probe, max_utilization)));
     }
 
-    /// Force freeze the current memtable to an immutable memtable
-    pub fn force_freeze_memtable(&self, _state_lock_observer: &MutexGuard<'_, ()>) -> Result<()> {
-        let new_memtable = Arc::new(MemTable::create(self.next_sst_id()));
-        {
-            // Acquire write lock
-            let mut state = self.state.write();
+    pub fn clear_utilization_probe(&self) {
+        self.inner.compaction_scheduler.set_utilization_probe(None);
+    }
 
-            // Swap the current memtable with a new one
-            let mut snapshot = state.as_ref().clone();
-            let old_memtable = replace(&mut snapshot.memtable, new_memtable);
+    /// Write the SSTs holding the keys in `[lower, upper)` to `dir`, rewriting only the SSTs that
+    /// cross a bound. See `range_export`.
+    pub fn export_range(
+        &self,
+        lower: &[u8],
+        upper: &[u8],
+        dir: impl AsRef<Path>,
+    ) -> Result<RangeExport> {
+        self.inner.export_range(lower, upper, dir)
+    }
+
+    /// Add the SSTs written by `export_range` to this storage, which must not have any key in the
+    /// exported range.
+    pub fn import_range(&self, dir: impl AsRef<Path>) -> Result<Vec<usize>> {
+        self.inner.import_range(dir)
+    }
+
+    /// Load sorted CSV or Parquet files, with keys increasing across the files in order, into
+    /// this storage, which must not have any key in their range. See `crate::bulk_import`.
+    #[cfg(any(feature = "csv", feature = "parquet"))]
+    pub fn bulk_import(
+        &self,
+        files: &[PathBuf],
+        import: &crate::bulk_import::BulkImport,
+    ) -> Result<Vec<usize>> {
+        self.inner.bulk_import(files, import)
+    }
+
+    /// The token of the last write applied to this instance. Pass it to the reads on a replica
+    /// to make them wait until the replica has applied the writes made so far.
+    pub fn session_token(&self) -> SessionToken {
+        self.inner.sequence.token()
+    }
+
+    /// Block until this instance has applied the writes up to `token`, e.g., before a scan on a
+    /// replica.
+    pub fn wait_for_session(&self, token: SessionToken, timeout: Duration) -> Result<()> {
+        self.inner.sequence.wait_for(token, timeout)
+    }
+
+    /// Apply a write shipped from the primary on a replica, where `sequence` is the sequence the
+    /// primary assigned to it. A value of `None` deletes the key.
+    pub fn apply_replicated(&self, sequence: u64, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        self.inner.apply_replicated(sequence, key, value)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.inner.sync()
+    }
+
+    pub fn scan(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        self.inner.scan(lower, upper)
+    }
+
+    /// Scan a range as of the snapshot at `read_ts`, see `LsmStorageInner::scan_with_ts`.
+    pub fn scan_with_ts(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        self.inner.scan_with_ts(lower, upper, read_ts)
+    }
+
+    /// Scan a range with the given read options, see `LsmStorageInner::scan_with_options`.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        self.inner.scan_with_options(lower, upper, options)
+    }
+
+    /// Scan a range in descending key order, see `LsmStorageInner::scan_rev`.
+    pub fn scan_rev(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        self.inner.scan_rev(lower, upper)
+    }
+
+    /// Sample about `n` keys of a range without scanning it, see `crate::key_sample`.
+    pub fn sample_keys(
+        &self,
+        n: usize,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<Vec<Bytes>> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        self.inner.sample_keys(n, lower, upper)
+    }
+
+    /// The approximate distribution of the keys over `buckets` ranges, see
+    /// `crate::key_distribution`.
+    pub fn key_distribution(&self, buckets: usize) -> Result<Vec<KeyBucket>> {
+        self.inner.key_distribution(buckets)
+    }
+
+    /// Scan the keys starting with `prefix`, skipping the SSTs whose prefix bloom filter rules it
+    /// out if `prefix_extractor` extracts `prefix` as a whole.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<FusedIterator<LsmIterator>> {
+        if self.inner.options.internal_keyspace && column_family::prefix_overlaps_reserved(prefix) {
+            // Scanned as a range, which `readable_bounds` can cut before the reserved keys
+            let upper = prefix_upper_bound(prefix);
+            let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+            let (lower, upper) = self.readable_bounds(Bound::Included(prefix), upper);
+            return self.inner.scan(lower, upper);
+        }
+        self.inner.scan_prefix(prefix)
+    }
+
+    /// Scan a range into chunks of serialized entries of at most `max_bytes_per_chunk` bytes, to
+    /// be sent over the network as they are. See `crate::scan_chunks`.
+    pub fn scan_chunks(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        max_bytes_per_chunk: usize,
+    ) -> Result<ScanChunks> {
+        let (lower, upper) = self.readable_bounds(lower, upper);
+        Ok(ScanChunks::new(
+            self.inner.scan(lower, upper)?,
+            max_bytes_per_chunk,
+        ))
+    }
+
+    /// Only call this in test cases due to race conditions
+    pub fn force_flush(&self) -> Result<()> {
+        if !self.inner.state.read().memtable.is_empty() {
+            self.inner
+                .force_freeze_memtable(&self.inner.state_lock.lock())?;
+        }
+        if !self.inner.state.read().imm_memtables.is_empty() {
+            self.inner.force_flush_next_imm_memtable()?;
+        }
+        Ok(())
+    }
+
+    pub fn force_full_compaction(&self) -> Result<()> {
+        self.inner.force_full_compaction()
+    }
+
+    /// Compact a key range down to the bottom level, see `LsmStorageInner::compact_range`.
+    pub fn compact_range(&self, first_key: &[u8], last_key: &[u8]) -> Result<()> {
+        self.inner.compact_range(first_key, last_key)
+    }
+
+    /// Stop the running compactions, see `LsmStorageInner::cancel_compactions`.
+    pub fn cancel_compactions(&self) {
+        self.inner.cancel_compactions()
+    }
+
+    /// The compaction that would run next, see `LsmStorageInner::explain_compaction`.
+    pub fn explain_compaction(&self) -> Option<CompactionPlan> {
+        self.inner.explain_compaction()
+    }
+
+    /// Switch to another compaction strategy, see `LsmStorageInner::change_compaction_strategy`.
+    pub fn change_compaction_strategy(&self, compaction_options: CompactionOptions) -> Result<()> {
+        self.inner.change_compaction_strategy(compaction_options)
+    }
+
+    /// Freeze the current memtable and flush all memtables to disk.
+    pub fn force_flush_all(&self) -> Result<()> {
+        self.inner.force_flush_all()
+    }
+
+    /// Block until the compaction controller has nothing left to do, or fail if that does not
+    /// happen within `timeout`.
+    pub fn wait_for_compactions(&self, timeout: Duration) -> Result<()> {
+        self.inner.wait_for_compactions(timeout)
+    }
+
+    /// Run a full compaction in the background. The returned handle resolves when it finishes.
+    pub fn schedule_full_compaction(&self) -> TaskHandle {
+        let inner = self.inner.clone();
+        TaskHandle::spawn(move || {
+            inner
+                .force_full_compaction()
+                .inspect_err(|e| inner.record_background_error(e))
+        })
+    }
+
+    /// Freeze the current memtable and flush the earliest immutable memtable in the background.
+    /// The returned handle resolves when the flush finishes.
+    pub fn schedule_flush(&self) -> TaskHandle {
+        let inner = self.inner.clone();
+        TaskHandle::spawn(move || {
+            if !inner.state.read().memtable.is_empty() {
+                inner.force_freeze_memtable(&inner.state_lock.lock())?;
+            }
+            if !inner.state.read().imm_memtables.is_empty() {
+                inner
+                    .force_flush_next_imm_memtable()
+                    .inspect_err(|e| inner.record_background_error(e))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Retry the work that failed with a background error, and accept writes again if it
+    /// succeeds. Call this after the cause of the error (e.g., a full disk) has been fixed.
+    pub fn resume(&self) -> Result<()> {
+        self.inner.resume()
+    }
+
+    /// The block cache hits and misses of each level, indexed by level (0 for L0).
+    pub fn block_cache_stats(&self) -> Vec<LevelCacheStats> {
+        self.inner.block_cache.level_stats()
+    }
+
+    /// The usage of the tenant configured with `prefix` in `tenant_quotas`.
+    pub fn tenant_usage(&self, prefix: &[u8]) -> Option<TenantUsage> {
+        self.inner.quotas.usage(prefix)
+    }
+
+    /// The usage of all tenants configured in `tenant_quotas`.
+    pub fn tenant_usages(&self) -> Vec<(Bytes, TenantUsage)> {
+        self.inner.quotas.usages()
+    }
+
+    /// The block prefetches of the scans so far, `None` if `scan_readahead` is not set.
+    pub fn prefetch_stats(&self) -> Option<PrefetchStats> {
+        self.inner.prefetcher.as_ref().map(Prefetcher::stats)
+    }
+
+    /// The counters of the storage since it was opened.
+    pub fn metrics(&self) -> LsmMetrics {
+        LsmMetrics {
+            block_cache: self.block_cache_stats(),
+            prefetch: self.prefetch_stats(),
+            tenants: self.tenant_usages(),
+            key_ranges: self.inner.key_range_counters.stats(),
+            negative_cache: self.inner.negative_cache.as_ref().map(NegativeCache::stats),
+            sorted_runs: self
+                .inner
+                .sorted_run_limiter
+                .as_ref()
+                .map(SortedRunLimiter::stats),
+            background_cpu: self.inner.cpu_usage.stats(),
+        }
+    }
+
+    /// The bytes held by the memtables, the block cache, the SST metadata, the iterators and the
+    /// transactions, to relate the memory of the process to the structures of the storage.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let state = self.inner.state.read().clone();
+        let block_cache = &self.inner.block_cache;
+        let (pooled_index, pooled_bloom) = block_cache.pooled_metadata_size();
+        MemoryUsage {
+            active_memtable: state.memtable.approximate_size() as u64,
+            immutable_memtables: state
+                .imm_memtables
+                .iter()
+                .map(|memtable| memtable.approximate_size() as u64)
+                .sum(),
+            block_cache: block_cache.cached_size(),
+            table_metadata: state
+                .sstables
+                .values()
+                .map(|sst| sst.block_index_size())
+                .sum::<u64>()
+                + pooled_index,
+            bloom_filters: state
+                .sstables
+                .values()
+                .map(|sst| sst.bloom_size())
+                .sum::<u64>()
+                + pooled_bloom,
+            pinned_blocks: block_cache.pinned_size(),
+            txn_write_sets: self.inner.mvcc().txn_write_sets_size(),
+        }
+    }
+
+    /// The total size of the obsolete SSTs in trash waiting to be deleted.
+    pub fn trash_size(&self) -> u64 {
+        self.inner.sst_file_manager.trash_size()
+    }
+
+    /// Returns a handle that resolves when the compaction thread finishes its next compaction
+    /// task, or fails with the error of that task.
+    pub fn notify_next_compaction(&self) -> TaskHandle {
+        self.inner.compaction_listeners.subscribe()
+    }
+
+    /// Returns a handle that resolves when the flush thread finishes its next flush, or fails
+    /// with the error of that flush.
+    pub fn notify_next_flush(&self) -> TaskHandle {
+        self.inner.flush_listeners.subscribe()
+    }
+}
+
+impl LsmStorageInner {
+    pub(crate) fn next_sst_id(&self) -> usize {
+        self.next_sst_id
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub(crate) fn mvcc(&self) -> &LsmMvccInner {
+        self.mvcc.as_ref().unwrap()
+    }
+
+    /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
+    /// not exist.
+    pub(crate) fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
+        let path = path.as_ref();
+        options.validate()?;
+        if !path.exists() {
+            std::fs::create_dir(path)?;
+        }
+        let stored = StoredOptions::read(path)?;
+        let migrate = match &stored {
+            Some(stored) => {
+                stored.check_format_version()?;
+                stored.check_internal_keyspace(&options)?;
+                stored.check_migration(&options)?
+            }
+            None => false,
+        };
+        let reserve_keyspace =
+            options.internal_keyspace && !stored.as_ref().is_some_and(|s| s.internal_keyspace);
+        for data_path in &options.data_paths {
+            std::fs::create_dir_all(&data_path.path)?;
+        }
+        let manifest_path = path.join("MANIFEST");
+        let (manifest, entries) = if manifest_path.exists() {
+            Manifest::recover(&manifest_path)?
+        } else {
+            (Manifest::create(&manifest_path)?, Vec::new())
+        };
+        let write_observers = Arc::new(WriteObservers::default());
+        let manifest = manifest.with_write_observers(write_observers.clone());
+        let epoch = manifest.epoch();
+        let max_sst_id = entries
+            .iter()
+            .flat_map(|entry| match &entry.record {
+                ManifestRecord::Flush(id)
+                | ManifestRecord::FlushToLevel(id, _)
+                | ManifestRecord::NewMemtable(id)
+                | ManifestRecord::DataPath(id, _) => vec![*id],
+                ManifestRecord::Compaction(_, output) => output.clone(),
+                ManifestRecord::Snapshot(snapshot) => snapshot
+                    .sst_epochs
+                    .iter()
+                    .chain(&snapshot.memtables)
+                    .map(|(id, _)| *id)
+                    .collect(),
+                ManifestRecord::NewEpoch(_) | ManifestRecord::CompactionStrategy(_) => vec![],
+            })
+            .max();
+        // The levels are laid out as for the last options, `migrate` changes the layout below
+        let layout = match stored {
+            Some(stored) => stored.compaction_options,
+            None => options.compaction_options.clone(),
+        };
+        let RecoveredManifest {
+            mut state,
+            sst_paths,
+            memtables,
+        } = RecoveredManifest::replay(&entries, &options, &layout);
+        let (memtable_id, next_sst_id) = match max_sst_id {
+            Some(max_sst_id) => (max_sst_id + 1, max_sst_id + 2),
+            None => (0, 1),
+        };
+
+        let compaction_controller = CompactionController::new(&options.compaction_options)
+            .with_entry_counts(options.entry_count_compaction.as_ref());
+
+        let deletion_collector =
+            Arc::new(DeletionCollector::new(options.deletion_compaction.clone()));
+
+        let quotas = QuotaTracker::new(options.tenant_quotas.clone());
+        let write_rate_limiter = RateLimiter::new(options.write_rate_limit);
+        let low_priority_read_limiter =
+            Arc::new(RateLimiter::new(options.low_priority_read_rate_limit));
+        let prefetcher = (options.scan_readahead > 0).then(Prefetcher::new);
+        let sorted_run_limiter = options.max_sorted_runs.map(SortedRunLimiter::new);
+        let sst_file_manager = SstFileManager::new(options.sst_delete_rate);
+        if options.data_paths.is_empty() {
+            sst_file_manager.recover_trash(path)?;
+        }
+        for data_path in &options.data_paths {
+            sst_file_manager.recover_trash(&data_path.path)?;
+        }
+        let live_ssts = live_ssts(&entries);
+        let data_dirs = match options.data_paths.is_empty() {
+            true => vec![path.to_path_buf()],
+            false => options.data_paths.iter().map(|p| p.path.clone()).collect(),
+        };
+        for data_dir in data_dirs {
+            for file in std::fs::read_dir(&data_dir)? {
+                let file = file?.path();
+                let Some((id, file_epoch)) = Self::parse_sst_file_name(&file) else {
+                    continue;
+                };
+                // Written by an older epoch but not referenced, e.g., by a fenced-off process
+                if file_epoch < epoch && live_ssts.get(&id) != Some(&file_epoch) {
+                    sst_file_manager.delete_file(&file)?;
+                }
+            }
+        }
+        let compaction_scheduler = CompactionScheduler::new(options.compaction_windows.clone());
+        let key_range_counters = KeyRangeCounters::new(options.key_range_stats_boundaries.clone());
+        let negative_cache = options.negative_cache_capacity.map(NegativeCache::new);
+        let block_cache = match options.block_cache_capacity {
+            Some(capacity) => BlockCache::with_byte_capacity(
+                capacity,
+                options.high_priority_cache_levels.clone(),
+                options.strict_capacity_limit,
+                options.block_cache_policy,
+            ),
+            None => BlockCache::with_high_priority_levels(
+                1024,
+                options.high_priority_cache_levels.clone(),
+                options.block_cache_policy,
+            ),
+        };
+        let block_cache = match options.metadata_cache_capacity {
+            Some(capacity) => block_cache.with_metadata_capacity(capacity),
+            None => block_cache,
+        };
+        let shared_metadata = options
+            .shared_metadata_namespace
+            .as_ref()
+            .map(SharedMetadata::new);
+
+        let block_cache = Arc::new(block_cache);
+        let mut sst_epochs = HashMap::new();
+        let sst_ids = state
+            .l0_sstables
+            .iter()
+            .chain(
+                state
+                    .levels
+                    .iter()
+                    .flat_map(|(_, level_sst_ids)| level_sst_ids),
+            )
+            .copied()
+            .collect::<Vec<_>>();
+        for sst_id in sst_ids {
+            let sst_epoch = live_ssts[&sst_id];
+            let data_dir = match options
+                .data_paths
+                .get(sst_paths.get(&sst_id).copied().unwrap_or(0))
+            {
+                Some(data_path) => &data_path.path,
+                None => path,
+            };
+            let sst_path = Self::path_of_sst_static(data_dir, sst_epoch, sst_id);
+            let file = FileObject::open(&sst_path)
+                .with_context(|| format!("failed to open SST {}", sst_path.display()))?;
+            let sst = SsTable::open(sst_id, Some(block_cache.clone()), file)?
+                .with_checksum_verification(options.verify_block_checksums);
+            if let Some(shared_metadata) = &shared_metadata {
+                sst.publish_metadata(shared_metadata)?;
+            }
+            state.sstables.insert(sst_id, Arc::new(sst));
+            sst_epochs.insert(sst_id, sst_epoch);
+        }
+        // The outputs of compactions are not sorted in recovery
+        let sstables = &state.sstables;
+        for (_, level_sst_ids) in &mut state.levels {
+            level_sst_ids.sort_by(|a, b| sstables[a].first_key().cmp(sstables[b].first_key()));
+        }
+        state.update_sst_levels();
+        quotas.refresh(&state);
+
+        // Replay the WALs of the memtables that were not flushed, from the oldest, even if
+        // `enable_wal` was turned off since. The other WALs are deleted.
+        let mut wal_paths = Vec::new();
+        for (&id, &wal_epoch) in &memtables {
+            let wal_path = Self::path_of_wal_static(path, wal_epoch, id);
+            // Deleted after flushing the memtable, or flushed into nothing
+            if !wal_path.exists() {
+                continue;
+            }
+            let memtable = MemTable::recover_from_wal(id, &wal_path)?
+                .with_write_observers(write_observers.clone());
+            if memtable.is_empty() {
+                continue;
+            }
+            state.imm_memtables.insert(0, Arc::new(memtable));
+            wal_paths.push(wal_path);
+        }
+        for file in std::fs::read_dir(path)? {
+            let file = file?.path();
+            if file.extension().is_some_and(|ext| ext == "wal") && !wal_paths.contains(&file) {
+                std::fs::remove_file(&file)?;
+            }
+        }
+        state.memtable = Arc::new(
+            match options.enable_wal {
+                true => MemTable::create_with_wal(
+                    memtable_id,
+                    Self::path_of_wal_static(path, epoch, memtable_id),
+                )?
+                .with_write_observers(write_observers.clone()),
+                false => MemTable::create(memtable_id),
+            }
+            .with_append_log(options.sequential_writes),
+        );
+        if options.enable_wal {
+            manifest.add_record_when_init(ManifestRecord::NewMemtable(memtable_id))?;
+        }
+        // The commit timestamp resumes from the latest write that was persisted
+        let initial_ts = state
+            .sstables
+            .values()
+            .map(|sst| sst.max_ts())
+            .chain(state.imm_memtables.iter().map(|memtable| memtable.max_ts()))
+            .max()
+            .unwrap_or(TS_DEFAULT);
+
+        let storage = Self {
+            state: Arc::new(RwLock::new(Arc::new(state))),
+            state_lock: Mutex::new(()),
+            path: path.to_path_buf(),
+            block_cache,
+            next_sst_id: AtomicUsize::new(next_sst_id),
+            compaction_controller: RwLock::new(Arc::new(compaction_controller)),
+            compaction_lock: Mutex::new(()),
+            compaction_cancellation: Mutex::new(CancellationToken::new()),
+            manifest: Some(manifest),
+            options: options.into(),
+            mvcc: Some(LsmMvccInner::new(initial_ts)),
+            flush_filters: RwLock::new(Vec::new()),
+            compaction_listeners: TaskNotifier::default(),
+            flush_listeners: TaskNotifier::default(),
+            background_error: Mutex::new(None),
+            event_listeners: RwLock::new(Vec::new()),
+            write_observers,
+            deletion_collector,
+            seek_compaction_sst: Mutex::new(None),
+            compaction_scheduler,
+            quotas,
+            key_distribution: KeyDistributionTracker::default(),
+            key_range_counters,
+            negative_cache,
+            write_rate_limiter,
+            low_priority_read_limiter,
+            sequence: SequenceTracker::default(),
+            write_lock: Mutex::new(()),
+            last_ttl_check: Mutex::new(Instant::now()),
+            shared_metadata,
+            sst_paths: RwLock::new(sst_paths),
+            sst_epochs: RwLock::new(sst_epochs),
+            sst_file_manager,
+            cpu_usage: BackgroundCpuUsage::default(),
+            epoch,
+            prefetcher,
+            sorted_run_limiter,
+        };
+        if migrate {
+            storage.compact_into_layout(
+                &storage.compaction_lock.lock(),
+                &storage.options.compaction_options,
+            )?;
+        }
+        if reserve_keyspace {
+            column_family::check_reserved_keys_unused(&storage)?;
+        }
+        storage.write_options_file(&storage.options)?;
+        storage.trigger_manifest_compaction()?;
+
+        Ok(storage)
+    }
+
+    /// Replace the records of the manifest with a snapshot of the state they describe, see
+    /// `Manifest::compact`.
+    pub fn compact_manifest(&self) -> Result<()> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(());
+        };
+        let state_lock = self.state_lock.lock();
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let sst_epochs = self.sst_epochs.read();
+        let mut sst_epochs = snapshot
+            .sstables
+            .keys()
+            .map(|id| (*id, sst_epochs.get(id).copied().unwrap_or(self.epoch)))
+            .collect::<Vec<_>>();
+        sst_epochs.sort();
+        let mut data_paths = self
+            .sst_paths
+            .read()
+            .iter()
+            .filter(|(id, _)| snapshot.sstables.contains_key(id))
+            .map(|(id, data_path)| (*id, *data_path))
+            .collect::<Vec<_>>();
+        data_paths.sort();
+        let memtables = std::iter::once(&snapshot.memtable)
+            .chain(&snapshot.imm_memtables)
+            .filter_map(|memtable| Self::parse_wal_file_name(memtable.wal_path()?))
+            .rev()
+            .collect();
+        manifest.compact(
+            &state_lock,
+            ManifestSnapshot {
+                l0_sstables: snapshot.l0_sstables.clone(),
+                levels: snapshot.levels.clone(),
+                compaction_options: self.compaction_controller().options(),
+                sst_epochs,
+                data_paths,
+                memtables,
+            },
+        )
+    }
+
+    /// Compact the manifest if it grew past `max_manifest_size`. Returns whether it was compacted.
+    pub(crate) fn trigger_manifest_compaction(&self) -> Result<bool> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(false);
+        };
+        let limit = self
+            .options
+            .max_manifest_size
+            .max(2 * manifest.compacted_size());
+        if manifest.size() <= limit {
+            return Ok(false);
+        }
+        self.compact_manifest()?;
+        Ok(true)
+    }
+
+    /// Sync the WAL of the current memtable, so that the writes to it survive a crash of the
+    /// machine. The WALs of the immutable memtables are synced as they are frozen.
+    pub fn sync(&self) -> Result<()> {
+        self.state.read().memtable.sync_wal()
+    }
+
+    /// Stop accepting writes after a background task failed, as retrying it blindly (e.g., on a
+    /// full disk or over a corrupted SST) would only pile up more memtables. Cancellations are
+    /// not failures of the storage and are not recorded.
+    pub(crate) fn record_background_error(&self, err: &anyhow::Error) {
+        if is_cancelled_error(err) {
+            return;
+        }
+        let mut background_error = self.background_error.lock();
+        if background_error.is_none() {
+            *background_error = Some(anyhow!("{:#}", err));
+        }
+    }
+
+    pub(crate) fn has_background_error(&self) -> bool {
+        self.background_error.lock().is_some()
+    }
+
+    fn check_background_error(&self) -> Result<()> {
+        if let Some(err) = &*self.background_error.lock() {
+            bail!(
+                "writes are stopped due to a background error, call resume() after fixing it: {:#}",
+                err
+            );
+        }
+        Ok(())
+    }
+
+    /// Flush the pending immutable memtables and clear the background error if that succeeds.
+    /// The error is not locked during the flushes, so that the writes checking it meanwhile fail
+    /// at once instead of waiting for them.
+    pub fn resume(&self) -> Result<()> {
+        if !self.has_background_error() {
+            return Ok(());
+        }
+        while !self.state.read().imm_memtables.is_empty() {
+            self.force_flush_next_imm_memtable()?;
+        }
+        *self.background_error.lock() = None;
+        Ok(())
+    }
+
+    pub fn add_flush_filter(&self, flush_filter: Box<dyn FlushFilter>) {
+        self.flush_filters.write().push(flush_filter);
+    }
+
+    /// Run the flush filters on a stored entry. The expiry time of a TTL is hidden from the
+    /// filters and kept when they change the value.
+    fn filter_flushed_entry(&self, key: &[u8], value: Option<&[u8]>) -> FlushDecision {
+        let flush_filters = self.flush_filters.read();
+        if flush_filters.is_empty() {
+            return FlushDecision::Keep;
+        }
+        let (value, expiry) = match (&self.options.ttl, value) {
+            (Some(_), Some(value)) => {
+                let (value, expiry) = ttl::split_expiry(value);
+                (Some(value), Some(expiry))
+            }
+            (_, value) => (value, None),
+        };
+        let mut changed_value: Option<Bytes> = None;
+        for flush_filter in flush_filters.iter() {
+            let current = changed_value.as_deref().or(value);
+            match flush_filter.filter(key, current) {
+                FlushDecision::Keep => {}
+                FlushDecision::Remove => return FlushDecision::Remove,
+                FlushDecision::ChangeValue(new_value) => changed_value = Some(new_value),
+            }
+        }
+        match (changed_value, expiry) {
+            (None, _) => FlushDecision::Keep,
+            (Some(value), Some(expiry)) => {
+                FlushDecision::ChangeValue(ttl::append_expiry(&value, expiry).into())
+            }
+            (Some(value), None) => FlushDecision::ChangeValue(value),
+        }
+    }
+
+    pub fn add_event_listener(&self, listener: EventListener) {
+        self.event_listeners.write().push(listener);
+    }
+
+    pub fn add_write_observer(&self, observer: Box<dyn WriteObserver>) {
+        self.write_observers.add(observer);
+    }
+
+    pub(crate) fn emit_event(&self, event: LsmEvent) {
+        for listener in self.event_listeners.read().iter() {
+            listener(&event);
+        }
+    }
+
+    /// Forget the keys found absent after keys were added other than by writes, which counts as
+    /// a write for the sessions.
+    pub(crate) fn invalidate_negative_cache(&self) {
+        let sequence = self.sequence.advance();
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.invalidate_all(sequence);
+        }
+    }
+
+    /// Check that the disk can hold the output of a task, emitting an event if it cannot.
+    pub(crate) fn has_disk_space_for(
+        &self,
+        task: BackgroundTask,
+        estimated_size: u64,
+        data_path: usize,
+    ) -> Result<bool> {
+        let available = available_disk_space(self.data_dir(data_path))?;
+        if available >= estimated_size.saturating_add(self.options.disk_space_reserve) {
+            return Ok(true);
+        }
+        self.emit_event(LsmEvent::InsufficientDiskSpace {
+            task,
+            estimated_size,
+            available,
+        });
+        Ok(false)
+    }
+
+    pub(crate) fn range_overlap(
+        user_begin: Bound<&[u8]>,
+        user_end: Bound<&[u8]>,
+        table_begin: &[u8],
+        table_end: &[u8],
+    ) -> bool {
+        match user_end {
+            Bound::Excluded(key) if key <= table_begin => {
+                return false;
+            }
+            Bound::Included(key) if key < table_begin => {
+                return false;
+            }
+            _ => {}
+        }
+        match user_begin {
+            Bound::Excluded(key) if key >= table_end => {
+                return false;
+            }
+            Bound::Included(key) if key > table_end => {
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.get_with_options(key, &ReadOptions::default())
+    }
+
+    /// Get a key as of the snapshot at `read_ts`, which sees the writes up to that timestamp
+    /// only. The compactions drop the versions below the watermark that a later one replaces,
+    /// unless a `Snapshot` at `read_ts` is held.
+    pub fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
+        let options = ReadOptions {
+            read_ts: Some(read_ts),
+            ..Default::default()
+        };
+        self.get_with_options(key, &options)
+    }
+
+    /// Get a key from the storage, only touching the tiers allowed by `options.read_tier`.
+    pub fn get_with_options(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Bytes>> {
+        if let Some(session) = options.session {
+            self.sequence.wait_for(session, options.session_timeout)?;
+        }
+        let _throttle = self
+            .read_throttle(options.priority)
+            .map(io_priority::throttle_reads);
+        self.key_range_counters.record_read(key);
+        // The negative cache only knows about the latest snapshot
+        let Some(negative_cache) = self
+            .negative_cache
+            .as_ref()
+            .filter(|_| options.read_ts.is_none())
+        else {
+            return self.get_unexpired(key, options);
+        };
+        if negative_cache.contains(key) {
+            return Ok(None);
+        }
+        let sequence = self.sequence.token().sequence;
+        let value = self.get_unexpired(key, options)?;
+        if value.is_none() {
+            negative_cache.insert(key, sequence);
+        }
+        Ok(value)
+    }
+
+    /// The limiter of the disk reads at `priority`, if they are throttled.
+    fn read_throttle(&self, priority: IoPriority) -> Option<Arc<RateLimiter>> {
+        (priority == IoPriority::Low).then(|| self.low_priority_read_limiter.clone())
+    }
+
+    /// Get a key, hiding the value if it expired.
+    fn get_unexpired(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Bytes>> {
+        let value = self.get_stored(key, options)?;
+        let Some(ttl_options) = &self.options.ttl else {
+            return Ok(value);
+        };
+        let now = ttl_options.now_millis();
+        Ok(value
+            .filter(|value| !ttl::is_expired(value, now))
+            .map(|value| value.slice(..ttl::split_expiry(&value).0.len())))
+    }
+
+    /// Get the value of a key as stored, i.e., with the expiry time if a TTL is configured.
+    fn get_stored(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Bytes>> {
+        // The timestamp is taken before the state, which then has all the writes up to it. The read
+        // is registered in the watermark meanwhile, so that no compaction drops what it reads.
+        let (read_ts, _reader) = match options.read_ts {
+            Some(read_ts) => (read_ts, None),
+            None => {
+                let reader = self.mvcc().new_reader();
+                (reader.read_ts(), Some(reader))
+            }
+        };
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+
+        // The range tombstones of a memtable or SST delete the key in the older ones only
+        for memtable in std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter()) {
+            if let Some(value) = memtable.get_entry_with_ts(key, read_ts) {
+                return Ok(value);
+            }
+            if memtable.is_range_deleted(key, read_ts) {
+                return Ok(None);
+            }
+        }
+
+        for sst_id in snapshot.l0_sstables.iter() {
+            let table = &snapshot.sstables[sst_id];
+            if table.may_contain_key(key)
+                && let Some(value) =
+                    Self::get_from_sst(table, key, read_ts, options.read_tier, &mut None)?
+            {
+                return Ok(value);
+            }
+            if table.is_range_deleted(key, read_ts) {
+                return Ok(None);
+            }
+        }
+
+        for (_, level_sst_ids) in snapshot.levels.iter() {
+            // SSTs within a level do not overlap, so at most one of them can contain the key. The
+            // last key of an SST may be the exclusive end of its range tombstones and the first
+            // key of the next SST, which is the one to read for that key.
+            let idx = level_sst_ids
+                .partition_point(|id| snapshot.sstables[id].first_key().key_ref() <= key);
+            let Some(sst_id) = idx.checked_sub(1).map(|idx| level_sst_ids[idx]) else {
+                continue;
+            };
+            let table = &snapshot.sstables[&sst_id];
+            if table.may_contain_key(key) {
+                if let Some(value) =
+                    Self::get_from_sst(table, key, read_ts, options.read_tier, &mut None)?
+                {
+                    return Ok(value);
+                }
+                self.record_useless_probe(table);
+            }
+            if table.is_range_deleted(key, read_ts) {
+                return Ok(None);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Count a lookup that read `table` without finding the key, and schedule a seek compaction
+    /// of the table once it reaches `seek_compaction_threshold`.
+    fn record_useless_probe(&self, table: &SsTable) {
+        let Some(threshold) = self.options.seek_compaction_threshold else {
+            return;
+        };
+        if table.record_useless_probe() == threshold {
+            self.seek_compaction_sst
+                .lock()
+                .get_or_insert(table.sst_id());
+        }
+    }
+
+    /// Point lookup of the latest version at or before `read_ts` in a single SST, `Some(None)` if
+    /// the key is deleted. The version can only be in the last block whose first key is smaller
+    /// than or equal to it, or in the next ones if the versions of the key spill over.
+    ///
+    /// `last_block` keeps the last block read from `table`, which is reused if the lookup lands
+    /// in it again, e.g., for the sorted keys of a `multi_get`.
+    fn get_from_sst(
+        table: &SsTable,
+        key: &[u8],
+        read_ts: u64,
+        read_tier: ReadTier,
+        last_block: &mut Option<(usize, Arc<Block>)>,
+    ) -> Result<Option<Option<Bytes>>> {
+        table.find_entry(key, read_ts, |blk_idx| {
+            if let Some((last_idx, block)) = last_block
+                && *last_idx == blk_idx
+            {
+                return Ok(block.clone());
+            }
+            let block = match read_tier {
+                ReadTier::All => table.read_block_cached(blk_idx)?,
+                ReadTier::BlockCacheOnly => table
+                    .read_block_from_cache(blk_idx)
+                    .ok_or_else(would_block)?,
+                ReadTier::MemtableOnly => return Err(would_block()),
+            };
+            *last_block = Some((blk_idx, block.clone()));
+            Ok(block)
+        })
+    }
+
+    /// Get many keys from one snapshot, see `MiniLsm::multi_get`.
+    pub fn multi_get<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Bytes>>> {
+        self.multi_get_with_options(keys, &ReadOptions::default())
+    }
+
+    /// Get many keys from one snapshot with the given read options, returning their values in
+    /// the order of `keys`.
+    pub fn multi_get_with_options<K: AsRef<[u8]>>(
+        &self,
+        keys: &[K],
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Bytes>>> {
+        if let Some(session) = options.session {
+            self.sequence.wait_for(session, options.session_timeout)?;
+        }
+        let _throttle = self
+            .read_throttle(options.priority)
+            .map(io_priority::throttle_reads);
+        let mut sorted_keys = keys.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+        for key in &sorted_keys {
+            self.key_range_counters.record_read(key);
+        }
+
+        // The negative cache only knows about the latest snapshot
+        let negative_cache = self
+            .negative_cache
+            .as_ref()
+            .filter(|_| options.read_ts.is_none());
+        let sequence = self.sequence.token().sequence;
+        let mut values = sorted_keys
+            .iter()
+            .map(|key| {
+                negative_cache
+                    .is_some_and(|negative_cache| negative_cache.contains(key))
+                    .then_some(None)
+            })
+            .collect::<Vec<_>>();
+        self.multi_get_stored(&sorted_keys, &mut values, options)?;
+
+        let expiry_now = self.options.ttl.as_ref().map(TtlOptions::now_millis);
+        let values = sorted_keys
+            .iter()
+            .zip(values)
+            .map(|(key, value)| {
+                let value = value.flatten();
+                if let Some(negative_cache) = negative_cache
+                    && value.is_none()
+                {
+                    negative_cache.insert(key, sequence);
+                }
+                match expiry_now {
+                    Some(now) => value
+                        .filter(|value| !ttl::is_expired(value, now))
+                        .map(|value| value.slice(..ttl::split_expiry(&value).0.len())),
+                    None => value,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let idx = sorted_keys.binary_search(&key.as_ref()).unwrap();
+                values[idx].clone()
+            })
+            .collect())
+    }
+
+    /// Look up the sorted, distinct `keys` as stored, like `get_stored`, filling in the `values`
+    /// that are still `None`, with `Some(None)` for a deleted key. Each SST is probed with the
+    /// keys in order, reading each of its blocks at most once for consecutive keys.
+    fn multi_get_stored(
+        &self,
+        keys: &[&[u8]],
+        values: &mut [Option<Option<Bytes>>],
+        options: &ReadOptions,
+    ) -> Result<()> {
+        let (read_ts, _reader) = match options.read_ts {
+            Some(read_ts) => (read_ts, None),
+            None => {
+                let reader = self.mvcc().new_reader();
+                (reader.read_ts(), Some(reader))
+            }
+        };
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        for memtable in std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter()) {
+            for (&key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_some() {
+                    continue;
+                }
+                if let Some(entry) = memtable.get_entry_with_ts(key, read_ts) {
+                    *value = Some(entry);
+                } else if memtable.is_range_deleted(key, read_ts) {
+                    *value = Some(None);
+                }
+            }
+        }
+
+        for sst_id in snapshot.l0_sstables.iter() {
+            let table = &snapshot.sstables[sst_id];
+            let mut last_block = None;
+            for (&key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_some() {
+                    continue;
+                }
+                if table.may_contain_key(key)
+                    && let Some(entry) =
+                        Self::get_from_sst(table, key, read_ts, options.read_tier, &mut last_block)?
+                {
+                    *value = Some(entry);
+                } else if table.is_range_deleted(key, read_ts) {
+                    *value = Some(None);
+                }
+            }
+        }
+
+        for (_, level_sst_ids) in snapshot.levels.iter() {
+            // The sorted keys visit the SSTs of the level in order, see `get_stored` for which
+            // SST may contain a key
+            let mut last_block = None;
+            let mut last_sst_id = None;
+            for (&key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_some() {
+                    continue;
+                }
+                let idx = level_sst_ids
+                    .partition_point(|id| snapshot.sstables[id].first_key().key_ref() <= key);
+                let Some(sst_id) = idx.checked_sub(1).map(|idx| level_sst_ids[idx]) else {
+                    continue;
+                };
+                if last_sst_id.replace(sst_id) != Some(sst_id) {
+                    last_block = None;
+                }
+                let table = &snapshot.sstables[&sst_id];
+                if table.may_contain_key(key) {
+                    if let Some(entry) =
+                        Self::get_from_sst(table, key, read_ts, options.read_tier, &mut last_block)?
+                    {
+                        *value = Some(entry);
+                        continue;
+                    }
+                    self.record_useless_probe(table);
+                }
+                if table.is_range_deleted(key, read_ts) {
+                    *value = Some(None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a batch of data into the storage atomically, see `MiniLsm::write_batch`.
+    pub fn write_batch<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        self.write_batch_locked(batch)
+    }
+
+    fn write_batch_locked<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
+        for record in batch {
+            match record {
+                WriteBatchRecord::Put(key, value) => {
+                    self.check_entry_size(key.as_ref(), value.as_ref())?
+                }
+                WriteBatchRecord::Del(key) => self.check_entry_size(key.as_ref(), &[])?,
+            }
+        }
+        for record in batch {
+            if let WriteBatchRecord::Put(key, _) = record {
+                self.quotas.check(key.as_ref())?;
+            }
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.stall_for_sorted_runs()?;
+        let num_bytes = self.write_batch_to_memtable(batch)?;
+        let sequence = self.sequence.advance();
+        if let Some(negative_cache) = &self.negative_cache {
+            for record in batch {
+                let (WriteBatchRecord::Put(key, _) | WriteBatchRecord::Del(key)) = record;
+                negative_cache.invalidate(key.as_ref(), sequence);
+            }
+        }
+
+        self.freeze_memtable_if_needed(num_bytes)
+    }
+
+    /// Write the records of a batch to the current memtable in one go, which the memtable cannot
+    /// be frozen in the middle of, at the same timestamp. Returns the size of the memtable.
+    fn write_batch_to_memtable<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+    ) -> Result<usize> {
+        let values_with_expiry = batch
+            .iter()
+            .map(|record| match record {
+                WriteBatchRecord::Put(key, value) => self
+                    .expiry_of(key.as_ref())
+                    .map(|expiry| ttl::append_expiry(value.as_ref(), expiry)),
+                WriteBatchRecord::Del(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let records = batch
+            .iter()
+            .zip(&values_with_expiry)
+            .map(|(record, value_with_expiry)| match record {
+                WriteBatchRecord::Put(key, value) => (
+                    key.as_ref(),
+                    Some(value_with_expiry.as_deref().unwrap_or(value.as_ref())),
+                ),
+                WriteBatchRecord::Del(key) => (key.as_ref(), None),
+            })
+            .collect::<Vec<_>>();
+        let state = self.state.read();
+        let ts = self.mvcc().latest_commit_ts() + 1;
+        state.memtable.write_batch(&records, ts)?;
+        // The batch becomes visible to the reads at once
+        self.mvcc().update_commit_ts(ts);
+        self.mvcc().record_commit(ts, |writes| {
+            (writes.keys).extend(records.iter().map(|(key, _)| Bytes::copy_from_slice(key)))
+        });
+        for (key, value) in &records {
+            let num_bytes = key.len() + value.map_or(0, <[u8]>::len);
+            self.quotas
+                .record_write(state.memtable.id(), key, num_bytes);
+            self.key_range_counters.record_write(key, num_bytes);
+        }
+        Ok(state.memtable.approximate_size())
+    }
+
+    /// Apply the batch if all of its preconditions hold. Returns the indices of the failed
+    /// preconditions, in which case nothing is written.
+    pub fn write_conditional(&self, batch: &WriteBatch) -> Result<Vec<usize>> {
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        let mut failed = Vec::new();
+        for (idx, precondition) in batch.preconditions().iter().enumerate() {
+            let holds = match precondition {
+                Precondition::Absent(key) => self.get(key)?.is_none(),
+                Precondition::ValueMatches(key, expected) => {
+                    self.get(key)?.as_ref() == Some(expected)
+                }
+            };
+            if !holds {
+                failed.push(idx);
+            }
+        }
+        if failed.is_empty() {
+            self.write_batch_locked(batch.records())?;
+        }
+        Ok(failed)
+    }
+
+    /// Write the batch of a committing transaction if `validate` passes, which runs while no
+    /// other write can commit.
+    pub(crate) fn write_batch_validated<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        validate: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        validate()?;
+        self.write_batch_locked(batch)
+    }
+
+    /// Put a key-value pair into the storage by writing into the current memtable.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.put_with_meta(key, value, 0)
+    }
+
+    /// Put a key-value pair with a user metadata byte into the storage.
+    pub fn put_with_meta(&self, key: &[u8], value: &[u8], meta: u8) -> Result<()> {
+        self.check_entry_size(key, value)?;
+        self.check_background_error()?;
+        self.quotas.check(key)?;
+        let _write_lock = self.write_lock.lock();
+        self.write_entry(key, Some(value), meta)
+    }
+
+    /// Remove a key from the storage by writing an empty value.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.check_entry_size(key, &[])?;
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        self.write_entry(key, None, 0)
+    }
+
+    /// Delete the keys from `start` to `end`, exclusive, with a range tombstone in the current
+    /// memtable, see `RangeTombstones`. The compactions drop the deleted keys, and the tombstone
+    /// once it reaches the bottom level.
+    pub fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<()> {
+        if start >= end {
+            bail!(
+                "range delete start {:?} is not before its end {:?}",
+                Bytes::copy_from_slice(start),
+                Bytes::copy_from_slice(end)
+            );
+        }
+        self.check_entry_size(start, &[])?;
+        self.check_entry_size(end, &[])?;
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        self.stall_for_sorted_runs()?;
+        let num_bytes = {
+            let state = self.state.read();
+            let ts = self.mvcc().latest_commit_ts() + 1;
+            state.memtable.delete_range(start, end, ts)?;
+            self.mvcc().update_commit_ts(ts);
+            self.mvcc().record_commit(ts, |writes| {
+                writes.ranges.push((
+                    Bound::Included(Bytes::copy_from_slice(start)),
+                    Bound::Excluded(Bytes::copy_from_slice(end)),
+                ))
+            });
+            state.memtable.approximate_size()
+        };
+        self.sequence.advance();
+
+        self.freeze_memtable_if_needed(num_bytes)
+    }
+
+    /// Take the next timestamp for writes that do not go through the memtable, e.g., the SSTs of
+    /// a bulk import. The writes after it get later timestamps.
+    #[cfg(any(feature = "csv", feature = "parquet"))]
+    pub(crate) fn reserve_ts(&self) -> u64 {
+        let _write_lock = self.write_lock.lock();
+        let ts = self.mvcc().latest_commit_ts() + 1;
+        self.mvcc().update_commit_ts(ts);
+        ts
+    }
+
+    /// Reject the keys and values over `max_key_size` and `max_value_size` before they reach the
+    /// memtable, as the block encoding could not store them.
+    pub(crate) fn check_entry_size(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if key.len() > self.options.max_key_size {
+            return Err(entry_too_large("key", key.len(), self.options.max_key_size));
+        }
+        if value.len() > self.options.max_value_size {
+            return Err(entry_too_large(
+                "value",
+                value.len(),
+                self.options.max_value_size,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write an entry to the current memtable and assign it the next sequence. The caller must
+    /// hold the write lock.
+    fn write_entry(&self, key: &[u8], value: Option<&[u8]>, meta: u8) -> Result<()> {
+        self.stall_for_sorted_runs()?;
+        let num_bytes = self.write_to_memtable(key, value, meta)?;
+        let sequence = self.sequence.advance();
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.invalidate(key, sequence);
+        }
+
+        self.freeze_memtable_if_needed(num_bytes)
+    }
+
+    /// The expiry time of a value put to `key` now, if the storage has a TTL. The keys the storage
+    /// keeps for itself, e.g., the windows of the idempotent writes, never expire, as retried
+    /// operations would be applied again once they did.
+    fn expiry_of(&self, key: &[u8]) -> Option<u64> {
+        let options = self.options.ttl.as_ref()?;
+        match self.options.internal_keyspace && column_family::is_internal_key(key) {
+            true => Some(u64::MAX),
+            false => Some(options.now_millis() + options.ttl.as_millis() as u64),
+        }
+    }
+
+    /// Put a value, or delete the key if `value` is `None`, at the next timestamp. The caller must
+    /// hold the write lock. Returns the size of the memtable.
+    fn write_to_memtable(&self, key: &[u8], value: Option<&[u8]>, meta: u8) -> Result<usize> {
+        let value_with_expiry;
+        let value = match (self.expiry_of(key), value) {
+            (Some(expiry), Some(value)) => {
+                value_with_expiry = ttl::append_expiry(value, expiry);
+                Some(&value_with_expiry[..])
+            }
+            (_, value) => value,
+        };
+        let state = self.state.read();
+        let ts = self.mvcc().latest_commit_ts() + 1;
+        (state.memtable).put_entry(KeySlice::from_slice(key, ts), value, meta)?;
+        self.mvcc().update_commit_ts(ts);
+        self.mvcc().record_commit(ts, |writes| {
+            writes.keys.insert(Bytes::copy_from_slice(key));
+        });
+        let value_len = value.map_or(0, <[u8]>::len);
+        self.quotas
+            .record_write(state.memtable.id(), key, key.len() + value_len);
+        self.key_range_counters
+            .record_write(key, key.len() + value_len);
+        Ok(state.memtable.approximate_size())
+    }
+
+    /// Put a key-value pair, waiting for the write rate limiter first if requested.
+    pub fn put_with_options(&self, key: &[u8], value: &[u8], options: &WriteOptions) -> Result<()> {
+        match options.operation {
+            Some(_) => self.write_batch_with_options(&[WriteBatchRecord::Put(key, value)], options),
+            None => {
+                if options.rate_limited {
+                    self.write_rate_limiter.request(key.len() + value.len());
+                }
+                self.put(key, value)
+            }
+        }
+    }
+
+    /// Delete a key, waiting for the write rate limiter first if requested.
+    pub fn delete_with_options(&self, key: &[u8], options: &WriteOptions) -> Result<()> {
+        match options.operation {
+            Some(_) => self.write_batch_with_options(&[WriteBatchRecord::Del(key)], options),
+            None => {
+                if options.rate_limited {
+                    self.write_rate_limiter.request(key.len());
+                }
+                self.delete(key)
+            }
+        }
+    }
+
+    /// Write a batch atomically with the given write options. A batch with an operation ID is
+    /// skipped if the operation was applied already, and the operation is recorded in the same
+    /// batch otherwise.
+    pub fn write_batch_with_options<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        if options.rate_limited {
+            let num_bytes = batch
+                .iter()
+                .map(|record| match record {
+                    WriteBatchRecord::Put(key, value) => key.as_ref().len() + value.as_ref().len(),
+                    WriteBatchRecord::Del(key) => key.as_ref().len(),
+                })
+                .sum();
+            self.write_rate_limiter.request(num_bytes);
+        }
+        let Some(operation) = options.operation else {
+            return self.write_batch(batch);
+        };
+        ensure!(
+            self.options.internal_keyspace,
+            "idempotent writes need internal_keyspace to store the applied operations"
+        );
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        // No other write can update the window of the client meanwhile
+        let window_key = idempotence::window_key(operation.client_id);
+        let mut window = match self.get(&window_key)? {
+            Some(value) => OperationWindow::decode(&value)?,
+            None => OperationWindow::default(),
+        };
+        if !window.insert(operation, self.options.idempotent_write_window)? {
+            return Ok(());
+        }
+        let window_value = window.encode();
+        let records = batch
+            .iter()
+            .map(|record| match record {
+                WriteBatchRecord::Put(key, value) => {
+                    WriteBatchRecord::Put(key.as_ref(), value.as_ref())
+                }
+                WriteBatchRecord::Del(key) => WriteBatchRecord::Del(key.as_ref()),
+            })
+            .chain(std::iter::once(WriteBatchRecord::Put(
+                &window_key[..],
+                &window_value[..],
+            )))
+            .collect::<Vec<_>>();
+        self.write_batch_locked(&records)
+    }
+
+    /// Apply a write replicated from the primary and advance the applied sequence to its
+    /// sequence. The write is not subject to the quotas, as the primary already accepted it.
+    pub fn apply_replicated(&self, sequence: u64, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        self.check_entry_size(key, value.unwrap_or_default())?;
+        self.check_background_error()?;
+        let _write_lock = self.write_lock.lock();
+        self.stall_for_sorted_runs()?;
+        let num_bytes = self.write_to_memtable(key, value, 0)?;
+        self.sequence.advance_to(sequence);
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.invalidate(key, self.sequence.token().sequence);
+        }
+
+        self.freeze_memtable_if_needed(num_bytes)
+    }
+
+    /// Block while `max_sorted_runs` holds the flushes back and `num_memtable_limit` immutable
+    /// memtables are waiting, until a compaction lets the next flush through.
+    fn stall_for_sorted_runs(&self) -> Result<()> {
+        let Some(limiter) = &self.sorted_run_limiter else {
+            return Ok(());
+        };
+        let must_stall = || {
+            let state = self.state.read();
+            state.imm_memtables.len() >= self.options.num_memtable_limit && limiter.at_bound(&state)
+        };
+        if !must_stall() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let result = loop {
+            if let Err(e) = self.check_background_error() {
+                break Err(e);
+            }
+            let next_flush = self.flush_listeners.subscribe();
+            if !must_stall() {
+                break Ok(());
+            }
+            // Also poll, as the flush may finish before we subscribe to it
+            if let Some(Err(e)) = next_flush.wait_timeout(POLL_INTERVAL) {
+                break Err(e);
+            }
+        };
+        limiter.record_stall(start.elapsed());
+        result
+    }
+
+    fn freeze_memtable_if_needed(&self, approximate_size: usize) -> Result<()> {
+        if approximate_size >= self.options.target_sst_size {
+            // Acquire state mutex to prevent concurrent freezers
+            let state_lock = self.state_lock.lock();
+
+            // Acquire read lock on the state
+            let state = self.state.read();
+
+            // Recheck if the memtable is needed to be frozen
+            if state.memtable.approximate_size() >= self.options.target_sst_size {
+                drop(state);
+                self.force_freeze_memtable(&state_lock)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn new_sst_builder(&self) -> SsTableBuilder {
+        let mut builder = SsTableBuilder::new(self.options.block_size)
+            .with_restart_interval(self.options.block_restart_interval)
+            .with_compression(self.options.block_compression)
+            .with_write_observers(self.write_observers.clone());
+        if self.quotas.is_enabled() {
+            builder = builder.with_tenant_prefixes(self.quotas.prefixes());
+        }
+        if self.options.ttl.is_some() {
+            builder = builder.with_expiry_tracking();
+        }
+        if self.options.verify_key_order {
+            builder = builder.with_key_order_check();
+        }
+        if let Some(chunk_size) = self.options.sst_sync_chunk_size {
+            builder = builder.with_sync_chunk_size(chunk_size);
+        }
+        if let Some(bits_per_key) = self.options.bloom_bits_per_key {
+            builder = builder.with_bloom_bits_per_key(bits_per_key);
+        }
+        if let Some(extractor) = &self.options.prefix_extractor {
+            builder = builder.with_prefix_extractor(extractor.clone());
+        }
+        match self.options.max_block_size {
+            Some(max_block_size) => builder.with_max_block_size(max_block_size),
+            None => builder,
+        }
+    }
+
+    pub(crate) fn path_of_sst_static(path: impl AsRef<Path>, epoch: u64, id: usize) -> PathBuf {
+        path.as_ref().join(format!("{:05}-{}.sst", id, epoch))
+    }
+
+    /// The id and the epoch of an SST file.
+    pub(crate) fn parse_sst_file_name(path: &Path) -> Option<(usize, u64)> {
+        Self::parse_file_name(path, ".sst")
+    }
+
+    /// The id and the epoch of a WAL file.
+    fn parse_wal_file_name(path: &Path) -> Option<(usize, u64)> {
+        Self::parse_file_name(path, ".wal")
+    }
+
+    fn parse_file_name(path: &Path, suffix: &str) -> Option<(usize, u64)> {
+        let name = path.file_name()?.to_str()?.strip_suffix(suffix)?;
+        let (id, epoch) = name.split_once('-')?;
+        Some((id.parse().ok()?, epoch.parse().ok()?))
+    }
+
+    /// The epoch of this process, bumped every time the directory is opened.
+    #[cfg(test)]
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub(crate) fn path_of_sst(&self, id: usize) -> PathBuf {
+        let data_path = self.sst_paths.read().get(&id).copied().unwrap_or(0);
+        let epoch = self
+            .sst_epochs
+            .read()
+            .get(&id)
+            .copied()
+            .unwrap_or(self.epoch);
+        Self::path_of_sst_static(self.data_dir(data_path), epoch, id)
+    }
+
+    /// The directory of the `data_path`-th entry of `data_paths`, or the main directory.
+    pub(crate) fn data_dir(&self, data_path: usize) -> &Path {
+        match self.options.data_paths.get(data_path) {
+            Some(data_path) => &data_path.path,
+            None => &self.path,
+        }
+    }
+
+    /// The index in `data_paths` of the directory holding an SST.
+    pub fn data_path_of_sst(&self, id: usize) -> usize {
+        self.sst_paths.read().get(&id).copied().unwrap_or(0)
+    }
+
+    /// The total size of the SSTs of `snapshot` in each of `data_paths`.
+    pub(crate) fn data_path_usage(&self, snapshot: &LsmStorageState) -> Vec<u64> {
+        let mut usage = vec![0; self.options.data_paths.len().max(1)];
+        let sst_paths = self.sst_paths.read();
+        for (id, sst) in &snapshot.sstables {
+            usage[sst_paths.get(id).copied().unwrap_or(0)] += sst.table_size();
+        }
+        usage
+    }
+
+    /// The first data path with room for `estimated_size` more bytes, or the last one.
+    pub(crate) fn data_path_for(&self, snapshot: &LsmStorageState, estimated_size: u64) -> usize {
+        let data_paths = &self.options.data_paths;
+        let usage = self.data_path_usage(snapshot);
+        data_paths
+            .iter()
+            .zip(usage)
+            .position(|(data_path, used)| used + estimated_size <= data_path.target_size)
+            .unwrap_or(data_paths.len().saturating_sub(1))
+    }
+
+    /// Build an SST in the given data path and publish its metadata if
+    /// `shared_metadata_namespace` is set.
+    pub(crate) fn build_sst(
+        &self,
+        builder: SsTableBuilder,
+        id: usize,
+        data_path: usize,
+    ) -> Result<Arc<SsTable>> {
+        if data_path != 0 {
+            self.sst_paths.write().insert(id, data_path);
+        }
+        let sst = builder
+            .build(id, Some(self.block_cache.clone()), self.path_of_sst(id))?
+            .with_checksum_verification(self.options.verify_block_checksums);
+        if let Some(shared_metadata) = &self.shared_metadata {
+            sst.publish_metadata(shared_metadata)?;
+        }
+        Ok(Arc::new(sst))
+    }
+
+    /// Sync the SSTs built with `sst_sync_chunk_size`, which `build_sst` left unsynced. SSTs
+    /// built without it were synced as they were written.
+    pub(crate) fn sync_new_ssts(&self, ssts: &[Arc<SsTable>]) -> Result<()> {
+        if self.options.sst_sync_chunk_size.is_some() {
+            for sst in ssts {
+                sst.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the file of an SST that is no longer part of the state, and its shared or pooled
+    /// metadata.
+    /// The file is moved to trash if `sst_delete_rate` is set.
+    pub(crate) fn remove_sst_file(&self, id: usize) -> Result<()> {
+        self.sst_file_manager.delete_file(&self.path_of_sst(id))?;
+        self.sst_paths.write().remove(&id);
+        self.sst_epochs.write().remove(&id);
+        if let Some(shared_metadata) = &self.shared_metadata {
+            shared_metadata.remove(id)?;
+        }
+        self.block_cache.remove_metadata(id);
+        Ok(())
+    }
+
+    pub(crate) fn path_of_wal_static(path: impl AsRef<Path>, epoch: u64, id: usize) -> PathBuf {
+        path.as_ref().join(format!("{:05}-{}.wal", id, epoch))
+    }
+
+    pub(crate) fn path_of_wal(&self, id: usize) -> PathBuf {
+        Self::path_of_wal_static(&self.path, self.epoch, id)
+    }
+
+    /// Record the options that shape the files on disk, see `StoredOptions`.
+    pub(crate) fn write_options_file(&self, options: &LsmStorageOptions) -> Result<()> {
+        StoredOptions::new(options).write(&self.path)?;
+        self.sync_dir()
+    }
+
+    pub(super) fn sync_dir(&self) -> Result<()> {
+        std::fs::File::open(&self.path)?.sync_all()?;
+        for data_path in &self.options.data_paths {
+            std::fs::File::open(&data_path.path)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Force freeze the current memtable to an immutable memtable
+    pub fn force_freeze_memtable(&self, state_lock_observer: &MutexGuard<'_, ()>) -> Result<()> {
+        let memtable_id = self.next_sst_id();
+        let new_memtable = Arc::new(
+            match self.options.enable_wal {
+                true => MemTable::create_with_wal(memtable_id, self.path_of_wal(memtable_id))?
+                    .with_write_observers(self.write_observers.clone()),
+                false => MemTable::create(memtable_id),
+            }
+            .with_append_log(self.options.sequential_writes),
+        );
+        if let Some(manifest) = &self.manifest
+            && self.options.enable_wal
+        {
+            manifest.add_record(
+                state_lock_observer,
+                ManifestRecord::NewMemtable(memtable_id),
+            )?;
+        }
+        let old_memtable = {
+            // Acquire write lock
+            let mut state = self.state.write();
+
+            // Swap the current memtable with a new one
+            let mut snapshot = state.as_ref().clone();
+            let old_memtable = replace(&mut snapshot.memtable, new_memtable);
 
             // Push the old memtable to the front of the imm_memtables list
-            snapshot.imm_memtables.insert(0, old_memtable);
+            snapshot.imm_memtables.insert(0, old_memtable.clone());
 
             // Replace the state with the new updated snapshot
             *state = Arc::new(snapshot);
-        }
+            old_memtable
+        };
+        old_memtable.sync_wal()?;
         Ok(())
     }
 
@@ -486,91 +3050,428 @@ impl LsmStorageInner {
             let state = self.state.read();
             Arc::clone(&state)
         };
-        let flush_memtable = snapshot
-            .imm_memtables
-            .last()
-            .expect("No immutable memtable found")
-            .clone();
-        let mut builder = SsTableBuilder::new(self.options.block_size);
-        flush_memtable.flush(&mut builder)?;
-
+        // The flush thread may have flushed the memtable before we acquired the state lock
+        let Some(flush_memtable) = snapshot.imm_memtables.last().cloned() else {
+            return Ok(());
+        };
         let sst_id = flush_memtable.id();
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
-
+        let sst = self.cpu_usage.measure(BackgroundTask::Flush, || {
+            let mut builder = self.new_sst_builder();
+            let watermark = self.mvcc().watermark();
+            flush_memtable.flush_filtered(&mut builder, watermark, |key, value| {
+                self.filter_flushed_entry(key, value)
+            })?;
+            // The flush filters may have removed all entries
+            if builder.is_empty() {
+                return Ok(None);
+            }
+            let sst = self.build_sst(builder, sst_id, 0)?;
+            self.sync_new_ssts(std::slice::from_ref(&sst))?;
+            Ok(Some(sst))
+        })?;
+        let flushed = sst.is_some();
+
+        // A running compaction may install its output in any level, so the flush only goes past
+        // L0 while none runs, which the compaction lock guarantees until the flush is recorded
+        let compaction_lock = match &sst {
+            Some(_) if self.options.flush_to_base_level => self.compaction_lock.try_lock(),
+            _ => None,
+        };
+        let flush_level = match (&sst, &compaction_lock) {
+            (Some(sst), Some(_)) => self.flush_level(&self.state.read(), sst),
+            _ => None,
+        };
+        let flush_to_l0 = self.compaction_controller().flush_to_l0();
         {
             let mut state = self.state.write();
             let mut snapshot = state.as_ref().clone();
 
-            let memtable = snapshot.imm_memtables.pop().unwrap();
-
-            snapshot.l0_sstables.insert(0, sst_id);
-            snapshot.sstables.insert(sst_id, sst);
+            snapshot.imm_memtables.pop();
+
+            if let Some(sst) = sst {
+                match flush_level {
+                    Some(level) => {
+                        let level_sst_ids = &mut snapshot.levels[level - 1].1;
+                        let idx = level_sst_ids.partition_point(|id| {
+                            snapshot.sstables[id].first_key() < sst.first_key()
+                        });
+                        level_sst_ids.insert(idx, sst_id);
+                        sst.set_level(level);
+                    }
+                    None => snapshot.add_flushed_sst(sst_id, flush_to_l0),
+                }
+                snapshot.sstables.insert(sst_id, sst);
+                snapshot.update_sst_levels();
+            }
 
+            self.quotas.refresh(&snapshot);
+            self.key_distribution.refresh(&snapshot);
             *state = Arc::new(snapshot);
         }
 
+        if let Some(manifest) = &self.manifest
+            && flushed
+        {
+            let record = match flush_level {
+                Some(level) => ManifestRecord::FlushToLevel(sst_id, level),
+                None => ManifestRecord::Flush(sst_id),
+            };
+            manifest.add_record(&state_lock, record)?;
+        }
+        drop(compaction_lock);
+        // The entries are in the SST now, or were all filtered out
+        if let Some(wal_path) = flush_memtable.wal_path() {
+            std::fs::remove_file(wal_path)?;
+        }
+
         Ok(())
     }
 
-    pub fn new_txn(&self) -> Result<()> {
-        // no-op
+    /// The level to flush `sst` into past L0, if L0 is empty and no SST down to the level the
+    /// compaction controller would compact L0 into overlaps with it.
+    fn flush_level(&self, snapshot: &LsmStorageState, sst: &SsTable) -> Option<usize> {
+        if !snapshot.l0_sstables.is_empty() {
+            return None;
+        }
+        let level = self.compaction_controller().l0_target_level(snapshot)?;
+        let overlaps = snapshot.levels[..level]
+            .iter()
+            .flat_map(|(_, sst_ids)| sst_ids.iter())
+            .any(|id| {
+                snapshot.sstables[id]
+                    .overlaps_range(sst.first_key().key_ref(), Some(sst.last_key().key_ref()))
+            });
+        (!overlaps).then_some(level)
+    }
+
+    /// Freeze the current memtable and flush all immutable memtables to disk.
+    pub fn force_flush_all(&self) -> Result<()> {
+        if !self.state.read().memtable.is_empty() {
+            self.force_freeze_memtable(&self.state_lock.lock())?;
+        }
+        while !self.state.read().imm_memtables.is_empty() {
+            self.force_flush_next_imm_memtable()?;
+        }
         Ok(())
     }
 
+    /// Start a transaction reading at the latest commit timestamp.
+    pub fn new_txn(self: &Arc<Self>) -> Result<Arc<Transaction>> {
+        self.check_background_error()?;
+        Ok(self.mvcc().new_txn(self.clone(), self.options.serializable))
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let state_lock = self.state_lock.lock();
+        if !self.state.read().memtable.is_empty() {
+            self.force_freeze_memtable(&state_lock)?;
+        }
+        // Writes after the snapshot go to the current memtable, which the snapshot leaves out
+        let reader = self.mvcc().new_reader();
+        let mut snapshot = self.state.read().as_ref().clone();
+        snapshot.memtable = Arc::new(MemTable::create(snapshot.memtable.id()));
+        Ok(Snapshot::new(
+            Arc::new(snapshot),
+            self.options.clone(),
+            reader,
+        ))
+    }
+
     /// Create an iterator over a range of keys.
     pub fn scan(
         &self,
-        _lower: Bound<&[u8]>,
-        _upper: Bound<&[u8]>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let reader = self.mvcc().new_reader();
+        self.scan_with_prefix(lower, upper, None, reader.read_ts(), None)
+    }
+
+    /// Create an iterator over a range of keys with the read timestamp, session and I/O priority
+    /// of `options`. Scans always read all tiers, so `options.read_tier` must be `All`.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        ensure!(
+            options.read_tier == ReadTier::All,
+            "scans cannot be limited to a read tier"
+        );
+        if let Some(session) = options.session {
+            self.sequence.wait_for(session, options.session_timeout)?;
+        }
+        let reader = self.mvcc().new_reader();
+        let read_ts = options.read_ts.unwrap_or(reader.read_ts());
+        let throttle = self.read_throttle(options.priority);
+        self.scan_with_prefix(lower, upper, None, read_ts, throttle)
+    }
+
+    /// Create an iterator over a range of keys as of the snapshot at `read_ts`, which sees the
+    /// writes up to that timestamp only, as long as they are kept, see `get_with_ts`.
+    pub fn scan_with_ts(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_with_prefix(lower, upper, None, read_ts, None)
+    }
+
+    /// Create an iterator over the keys starting with `prefix`.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<FusedIterator<LsmIterator>> {
+        let upper = prefix_upper_bound(prefix);
+        let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let reader = self.mvcc().new_reader();
+        self.scan_with_prefix(
+            Bound::Included(prefix),
+            upper,
+            Some(prefix),
+            reader.read_ts(),
+            None,
+        )
+    }
+
+    /// Scan the keys the storage keeps for itself, e.g., the registry of the column families,
+    /// without counting the read in the key range stats.
+    pub(crate) fn scan_internal(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let reader = self.mvcc().new_reader();
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let iter = Self::scan_state(
+            &snapshot,
+            lower,
+            upper,
+            None,
+            reader.read_ts(),
+            &self.options,
+            None,
+            false,
+        )?;
+        Ok(FusedIterator::new(iter))
+    }
+
+    /// Create an iterator over a range of keys at `read_ts`, which all start with `prefix` if it
+    /// is set. The timestamp must be taken before the state, so that the state has all the
+    /// writes up to it. The disk reads of the scan wait for `throttle` if set, and are not
+    /// prefetched in the background then.
+    fn scan_with_prefix(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        prefix: Option<&[u8]>,
+        read_ts: u64,
+        throttle: Option<Arc<RateLimiter>>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let first_key = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => &[],
+        };
+        self.key_range_counters.record_read(first_key);
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let prefetch = self
+            .prefetcher
+            .as_ref()
+            .filter(|_| throttle.is_none())
+            .map(|prefetcher| prefetcher.new_scan(self.options.scan_readahead));
+        let _throttle = throttle.clone().map(io_priority::throttle_reads);
+        let mut iter = Self::scan_state(
+            &snapshot,
+            lower,
+            upper,
+            prefix,
+            read_ts,
+            &self.options,
+            prefetch,
+            false,
+        )?;
+        if self.options.deletion_compaction.is_some() {
+            iter = iter.with_deletion_collector(self.deletion_collector.clone());
+        }
+        if let Some(throttle) = throttle {
+            iter = iter.with_read_throttle(throttle);
+        }
+        Ok(FusedIterator::new(iter).with_misuse_errors(self.options.iterator_misuse_errors))
+    }
+
+    /// Create an iterator over a range of keys in descending order, from the last key within
+    /// `upper` down to `lower`. The block prefetches of forward scans are not used.
+    pub fn scan_rev(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
     ) -> Result<FusedIterator<LsmIterator>> {
+        let last_key = match upper {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => &[],
+        };
+        self.key_range_counters.record_read(last_key);
+        let reader = self.mvcc().new_reader();
         let snapshot = {
             let state = self.state.read();
             Arc::clone(&state)
         };
+        let iter = Self::scan_state(
+            &snapshot,
+            lower,
+            upper,
+            None,
+            reader.read_ts(),
+            &self.options,
+            None,
+            true,
+        )?;
+        Ok(FusedIterator::new(iter).with_misuse_errors(self.options.iterator_misuse_errors))
+    }
 
+    /// Create an iterator over a range of keys in the given state at `read_ts`, prefetching the
+    /// blocks of the SSTs with `prefetch` if set. The keys are in descending order if `reverse`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn scan_state(
+        snapshot: &LsmStorageState,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        prefix: Option<&[u8]>,
+        read_ts: u64,
+        options: &LsmStorageOptions,
+        prefetch: Option<Arc<ScanPrefetch>>,
+        reverse: bool,
+    ) -> Result<LsmIterator> {
+        // Each memtable and SST hides the keys deleted by the range tombstones of the newer ones
+        let mut newer_tombstones = Arc::new(RangeTombstones::new());
+        let mut hide_deleted_ranges = |tombstones: &RangeTombstones| {
+            let newer = newer_tombstones.clone();
+            if !tombstones.is_empty() {
+                let mut merged = RangeTombstones::clone(&newer);
+                merged.extend(tombstones);
+                newer_tombstones = Arc::new(merged);
+            }
+            newer
+        };
         let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
-        memtable_iters.push(Box::new(snapshot.memtable.scan(_lower, _upper)));
-        for memtable in snapshot.imm_memtables.iter() {
-            memtable_iters.push(Box::new(memtable.scan(_lower, _upper)));
+        for memtable in std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter()) {
+            let tombstones = hide_deleted_ranges(&memtable.range_tombstones());
+            memtable_iters.push(Box::new(if reverse {
+                RangeDeleteIterator::new_rev(memtable.scan_rev(lower, upper), tombstones, read_ts)?
+            } else {
+                RangeDeleteIterator::new(memtable.scan(lower, upper), tombstones, read_ts)?
+            }));
         }
 
-        let memtable_iter = MergeIterator::create(memtable_iters);
-        let mut sst_iters = Vec::with_capacity(snapshot.l0_sstables.len());
-        for sst_id in snapshot.l0_sstables.iter() {
-            let table = snapshot.sstables[sst_id].clone();
-            if Self::range_overlap(
-                _lower,
-                _upper,
-                table.first_key().raw_ref(),
-                table.last_key().raw_ref(),
-            ) {
-                let iter = match _lower {
-                    Bound::Included(key) => {
-                        SsTableIterator::create_and_seek_to_key(table, Key::from_slice(key))?
-                    }
-                    Bound::Excluded(key) => {
-                        let mut iter =
-                            SsTableIterator::create_and_seek_to_key(table, Key::from_slice(key))?;
-                        if iter.is_valid() && iter.key() == Key::from_slice(key) {
-                            iter.next()?;
-                        }
-                        iter
-                    }
-                    Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table)?,
-                };
-                sst_iters.push(Box::new(iter));
+        let memtable_iter = match reverse {
+            true => MergeIterator::create_rev(memtable_iters),
+            false => MergeIterator::create(memtable_iters),
+        };
+        // The prefix bloom filters only rule out a prefix that is a whole extracted prefix
+        let prefix_filter = prefix
+            .zip(options.prefix_extractor.as_deref())
+            .filter(|(prefix, extractor)| extractor.extract(prefix) == Some(*prefix));
+        // L0 SSTs come first so that the merge iterator prefers them over the lower levels. Each
+        // L0 SST is a sorted run on its own, while the SSTs of a level form one sorted run that a
+        // single concat iterator reads one SST after another.
+        let overlapping = |sst_ids: &[usize]| {
+            sst_ids
+                .iter()
+                .map(|sst_id| snapshot.sstables[sst_id].clone())
+                .filter(|table| {
+                    Self::range_overlap(
+                        lower,
+                        upper,
+                        table.first_key().key_ref(),
+                        table.last_key().key_ref(),
+                    ) && prefix_filter.is_none_or(|(prefix, extractor)| {
+                        table.may_contain_prefix(prefix, extractor)
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut runs = overlapping(&snapshot.l0_sstables)
+            .into_iter()
+            .map(|table| vec![table])
+            .collect::<Vec<_>>();
+        runs.extend(
+            snapshot
+                .levels
+                .iter()
+                .map(|(_, level_sst_ids)| overlapping(level_sst_ids))
+                .filter(|tables| !tables.is_empty()),
+        );
+
+        let budget = options.scan_memory_budget;
+        if let Some(max) = options.max_pinned_blocks_per_iterator
+            && runs.len() > max
+        {
+            bail!(
+                "scan would pin {} blocks at once, one for each L0 SST and level, exceeding \
+                 max_pinned_blocks_per_iterator of {}",
+                runs.len(),
+                max
+            );
+        }
+
+        let memory = budget.map(|budget| Arc::new(ScanMemory::new(budget)));
+        let mut sst_iters = Vec::with_capacity(runs.len());
+        for run in runs {
+            let mut tombstones = RangeTombstones::new();
+            for table in &run {
+                tombstones.extend(table.range_tombstones());
+            }
+            let newer_tombstones = hide_deleted_ranges(&tombstones);
+            if reverse {
+                let iter =
+                    SstConcatIterator::create_and_seek_to_bound_rev(run, upper, memory.clone())?;
+                sst_iters.push(Box::new(RangeDeleteIterator::new_rev(
+                    iter,
+                    newer_tombstones,
+                    read_ts,
+                )?));
+                continue;
             }
+            let iter = SstConcatIterator::create_and_seek_to_bound(
+                run,
+                lower,
+                prefetch.clone(),
+                memory.clone(),
+            )?;
+            sst_iters.push(Box::new(RangeDeleteIterator::new(
+                iter,
+                newer_tombstones,
+                read_ts,
+            )?));
         }
 
-        let sst_iter = MergeIterator::create(sst_iters);
-        let iter = TwoMergeIterator::create(memtable_iter, sst_iter)?;
-        Ok(FusedIterator::new(LsmIterator::new(
-            iter,
-            map_bound(_upper),
-        )?))
+        let expiry_now = options.ttl.as_ref().map(TtlOptions::now_millis);
+        let mut iter = if reverse {
+            let sst_iter = MergeIterator::create_rev(sst_iters);
+            let iter = TwoMergeIterator::create_rev(memtable_iter, sst_iter)?;
+            LsmIterator::new_rev(iter, map_bound(lower), read_ts, expiry_now)?
+        } else {
+            let sst_iter = MergeIterator::create(sst_iters);
+            let iter = TwoMergeIterator::create(memtable_iter, sst_iter)?;
+            LsmIterator::new(
+                iter,
+                map_bound(lower),
+                map_bound(upper),
+                read_ts,
+                expiry_now,
+            )?
+        };
+        if let Some(prefetch) = prefetch {
+            iter = iter.with_prefetch(prefetch);
+        }
+        if let Some(memory) = memory {
+            iter = iter.with_memory(memory)?;
+        }
+        Ok(iter)
     }
 }
@@ -15,34 +15,233 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
-use std::fs::File;
-use std::path::Path;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 
-use crate::compact::CompactionTask;
+use crate::compact::{CompactionOptions, CompactionTask};
+use crate::write_observer::{FileKind, WriteObservers};
 
+/// The default of `LsmStorageOptions::max_manifest_size`.
+pub const DEFAULT_MAX_MANIFEST_SIZE: u64 = 64 << 20;
+
+/// The manifest. Each record is tagged with the epoch of the process that wrote it, which is
+/// bumped on every open, so that records appended by a stale process after another one opened
+/// the directory (e.g., a zombie after a failover) are ignored on recovery.
+///
+/// The records only grow, so `compact` replaces them with a snapshot of the state they describe
+/// once there are too many, see `LsmStorageOptions::max_manifest_size`.
 pub struct Manifest {
     file: Arc<Mutex<File>>,
+    path: PathBuf,
+    /// The size of the file, including the records of stale epochs.
+    size: AtomicU64,
+    /// The size of the file after the last `compact`.
+    compacted_size: AtomicU64,
+    epoch: u64,
+    /// Notified of the bytes of each record as it is appended.
+    observers: Option<Arc<WriteObservers>>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum ManifestRecord {
     Flush(usize),
+    /// The memtable with the given id was flushed past L0, into the given level, see
+    /// `LsmStorageOptions::flush_to_base_level`.
+    FlushToLevel(usize, usize),
     NewMemtable(usize),
     Compaction(CompactionTask, Vec<usize>),
+    /// The SST with the given id was placed in the given entry of `data_paths`. SSTs without
+    /// this record are in the first data path.
+    DataPath(usize, usize),
+    /// A process opened the directory for writing with this epoch.
+    NewEpoch(u64),
+    /// The compaction strategy changed, and the levels were laid out for it.
+    CompactionStrategy(CompactionOptions),
+    /// The state described by the records before, which `Manifest::compact` replaced.
+    Snapshot(ManifestSnapshot),
+}
+
+/// The state of the directory when the manifest was compacted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestSnapshot {
+    /// The L0 SSTs, from the latest to the earliest.
+    pub l0_sstables: Vec<usize>,
+    /// The SSTs of each level, or of each tier, laid out for `compaction_options`.
+    pub levels: Vec<(usize, Vec<usize>)>,
+    pub compaction_options: CompactionOptions,
+    /// The epoch each SST was created in.
+    pub sst_epochs: Vec<(usize, u64)>,
+    /// The SSTs that are not in the first data path, see `ManifestRecord::DataPath`.
+    pub data_paths: Vec<(usize, usize)>,
+    /// The memtables that were not flushed, with the epoch their WAL was created in.
+    pub memtables: Vec<(usize, u64)>,
+}
+
+/// A record with the epoch of the process that wrote it.
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub epoch: u64,
+    pub record: ManifestRecord,
 }
 
 impl Manifest {
+    /// Create a manifest starting with epoch 1.
     pub fn create(_path: impl AsRef<Path>) -> Result<Self> {
-        unimplemented!()
+        let file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .append(true)
+            .open(&_path)
+            .context("failed to create manifest")?;
+        let manifest = Self {
+            file: Arc::new(Mutex::new(file)),
+            path: _path.as_ref().to_path_buf(),
+            size: AtomicU64::new(0),
+            compacted_size: AtomicU64::new(0),
+            epoch: 1,
+            observers: None,
+        };
+        manifest.add_record_when_init(ManifestRecord::NewEpoch(1))?;
+        Ok(manifest)
+    }
+
+    /// Recover the records of a manifest, skipping the records of stale epochs, and start a new
+    /// epoch.
+    pub fn recover(_path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestEntry>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&_path)
+            .context("failed to recover manifest")?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let (epoch, entries) = Self::decode_entries(&buf)?;
+        let manifest = Self {
+            file: Arc::new(Mutex::new(file)),
+            path: _path.as_ref().to_path_buf(),
+            size: AtomicU64::new(buf.len() as u64),
+            compacted_size: AtomicU64::new(0),
+            epoch: epoch + 1,
+            observers: None,
+        };
+        manifest.add_record_when_init(ManifestRecord::NewEpoch(epoch + 1))?;
+        Ok((manifest, entries))
+    }
+
+    /// Read the records of a manifest like `recover`, without opening it for writing.
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<ManifestEntry>> {
+        let buf = std::fs::read(path).context("failed to read manifest")?;
+        Ok(Self::decode_entries(&buf)?.1)
+    }
+
+    /// Decode the records, skipping the records of stale epochs. Returns the latest epoch too.
+    fn decode_entries(buf: &[u8]) -> Result<(u64, Vec<ManifestEntry>)> {
+        let mut epoch = 0;
+        let mut entries = Vec::new();
+        for entry in serde_json::Deserializer::from_slice(buf).into_iter::<ManifestEntry>() {
+            let entry = entry?;
+            if let ManifestRecord::NewEpoch(new_epoch) = entry.record {
+                epoch = epoch.max(new_epoch);
+            } else if entry.epoch < epoch {
+                // Written by a process that was fenced off by a newer epoch
+                continue;
+            }
+            entries.push(entry);
+        }
+        Ok((epoch, entries))
+    }
+
+    /// Replace the manifest at `path` atomically with one holding `entries`. Returns the size
+    /// of the new manifest.
+    pub fn rewrite(path: impl AsRef<Path>, entries: &[ManifestEntry]) -> Result<u64> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut buf = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut buf, entry)?;
+        }
+        std::fs::write(&tmp_path, &buf)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(buf.len() as u64)
+    }
+
+    /// Replace the records with the epoch of this process and `snapshot`, the state they
+    /// describe. Fails if another process opened the directory since, as its records would be
+    /// lost.
+    pub fn compact(
+        &self,
+        _state_lock_observer: &MutexGuard<()>,
+        snapshot: ManifestSnapshot,
+    ) -> Result<()> {
+        let mut file = self.file.lock();
+        let (latest_epoch, _) = Self::decode_entries(&std::fs::read(&self.path)?)?;
+        if latest_epoch > self.epoch {
+            bail!(
+                "epoch {} was fenced off by epoch {}, not compacting the manifest",
+                self.epoch,
+                latest_epoch
+            );
+        }
+        let entries = [
+            ManifestRecord::NewEpoch(self.epoch),
+            ManifestRecord::Snapshot(snapshot),
+        ]
+        .map(|record| ManifestEntry {
+            epoch: self.epoch,
+            record,
+        });
+        let size = Self::rewrite(&self.path, &entries)?;
+        if let Some(dir) = self.path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        *file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size.store(size, Ordering::Relaxed);
+        self.compacted_size.store(size, Ordering::Relaxed);
+        if let Some(observers) = &self.observers {
+            observers.notify(FileKind::Manifest, size);
+        }
+        Ok(())
+    }
+
+    /// Notify `observers` of the records appended from now on.
+    pub fn with_write_observers(mut self, observers: Arc<WriteObservers>) -> Self {
+        self.observers = Some(observers);
+        self
+    }
+
+    /// The epoch of this process.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
     }
 
-    pub fn recover(_path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestRecord>)> {
-        unimplemented!()
+    /// The size of the manifest file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// The size of the manifest file right after the last `compact`, 0 if it was not compacted
+    /// by this process.
+    pub fn compacted_size(&self) -> u64 {
+        self.compacted_size.load(Ordering::Relaxed)
+    }
+
+    /// Flush the records to the disk. Each record is synced as it is added, so this only matters
+    /// for a caller that needs the file on disk before going on, like `MiniLsm::close`.
+    pub fn sync(&self) -> Result<()> {
+        self.file.lock().sync_all()?;
+        Ok(())
     }
 
     pub fn add_record(
@@ -54,6 +253,48 @@ impl Manifest {
     }
 
     pub fn add_record_when_init(&self, _record: ManifestRecord) -> Result<()> {
-        unimplemented!()
+        let entry = ManifestEntry {
+            epoch: self.epoch,
+            record: _record,
+        };
+        let buf = serde_json::to_vec(&entry)?;
+        let mut file = self.file.lock();
+        file.write_all(&buf)?;
+        self.size.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        if let Some(observers) = &self.observers {
+            observers.notify(FileKind::Manifest, buf.len() as u64);
+        }
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// The SSTs referenced by the recovered records, with the epoch they were created in.
+pub fn live_ssts(entries: &[ManifestEntry]) -> HashMap<usize, u64> {
+    let mut live = HashMap::new();
+    for entry in entries {
+        match &entry.record {
+            ManifestRecord::Flush(sst_id) | ManifestRecord::FlushToLevel(sst_id, _) => {
+                live.insert(*sst_id, entry.epoch);
+            }
+            ManifestRecord::Compaction(task, output) => {
+                let input = task
+                    .input_sst_ids()
+                    .into_iter()
+                    .filter_map(|sst_id| live.remove_entry(&sst_id))
+                    .collect::<HashMap<_, _>>();
+                // An SST moved as it is keeps the epoch it was created in
+                live.extend(
+                    output
+                        .iter()
+                        .map(|sst_id| (*sst_id, input.get(sst_id).copied().unwrap_or(entry.epoch))),
+                );
+            }
+            ManifestRecord::Snapshot(snapshot) => {
+                live = snapshot.sst_epochs.iter().copied().collect();
+            }
+            _ => {}
+        }
     }
+    live
 }
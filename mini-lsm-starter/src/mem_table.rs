@@ -12,33 +12,181 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
-
 use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
-use anyhow::{Ok, Result};
+use anyhow::{Ok, Result, ensure};
 use bytes::Bytes;
 use crossbeam_skiplist::SkipMap;
 use ouroboros::self_referencing;
+use parking_lot::RwLock;
 
+use crate::compact::VersionGcIterator;
 use crate::iterators::StorageIterator;
-use crate::key::{Key, KeySlice};
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT, TS_MAX, TS_MIN, TS_RANGE_BEGIN, TS_RANGE_END};
+use crate::lsm_storage::FlushDecision;
+use crate::range_tombstone::RangeTombstones;
 use crate::table::SsTableBuilder;
 use crate::wal::Wal;
+use crate::write_observer::WriteObservers;
 
 /// A basic mem-table based on crossbeam-skiplist.
 ///
 /// An initial implementation of memtable is part of week 1, day 1. It will be incrementally implemented in other
 /// chapters of week 1 and week 2.
+///
+/// Each value in the skipmap is followed by its user metadata byte. Deletes are stored as empty
+/// values without the metadata byte, so that they can be told apart from empty values. The keys
+/// are versioned by the timestamp of their write, see `crate::key`.
 pub struct MemTable {
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<KeyBytes, Bytes>>,
+    /// The entries written in increasing key order, see `AppendLog`.
+    log: Option<Arc<AppendLog>>,
     wal: Option<Wal>,
     id: usize,
     approximate_size: Arc<AtomicUsize>,
+    /// The number of overwrites of existing keys started and finished. The skipmap replaces a
+    /// key by removing it before inserting the new entry, so a concurrent lookup could miss the
+    /// key in between and fall through to an older version below. `get_entry` retries the misses
+    /// that overlap an overwrite.
+    overwrites_started: AtomicU64,
+    overwrites_finished: AtomicU64,
+    /// The ranges deleted by `delete_range`, which only delete the keys of the older memtables
+    /// and SSTs, see `RangeTombstones`.
+    range_tombstones: RwLock<RangeTombstones>,
+    /// The largest timestamp written to the mem-table.
+    max_ts: AtomicU64,
+}
+
+/// The entries of a memtable written in strictly increasing key order, appended to a vector
+/// instead of inserted into the skipmap, see `LsmStorageOptions::sequential_writes`. The first
+/// write that is not after the last key seals the log, and goes to the skipmap like all the
+/// writes after it. The entries of the skipmap are thus newer than those of the log.
+#[derive(Default)]
+struct AppendLog {
+    entries: RwLock<Vec<(KeyBytes, Bytes)>>,
+    sealed: AtomicBool,
+}
+
+impl AppendLog {
+    /// Append an entry if its key is after the last one, or seal the log. Returns whether the
+    /// entry was appended.
+    fn try_append(&self, key: &KeyBytes, value: &Bytes) -> bool {
+        if self.sealed.load(Ordering::Acquire) {
+            return false;
+        }
+        let mut entries = self.entries.write();
+        if entries.last().is_some_and(|(last_key, _)| last_key >= key) {
+            self.sealed.store(true, Ordering::Release);
+            return false;
+        }
+        entries.push((key.clone(), value.clone()));
+        true
+    }
+
+    /// The newest version of `key` at or below `read_ts`.
+    fn get(&self, key: &[u8], read_ts: u64) -> Option<Bytes> {
+        let entries = self.entries.read();
+        let key = KeySlice::from_slice(key, read_ts);
+        let idx = entries.partition_point(|(entry_key, _)| entry_key.as_key_slice() < key);
+        let (entry_key, value) = entries.get(idx)?;
+        (entry_key.key_ref() == key.key_ref()).then(|| value.clone())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.read().is_empty()
+    }
+}
+
+/// A position in an `AppendLog`, which only grows, so that positions stay valid.
+struct LogCursor {
+    log: Arc<AppendLog>,
+    /// The index of the next entry, or one past it when moving backwards.
+    next: usize,
+    lower: Bound<KeyBytes>,
+    upper: Bound<KeyBytes>,
+    reverse: bool,
+}
+
+impl LogCursor {
+    fn new(
+        log: Arc<AppendLog>,
+        lower: Bound<KeyBytes>,
+        upper: Bound<KeyBytes>,
+        reverse: bool,
+    ) -> Self {
+        let next = {
+            let entries = log.entries.read();
+            if reverse {
+                entries.partition_point(|(key, _)| match &upper {
+                    Bound::Included(upper) => key <= upper,
+                    Bound::Excluded(upper) => key < upper,
+                    Bound::Unbounded => true,
+                })
+            } else {
+                entries.partition_point(|(key, _)| match &lower {
+                    Bound::Included(lower) => key < lower,
+                    Bound::Excluded(lower) => key <= lower,
+                    Bound::Unbounded => false,
+                })
+            }
+        };
+        Self {
+            log,
+            next,
+            lower,
+            upper,
+            reverse,
+        }
+    }
+
+    /// The entry at the position, `None` past the end of the log or of the range.
+    fn peek(&self) -> Option<(KeyBytes, Bytes)> {
+        let entries = self.log.entries.read();
+        if self.reverse {
+            let (key, value) = entries.get(self.next.checked_sub(1)?)?;
+            let in_range = match &self.lower {
+                Bound::Included(lower) => key >= lower,
+                Bound::Excluded(lower) => key > lower,
+                Bound::Unbounded => true,
+            };
+            return in_range.then(|| (key.clone(), value.clone()));
+        }
+        let (key, value) = entries.get(self.next)?;
+        let in_range = match &self.upper {
+            Bound::Included(upper) => key <= upper,
+            Bound::Excluded(upper) => key < upper,
+            Bound::Unbounded => true,
+        };
+        in_range.then(|| (key.clone(), value.clone()))
+    }
+
+    fn advance(&mut self) {
+        if self.reverse {
+            self.next -= 1;
+        } else {
+            self.next += 1;
+        }
+    }
+
+    /// Move to the first entry at or after `key`.
+    fn seek(&mut self, key: &KeyBytes) {
+        self.next = self
+            .log
+            .entries
+            .read()
+            .partition_point(|(entry_key, _)| entry_key < key);
+    }
+}
+
+/// Split a value stored in the skipmap into the value and its metadata byte.
+fn split_value_meta(value: &[u8]) -> (&[u8], u8) {
+    match value.split_last() {
+        Some((meta, value)) => (value, *meta),
+        None => (value, 0),
+    }
 }
 
 /// Create a bound of `Bytes` from a bound of `&[u8]`.
@@ -50,25 +198,93 @@ pub(crate) fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
     }
 }
 
+/// Create the bounds of the versioned keys of all versions of the user keys within `lower` and
+/// `upper`.
+pub(crate) fn map_key_bounds(
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> (Bound<KeyBytes>, Bound<KeyBytes>) {
+    let key = |x: &[u8], ts| KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(x), ts);
+    let lower = match lower {
+        Bound::Included(x) => Bound::Included(key(x, TS_RANGE_BEGIN)),
+        Bound::Excluded(x) => Bound::Excluded(key(x, TS_RANGE_END)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let upper = match upper {
+        Bound::Included(x) => Bound::Included(key(x, TS_RANGE_END)),
+        Bound::Excluded(x) => Bound::Excluded(key(x, TS_RANGE_BEGIN)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (lower, upper)
+}
+
 impl MemTable {
     /// Create a new mem-table.
-    pub fn create(_id: usize) -> Self {
+    pub fn create(id: usize) -> Self {
         Self {
             map: Arc::new(SkipMap::new()),
+            log: None,
             wal: None,
-            id: _id,
+            id,
             approximate_size: Arc::new(AtomicUsize::new(0)),
+            overwrites_started: AtomicU64::new(0),
+            overwrites_finished: AtomicU64::new(0),
+            range_tombstones: RwLock::new(RangeTombstones::new()),
+            max_ts: AtomicU64::new(TS_DEFAULT),
         }
     }
 
     /// Create a new mem-table with WAL
-    pub fn create_with_wal(_id: usize, _path: impl AsRef<Path>) -> Result<Self> {
-        unimplemented!()
+    pub fn create_with_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            wal: Some(Wal::create(path)?),
+            ..Self::create(id)
+        })
     }
 
     /// Create a memtable from WAL
-    pub fn recover_from_wal(_id: usize, _path: impl AsRef<Path>) -> Result<Self> {
-        unimplemented!()
+    pub fn recover_from_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
+        let map = SkipMap::new();
+        let mut range_tombstones = RangeTombstones::new();
+        let wal = Wal::recover_with_range_deletes(path, &map, &mut range_tombstones)?;
+        let approximate_size = map
+            .iter()
+            .map(|entry| entry.key().key_len() + entry.value().len().saturating_sub(1))
+            .sum::<usize>()
+            + range_tombstones.size();
+        let max_ts = map
+            .iter()
+            .map(|entry| entry.key().ts())
+            .chain(range_tombstones.max_ts())
+            .max()
+            .unwrap_or(TS_DEFAULT);
+        Ok(Self {
+            map: Arc::new(map),
+            wal: Some(wal),
+            approximate_size: Arc::new(AtomicUsize::new(approximate_size)),
+            range_tombstones: RwLock::new(range_tombstones),
+            max_ts: AtomicU64::new(max_ts),
+            ..Self::create(id)
+        })
+    }
+
+    /// Notify `observers` of the writes to the WAL of the mem-table.
+    pub fn with_write_observers(mut self, observers: Arc<WriteObservers>) -> Self {
+        self.wal = self.wal.map(|wal| wal.with_write_observers(observers));
+        self
+    }
+
+    /// Append the writes in increasing key order to a vector while they come in that order, see
+    /// `LsmStorageOptions::sequential_writes`.
+    pub fn with_append_log(mut self, enabled: bool) -> Self {
+        // The entries recovered from the WAL would be shadowed by the appended ones
+        self.log = (enabled && self.map.is_empty()).then(Default::default);
+        self
+    }
+
+    /// The path of the WAL of the mem-table, if it has one.
+    pub fn wal_path(&self) -> Option<&Path> {
+        self.wal.as_ref().map(Wal::path)
     }
 
     pub fn for_testing_put_slice(&self, key: &[u8], value: &[u8]) -> Result<()> {
@@ -90,9 +306,63 @@ impl MemTable {
         self.scan(lower, upper)
     }
 
-    /// Get a value by key.
-    pub fn get(&self, _key: &[u8]) -> Option<Bytes> {
-        self.map.get(_key).map(|entry| entry.value().clone())
+    /// Get a value by key. A delete is returned as an empty value.
+    pub fn get(&self, key: &[u8]) -> Option<Bytes> {
+        self.get_entry(key).map(Option::unwrap_or_default)
+    }
+
+    /// Get the latest entry of a key, `Some(None)` if the key is deleted.
+    pub fn get_entry(&self, key: &[u8]) -> Option<Option<Bytes>> {
+        self.get_entry_with_ts(key, TS_MAX)
+    }
+
+    /// Get the newest entry of a key written at or before `read_ts`, `Some(None)` if it is a
+    /// delete.
+    pub fn get_entry_with_ts(&self, key: &[u8], read_ts: u64) -> Option<Option<Bytes>> {
+        let lower = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key), read_ts);
+        let upper = KeyBytes::from_bytes_with_ts(lower.clone().into_inner(), TS_MIN);
+        loop {
+            let finished = self.overwrites_finished.load(Ordering::SeqCst);
+            let started = self.overwrites_started.load(Ordering::SeqCst);
+            let entry = self
+                .map
+                .range((Bound::Included(&lower), Bound::Included(&upper)))
+                .next();
+            let value = match entry {
+                Some(entry) => Some(entry.value().clone()),
+                // The miss is real if no overwrite was running at any point of the lookup
+                None if started == finished
+                    && self.overwrites_started.load(Ordering::SeqCst) == started =>
+                {
+                    self.log.as_ref().and_then(|log| log.get(key, read_ts))
+                }
+                None => {
+                    std::hint::spin_loop();
+                    continue;
+                }
+            };
+            return value.map(|value| (!value.is_empty()).then(|| value.slice(..value.len() - 1)));
+        }
+    }
+
+    /// Insert an entry into the skipmap, marking overwrites for `get_entry`. Writes of the same
+    /// key must not race, which the storage ensures with its write lock.
+    fn insert(&self, key: KeyBytes, value: Bytes) {
+        self.max_ts.fetch_max(key.ts(), Ordering::SeqCst);
+        if self
+            .log
+            .as_ref()
+            .is_some_and(|log| log.try_append(&key, &value))
+        {
+            return;
+        }
+        if !self.map.contains_key(&key) {
+            self.map.insert(key, value);
+            return;
+        }
+        self.overwrites_started.fetch_add(1, Ordering::SeqCst);
+        self.map.insert(key, value);
+        self.overwrites_finished.fetch_add(1, Ordering::SeqCst);
     }
 
     /// Put a key-value pair into the mem-table.
@@ -100,18 +370,134 @@ impl MemTable {
     /// In week 1, day 1, simply put the key-value pair into the skipmap.
     /// In week 2, day 6, also flush the data to WAL.
     /// In week 3, day 5, modify the function to use the batch API.
-    pub fn put(&self, _key: &[u8], _value: &[u8]) -> Result<()> {
-        let num_bytes = _key.len() + _value.len();
-        self.map
-            .insert(Bytes::copy_from_slice(_key), Bytes::copy_from_slice(_value));
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.put_entry(KeySlice::from_slice(key, TS_DEFAULT), Some(value), 0)
+    }
+
+    /// Delete a key from the mem-table.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.put_entry(KeySlice::from_slice(key, TS_DEFAULT), None, 0)
+    }
+
+    /// Put a version of a key with a user metadata byte into the mem-table, or a delete if
+    /// `value` is `None`.
+    pub fn put_entry(&self, key: KeySlice, value: Option<&[u8]>, meta: u8) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            match value {
+                Some(value) => wal.put_with_meta(key, value, meta)?,
+                None => wal.delete(key)?,
+            }
+        }
+        let num_bytes = key.key_len() + value.map_or(0, <[u8]>::len);
+        let stored = match value {
+            Some(value) => {
+                let mut stored = Vec::with_capacity(value.len() + 1);
+                stored.extend_from_slice(value);
+                stored.push(meta);
+                Bytes::from(stored)
+            }
+            None => Bytes::new(),
+        };
+        let key = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key.key_ref()), key.ts());
+        self.insert(key, stored);
+        self.approximate_size
+            .fetch_add(num_bytes, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Delete the keys in `[start, end)` at timestamp `ts`: those of the older memtables and
+    /// SSTs with a range tombstone, and those of this memtable with point deletes, so that the
+    /// keys written to it from now on are not deleted. Writes of the same keys must not race, as
+    /// for `insert`.
+    pub fn delete_range(&self, start: &[u8], end: &[u8], ts: u64) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            wal.delete_range(start, end, ts)?;
+        }
+        // The tombstone comes first, so that a lookup does not miss the point delete of a key
+        // and find it in an older memtable
+        self.range_tombstones.write().insert(start, end, ts);
+        self.max_ts.fetch_max(ts, Ordering::SeqCst);
+        let mut num_bytes = start.len() + end.len();
+        let mut iter = self.scan(Bound::Included(start), Bound::Excluded(end));
+        let mut deleted = Vec::new();
+        let mut prev_key = Vec::new();
+        while iter.is_valid() {
+            // Only the latest version of each key decides whether it is deleted already
+            let key = iter.key().key_ref();
+            if prev_key != key {
+                prev_key.clear();
+                prev_key.extend_from_slice(key);
+                if !iter.is_deleted() {
+                    deleted.push(Bytes::copy_from_slice(key));
+                }
+            }
+            iter.next()?;
+        }
+        for key in deleted {
+            num_bytes += key.len();
+            self.insert(KeyBytes::from_bytes_with_ts(key, ts), Bytes::new());
+        }
         self.approximate_size
             .fetch_add(num_bytes, std::sync::atomic::Ordering::Relaxed);
         Ok(())
     }
 
-    /// Implement this in week 3, day 5; if you want to implement this earlier, use `&[u8]` as the key type.
-    pub fn put_batch(&self, _data: &[(KeySlice, &[u8])]) -> Result<()> {
-        unimplemented!()
+    /// Whether `key` is deleted in the older memtables and SSTs by a range delete visible at
+    /// `read_ts`.
+    pub fn is_range_deleted(&self, key: &[u8], read_ts: u64) -> bool {
+        self.range_tombstones.read().covers(key, read_ts)
+    }
+
+    /// The largest timestamp written to the mem-table.
+    pub fn max_ts(&self) -> u64 {
+        self.max_ts.load(Ordering::SeqCst)
+    }
+
+    /// The ranges deleted in the older memtables and SSTs.
+    pub fn range_tombstones(&self) -> RangeTombstones {
+        self.range_tombstones.read().clone()
+    }
+
+    /// Write the puts and deletes (with a `None` value) of a batch at timestamp `ts` as a single
+    /// WAL frame, so that the recovery replays all of them or none, then insert them.
+    pub fn write_batch(&self, records: &[(&[u8], Option<&[u8]>)], ts: u64) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            wal.write_batch(records, ts)?;
+        }
+        for (key, value) in records {
+            let stored = match value {
+                Some(value) => {
+                    let mut stored = Vec::with_capacity(value.len() + 1);
+                    stored.extend_from_slice(value);
+                    stored.push(0);
+                    Bytes::from(stored)
+                }
+                None => Bytes::new(),
+            };
+            self.insert(
+                KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key), ts),
+                stored,
+            );
+            self.approximate_size.fetch_add(
+                key.len() + value.map_or(0, <[u8]>::len),
+                std::sync::atomic::Ordering::Relaxed,
+            );
+        }
+        Ok(())
+    }
+
+    /// Put the entries of a batch, which must have the same timestamp, see `write_batch`.
+    pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
+        let ts = data.first().map_or(TS_DEFAULT, |(key, _)| key.ts());
+        ensure!(
+            data.iter().all(|(key, _)| key.ts() == ts),
+            "the keys of a batch must have the same timestamp"
+        );
+        let records = data
+            .iter()
+            .map(|(key, value)| (key.key_ref(), Some(*value)))
+            .collect::<Vec<_>>();
+        self.write_batch(&records, ts)
     }
 
     pub fn sync_wal(&self) -> Result<()> {
@@ -121,33 +507,80 @@ impl MemTable {
         Ok(())
     }
 
-    /// Get an iterator over a range of keys.
-    pub fn scan(&self, _lower: Bound<&[u8]>, _upper: Bound<&[u8]>) -> MemTableIterator {
-        let (lower, upper) = (map_bound(_lower), map_bound(_upper));
+    /// Get an iterator over all versions of a range of keys, the newest version of each key
+    /// first.
+    pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> MemTableIterator {
+        self.scan_with_order(lower, upper, false)
+    }
+
+    /// Get an iterator over all versions of a range of keys in descending order, so the oldest
+    /// version of each key first.
+    pub fn scan_rev(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> MemTableIterator {
+        self.scan_with_order(lower, upper, true)
+    }
+
+    fn scan_with_order(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        reverse: bool,
+    ) -> MemTableIterator {
+        let (lower, upper) = map_key_bounds(lower, upper);
 
+        let log = self
+            .log
+            .as_ref()
+            .map(|log| LogCursor::new(log.clone(), lower.clone(), upper.clone(), reverse));
         let mut iter = MemTableIteratorBuilder {
             map: self.map.clone(),
+            upper: upper.clone(),
             iter_builder: |map| map.range((lower, upper)),
-            item: (Bytes::new(), Bytes::new()),
+            map_item: (KeyBytes::new(), Bytes::new()),
+            log,
+            reverse,
+            item: (KeyBytes::new(), Bytes::new()),
         }
         .build();
 
-        let next_item = match iter.with_iter_mut(|iter| iter.next()) {
+        let next_item = match iter.with_iter_mut(|iter| next_map_entry(iter, reverse)) {
             Some(entry) => (entry.key().clone(), entry.value().clone()),
-            None => (Bytes::new(), Bytes::new()),
+            None => (KeyBytes::new(), Bytes::new()),
         };
-        iter.with_mut(|fields| *fields.item = next_item);
+        iter.with_mut(|fields| *fields.map_item = next_item);
+        iter.advance();
         iter
     }
 
     /// Flush the mem-table to SSTable. Implement in week 1 day 6.
-    pub fn flush(&self, _builder: &mut SsTableBuilder) -> Result<()> {
-        for entry in self.map.iter() {
-            _builder.add(
-                Key::from_slice(entry.key().as_ref()),
-                entry.value().as_ref(),
-            );
+    pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
+        self.flush_filtered(builder, TS_MIN, |_, _| FlushDecision::Keep)
+    }
+
+    /// Flush the mem-table to SSTable, letting `filter` drop or change each entry. The filter is
+    /// given `None` as the value of a delete. The versions older than the latest one at or below
+    /// `watermark` are not flushed.
+    pub fn flush_filtered(
+        &self,
+        builder: &mut SsTableBuilder,
+        watermark: u64,
+        filter: impl Fn(&[u8], Option<&[u8]>) -> FlushDecision,
+    ) -> Result<()> {
+        let mut iter = VersionGcIterator::new(
+            self.scan(Bound::Unbounded, Bound::Unbounded),
+            watermark,
+            false,
+        )?;
+        while iter.is_valid() {
+            let key = iter.key();
+            let (value, meta, is_delete) = (iter.value(), iter.value_meta(), iter.is_deleted());
+            match filter(key.key_ref(), (!is_delete).then_some(value)) {
+                FlushDecision::Keep => builder.add_entry(key, value, meta, is_delete),
+                FlushDecision::Remove => {}
+                FlushDecision::ChangeValue(value) => builder.add_entry(key, &value, meta, false),
+            }
+            iter.next()?;
         }
+        builder.add_range_tombstones(&self.range_tombstones.read());
         Ok(())
     }
 
@@ -163,11 +596,30 @@ impl MemTable {
     /// Only use this function when closing the database
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
+            && self.log.as_ref().is_none_or(|log| log.is_empty())
+            && self.range_tombstones.read().is_empty()
     }
 }
 
-type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
+    'a,
+    KeyBytes,
+    (Bound<KeyBytes>, Bound<KeyBytes>),
+    KeyBytes,
+    Bytes,
+>;
+
+/// The next entry of a skipmap range, from its back when moving in reverse.
+fn next_map_entry<'a>(
+    iter: &mut SkipMapRangeIter<'a>,
+    reverse: bool,
+) -> Option<crossbeam_skiplist::map::Entry<'a, KeyBytes, Bytes>> {
+    if reverse {
+        iter.next_back()
+    } else {
+        iter.next()
+    }
+}
 
 /// An iterator over a range of `SkipMap`. This is a self-referential structure and please refer to week 1, day 2
 /// chapter for more information.
@@ -176,24 +628,71 @@ type SkipMapRangeIter<'a> =
 #[self_referencing]
 pub struct MemTableIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<KeyBytes, Bytes>>,
     /// Stores a skipmap iterator that refers to the lifetime of `MemTableIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
+    /// The next entry of the skipmap, with an empty key once there is none.
+    map_item: (KeyBytes, Bytes),
+    /// The entries of the append log in the range, merged with those of the skipmap.
+    log: Option<LogCursor>,
+    /// The upper bound of the range, kept for `seek`.
+    upper: Bound<KeyBytes>,
+    /// Whether the entries are visited in descending key order.
+    reverse: bool,
     /// Stores the current key-value pair.
-    item: (Bytes, Bytes),
+    item: (KeyBytes, Bytes),
+}
+
+impl MemTableIterator {
+    /// Move to the next entry of the skipmap or of the append log, whichever has the smaller
+    /// key (the larger one in reverse). The skipmap entry wins over the log entry of the same
+    /// key, which is older.
+    fn advance(&mut self) {
+        self.with_mut(|fields| {
+            let reverse = *fields.reverse;
+            let log_item = fields.log.as_ref().and_then(LogCursor::peek);
+            let from_map = match &log_item {
+                None => true,
+                Some(_) if fields.map_item.0.is_empty() => false,
+                Some((log_key, _)) if reverse => fields.map_item.0 >= *log_key,
+                Some((log_key, _)) => fields.map_item.0 <= *log_key,
+            };
+            if !from_map {
+                fields.log.as_mut().unwrap().advance();
+                *fields.item = log_item.unwrap();
+                return;
+            }
+            if log_item.is_some_and(|(log_key, _)| log_key == fields.map_item.0) {
+                fields.log.as_mut().unwrap().advance();
+            }
+            let next_item = match next_map_entry(fields.iter, reverse) {
+                Some(entry) => (entry.key().clone(), entry.value().clone()),
+                None => (KeyBytes::new(), Bytes::new()),
+            };
+            *fields.item = std::mem::replace(fields.map_item, next_item);
+        });
+    }
 }
 
 impl StorageIterator for MemTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
-        self.borrow_item().1.as_ref()
+        split_value_meta(&self.borrow_item().1).0
+    }
+
+    fn value_meta(&self) -> u8 {
+        split_value_meta(&self.borrow_item().1).1
     }
 
-    fn key(&self) -> KeySlice {
-        KeySlice::from_slice(self.borrow_item().0.as_ref())
+    fn is_deleted(&self) -> bool {
+        self.borrow_item().1.is_empty()
+    }
+
+    fn key(&self) -> KeySlice<'_> {
+        self.borrow_item().0.as_key_slice()
     }
 
     fn is_valid(&self) -> bool {
@@ -201,12 +700,30 @@ impl StorageIterator for MemTableIterator {
     }
 
     fn next(&mut self) -> Result<()> {
-        let next_item = match self.with_iter_mut(|iter| iter.next()) {
-            Some(entry) => (entry.key().clone(), entry.value().clone()),
-            None => (Bytes::new(), Bytes::new()),
-        };
+        self.advance();
+        Ok(())
+    }
 
-        self.with_mut(|fields| *fields.item = next_item);
+    /// Restart the range of the skipmap and the append log at `key`.
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        anyhow::ensure!(
+            !*self.borrow_reverse(),
+            "seek is not supported by reverse iterators"
+        );
+        let key = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key.key_ref()), key.ts());
+        self.with_mut(|fields| {
+            *fields.iter = fields
+                .map
+                .range((Bound::Included(key.clone()), fields.upper.clone()));
+            if let Some(log) = fields.log.as_mut() {
+                log.seek(&key);
+            }
+            *fields.map_item = match fields.iter.next() {
+                Some(entry) => (entry.key().clone(), entry.value().clone()),
+                None => (KeyBytes::new(), Bytes::new()),
+            };
+        });
+        self.advance();
         Ok(())
     }
 }
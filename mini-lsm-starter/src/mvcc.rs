@@ -12,44 +12,116 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
-
 pub mod txn;
 pub mod watermark;
 
 use std::{
-    collections::{BTreeMap, HashSet},
-    sync::Arc,
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
+use bytes::Bytes;
 use parking_lot::Mutex;
 
 use self::{txn::Transaction, watermark::Watermark};
 use crate::lsm_storage::LsmStorageInner;
 
+/// A range of keys from a lower to an upper bound.
+pub(crate) type KeyRange = (Bound<Bytes>, Bound<Bytes>);
+
+/// The keys and ranges written at a commit timestamp.
+#[derive(Default)]
 pub(crate) struct CommittedTxnData {
-    pub(crate) key_hashes: HashSet<u32>,
-    #[allow(dead_code)]
-    pub(crate) read_ts: u64,
-    #[allow(dead_code)]
-    pub(crate) commit_ts: u64,
+    pub(crate) keys: BTreeSet<Bytes>,
+    pub(crate) ranges: Vec<KeyRange>,
+}
+
+impl CommittedTxnData {
+    /// Whether the writes include `key`.
+    pub(crate) fn writes_key(&self, key: &[u8]) -> bool {
+        self.keys.contains(key)
+            || self
+                .ranges
+                .iter()
+                .any(|(lower, upper)| in_range(key, lower.as_ref(), upper.as_ref()))
+    }
+
+    /// Whether the writes include a key in the range.
+    pub(crate) fn writes_range(&self, lower: Bound<&Bytes>, upper: Bound<&Bytes>) -> bool {
+        if !may_hold_keys(lower, upper) {
+            return false;
+        }
+        self.keys
+            .range::<[u8], _>((lower.map(|key| &key[..]), upper.map(|key| &key[..])))
+            .next()
+            .is_some()
+            || self.ranges.iter().any(|(written_lower, written_upper)| {
+                may_hold_keys(lower, written_upper.as_ref())
+                    && may_hold_keys(written_lower.as_ref(), upper)
+            })
+    }
+}
+
+/// Whether a range from `lower` to `upper` may hold a key, i.e., `lower` does not start after
+/// `upper`.
+fn may_hold_keys(lower: Bound<&Bytes>, upper: Bound<&Bytes>) -> bool {
+    match (lower, upper) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => true,
+        (Bound::Included(lower), Bound::Included(upper)) => lower <= upper,
+        (
+            Bound::Included(lower) | Bound::Excluded(lower),
+            Bound::Included(upper) | Bound::Excluded(upper),
+        ) => lower < upper,
+    }
+}
+
+fn bound_len(bound: &Bound<Bytes>) -> usize {
+    match bound {
+        Bound::Included(key) | Bound::Excluded(key) => key.len(),
+        Bound::Unbounded => 0,
+    }
+}
+
+fn in_range(key: &[u8], lower: Bound<&Bytes>, upper: Bound<&Bytes>) -> bool {
+    let above_lower = match lower {
+        Bound::Included(lower) => key >= &lower[..],
+        Bound::Excluded(lower) => key > &lower[..],
+        Bound::Unbounded => true,
+    };
+    let below_upper = match upper {
+        Bound::Included(upper) => key <= &upper[..],
+        Bound::Excluded(upper) => key < &upper[..],
+        Bound::Unbounded => true,
+    };
+    above_lower && below_upper
+}
+
+/// The writes committed after the read timestamp of the oldest active transaction, which the
+/// transactions validate their reads and writes against when they commit.
+#[derive(Default)]
+pub(crate) struct CommittedTxns {
+    /// The read timestamps of the active transactions.
+    active: Watermark,
+    pub(crate) writes: BTreeMap<u64, CommittedTxnData>,
 }
 
 pub(crate) struct LsmMvccInner {
-    pub(crate) write_lock: Mutex<()>,
-    pub(crate) commit_lock: Mutex<()>,
     pub(crate) ts: Arc<Mutex<(u64, Watermark)>>,
-    pub(crate) committed_txns: Arc<Mutex<BTreeMap<u64, CommittedTxnData>>>,
+    pub(crate) committed_txns: Arc<Mutex<CommittedTxns>>,
+    /// The bytes of the writes buffered by the transactions not dropped yet.
+    pub(crate) txn_write_size: AtomicU64,
 }
 
 impl LsmMvccInner {
     pub fn new(initial_ts: u64) -> Self {
         Self {
-            write_lock: Mutex::new(()),
-            commit_lock: Mutex::new(()),
             ts: Arc::new(Mutex::new((initial_ts, Watermark::new()))),
-            committed_txns: Arc::new(Mutex::new(BTreeMap::new())),
+            committed_txns: Arc::new(Mutex::new(CommittedTxns::default())),
+            txn_write_size: AtomicU64::new(0),
         }
     }
 
@@ -67,7 +139,104 @@ impl LsmMvccInner {
         ts.1.watermark().unwrap_or(ts.0)
     }
 
+    /// Start a read at the latest commit timestamp, whose versions are kept until the returned
+    /// guard is dropped.
+    pub(crate) fn new_reader(&self) -> ReadTsGuard {
+        let mut ts = self.ts.lock();
+        let read_ts = ts.0;
+        ts.1.add_reader(read_ts);
+        ReadTsGuard {
+            ts: self.ts.clone(),
+            read_ts,
+        }
+    }
+
+    /// Start a transaction reading at the latest commit timestamp, which validates the ranges it
+    /// scans if `serializable`. The writes committed after it are recorded until it ends.
     pub fn new_txn(&self, inner: Arc<LsmStorageInner>, serializable: bool) -> Arc<Transaction> {
-        unimplemented!()
+        let mut committed_txns = self.committed_txns.lock();
+        let reader = self.new_reader();
+        committed_txns.active.add_reader(reader.read_ts());
+        Arc::new(Transaction::new(inner, reader, serializable))
+    }
+
+    /// Record the writes committed at `commit_ts` if an active transaction started before it.
+    /// `writes` is only called then. Writers call this after updating the commit timestamp.
+    pub(crate) fn record_commit(&self, commit_ts: u64, writes: impl FnOnce(&mut CommittedTxnData)) {
+        let mut committed_txns = self.committed_txns.lock();
+        if committed_txns
+            .active
+            .watermark()
+            .is_some_and(|read_ts| read_ts < commit_ts)
+        {
+            writes(committed_txns.writes.entry(commit_ts).or_default());
+        }
+    }
+
+    /// The commit timestamp of the first writes committed after `read_ts` that `conflicts` with.
+    pub(crate) fn find_conflict(
+        &self,
+        read_ts: u64,
+        conflicts: impl Fn(&CommittedTxnData) -> bool,
+    ) -> Option<u64> {
+        let committed_txns = self.committed_txns.lock();
+        committed_txns
+            .writes
+            .range(read_ts + 1..)
+            .find(|(_, writes)| conflicts(writes))
+            .map(|(commit_ts, _)| *commit_ts)
+    }
+
+    /// The bytes of the writes buffered by the transactions and of the committed writes they
+    /// validate against.
+    pub(crate) fn txn_write_sets_size(&self) -> u64 {
+        let committed_txns = self.committed_txns.lock();
+        let committed = committed_txns
+            .writes
+            .values()
+            .map(|writes| {
+                let keys = writes.keys.iter().map(Bytes::len).sum::<usize>();
+                let ranges = writes
+                    .ranges
+                    .iter()
+                    .map(|(lower, upper)| bound_len(lower) + bound_len(upper))
+                    .sum::<usize>();
+                (keys + ranges) as u64
+            })
+            .sum::<u64>();
+        committed + self.txn_write_size.load(Ordering::Relaxed)
+    }
+
+    /// End the transaction reading at `read_ts`, dropping the writes that no active transaction
+    /// needs to validate against anymore.
+    pub(crate) fn end_txn(&self, read_ts: u64) {
+        let mut committed_txns = self.committed_txns.lock();
+        committed_txns.active.remove_reader(read_ts);
+        match committed_txns.active.watermark() {
+            Some(oldest) => {
+                let kept = committed_txns.writes.split_off(&(oldest + 1));
+                committed_txns.writes = kept;
+            }
+            None => committed_txns.writes.clear(),
+        }
+    }
+}
+
+/// A read registered in the watermark, which keeps the compactions from dropping the versions it
+/// sees until it is dropped.
+pub(crate) struct ReadTsGuard {
+    ts: Arc<Mutex<(u64, Watermark)>>,
+    read_ts: u64,
+}
+
+impl ReadTsGuard {
+    pub(crate) fn read_ts(&self) -> u64 {
+        self.read_ts
+    }
+}
+
+impl Drop for ReadTsGuard {
+    fn drop(&mut self) {
+        self.ts.lock().1.remove_reader(self.read_ts);
     }
 }
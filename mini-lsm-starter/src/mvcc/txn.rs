@@ -12,99 +12,285 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
+//! Optimistic transactions, which buffer their writes and read the snapshot at their start,
+//! and only commit if no write committed since then touched a key they read or write, or with
+//! `LsmStorageOptions::serializable`, a range they scanned.
 
 use std::{
-    collections::HashSet,
+    collections::BTreeSet,
+    fmt,
     ops::Bound,
-    sync::{Arc, atomic::AtomicBool},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
 use anyhow::Result;
 use bytes::Bytes;
-use crossbeam_skiplist::SkipMap;
+use crossbeam_skiplist::{SkipMap, map::Entry};
 use ouroboros::self_referencing;
 use parking_lot::Mutex;
 
 use crate::{
     iterators::{StorageIterator, two_merge_iterator::TwoMergeIterator},
     lsm_iterator::{FusedIterator, LsmIterator},
-    lsm_storage::LsmStorageInner,
+    lsm_storage::{LsmStorageInner, WriteBatchRecord},
+    mem_table::map_bound,
+    mvcc::{KeyRange, ReadTsGuard},
 };
 
+/// The error returned when a transaction cannot commit, as a write committed after its read
+/// timestamp touched a key it read or writes, or a range it scanned if it is serializable. The
+/// transaction can be retried from the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictError {
+    pub read_ts: u64,
+    pub commit_ts: u64,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction reading at ts {} conflicts with the write committed at ts {}",
+            self.read_ts, self.commit_ts
+        )
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// Returns true if the error is caused by a transaction conflicting with another write.
+pub fn is_conflict_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ConflictError>().is_some()
+}
+
+/// A transaction, which reads the storage at the commit timestamp of its start along with its own
+/// writes. Its writes are buffered until `commit` writes them as one batch, which fails with a
+/// `ConflictError` if a write committed in the meantime touched a key the transaction read or
+/// writes. A serializable transaction also fails if a write touched a range it scanned, e.g., by
+/// adding a key to it. Until it is dropped, the compactions keep the versions it reads.
 pub struct Transaction {
-    pub(crate) read_ts: u64,
     pub(crate) inner: Arc<LsmStorageInner>,
-    pub(crate) local_storage: Arc<SkipMap<Bytes, Bytes>>,
+    reader: ReadTsGuard,
+    /// The buffered writes, `None` for deletes
+    pub(crate) local_storage: Arc<SkipMap<Bytes, Option<Bytes>>>,
     pub(crate) committed: Arc<AtomicBool>,
-    /// Write set and read set
-    pub(crate) key_hashes: Option<Mutex<(HashSet<u32>, HashSet<u32>)>>,
+    /// The keys read from the storage
+    pub(crate) read_set: Mutex<BTreeSet<Bytes>>,
+    /// The ranges scanned, if the transaction is serializable
+    pub(crate) read_ranges: Option<Mutex<Vec<KeyRange>>>,
+    /// The bytes of the keys and values in `local_storage`
+    write_size: AtomicU64,
 }
 
 impl Transaction {
+    pub(crate) fn new(
+        inner: Arc<LsmStorageInner>,
+        reader: ReadTsGuard,
+        serializable: bool,
+    ) -> Self {
+        Self {
+            inner,
+            reader,
+            local_storage: Arc::new(SkipMap::new()),
+            committed: Arc::new(AtomicBool::new(false)),
+            read_set: Mutex::new(BTreeSet::new()),
+            read_ranges: serializable.then(|| Mutex::new(Vec::new())),
+            write_size: AtomicU64::new(0),
+        }
+    }
+
+    /// The timestamp the transaction reads at.
+    pub fn read_ts(&self) -> u64 {
+        self.reader.read_ts()
+    }
+
+    fn check_not_committed(&self) {
+        assert!(
+            !self.committed.load(Ordering::SeqCst),
+            "cannot operate on committed txn"
+        );
+    }
+
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
-        unimplemented!()
+        self.check_not_committed();
+        if let Some(entry) = self.local_storage.get(key) {
+            return Ok(entry.value().clone());
+        }
+        self.read_set.lock().insert(Bytes::copy_from_slice(key));
+        self.inner.get_with_ts(key, self.read_ts())
     }
 
+    /// Create an iterator over a range of keys, where the writes of the transaction replace the
+    /// ones of the storage. The keys it yields join the read set, and the whole range if the
+    /// transaction is serializable, whether the iterator reaches its end or not.
     pub fn scan(self: &Arc<Self>, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
-        unimplemented!()
+        self.check_not_committed();
+        if let Some(read_ranges) = &self.read_ranges {
+            read_ranges
+                .lock()
+                .push((map_bound(lower), map_bound(upper)));
+        }
+        let mut local_iter = TxnLocalIteratorBuilder {
+            map: self.local_storage.clone(),
+            iter_builder: |map| map.range((map_bound(lower), map_bound(upper))),
+            item: None,
+        }
+        .build();
+        local_iter.next()?;
+        TxnIterator::create(
+            self.clone(),
+            TwoMergeIterator::create(
+                local_iter,
+                self.inner.scan_with_ts(lower, upper, self.read_ts())?,
+            )?,
+        )
     }
 
     pub fn put(&self, key: &[u8], value: &[u8]) {
-        unimplemented!()
+        self.check_not_committed();
+        self.buffer_write(key, Some(Bytes::copy_from_slice(value)));
     }
 
     pub fn delete(&self, key: &[u8]) {
-        unimplemented!()
+        self.check_not_committed();
+        self.buffer_write(key, None);
     }
 
+    fn buffer_write(&self, key: &[u8], value: Option<Bytes>) {
+        let entry_size =
+            |value: &Option<Bytes>| (key.len() + value.as_ref().map_or(0, Bytes::len)) as u64;
+        let added = entry_size(&value);
+        let replaced = self
+            .local_storage
+            .get(key)
+            .map_or(0, |entry| entry_size(entry.value()));
+        self.local_storage
+            .insert(Bytes::copy_from_slice(key), value);
+        let txn_write_size = &self.inner.mvcc().txn_write_size;
+        txn_write_size.fetch_add(added, Ordering::Relaxed);
+        txn_write_size.fetch_sub(replaced, Ordering::Relaxed);
+        self.write_size.fetch_add(added, Ordering::Relaxed);
+        self.write_size.fetch_sub(replaced, Ordering::Relaxed);
+    }
+
+    /// Write the buffered writes as one batch, through the WAL if it is enabled. A read-only
+    /// transaction always commits, as its reads are a consistent snapshot.
     pub fn commit(&self) -> Result<()> {
-        unimplemented!()
+        self.committed
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .expect("cannot operate on committed txn");
+        let batch = self
+            .local_storage
+            .iter()
+            .map(|entry| match entry.value() {
+                Some(value) => WriteBatchRecord::Put(entry.key().clone(), value.clone()),
+                None => WriteBatchRecord::Del(entry.key().clone()),
+            })
+            .collect::<Vec<_>>();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let read_set = self.read_set.lock();
+        let read_ranges = self
+            .read_ranges
+            .as_ref()
+            .map(|read_ranges| read_ranges.lock());
+        self.inner.write_batch_validated(&batch, || {
+            let conflict = self.inner.mvcc().find_conflict(self.read_ts(), |writes| {
+                read_set.iter().any(|key| writes.writes_key(key))
+                    || self
+                        .local_storage
+                        .iter()
+                        .any(|entry| writes.writes_key(entry.key()))
+                    || read_ranges.as_ref().is_some_and(|read_ranges| {
+                        read_ranges.iter().any(|(lower, upper)| {
+                            writes.writes_range(lower.as_ref(), upper.as_ref())
+                        })
+                    })
+            });
+            match conflict {
+                Some(commit_ts) => Err(ConflictError {
+                    read_ts: self.read_ts(),
+                    commit_ts,
+                }
+                .into()),
+                None => Ok(()),
+            }
+        })
     }
 }
 
 impl Drop for Transaction {
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        let mvcc = self.inner.mvcc();
+        mvcc.txn_write_size
+            .fetch_sub(*self.write_size.get_mut(), Ordering::Relaxed);
+        mvcc.end_txn(self.read_ts());
+    }
 }
 
 type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Option<Bytes>>;
 
 #[self_referencing]
 pub struct TxnLocalIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<Bytes, Option<Bytes>>>,
     /// Stores a skipmap iterator that refers to the lifetime of `TxnLocalIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
-    /// Stores the current key-value pair.
-    item: (Bytes, Bytes),
+    /// Stores the current key-value pair, `None` once the iterator is exhausted.
+    item: Option<(Bytes, Option<Bytes>)>,
+}
+
+impl TxnLocalIterator {
+    fn entry_to_item(
+        entry: Option<Entry<'_, Bytes, Option<Bytes>>>,
+    ) -> Option<(Bytes, Option<Bytes>)> {
+        entry.map(|entry| (entry.key().clone(), entry.value().clone()))
+    }
 }
 
 impl StorageIterator for TxnLocalIterator {
     type KeyType<'a> = &'a [u8];
 
     fn value(&self) -> &[u8] {
-        unimplemented!()
+        self.borrow_item()
+            .as_ref()
+            .and_then(|(_, value)| value.as_deref())
+            .unwrap_or_default()
     }
 
     fn key(&self) -> &[u8] {
-        unimplemented!()
+        self.borrow_item()
+            .as_ref()
+            .map(|(key, _)| &key[..])
+            .unwrap_or_default()
     }
 
     fn is_valid(&self) -> bool {
-        unimplemented!()
+        self.borrow_item().is_some()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.borrow_item()
+            .as_ref()
+            .is_some_and(|(_, value)| value.is_none())
     }
 
     fn next(&mut self) -> Result<()> {
-        unimplemented!()
+        let item = self.with_iter_mut(|iter| TxnLocalIterator::entry_to_item(iter.next()));
+        self.with_item_mut(|x| *x = item);
+        Ok(())
     }
 }
 
 pub struct TxnIterator {
-    _txn: Arc<Transaction>,
+    txn: Arc<Transaction>,
     iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
 }
 
@@ -113,7 +299,21 @@ impl TxnIterator {
         txn: Arc<Transaction>,
         iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
     ) -> Result<Self> {
-        unimplemented!()
+        let mut iter = Self { txn, iter };
+        iter.skip_deletes()?;
+        Ok(iter)
+    }
+
+    /// Skip the keys the transaction deleted, and add the storage keys it moves to to the read
+    /// set.
+    fn skip_deletes(&mut self) -> Result<()> {
+        while self.iter.is_valid() && self.iter.is_deleted() {
+            self.iter.next()?;
+        }
+        if self.iter.is_valid() && !self.txn.local_storage.contains_key(self.iter.key()) {
+            (self.txn.read_set.lock()).insert(Bytes::copy_from_slice(self.iter.key()));
+        }
+        Ok(())
     }
 }
 
@@ -135,8 +335,17 @@ impl StorageIterator for TxnIterator {
         self.iter.is_valid()
     }
 
+    fn is_deleted(&self) -> bool {
+        false
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.iter.value_meta()
+    }
+
     fn next(&mut self) -> Result<()> {
-        unimplemented!()
+        self.iter.next()?;
+        self.skip_deletes()
     }
 
     fn num_active_iterators(&self) -> usize {
@@ -12,11 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
-
 use std::collections::BTreeMap;
 
+/// The timestamps of the active reads, with the number of reads at each.
 pub struct Watermark {
     readers: BTreeMap<u64, usize>,
 }
@@ -34,15 +32,27 @@ impl Watermark {
         }
     }
 
-    pub fn add_reader(&mut self, ts: u64) {}
+    pub fn add_reader(&mut self, ts: u64) {
+        *self.readers.entry(ts).or_default() += 1;
+    }
 
-    pub fn remove_reader(&mut self, ts: u64) {}
+    pub fn remove_reader(&mut self, ts: u64) {
+        let count = self
+            .readers
+            .get_mut(&ts)
+            .expect("no reader at this timestamp");
+        *count -= 1;
+        if *count == 0 {
+            self.readers.remove(&ts);
+        }
+    }
 
     pub fn num_retained_snapshots(&self) -> usize {
         self.readers.len()
     }
 
+    /// The lowest timestamp read, `None` if there are no readers.
     pub fn watermark(&self) -> Option<u64> {
-        Some(0)
+        self.readers.first_key_value().map(|(ts, _)| *ts)
     }
 }
@@ -0,0 +1,106 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Remembers the keys that gets found absent, for workloads probing the same missing keys over
+//! and over, e.g., a cache-aside layer looking up keys it never wrote. See
+//! `LsmStorageOptions::negative_cache_capacity`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use moka::sync::ConcurrentCacheExt;
+use parking_lot::Mutex;
+
+use crate::key_range_stats::key_hash;
+
+const NUM_STRIPES: usize = 64;
+
+/// The lookups served by the negative cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NegativeCacheStats {
+    /// The gets answered from the cache without reading the memtables or SSTs.
+    pub hits: u64,
+    /// The keys currently cached as absent.
+    pub entries: u64,
+}
+
+/// Maps each key to the sequence as of which it was absent. A write removes its key, and the
+/// keys are split into stripes that remember the sequence of their last write, so that a get
+/// that raced with a write to its stripe does not cache a result the write made stale.
+pub(crate) struct NegativeCache {
+    cache: moka::sync::Cache<Bytes, u64>,
+    /// The sequence of the last write to a key of each stripe.
+    stripes: Vec<Mutex<u64>>,
+    hits: AtomicU64,
+}
+
+impl NegativeCache {
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            cache: moka::sync::Cache::new(capacity),
+            stripes: (0..NUM_STRIPES).map(|_| Mutex::new(0)).collect(),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    fn stripe_of(&self, key: &[u8]) -> &Mutex<u64> {
+        &self.stripes[key_hash(key) as usize % NUM_STRIPES]
+    }
+
+    /// Whether `key` is known to be absent.
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        let hit = self.cache.contains_key(key);
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    /// Remember that `key` was absent in a get that started at `sequence`, unless a write to its
+    /// stripe was applied since.
+    pub(crate) fn insert(&self, key: &[u8], sequence: u64) {
+        let last_write = self.stripe_of(key).lock();
+        if *last_write <= sequence {
+            self.cache.insert(Bytes::copy_from_slice(key), sequence);
+        }
+    }
+
+    /// Forget `key` after a write to it with `sequence` was applied.
+    pub(crate) fn invalidate(&self, key: &[u8], sequence: u64) {
+        let mut last_write = self.stripe_of(key).lock();
+        *last_write = (*last_write).max(sequence);
+        self.cache.invalidate(key);
+    }
+
+    /// Forget all keys after the SSTs of another storage were added with `sequence`.
+    pub(crate) fn invalidate_all(&self, sequence: u64) {
+        let mut stripes = self
+            .stripes
+            .iter()
+            .map(|stripe| stripe.lock())
+            .collect::<Vec<_>>();
+        for last_write in stripes.iter_mut() {
+            **last_write = (**last_write).max(sequence);
+        }
+        self.cache.invalidate_all();
+    }
+
+    pub(crate) fn stats(&self) -> NegativeCacheStats {
+        self.cache.sync();
+        NegativeCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            entries: self.cache.entry_count(),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `OPTIONS` file records the options that shape the files on disk, so that opening the
+//! storage with incompatible options fails instead of misreading the existing layout.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::compact::CompactionOptions;
+use crate::lsm_storage::LsmStorageOptions;
+
+pub const OPTIONS_FILE: &str = "OPTIONS";
+
+/// The version of the on-disk format of the SSTs, WALs and manifest written by this build. A
+/// change to any of the encodings bumps it and checks in fixtures written with the new version,
+/// see `tests/format_compat.rs`.
+pub const FORMAT_VERSION: u32 = 9;
+
+/// The oldest format version this build can read.
+pub const MIN_READABLE_FORMAT_VERSION: u32 = 1;
+
+/// The oldest format version of a storage directory that this build can open.
+pub fn report_min_readable_version() -> u32 {
+    MIN_READABLE_FORMAT_VERSION
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredOptions {
+    /// The format version of the newest files in the directory. `OPTIONS` files without it
+    /// predate the versioning and have version 1.
+    #[serde(default = "first_format_version")]
+    pub format_version: u32,
+    pub compaction_options: CompactionOptions,
+    /// Whether the keys from `column_family::CF_KEY_PREFIX` on were reserved, see
+    /// `LsmStorageOptions::internal_keyspace`. Missing in the `OPTIONS` files before it.
+    #[serde(default)]
+    pub internal_keyspace: bool,
+}
+
+fn first_format_version() -> u32 {
+    1
+}
+
+impl StoredOptions {
+    pub fn new(options: &LsmStorageOptions) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            compaction_options: options.compaction_options.clone(),
+            internal_keyspace: options.internal_keyspace,
+        }
+    }
+
+    /// Read the `OPTIONS` file in `path`, if any.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let path = path.join(OPTIONS_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(&path)?;
+        let stored = serde_json::from_slice(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(Some(stored))
+    }
+
+    /// Replace the `OPTIONS` file in `path` atomically.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.join(format!("{}.tmp", OPTIONS_FILE));
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(&tmp_path, path.join(OPTIONS_FILE))?;
+        Ok(())
+    }
+
+    /// Fail if the files in the directory have a format version this build cannot read.
+    pub fn check_format_version(&self) -> Result<()> {
+        if !(MIN_READABLE_FORMAT_VERSION..=FORMAT_VERSION).contains(&self.format_version) {
+            bail!(
+                "the files have format version {}, but this build reads versions {} to {}",
+                self.format_version,
+                MIN_READABLE_FORMAT_VERSION,
+                FORMAT_VERSION
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if the keys reserved with `internal_keyspace` would become plain keys, which would
+    /// mix the column families and the windows of the idempotent writes into the default
+    /// column family.
+    pub fn check_internal_keyspace(&self, options: &LsmStorageOptions) -> Result<()> {
+        if self.internal_keyspace && !options.internal_keyspace {
+            bail!(
+                "the {} file does not match the options: internal_keyspace cannot be turned off \
+                 once it was on",
+                OPTIONS_FILE
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail with a descriptive error if the compaction layout changed and no migration was
+    /// requested. Returns whether a migration is needed.
+    pub fn check_migration(&self, options: &LsmStorageOptions) -> Result<bool> {
+        let Some(change) = self
+            .compaction_options
+            .layout_change(&options.compaction_options)
+        else {
+            return Ok(false);
+        };
+        if !options.migrate_compaction_layout {
+            bail!(
+                "the {} file does not match the options: {}, which requires compacting all SSTs \
+                 into one sorted run; set `migrate_compaction_layout` to do so while opening",
+                OPTIONS_FILE,
+                change
+            );
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Block readahead for scans.
+//!
+//! With `scan_readahead` set, each SST iterator of a scan queues the reads of the next blocks
+//! to a background thread, which loads them into the block cache. The scan cancels its queued
+//! prefetches once it reaches its end bound or is dropped, and the prefetched blocks it never
+//! read are counted as wasted.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crossbeam_channel::Sender;
+use parking_lot::Mutex;
+
+use crate::table::SsTable;
+
+/// The prefetches of all scans so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// The bytes of the blocks read into the block cache ahead of the scans.
+    pub prefetched_bytes: u64,
+    /// The bytes of the prefetched blocks that the scans never read.
+    pub wasted_bytes: u64,
+    /// The number of queued prefetches dropped because their scan had ended.
+    pub cancelled_blocks: u64,
+}
+
+#[derive(Default)]
+struct PrefetchCounters {
+    prefetched_bytes: AtomicU64,
+    wasted_bytes: AtomicU64,
+    cancelled_blocks: AtomicU64,
+}
+
+struct PrefetchJob {
+    table: Arc<SsTable>,
+    block_idx: usize,
+    scan: Arc<ScanPrefetch>,
+}
+
+/// Runs the prefetches of the scans in a background thread, which stops once the prefetcher
+/// and all scans using it are dropped.
+pub struct Prefetcher {
+    sender: Sender<PrefetchJob>,
+    counters: Arc<PrefetchCounters>,
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded::<PrefetchJob>();
+        let counters = Arc::new(PrefetchCounters::default());
+        std::thread::spawn(move || {
+            for job in receiver {
+                if let Err(e) = job.run() {
+                    eprintln!("prefetch failed: {:#}", e);
+                }
+            }
+        });
+        Self { sender, counters }
+    }
+
+    /// Start the prefetches of a scan reading `readahead` blocks ahead in each SST.
+    pub fn new_scan(&self, readahead: usize) -> Arc<ScanPrefetch> {
+        Arc::new(ScanPrefetch {
+            readahead,
+            sender: self.sender.clone(),
+            counters: self.counters.clone(),
+            cancelled: AtomicBool::new(false),
+            unread: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn stats(&self) -> PrefetchStats {
+        PrefetchStats {
+            prefetched_bytes: self.counters.prefetched_bytes.load(Ordering::Relaxed),
+            wasted_bytes: self.counters.wasted_bytes.load(Ordering::Relaxed),
+            cancelled_blocks: self.counters.cancelled_blocks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Prefetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrefetchJob {
+    fn run(self) -> anyhow::Result<()> {
+        let scan = &self.scan;
+        if scan.cancelled.load(Ordering::Acquire) {
+            scan.counters
+                .cancelled_blocks
+                .fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        if self.table.read_block_from_cache(self.block_idx).is_some() {
+            return Ok(());
+        }
+        self.table.read_block_cached(self.block_idx)?;
+        let len = self.table.block_len(self.block_idx)? as u64;
+        scan.counters
+            .prefetched_bytes
+            .fetch_add(len, Ordering::Relaxed);
+        let mut unread = scan.unread.lock();
+        if scan.cancelled.load(Ordering::Acquire) {
+            scan.counters.wasted_bytes.fetch_add(len, Ordering::Relaxed);
+        } else {
+            unread.insert((self.table.sst_id(), self.block_idx), len);
+        }
+        Ok(())
+    }
+}
+
+/// The prefetches of one scan, shared by its SST iterators.
+pub struct ScanPrefetch {
+    readahead: usize,
+    sender: Sender<PrefetchJob>,
+    counters: Arc<PrefetchCounters>,
+    cancelled: AtomicBool,
+    /// The prefetched blocks the scan has not read yet, with their sizes.
+    unread: Mutex<HashMap<(usize, usize), u64>>,
+}
+
+impl ScanPrefetch {
+    pub fn readahead(&self) -> usize {
+        self.readahead
+    }
+
+    /// Queue the prefetch of a block.
+    pub fn prefetch(self: &Arc<Self>, table: &Arc<SsTable>, block_idx: usize) {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let job = PrefetchJob {
+            table: table.clone(),
+            block_idx,
+            scan: self.clone(),
+        };
+        self.sender.send(job).ok();
+    }
+
+    /// Record that the scan read a block.
+    pub fn read(&self, sst_id: usize, block_idx: usize) {
+        self.unread.lock().remove(&(sst_id, block_idx));
+    }
+
+    /// Drop the queued prefetches and count the unread prefetched blocks as wasted.
+    pub fn cancel(&self) {
+        let mut unread = self.unread.lock();
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let wasted = unread.drain().map(|(_, len)| len).sum();
+        self.counters
+            .wasted_bytes
+            .fetch_add(wasted, Ordering::Relaxed);
+    }
+}
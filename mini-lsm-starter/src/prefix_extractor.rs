@@ -0,0 +1,45 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prefix extractors, which map the keys to the prefixes that the prefix bloom filters of the
+//! SSTs are built over, so that `MiniLsm::scan_prefix` skips the SSTs without a prefix. See
+//! `LsmStorageOptions::prefix_extractor`.
+
+use std::fmt::Debug;
+
+pub trait PrefixExtractor: Send + Sync + Debug {
+    /// The name stored in the SSTs built with this extractor. Only the SSTs built with an
+    /// extractor of the same name are skipped by prefix scans, so extractors that map the keys
+    /// differently must have different names.
+    fn name(&self) -> String;
+
+    /// The prefix of `key`, or `None` to leave the key out of the prefix bloom filter. If
+    /// `extract(prefix)` is `prefix`, every key starting with `prefix` must have `prefix` as its
+    /// prefix too, as prefix scans only skip SSTs for such prefixes.
+    fn extract<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]>;
+}
+
+/// The first `len` bytes of the keys that are at least as long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn name(&self) -> String {
+        format!("fixed:{}", self.0)
+    }
+
+    fn extract<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        key.get(..self.0)
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attributes the bytes written and stored to tenants, identified by key prefixes, and enforces
+//! the optional per-tenant limits on the write path.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+
+use crate::lsm_storage::LsmStorageState;
+
+/// A tenant owning all keys starting with `prefix`.
+#[derive(Debug, Clone)]
+pub struct TenantQuota {
+    pub prefix: Bytes,
+    /// Reject puts once the tenant stores more than this many bytes.
+    pub limit: Option<u64>,
+}
+
+/// The bytes attributed to a tenant. Both counts include the size of keys and values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    /// All bytes ever written through puts and deletes.
+    pub bytes_written: u64,
+    /// The bytes in the memtables and SSTs. Overwritten and deleted entries are counted until a
+    /// flush or compaction removes them.
+    pub bytes_stored: u64,
+}
+
+/// The error returned when a put would exceed the limit of a tenant.
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    pub prefix: Bytes,
+    pub bytes_stored: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "quota exceeded for prefix {:?}: {} bytes stored, limit is {}",
+            self.prefix, self.bytes_stored, self.limit
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// The index of the tenant owning `key`, preferring the longest matching prefix.
+pub(crate) fn tenant_of<'a>(
+    prefixes: impl IntoIterator<Item = &'a [u8]>,
+    key: &[u8],
+) -> Option<usize> {
+    prefixes
+        .into_iter()
+        .enumerate()
+        .filter(|(_, prefix)| key.starts_with(prefix))
+        .max_by_key(|(_, prefix)| prefix.len())
+        .map(|(idx, _)| idx)
+}
+
+pub(crate) struct QuotaTracker {
+    tenants: Vec<TenantQuota>,
+    prefixes: Vec<Bytes>,
+    bytes_written: Vec<AtomicU64>,
+    /// The bytes of each tenant in each memtable, keyed by memtable id.
+    memtable_bytes: Mutex<HashMap<usize, Vec<u64>>>,
+    /// The bytes of each tenant in the SSTs, summed from the table properties.
+    sst_bytes: RwLock<Vec<u64>>,
+}
+
+impl QuotaTracker {
+    pub(crate) fn new(tenants: Vec<TenantQuota>) -> Self {
+        let num_tenants = tenants.len();
+        Self {
+            prefixes: tenants.iter().map(|tenant| tenant.prefix.clone()).collect(),
+            tenants,
+            bytes_written: (0..num_tenants).map(|_| AtomicU64::new(0)).collect(),
+            memtable_bytes: Mutex::new(HashMap::new()),
+            sst_bytes: RwLock::new(vec![0; num_tenants]),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.tenants.is_empty()
+    }
+
+    /// The prefixes whose sizes are recorded in the table properties.
+    pub(crate) fn prefixes(&self) -> &[Bytes] {
+        &self.prefixes
+    }
+
+    fn usage_of(&self, idx: usize) -> TenantUsage {
+        let memtable_bytes = self
+            .memtable_bytes
+            .lock()
+            .values()
+            .map(|bytes| bytes[idx])
+            .sum::<u64>();
+        TenantUsage {
+            bytes_written: self.bytes_written[idx].load(Ordering::Relaxed),
+            bytes_stored: self.sst_bytes.read()[idx] + memtable_bytes,
+        }
+    }
+
+    pub(crate) fn usage(&self, prefix: &[u8]) -> Option<TenantUsage> {
+        let idx = self.prefixes.iter().position(|p| p == prefix)?;
+        Some(self.usage_of(idx))
+    }
+
+    pub(crate) fn usages(&self) -> Vec<(Bytes, TenantUsage)> {
+        (0..self.tenants.len())
+            .map(|idx| (self.prefixes[idx].clone(), self.usage_of(idx)))
+            .collect()
+    }
+
+    /// Fail if a put of `key` would add to a tenant already over its limit.
+    pub(crate) fn check(&self, key: &[u8]) -> Result<(), QuotaExceeded> {
+        let Some(idx) = tenant_of(self.prefixes.iter().map(|p| &p[..]), key) else {
+            return Ok(());
+        };
+        let Some(limit) = self.tenants[idx].limit else {
+            return Ok(());
+        };
+        let bytes_stored = self.usage_of(idx).bytes_stored;
+        if bytes_stored >= limit {
+            return Err(QuotaExceeded {
+                prefix: self.prefixes[idx].clone(),
+                bytes_stored,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Attribute a write of `bytes` to the owner of `key` and to the memtable it went to.
+    pub(crate) fn record_write(&self, memtable_id: usize, key: &[u8], bytes: usize) {
+        let Some(idx) = tenant_of(self.prefixes.iter().map(|p| &p[..]), key) else {
+            return;
+        };
+        self.bytes_written[idx].fetch_add(bytes as u64, Ordering::Relaxed);
+        self.memtable_bytes
+            .lock()
+            .entry(memtable_id)
+            .or_insert_with(|| vec![0; self.tenants.len()])[idx] += bytes as u64;
+    }
+
+    /// Recompute the bytes stored in the SSTs after the set of SSTs changed. The memtables no
+    /// longer in `snapshot` have been flushed, so their bytes are now counted in the SSTs.
+    pub(crate) fn refresh(&self, snapshot: &LsmStorageState) {
+        let mut sst_bytes = vec![0; self.tenants.len()];
+        for sst in snapshot.sstables.values() {
+            for (prefix, bytes) in &sst.properties().prefix_sizes {
+                if let Some(idx) = self.prefixes.iter().position(|p| p == prefix) {
+                    sst_bytes[idx] += bytes;
+                }
+            }
+        }
+        *self.sst_bytes.write() = sst_bytes;
+        self.memtable_bytes.lock().retain(|id, _| {
+            *id == snapshot.memtable.id() || snapshot.imm_memtables.iter().any(|m| m.id() == *id)
+        });
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Moving a key range between storage directories, for a sharding layer that splits a shard
+//! into two directories or merges two shards into one.
+//!
+//! `export_range` writes the SSTs holding a range into a directory: the SSTs entirely inside the
+//! range are copied as they are, and only those whose keys or range tombstones cross one of the
+//! bounds are rewritten, keeping the parts of their range tombstones inside the range. The
+//! `EXPORT` file, written last, lists them from the newest to the oldest. `import_range` adds
+//! them to another storage on top of L0, where the compactions eventually merge them.
+
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail, ensure};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::iterators::StorageIterator;
+use crate::key::{KeySlice, TS_RANGE_BEGIN};
+use crate::lsm_storage::LsmStorageInner;
+use crate::manifest::ManifestRecord;
+use crate::table::{FileObject, SsTable, SsTableIterator};
+use crate::write_observer::FileKind;
+
+pub const EXPORT_FILE: &str = "EXPORT";
+
+/// The SSTs holding the keys in `[lower, upper)`, as written by `export_range`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeExport {
+    pub lower: Vec<u8>,
+    pub upper: Vec<u8>,
+    /// From the newest to the oldest.
+    pub ssts: Vec<ExportedSst>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedSst {
+    /// The file name in the export directory.
+    pub file: String,
+    /// Whether the SST crossed a bound of the range and was rewritten, rather than copied.
+    pub rewritten: bool,
+}
+
+impl RangeExport {
+    /// Read the `EXPORT` file of an export directory.
+    pub fn read(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(EXPORT_FILE);
+        let buf = std::fs::read(&path)
+            .with_context(|| format!("{} is not a complete export", dir.as_ref().display()))?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+}
+
+fn sync_dir(dir: &Path) -> Result<()> {
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+impl LsmStorageInner {
+    /// Write the SSTs holding the keys in `[lower, upper)` to the empty or missing directory
+    /// `dir`. The memtables are flushed first, and the compactions wait until the export is
+    /// done. Writes to the range should be stopped before, as those made during the export may
+    /// be left out.
+    pub fn export_range(
+        &self,
+        lower: &[u8],
+        upper: &[u8],
+        dir: impl AsRef<Path>,
+    ) -> Result<RangeExport> {
+        let dir = dir.as_ref();
+        ensure!(
+            lower < upper,
+            "the lower bound must be less than the upper bound"
+        );
+        std::fs::create_dir_all(dir)?;
+        ensure!(
+            std::fs::read_dir(dir)?.next().is_none(),
+            "export directory {} is not empty",
+            dir.display()
+        );
+        self.force_flush_all()?;
+        // Compactions would delete the files we copy
+        let _compaction_lock = self.compaction_lock.lock();
+        let snapshot = {
+            let state = self.state.read();
+            Arc::clone(&state)
+        };
+        let sst_ids = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flat_map(|(_, level)| level.iter()));
+        let mut export = RangeExport {
+            lower: lower.to_vec(),
+            upper: upper.to_vec(),
+            ssts: Vec::new(),
+        };
+        for sst_id in sst_ids {
+            let sst = &snapshot.sstables[sst_id];
+            if sst.last_key().key_ref() < lower || sst.first_key().key_ref() >= upper {
+                continue;
+            }
+            let file = format!("{:05}.sst", export.ssts.len());
+            // The key range of an SST covers its range tombstones, whose exclusive end may be
+            // `upper` itself, so the last key is checked apart
+            let last_key = sst
+                .metadata()?
+                .block_meta()
+                .last()
+                .unwrap()
+                .last_key
+                .clone();
+            let tombstones = sst.range_tombstones();
+            let rewritten = sst.first_key().key_ref() < lower
+                || last_key.key_ref() >= upper
+                || tombstones.clip(lower, upper) != *tombstones;
+            if !rewritten {
+                std::fs::copy(self.path_of_sst(*sst_id), dir.join(&file))?;
+                std::fs::File::open(dir.join(&file))?.sync_all()?;
+            } else if !self.rewrite_range(sst, lower, upper, &dir.join(&file))? {
+                // No key of the SST is in the range
+                continue;
+            }
+            export.ssts.push(ExportedSst { file, rewritten });
+        }
+        let path = dir.join(EXPORT_FILE);
+        std::fs::write(&path, serde_json::to_vec(&export)?)?;
+        std::fs::File::open(&path)?.sync_all()?;
+        sync_dir(dir)?;
+        Ok(export)
+    }
+
+    /// Write the entries of `sst` in `[lower, upper)` to `path`, including the deletes and the
+    /// parts of the range tombstones in the range. Returns whether there were any.
+    fn rewrite_range(
+        &self,
+        sst: &Arc<SsTable>,
+        lower: &[u8],
+        upper: &[u8],
+        path: &Path,
+    ) -> Result<bool> {
+        let mut iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            KeySlice::from_slice(lower, TS_RANGE_BEGIN),
+        )?;
+        let mut builder = self.new_sst_builder();
+        builder.add_range_tombstones(&sst.range_tombstones().clip(lower, upper));
+        while iter.is_valid() && iter.key().key_ref() < upper {
+            builder.add_entry(
+                iter.key(),
+                iter.value(),
+                iter.value_meta(),
+                iter.is_deleted(),
+            );
+            iter.next()?;
+        }
+        if builder.is_empty() {
+            return Ok(false);
+        }
+        let sst = Arc::new(builder.build(0, None, path)?);
+        self.sync_new_ssts(&[sst])?;
+        Ok(true)
+    }
+
+    /// Add the SSTs exported to `dir` by `export_range` on top of L0, keeping their order. The
+    /// storage must not have any key in the range of the export, e.g., when merging two shards.
+    /// Returns the ids of the imported SSTs, from the oldest to the newest.
+    pub fn import_range(&self, dir: impl AsRef<Path>) -> Result<Vec<usize>> {
+        let dir = dir.as_ref();
+        let export = RangeExport::read(dir)?;
+        let range = (
+            Bound::Included(export.lower.as_slice()),
+            Bound::Excluded(export.upper.as_slice()),
+        );
+        if self.scan(range.0, range.1)?.is_valid() {
+            bail!(
+                "cannot import range {:?}..{:?} over existing keys",
+                bytes::Bytes::copy_from_slice(&export.lower),
+                bytes::Bytes::copy_from_slice(&export.upper)
+            );
+        }
+        // Copy the files from the oldest to the newest before installing them, as each goes on
+        // top of L0
+        let mut ssts = Vec::with_capacity(export.ssts.len());
+        for exported in export.ssts.iter().rev() {
+            let id = self.next_sst_id();
+            let path = self.path_of_sst(id);
+            let bytes = std::fs::copy(dir.join(&exported.file), &path)
+                .with_context(|| format!("failed to import {}", exported.file))?;
+            self.write_observers.notify(FileKind::Sst, bytes);
+            std::fs::File::open(&path)?.sync_all()?;
+            let sst = SsTable::open(id, Some(self.block_cache.clone()), FileObject::open(&path)?)?
+                .with_checksum_verification(self.options.verify_block_checksums);
+            if let Some(shared_metadata) = &self.shared_metadata {
+                sst.publish_metadata(shared_metadata)?;
+            }
+            ssts.push(Arc::new(sst));
+        }
+        self.sync_dir()?;
+        self.install_l0_ssts(ssts)
+    }
+
+    /// Add SSTs on top of L0, or as new tiers with tiered compaction, from the oldest to the
+    /// newest, once their files are synced. Returns their ids.
+    pub(crate) fn install_l0_ssts(&self, ssts: Vec<Arc<SsTable>>) -> Result<Vec<usize>> {
+        // The reads see the keys of the SSTs once the commit timestamp reaches theirs
+        let max_ts = ssts.iter().map(|sst| sst.max_ts()).max();
+        let _write_lock = self.write_lock.lock();
+        if let Some(max_ts) = max_ts
+            && max_ts > self.mvcc().latest_commit_ts()
+        {
+            self.mvcc().update_commit_ts(max_ts);
+        }
+        // The transactions treat the SSTs as writes to their whole key ranges
+        self.mvcc()
+            .record_commit(self.mvcc().latest_commit_ts(), |writes| {
+                for sst in &ssts {
+                    writes.ranges.push((
+                        Bound::Included(Bytes::copy_from_slice(sst.first_key().key_ref())),
+                        Bound::Included(Bytes::copy_from_slice(sst.last_key().key_ref())),
+                    ));
+                }
+            });
+        let state_lock = self.state_lock.lock();
+        let ids = ssts.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+        let flush_to_l0 = self.compaction_controller().flush_to_l0();
+        {
+            let mut state = self.state.write();
+            let mut snapshot = state.as_ref().clone();
+            for sst in ssts {
+                snapshot.add_flushed_sst(sst.sst_id(), flush_to_l0);
+                snapshot.sstables.insert(sst.sst_id(), sst);
+            }
+            snapshot.update_sst_levels();
+            self.quotas.refresh(&snapshot);
+            self.key_distribution.refresh(&snapshot);
+            *state = Arc::new(snapshot);
+        }
+        self.invalidate_negative_cache();
+        if let Some(manifest) = &self.manifest {
+            for id in &ids {
+                manifest.add_record(&state_lock, ManifestRecord::Flush(*id))?;
+            }
+        }
+        Ok(ids)
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Range tombstones written by `delete_range`, and the iterator hiding the keys they delete.
+
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::iterators::StorageIterator;
+use crate::key::{KeySlice, KeyVec, TS_DEFAULT};
+
+/// The key ranges deleted by the range tombstones of a memtable or an SST, as sorted and disjoint
+/// `[start, end)` ranges, each with the timestamp of the earliest range delete covering it.
+///
+/// The tombstones of a memtable or SST only delete the keys of the older memtables and SSTs, for
+/// the reads at or after their timestamp. The keys it stores itself were either written after the
+/// range was deleted, or deleted with point deletes at the same time, which compactions add too
+/// (see `RangeDeleteIterator::for_compaction`), so overlapping tombstones are merged into the
+/// fragments they share, keeping the earlier timestamp.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeTombstones {
+    ranges: Vec<(Bytes, Bytes, u64)>,
+}
+
+impl RangeTombstones {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete the keys in `[start, end)` at timestamp `ts`, fragmenting the ranges it overlaps.
+    /// Empty ranges are ignored.
+    pub fn insert(&mut self, start: &[u8], end: &[u8], ts: u64) {
+        if start >= end {
+            return;
+        }
+        let first = self.ranges.partition_point(|(_, e, _)| e.as_ref() <= start);
+        let last = self.ranges.partition_point(|(s, _, _)| s.as_ref() < end);
+        // The fragments replacing the overlapped ones, where `cursor` is the end of the last one
+        let mut fragments = Vec::with_capacity(last - first + 2);
+        let mut cursor = Bytes::copy_from_slice(start);
+        for (s, e, t) in self.ranges.drain(first..last) {
+            if s.as_ref() < start {
+                fragments.push((s, cursor.clone(), t));
+            } else if s > cursor {
+                fragments.push((cursor, s.clone(), ts));
+                cursor = s;
+            }
+            if e.as_ref() > end {
+                let end = Bytes::copy_from_slice(end);
+                fragments.push((cursor, end.clone(), t.min(ts)));
+                cursor = end.clone();
+                fragments.push((end, e, t));
+                break;
+            }
+            fragments.push((cursor, e.clone(), t.min(ts)));
+            cursor = e;
+        }
+        if cursor.as_ref() < end {
+            fragments.push((cursor, Bytes::copy_from_slice(end), ts));
+        }
+        self.ranges.splice(first..first, fragments);
+        // Merge the touching fragments with the same timestamp
+        self.ranges.dedup_by(|(s, e, t), (_, prev_e, prev_t)| {
+            if prev_e != s || prev_t != t {
+                return false;
+            }
+            *prev_e = e.clone();
+            true
+        });
+    }
+
+    /// Delete the ranges of `other` too.
+    pub fn extend(&mut self, other: &RangeTombstones) {
+        for (start, end, ts) in other.iter() {
+            self.insert(start, end, ts);
+        }
+    }
+
+    /// Whether `key` is in one of the ranges deleted at or before `read_ts`.
+    pub fn covers(&self, key: &[u8], read_ts: u64) -> bool {
+        let idx = self
+            .ranges
+            .partition_point(|(_, end, _)| end.as_ref() <= key);
+        self.ranges
+            .get(idx)
+            .is_some_and(|(start, _, ts)| start.as_ref() <= key && *ts <= read_ts)
+    }
+
+    /// Whether any key in `[lower, upper]` is in one of the ranges.
+    pub fn overlaps(&self, lower: &[u8], upper: &[u8]) -> bool {
+        let idx = self
+            .ranges
+            .partition_point(|(_, end, _)| end.as_ref() <= lower);
+        self.ranges
+            .get(idx)
+            .is_some_and(|(start, _, _)| start.as_ref() <= upper)
+    }
+
+    /// The ranges as `(start, end, ts)` triples, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8], u64)> {
+        self.ranges
+            .iter()
+            .map(|(start, end, ts)| (start.as_ref(), end.as_ref(), *ts))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// The start of the first range.
+    pub fn first_key(&self) -> Option<&[u8]> {
+        self.ranges.first().map(|(start, _, _)| start.as_ref())
+    }
+
+    /// The exclusive end of the last range.
+    pub fn end_key(&self) -> Option<&[u8]> {
+        self.ranges.last().map(|(_, end, _)| end.as_ref())
+    }
+
+    /// The latest timestamp of the ranges.
+    pub fn max_ts(&self) -> Option<u64> {
+        self.ranges.iter().map(|(_, _, ts)| *ts).max()
+    }
+
+    /// Remove and return the parts of the ranges before `key`, cutting the range that spans it
+    /// in two.
+    pub fn split_before(&mut self, key: &[u8]) -> RangeTombstones {
+        let idx = self
+            .ranges
+            .partition_point(|(_, end, _)| end.as_ref() <= key);
+        let mut before = self.ranges.drain(..idx).collect::<Vec<_>>();
+        if let Some((start, _, ts)) = self.ranges.first_mut()
+            && start.as_ref() < key
+        {
+            let key = Bytes::copy_from_slice(key);
+            before.push((std::mem::replace(start, key.clone()), key, *ts));
+        }
+        RangeTombstones { ranges: before }
+    }
+
+    /// The parts of the ranges in `[lower, upper)`.
+    pub fn clip(&self, lower: &[u8], upper: &[u8]) -> RangeTombstones {
+        let ranges = self
+            .iter()
+            .filter_map(|(start, end, ts)| {
+                let (start, end) = (start.max(lower), end.min(upper));
+                (start < end).then(|| {
+                    (
+                        Bytes::copy_from_slice(start),
+                        Bytes::copy_from_slice(end),
+                        ts,
+                    )
+                })
+            })
+            .collect();
+        RangeTombstones { ranges }
+    }
+
+    /// The in-memory size of the ranges.
+    pub fn size(&self) -> usize {
+        self.iter()
+            .map(|(start, end, _)| start.len() + end.len() + std::mem::size_of::<u64>())
+            .sum()
+    }
+
+    /// Encode the ranges, each followed by its timestamp since format version 9.
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u32(self.ranges.len() as u32);
+        for (start, end, ts) in &self.ranges {
+            buf.put_u16(start.len() as u16);
+            buf.put_slice(start);
+            buf.put_u16(end.len() as u16);
+            buf.put_slice(end);
+            buf.put_u64(*ts);
+        }
+    }
+
+    /// Decode the ranges, whose timestamps are `TS_DEFAULT` if `timestamps` is false as in the
+    /// SSTs before format version 9.
+    pub(crate) fn decode(buf: &mut impl Buf, timestamps: bool) -> Result<Self> {
+        if buf.remaining() < 4 {
+            bail!("range tombstones are truncated");
+        }
+        let num_ranges = buf.get_u32() as usize;
+        let mut ranges = Vec::with_capacity(num_ranges.min(buf.remaining() / 4));
+        for _ in 0..num_ranges {
+            let mut read_key = || {
+                if buf.remaining() < 2 {
+                    bail!("range tombstones are truncated");
+                }
+                let len = buf.get_u16() as usize;
+                if buf.remaining() < len {
+                    bail!("range tombstones are truncated");
+                }
+                Ok(buf.copy_to_bytes(len))
+            };
+            let (start, end) = (read_key()?, read_key()?);
+            let ts = match timestamps {
+                true if buf.remaining() < 8 => bail!("range tombstones are truncated"),
+                true => buf.get_u64(),
+                false => TS_DEFAULT,
+            };
+            ranges.push((start, end, ts));
+        }
+        Ok(Self { ranges })
+    }
+}
+
+/// Skips the entries of an iterator over a memtable or over SSTs that are deleted by the range
+/// tombstones of the newer memtables and SSTs, for a read at `read_ts`.
+pub struct RangeDeleteIterator<I> {
+    iter: I,
+    tombstones: Arc<RangeTombstones>,
+    read_ts: u64,
+    /// The first range that does not end before the current key. The keys only grow, so the
+    /// ranges before it are never checked again.
+    next_range: usize,
+    /// Whether the tombstones after `read_ts` are turned into point deletes, see `for_compaction`.
+    emit_deletes: bool,
+    /// The point delete yielded before the current entry of `iter`, if any.
+    delete: Option<KeyVec>,
+    /// The key of the last point delete yielded.
+    deleted_key: Vec<u8>,
+    /// Whether the keys of `iter` are in descending order, see `new_rev`.
+    reverse: bool,
+}
+
+impl<I> RangeDeleteIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    pub fn new(iter: I, tombstones: Arc<RangeTombstones>, read_ts: u64) -> Result<Self> {
+        Self::create(iter, tombstones, read_ts, false)
+    }
+
+    /// Drop the entries deleted by tombstones at or below the watermark, like a read at the
+    /// watermark. The keys deleted by a later tombstone are kept for the reads before it, and get
+    /// a point delete at the timestamp of the tombstone, so that they stay deleted for the reads
+    /// after it once the compaction output holds them with the tombstone.
+    pub fn for_compaction(
+        iter: I,
+        tombstones: Arc<RangeTombstones>,
+        watermark: u64,
+    ) -> Result<Self> {
+        Self::create(iter, tombstones, watermark, true)
+    }
+
+    /// Drop the entries deleted as of `read_ts` from an iterator yielding its keys in descending
+    /// order.
+    pub fn new_rev(iter: I, tombstones: Arc<RangeTombstones>, read_ts: u64) -> Result<Self> {
+        let mut iter = Self {
+            iter,
+            tombstones,
+            read_ts,
+            next_range: 0,
+            emit_deletes: false,
+            delete: None,
+            deleted_key: Vec::new(),
+            reverse: true,
+        };
+        iter.skip_deleted_ranges()?;
+        Ok(iter)
+    }
+
+    fn create(
+        iter: I,
+        tombstones: Arc<RangeTombstones>,
+        read_ts: u64,
+        emit_deletes: bool,
+    ) -> Result<Self> {
+        let mut iter = Self {
+            iter,
+            tombstones,
+            read_ts,
+            next_range: 0,
+            emit_deletes,
+            delete: None,
+            deleted_key: Vec::new(),
+            reverse: false,
+        };
+        iter.skip_deleted_ranges()?;
+        Ok(iter)
+    }
+
+    fn skip_deleted_ranges(&mut self) -> Result<()> {
+        if self.reverse {
+            while self.iter.is_valid()
+                && self
+                    .tombstones
+                    .covers(self.iter.key().key_ref(), self.read_ts)
+            {
+                self.iter.next()?;
+            }
+            return Ok(());
+        }
+        let ranges = &self.tombstones.ranges;
+        while self.iter.is_valid() {
+            let key = self.iter.key();
+            while ranges
+                .get(self.next_range)
+                .is_some_and(|(_, end, _)| end.as_ref() <= key.key_ref())
+            {
+                self.next_range += 1;
+            }
+            match ranges.get(self.next_range) {
+                Some((start, _, ts)) if start.as_ref() <= key.key_ref() => {
+                    if *ts <= self.read_ts {
+                        self.iter.next()?;
+                        continue;
+                    }
+                    if self.emit_deletes && key.ts() < *ts && key.key_ref() != self.deleted_key {
+                        self.deleted_key.clear();
+                        self.deleted_key.extend_from_slice(key.key_ref());
+                        self.delete = Some(KeyVec::from_vec_with_ts(key.key_ref().to_vec(), *ts));
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I> StorageIterator for RangeDeleteIterator<I>
+where
+    I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+{
+    type KeyType<'a>
+        = KeySlice<'a>
+    where
+        Self: 'a;
+
+    fn key(&self) -> KeySlice<'_> {
+        match &self.delete {
+            Some(key) => key.as_key_slice(),
+            None => self.iter.key(),
+        }
+    }
+
+    fn value(&self) -> &[u8] {
+        match self.delete {
+            Some(_) => &[],
+            None => self.iter.value(),
+        }
+    }
+
+    fn value_meta(&self) -> u8 {
+        match self.delete {
+            Some(_) => 0,
+            None => self.iter.value_meta(),
+        }
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.delete.is_some() || self.iter.is_deleted()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.delete.is_some() || self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        // The entry after the point delete was checked already
+        if self.delete.take().is_some() {
+            return Ok(());
+        }
+        self.iter.next()?;
+        self.skip_deleted_ranges()
+    }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        self.iter.seek(key)?;
+        self.next_range = self
+            .tombstones
+            .ranges
+            .partition_point(|(_, end, _)| end.as_ref() <= key.key_ref());
+        self.delete = None;
+        self.deleted_key.clear();
+        self.skip_deleted_ranges()
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.iter.num_active_iterators()
+    }
+}
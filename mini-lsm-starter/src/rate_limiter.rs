@@ -0,0 +1,93 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A token bucket limiting the rate of the writes that opt into it with `WriteOptions`.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Limits the number of bytes per second passing through `request`. The bucket holds up to one
+/// second worth of bytes, so a writer idle for a while can burst up to the rate.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    bytes_per_sec: Option<u64>,
+    /// The bytes that can be written without waiting, negative if requests already reserved the
+    /// bytes that will be refilled in the future.
+    available: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, bytes_per_sec: u64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available =
+            (self.available + elapsed * bytes_per_sec as f64).min(bytes_per_sec as f64);
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// Create a rate limiter, which does not limit anything if `bytes_per_sec` is `None`.
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                bytes_per_sec,
+                available: bytes_per_sec.unwrap_or(0) as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> Option<u64> {
+        self.bucket.lock().bytes_per_sec
+    }
+
+    /// Change the rate, taking effect for the next request. A rate of 0 is treated as 1 byte per
+    /// second to keep the writers making progress.
+    pub fn set_bytes_per_sec(&self, bytes_per_sec: Option<u64>) {
+        let mut bucket = self.bucket.lock();
+        if let Some(old_rate) = bucket.bytes_per_sec {
+            bucket.refill(old_rate);
+        }
+        let bytes_per_sec = bytes_per_sec.map(|rate| rate.max(1));
+        if bucket.bytes_per_sec.is_none() {
+            bucket.available = bytes_per_sec.unwrap_or(0) as f64;
+        }
+        bucket.bytes_per_sec = bytes_per_sec;
+        bucket.last_refill = Instant::now();
+    }
+
+    /// Reserve `bytes` and sleep until the bucket has refilled them. Requests are served in the
+    /// order they reserve, and a request larger than the bucket simply waits longer.
+    pub fn request(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock();
+            let Some(bytes_per_sec) = bucket.bytes_per_sec else {
+                return;
+            };
+            bucket.refill(bytes_per_sec);
+            bucket.available -= bytes as f64;
+            if bucket.available >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.available / bytes_per_sec as f64)
+        };
+        std::thread::sleep(wait);
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scans serialized into chunks of length-prefixed frames, which a server can send as they are,
+//! e.g., one chunk per gRPC message, instead of copying each entry into its own message.
+//!
+//! Each frame is `key_len (4B) | key | value_len (4B) | value`, and a chunk is a sequence of
+//! frames. See `MiniLsm::scan_chunks`.
+
+use anyhow::{Result, bail};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+
+const FRAME_HEADER_SIZE: usize = 2 * 4;
+
+/// The bytes of the frame of an entry.
+pub fn frame_size(key: &[u8], value: &[u8]) -> usize {
+    FRAME_HEADER_SIZE + key.len() + value.len()
+}
+
+/// Splits the entries of an iterator into chunks of at most `max_bytes_per_chunk` bytes. An entry
+/// whose frame alone exceeds the limit gets a chunk of its own. The chunks end after the first
+/// error.
+pub struct ScanChunks {
+    iter: FusedIterator<LsmIterator>,
+    max_bytes_per_chunk: usize,
+    failed: bool,
+}
+
+impl ScanChunks {
+    pub fn new(iter: FusedIterator<LsmIterator>, max_bytes_per_chunk: usize) -> Self {
+        Self {
+            iter,
+            max_bytes_per_chunk,
+            failed: false,
+        }
+    }
+
+    fn next_chunk(&mut self) -> Result<Bytes> {
+        let mut chunk = Vec::with_capacity(self.max_bytes_per_chunk);
+        while self.iter.is_valid() {
+            let (key, value) = (self.iter.key(), self.iter.value());
+            if !chunk.is_empty() && chunk.len() + frame_size(key, value) > self.max_bytes_per_chunk
+            {
+                break;
+            }
+            chunk.put_u32(key.len() as u32);
+            chunk.put_slice(key);
+            chunk.put_u32(value.len() as u32);
+            chunk.put_slice(value);
+            self.iter.next()?;
+        }
+        Ok(chunk.into())
+    }
+}
+
+impl Iterator for ScanChunks {
+    type Item = Result<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || !self.iter.is_valid() {
+            return None;
+        }
+        let chunk = self.next_chunk();
+        self.failed = chunk.is_err();
+        Some(chunk)
+    }
+}
+
+/// Decode the entries of a chunk, sharing its memory.
+pub fn decode_chunk(chunk: &Bytes) -> Result<Vec<(Bytes, Bytes)>> {
+    let mut entries = Vec::new();
+    let mut buf = chunk.clone();
+    while buf.has_remaining() {
+        let mut field = || {
+            if buf.remaining() < 4 {
+                bail!("chunk is truncated");
+            }
+            let len = buf.get_u32() as usize;
+            if buf.remaining() < len {
+                bail!("chunk is truncated");
+            }
+            Ok(buf.split_to(len))
+        };
+        let key = field()?;
+        let value = field()?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memory accounting of the blocks held by the SST iterators of a scan. A scan over many
+//! memtables and SSTs holds a decoded block for each SST it merges, which is bounded by
+//! `LsmStorageOptions::scan_memory_budget`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Result, bail};
+
+/// What a scan does when its blocks would exceed the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanBudgetAction {
+    /// Keep scanning. A scan always reads the SSTs of each sorted level one after another, so it
+    /// holds one block per level, and the budget is only exceeded if there are many L0 SSTs.
+    OneBlockPerLevel,
+    /// Fail the scan with an error.
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanMemoryBudget {
+    pub bytes: usize,
+    pub on_exceeded: ScanBudgetAction,
+}
+
+/// The bytes of the blocks currently held by the SST iterators of a scan.
+#[derive(Debug)]
+pub struct ScanMemory {
+    budget: ScanMemoryBudget,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl ScanMemory {
+    pub fn new(budget: ScanMemoryBudget) -> Self {
+        Self {
+            budget,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn charge(&self, bytes: usize) {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(used, Ordering::Relaxed);
+    }
+
+    pub fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The most bytes held at once so far.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn budget(&self) -> ScanMemoryBudget {
+        self.budget
+    }
+
+    /// Fail if the scan is over budget and configured to fail.
+    pub fn check(&self) -> Result<()> {
+        let used = self.used();
+        if self.budget.on_exceeded == ScanBudgetAction::Fail && used > self.budget.bytes {
+            bail!(
+                "scan holds {} bytes of blocks, exceeding its memory budget of {} bytes",
+                used,
+                self.budget.bytes
+            );
+        }
+        Ok(())
+    }
+}
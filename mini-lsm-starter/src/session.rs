@@ -0,0 +1,97 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-your-writes sessions across a primary and its replicas. Each write is assigned the next
+//! sequence of the instance. A replica applies the writes shipped from the primary with the
+//! primary's sequences, so a reader holding the `SessionToken` of its last write on the primary
+//! can wait until a replica has caught up with it.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::{Condvar, Mutex};
+
+/// The sequence of the last write a session has seen committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SessionToken {
+    pub sequence: u64,
+}
+
+impl SessionToken {
+    pub fn encode(&self) -> [u8; 8] {
+        self.sequence.to_be_bytes()
+    }
+
+    pub fn decode(buf: [u8; 8]) -> Self {
+        Self {
+            sequence: u64::from_be_bytes(buf),
+        }
+    }
+}
+
+/// The error returned when an instance has not applied the writes of a session in time. It is an
+/// `std::io::Error` of kind `TimedOut`, so that callers can retry or read from the primary.
+fn session_timed_out(token: SessionToken, applied: u64) -> anyhow::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        format!(
+            "timed out waiting for sequence {}, applied up to {}",
+            token.sequence, applied
+        ),
+    )
+    .into()
+}
+
+#[derive(Default)]
+pub(crate) struct SequenceTracker {
+    applied: Mutex<u64>,
+    cond: Condvar,
+}
+
+impl SequenceTracker {
+    /// Assign the next sequence to a write that was just applied.
+    pub(crate) fn advance(&self) -> u64 {
+        let mut applied = self.applied.lock();
+        *applied += 1;
+        self.cond.notify_all();
+        *applied
+    }
+
+    /// Record a replicated write applied with the sequence assigned by the primary.
+    pub(crate) fn advance_to(&self, sequence: u64) {
+        let mut applied = self.applied.lock();
+        if sequence > *applied {
+            *applied = sequence;
+            self.cond.notify_all();
+        }
+    }
+
+    pub(crate) fn token(&self) -> SessionToken {
+        SessionToken {
+            sequence: *self.applied.lock(),
+        }
+    }
+
+    /// Block until the writes up to the sequence of `token` have been applied.
+    pub(crate) fn wait_for(&self, token: SessionToken, timeout: Duration) -> Result<()> {
+        let mut applied = self.applied.lock();
+        let _ =
+            self.cond
+                .wait_while_for(&mut applied, |applied| *applied < token.sequence, timeout);
+        if *applied < token.sequence {
+            return Err(session_timed_out(token, *applied));
+        }
+        Ok(())
+    }
+}
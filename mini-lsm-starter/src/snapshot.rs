@@ -0,0 +1,160 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Point-in-time snapshots of the storage and the diff between two of them.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, LsmStorageState};
+use crate::mvcc::ReadTsGuard;
+
+/// A consistent view of the storage at the time it was taken. It only references immutable
+/// memtables and SSTs, which it keeps alive until it is dropped, and reads them at the commit
+/// timestamp of the storage when it was taken. Until then, the compactions keep the versions it
+/// reads, which `LsmStorageInner::get_with_ts` can read at `read_ts` too.
+#[derive(Clone)]
+pub struct Snapshot {
+    state: Arc<LsmStorageState>,
+    options: Arc<LsmStorageOptions>,
+    reader: Arc<ReadTsGuard>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(
+        state: Arc<LsmStorageState>,
+        options: Arc<LsmStorageOptions>,
+        reader: ReadTsGuard,
+    ) -> Self {
+        Self {
+            state,
+            options,
+            reader: Arc::new(reader),
+        }
+    }
+
+    /// The timestamp the snapshot reads at.
+    pub fn read_ts(&self) -> u64 {
+        self.reader.read_ts()
+    }
+
+    /// Create an iterator over a range of keys as of this snapshot.
+    pub fn scan(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        Ok(FusedIterator::new(LsmStorageInner::scan_state(
+            &self.state,
+            lower,
+            upper,
+            None,
+            self.read_ts(),
+            &self.options,
+            None,
+            false,
+        )?)
+        .with_misuse_errors(self.options.iterator_misuse_errors))
+    }
+}
+
+/// Yields the keys whose visible value differs between two snapshots, by merging the scans of
+/// both. `value` is the value in the newer snapshot and `old_value` the value in the older one,
+/// either of which is empty if the key does not exist in that snapshot.
+pub struct DiffIterator {
+    old: FusedIterator<LsmIterator>,
+    new: FusedIterator<LsmIterator>,
+}
+
+impl DiffIterator {
+    pub(crate) fn create(
+        old: FusedIterator<LsmIterator>,
+        new: FusedIterator<LsmIterator>,
+    ) -> Result<Self> {
+        let mut iter = Self { old, new };
+        iter.skip_unchanged()?;
+        Ok(iter)
+    }
+
+    fn in_old(&self) -> bool {
+        self.old.is_valid() && (!self.new.is_valid() || self.old.key() <= self.new.key())
+    }
+
+    fn in_new(&self) -> bool {
+        self.new.is_valid() && (!self.old.is_valid() || self.new.key() <= self.old.key())
+    }
+
+    fn skip_unchanged(&mut self) -> Result<()> {
+        while self.old.is_valid()
+            && self.new.is_valid()
+            && self.old.key() == self.new.key()
+            && self.old.value() == self.new.value()
+            && self.old.value_meta() == self.new.value_meta()
+        {
+            self.old.next()?;
+            self.new.next()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the current key does not exist in the older snapshot.
+    pub fn old_is_deleted(&self) -> bool {
+        !self.in_old()
+    }
+
+    /// The value of the current key in the older snapshot.
+    pub fn old_value(&self) -> &[u8] {
+        if self.in_old() { self.old.value() } else { &[] }
+    }
+}
+
+impl StorageIterator for DiffIterator {
+    type KeyType<'a> = &'a [u8];
+
+    fn key(&self) -> &[u8] {
+        if self.in_new() {
+            self.new.key()
+        } else {
+            self.old.key()
+        }
+    }
+
+    fn value(&self) -> &[u8] {
+        if self.in_new() { self.new.value() } else { &[] }
+    }
+
+    /// Whether the current key does not exist in the newer snapshot.
+    fn is_deleted(&self) -> bool {
+        !self.in_new()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.old.is_valid() || self.new.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        let (in_old, in_new) = (self.in_old(), self.in_new());
+        if in_old {
+            self.old.next()?;
+        }
+        if in_new {
+            self.new.next()?;
+        }
+        self.skip_unchanged()
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A hard bound on the sorted runs of SSTs that reads go through, see
+//! `LsmStorageOptions::max_sorted_runs`.
+//!
+//! Each flush adds a sorted run: an L0 SST, or a new tier with tiered compaction. Once the SSTs
+//! form `max_sorted_runs` runs, the flush thread holds the immutable memtables back until a
+//! compaction merges some of the runs. Once `num_memtable_limit` immutable memtables are held
+//! back, the writes stall as well, so that the memtables do not grow without bound.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::lsm_storage::LsmStorageState;
+
+/// The write stalls forced by `max_sorted_runs` so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SortedRunStats {
+    /// The number of writes that waited for a flush to get through.
+    pub stalls: u64,
+    /// The total time these writes waited.
+    pub stall_time: Duration,
+}
+
+/// The sorted runs of SSTs in `state`: each L0 SST, and each non-empty level or tier.
+pub fn num_sorted_runs(state: &LsmStorageState) -> usize {
+    state.l0_sstables.len()
+        + state
+            .levels
+            .iter()
+            .filter(|(_, sst_ids)| !sst_ids.is_empty())
+            .count()
+}
+
+pub(crate) struct SortedRunLimiter {
+    max_sorted_runs: usize,
+    stalls: AtomicU64,
+    stall_micros: AtomicU64,
+}
+
+impl SortedRunLimiter {
+    pub(crate) fn new(max_sorted_runs: usize) -> Self {
+        Self {
+            max_sorted_runs,
+            stalls: AtomicU64::new(0),
+            stall_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether another flush would exceed the bound.
+    pub(crate) fn at_bound(&self, state: &LsmStorageState) -> bool {
+        num_sorted_runs(state) >= self.max_sorted_runs
+    }
+
+    pub(crate) fn record_stall(&self, stall_time: Duration) {
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+        self.stall_micros
+            .fetch_add(stall_time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> SortedRunStats {
+        SortedRunStats {
+            stalls: self.stalls.load(Ordering::Relaxed),
+            stall_time: Duration::from_micros(self.stall_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
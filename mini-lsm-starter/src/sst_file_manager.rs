@@ -0,0 +1,131 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deletes obsolete SSTs in the background at a limited rate, as deleting many large files at
+//! once after a big compaction causes latency spikes on some file systems.
+//!
+//! An obsolete SST is moved to the `trash` directory next to it, which is on the same file
+//! system. The background thread then shrinks each file in trash by `DELETE_CHUNK_SIZE` at a
+//! time before removing it.
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::rate_limiter::RateLimiter;
+
+/// The name of the directory of the files waiting to be deleted.
+pub const TRASH_DIR: &str = "trash";
+
+/// The number of bytes truncated from a file in trash at a time.
+const DELETE_CHUNK_SIZE: u64 = 1 << 20;
+
+pub struct SstFileManager {
+    /// Files are deleted right away if this is `None`.
+    delete_rate: Option<u64>,
+    rate_limiter: RateLimiter,
+    trash: Mutex<VecDeque<PathBuf>>,
+    trash_size: AtomicU64,
+}
+
+impl SstFileManager {
+    /// Create a file manager deleting `delete_rate` bytes per second, or deleting files right away
+    /// if it is `None`.
+    pub fn new(delete_rate: Option<u64>) -> Self {
+        Self {
+            delete_rate,
+            rate_limiter: RateLimiter::new(delete_rate),
+            trash: Mutex::new(VecDeque::new()),
+            trash_size: AtomicU64::new(0),
+        }
+    }
+
+    pub fn delete_rate(&self) -> Option<u64> {
+        self.delete_rate
+    }
+
+    /// The total size of the files waiting to be deleted.
+    pub fn trash_size(&self) -> u64 {
+        self.trash_size.load(Ordering::Relaxed)
+    }
+
+    /// Queue the files left in the trash of `dir`, e.g., by a previous process.
+    pub fn recover_trash(&self, dir: &Path) -> Result<()> {
+        if self.delete_rate.is_none() {
+            return Ok(());
+        }
+        let trash_dir = dir.join(TRASH_DIR);
+        if !trash_dir.exists() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(trash_dir)? {
+            let entry = entry?;
+            self.add_to_trash(entry.path(), entry.metadata()?.len());
+        }
+        Ok(())
+    }
+
+    fn add_to_trash(&self, path: PathBuf, size: u64) {
+        self.trash_size.fetch_add(size, Ordering::Relaxed);
+        self.trash.lock().push_back(path);
+    }
+
+    /// Delete a file, or move it to trash if the deletion rate is limited.
+    pub fn delete_file(&self, path: &Path) -> Result<()> {
+        if self.delete_rate.is_none() {
+            std::fs::remove_file(path)?;
+            return Ok(());
+        }
+        let trash_dir = path.parent().unwrap_or(Path::new(".")).join(TRASH_DIR);
+        std::fs::create_dir_all(&trash_dir)?;
+        let trash_path = trash_dir.join(path.file_name().unwrap_or_default());
+        let size = std::fs::metadata(path)?.len();
+        std::fs::rename(path, &trash_path)?;
+        self.add_to_trash(trash_path, size);
+        Ok(())
+    }
+
+    /// Delete up to `DELETE_CHUNK_SIZE` bytes of the oldest file in trash, waiting for the rate
+    /// limit. Returns false if the trash is empty.
+    pub fn delete_trash_chunk(&self) -> Result<bool> {
+        let Some(path) = self.trash.lock().front().cloned() else {
+            return Ok(false);
+        };
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Deleted by someone else
+                self.trash.lock().pop_front();
+                return Ok(true);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let chunk = size.min(DELETE_CHUNK_SIZE);
+        self.rate_limiter.request(chunk as usize);
+        if size > chunk {
+            let file = OpenOptions::new().write(true).open(&path)?;
+            file.set_len(size - chunk)?;
+            file.sync_all()?;
+        } else {
+            std::fs::remove_file(&path)?;
+            self.trash.lock().pop_front();
+        }
+        self.trash_size.fetch_sub(chunk, Ordering::Relaxed);
+        Ok(true)
+    }
+}
@@ -18,19 +18,29 @@
 pub(crate) mod bloom;
 mod builder;
 mod iterator;
+mod shared_meta;
 
 use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 pub use builder::SsTableBuilder;
-use bytes::{Buf, BufMut};
+use bytes::{Buf, BufMut, Bytes};
 pub use iterator::SsTableIterator;
+pub use shared_meta::{SharedMetadata, SharedRegion};
 
-use crate::block::Block;
-use crate::key::{KeyBytes, KeySlice};
+use crate::block::{Block, BlockIterator, ChecksumMode, CompressionType};
+use crate::block_cache::{BlockHandle, MetadataCharge};
+use crate::io_priority;
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT, TS_MAX, TS_RANGE_BEGIN, TS_RANGE_END};
 use crate::lsm_storage::BlockCache;
+use crate::prefix_extractor::PrefixExtractor;
+use crate::quota::tenant_of;
+use crate::range_tombstone::RangeTombstones;
+use crate::ttl::ExpiryHistogram;
+use crate::write_observer::{FileKind, WriteObservers};
 
 use self::bloom::Bloom;
 
@@ -59,32 +69,43 @@ impl BlockMeta {
             estimated_size += std::mem::size_of::<u32>();
             // The size of key length
             estimated_size += std::mem::size_of::<u16>();
-            // The size of actual key
-            estimated_size += meta.first_key.len();
+            // The size of actual key and timestamp
+            estimated_size += meta.first_key.raw_len();
             // The size of key length
             estimated_size += std::mem::size_of::<u16>();
-            // The size of actual key
-            estimated_size += meta.last_key.len();
+            // The size of actual key and timestamp
+            estimated_size += meta.last_key.raw_len();
         }
         buf.reserve(estimated_size);
         for meta in block_meta {
             buf.put_u32(meta.offset as u32);
-            buf.put_u16(meta.first_key.len() as u16);
-            buf.extend_from_slice(meta.first_key.raw_ref());
-            buf.put_u16(meta.last_key.len() as u16);
-            buf.extend_from_slice(meta.last_key.raw_ref());
+            buf.put_u16(meta.first_key.key_len() as u16);
+            buf.extend_from_slice(meta.first_key.key_ref());
+            buf.put_u64(meta.first_key.ts());
+            buf.put_u16(meta.last_key.key_len() as u16);
+            buf.extend_from_slice(meta.last_key.key_ref());
+            buf.put_u64(meta.last_key.ts());
         }
     }
 
-    /// Decode block meta from a buffer.
-    pub fn decode_block_meta(mut buf: impl Buf) -> Vec<BlockMeta> {
+    /// Decode block meta from a buffer. The keys are not followed by their timestamps if
+    /// `timestamps` is false, as in the SSTs before format version 9.
+    pub fn decode_block_meta(mut buf: impl Buf, timestamps: bool) -> Vec<BlockMeta> {
+        let read_key = |buf: &mut dyn Buf| {
+            let key_len = buf.get_u16() as usize;
+            let key = buf.copy_to_bytes(key_len);
+            let ts = if timestamps {
+                buf.get_u64()
+            } else {
+                TS_DEFAULT
+            };
+            KeyBytes::from_bytes_with_ts(key, ts)
+        };
         let mut block_meta = Vec::new();
         while buf.has_remaining() {
             let offset = buf.get_u32() as usize;
-            let first_key_len = buf.get_u16() as usize;
-            let first_key = KeyBytes::from_bytes(buf.copy_to_bytes(first_key_len));
-            let last_key_len = buf.get_u16() as usize;
-            let last_key = KeyBytes::from_bytes(buf.copy_to_bytes(last_key_len));
+            let first_key = read_key(&mut buf);
+            let last_key = read_key(&mut buf);
             block_meta.push(BlockMeta {
                 offset,
                 first_key,
@@ -95,6 +116,201 @@ impl BlockMeta {
     }
 }
 
+/// Statistics of an SSTable, stored after the bloom filter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableProperties {
+    /// The block size chosen by the builder based on the average entry size.
+    pub block_size: u32,
+    /// The number of key-value pairs, including deletes.
+    pub num_entries: u64,
+    /// The total length of the keys.
+    pub raw_key_size: u64,
+    /// The total length of the values.
+    pub raw_value_size: u64,
+    /// The total length of the keys and values starting with each of the prefixes the builder
+    /// was configured with, see `SsTableBuilder::with_tenant_prefixes`.
+    pub prefix_sizes: Vec<(Bytes, u64)>,
+    /// The expiry times of the entries written with a TTL.
+    pub expiry_histogram: ExpiryHistogram,
+    /// Whether each block ends with a CRC32 checksum, which SSTs of format version 1 lack.
+    pub block_checksums: bool,
+    /// Whether each block is followed by the flag of its codec, see `Block::encode_compressed`,
+    /// which SSTs before format version 4 lack.
+    pub block_codecs: bool,
+    /// The name of the prefix extractor the SST was built with, see
+    /// `SsTableBuilder::with_prefix_extractor`.
+    pub prefix_extractor: Option<String>,
+    /// The encoded bloom filter over the prefixes of the keys, set with `prefix_extractor`.
+    pub prefix_bloom: Option<Bytes>,
+    /// The ranges deleted in the older SSTs by range deletes, which SSTs before format version 8
+    /// lack.
+    pub range_tombstones: RangeTombstones,
+    /// The latest timestamp of the keys and range tombstones. SSTs before format version 9 lack
+    /// it, as their keys are stored without timestamps.
+    pub max_ts: Option<u64>,
+}
+
+/// Set in the byte of `block_codecs` if the range tombstones follow it.
+const RANGE_TOMBSTONES_FLAG: u8 = 1 << 1;
+
+/// Set in the byte of `block_codecs` if the keys are stored with their timestamps, in which case
+/// the maximum timestamp follows it.
+const TIMESTAMPS_FLAG: u8 = 1 << 2;
+
+impl TableProperties {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u32(self.block_size);
+        buf.put_u64(self.num_entries);
+        buf.put_u64(self.raw_key_size);
+        buf.put_u64(self.raw_value_size);
+        buf.put_u32(self.prefix_sizes.len() as u32);
+        for (prefix, size) in &self.prefix_sizes {
+            buf.put_u16(prefix.len() as u16);
+            buf.put_slice(prefix);
+            buf.put_u64(*size);
+        }
+        self.expiry_histogram.encode(buf);
+        buf.put_u8(self.block_checksums as u8);
+        let mut codec_flags = self.block_codecs as u8;
+        if !self.range_tombstones.is_empty() {
+            codec_flags |= RANGE_TOMBSTONES_FLAG;
+        }
+        if self.max_ts.is_some() {
+            codec_flags |= TIMESTAMPS_FLAG;
+        }
+        buf.put_u8(codec_flags);
+        if let Some(max_ts) = self.max_ts {
+            buf.put_u64(max_ts);
+        }
+        if !self.range_tombstones.is_empty() {
+            self.range_tombstones.encode(buf);
+        }
+        if let Some(name) = &self.prefix_extractor {
+            let prefix_bloom = self.prefix_bloom.as_deref().unwrap_or_default();
+            buf.put_u16(name.len() as u16);
+            buf.put_slice(name.as_bytes());
+            buf.put_u32(prefix_bloom.len() as u32);
+            buf.put_slice(prefix_bloom);
+        }
+    }
+
+    pub fn decode(mut buf: impl Buf) -> Result<Self> {
+        if buf.remaining() < 32 {
+            bail!("table properties are truncated");
+        }
+        let mut properties = Self {
+            block_size: buf.get_u32(),
+            num_entries: buf.get_u64(),
+            raw_key_size: buf.get_u64(),
+            raw_value_size: buf.get_u64(),
+            prefix_sizes: Vec::new(),
+            expiry_histogram: ExpiryHistogram::default(),
+            block_checksums: false,
+            block_codecs: false,
+            prefix_extractor: None,
+            prefix_bloom: None,
+            range_tombstones: RangeTombstones::new(),
+            max_ts: None,
+        };
+        let num_prefixes = buf.get_u32();
+        for _ in 0..num_prefixes {
+            if buf.remaining() < 2 {
+                bail!("table properties are truncated");
+            }
+            let prefix_len = buf.get_u16() as usize;
+            if buf.remaining() < prefix_len + 8 {
+                bail!("table properties are truncated");
+            }
+            let prefix = buf.copy_to_bytes(prefix_len);
+            properties.prefix_sizes.push((prefix, buf.get_u64()));
+        }
+        properties.expiry_histogram = ExpiryHistogram::decode(&mut buf)?;
+        // Absent in format version 1
+        properties.block_checksums = buf.has_remaining() && buf.get_u8() != 0;
+        // Absent before format version 4
+        let codec_flags = if buf.has_remaining() { buf.get_u8() } else { 0 };
+        properties.block_codecs = codec_flags & !(RANGE_TOMBSTONES_FLAG | TIMESTAMPS_FLAG) != 0;
+        // Absent before format version 9
+        let timestamps = codec_flags & TIMESTAMPS_FLAG != 0;
+        if timestamps {
+            if buf.remaining() < 8 {
+                bail!("table properties are truncated");
+            }
+            properties.max_ts = Some(buf.get_u64());
+        }
+        // Absent before format version 8, and without range deletes
+        if codec_flags & RANGE_TOMBSTONES_FLAG != 0 {
+            properties.range_tombstones = RangeTombstones::decode(&mut buf, timestamps)?;
+        }
+        // Absent before format version 6, and without a prefix extractor
+        if buf.has_remaining() {
+            if buf.remaining() < 2 {
+                bail!("table properties are truncated");
+            }
+            let name_len = buf.get_u16() as usize;
+            if buf.remaining() < name_len + 4 {
+                bail!("table properties are truncated");
+            }
+            let name = String::from_utf8(buf.copy_to_bytes(name_len).to_vec())
+                .context("invalid prefix extractor name")?;
+            let bloom_len = buf.get_u32() as usize;
+            if buf.remaining() < bloom_len {
+                bail!("table properties are truncated");
+            }
+            properties.prefix_extractor = Some(name);
+            properties.prefix_bloom = Some(buf.copy_to_bytes(bloom_len));
+        }
+        Ok(properties)
+    }
+
+    /// Whether the keys of the SST are stored with their timestamps.
+    pub fn has_timestamps(&self) -> bool {
+        self.max_ts.is_some()
+    }
+
+    pub(crate) fn add_entry(&mut self, key: &[u8], value_len: usize) {
+        self.num_entries += 1;
+        self.raw_key_size += key.len() as u64;
+        self.raw_value_size += value_len as u64;
+        let prefixes = self.prefix_sizes.iter().map(|(prefix, _)| &prefix[..]);
+        if let Some(idx) = tenant_of(prefixes, key) {
+            self.prefix_sizes[idx].1 += (key.len() + value_len) as u64;
+        }
+    }
+}
+
+/// Start the write-back of a range of `file`, or also wait for it if `wait` is set, without
+/// flushing its metadata.
+#[cfg(target_os = "linux")]
+fn write_back(file: &File, offset: usize, len: usize, wait: bool) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let flags = match wait {
+        true => {
+            libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                | libc::SYNC_FILE_RANGE_WRITE
+                | libc::SYNC_FILE_RANGE_WAIT_AFTER
+        }
+        false => libc::SYNC_FILE_RANGE_WRITE,
+    };
+    // SAFETY: the file descriptor stays open for the duration of the call
+    let result =
+        unsafe { libc::sync_file_range(file.as_raw_fd(), offset as i64, len as i64, flags) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Without `sync_file_range`, the write-back cannot be started early, and waiting for a range
+/// syncs the data of all of `file`.
+#[cfg(not(target_os = "linux"))]
+fn write_back(file: &File, _offset: usize, _len: usize, wait: bool) -> Result<()> {
+    if wait {
+        file.sync_data()?;
+    }
+    Ok(())
+}
+
 /// A file object.
 pub struct FileObject(Option<File>, u64);
 
@@ -115,14 +331,64 @@ impl FileObject {
 
     /// Create a new file object (day 2) and write the file to the disk (day 4).
     pub fn create(path: &Path, data: Vec<u8>) -> Result<Self> {
-        std::fs::write(path, &data)?;
-        File::open(path)?.sync_all()?;
+        Self::create_observed(path, data, None, None)
+    }
+
+    /// Create a file like `create`, but write it in chunks of `chunk_size` bytes. The write-back
+    /// of each chunk starts as soon as it is written, after waiting for the previous chunk, so
+    /// that at most two chunks of the file are queued on the disk at a time. The file is not
+    /// synced, `sync` it before referencing it.
+    pub fn create_chunked(path: &Path, data: Vec<u8>, chunk_size: usize) -> Result<Self> {
+        Self::create_observed(path, data, Some(chunk_size), None)
+    }
+
+    /// Create a file with `create_chunked` if `chunk_size` is set, or with `create` otherwise,
+    /// notifying `observers` of each write as an SST write.
+    pub fn create_observed(
+        path: &Path,
+        data: Vec<u8>,
+        chunk_size: Option<usize>,
+        observers: Option<&WriteObservers>,
+    ) -> Result<Self> {
+        let Some(chunk_size) = chunk_size else {
+            std::fs::write(path, &data)?;
+            if let Some(observers) = observers {
+                observers.notify(FileKind::Sst, data.len() as u64);
+            }
+            File::open(path)?.sync_all()?;
+            return Ok(FileObject(
+                Some(File::options().read(true).write(false).open(path)?),
+                data.len() as u64,
+            ));
+        };
+        use std::io::Write;
+        let mut file = File::create(path)?;
+        let mut offset = 0;
+        for chunk in data.chunks(chunk_size) {
+            file.write_all(chunk)?;
+            if let Some(observers) = observers {
+                observers.notify(FileKind::Sst, chunk.len() as u64);
+            }
+            write_back(&file, offset, chunk.len(), false)?;
+            if offset > 0 {
+                write_back(&file, offset - chunk_size, chunk_size, true)?;
+            }
+            offset += chunk.len();
+        }
         Ok(FileObject(
             Some(File::options().read(true).write(false).open(path)?),
             data.len() as u64,
         ))
     }
 
+    /// Flush the data of the file to the disk.
+    pub fn sync(&self) -> Result<()> {
+        if let Some(file) = &self.0 {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
     pub fn open(path: &Path) -> Result<Self> {
         let file = File::options().read(true).write(false).open(path)?;
         let size = file.metadata()?.len();
@@ -130,21 +396,90 @@ impl FileObject {
     }
 }
 
+/// The block index and bloom filter of an SST, as kept in the metadata pool of the block cache,
+/// see `BlockCache::with_metadata_capacity`.
+pub struct TableMetadata {
+    pub(crate) block_meta: Vec<BlockMeta>,
+    pub(crate) bloom: Option<Bloom>,
+}
+
+impl TableMetadata {
+    pub(crate) fn size(&self) -> u64 {
+        metadata_size(&self.block_meta, self.bloom.as_ref())
+    }
+
+    pub(crate) fn block_index_size(&self) -> u64 {
+        metadata_size(&self.block_meta, None)
+    }
+
+    pub(crate) fn bloom_size(&self) -> u64 {
+        self.bloom
+            .as_ref()
+            .map_or(0, |bloom| bloom.filter.len() as u64)
+    }
+}
+
+/// The block index and bloom filter of an SST, borrowed from the SST or held from the metadata
+/// pool, which cannot evict them while they are in use.
+pub(crate) enum MetadataRef<'a> {
+    Resident(&'a SsTable),
+    Pooled(Arc<TableMetadata>),
+}
+
+impl MetadataRef<'_> {
+    pub(crate) fn block_meta(&self) -> &[BlockMeta] {
+        match self {
+            Self::Resident(sst) => &sst.block_meta,
+            Self::Pooled(metadata) => &metadata.block_meta,
+        }
+    }
+
+    pub(crate) fn bloom(&self) -> Option<&Bloom> {
+        match self {
+            Self::Resident(sst) => sst.bloom.as_ref(),
+            Self::Pooled(metadata) => metadata.bloom.as_ref(),
+        }
+    }
+}
+
 /// An SSTable.
 pub struct SsTable {
     /// The actual storage unit of SsTable, the format is as above.
     pub(crate) file: FileObject,
-    /// The meta blocks that hold info for data blocks.
+    /// The meta blocks that hold info for data blocks, empty if they are kept in the metadata
+    /// pool of the block cache, see `metadata`.
     pub(crate) block_meta: Vec<BlockMeta>,
     /// The offset that indicates the start point of meta blocks in `file`.
     pub(crate) block_meta_offset: usize,
+    /// The offsets of the bloom filter and of the table properties in `file`, to read the bloom
+    /// filter again once evicted from the metadata pool.
+    bloom_offset: usize,
+    properties_offset: usize,
+    num_blocks: usize,
+    /// Whether `block_meta` and `bloom` are kept in the metadata pool of the block cache.
+    pooled_metadata: bool,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
     first_key: KeyBytes,
     last_key: KeyBytes,
     pub(crate) bloom: Option<Bloom>,
+    /// The bloom filter over the prefixes of the keys, see `TableProperties::prefix_extractor`.
+    prefix_bloom: Option<Bloom>,
     /// The maximum timestamp stored in this SST, implemented in week 3.
     max_ts: u64,
+    /// The number of point lookups that read this SST without finding the key.
+    useless_probes: AtomicUsize,
+    /// The level of this SST, 0 for L0. Used to attribute block cache reads to levels.
+    level: AtomicUsize,
+    /// Set while a compaction task has claimed this SST as its input, see `CompactionClaim`.
+    being_compacted: AtomicBool,
+    properties: TableProperties,
+    /// The shared memory `bloom` is mapped from.
+    shared_metadata: Option<Arc<SharedRegion>>,
+    /// The charge of the block index and bloom filter to the block cache.
+    metadata_charge: Option<MetadataCharge>,
+    /// Whether to verify the checksums of the blocks read, see `verify_block_checksums`.
+    verify_checksums: bool,
 }
 
 impl SsTable {
@@ -155,24 +490,167 @@ impl SsTable {
 
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
+        Self::open_inner(id, block_cache, file, None)
+    }
+
+    /// Open an SST file for reading on its own, without a block cache or a storage engine. The
+    /// SST is given id 0.
+    pub fn open_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(0, None, FileObject::open(path.as_ref())?)
+    }
+
+    /// Iterate over all entries of the SST, including deletes, see `StorageIterator::is_deleted`.
+    pub fn iter(self: &Arc<Self>) -> Result<SsTableIterator> {
+        SsTableIterator::create_and_seek_to_first(self.clone())
+    }
+
+    /// Point lookup of the latest version of `key`, `Some(None)` if the SST stores a delete of
+    /// it. Values are returned as stored, e.g., with the expiry time of a TTL appended.
+    pub fn get_entry(&self, key: &[u8]) -> Result<Option<Option<Bytes>>> {
+        self.get_entry_with_ts(key, TS_MAX)
+    }
+
+    /// Point lookup of the latest version of `key` at or before `read_ts`, see `get_entry`.
+    pub fn get_entry_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Option<Bytes>>> {
+        if !self.may_contain_key(key) {
+            return Ok(None);
+        }
+        self.find_entry(key, read_ts, |block_idx| self.read_block_cached(block_idx))
+    }
+
+    /// Find the latest version of `key` at or before `read_ts`, reading the blocks with
+    /// `read_block`. The lookup starts in the last block whose first key is not after the
+    /// version, and may continue in the next block, which the older versions can spill over to.
+    pub(crate) fn find_entry(
+        &self,
+        key: &[u8],
+        read_ts: u64,
+        mut read_block: impl FnMut(usize) -> Result<Arc<Block>>,
+    ) -> Result<Option<Option<Bytes>>> {
+        let key = KeySlice::from_slice(key, read_ts);
+        let metadata = self.metadata()?;
+        let block_meta = metadata.block_meta();
+        let mut block_idx = find_block_idx(block_meta, key);
+        loop {
+            let iter = BlockIterator::create_and_seek_to_key(read_block(block_idx)?, key);
+            if iter.is_valid() {
+                if iter.key().key_ref() != key.key_ref() {
+                    return Ok(None);
+                }
+                if iter.is_deleted() {
+                    return Ok(Some(None));
+                }
+                return Ok(Some(Some(Bytes::copy_from_slice(iter.value()))));
+            }
+            block_idx += 1;
+            if block_meta
+                .get(block_idx)
+                .is_none_or(|meta| meta.first_key.key_ref() != key.key_ref())
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Open SSTable from a file, using the bloom filter and block index published in shared
+    /// memory by another process, or publishing them if this is the first process to open it.
+    pub fn open_with_shared_metadata(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        shared_metadata: &SharedMetadata,
+    ) -> Result<Self> {
+        Self::open_inner(id, block_cache, file, Some(shared_metadata))
+    }
+
+    fn open_inner(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        shared_metadata: Option<&SharedMetadata>,
+    ) -> Result<Self> {
         let file_len = file.size();
-        let bloom_offset = (&(file.read(file_len - 4, 4)?)[..]).get_u32() as u64;
-        let bloom = Bloom::decode(&file.read(bloom_offset, file_len - 4 - bloom_offset)?[..])
-            .map_err(|e| anyhow!("Failed to decode bloom filter: {}", e))?;
+        let properties_offset = (&(file.read(file_len - 4, 4)?)[..]).get_u32() as u64;
+        let properties = TableProperties::decode(
+            &file.read(properties_offset, file_len - 4 - properties_offset)?[..],
+        )?;
+        let bloom_offset = (&(file.read(properties_offset - 4, 4)?)[..]).get_u32() as u64;
         let block_meta_offset = (&(file.read(bloom_offset - 4, 4)?)[..]).get_u32() as u64;
-        let block_meta = BlockMeta::decode_block_meta(
-            &file.read(block_meta_offset, bloom_offset - 4 - block_meta_offset)?[..],
-        );
+        let read_bloom = || file.read(bloom_offset, properties_offset - 4 - bloom_offset);
+        let read_block_meta = || file.read(block_meta_offset, bloom_offset - 4 - block_meta_offset);
+
+        let generation = metadata_generation(&properties, file_len);
+        let shared_region = match shared_metadata {
+            Some(shared_metadata) => match shared_metadata.open(id, generation)? {
+                Some(region) => Some(region),
+                None => {
+                    shared_metadata.publish(id, generation, &read_bloom()?, &read_block_meta()?)?;
+                    shared_metadata.open(id, generation)?
+                }
+            },
+            None => None,
+        }
+        .map(Arc::new);
+        let (bloom, block_meta) = match &shared_region {
+            Some(region) => (
+                region.bloom()?,
+                BlockMeta::decode_block_meta(region.block_meta(), properties.has_timestamps()),
+            ),
+            None => decode_metadata(&read_bloom()?, &read_block_meta()?, &properties)?,
+        };
+        // The metadata mapped from shared memory is not duplicated into the pool
+        let pooled_metadata = shared_region.is_none()
+            && block_cache
+                .as_ref()
+                .is_some_and(|block_cache| block_cache.has_metadata_pool());
+        let metadata_charge = match &block_cache {
+            Some(block_cache) if !pooled_metadata => {
+                Some(block_cache.charge_metadata(metadata_size(&block_meta, Some(&bloom)))?)
+            }
+            _ => None,
+        };
+        let prefix_bloom = match &properties.prefix_bloom {
+            Some(prefix_bloom) => Some(
+                Bloom::decode(prefix_bloom)
+                    .map_err(|e| anyhow!("Failed to decode prefix bloom filter: {}", e))?,
+            ),
+            None => None,
+        };
+        let (first_key, last_key) = key_range(&block_meta, &properties.range_tombstones);
+        let num_blocks = block_meta.len();
+        let (block_meta, bloom) = match &block_cache {
+            Some(block_cache) if pooled_metadata => {
+                let metadata = TableMetadata {
+                    block_meta,
+                    bloom: Some(bloom),
+                };
+                block_cache.insert_metadata(id, Arc::new(metadata));
+                (vec![], None)
+            }
+            _ => (block_meta, Some(bloom)),
+        };
         Ok(Self {
             file,
             block_meta_offset: block_meta_offset as usize,
+            bloom_offset: bloom_offset as usize,
+            properties_offset: properties_offset as usize,
+            num_blocks,
+            pooled_metadata,
             id,
             block_cache,
-            first_key: block_meta.first().unwrap().first_key.clone(),
-            last_key: block_meta.last().unwrap().last_key.clone(),
+            first_key,
+            last_key,
             block_meta,
-            bloom: Some(bloom),
-            max_ts: 0,
+            bloom,
+            prefix_bloom,
+            max_ts: properties.max_ts.unwrap_or(TS_DEFAULT),
+            useless_probes: AtomicUsize::new(0),
+            level: AtomicUsize::new(0),
+            being_compacted: AtomicBool::new(false),
+            properties,
+            shared_metadata: shared_region,
+            metadata_charge,
+            verify_checksums: true,
         })
     }
 
@@ -187,52 +665,177 @@ impl SsTable {
             file: FileObject(None, file_size),
             block_meta: vec![],
             block_meta_offset: 0,
+            bloom_offset: 0,
+            properties_offset: 0,
+            num_blocks: 0,
+            pooled_metadata: false,
             id,
             block_cache: None,
             first_key,
             last_key,
             bloom: None,
+            prefix_bloom: None,
             max_ts: 0,
+            useless_probes: AtomicUsize::new(0),
+            level: AtomicUsize::new(0),
+            being_compacted: AtomicBool::new(false),
+            properties: TableProperties::default(),
+            shared_metadata: None,
+            metadata_charge: None,
+            verify_checksums: true,
         }
     }
 
-    /// Read a block from the disk.
-    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self.block_meta[block_idx].offset;
-        let offset_end = self
-            .block_meta
+    /// The block index and bloom filter, read from the disk if they were evicted from the
+    /// metadata pool of the block cache.
+    pub(crate) fn metadata(&self) -> Result<MetadataRef<'_>> {
+        let Some(block_cache) = self.block_cache.as_ref().filter(|_| self.pooled_metadata) else {
+            return Ok(MetadataRef::Resident(self));
+        };
+        let metadata = block_cache.try_get_metadata(self.id, || {
+            let read =
+                |start: usize, end: usize| self.file.read(start as u64, (end - start) as u64);
+            let (bloom, block_meta) = decode_metadata(
+                &read(self.bloom_offset, self.properties_offset - 4)?,
+                &read(self.block_meta_offset, self.bloom_offset - 4)?,
+                &self.properties,
+            )
+            .with_context(|| format!("failed to read the metadata of SST {}", self.id))?;
+            Ok(TableMetadata {
+                block_meta,
+                bloom: Some(bloom),
+            })
+        })?;
+        Ok(MetadataRef::Pooled(metadata))
+    }
+
+    /// Read the encoded bytes of a block from the disk, throttled for low-priority reads.
+    pub fn read_block_encoded(&self, block_idx: usize) -> Result<Vec<u8>> {
+        let metadata = self.metadata()?;
+        let offset = metadata.block_meta()[block_idx].offset;
+        let len = self.encoded_len(metadata.block_meta(), block_idx);
+        io_priority::before_disk_read(len);
+        self.file.read(offset as u64, len as u64)
+    }
+
+    /// The length of an encoded block.
+    pub fn block_len(&self, block_idx: usize) -> Result<usize> {
+        Ok(self.encoded_len(self.metadata()?.block_meta(), block_idx))
+    }
+
+    fn encoded_len(&self, block_meta: &[BlockMeta], block_idx: usize) -> usize {
+        let offset_end = block_meta
             .get(block_idx + 1)
             .map_or(self.block_meta_offset, |x| x.offset);
-        let block_data = self
-            .file
-            .read(offset as u64, (offset_end - offset) as u64)?;
-        Ok(Arc::new(Block::decode(&block_data[..])))
+        offset_end - block_meta[block_idx].offset
+    }
+
+    /// Read a block from the disk. Fails with a `ChecksumError` or a `CorruptionError` if the
+    /// block was corrupted.
+    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
+        let block_data = self.read_block_encoded(block_idx)?;
+        Ok(Arc::new(self.decode_block(block_idx, block_data.into())?))
+    }
+
+    /// Read a block and its encoding in the current format, to copy it into another SST as-is.
+    pub fn read_block_for_copy(&self, block_idx: usize) -> Result<(Arc<Block>, Bytes)> {
+        let encoded = Bytes::from(self.read_block_encoded(block_idx)?);
+        let block = Arc::new(self.decode_block(block_idx, encoded.clone())?);
+        if self.properties.block_codecs {
+            return Ok((block, encoded));
+        }
+        let encoded = block.encode_compressed(CompressionType::None);
+        Ok((block, encoded))
+    }
+
+    fn decode_block(&self, block_idx: usize, data: Bytes) -> Result<Block> {
+        let mode = match (self.properties.block_checksums, self.verify_checksums) {
+            (false, _) => ChecksumMode::Absent,
+            (true, false) => ChecksumMode::Skip,
+            (true, true) => ChecksumMode::Verify,
+        };
+        let block = if self.properties.block_codecs {
+            Block::decode_compressed(data, mode)
+        } else {
+            Block::try_decode(data, mode)
+        };
+        block.with_context(|| format!("failed to read block {} of SST {}", block_idx, self.id))
+    }
+
+    /// Flush the file of an SST built with `SsTableBuilder::with_sync_chunk_size` to the disk.
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync()
+    }
+
+    /// Skip the verification of the block checksums on reads if `verify` is false.
+    pub fn with_checksum_verification(mut self, verify: bool) -> Self {
+        self.verify_checksums = verify;
+        self
     }
 
     /// Read a block from disk, with block cache. (Day 4)
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
         if let Some(ref block_cache) = self.block_cache {
-            let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
-            Ok(blk)
+            block_cache.try_get_with(self.level(), (self.id, block_idx), || {
+                self.read_block(block_idx)
+            })
         } else {
             self.read_block(block_idx)
         }
     }
 
+    /// Read a block for an iterator, which pins it in the block cache until the handle is dropped.
+    pub fn read_block_pinned(&self, block_idx: usize) -> Result<BlockHandle> {
+        let block = self.read_block_cached(block_idx)?;
+        Ok(match &self.block_cache {
+            Some(block_cache) => block_cache.pin(block),
+            None => BlockHandle::uncached(block),
+        })
+    }
+
+    /// Read a block only if it is already in the block cache, never touching the disk.
+    pub fn read_block_from_cache(&self, block_idx: usize) -> Option<Arc<Block>> {
+        self.block_cache
+            .as_ref()
+            .and_then(|block_cache| block_cache.get(self.level(), &(self.id, block_idx)))
+    }
+
+    /// Check the key range and the bloom filter to see if a version of `key` may be stored in
+    /// this SST. If the bloom filter cannot be read back from the disk, the key may be stored,
+    /// and the lookup fails on reading the block index instead.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        if key < self.first_key.key_ref() || key > self.last_key.key_ref() {
+            return false;
+        }
+        match self.metadata() {
+            Ok(metadata) => metadata
+                .bloom()
+                .is_none_or(|bloom| bloom.may_contain(farmhash::fingerprint32(key))),
+            Err(_) => true,
+        }
+    }
+
+    /// Check the prefix bloom filter to see if a key starting with `prefix` may be stored in this
+    /// SST, where `prefix` is a prefix extracted by `extractor`. SSTs built without a prefix
+    /// extractor, or with another one, may store any prefix.
+    pub fn may_contain_prefix(&self, prefix: &[u8], extractor: &dyn PrefixExtractor) -> bool {
+        let Some(prefix_bloom) = &self.prefix_bloom else {
+            return true;
+        };
+        self.properties.prefix_extractor.as_deref() != Some(&extractor.name())
+            || prefix_bloom.may_contain(farmhash::fingerprint32(prefix))
+    }
+
     /// Find the block that may contain `key`.
     /// Note: You may want to make use of the `first_key` stored in `BlockMeta`.
     /// You may also assume the key-value pairs stored in each consecutive block are sorted.
-    pub fn find_block_idx(&self, key: KeySlice) -> usize {
-        self.block_meta
-            .partition_point(|meta| meta.first_key.as_key_slice() <= key)
-            .saturating_sub(1)
+    pub fn find_block_idx(&self, key: KeySlice) -> Result<usize> {
+        Ok(find_block_idx(self.metadata()?.block_meta(), key))
     }
 
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
-        self.block_meta.len()
+        self.num_blocks
     }
 
     pub fn first_key(&self) -> &KeyBytes {
@@ -243,6 +846,24 @@ impl SsTable {
         &self.last_key
     }
 
+    /// Whether `key` is deleted in the older SSTs by the range tombstones of this SST, for a read
+    /// at `read_ts`.
+    pub fn is_range_deleted(&self, key: &[u8], read_ts: u64) -> bool {
+        self.properties.range_tombstones.covers(key, read_ts)
+    }
+
+    /// The ranges deleted in the older SSTs by this SST.
+    pub fn range_tombstones(&self) -> &RangeTombstones {
+        &self.properties.range_tombstones
+    }
+
+    /// Whether the keys of the SST overlap with the range from `first_key` to `last_key`, both
+    /// inclusive, or to the end if `last_key` is `None`.
+    pub fn overlaps_range(&self, first_key: &[u8], last_key: Option<&[u8]>) -> bool {
+        self.last_key.key_ref() >= first_key
+            && last_key.is_none_or(|last_key| self.first_key.key_ref() <= last_key)
+    }
+
     pub fn table_size(&self) -> u64 {
         self.file.1
     }
@@ -251,7 +872,149 @@ impl SsTable {
         self.id
     }
 
+    /// The latest timestamp of the keys and range tombstones, `TS_DEFAULT` for the SSTs whose
+    /// keys have no timestamps.
     pub fn max_ts(&self) -> u64 {
         self.max_ts
     }
+
+    /// Count a point lookup that read this SST without finding the key. Returns the new count.
+    pub fn record_useless_probe(&self) -> usize {
+        self.useless_probes.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn useless_probes(&self) -> usize {
+        self.useless_probes.load(Ordering::Relaxed)
+    }
+
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Publish the bloom filter and block index of this SST for other processes, see
+    /// `SharedMetadata`.
+    pub fn publish_metadata(&self, shared_metadata: &SharedMetadata) -> Result<()> {
+        let metadata = self.metadata()?;
+        let mut bloom = Vec::new();
+        if let Some(b) = metadata.bloom() {
+            b.encode(&mut bloom);
+        }
+        let mut block_meta = Vec::new();
+        BlockMeta::encode_block_meta(metadata.block_meta(), &mut block_meta);
+        shared_metadata.publish(self.id, self.metadata_generation(), &bloom, &block_meta)
+    }
+
+    /// Tells the SSTs with the same id apart in the names of their shared metadata, see
+    /// `SharedMetadata`.
+    pub fn metadata_generation(&self) -> u32 {
+        metadata_generation(&self.properties, self.file.size())
+    }
+
+    /// Whether the bloom filter of this SST is mapped from shared memory.
+    pub fn has_shared_metadata(&self) -> bool {
+        self.shared_metadata.is_some()
+    }
+
+    pub fn level(&self) -> usize {
+        self.level.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_level(&self, level: usize) {
+        self.level.store(level, Ordering::Relaxed);
+    }
+
+    /// Whether a compaction task has claimed this SST as its input.
+    pub fn is_being_compacted(&self) -> bool {
+        self.being_compacted.load(Ordering::Acquire)
+    }
+
+    /// Claim this SST for a compaction task. Returns false if another task has claimed it.
+    pub(crate) fn try_claim_for_compaction(&self) -> bool {
+        self.being_compacted
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    pub(crate) fn release_compaction_claim(&self) {
+        self.being_compacted.store(false, Ordering::Release);
+    }
+
+    /// The bytes of the decoded block index and bloom filter, which are charged to the block
+    /// cache while the SST is open, 0 if they are kept in its metadata pool instead.
+    pub fn metadata_size(&self) -> u64 {
+        metadata_size(&self.block_meta, self.bloom.as_ref())
+    }
+
+    /// The bytes of the decoded block index, unless it is kept in the metadata pool.
+    pub fn block_index_size(&self) -> u64 {
+        metadata_size(&self.block_meta, None)
+    }
+
+    /// The bytes of the bloom filters over the keys and their prefixes.
+    pub fn bloom_size(&self) -> u64 {
+        [&self.bloom, &self.prefix_bloom]
+            .into_iter()
+            .flatten()
+            .map(|bloom| bloom.filter.len() as u64)
+            .sum()
+    }
+}
+
+/// The key range of an SST, from the first key of its blocks or the start of its first range
+/// tombstone to the last key of its blocks or the end of its last range tombstone. As the end of
+/// a range tombstone is exclusive, the next SST of a level may start with the last key.
+pub(crate) fn key_range(
+    block_meta: &[BlockMeta],
+    tombstones: &RangeTombstones,
+) -> (KeyBytes, KeyBytes) {
+    let mut first_key = block_meta.first().unwrap().first_key.clone();
+    let mut last_key = block_meta.last().unwrap().last_key.clone();
+    if let Some(start) = tombstones.first_key()
+        && start < first_key.key_ref()
+    {
+        first_key = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(start), TS_RANGE_BEGIN);
+    }
+    if let Some(end) = tombstones.end_key()
+        && end > last_key.key_ref()
+    {
+        last_key = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(end), TS_RANGE_END);
+    }
+    (first_key, last_key)
+}
+
+/// Find the block that may contain `key` in a block index.
+fn find_block_idx(block_meta: &[BlockMeta], key: KeySlice) -> usize {
+    block_meta
+        .partition_point(|meta| meta.first_key.as_key_slice() <= key)
+        .saturating_sub(1)
+}
+
+/// Decode the encoded bloom filter and block index of an SST.
+/// A checksum of the properties and the size of an SST, see `SsTable::metadata_generation`.
+fn metadata_generation(properties: &TableProperties, file_size: u64) -> u32 {
+    let mut buf = Vec::new();
+    properties.encode(&mut buf);
+    buf.put_u64(file_size);
+    crc32fast::hash(&buf)
+}
+
+fn decode_metadata(
+    bloom: &[u8],
+    block_meta: &[u8],
+    properties: &TableProperties,
+) -> Result<(Bloom, Vec<BlockMeta>)> {
+    Ok((
+        Bloom::decode(bloom).map_err(|e| anyhow!("Failed to decode bloom filter: {}", e))?,
+        BlockMeta::decode_block_meta(block_meta, properties.has_timestamps()),
+    ))
+}
+
+pub(crate) fn metadata_size(block_meta: &[BlockMeta], bloom: Option<&Bloom>) -> u64 {
+    let block_meta_size = block_meta
+        .iter()
+        .map(|meta| {
+            std::mem::size_of::<BlockMeta>() + meta.first_key.key_len() + meta.last_key.key_len()
+        })
+        .sum::<usize>();
+    (block_meta_size + bloom.map_or(0, |bloom| bloom.filter.len())) as u64
 }
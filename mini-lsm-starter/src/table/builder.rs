@@ -14,25 +14,60 @@
 
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 
-use anyhow::Result;
-use bytes::BufMut;
+use anyhow::{Result, bail};
+use bytes::{BufMut, Bytes};
 
-use super::{BlockMeta, SsTable};
-use crate::key::KeyBytes;
+use super::{BlockMeta, SsTable, TableMetadata, TableProperties, key_range, metadata_size};
+use crate::block::{Block, BlockBuilder, BlockIterator, CompressionType, DEFAULT_RESTART_INTERVAL};
+use crate::iterators::StorageIterator;
+use crate::key::{KeyBytes, KeyVec, TS_DEFAULT};
+use crate::lsm_storage::{MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use crate::prefix_extractor::PrefixExtractor;
+use crate::range_tombstone::RangeTombstones;
 use crate::table::FileObject;
 use crate::table::bloom::Bloom;
-use crate::{block::BlockBuilder, key::KeySlice, lsm_storage::BlockCache};
+use crate::ttl::{EXPIRY_LEN, ExpiryHistogram, split_expiry};
+use crate::write_observer::WriteObservers;
+use crate::{key::KeySlice, lsm_storage::BlockCache};
+
+/// The number of entries a block should hold when the block size is chosen automatically.
+const TARGET_ENTRIES_PER_BLOCK: usize = 32;
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
     builder: BlockBuilder,
-    first_key: Vec<u8>,
-    last_key: Vec<u8>,
+    first_key: KeyVec,
+    last_key: KeyVec,
     data: Vec<u8>,
     pub(crate) meta: Vec<BlockMeta>,
     block_size: usize,
+    /// If set, the block size is chosen between `block_size` and this for each new block.
+    max_block_size: Option<usize>,
     key_hashes: Vec<u32>,
+    properties: TableProperties,
+    /// The expiry times of the entries, if they are tracked in the table properties.
+    expiries: Option<Vec<u64>>,
+    /// Whether to check that the keys are added in strictly increasing order.
+    verify_key_order: bool,
+    /// The first key order violation, which makes `build` fail.
+    key_order_violation: Option<anyhow::Error>,
+    /// Write the file in chunks of this size and leave the final sync to the caller.
+    sync_chunk_size: Option<usize>,
+    /// The number of entries between two restart points of each block.
+    restart_interval: usize,
+    write_observers: Option<Arc<WriteObservers>>,
+    /// The codec of the blocks.
+    compression: CompressionType,
+    /// The bits of the bloom filter for each key, chosen for a 1% false positive rate if unset.
+    bloom_bits_per_key: Option<usize>,
+    /// If set, a second bloom filter is built over the prefixes of the keys.
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// The hashes of the prefixes, once for each run of keys with the same prefix.
+    prefix_hashes: Vec<u32>,
+    /// The largest timestamp of the keys added.
+    max_ts: u64,
 }
 
 impl SsTableBuilder {
@@ -40,39 +75,359 @@ impl SsTableBuilder {
     pub fn new(block_size: usize) -> Self {
         Self {
             builder: BlockBuilder::new(block_size),
-            first_key: Vec::new(),
-            last_key: Vec::new(),
+            first_key: KeyVec::new(),
+            last_key: KeyVec::new(),
             data: Vec::new(),
             meta: Vec::new(),
             block_size,
+            max_block_size: None,
             key_hashes: Vec::new(),
+            properties: TableProperties {
+                block_checksums: true,
+                block_codecs: true,
+                ..Default::default()
+            },
+            expiries: None,
+            verify_key_order: cfg!(debug_assertions),
+            key_order_violation: None,
+            sync_chunk_size: None,
+            restart_interval: DEFAULT_RESTART_INTERVAL,
+            write_observers: None,
+            compression: CompressionType::None,
+            bloom_bits_per_key: None,
+            prefix_extractor: None,
+            prefix_hashes: Vec::new(),
+            max_ts: TS_DEFAULT,
         }
     }
 
+    /// Choose the size of each block between the block size of the builder and `max_block_size`,
+    /// so that a block holds about `TARGET_ENTRIES_PER_BLOCK` entries of the average size seen so
+    /// far. The size is capped to 64KB, as the entry offsets in a block are 16-bit.
+    pub fn with_max_block_size(mut self, max_block_size: usize) -> Self {
+        self.max_block_size = Some(max_block_size.clamp(self.block_size, u16::MAX as usize));
+        self
+    }
+
+    /// Check that the keys are added in strictly increasing order, so that `build` fails instead
+    /// of writing a corrupt SSTable. This is always on in debug builds.
+    pub fn with_key_order_check(mut self) -> Self {
+        self.verify_key_order = true;
+        self
+    }
+
+    /// Write the SST in chunks of `chunk_size` bytes with `FileObject::create_chunked`. The SST
+    /// is not synced by `build`, the caller must `SsTable::sync` it before it is referenced.
+    pub fn with_sync_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.sync_chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Build the blocks with a restart point every `restart_interval` entries, see
+    /// `BlockBuilder::with_restart_interval`.
+    pub fn with_restart_interval(mut self, restart_interval: usize) -> Self {
+        self.restart_interval = restart_interval;
+        self.builder = BlockBuilder::new(self.block_size).with_restart_interval(restart_interval);
+        self
+    }
+
+    /// Compress each block with `compression` as it is written. Blocks that do not get smaller
+    /// are written uncompressed.
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Build the bloom filter with `bits_per_key` bits for each key. More bits make fewer false
+    /// positives, which skip fewer reads of the SST for absent keys.
+    pub fn with_bloom_bits_per_key(mut self, bits_per_key: usize) -> Self {
+        self.bloom_bits_per_key = Some(bits_per_key);
+        self
+    }
+
+    /// Build a bloom filter over the prefixes `extractor` extracts from the keys, which
+    /// `SsTable::may_contain_prefix` checks.
+    pub fn with_prefix_extractor(mut self, extractor: Arc<dyn PrefixExtractor>) -> Self {
+        self.prefix_extractor = Some(extractor);
+        self
+    }
+
+    /// Notify `observers` of the writes to the SST file.
+    pub fn with_write_observers(mut self, observers: Arc<WriteObservers>) -> Self {
+        self.write_observers = Some(observers);
+        self
+    }
+
+    /// Record the total size of the entries starting with each of `prefixes` in the table
+    /// properties. An entry matching several prefixes is attributed to the longest one.
+    pub fn with_tenant_prefixes(mut self, prefixes: &[Bytes]) -> Self {
+        self.properties.prefix_sizes = prefixes.iter().map(|prefix| (prefix.clone(), 0)).collect();
+        self
+    }
+
+    /// Record a histogram of the expiry times appended to the values in the table properties,
+    /// see `crate::ttl`.
+    pub fn with_expiry_tracking(mut self) -> Self {
+        self.expiries = Some(Vec::new());
+        self
+    }
+
+    fn record_expiry(&mut self, value: &[u8]) {
+        if let Some(expiries) = &mut self.expiries
+            && value.len() >= EXPIRY_LEN
+        {
+            expiries.push(split_expiry(value).1);
+        }
+    }
+
+    /// The block size to use for the next block.
+    fn next_block_size(&self) -> usize {
+        let Some(max_block_size) = self.max_block_size else {
+            return self.block_size;
+        };
+        if self.properties.num_entries == 0 {
+            return self.block_size;
+        }
+        let raw_size = self.properties.raw_key_size + self.properties.raw_value_size;
+        let avg_entry_size = (raw_size / self.properties.num_entries) as usize;
+        (avg_entry_size * TARGET_ENTRIES_PER_BLOCK).clamp(self.block_size, max_block_size)
+    }
+
     /// Adds a key-value pair to SSTable.
     ///
     /// Note: You should split a new block when the current block is full.(`std::mem::replace` may
     /// be helpful here)
     pub fn add(&mut self, key: KeySlice, value: &[u8]) {
+        self.add_with_meta(key, value, 0);
+    }
+
+    /// Adds a key-value pair with a user metadata byte to SSTable.
+    pub fn add_with_meta(&mut self, key: KeySlice, value: &[u8], meta: u8) {
+        self.add_entry(key, value, meta, value.is_empty());
+    }
+
+    /// Adds a put or a delete to SSTable. Unlike `add`, an empty value is only a delete if
+    /// `is_delete` is set.
+    pub fn add_entry(&mut self, key: KeySlice, value: &[u8], meta: u8, is_delete: bool) {
+        self.verify_key(key);
         if self.first_key.is_empty() {
-            self.first_key.clear();
-            self.first_key.extend(key.raw_ref());
+            self.first_key.set_from_slice(key);
         }
 
-        self.key_hashes.push(farmhash::fingerprint32(key.raw_ref()));
+        self.record_key(key);
+        self.properties.add_entry(key.key_ref(), value.len());
+        self.record_expiry(value);
 
-        if self.builder.add(key, value) {
-            self.last_key.clear();
-            self.last_key.extend(key.raw_ref());
+        if self.builder.add_entry(key, value, meta, is_delete) {
+            self.last_key.set_from_slice(key);
             return;
         }
 
         self.split_new_block();
-        let _ = self.builder.add(key, value);
-        self.first_key.clear();
-        self.first_key.extend(key.raw_ref());
-        self.last_key.clear();
-        self.last_key.extend(key.raw_ref());
+        let _ = self.builder.add_entry(key, value, meta, is_delete);
+        self.first_key.set_from_slice(key);
+        self.last_key.set_from_slice(key);
+    }
+
+    /// Record the timestamp of a key and the hash of its user key, and of its prefix if it starts
+    /// a run of keys with that prefix, for the bloom filters. The versions of a key are hashed
+    /// once.
+    fn record_key(&mut self, key: KeySlice) {
+        self.max_ts = self.max_ts.max(key.ts());
+        let key = key.key_ref();
+        let hash = farmhash::fingerprint32(key);
+        if self.key_hashes.last() != Some(&hash) {
+            self.key_hashes.push(hash);
+        }
+        if let Some(prefix) = self
+            .prefix_extractor
+            .as_ref()
+            .and_then(|extractor| extractor.extract(key))
+        {
+            let hash = farmhash::fingerprint32(prefix);
+            if self.prefix_hashes.last() != Some(&hash) {
+                self.prefix_hashes.push(hash);
+            }
+        }
+    }
+
+    /// The last key added to the SSTable, if any.
+    fn last_added_key(&self) -> Option<KeySlice<'_>> {
+        if !self.last_key.is_empty() {
+            return Some(self.last_key.as_key_slice());
+        }
+        self.meta.last().map(|meta| meta.last_key.as_key_slice())
+    }
+
+    /// Whether `key` is the user key of the last key added, whose versions must not be split
+    /// across SSTs, as the reads of a level only look into the last SST starting before a key.
+    pub(crate) fn is_last_key(&self, key: &[u8]) -> bool {
+        self.last_added_key()
+            .is_some_and(|last_key| last_key.key_ref() == key)
+    }
+
+    /// Adds a key-value pair like `add`, but fails instead of building a corrupt SSTable if the
+    /// key is not greater than the previous one, or if the key or value is too large for the
+    /// block encoding. This is the API for producing SSTs outside of the storage engine. The
+    /// entries are written at `TS_DEFAULT`, which every read sees.
+    pub fn try_add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let key = KeySlice::from_slice(key, TS_DEFAULT);
+        self.check_entry(key, value)?;
+        self.add_entry(key, value, 0, false);
+        Ok(())
+    }
+
+    /// Adds a delete of `key`, with the same checks as `try_add`.
+    pub fn try_delete(&mut self, key: &[u8]) -> Result<()> {
+        let key = KeySlice::from_slice(key, TS_DEFAULT);
+        self.check_entry(key, &[])?;
+        self.add_entry(key, &[], 0, true);
+        Ok(())
+    }
+
+    fn check_entry(&self, key: KeySlice, value: &[u8]) -> Result<()> {
+        let (key, value_len) = (key.key_ref(), value.len());
+        if key.is_empty() {
+            bail!("empty keys are not allowed");
+        }
+        if key.len() > MAX_KEY_SIZE || value_len > MAX_VALUE_SIZE {
+            bail!(
+                "key of {} bytes or value of {} bytes exceeds the limit of {} and {} bytes",
+                key.len(),
+                value_len,
+                MAX_KEY_SIZE,
+                MAX_VALUE_SIZE
+            );
+        }
+        self.check_key_order(KeySlice::from_slice(key, TS_DEFAULT))
+    }
+
+    /// Check that `key` follows the last key added, in the order of user keys ascending and then
+    /// timestamps descending.
+    fn check_key_order(&self, key: KeySlice) -> Result<()> {
+        match self.last_added_key() {
+            Some(last_key) if key == last_key => {
+                bail!("duplicate key {:?}", Bytes::copy_from_slice(key.key_ref()))
+            }
+            Some(last_key) if key < last_key => bail!(
+                "key {:?} is added after the greater key {:?}",
+                key,
+                last_key
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the first key that breaks the order if the order is checked.
+    fn verify_key(&mut self, key: KeySlice) {
+        if !self.verify_key_order || self.key_order_violation.is_some() {
+            return;
+        }
+        if let Err(e) = self.check_key_order(key) {
+            self.key_order_violation = Some(e);
+        }
+    }
+
+    /// Builds the SSTable at `path` without a block cache, failing if it is empty. The SSTable
+    /// is given id 0 and can be read with `SsTable::open_path`.
+    pub fn finish(self, path: impl AsRef<Path>) -> Result<SsTable> {
+        if self.is_empty() {
+            bail!("cannot build an SSTable without entries");
+        }
+        self.build(0, None, path)
+    }
+
+    /// Adds the entries of a sorted iterator until the iterator is exhausted or the estimated size
+    /// of the SSTable reaches `target_size` at the first version of a key. Entries with an empty value are skipped if
+    /// `skip_deletes` is set.
+    ///
+    /// This is the fast path of `add` for compaction and flush: the size of each entry is only
+    /// computed once to decide whether it fits into the current block, and the first key of a
+    /// block is only copied when the block is started.
+    pub fn add_sorted_entries<I>(
+        &mut self,
+        iter: &mut I,
+        target_size: usize,
+        skip_deletes: bool,
+    ) -> Result<()>
+    where
+        I: for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+    {
+        while iter.is_valid()
+            && (self.estimated_size() < target_size || self.is_last_key(iter.key().key_ref()))
+        {
+            let (key, value) = (iter.key(), iter.value());
+            let is_delete = iter.is_deleted();
+            if skip_deletes && is_delete {
+                iter.next()?;
+                continue;
+            }
+            self.verify_key(key);
+            let meta = iter.value_meta();
+            let entry_size = BlockBuilder::entry_size(key, value, meta);
+            if !self.builder.is_empty() && !self.builder.fits(entry_size) {
+                self.split_new_block();
+            }
+            if self.builder.is_empty() {
+                self.first_key.set_from_slice(key);
+            }
+            self.record_key(key);
+            self.properties.add_entry(key.key_ref(), value.len());
+            self.record_expiry(value);
+            self.builder.add_unchecked(key, value, meta, is_delete);
+            self.last_key.set_from_slice(key);
+            iter.next()?;
+        }
+        Ok(())
+    }
+
+    /// Appends an encoded block copied from another SSTable without re-encoding it, which is used
+    /// when a block passes through a compaction unmodified. All keys in the block must be greater
+    /// than the keys added before.
+    pub fn add_encoded_block(&mut self, block: Arc<Block>, encoded: &[u8]) {
+        let mut iter = BlockIterator::create_and_seek_to_first(block);
+        self.verify_key(iter.key());
+        self.split_new_block();
+        let first_key = iter.key().to_key_vec();
+        let mut last_key = KeyVec::new();
+        while iter.is_valid() {
+            if self.verify_key_order
+                && self.key_order_violation.is_none()
+                && !last_key.is_empty()
+                && iter.key() <= last_key.as_key_slice()
+            {
+                self.key_order_violation = Some(anyhow::anyhow!(
+                    "key {:?} follows the greater or equal key {:?} in a copied block",
+                    iter.key(),
+                    last_key
+                ));
+            }
+            self.record_key(iter.key());
+            self.properties
+                .add_entry(iter.key().key_ref(), iter.value().len());
+            self.record_expiry(iter.value());
+            last_key.set_from_slice(iter.key());
+            iter.next();
+        }
+        self.meta.push(BlockMeta {
+            offset: self.data.len(),
+            first_key: first_key.into_key_bytes(),
+            last_key: last_key.into_key_bytes(),
+        });
+        self.data.extend_from_slice(encoded);
+    }
+
+    /// Add range tombstones deleting the keys of the SSTs older than this one, see
+    /// `RangeTombstones`. The key range of the SST covers them.
+    pub fn add_range_tombstones(&mut self, tombstones: &RangeTombstones) {
+        self.properties.range_tombstones.extend(tombstones);
+    }
+
+    /// Check if no key-value pair or range tombstone has been added to the SSTable.
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_empty()
+            && self.builder.is_empty()
+            && self.properties.range_tombstones.is_empty()
     }
 
     /// Get the estimated size of the SSTable.
@@ -90,31 +445,107 @@ impl SsTableBuilder {
         block_cache: Option<Arc<BlockCache>>,
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
+        if let Some(e) = self.key_order_violation.take() {
+            return Err(e.context(format!("refusing to build SST {} out of order", id)));
+        }
+        // The SSTs are indexed by their blocks, so an SST of range tombstones only also stores
+        // the delete of the first key they delete, at the timestamp of the tombstone
+        if self.meta.is_empty() && self.builder.is_empty() {
+            let first_tombstone = (self.properties.range_tombstones.iter())
+                .next()
+                .map(|(start, _, ts)| (Bytes::copy_from_slice(start), ts));
+            if let Some((start, ts)) = first_tombstone {
+                self.add_entry(KeySlice::from_slice(&start, ts), &[], 0, true);
+            }
+        }
         self.split_new_block();
+        self.properties.block_size = self.next_block_size() as u32;
+        let tombstones_max_ts = self.properties.range_tombstones.max_ts();
+        self.properties.max_ts = Some(self.max_ts.max(tombstones_max_ts.unwrap_or(TS_DEFAULT)));
+        if let Some(expiries) = self.expiries.take() {
+            self.properties.expiry_histogram = ExpiryHistogram::build(expiries);
+        }
         let mut buf = self.data;
         let block_meta_offset = buf.len();
         BlockMeta::encode_block_meta(&self.meta, &mut buf);
         buf.put_u32(block_meta_offset as u32);
 
-        let bloom = Bloom::build_from_key_hashes(
-            &self.key_hashes,
-            Bloom::bloom_bits_per_key(self.key_hashes.len(), 0.01),
-        );
+        let bits_per_key = self
+            .bloom_bits_per_key
+            .unwrap_or_else(|| Bloom::bloom_bits_per_key(self.key_hashes.len(), 0.01));
+        let bloom = Bloom::build_from_key_hashes(&self.key_hashes, bits_per_key);
         let bloom_offset = buf.len();
         bloom.encode(&mut buf);
         buf.put_u32(bloom_offset as u32);
 
-        let file = FileObject::create(path.as_ref(), buf)?;
+        let prefix_bloom = self.prefix_extractor.as_ref().map(|extractor| {
+            let bits_per_key = self
+                .bloom_bits_per_key
+                .unwrap_or_else(|| Bloom::bloom_bits_per_key(self.prefix_hashes.len(), 0.01));
+            let prefix_bloom = Bloom::build_from_key_hashes(&self.prefix_hashes, bits_per_key);
+            let mut encoded = Vec::new();
+            prefix_bloom.encode(&mut encoded);
+            self.properties.prefix_extractor = Some(extractor.name());
+            self.properties.prefix_bloom = Some(encoded.into());
+            prefix_bloom
+        });
+
+        let properties_offset = buf.len();
+        self.properties.encode(&mut buf);
+        buf.put_u32(properties_offset as u32);
+
+        // Charged before the file is written, so that an SST over a strict cache limit is not left
+        // on disk
+        let pooled_metadata = block_cache
+            .as_ref()
+            .is_some_and(|block_cache| block_cache.has_metadata_pool());
+        let metadata_charge = match &block_cache {
+            Some(block_cache) if !pooled_metadata => {
+                Some(block_cache.charge_metadata(metadata_size(&self.meta, Some(&bloom)))?)
+            }
+            _ => None,
+        };
+        let file = FileObject::create_observed(
+            path.as_ref(),
+            buf,
+            self.sync_chunk_size,
+            self.write_observers.as_deref(),
+        )?;
+        let (first_key, last_key) = key_range(&self.meta, &self.properties.range_tombstones);
+        let num_blocks = self.meta.len();
+        let (block_meta, bloom) = match &block_cache {
+            Some(block_cache) if pooled_metadata => {
+                let metadata = TableMetadata {
+                    block_meta: self.meta,
+                    bloom: Some(bloom),
+                };
+                block_cache.insert_metadata(id, Arc::new(metadata));
+                (vec![], None)
+            }
+            _ => (self.meta, Some(bloom)),
+        };
         Ok(SsTable {
             file,
             block_meta_offset,
+            bloom_offset,
+            properties_offset,
+            num_blocks,
+            pooled_metadata,
             id,
             block_cache,
-            first_key: self.meta.first().unwrap().first_key.clone(),
-            last_key: self.meta.last().unwrap().last_key.clone(),
-            block_meta: self.meta,
-            bloom: Some(bloom),
-            max_ts: 0,
+            first_key,
+            last_key,
+            block_meta,
+            bloom,
+            prefix_bloom,
+            max_ts: self.properties.max_ts.unwrap_or(TS_DEFAULT),
+            useless_probes: AtomicUsize::new(0),
+            level: AtomicUsize::new(0),
+            being_compacted: AtomicBool::new(false),
+            properties: self.properties,
+            shared_metadata: None,
+            metadata_charge,
+            verify_checksums: true,
         })
     }
 
@@ -122,14 +553,24 @@ impl SsTableBuilder {
         if self.builder.is_empty() {
             return;
         }
-        let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
-        let encoded_block = builder.build().encode();
         self.meta.push(BlockMeta {
             offset: self.data.len(),
-            first_key: KeyBytes::from_vec(std::mem::take(&mut self.first_key)),
-            last_key: KeyBytes::from_vec(std::mem::take(&mut self.last_key)),
+            first_key: KeyBytes::from_bytes_with_ts(
+                Bytes::copy_from_slice(self.first_key.key_ref()),
+                self.first_key.ts(),
+            ),
+            last_key: KeyBytes::from_bytes_with_ts(
+                Bytes::copy_from_slice(self.last_key.key_ref()),
+                self.last_key.ts(),
+            ),
         });
-        self.data.extend(encoded_block);
+        self.first_key.clear();
+        self.last_key.clear();
+        self.builder
+            .encode_compressed_into(self.compression, &mut self.data);
+        // Reuse the buffers of the block builder rather than allocating new ones for each block
+        let next_block_size = self.next_block_size();
+        self.builder.reset(next_block_size);
     }
 
     #[cfg(test)]
@@ -12,44 +12,218 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ops::Bound;
 use std::sync::Arc;
 
 use anyhow::Result;
 
 use super::SsTable;
-use crate::{block::BlockIterator, iterators::StorageIterator, key::KeySlice};
+use crate::block_cache::BlockHandle;
+use crate::key::{KeySlice, TS_RANGE_BEGIN, TS_RANGE_END};
+use crate::prefetch::ScanPrefetch;
+use crate::scan_memory::ScanMemory;
+use crate::{block::BlockIterator, iterators::StorageIterator};
 
 /// An iterator over the contents of an SSTable.
 pub struct SsTableIterator {
     table: Arc<SsTable>,
     blk_iter: BlockIterator,
     blk_idx: usize,
+    /// Pins the current block in the block cache, until the iterator moves past it.
+    block_handle: Option<BlockHandle>,
+    /// Queues the reads of the blocks after the current one, if set.
+    prefetch: Option<Arc<ScanPrefetch>>,
+    /// The last block queued for prefetching.
+    prefetched_to: usize,
+    /// Accounts the current block of the iterator, if set.
+    memory: Option<Arc<ScanMemory>>,
+    /// The bytes of the current block charged to `memory`.
+    charged: usize,
 }
 
 impl SsTableIterator {
     /// Create a new iterator and seek to the first key-value pair in the first data block.
     pub fn create_and_seek_to_first(table: Arc<SsTable>) -> Result<Self> {
+        let block_handle = table.read_block_pinned(0)?;
         Ok(Self {
-            blk_iter: BlockIterator::create_and_seek_to_first(table.read_block_cached(0)?),
+            blk_iter: BlockIterator::create_and_seek_to_first(block_handle.block().clone()),
             blk_idx: 0,
+            block_handle: Some(block_handle),
             table,
+            prefetch: None,
+            prefetched_to: 0,
+            memory: None,
+            charged: 0,
         })
     }
 
+    /// Create a new iterator and seek to the first key-value pair within `lower`.
+    pub fn create_and_seek_to_bound(table: Arc<SsTable>, lower: Bound<&[u8]>) -> Result<Self> {
+        let iter = match lower {
+            Bound::Included(key) => {
+                Self::create_and_seek_to_key(table, KeySlice::from_slice(key, TS_RANGE_BEGIN))?
+            }
+            Bound::Excluded(key) => {
+                let mut iter =
+                    Self::create_and_seek_to_key(table, KeySlice::from_slice(key, TS_RANGE_BEGIN))?;
+                while iter.is_valid() && iter.key().key_ref() == key {
+                    iter.next()?;
+                }
+                iter
+            }
+            Bound::Unbounded => Self::create_and_seek_to_first(table)?,
+        };
+        Ok(iter)
+    }
+
+    /// Create a new iterator and seek to the last key-value pair in the last data block.
+    pub fn create_and_seek_to_last(table: Arc<SsTable>) -> Result<Self> {
+        let blk_idx = table.num_of_blocks().saturating_sub(1);
+        let block_handle = table.read_block_pinned(blk_idx)?;
+        Ok(Self {
+            blk_iter: BlockIterator::create_and_seek_to_last(block_handle.block().clone()),
+            blk_idx,
+            block_handle: Some(block_handle),
+            table,
+            prefetch: None,
+            prefetched_to: 0,
+            memory: None,
+            charged: 0,
+        })
+    }
+
+    /// Create a new iterator and seek to the last key-value pair which <= `key`.
+    pub fn create_and_seek_to_key_rev(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
+        let mut iter = Self::create_and_seek_to_key(table, key)?;
+        if !iter.is_valid() {
+            iter.seek_to_last()?;
+        } else if iter.key() > key {
+            iter.prev()?;
+        }
+        Ok(iter)
+    }
+
+    /// Create a new iterator and seek to the last key-value pair within `upper`, for reading the
+    /// SST backward with `prev`.
+    pub fn create_and_seek_to_bound_rev(table: Arc<SsTable>, upper: Bound<&[u8]>) -> Result<Self> {
+        let iter = match upper {
+            Bound::Included(key) => {
+                Self::create_and_seek_to_key_rev(table, KeySlice::from_slice(key, TS_RANGE_END))?
+            }
+            Bound::Excluded(key) => {
+                let mut iter = Self::create_and_seek_to_key_rev(
+                    table,
+                    KeySlice::from_slice(key, TS_RANGE_BEGIN),
+                )?;
+                while iter.is_valid() && iter.key().key_ref() == key {
+                    iter.prev()?;
+                }
+                iter
+            }
+            Bound::Unbounded => Self::create_and_seek_to_last(table)?,
+        };
+        Ok(iter)
+    }
+
+    /// Charge the current block of the iterator to `memory` as the iterator moves.
+    pub fn with_memory(mut self, memory: Arc<ScanMemory>) -> Result<Self> {
+        self.memory = Some(memory);
+        self.charge_block()?;
+        Ok(self)
+    }
+
+    fn charge_block(&mut self) -> Result<()> {
+        let Some(memory) = &self.memory else {
+            return Ok(());
+        };
+        memory.release(self.charged);
+        self.charged = if self.blk_iter.is_valid() {
+            self.table.block_len(self.blk_idx)?
+        } else {
+            0
+        };
+        memory.charge(self.charged);
+        Ok(())
+    }
+
+    /// Read `readahead` blocks ahead of the current one in the background as the iterator moves.
+    pub fn with_prefetch(mut self, prefetch: Arc<ScanPrefetch>) -> Self {
+        self.prefetch = Some(prefetch);
+        self.prefetched_to = self.blk_idx;
+        self.on_block_read();
+        self
+    }
+
+    /// Record the read of the current block and queue the prefetch of the next ones.
+    fn on_block_read(&mut self) {
+        let Some(prefetch) = &self.prefetch else {
+            return;
+        };
+        if self.blk_idx >= self.table.num_of_blocks() {
+            return;
+        }
+        prefetch.read(self.table.sst_id(), self.blk_idx);
+        let last = (self.blk_idx + prefetch.readahead()).min(self.table.num_of_blocks() - 1);
+        for blk_idx in (self.prefetched_to.max(self.blk_idx) + 1)..=last {
+            prefetch.prefetch(&self.table, blk_idx);
+        }
+        self.prefetched_to = self.prefetched_to.max(last);
+    }
+
     /// Seek to the first key-value pair in the first data block.
     pub fn seek_to_first(&mut self) -> Result<()> {
-        self.blk_iter = BlockIterator::create_and_seek_to_first(self.table.read_block_cached(0)?);
+        self.block_handle = None;
+        let block_handle = self.table.read_block_pinned(0)?;
+        self.blk_iter
+            .seek_to_first_of_block(block_handle.block().clone());
+        self.block_handle = Some(block_handle);
         self.blk_idx = 0;
-        Ok(())
+        self.on_block_read();
+        self.charge_block()
+    }
+
+    /// Seek to the last key-value pair in the last data block.
+    pub fn seek_to_last(&mut self) -> Result<()> {
+        self.block_handle = None;
+        let blk_idx = self.table.num_of_blocks().saturating_sub(1);
+        let block_handle = self.table.read_block_pinned(blk_idx)?;
+        self.blk_iter
+            .seek_to_last_of_block(block_handle.block().clone());
+        self.block_handle = Some(block_handle);
+        self.blk_idx = blk_idx;
+        self.charge_block()
+    }
+
+    /// Move to the previous key-value pair, invalidating the iterator before the first one. The
+    /// blocks are not prefetched backward.
+    pub fn prev(&mut self) -> Result<()> {
+        self.blk_iter.prev();
+        if self.blk_iter.is_valid() {
+            return Ok(());
+        }
+        self.block_handle = None;
+        if self.blk_idx > 0 {
+            self.blk_idx -= 1;
+            let block_handle = self.table.read_block_pinned(self.blk_idx)?;
+            self.blk_iter
+                .seek_to_last_of_block(block_handle.block().clone());
+            self.block_handle = Some(block_handle);
+        }
+        self.charge_block()
     }
 
     /// Create a new iterator and seek to the first key-value pair which >= `key`.
     pub fn create_and_seek_to_key(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&table, key)?;
+        let (blk_idx, blk_iter, block_handle) = Self::seek_to_key_inner(&table, key)?;
         Ok(Self {
             table,
             blk_iter,
             blk_idx,
+            block_handle,
+            prefetch: None,
+            prefetched_to: 0,
+            memory: None,
+            charged: 0,
         })
     }
 
@@ -57,24 +231,35 @@ impl SsTableIterator {
     /// Note: You probably want to review the handout for detailed explanation when implementing
     /// this function.
     pub fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&self.table, key)?;
+        self.block_handle = None;
+        let (blk_idx, blk_iter, block_handle) = Self::seek_to_key_inner(&self.table, key)?;
         self.blk_idx = blk_idx;
         self.blk_iter = blk_iter;
-        Ok(())
+        self.block_handle = block_handle;
+        self.on_block_read();
+        self.charge_block()
     }
 
-    fn seek_to_key_inner(table: &Arc<SsTable>, key: KeySlice) -> Result<(usize, BlockIterator)> {
-        let mut blk_idx = table.find_block_idx(key);
-        let mut blk_iter =
-            BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
-        if !blk_iter.is_valid() {
-            blk_idx += 1;
-            if blk_idx < table.num_of_blocks() {
-                blk_iter =
-                    BlockIterator::create_and_seek_to_first(table.read_block_cached(blk_idx)?);
-            }
+    /// Seek to `key`, returning the block it is in and the handle pinning it, `None` if `key` is
+    /// past the last block.
+    fn seek_to_key_inner(
+        table: &Arc<SsTable>,
+        key: KeySlice,
+    ) -> Result<(usize, BlockIterator, Option<BlockHandle>)> {
+        let blk_idx = table.find_block_idx(key)?;
+        let block_handle = table.read_block_pinned(blk_idx)?;
+        let blk_iter = BlockIterator::create_and_seek_to_key(block_handle.block().clone(), key);
+        if blk_iter.is_valid() {
+            return Ok((blk_idx, blk_iter, Some(block_handle)));
+        }
+        drop(block_handle);
+        let blk_idx = blk_idx + 1;
+        if blk_idx >= table.num_of_blocks() {
+            return Ok((blk_idx, blk_iter, None));
         }
-        Ok((blk_idx, blk_iter))
+        let block_handle = table.read_block_pinned(blk_idx)?;
+        let blk_iter = BlockIterator::create_and_seek_to_first(block_handle.block().clone());
+        Ok((blk_idx, blk_iter, Some(block_handle)))
     }
 }
 
@@ -82,7 +267,7 @@ impl StorageIterator for SsTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     /// Return the `key` that's held by the underlying block iterator.
-    fn key(&self) -> KeySlice {
+    fn key(&self) -> KeySlice<'_> {
         self.blk_iter.key()
     }
 
@@ -91,6 +276,14 @@ impl StorageIterator for SsTableIterator {
         self.blk_iter.value()
     }
 
+    fn value_meta(&self) -> u8 {
+        self.blk_iter.value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.blk_iter.is_deleted()
+    }
+
     /// Return whether the current block iterator is valid or not.
     fn is_valid(&self) -> bool {
         self.blk_iter.is_valid()
@@ -101,13 +294,29 @@ impl StorageIterator for SsTableIterator {
     fn next(&mut self) -> Result<()> {
         self.blk_iter.next();
         if !self.blk_iter.is_valid() {
+            self.block_handle = None;
             self.blk_idx += 1;
             if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
-                    self.table.read_block_cached(self.blk_idx)?,
-                );
+                let block_handle = self.table.read_block_pinned(self.blk_idx)?;
+                self.blk_iter
+                    .seek_to_first_of_block(block_handle.block().clone());
+                self.block_handle = Some(block_handle);
+                self.on_block_read();
             }
+            self.charge_block()?;
         }
         Ok(())
     }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        self.seek_to_key(key)
+    }
+}
+
+impl Drop for SsTableIterator {
+    fn drop(&mut self) {
+        if let Some(memory) = &self.memory {
+            memory.release(self.charged);
+        }
+    }
 }
@@ -0,0 +1,266 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SST metadata in POSIX shared memory, so that processes reading the same directory map a
+//! single copy of the bloom filters instead of each decoding their own.
+//!
+//! The first process to open an SST publishes its encoded bloom filter and block index in a
+//! shared memory object named after the namespace, the SST id and the generation of the SST, see
+//! `SsTable::metadata_generation`. The generation keeps an object left behind by an SST with the
+//! same id, e.g., of a directory that was removed and created again, from being taken for the
+//! metadata of another SST. The other processes map the object read-only. The bloom filter is
+//! used in place, while the block index is still decoded into each process, as its keys are
+//! cloned out of the SST.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::{Result, bail, ensure};
+use bytes::{Buf, BufMut, Bytes};
+use parking_lot::Mutex;
+
+use super::bloom::Bloom;
+
+/// Written last by the publisher, so that a region is only used once it is complete.
+const READY_MAGIC: u32 = 0x5353_544d;
+
+/// The magic, the length of the bloom filter and the length of the block index.
+const HEADER_LEN: usize = 12;
+
+/// The shared memory objects of the SSTs of a directory.
+#[derive(Debug)]
+pub struct SharedMetadata {
+    namespace: String,
+    /// The generations of the SSTs this process published or mapped, to find their objects on
+    /// removal.
+    generations: Mutex<HashMap<usize, u32>>,
+}
+
+impl SharedMetadata {
+    /// The namespace must be the same for all processes reading a directory and different for
+    /// different directories, e.g., a name derived from the path.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            generations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn name(&self, sst_id: usize, generation: u32) -> Result<CString> {
+        Ok(CString::new(format!(
+            "/{}-{}-{:08x}",
+            self.namespace, sst_id, generation
+        ))?)
+    }
+
+    /// Map the metadata of an SST, `None` if it is not published (yet).
+    pub fn open(&self, sst_id: usize, generation: u32) -> Result<Option<SharedRegion>> {
+        let name = self.name(sst_id, generation)?;
+        // SAFETY: `name` is NUL-terminated
+        let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDONLY, 0) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::NotFound {
+                return Ok(None);
+            }
+            return Err(err.into());
+        }
+        let region = map_fd(fd, None, libc::PROT_READ);
+        // SAFETY: the file descriptor is not used after this
+        unsafe { libc::close(fd) };
+        let region = region?;
+        if region.len < HEADER_LEN || region.magic().load(Ordering::Acquire) != READY_MAGIC {
+            return Ok(None);
+        }
+        let (bloom_len, block_meta_len) = region.lens();
+        if HEADER_LEN + bloom_len + block_meta_len > region.len {
+            bail!(
+                "corrupt shared metadata of SST {}: {} bytes of bloom filter and block index in \
+                 a region of {} bytes",
+                sst_id,
+                bloom_len + block_meta_len,
+                region.len
+            );
+        }
+        self.generations.lock().insert(sst_id, generation);
+        Ok(Some(region))
+    }
+
+    /// Publish the encoded bloom filter and block index of an SST. Does nothing if another
+    /// process published them already.
+    pub fn publish(
+        &self,
+        sst_id: usize,
+        generation: u32,
+        bloom: &[u8],
+        block_meta: &[u8],
+    ) -> Result<()> {
+        self.generations.lock().insert(sst_id, generation);
+        let name = self.name(sst_id, generation)?;
+        // SAFETY: `name` is NUL-terminated
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                0o600,
+            )
+        };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::AlreadyExists {
+                return Ok(());
+            }
+            return Err(err.into());
+        }
+        let len = HEADER_LEN + bloom.len() + block_meta.len();
+        let region = map_fd(fd, Some(len), libc::PROT_READ | libc::PROT_WRITE);
+        // SAFETY: the file descriptor is not used after this
+        unsafe { libc::close(fd) };
+        let region = region?;
+        // SAFETY: the object was created by this process and has not been marked ready, so no
+        // other process reads it yet
+        let data = unsafe { std::slice::from_raw_parts_mut(region.ptr, region.len) };
+        let mut buf = &mut data[4..];
+        buf.put_u32(bloom.len() as u32);
+        buf.put_u32(block_meta.len() as u32);
+        buf.put_slice(bloom);
+        buf.put_slice(block_meta);
+        region.magic().store(READY_MAGIC, Ordering::Release);
+        Ok(())
+    }
+
+    /// Remove the metadata of a deleted SST published or mapped by this process. Processes that
+    /// mapped it keep their mapping.
+    pub fn remove(&self, sst_id: usize) -> Result<()> {
+        let Some(generation) = self.generations.lock().remove(&sst_id) else {
+            return Ok(());
+        };
+        let name = self.name(sst_id, generation)?;
+        // SAFETY: `name` is NUL-terminated
+        if unsafe { libc::shm_unlink(name.as_ptr()) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Map a shared memory object, resizing it to `len` first if given.
+fn map_fd(fd: libc::c_int, len: Option<usize>, prot: libc::c_int) -> Result<SharedRegion> {
+    let len = match len {
+        Some(len) => {
+            // SAFETY: `fd` is a valid file descriptor
+            if unsafe { libc::ftruncate(fd, len as libc::off_t) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            len
+        }
+        None => {
+            let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+            // SAFETY: `fd` is a valid file descriptor and `stat` is only read after `fstat`
+            // fills it
+            if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            unsafe { stat.assume_init() }.st_size as usize
+        }
+    };
+    if len == 0 {
+        return Ok(SharedRegion {
+            ptr: std::ptr::null_mut(),
+            len,
+        });
+    }
+    // SAFETY: a new mapping of `len` bytes of `fd`, unmapped when the region is dropped
+    let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0) };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(SharedRegion {
+        ptr: ptr as *mut u8,
+        len,
+    })
+}
+
+/// A mapping of the published metadata of an SST.
+pub struct SharedRegion {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is only written before it is marked ready
+unsafe impl Send for SharedRegion {}
+unsafe impl Sync for SharedRegion {}
+
+impl SharedRegion {
+    fn magic(&self) -> &AtomicU32 {
+        // SAFETY: the mapping is page-aligned and at least `HEADER_LEN` bytes long
+        unsafe { AtomicU32::from_ptr(self.ptr as *mut u32) }
+    }
+
+    fn data(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and not written once it is ready
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// The lengths of the bloom filter and of the block index, which `SharedMetadata::open`
+    /// checked against the length of the region.
+    fn lens(&self) -> (usize, usize) {
+        let mut header = &self.data()[4..HEADER_LEN];
+        (header.get_u32() as usize, header.get_u32() as usize)
+    }
+
+    /// The encoded block index.
+    pub fn block_meta(&self) -> &[u8] {
+        let (bloom_len, block_meta_len) = self.lens();
+        let start = HEADER_LEN + bloom_len;
+        &self.data()[start..start + block_meta_len]
+    }
+
+    /// The bloom filter, using the bits in the shared memory instead of a copy. The filter keeps
+    /// the region mapped.
+    pub(crate) fn bloom(self: &Arc<Self>) -> Result<Bloom> {
+        let (bloom_len, _) = self.lens();
+        ensure!(bloom_len > 0, "the shared metadata has no bloom filter");
+        let k = self.data()[HEADER_LEN + bloom_len - 1];
+        let filter = Bytes::from_owner(RegionOwner(self.clone()));
+        Ok(Bloom {
+            filter: filter.slice(HEADER_LEN..HEADER_LEN + bloom_len - 1),
+            k,
+        })
+    }
+}
+
+/// A region shared with the `Bytes` of a bloom filter.
+struct RegionOwner(Arc<SharedRegion>);
+
+impl AsRef<[u8]> for RegionOwner {
+    fn as_ref(&self) -> &[u8] {
+        self.0.data()
+    }
+}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            // SAFETY: the mapping was created by `map_fd` and is not referenced anymore
+            unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+        }
+    }
+}
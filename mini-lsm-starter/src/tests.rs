@@ -1,7 +1,86 @@
 //! DO NOT MODIFY -- Mini-LSM tests modules
 //! This file will be automatically rewritten by the copy-test command.
 
+mod background_error;
+mod block_checksum;
+mod block_compression;
+mod block_pinning;
+mod block_restart;
+#[cfg(feature = "csv")]
+mod bulk_import;
+mod cache_charge;
+mod cache_stats;
+mod cancellation;
+mod column_family;
+mod compact_range;
+mod compaction_claim;
+mod compaction_filter;
+mod compaction_schedule;
+mod compaction_strategy;
+mod concurrent_compaction;
+mod conditional_write;
+mod cpu_usage;
+mod data_paths;
+mod delete_range;
+mod deletion_compaction;
+mod disk_space;
+mod empty_value;
+mod entry_compaction;
+mod epoch;
+#[cfg(any(feature = "csv", feature = "parquet"))]
+mod export;
+mod fifo_compaction;
+mod flush_filter;
+mod flush_to_base_level;
+mod format_compat;
+mod format_migration;
 mod harness;
+mod idempotent_writes;
+mod io_priority;
+mod iterator_misuse;
+mod iterator_seek;
+mod key_distribution;
+mod key_range_stats;
+mod key_sample;
+mod lazy_leveled;
+mod leveled_compaction;
+mod linearizability;
+mod manifest_compaction;
+mod memory_usage;
+mod merge_iterator;
+mod metadata_cache;
+mod multi_get;
+mod mvcc;
+mod negative_cache;
+mod options_file;
+mod prefetch;
+mod prefix_bloom;
+mod quota;
+mod range_export;
+mod rate_limiter;
+mod read_tier;
+mod scan_chunks;
+mod scan_memory;
+mod scan_rev;
+#[cfg(feature = "tokio")]
+mod scan_stream;
+mod seek_compaction;
+mod sequential_writes;
+mod session;
+mod shared_metadata;
+mod simple_leveled;
+mod size_limits;
+mod snapshot_diff;
+mod sorted_runs;
+mod sst_builder;
+mod sst_reader;
+mod task_handle;
+mod tiered_compaction;
+mod trash;
+mod ttl;
+mod txn;
+mod value_meta;
+mod wal_recovery;
 mod week1_day1;
 mod week1_day2;
 mod week1_day3;
@@ -9,3 +88,5 @@ mod week1_day4;
 mod week1_day5;
 mod week1_day6;
 mod week1_day7;
+mod write_batch;
+mod write_observer;
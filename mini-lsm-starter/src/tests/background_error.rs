@@ -0,0 +1,228 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, SimpleLeveledCompactionOptions};
+use crate::compaction_filter::{CompactionDecision, CompactionFilter};
+use crate::lsm_storage::{FlushDecision, FlushFilter, LsmEvent, LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_background_error_and_resume() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("db");
+    let storage = MiniLsm::open(&path, LsmStorageOptions::default_for_week1_day6_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+
+    // Make the flush thread fail to create SST files
+    std::fs::remove_dir_all(&path).unwrap();
+    let handle = storage.notify_next_flush();
+    for i in 0..3 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    assert!(
+        handle
+            .wait_timeout(Duration::from_secs(5))
+            .expect("flush thread did not flush")
+            .is_err()
+    );
+
+    assert!(storage.put(b"4", b"value").is_err());
+    assert!(storage.delete(b"1").is_err());
+    assert_eq!(
+        storage.get(b"1").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+    assert!(storage.resume().is_err());
+    assert!(storage.put(b"4", b"value").is_err());
+
+    std::fs::create_dir(&path).unwrap();
+    storage.resume().unwrap();
+    assert!(storage.inner.state.read().imm_memtables.is_empty());
+    storage.put(b"4", b"value").unwrap();
+    assert_eq!(
+        storage.get(b"2").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+}
+
+/// Blocks the flushes until the sender of `release` is dropped, telling when the first one starts.
+struct BlockingFlushFilter {
+    started: Mutex<mpsc::Sender<()>>,
+    release: Mutex<mpsc::Receiver<()>>,
+}
+
+impl FlushFilter for BlockingFlushFilter {
+    fn filter(&self, _key: &[u8], _value: Option<&[u8]>) -> FlushDecision {
+        let _ = self.started.lock().send(());
+        let _ = self.release.lock().recv_timeout(Duration::from_secs(5));
+        FlushDecision::Keep
+    }
+}
+
+#[test]
+fn test_writes_fail_while_resuming() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("db");
+    let storage = MiniLsm::open(&path, LsmStorageOptions::default_for_week1_day6_test()).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    let handle = storage.notify_next_flush();
+    for i in 0..3 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    assert!(
+        handle
+            .wait_timeout(Duration::from_secs(5))
+            .expect("flush thread did not flush")
+            .is_err()
+    );
+    std::fs::create_dir(&path).unwrap();
+
+    let (started_tx, started_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+    storage.add_flush_filter(Box::new(BlockingFlushFilter {
+        started: Mutex::new(started_tx),
+        release: Mutex::new(release_rx),
+    }));
+    let resume = {
+        let storage = storage.clone();
+        std::thread::spawn(move || storage.resume())
+    };
+    started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    // The writes fail without waiting for the flush of the resume
+    let start = Instant::now();
+    assert!(storage.put(b"3", b"value").is_err());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    drop(release_tx);
+    resume.join().unwrap().unwrap();
+    assert!(storage.inner.state.read().imm_memtables.is_empty());
+    storage.put(b"3", b"value").unwrap();
+    assert_eq!(
+        storage.get(b"1").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+}
+
+#[derive(Debug)]
+struct PanickingFilter;
+
+impl CompactionFilter for PanickingFilter {
+    fn filter(&self, _key: &[u8], _value: &[u8]) -> CompactionDecision {
+        panic!("filter bug");
+    }
+}
+
+fn simple_leveled() -> CompactionOptions {
+    CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 2,
+    })
+}
+
+/// Open a storage with a compaction thread, recording its background errors.
+fn open_with_events(
+    path: &std::path::Path,
+    options: LsmStorageOptions,
+) -> (Arc<MiniLsm>, Arc<Mutex<Vec<LsmEvent>>>) {
+    let storage = MiniLsm::open(path, options).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    storage.add_event_listener(Box::new(move |event| recorded.lock().push(event.clone())));
+    (storage, events)
+}
+
+#[test]
+fn test_background_panic() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        compaction_filter: Some(Arc::new(PanickingFilter)),
+        ..LsmStorageOptions::default_for_week2_test(simple_leveled())
+    };
+    let (storage, events) = open_with_events(dir.path(), options);
+    let handle = storage.notify_next_compaction();
+    for round in 0..2 {
+        storage
+            .put(b"key", format!("{}", round).as_bytes())
+            .unwrap();
+        storage.force_flush().unwrap();
+    }
+    let err = handle
+        .wait_timeout(Duration::from_secs(5))
+        .expect("compaction thread did not compact")
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("compaction panicked: filter bug"));
+
+    let err = storage.put(b"key", b"value").unwrap_err();
+    assert!(format!("{:#}", err).contains("filter bug"), "{:#}", err);
+    assert!(matches!(
+        &events.lock()[..],
+        [LsmEvent::BackgroundError { task: "compaction", error }] if error.contains("filter bug")
+    ));
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from_static(b"1")));
+}
+
+#[test]
+fn test_background_corruption_error() {
+    let dir = tempdir().unwrap();
+    let (storage, events) = open_with_events(
+        dir.path(),
+        LsmStorageOptions::default_for_week2_test(simple_leveled()),
+    );
+    storage.put(b"key", b"0").unwrap();
+    storage.force_flush().unwrap();
+    // Flip a byte of the value in the first SST, whose checksum no longer matches
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(storage.inner.path_of_sst(sst_id))
+        .unwrap();
+    file.write_all_at(b"X", 1 + 1 + 3 + 8 + 1).unwrap();
+
+    // The second SST makes L0 reach its trigger
+    let handle = storage.notify_next_compaction();
+    storage.put(b"key", b"1").unwrap();
+    storage.force_flush().unwrap();
+    let err = handle
+        .wait_timeout(Duration::from_secs(5))
+        .expect("compaction thread did not compact")
+        .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("checksum mismatch"),
+        "{:#}",
+        err
+    );
+    // Recorded and reported once, rather than retried on every tick
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(storage.put(b"key", b"value").is_err());
+    assert!(matches!(
+        &events.lock()[..],
+        [LsmEvent::BackgroundError { task: "compaction", error }] if error.contains("checksum")
+    ));
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from_static(b"1")));
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::os::unix::fs::FileExt;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::block::{
+    Block, BlockBuilder, CHECKSUM_SIZE, ChecksumError, ChecksumMode, CompressionType,
+    is_checksum_error, is_corruption_error,
+};
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::table::SsTableBuilder;
+
+#[test]
+fn test_block_checksum() {
+    let mut builder = BlockBuilder::new(4096);
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"key"), b"value"));
+    let mut encoded = builder.build().encode().to_vec();
+    assert!(Block::try_decode(Bytes::copy_from_slice(&encoded), ChecksumMode::Verify).is_ok());
+    encoded[5] ^= 1;
+    let err = Block::try_decode(Bytes::copy_from_slice(&encoded), ChecksumMode::Verify)
+        .err()
+        .unwrap();
+    let err = err.downcast_ref::<ChecksumError>().unwrap();
+    assert_ne!(err.stored, err.computed);
+    assert!(Block::try_decode(Bytes::copy_from_slice(&encoded), ChecksumMode::Skip).is_ok());
+    let err = Block::try_decode(Bytes::from_static(&[1, 2]), ChecksumMode::Verify)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.downcast_ref::<ChecksumError>(),
+        Some(&ChecksumError {
+            stored: 0,
+            computed: crc32fast::hash(&[1, 2])
+        })
+    );
+}
+
+#[test]
+fn test_malformed_block() {
+    let mut builder = BlockBuilder::new(4096).with_restart_interval(1);
+    for key in [&b"a"[..], b"b", b"c"] {
+        assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(key), b"value"));
+    }
+    let encoded = builder.build().encode();
+    // Without the checksum, which would catch all of these
+    let block = encoded.slice(..encoded.len() - CHECKSUM_SIZE);
+    let restarts_offset = block.len() - 2;
+    let offsets_offset = restarts_offset - 3 * 2;
+    let malformed = |offset: usize, bytes: &[u8]| {
+        let mut block = block.to_vec();
+        block[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Block::try_decode(block.into(), ChecksumMode::Absent)
+            .err()
+            .unwrap()
+    };
+    assert!(Block::try_decode(block.clone(), ChecksumMode::Absent).is_ok());
+    for err in [
+        // More restart points than bytes, or none
+        malformed(restarts_offset, &0x4fffu16.to_be_bytes()),
+        malformed(restarts_offset, &0xc000u16.to_be_bytes()),
+        // Not starting at the first entry, out of order, or past the entries
+        malformed(offsets_offset, &[0, 1]),
+        malformed(offsets_offset + 4, &[0, 1]),
+        malformed(offsets_offset + 4, &offsets_offset.to_be_bytes()[6..]),
+    ] {
+        assert!(is_corruption_error(&err), "{:#}", err);
+    }
+    let err = Block::try_decode(Bytes::from_static(&[1]), ChecksumMode::Absent)
+        .err()
+        .unwrap();
+    assert!(is_corruption_error(&err), "{:#}", err);
+}
+
+#[test]
+fn test_malformed_block_read() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        verify_block_checksums: false,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let sst = storage.inner.state.read().sstables[&sst_id].clone();
+    // The number of restart points, before the checksum and the codec flag of the only block
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(storage.inner.path_of_sst(sst_id))
+        .unwrap();
+    let offset = sst.block_len(0).unwrap() - CHECKSUM_SIZE - 1 - 2;
+    file.write_all_at(&0xc0ffu16.to_be_bytes(), offset as u64)
+        .unwrap();
+
+    let err = storage.get(b"key").unwrap_err();
+    assert!(is_corruption_error(&err), "{:#}", err);
+    assert!(format!("{:#}", err).contains("block 0 of SST"), "{:#}", err);
+    let err = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .err()
+        .unwrap();
+    assert!(is_corruption_error(&err), "{:#}", err);
+}
+
+/// Flip a bit in the first value of the only SST of a storage.
+fn corrupt_first_sst(storage: &MiniLsm) {
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(storage.inner.path_of_sst(sst_id))
+        .unwrap();
+    // Past the one-byte varint lengths of the overlap and the key, the key, its timestamp and the
+    // value length
+    file.write_all_at(b"V", 1 + 1 + 9 + 8 + 1).unwrap();
+}
+
+#[test]
+fn test_corrupted_block() {
+    for verify_block_checksums in [true, false] {
+        let dir = tempdir().unwrap();
+        let options = LsmStorageOptions {
+            verify_block_checksums,
+            ..LsmStorageOptions::default_for_week1_test()
+        };
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        for idx in 0..10 {
+            let key = format!("key_{:05}", idx);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.force_flush().unwrap();
+        corrupt_first_sst(&storage);
+
+        let get = storage.get(b"key_00000");
+        let scan = storage.scan(Bound::Unbounded, Bound::Unbounded);
+        if verify_block_checksums {
+            let err = get.unwrap_err();
+            assert!(is_checksum_error(&err), "{:#}", err);
+            assert!(format!("{:#}", err).contains("block 0 of SST"), "{:#}", err);
+            assert!(is_checksum_error(&scan.err().unwrap()));
+        } else {
+            assert_eq!(get.unwrap(), Some(Bytes::from_static(b"Value")));
+            assert!(scan.is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_read_block_for_copy() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(128);
+    builder.try_add(b"key", b"value").unwrap();
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let (block, encoded) = sst.read_block_for_copy(0).unwrap();
+    assert_eq!(encoded, sst.read_block_encoded(0).unwrap());
+    assert_eq!(
+        block.encode_compressed(CompressionType::None).to_vec(),
+        encoded
+    );
+}
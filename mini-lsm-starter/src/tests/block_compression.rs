@@ -0,0 +1,213 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::block::{Block, BlockBuilder, BlockIterator, ChecksumMode, CompressionType};
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::table::SsTableBuilder;
+
+const CODECS: [CompressionType; 3] = [
+    CompressionType::None,
+    CompressionType::Lz4,
+    CompressionType::Zstd(3),
+];
+
+fn value_of(idx: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}", idx).repeat(8))
+}
+
+#[test]
+fn test_block_compression() {
+    let mut builder = BlockBuilder::new(4096);
+    let mut idx = 0;
+    while builder.add(
+        KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+        &value_of(idx),
+    ) {
+        idx += 1;
+    }
+    let block = builder.build();
+    let mut sizes = Vec::new();
+    for codec in CODECS {
+        let encoded = block.encode_compressed(codec);
+        sizes.push(encoded.len());
+        let decoded =
+            Arc::new(Block::decode_compressed(encoded.clone(), ChecksumMode::Verify).unwrap());
+        let iter = BlockIterator::create_and_seek_to_key(
+            decoded,
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx / 2)),
+        );
+        assert_eq!(iter.value(), value_of(idx / 2));
+    }
+    assert_eq!(sizes[0], block.encode().len() + 1);
+    assert!(
+        sizes[1] < sizes[0] / 2 && sizes[2] < sizes[0] / 2,
+        "{:?}",
+        sizes
+    );
+
+    // Incompressible blocks are stored as they are
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut builder = BlockBuilder::new(4096);
+    let value = (0..1000).map(|_| rng.r#gen::<u8>()).collect::<Vec<_>>();
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"key"), &value));
+    let block = builder.build();
+    for codec in CODECS {
+        let encoded = block.encode_compressed(codec);
+        assert_eq!(encoded, block.encode_compressed(CompressionType::None));
+    }
+
+    // Corruptions are errors rather than panics
+    let mut encoded = block.encode_compressed(CompressionType::None).to_vec();
+    *encoded.last_mut().unwrap() = 9;
+    let err = Block::decode_compressed(encoded.into(), ChecksumMode::Verify)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown"), "{}", err);
+    let mut builder = BlockBuilder::new(4096);
+    for idx in 0..10 {
+        assert!(builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx)
+        ));
+    }
+    let mut encoded = builder
+        .build()
+        .encode_compressed(CompressionType::Lz4)
+        .to_vec();
+    assert_eq!(encoded.last(), Some(&CompressionType::Lz4.flag()));
+    encoded[10] ^= 0xff;
+    assert!(Block::decode_compressed(encoded.into(), ChecksumMode::Verify).is_err());
+}
+
+#[test]
+fn test_block_decode_without_copy() {
+    let mut builder = BlockBuilder::new(4096);
+    for idx in 0..10 {
+        assert!(builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx)
+        ));
+    }
+    let encoded = builder.build().encode_compressed(CompressionType::None);
+    let block = Block::decode_compressed(encoded.clone(), ChecksumMode::Verify).unwrap();
+    // The data and the offsets of an uncompressed block are views into the encoded buffer
+    let encoded_range = encoded.as_ptr_range();
+    assert_eq!(block.data.as_ptr(), encoded_range.start);
+    assert!(encoded_range.contains(&block.offsets.as_ptr()));
+    let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(block));
+    for idx in 0..10 {
+        assert_eq!(iter.key().key_ref(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx));
+        iter.next();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_block_builder_reset() {
+    let mut builder = BlockBuilder::new(4096);
+    let mut out = Vec::new();
+    for (start, codec) in [0, 100, 200].into_iter().zip(CODECS) {
+        let mut fresh = BlockBuilder::new(4096);
+        for idx in start..start + 20 {
+            assert!(builder.add(
+                KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+                &value_of(idx)
+            ));
+            assert!(fresh.add(
+                KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+                &value_of(idx)
+            ));
+        }
+        // A reset builder encodes the same block as a new one
+        let offset = out.len();
+        builder.encode_compressed_into(codec, &mut out);
+        assert_eq!(out[offset..], fresh.build().encode_compressed(codec));
+        builder.reset(4096);
+        assert!(builder.is_empty());
+    }
+}
+
+#[test]
+fn test_sst_compression() {
+    let dir = tempdir().unwrap();
+    let mut ssts = Vec::new();
+    for (idx, codec) in CODECS.into_iter().enumerate() {
+        let mut builder = SsTableBuilder::new(4096).with_compression(codec);
+        for idx in 0..1000 {
+            builder.add(
+                KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+                &value_of(idx),
+            );
+        }
+        let path = dir.path().join(format!("{}.sst", idx));
+        let sst = Arc::new(builder.build_for_test(&path).unwrap());
+        for idx in [0, 500, 999] {
+            assert_eq!(
+                sst.get_entry(&key_of(idx)).unwrap(),
+                Some(Some(value_of(idx)))
+            );
+        }
+        ssts.push((sst, std::fs::metadata(&path).unwrap().len()));
+    }
+    assert!(ssts[1].1 < ssts[0].1 && ssts[2].1 < ssts[0].1);
+
+    // Blocks are copied with their codec
+    let mut builder = SsTableBuilder::new(4096);
+    for block_idx in 0..ssts[1].0.num_of_blocks() {
+        let (block, encoded) = ssts[1].0.read_block_for_copy(block_idx).unwrap();
+        builder.add_encoded_block(block, &encoded);
+    }
+    let sst = builder.build_for_test(dir.path().join("copy.sst")).unwrap();
+    assert_eq!(
+        sst.get_entry(&key_of(123)).unwrap(),
+        Some(Some(value_of(123)))
+    );
+
+    let options = LsmStorageOptions {
+        block_compression: CompressionType::Zstd(100),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+    let options = LsmStorageOptions {
+        block_compression: CompressionType::Lz4,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(dir.path().join("storage"), options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 50..150 {
+        storage.put(&key_of(idx), &value_of(idx + 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for idx in 0..150 {
+        let expected = if idx < 50 {
+            value_of(idx)
+        } else {
+            value_of(idx + 1)
+        };
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(expected));
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key(i: usize) -> String {
+    format!("key_{:05}", i)
+}
+
+fn open(dir: &Path, options: LsmStorageOptions) -> Arc<MiniLsm> {
+    let storage = MiniLsm::open(dir, options).unwrap();
+    for i in 0..1000 {
+        storage.put(key(i).as_bytes(), &[b'x'; 100]).unwrap();
+        if i % 100 == 99 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.force_full_compaction().unwrap();
+    storage.put(key(500).as_bytes(), b"new").unwrap();
+    storage.force_flush().unwrap();
+    storage
+}
+
+#[test]
+fn test_pinned_blocks_charged_to_block_cache() {
+    let dir = tempdir().unwrap();
+    let capacity = 16 * 4096;
+    let options = LsmStorageOptions {
+        target_sst_size: 8192,
+        block_cache_capacity: Some(capacity),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = open(dir.path(), options);
+    let block_cache = storage.inner.block_cache.clone();
+    assert_eq!(block_cache.pinned_blocks(), 0);
+
+    // Each SST iterator pins its current block, and releases it once it moves past it
+    let mut iter = storage
+        .scan(Bound::Included(b"key_00100"), Bound::Unbounded)
+        .unwrap();
+    let num_ssts = storage.inner.state.read().levels[0].1.len() + 1;
+    let mut max_pinned = 0;
+    while iter.is_valid() {
+        let pinned = block_cache.pinned_blocks();
+        assert!(pinned as usize <= num_ssts, "{}", pinned);
+        max_pinned = max_pinned.max(pinned);
+        assert_eq!(block_cache.pinned_size() > 0, pinned > 0);
+        assert!(block_cache.usage() <= capacity, "{}", block_cache.usage());
+        iter.next().unwrap();
+    }
+    assert!(max_pinned >= 2);
+    assert_eq!(block_cache.pinned_blocks(), 0);
+    drop(iter);
+    assert_eq!(block_cache.pinned_blocks(), 0);
+    assert_eq!(block_cache.pinned_size(), 0);
+
+    let iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(block_cache.pinned_blocks() > 0);
+    drop(iter);
+    assert_eq!(block_cache.pinned_blocks(), 0);
+}
+
+#[test]
+fn test_sorted_level_read_one_sst_at_a_time() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        target_sst_size: 8192,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = open(dir.path(), options);
+    assert!(storage.inner.state.read().levels[0].1.len() > 2);
+    let block_cache = storage.inner.block_cache.clone();
+
+    // Without any limits, the level is still read by a single iterator next to the L0 SST
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert!(block_cache.pinned_blocks() <= 2);
+        let expected_value = match iter.key() == key(500).as_bytes() {
+            true => &b"new"[..],
+            false => &[b'x'; 100][..],
+        };
+        assert_eq!(iter.value(), expected_value);
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+}
+
+#[test]
+fn test_max_pinned_blocks_per_iterator() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        target_sst_size: 8192,
+        max_pinned_blocks_per_iterator: Some(2),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = open(dir.path(), options);
+    assert!(storage.inner.state.read().levels[0].1.len() > 2);
+    let block_cache = storage.inner.block_cache.clone();
+
+    // The scan reads the SSTs of the level one after another
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert!(block_cache.pinned_blocks() <= 2);
+        if iter.key() == key(500).as_bytes() {
+            assert_eq!(iter.value(), b"new");
+        }
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+    drop(iter);
+
+    // One block for each L0 SST and the level
+    storage.put(key(501).as_bytes(), b"new").unwrap();
+    storage.force_flush().unwrap();
+    let err = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("max_pinned_blocks_per_iterator"),
+        "{}",
+        err
+    );
+    // A narrow scan only reads a few SSTs
+    let iter = storage
+        .scan(Bound::Included(b"key_00300"), Bound::Included(b"key_00302"))
+        .unwrap();
+    assert_eq!(iter.key(), b"key_00300");
+}
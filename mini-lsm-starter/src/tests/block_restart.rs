@@ -0,0 +1,223 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::BufMut;
+use tempfile::tempdir;
+
+use crate::block::{Block, BlockBuilder, BlockIterator, EntryLayout};
+use crate::key::{KeySlice, KeyVec};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx * 2).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{}", idx).into_bytes()
+}
+
+fn build_block(restart_interval: usize) -> Block {
+    let mut builder = BlockBuilder::new(65536).with_restart_interval(restart_interval);
+    for idx in 0..100 {
+        let key = key_of(idx);
+        let added = match idx % 10 {
+            3 => builder.add_entry(KeySlice::for_testing_from_slice_no_ts(&key), b"", 0, true),
+            7 => builder.add_with_meta(
+                KeySlice::for_testing_from_slice_no_ts(&key),
+                &value_of(idx),
+                1,
+            ),
+            _ => builder.add(KeySlice::for_testing_from_slice_no_ts(&key), &value_of(idx)),
+        };
+        assert!(added);
+    }
+    builder.build()
+}
+
+fn check_entry(iter: &BlockIterator, idx: usize) {
+    assert!(iter.is_valid());
+    assert_eq!(iter.key().key_ref(), key_of(idx));
+    assert_eq!(iter.is_deleted(), idx % 10 == 3);
+    if idx % 10 != 3 {
+        assert_eq!(iter.value(), value_of(idx));
+    }
+    assert_eq!(iter.value_meta(), (idx % 10 == 7) as u8);
+}
+
+#[test]
+fn test_block_restart_points() {
+    let mut sizes = Vec::new();
+    for restart_interval in [1, 3, 16] {
+        let block = build_block(restart_interval);
+        assert_eq!(block.num_restarts(), 100usize.div_ceil(restart_interval));
+        assert!(block.has_deletes());
+        let encoded = block.encode();
+        sizes.push(encoded.len());
+        let block = Arc::new(Block::decode(&encoded));
+
+        let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+        for idx in 0..100 {
+            check_entry(&iter, idx);
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        for idx in 0..100 {
+            let iter = BlockIterator::create_and_seek_to_key(
+                block.clone(),
+                KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            );
+            check_entry(&iter, idx);
+            // Between two keys
+            let mut key = key_of(idx);
+            key.push(b'a');
+            let mut iter = BlockIterator::create_and_seek_to_key(
+                block.clone(),
+                KeySlice::for_testing_from_slice_no_ts(&key),
+            );
+            if idx == 99 {
+                assert!(!iter.is_valid());
+            } else {
+                check_entry(&iter, idx + 1);
+                iter.next();
+                if idx < 98 {
+                    check_entry(&iter, idx + 2);
+                }
+            }
+        }
+        let iter = BlockIterator::create_and_seek_to_key(
+            block,
+            KeySlice::for_testing_from_slice_no_ts(b"a"),
+        );
+        check_entry(&iter, 0);
+    }
+    // The keys share their prefix with the previous key rather than with the first key only
+    assert!(sizes[2] < sizes[1] && sizes[1] < sizes[0], "{:?}", sizes);
+}
+
+#[test]
+fn test_block_without_restart_points() {
+    // Blocks written before restart points list every entry, delta-encoded against the first key
+    let mut data = Vec::new();
+    let mut offsets = Vec::new();
+    let first_key = b"abc1";
+    for key in [&b"abc1"[..], b"abc2", b"abd", b"abd5"] {
+        let overlap = if offsets.is_empty() {
+            0
+        } else {
+            key.iter()
+                .zip(first_key)
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        offsets.put_u16(data.len() as u16);
+        data.put_u16(overlap as u16);
+        data.put_u16((key.len() - overlap) as u16);
+        data.put(&key[overlap..]);
+        data.put_u16(1);
+        data.put_u8(key[key.len() - 1]);
+    }
+    let block = Arc::new(Block {
+        data: data.into(),
+        offsets: offsets.into(),
+        layout: EntryLayout {
+            fixed_lengths: true,
+            timestamps: false,
+        },
+    });
+    assert!(!block.has_deletes());
+    let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        assert_eq!(
+            iter.value(),
+            &iter.key().key_ref()[iter.key().key_len() - 1..]
+        );
+        keys.push(iter.key().key_ref().to_vec());
+        iter.next();
+    }
+    assert_eq!(
+        keys,
+        vec![
+            b"abc1".to_vec(),
+            b"abc2".to_vec(),
+            b"abd".to_vec(),
+            b"abd5".to_vec()
+        ]
+    );
+    // Keys shorter than the prefix shared with the first key, and equal to a restart point
+    for (key, expected) in [
+        (&b"abd1"[..], &b"abd5"[..]),
+        (b"ab", b"abc1"),
+        (b"abd", b"abd"),
+    ] {
+        let key = KeyVec::for_testing_from_vec_no_ts(key.to_vec());
+        let iter = BlockIterator::create_and_seek_to_key(block.clone(), key.as_key_slice());
+        assert_eq!(iter.key().key_ref(), expected);
+    }
+}
+
+#[test]
+fn test_block_seek_large_block() {
+    for restart_interval in [1, 16] {
+        let mut builder = BlockBuilder::new(65535).with_restart_interval(restart_interval);
+        let mut num_entries = 0;
+        while builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(num_entries)),
+            b"v",
+        ) {
+            num_entries += 1;
+        }
+        assert!(num_entries > 2500, "{}", num_entries);
+        let block = Arc::new(builder.build());
+        let mut iter = BlockIterator::create_and_seek_to_first(block);
+        for idx in (0..num_entries).rev() {
+            iter.seek_to_key(KeySlice::for_testing_from_slice_no_ts(&key_of(idx)));
+            assert_eq!(iter.key().key_ref(), key_of(idx));
+            let mut key = key_of(idx);
+            key.pop();
+            iter.seek_to_key(KeySlice::for_testing_from_slice_no_ts(&key));
+            assert_eq!(iter.key().key_ref(), key_of(idx / 5 * 5));
+        }
+        iter.seek_to_key(KeySlice::for_testing_from_slice_no_ts(b"z"));
+        assert!(!iter.is_valid());
+    }
+}
+
+#[test]
+fn test_block_restart_interval_option() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_restart_interval: 0,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+    let options = LsmStorageOptions {
+        block_restart_interval: 4,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 0..100 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap().unwrap(), value_of(idx));
+        let mut key = key_of(idx);
+        key.push(b'a');
+        assert_eq!(storage.get(&key).unwrap(), None);
+    }
+}
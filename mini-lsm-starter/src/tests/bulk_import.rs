@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tempfile::tempdir;
+
+use crate::bulk_import::{BulkImport, ImportFormat, parse_hex};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn open(dir: &tempfile::TempDir) -> Arc<MiniLsm> {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.block_size = 1024;
+    options.target_sst_size = 1024;
+    MiniLsm::open(dir, options).unwrap()
+}
+
+fn count_ssts(dir: &tempfile::TempDir) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count()
+}
+
+#[test]
+fn test_bulk_import_csv() {
+    let input = tempdir().unwrap();
+    let files = (0..4)
+        .map(|file| {
+            let path = input.path().join(format!("{}.csv", file));
+            let mut csv = String::from("key,value\n");
+            for idx in file * 1000..(file + 1) * 1000 {
+                csv.push_str(&format!("key_{:05},{:04x}\n", idx, idx));
+            }
+            std::fs::write(&path, csv).unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    storage.put(b"a", b"before").unwrap();
+    let reports = Arc::new(AtomicUsize::new(0));
+    let last_rows = Arc::new(AtomicUsize::new(0));
+    let import = BulkImport::new(ImportFormat::Csv)
+        .with_value_parser(parse_hex)
+        .with_workers(2)
+        .with_progress({
+            let (reports, last_rows) = (reports.clone(), last_rows.clone());
+            move |progress| {
+                assert!(progress.files_done <= progress.files);
+                reports.fetch_add(1, Ordering::Relaxed);
+                last_rows.store(progress.rows as usize, Ordering::Relaxed);
+            }
+        });
+    let ids = storage.bulk_import(&files, &import).unwrap();
+    assert!(ids.len() > 4);
+    assert!(reports.load(Ordering::Relaxed) >= ids.len());
+    assert_eq!(last_rows.load(Ordering::Relaxed), 4000);
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), ids.len());
+
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"before");
+    for idx in [0u16, 999, 1000, 2345, 3999] {
+        let key = format!("key_{:05}", idx);
+        let value = storage.get(key.as_bytes()).unwrap().unwrap();
+        assert_eq!(&value[..], idx.to_be_bytes());
+    }
+    let mut iter = storage
+        .scan(Bound::Excluded(b"a"), Bound::Unbounded)
+        .unwrap();
+    let mut rows = 0;
+    while iter.is_valid() {
+        rows += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(rows, 4000);
+
+    // Importing over the keys imported above fails and leaves no files behind
+    let num_ssts = count_ssts(&dir);
+    assert!(storage.bulk_import(&files[1..2], &import).is_err());
+    assert_eq!(count_ssts(&dir), num_ssts);
+}
+
+#[test]
+fn test_bulk_import_unsorted() {
+    let input = tempdir().unwrap();
+    let write = |name: &str, csv: &str| -> PathBuf {
+        let path = input.path().join(name);
+        std::fs::write(&path, csv).unwrap();
+        path
+    };
+    let sorted_1 = write("sorted_1.csv", "key,value\nb,1\nc,2\n");
+    let sorted_2 = write("sorted_2.csv", "key,value\nd,3\ne,4\n");
+    let unsorted = write("unsorted.csv", "key,value\nf,5\nf,6\n");
+    let empty_key = write("empty_key.csv", "key,value\n,7\n");
+
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    let import = BulkImport::new(ImportFormat::Csv);
+    for files in [
+        vec![sorted_1.clone(), unsorted],
+        vec![sorted_2.clone(), sorted_1.clone()],
+        vec![empty_key],
+    ] {
+        assert!(storage.bulk_import(&files, &import).is_err());
+        assert_eq!(count_ssts(&dir), 0);
+        assert!(storage.inner.state.read().l0_sstables.is_empty());
+    }
+    assert_eq!(
+        storage
+            .bulk_import(&[sorted_1, sorted_2], &import)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(&storage.get(b"e").unwrap().unwrap()[..], b"4");
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn charged_metadata(storage: &MiniLsm) -> u64 {
+    let snapshot = storage.inner.state.read().clone();
+    snapshot
+        .sstables
+        .values()
+        .map(|table| table.metadata_size())
+        .sum()
+}
+
+#[test]
+fn test_metadata_charged_to_block_cache() {
+    let dir = tempdir().unwrap();
+    let capacity = 16 * 4096;
+    let options = LsmStorageOptions {
+        block_cache_capacity: Some(capacity),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..2000 {
+        storage
+            .put(format!("key_{:05}", i).as_bytes(), &[b'x'; 100])
+            .unwrap();
+        if i % 500 == 499 {
+            storage.force_flush().unwrap();
+        }
+    }
+    for i in 0..2000 {
+        assert!(
+            storage
+                .get(format!("key_{:05}", i).as_bytes())
+                .unwrap()
+                .is_some()
+        );
+    }
+    let block_cache = &storage.inner.block_cache;
+    assert!(block_cache.metadata_size() > 0);
+    assert_eq!(block_cache.metadata_size(), charged_metadata(&storage));
+    assert!(block_cache.usage() <= capacity, "{}", block_cache.usage());
+
+    // The metadata of the compacted SSTs is released
+    storage.force_full_compaction().unwrap();
+    assert_eq!(block_cache.metadata_size(), charged_metadata(&storage));
+    assert!(block_cache.usage() <= capacity, "{}", block_cache.usage());
+}
+
+#[test]
+fn test_strict_capacity_limit() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_cache_capacity: Some(64),
+        strict_capacity_limit: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    let err = storage.force_flush().unwrap_err();
+    assert!(err.to_string().contains("does not fit"), "{}", err);
+    assert_eq!(storage.inner.block_cache.metadata_size(), 0);
+    assert!(storage.inner.state.read().l0_sstables.is_empty());
+}
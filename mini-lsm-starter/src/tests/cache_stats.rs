@@ -0,0 +1,129 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::block::{Block, BlockBuilder};
+use crate::block_cache::{BlockCache, BlockCachePolicy, LevelCacheStats};
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_per_level_cache_stats() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        high_priority_cache_levels: vec![1],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.force_flush().unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(storage.get(b"1").unwrap(), Some(Bytes::from_static(b"233")));
+        assert_eq!(
+            storage.get(b"2").unwrap(),
+            Some(Bytes::from_static(b"2333"))
+        );
+    }
+    let stats = storage.block_cache_stats();
+    assert_eq!(stats[0], LevelCacheStats { hits: 2, misses: 1 });
+    assert_eq!(stats[1], LevelCacheStats { hits: 2, misses: 1 });
+    assert!((stats[1].hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+
+    let snapshot = storage.inner.state.read().clone();
+    let l1_sst = &snapshot.sstables[&snapshot.levels[0].1[0]];
+    assert_eq!(l1_sst.level(), 1);
+    assert!(l1_sst.read_block_from_cache(0).is_some());
+}
+
+fn block(value: &[u8]) -> Arc<Block> {
+    let mut builder = BlockBuilder::new(4096);
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"key"), value));
+    Arc::new(builder.build())
+}
+
+#[test]
+fn test_lru_block_cache() {
+    let block_size = {
+        let block = block(b"0");
+        (block.data.len() + block.offsets.len()) as u64
+    };
+    let cache =
+        BlockCache::with_byte_capacity(2 * block_size, Vec::new(), false, BlockCachePolicy::Lru);
+    let read = |block_idx: usize| {
+        cache
+            .try_get_with(0, (1, block_idx), || {
+                Ok(block(block_idx.to_string().as_bytes()))
+            })
+            .unwrap()
+    };
+    read(0);
+    read(1);
+    read(0);
+    // Evicts block 1, which was read the least recently
+    read(2);
+    assert!(cache.get(0, &(1, 0)).is_some());
+    assert!(cache.get(0, &(1, 1)).is_none());
+    assert!(cache.get(0, &(1, 2)).is_some());
+    assert_eq!(cache.usage(), 2 * block_size);
+    assert_eq!(
+        cache.level_stats()[0],
+        LevelCacheStats { hits: 3, misses: 4 }
+    );
+}
+
+#[test]
+fn test_lru_block_cache_scan() {
+    let dir = tempdir().unwrap();
+    let capacity = 8 * 4096;
+    let options = LsmStorageOptions {
+        block_cache_capacity: Some(capacity),
+        block_cache_policy: BlockCachePolicy::Lru,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..2000 {
+        storage
+            .put(format!("key_{:05}", i).as_bytes(), &[b'x'; 100])
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    let scan = || {
+        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+        let mut count = 0;
+        while iter.is_valid() {
+            count += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(count, 2000);
+    };
+    scan();
+    let block_cache = &storage.inner.block_cache;
+    assert!(block_cache.usage() <= capacity, "{}", block_cache.usage());
+    // The last blocks read by the scan are cached
+    let snapshot = storage.inner.state.read().clone();
+    let sst = &snapshot.sstables[&snapshot.l0_sstables[0]];
+    assert!(sst.num_of_blocks() > 8);
+    assert!(sst.read_block_from_cache(sst.num_of_blocks() - 1).is_some());
+    assert!(sst.read_block_from_cache(0).is_none());
+}
@@ -0,0 +1,178 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::cancel::{CancellationToken, Cancelled, is_cancelled_error};
+use crate::compact::CompactionOptions;
+use crate::compaction_filter::{CompactionDecision, CompactionFilter};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_cancel_scan() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..100 {
+        storage
+            .put(format!("key_{:03}", idx).as_bytes(), b"value")
+            .unwrap();
+    }
+    let token = CancellationToken::new();
+    let mut iter = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .with_cancellation(token.clone());
+    iter.next().unwrap();
+    assert_eq!(iter.key(), b"key_001");
+    token.cancel();
+    assert!(token.is_cancelled());
+    let err = iter.next().unwrap_err();
+    assert!(is_cancelled_error(&err), "{}", err);
+    assert!(!iter.is_valid());
+
+    // Deleted keys are skipped without reading past a cancellation either
+    for idx in 0..100 {
+        storage
+            .delete(format!("key_{:03}", idx).as_bytes())
+            .unwrap();
+    }
+    storage.put(b"key_999", b"value").unwrap();
+    let mut iter = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .with_cancellation(token);
+    assert!(is_cancelled_error(&iter.next().unwrap_err()));
+}
+
+#[test]
+fn test_scan_deadline() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"2").unwrap();
+    let token = CancellationToken::with_timeout(Duration::from_secs(3600));
+    let mut iter = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .with_cancellation(token);
+    iter.next().unwrap();
+    assert_eq!(iter.key(), b"b");
+
+    let token = CancellationToken::with_timeout(Duration::ZERO);
+    let mut iter = storage
+        .scan_rev(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .with_cancellation(token);
+    let err = iter.next().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<Cancelled>(),
+        Some(&Cancelled {
+            deadline_exceeded: true
+        })
+    );
+}
+
+/// Cancels the compactions of the storage from within the first one.
+#[derive(Debug, Default)]
+struct CancellingFilter {
+    storage: OnceLock<Weak<LsmStorageInner>>,
+}
+
+impl CompactionFilter for CancellingFilter {
+    fn filter(&self, _key: &[u8], _value: &[u8]) -> CompactionDecision {
+        if let Some(storage) = self.storage.get().and_then(Weak::upgrade) {
+            storage.cancel_compactions();
+        }
+        CompactionDecision::Keep
+    }
+}
+
+fn sst_files(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count()
+}
+
+#[test]
+fn test_cancel_compaction() {
+    let dir = tempdir().unwrap();
+    let filter = Arc::new(CancellingFilter::default());
+    let options = LsmStorageOptions {
+        target_sst_size: 256,
+        compaction_filter: Some(filter.clone()),
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+    };
+    let storage = Arc::new(LsmStorageInner::open(&dir, options).unwrap());
+    for round in 0..2 {
+        for idx in 0..100 {
+            let value = format!("value_{}", round);
+            storage
+                .put(format!("key_{:03}", idx).as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        storage.force_flush_all().unwrap();
+    }
+    let num_files = sst_files(dir.path());
+    let sst_ids = storage
+        .state
+        .read()
+        .sstables
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+
+    filter.storage.set(Arc::downgrade(&storage)).unwrap();
+    let err = storage.force_full_compaction().unwrap_err();
+    assert!(is_cancelled_error(&err), "{}", err);
+    // The outputs written before the cancellation are removed, and the inputs stay
+    assert_eq!(sst_files(dir.path()), num_files);
+    let mut state_ids = storage
+        .state
+        .read()
+        .sstables
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    state_ids.sort();
+    let mut expected_ids = sst_ids;
+    expected_ids.sort();
+    assert_eq!(state_ids, expected_ids);
+    assert_eq!(
+        storage.get(b"key_050").unwrap().as_deref(),
+        Some(&b"value_1"[..])
+    );
+
+    // The next compaction gets a fresh token, cancelled by the filter again
+    assert!(is_cancelled_error(
+        &storage.force_full_compaction().unwrap_err()
+    ));
+    let filter_free = LsmStorageOptions {
+        target_sst_size: 256,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+    };
+    drop(storage);
+    let storage = LsmStorageInner::open(&dir, filter_free).unwrap();
+    storage.force_full_compaction().unwrap();
+    assert!(storage.state.read().l0_sstables.is_empty());
+    assert_eq!(
+        storage.get(b"key_050").unwrap().as_deref(),
+        Some(&b"value_1"[..])
+    );
+}
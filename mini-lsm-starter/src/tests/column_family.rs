@@ -0,0 +1,276 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::column_family::{CF_KEY_PREFIX, ColumnFamilyIterator};
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord, WriteOptions};
+use crate::scan_chunks::decode_chunk;
+use crate::write_batch::WriteBatch;
+
+fn collect(mut iter: ColumnFamilyIterator) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+fn entry(key: &'static str, value: &'static str) -> (Bytes, Bytes) {
+    (Bytes::from(key), Bytes::from(value))
+}
+
+#[test]
+fn test_column_families() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        internal_keyspace: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let users = storage.create_cf("users").unwrap();
+    let orders = storage.create_cf("orders").unwrap();
+    assert!(storage.create_cf("users").is_err());
+    assert!(storage.create_cf("").is_err());
+    assert_eq!(storage.list_cfs(), vec!["orders", "users"]);
+
+    // The same key in each column family
+    storage.put(b"a", b"default").unwrap();
+    storage.put_cf(&users, b"a", b"user a").unwrap();
+    storage.put_cf(&users, b"b", b"user b").unwrap();
+    storage.put_cf(&orders, b"a", b"order a").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("default")));
+    assert_eq!(
+        storage.get_cf(&users, b"a").unwrap(),
+        Some(Bytes::from("user a"))
+    );
+    assert_eq!(
+        storage.get_cf(&orders, b"a").unwrap(),
+        Some(Bytes::from("order a"))
+    );
+    assert_eq!(storage.get_cf(&orders, b"b").unwrap(), None);
+
+    // A batch over several column families
+    let mut batch = WriteBatch::new();
+    batch
+        .put_cf(&users, b"c", b"user c")
+        .delete_cf(&users, b"a")
+        .put_cf(&orders, b"c", b"order c")
+        .put(b"c", b"default");
+    storage.write_batch(&batch).unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(
+        collect(
+            storage
+                .scan_cf(&users, Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+        ),
+        vec![entry("b", "user b"), entry("c", "user c")]
+    );
+    assert_eq!(
+        collect(
+            storage
+                .scan_cf(&orders, Bound::Excluded(b"a"), Bound::Included(b"c"))
+                .unwrap()
+        ),
+        vec![entry("c", "order c")]
+    );
+    // The default column family does not see the others
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, vec![Bytes::from("a"), Bytes::from("c")]);
+    let reserved = [CF_KEY_PREFIX, b"x"].concat();
+    assert!(storage.put(&reserved, b"x").is_err());
+    // A range deletion stops before the reserved keys
+    storage.delete_range(b"b", &reserved).unwrap();
+    assert_eq!(storage.get(b"c").unwrap(), None);
+    assert_eq!(
+        storage.get_cf(&users, b"c").unwrap(),
+        Some(Bytes::from("user c"))
+    );
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    assert_eq!(storage.list_cfs(), vec!["orders", "users"]);
+    let users = storage.cf("users").unwrap();
+    assert_eq!(
+        storage.get_cf(&users, b"c").unwrap(),
+        Some(Bytes::from("user c"))
+    );
+
+    // A dropped column family loses its keys and handles, and its name can be used again
+    storage.drop_cf("users").unwrap();
+    assert!(storage.drop_cf("users").is_err());
+    assert!(storage.get_cf(&users, b"c").is_err());
+    assert!(storage.cf("users").is_none());
+    let users = storage.create_cf("users").unwrap();
+    assert_eq!(storage.get_cf(&users, b"c").unwrap(), None);
+    let orders = storage.cf("orders").unwrap();
+    assert_eq!(
+        storage.get_cf(&orders, b"c").unwrap(),
+        Some(Bytes::from("order c"))
+    );
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let users = storage.cf("users").unwrap();
+    assert!(
+        collect(
+            storage
+                .scan_cf(&users, Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+        )
+        .is_empty()
+    );
+}
+
+#[test]
+fn test_internal_keyspace_opt_in() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let reserved = [CF_KEY_PREFIX, b"x"].concat();
+    // Without the reservation every key is a plain key
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    assert!(storage.create_cf("users").is_err());
+    storage.put(&reserved, b"plain").unwrap();
+    assert_eq!(storage.get(&reserved).unwrap(), Some(Bytes::from("plain")));
+    let mut iter = storage
+        .scan(Bound::Included(CF_KEY_PREFIX), Bound::Unbounded)
+        .unwrap();
+    assert!(iter.is_valid());
+    assert_eq!(iter.key(), &reserved[..]);
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    drop(iter);
+    drop(storage);
+
+    // The reservation cannot be turned on over the keys in use
+    let reserving = LsmStorageOptions {
+        internal_keyspace: true,
+        ..options.clone()
+    };
+    assert!(MiniLsm::open(&dir, reserving.clone()).is_err());
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.delete(&reserved).unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, reserving).unwrap();
+    storage.create_cf("users").unwrap();
+    drop(storage);
+
+    // Nor turned off once it was used
+    assert!(MiniLsm::open(&dir, options).is_err());
+}
+
+fn keys(mut iter: FusedIterator<LsmIterator>) -> Vec<Bytes> {
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    keys
+}
+
+#[test]
+fn test_reserved_keys_of_batches_and_scans() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        internal_keyspace: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let users = storage.create_cf("users").unwrap();
+    storage.put(b"a", b"default").unwrap();
+    storage.put_cf(&users, b"a", b"user a").unwrap();
+
+    // No batch can write the registry or the windows of the idempotent writes
+    let registry = [CF_KEY_PREFIX, &[0; 4], b"users"].concat();
+    let mut batch = WriteBatch::new();
+    batch.put(b"b", b"default").put(&registry, b"\0\0\0\x09");
+    assert!(storage.write_batch(&batch).is_err());
+    assert!(
+        storage
+            .write_batch(&[WriteBatchRecord::Del(&registry[..])])
+            .is_err()
+    );
+    assert!(
+        storage
+            .write_batch_with_options(&batch, &WriteOptions::default())
+            .is_err()
+    );
+    assert!(storage.write_conditional(&batch).is_err());
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(
+        storage.get_cf(&users, b"a").unwrap(),
+        Some(Bytes::from("user a"))
+    );
+
+    // A batch built with a handle of a dropped column family fails when it is written
+    let mut batch = WriteBatch::new();
+    batch.put_cf(&users, b"b", b"user b");
+    storage.drop_cf("users").unwrap();
+    assert!(storage.write_batch(&batch).is_err());
+    let users = storage.create_cf("users").unwrap();
+    assert!(storage.write_batch(&batch).is_err());
+    let mut batch = WriteBatch::new();
+    batch.put_cf(&users, b"b", b"user b");
+    storage.write_batch(&batch).unwrap();
+    storage.force_flush().unwrap();
+
+    // The scans without bounds stop before the reserved keys
+    assert_eq!(
+        keys(storage.scan_prefix(b"").unwrap()),
+        vec![Bytes::from("a")]
+    );
+    assert!(keys(storage.scan_prefix(b"\xff").unwrap()).is_empty());
+    assert!(keys(storage.scan_prefix(CF_KEY_PREFIX).unwrap()).is_empty());
+    let chunks = storage
+        .scan_chunks(Bound::Unbounded, Bound::Unbounded, 4096)
+        .unwrap()
+        .map(|chunk| decode_chunk(&chunk.unwrap()).unwrap())
+        .collect::<Vec<_>>()
+        .concat();
+    assert_eq!(chunks, vec![entry("a", "default")]);
+    let samples = storage
+        .sample_keys(16, Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert!(samples.iter().all(|key| key.as_ref() < CF_KEY_PREFIX));
+    let buckets = storage.key_distribution(4).unwrap();
+    assert!(
+        buckets
+            .iter()
+            .all(|bucket| bucket.lower.as_ref() < CF_KEY_PREFIX)
+    );
+    assert_eq!(
+        buckets.last().unwrap().upper,
+        Some(Bytes::from_static(CF_KEY_PREFIX))
+    );
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::table::SsTableIterator;
+
+fn leveled_options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        block_size: 256,
+        target_sst_size: 1024,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 100,
+                max_levels: 3,
+                base_level_size_mb: 1,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_compact_range() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, leveled_options()).unwrap();
+    for i in 0..300 {
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush_all().unwrap();
+    for i in 100..200 {
+        storage.delete(format!("key_{:03}", i).as_bytes()).unwrap();
+    }
+    // The tombstones are still in the memtable, compact_range flushes them first
+    storage.compact_range(b"key_100", b"key_199").unwrap();
+
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.memtable.is_empty());
+    assert!(snapshot.l0_sstables.is_empty());
+    let (bottom_level, upper_levels) = snapshot.levels.split_last().unwrap();
+    for (_, sst_ids) in upper_levels {
+        assert!(
+            sst_ids
+                .iter()
+                .all(|id| !snapshot.sstables[id].overlaps_range(b"key_100", Some(b"key_199")))
+        );
+    }
+    // Neither the deleted values nor their tombstones are left
+    for id in &bottom_level.1 {
+        let mut iter =
+            SsTableIterator::create_and_seek_to_first(snapshot.sstables[id].clone()).unwrap();
+        while iter.is_valid() {
+            let key = iter.key().key_ref();
+            assert!(!(&b"key_100"[..]..=&b"key_199"[..]).contains(&key));
+            iter.next().unwrap();
+        }
+    }
+    assert!(storage.get(b"key_150").unwrap().is_none());
+    assert!(storage.get(b"key_050").unwrap().is_some());
+    assert!(storage.get(b"key_250").unwrap().is_some());
+
+    // Compacting a range already at the bottom rewrites its SSTs in place
+    let bottom_ssts = bottom_level.1.clone();
+    storage.compact_range(b"key_000", b"key_010").unwrap();
+    let snapshot = storage.state.read().clone();
+    assert_eq!(snapshot.levels.len(), 3);
+    assert_ne!(snapshot.levels[2].1, bottom_ssts);
+    assert!(storage.get(b"key_005").unwrap().is_some());
+}
+
+#[test]
+fn test_compact_range_errors() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, leveled_options()).unwrap();
+    assert!(storage.compact_range(b"b", b"a").is_err());
+    drop(storage);
+
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(
+        TieredCompactionOptions {
+            num_tiers: 3,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        },
+    ));
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    assert!(storage.compact_range(b"a", b"b").is_err());
+}
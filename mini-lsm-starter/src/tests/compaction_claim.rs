@@ -0,0 +1,61 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionClaim, CompactionOptions, SimpleLeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+#[test]
+fn test_compaction_claim() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 2,
+        },
+    ));
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    for i in 0..3 {
+        storage
+            .put(format!("key_{}", i).as_bytes(), b"value")
+            .unwrap();
+        storage.force_flush_all().unwrap();
+    }
+    let snapshot = storage.state.read().clone();
+    let l0_sstables = snapshot.l0_sstables.clone();
+
+    // Another task holds one of the inputs, so the task of the controller is not run
+    let claim = CompactionClaim::try_claim(&snapshot, &l0_sstables[..1]).unwrap();
+    assert!(snapshot.sstables[&l0_sstables[0]].is_being_compacted());
+    assert!(!storage.trigger_compaction().unwrap());
+    assert_eq!(storage.state.read().l0_sstables, l0_sstables);
+
+    // Claims that conflict do not keep any of their SSTs
+    assert!(CompactionClaim::try_claim(&snapshot, &[l0_sstables[2], l0_sstables[0]]).is_none());
+    assert!(!snapshot.sstables[&l0_sstables[2]].is_being_compacted());
+
+    drop(claim);
+    assert!(!snapshot.sstables[&l0_sstables[0]].is_being_compacted());
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(storage.state.read().l0_sstables.is_empty());
+    // The claim of the task was released along with it
+    assert!(
+        snapshot
+            .sstables
+            .values()
+            .all(|sst| !sst.is_being_compacted())
+    );
+}
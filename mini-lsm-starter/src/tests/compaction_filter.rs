@@ -0,0 +1,116 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, LeveledCompactionOptions};
+use crate::compaction_filter::{CompactionDecision, CompactionFilter};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+/// Purges the expired sessions and marks the live ones.
+#[derive(Debug)]
+struct SessionFilter;
+
+impl CompactionFilter for SessionFilter {
+    fn filter(&self, key: &[u8], value: &[u8]) -> CompactionDecision {
+        if !key.starts_with(b"session_") {
+            return CompactionDecision::Keep;
+        }
+        match value {
+            b"expired" => CompactionDecision::Remove,
+            b"live" => CompactionDecision::ChangeValue(Bytes::from_static(b"LIVE")),
+            _ => CompactionDecision::Keep,
+        }
+    }
+}
+
+fn options(compaction_options: CompactionOptions) -> LsmStorageOptions {
+    LsmStorageOptions {
+        compaction_filter: Some(Arc::new(SessionFilter)),
+        ..LsmStorageOptions::default_for_week2_test(compaction_options)
+    }
+}
+
+#[test]
+fn test_compaction_filter() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(CompactionOptions::NoCompaction)).unwrap();
+    storage.put(b"session_1", b"expired").unwrap();
+    storage.put(b"session_2", b"live").unwrap();
+    storage.put(b"session_3", b"idle").unwrap();
+    storage.put(b"user_1", b"expired").unwrap();
+    storage.force_flush_all().unwrap();
+    // Flushes are not filtered
+    assert_eq!(
+        storage.get(b"session_1").unwrap().as_deref(),
+        Some(&b"expired"[..])
+    );
+
+    storage.force_full_compaction().unwrap();
+    assert!(storage.get(b"session_1").unwrap().is_none());
+    assert_eq!(
+        storage.get(b"session_2").unwrap().as_deref(),
+        Some(&b"LIVE"[..])
+    );
+    assert_eq!(
+        storage.get(b"session_3").unwrap().as_deref(),
+        Some(&b"idle"[..])
+    );
+    assert_eq!(
+        storage.get(b"user_1").unwrap().as_deref(),
+        Some(&b"expired"[..])
+    );
+    // The removed entry is dropped from the bottom level
+    let snapshot = storage.state.read().clone();
+    let num_entries = snapshot.levels[0]
+        .1
+        .iter()
+        .map(|id| snapshot.sstables[id].properties().num_entries)
+        .sum::<u64>();
+    assert_eq!(num_entries, 3);
+}
+
+#[test]
+fn test_compaction_filter_above_bottom_level() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(
+        &dir,
+        options(CompactionOptions::Leveled(LeveledCompactionOptions {
+            level_size_multiplier: 2,
+            level0_file_num_compaction_trigger: 100,
+            max_levels: 2,
+            base_level_size_mb: 1,
+        })),
+    )
+    .unwrap();
+    storage.put(b"session_1", b"idle").unwrap();
+    storage.compact_range(b"session_1", b"session_1").unwrap();
+    assert_eq!(storage.state.read().levels[1].1.len(), 1);
+
+    // Removing the newer version in L1 leaves a tombstone, so that the older version in the
+    // bottom level does not come back
+    storage.put(b"session_1", b"expired").unwrap();
+    storage.compact_range(b"session_1", b"session_1").unwrap();
+    assert!(storage.get(b"session_1").unwrap().is_none());
+    let snapshot = storage.state.read().clone();
+    assert!(
+        snapshot
+            .levels
+            .iter()
+            .all(|(_, sst_ids)| sst_ids.is_empty())
+    );
+}
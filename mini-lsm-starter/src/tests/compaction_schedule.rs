@@ -0,0 +1,116 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::compact::{
+    CompactionOptions, CompactionWindow, SimpleLeveledCompactionOptions, time_of_day_utc,
+};
+use crate::lsm_storage::{LsmEvent, LsmStorageInner, LsmStorageOptions};
+
+fn hours(hours: u64) -> Duration {
+    Duration::from_secs(hours * 60 * 60)
+}
+
+#[test]
+fn test_compaction_window() {
+    let window = CompactionWindow::new(hours(1), hours(5));
+    assert!(!window.contains(hours(0)));
+    assert!(window.contains(hours(1)));
+    assert!(window.contains(hours(4)));
+    assert!(!window.contains(hours(5)));
+    // Wraps around midnight
+    let window = CompactionWindow::new(hours(22), hours(6));
+    assert!(window.contains(hours(23)));
+    assert!(window.contains(hours(0)));
+    assert!(!window.contains(hours(6)));
+    assert!(!window.contains(hours(12)));
+
+    let options = LsmStorageOptions {
+        compaction_windows: vec![CompactionWindow::new(hours(22), hours(24))],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+}
+
+#[test]
+fn test_heavy_compaction_deferred() {
+    let dir = tempdir().unwrap();
+    // A window that does not contain the current time
+    let now = time_of_day_utc().as_secs();
+    let window = CompactionWindow::new(
+        Duration::from_secs((now + hours(6).as_secs()) % hours(24).as_secs()),
+        Duration::from_secs((now + hours(12).as_secs()) % hours(24).as_secs()),
+    );
+    let options = LsmStorageOptions {
+        compaction_windows: vec![window],
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+            SimpleLeveledCompactionOptions {
+                size_ratio_percent: 200,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 2,
+            },
+        ))
+    };
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    storage.add_event_listener(Box::new(move |event| recorded.lock().push(event.clone())));
+
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush_all().unwrap();
+    let sst_id = {
+        let _state_lock = storage.state_lock.lock();
+        let mut state = storage.state.write();
+        let mut snapshot = state.as_ref().clone();
+        let sst_id = snapshot.l0_sstables.pop().unwrap();
+        snapshot.levels[0].1.push(sst_id);
+        *state = snapshot.into();
+        sst_id
+    };
+
+    // L1 to L2 is deferred and the trigger kept for the next tick
+    *storage.seek_compaction_sst.lock() = Some(sst_id);
+    assert!(!storage.trigger_seek_compaction().unwrap());
+    assert_eq!(*storage.seek_compaction_sst.lock(), Some(sst_id));
+    assert_eq!(storage.state.read().levels[0].1, vec![sst_id]);
+    assert_eq!(
+        events.lock().as_slice(),
+        &[LsmEvent::HeavyCompactionDeferred {
+            input_sst_ids: vec![sst_id]
+        }]
+    );
+
+    // A busy machine does not let it run either
+    storage
+        .compaction_scheduler
+        .set_utilization_probe(Some((Box::new(|| 0.9), 0.5)));
+    assert!(!storage.trigger_seek_compaction().unwrap());
+    storage
+        .compaction_scheduler
+        .set_utilization_probe(Some((Box::new(|| 0.1), 0.5)));
+    assert!(storage.trigger_seek_compaction().unwrap());
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.levels[0].1.is_empty());
+    assert_eq!(snapshot.levels[1].1.len(), 1);
+    assert_eq!(events.lock().len(), 2);
+    assert_eq!(
+        storage.get(b"key").unwrap(),
+        Some(bytes::Bytes::from_static(b"value"))
+    );
+}
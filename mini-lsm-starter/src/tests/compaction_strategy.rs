@@ -0,0 +1,117 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::options_file::StoredOptions;
+
+fn leveled(level_size_multiplier: usize) -> CompactionOptions {
+    CompactionOptions::Leveled(LeveledCompactionOptions {
+        level_size_multiplier,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 4,
+        base_level_size_mb: 1,
+    })
+}
+
+fn put_and_flush(storage: &LsmStorageInner, round: usize) {
+    for i in 0..100 {
+        let value = format!("value_{}_{}", i, round);
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), value.as_bytes())
+            .unwrap();
+    }
+    storage.force_flush_all().unwrap();
+}
+
+fn check_values(storage: &LsmStorageInner, round: usize) {
+    for i in 0..100 {
+        let value = storage.get(format!("key_{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.unwrap(), format!("value_{}_{}", i, round).as_bytes());
+    }
+}
+
+#[test]
+fn test_change_compaction_strategy() {
+    let dir = tempdir().unwrap();
+    // Without the compaction thread, so that only the switches reshape the levels
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for round in 0..3 {
+        put_and_flush(&storage, round);
+    }
+    // Nothing is compacted in the background without a strategy
+    assert!(storage.explain_compaction().is_none());
+
+    // The L0 SSTs become the only tier
+    let tiered = CompactionOptions::Tiered(TieredCompactionOptions {
+        num_tiers: 3,
+        max_size_amplification_percent: 200,
+        size_ratio: 1,
+        min_merge_width: 2,
+        max_merge_width: None,
+    });
+    storage.change_compaction_strategy(tiered).unwrap();
+    {
+        let snapshot = storage.state.read();
+        assert!(snapshot.l0_sstables.is_empty());
+        assert_eq!(snapshot.levels.len(), 1);
+        assert_eq!(snapshot.levels[0].0, snapshot.levels[0].1[0]);
+    }
+    check_values(&storage, 2);
+    put_and_flush(&storage, 3);
+
+    // Tiered to leveled compacts the tiers and L0 into the bottom level
+    storage.change_compaction_strategy(leveled(2)).unwrap();
+    {
+        let snapshot = storage.state.read();
+        assert!(snapshot.l0_sstables.is_empty());
+        assert_eq!(snapshot.levels.len(), 4);
+        assert!(snapshot.levels[..3].iter().all(|(_, ssts)| ssts.is_empty()));
+        assert_eq!(snapshot.levels[3].1.len(), snapshot.sstables.len());
+        assert_eq!(snapshot.sstables[&snapshot.levels[3].1[0]].level(), 4);
+    }
+    check_values(&storage, 3);
+    let stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.compaction_options.style(), "leveled");
+    let manifest = std::fs::read_to_string(dir.path().join("MANIFEST")).unwrap();
+    assert!(manifest.contains(r#""CompactionStrategy":{"Leveled""#));
+
+    // Same layout, only the controller changes
+    put_and_flush(&storage, 4);
+    let levels = storage.state.read().levels.clone();
+    storage.change_compaction_strategy(leveled(4)).unwrap();
+    assert_eq!(storage.state.read().levels, levels);
+    assert_eq!(storage.state.read().l0_sstables.len(), 1);
+
+    // Invalid strategies are rejected without changing anything
+    assert!(storage.change_compaction_strategy(leveled(1)).is_err());
+    assert!(matches!(
+        storage.compaction_controller().options(),
+        CompactionOptions::Leveled(LeveledCompactionOptions {
+            level_size_multiplier: 4,
+            ..
+        })
+    ));
+
+    storage
+        .change_compaction_strategy(CompactionOptions::NoCompaction)
+        .unwrap();
+    let snapshot = storage.state.read().clone();
+    assert_eq!(snapshot.levels.len(), 1);
+    assert_eq!(snapshot.sstables.len(), snapshot.levels[0].1.len());
+    check_values(&storage, 4);
+    storage.force_full_compaction().unwrap();
+}
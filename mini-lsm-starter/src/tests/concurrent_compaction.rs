@@ -0,0 +1,99 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, EntryCountCompactionOptions, LeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+fn options(max_concurrent_compactions: usize) -> LsmStorageOptions {
+    LsmStorageOptions {
+        block_size: 256,
+        target_sst_size: 1024,
+        entry_count_compaction: Some(EntryCountCompactionOptions {
+            base_level_entries: 200,
+            level0_entry_compaction_trigger: None,
+        }),
+        max_concurrent_compactions,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                base_level_size_mb: 1,
+            },
+        ))
+    }
+}
+
+/// Write 300 keys spread over the whole key space and flush them into an L0 SST.
+fn put_and_flush(storage: &LsmStorageInner, round: usize, expected: &mut BTreeMap<String, String>) {
+    for i in 0..300 {
+        let key = format!("key_{:03}", i * 7 % 1000 + round);
+        let value = format!("value_{}", round);
+        storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+        expected.insert(key, value);
+    }
+    storage.force_flush_all().unwrap();
+}
+
+#[test]
+fn test_concurrent_compactions() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(4)).unwrap();
+    let mut expected = BTreeMap::new();
+    let mut max_tasks = 0;
+    for round in 0..12 {
+        put_and_flush(&storage, round, &mut expected);
+        loop {
+            let snapshot = storage.state.read().clone();
+            let tasks = storage
+                .compaction_controller()
+                .generate_compaction_tasks(&snapshot, 4);
+            max_tasks = max_tasks.max(tasks.len());
+            if !storage.trigger_compaction().unwrap() {
+                break;
+            }
+        }
+    }
+    assert!(max_tasks > 1);
+
+    // The levels stay sorted runs whatever order the tasks finished in
+    let snapshot = storage.state.read().clone();
+    for (_, sst_ids) in &snapshot.levels {
+        for pair in sst_ids.windows(2) {
+            let (left, right) = (&snapshot.sstables[&pair[0]], &snapshot.sstables[&pair[1]]);
+            assert!(left.last_key() < right.first_key());
+        }
+    }
+    for (key, value) in &expected {
+        assert_eq!(
+            storage.get(key.as_bytes()).unwrap().as_deref(),
+            Some(value.as_bytes())
+        );
+    }
+    drop(storage);
+
+    // The manifest replays the tasks in the order they were installed
+    let storage = LsmStorageInner::open(&dir, options(4)).unwrap();
+    assert_eq!(storage.state.read().levels, snapshot.levels);
+}
+
+#[test]
+fn test_max_concurrent_compactions_validation() {
+    assert!(options(0).validate().is_err());
+    assert!(options(1).validate().is_ok());
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord};
+use crate::write_batch::WriteBatch;
+
+#[test]
+fn test_conditional_write_batch() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"lock"[..], &b"owner1"[..]),
+            WriteBatchRecord::Put(&b"tmp"[..], &b"1"[..]),
+            WriteBatchRecord::Del(&b"tmp"[..]),
+        ])
+        .unwrap();
+    assert_eq!(storage.get(b"tmp").unwrap(), None);
+
+    let mut batch = WriteBatch::new();
+    batch
+        .put_if_absent(b"lock", b"owner2")
+        .delete_if_value_matches(b"lock", b"owner2")
+        .put(b"other", b"1");
+    assert_eq!(storage.write_conditional(&batch).unwrap(), vec![0, 1]);
+    assert_eq!(storage.get(b"other").unwrap(), None);
+
+    let mut batch = WriteBatch::new();
+    batch
+        .delete_if_value_matches(b"lock", b"owner1")
+        .put(b"other", b"1");
+    assert!(storage.write_conditional(&batch).unwrap().is_empty());
+    assert_eq!(storage.get(b"lock").unwrap(), None);
+    assert_eq!(
+        storage.get(b"other").unwrap(),
+        Some(Bytes::from_static(b"1"))
+    );
+}
+
+#[test]
+fn test_concurrent_put_if_absent() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let threads = (0..8)
+        .map(|idx| {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                let mut batch = WriteBatch::new();
+                batch.put_if_absent(b"lock", format!("owner{}", idx).as_bytes());
+                storage.write_conditional(&batch).unwrap().is_empty()
+            })
+        })
+        .collect::<Vec<_>>();
+    let acquired = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .filter(|acquired| *acquired)
+        .count();
+    assert_eq!(acquired, 1);
+}
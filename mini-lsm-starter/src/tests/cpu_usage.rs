@@ -0,0 +1,46 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_background_cpu_usage() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.metrics().background_cpu, Default::default());
+    for round in 0..2 {
+        for i in 0..1000 {
+            storage
+                .put(
+                    format!("key_{:05}", i).as_bytes(),
+                    format!("{}", round).as_bytes(),
+                )
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    let stats = storage.metrics().background_cpu;
+    assert_eq!(stats.flushes, 2);
+    assert!(stats.flush_cpu_time > std::time::Duration::ZERO);
+    assert_eq!(stats.compactions, 0);
+
+    storage.force_full_compaction().unwrap();
+    let compacted = storage.metrics().background_cpu;
+    assert_eq!(compacted.flushes, 2);
+    assert_eq!(compacted.flush_cpu_time, stats.flush_cpu_time);
+    assert_eq!(compacted.compactions, 1);
+    assert!(compacted.compaction_cpu_time > std::time::Duration::ZERO);
+}
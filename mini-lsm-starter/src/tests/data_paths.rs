@@ -0,0 +1,69 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{DataPath, LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_data_paths() {
+    let dir = tempdir().unwrap();
+    let fast = dir.path().join("fast");
+    let slow = dir.path().join("slow");
+    let options = LsmStorageOptions {
+        data_paths: vec![
+            DataPath {
+                path: fast.clone(),
+                target_size: 64 << 10,
+            },
+            DataPath {
+                path: slow.clone(),
+                target_size: u64::MAX,
+            },
+        ],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(dir.path().join("db"), options).unwrap();
+    let key = |i: usize| format!("key_{:05}", i);
+    let value = [b'x'; 100];
+    let count_ssts = |path: &std::path::Path| std::fs::read_dir(path).unwrap().count();
+
+    // A small compaction stays on the fast disk
+    for i in 0..100 {
+        storage.put(key(i).as_bytes(), &value).unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(count_ssts(&fast), 1);
+    storage.force_full_compaction().unwrap();
+    let sst_id = storage.inner.state.read().levels[0].1[0];
+    assert_eq!(storage.inner.data_path_of_sst(sst_id), 0);
+    assert_eq!((count_ssts(&fast), count_ssts(&slow)), (1, 0));
+
+    // Flushes always go to the fast disk, the large compaction output goes to the slow one
+    for i in 0..1000 {
+        storage.put(key(i).as_bytes(), &value).unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(count_ssts(&fast), 2);
+    storage.force_full_compaction().unwrap();
+    let sst_id = storage.inner.state.read().levels[0].1[0];
+    assert_eq!(storage.inner.data_path_of_sst(sst_id), 1);
+    assert!(storage.inner.path_of_sst(sst_id).starts_with(&slow));
+    assert_eq!((count_ssts(&fast), count_ssts(&slow)), (0, 1));
+    assert_eq!(
+        storage.get(b"key_00999").unwrap(),
+        Some(Bytes::copy_from_slice(&value))
+    );
+}
@@ -0,0 +1,185 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::{check_lsm_iter_result_by_key, key_of};
+use crate::key::{KeySlice, TS_DEFAULT};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm};
+use crate::range_tombstone::RangeTombstones;
+use crate::table::{SsTable, SsTableBuilder};
+
+fn value_of(idx: usize) -> Bytes {
+    Bytes::from(format!("value_{:03}", idx))
+}
+
+/// The entries left after putting the keys `0..10`, deleting `3..7` and putting `5` again.
+fn expected_entries() -> Vec<(Bytes, Bytes)> {
+    (0..10)
+        .filter(|idx| !(3..7).contains(idx) || *idx == 5)
+        .map(|idx| (key_of(idx), value_of(idx)))
+        .collect()
+}
+
+fn check_entries(storage: &LsmStorageInner) {
+    for idx in 0..10 {
+        let expected = expected_entries()
+            .into_iter()
+            .find(|(key, _)| key == &key_of(idx))
+            .map(|(_, value)| value);
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), expected, "key {}", idx);
+    }
+    check_lsm_iter_result_by_key(
+        &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        expected_entries(),
+    );
+    check_lsm_iter_result_by_key(
+        &mut storage
+            .scan(Bound::Included(&key_of(4)), Bound::Excluded(&key_of(8)))
+            .unwrap(),
+        vec![(key_of(5), value_of(5)), (key_of(7), value_of(7))],
+    );
+}
+
+fn write_entries(storage: &LsmStorageInner, flush_before_delete: bool) {
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    if flush_before_delete {
+        storage.force_flush_all().unwrap();
+    }
+    storage.delete_range(&key_of(3), &key_of(7)).unwrap();
+    storage.put(&key_of(5), &value_of(5)).unwrap();
+}
+
+#[test]
+fn test_delete_range_memtable() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    write_entries(&storage, false);
+    check_entries(&storage);
+    storage.force_flush_all().unwrap();
+    check_entries(&storage);
+    assert!(storage.delete_range(&key_of(7), &key_of(7)).is_err());
+}
+
+#[test]
+fn test_delete_range_older_sst() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    write_entries(&storage, true);
+    check_entries(&storage);
+    storage.force_flush_all().unwrap();
+    check_entries(&storage);
+    // Moved to L1, where the SST with the tombstone is looked up by key
+    storage.force_full_compaction().unwrap();
+    check_entries(&storage);
+}
+
+#[test]
+fn test_delete_range_recovery() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    write_entries(&storage.inner, true);
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check_entries(&storage.inner);
+}
+
+#[test]
+fn test_delete_range_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush_all().unwrap();
+    // An SST with a range tombstone and no other entries
+    storage.delete_range(&key_of(2), &key_of(20)).unwrap();
+    storage.force_flush_all().unwrap();
+    assert_eq!(storage.get(&key_of(1)).unwrap(), Some(value_of(1)));
+    assert_eq!(storage.get(&key_of(2)).unwrap(), None);
+
+    // The bottom level drops the deleted keys and the tombstone
+    storage.force_full_compaction().unwrap();
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.l0_sstables.is_empty());
+    let (_, level) = snapshot.levels.last().unwrap();
+    assert_eq!(level.len(), 1);
+    let sst = &snapshot.sstables[&level[0]];
+    assert!(sst.range_tombstones().is_empty());
+    assert_eq!(sst.properties().num_entries, 2);
+    check_lsm_iter_result_by_key(
+        &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![(key_of(0), value_of(0)), (key_of(1), value_of(1))],
+    );
+}
+
+#[test]
+fn test_range_tombstones_split() {
+    let mut tombstones = RangeTombstones::new();
+    tombstones.insert(b"c", b"e", TS_DEFAULT);
+    tombstones.insert(b"a", b"b", TS_DEFAULT);
+    tombstones.insert(b"d", b"g", TS_DEFAULT);
+    tombstones.insert(b"b", b"c", TS_DEFAULT);
+    assert_eq!(tombstones.len(), 1);
+    assert!(tombstones.covers(b"a", TS_DEFAULT));
+    assert!(tombstones.covers(b"f", TS_DEFAULT));
+    assert!(!tombstones.covers(b"g", TS_DEFAULT));
+
+    tombstones.insert(b"x", b"z", TS_DEFAULT);
+    let before = tombstones.split_before(b"c");
+    assert_eq!(
+        before.iter().collect::<Vec<_>>(),
+        vec![(&b"a"[..], &b"c"[..], TS_DEFAULT)]
+    );
+    assert_eq!(
+        tombstones.iter().collect::<Vec<_>>(),
+        vec![
+            (&b"c"[..], &b"g"[..], TS_DEFAULT),
+            (&b"x"[..], &b"z"[..], TS_DEFAULT)
+        ]
+    );
+}
+
+#[test]
+fn test_sst_range_tombstones() {
+    let dir = tempdir().unwrap();
+    let mut tombstones = RangeTombstones::new();
+    tombstones.insert(b"a", b"c", TS_DEFAULT);
+    tombstones.insert(b"x", b"z", TS_DEFAULT);
+    let mut builder = SsTableBuilder::new(128);
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"m"), b"1");
+    builder.add_range_tombstones(&tombstones);
+    let path = dir.path().join("1.sst");
+    let sst = builder.build(1, None, &path).unwrap();
+    assert_eq!(sst.first_key().key_ref(), b"a");
+    assert_eq!(sst.last_key().key_ref(), b"z");
+    assert!(sst.is_range_deleted(b"b", TS_DEFAULT));
+    assert!(!sst.is_range_deleted(b"m", TS_DEFAULT));
+    drop(sst);
+
+    let sst = SsTable::open_path(&path).unwrap();
+    assert_eq!(sst.range_tombstones(), &tombstones);
+    assert_eq!(sst.first_key().key_ref(), b"a");
+    assert_eq!(sst.last_key().key_ref(), b"z");
+}
@@ -0,0 +1,95 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::compact::DeletionCompactionOptions;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::table::SsTableIterator;
+
+#[test]
+fn test_deletion_triggered_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        deletion_compaction: Some(DeletionCompactionOptions {
+            min_tombstones: 10,
+            tombstone_ratio_percent: 50,
+        }),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    for i in 0..90 {
+        storage.delete(format!("key_{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush_all().unwrap();
+    // Move the SST with the tombstones to L1
+    {
+        let _state_lock = storage.state_lock.lock();
+        let mut state = storage.state.write();
+        let mut snapshot = state.as_ref().clone();
+        let l0_sstables = std::mem::take(&mut snapshot.l0_sstables);
+        snapshot.levels[0].1 = l0_sstables;
+        *state = snapshot.into();
+    }
+
+    // Nothing to compact before a scan reports the tombstones
+    assert!(!storage.trigger_deletion_compaction().unwrap());
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(cnt, 10);
+    drop(iter);
+
+    assert!(storage.trigger_deletion_compaction().unwrap());
+    assert!(!storage.trigger_deletion_compaction().unwrap());
+    let snapshot = storage.state.read().clone();
+    assert_eq!(snapshot.levels[0].1.len(), 1);
+    let sst = snapshot.sstables[&snapshot.levels[0].1[0]].clone();
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        assert!(!iter.value().is_empty());
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(cnt, 10);
+}
+
+#[test]
+fn test_lsm_iterator_stats() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.delete(b"2").unwrap();
+    storage.delete(b"3").unwrap();
+    storage.put(b"4", b"2333").unwrap();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    let stats = iter.stats();
+    assert_eq!(stats.tombstones_skipped, 2);
+    assert_eq!(stats.entries_returned, 2);
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{BackgroundTask, LsmEvent, LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_flush_deferred_on_insufficient_disk_space() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        disk_space_reserve: u64::MAX / 2,
+        ..LsmStorageOptions::default_for_week1_day6_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    storage.add_event_listener(Box::new(move |event| {
+        tx.send(event.clone()).ok();
+    }));
+    for i in 0..3 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    let event = rx
+        .recv_timeout(Duration::from_secs(5))
+        .expect("flush was not deferred");
+    assert!(matches!(
+        event,
+        LsmEvent::InsufficientDiskSpace {
+            task: BackgroundTask::Flush,
+            ..
+        }
+    ));
+    let state = storage.inner.state.read();
+    assert!(state.l0_sstables.is_empty());
+    assert_eq!(state.imm_memtables.len(), 3);
+}
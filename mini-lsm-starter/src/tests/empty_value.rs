@@ -0,0 +1,88 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::block::{Block, BlockBuilder, BlockIterator};
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mem_table::MemTable;
+
+#[test]
+fn test_block_empty_value() {
+    let mut builder = BlockBuilder::new(4096);
+    assert!(builder.add_entry(KeySlice::for_testing_from_slice_no_ts(b"1"), b"", 0, false));
+    assert!(builder.add_entry(KeySlice::for_testing_from_slice_no_ts(b"2"), b"", 0, true));
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"3"), b""));
+    let block = Block::decode(&builder.build().encode());
+    assert!(block.has_deletes());
+    let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(block));
+    let mut deleted = Vec::new();
+    while iter.is_valid() {
+        assert_eq!(iter.value(), b"");
+        deleted.push(iter.is_deleted());
+        iter.next();
+    }
+    assert_eq!(deleted, vec![false, true, true]);
+}
+
+#[test]
+fn test_memtable_empty_value() {
+    let memtable = MemTable::create(0);
+    memtable.put(b"1", b"").unwrap();
+    memtable.delete(b"2").unwrap();
+    assert_eq!(memtable.get_entry(b"1"), Some(Some(Bytes::new())));
+    assert_eq!(memtable.get_entry(b"2"), Some(None));
+    assert_eq!(memtable.get_entry(b"3"), None);
+    let mut iter = memtable.scan(Bound::Unbounded, Bound::Unbounded);
+    assert!(!iter.is_deleted());
+    iter.next().unwrap();
+    assert!(iter.is_deleted());
+}
+
+#[test]
+fn test_empty_value_is_not_a_delete() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"2", b"233").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"1", b"").unwrap();
+    storage.delete(b"2").unwrap();
+    storage.put(b"3", b"").unwrap();
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(storage.get(b"1").unwrap(), Some(Bytes::new()));
+        assert_eq!(storage.get(b"2").unwrap(), None);
+        assert_eq!(storage.get(b"3").unwrap(), Some(Bytes::new()));
+        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+        let mut keys = Vec::new();
+        while iter.is_valid() {
+            assert_eq!(iter.value(), b"");
+            keys.push(Bytes::copy_from_slice(iter.key()));
+            iter.next().unwrap();
+        }
+        assert_eq!(keys, vec![Bytes::from("1"), Bytes::from("3")]);
+    };
+    check(&storage);
+    storage.force_flush().unwrap();
+    check(&storage);
+    storage.force_full_compaction().unwrap();
+    check(&storage);
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use super::harness::put_and_flush;
+use crate::compact::{
+    CompactionOptions, CompactionTask, EntryCountCompactionOptions, LeveledCompactionOptions,
+};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+fn options(entry_count_compaction: Option<EntryCountCompactionOptions>) -> LsmStorageOptions {
+    LsmStorageOptions {
+        entry_count_compaction,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 100,
+                max_levels: 3,
+                base_level_size_mb: 1,
+            },
+        ))
+    }
+}
+
+/// Write 100 small entries and flush them into an L0 SST.
+fn level_entries(storage: &LsmStorageInner) -> Vec<u64> {
+    let snapshot = storage.state.read();
+    snapshot
+        .levels
+        .iter()
+        .map(|(_, sst_ids)| {
+            sst_ids
+                .iter()
+                .map(|id| snapshot.sstables[id].properties().num_entries)
+                .sum()
+        })
+        .collect()
+}
+
+#[test]
+fn test_entry_count_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(None)).unwrap();
+    for round in 0..4 {
+        put_and_flush(&storage, round);
+    }
+    // The SSTs are far from the byte sizes and the file count that trigger a compaction
+    assert!(storage.explain_compaction().is_none());
+    drop(storage);
+
+    let storage = LsmStorageInner::open(
+        &dir,
+        options(Some(EntryCountCompactionOptions {
+            base_level_entries: 200,
+            level0_entry_compaction_trigger: Some(300),
+        })),
+    )
+    .unwrap();
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Leveled(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    // Everything is empty below L0, so the bottom level is the base level
+    assert_eq!(task.upper_level, None);
+    assert_eq!(task.lower_level, 3);
+    assert_eq!(plan.levels[0].num_entries, 400);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(storage.state.read().l0_sstables.is_empty());
+    assert_eq!(level_entries(&storage), vec![0, 0, 400]);
+    assert!(storage.explain_compaction().is_none());
+
+    // With 400 entries at the bottom, L2 becomes the base level with a target of 200 entries
+    for round in 4..7 {
+        put_and_flush(&storage, round);
+    }
+    assert!(storage.trigger_compaction().unwrap());
+    assert_eq!(level_entries(&storage), vec![0, 300, 400]);
+    // L2 is over its target, so one of its SSTs goes down
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Leveled(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.upper_level, Some(2));
+    assert_eq!(task.lower_level, 3);
+    while storage.trigger_compaction().unwrap() {}
+    let entries = level_entries(&storage);
+    assert_eq!(entries.iter().sum::<u64>(), 700);
+    assert!(entries[1] <= entries[2] / 2);
+    for round in 0..7 {
+        assert_eq!(
+            storage
+                .get(format!("key_{:02}_{:03}", round, 99).as_bytes())
+                .unwrap()
+                .as_deref(),
+            Some(&b"value"[..])
+        );
+    }
+}
+
+#[test]
+fn test_entry_count_options_validation() {
+    let dir = tempdir().unwrap();
+    let options = options(Some(EntryCountCompactionOptions {
+        base_level_entries: 0,
+        level0_entry_compaction_trigger: None,
+    }));
+    assert!(LsmStorageInner::open(&dir, options).is_err());
+}
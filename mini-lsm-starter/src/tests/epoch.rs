@@ -0,0 +1,53 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_epoch_fencing() {
+    let dir = tempdir().unwrap();
+    let sst_files = || {
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".sst"))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+
+    let zombie = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(zombie.inner.epoch(), 1);
+    zombie.put(b"key", b"1").unwrap();
+    zombie.force_flush().unwrap();
+
+    // Another process takes over while the old one keeps writing
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.inner.epoch(), 2);
+    zombie.put(b"key", b"2").unwrap();
+    zombie.force_flush().unwrap();
+    storage.put(b"key", b"3").unwrap();
+    storage.force_flush().unwrap();
+    // Both processes allocated SST 1, the epoch keeps their files apart
+    assert_eq!(sst_files(), ["00000-1.sst", "00001-1.sst", "00001-2.sst"]);
+    drop(zombie);
+    drop(storage);
+
+    // The SST flushed by the zombie after the takeover is not in the manifest
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.inner.epoch(), 3);
+    assert_eq!(sst_files(), ["00000-1.sst", "00001-2.sst"]);
+}
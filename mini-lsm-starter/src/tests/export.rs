@@ -0,0 +1,150 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::export::Exporter;
+#[cfg(feature = "csv")]
+use crate::export::decode_hex;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn storage_with_entries(dir: &tempfile::TempDir) -> std::sync::Arc<MiniLsm> {
+    let storage = MiniLsm::open(dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..100 {
+        storage
+            .put(format!("key_{:03}", idx).as_bytes(), &[idx as u8, 0xff])
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete(b"key_001").unwrap();
+    storage
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn test_export_csv() {
+    let dir = tempdir().unwrap();
+    let storage = storage_with_entries(&dir);
+    let snapshot = storage.snapshot().unwrap();
+    storage.put(b"key_050", b"after the snapshot").unwrap();
+
+    let mut csv = Vec::new();
+    let rows = Exporter::new()
+        .with_value_decoder(decode_hex)
+        .write_csv(
+            snapshot
+                .scan(Bound::Unbounded, Bound::Excluded(b"key_003"))
+                .unwrap(),
+            &mut csv,
+        )
+        .unwrap();
+    assert_eq!(rows, 2);
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "key,value\nkey_000,00ff\nkey_002,02ff\n"
+    );
+
+    let exporter = Exporter::new().with_key_decoder(|key| anyhow::bail!("bad key {:?}", key));
+    let scan = snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(exporter.write_csv(scan, Vec::new()).is_err());
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_export_parquet() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let dir = tempdir().unwrap();
+    let storage = storage_with_entries(&dir);
+    let snapshot = storage.snapshot().unwrap();
+
+    let mut file = Vec::new();
+    let rows = Exporter::new()
+        .with_value_decoder(|value| Ok(value[0].to_string()))
+        .with_rows_per_group(32)
+        .write_parquet(
+            snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+            &mut file,
+        )
+        .unwrap();
+    assert_eq!(rows, 99);
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(file)).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 4);
+    let entries = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            (
+                row.get_string(0).unwrap().clone(),
+                row.get_string(1).unwrap().clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let expected = (0..100)
+        .filter(|idx| *idx != 1)
+        .map(|idx| (format!("key_{:03}", idx), idx.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(entries, expected);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_export_parquet_bulk_import() {
+    use crate::bulk_import::{BulkImport, ImportFormat};
+
+    let source_dir = tempdir().unwrap();
+    let source = MiniLsm::open(&source_dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..1000 {
+        source
+            .put(
+                format!("key_{:05}", idx).as_bytes(),
+                format!("value_{}", idx).as_bytes(),
+            )
+            .unwrap();
+    }
+    let input = tempdir().unwrap();
+    let files = [
+        (Bound::Unbounded, Bound::Excluded(&b"key_00600"[..])),
+        (Bound::Included(&b"key_00600"[..]), Bound::Unbounded),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(file, (lower, upper))| {
+        let path = input.path().join(format!("{}.parquet", file));
+        Exporter::new()
+            .with_rows_per_group(128)
+            .write_parquet(
+                source.scan(lower, upper).unwrap(),
+                std::fs::File::create(&path).unwrap(),
+            )
+            .unwrap();
+        path
+    })
+    .collect::<Vec<_>>();
+
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage
+        .bulk_import(&files, &BulkImport::new(ImportFormat::Parquet))
+        .unwrap();
+    for idx in [0, 599, 600, 999] {
+        let value = storage.get(format!("key_{:05}", idx).as_bytes()).unwrap();
+        assert_eq!(&value.unwrap()[..], format!("value_{}", idx).as_bytes());
+    }
+}
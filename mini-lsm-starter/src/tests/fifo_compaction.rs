@@ -0,0 +1,109 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use super::harness::put_and_flush;
+use crate::compact::{CompactionOptions, CompactionTask, FifoCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+/// The size of the SST of one round.
+fn round_sst_size() -> u64 {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    put_and_flush(&storage, 0);
+    let snapshot = storage.state.read();
+    snapshot.sstables.values().map(|sst| sst.table_size()).sum()
+}
+
+fn fifo_options(max_table_files_size: u64) -> LsmStorageOptions {
+    LsmStorageOptions::default_for_week2_test(CompactionOptions::Fifo(FifoCompactionOptions {
+        max_table_files_size,
+    }))
+}
+
+#[test]
+fn test_fifo_compaction_drops_oldest_ssts() {
+    let dir = tempdir().unwrap();
+    let budget = round_sst_size() * 5 / 2;
+    let storage = LsmStorageInner::open(&dir, fifo_options(budget)).unwrap();
+    put_and_flush(&storage, 0);
+    put_and_flush(&storage, 1);
+    assert!(storage.explain_compaction().is_none());
+
+    put_and_flush(&storage, 2);
+    let l0_sstables = storage.state.read().l0_sstables.clone();
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Fifo(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.sst_ids, vec![l0_sstables[2]]);
+    assert_eq!(plan.estimated_output_size, 0);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(!storage.trigger_compaction().unwrap());
+
+    // The remaining SSTs are kept as they are
+    assert_eq!(storage.state.read().l0_sstables, l0_sstables[..2]);
+    assert!(!storage.path_of_sst(l0_sstables[2]).exists());
+
+    for round in 3..6 {
+        put_and_flush(&storage, round);
+        assert!(storage.trigger_compaction().unwrap());
+    }
+    let snapshot = storage.state.read().clone();
+    assert_eq!(snapshot.l0_sstables.len(), 2);
+    assert!(snapshot.levels.is_empty());
+    let total_size = snapshot
+        .sstables
+        .values()
+        .map(|sst| sst.table_size())
+        .sum::<u64>();
+    assert!(total_size <= budget);
+    for round in 0..4 {
+        let key = format!("key_{:02}_{:03}", round, 0);
+        assert!(storage.get(key.as_bytes()).unwrap().is_none());
+    }
+    for round in 4..6 {
+        let key = format!("key_{:02}_{:03}", round, 0);
+        assert!(storage.get(key.as_bytes()).unwrap().is_some());
+    }
+}
+
+#[test]
+fn test_fifo_compaction_recovery() {
+    let dir = tempdir().unwrap();
+    let options = fifo_options(round_sst_size() * 3 / 2);
+    let storage = LsmStorageInner::open(&dir, options.clone()).unwrap();
+    for round in 0..3 {
+        put_and_flush(&storage, round);
+        while storage.trigger_compaction().unwrap() {}
+    }
+    let l0_sstables = storage.state.read().l0_sstables.clone();
+    assert_eq!(l0_sstables.len(), 1);
+    drop(storage);
+
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    assert_eq!(storage.state.read().l0_sstables, l0_sstables);
+    assert!(storage.get(b"key_01_000").unwrap().is_none());
+    assert!(storage.get(b"key_02_000").unwrap().is_some());
+}
+
+#[test]
+fn test_fifo_compaction_validation() {
+    assert!(fifo_options(0).validate().is_err());
+    let mut options = fifo_options(1 << 20);
+    options.max_sorted_runs = Some(4);
+    assert!(options.validate().is_err());
+}
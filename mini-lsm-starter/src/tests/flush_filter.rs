@@ -0,0 +1,71 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{FlushDecision, FlushFilter, LsmStorageOptions, MiniLsm};
+
+/// Drops released locks and redacts the values of secrets.
+struct LockFilter;
+
+impl FlushFilter for LockFilter {
+    fn filter(&self, key: &[u8], value: Option<&[u8]>) -> FlushDecision {
+        if key.starts_with(b"lock/") && value.is_none_or(|value| value == b"released") {
+            FlushDecision::Remove
+        } else if key.starts_with(b"secret/") && value.is_some() {
+            FlushDecision::ChangeValue(Bytes::from_static(b"redacted"))
+        } else {
+            FlushDecision::Keep
+        }
+    }
+}
+
+#[test]
+fn test_flush_filter() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.add_flush_filter(Box::new(LockFilter));
+
+    // A memtable with only released locks does not produce an SST
+    storage.put(b"lock/1", b"released").unwrap();
+    storage.delete(b"lock/2").unwrap();
+    storage.force_flush().unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(state.imm_memtables.is_empty());
+        assert!(state.l0_sstables.is_empty());
+    }
+    assert_eq!(storage.get(b"lock/1").unwrap(), None);
+
+    storage.put(b"lock/3", b"held").unwrap();
+    storage.put(b"lock/4", b"released").unwrap();
+    storage.put(b"secret/1", b"password").unwrap();
+    storage.put(b"user/1", b"alice").unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 1);
+    assert_eq!(
+        storage.get(b"lock/3").unwrap(),
+        Some(Bytes::from_static(b"held"))
+    );
+    assert_eq!(storage.get(b"lock/4").unwrap(), None);
+    assert_eq!(
+        storage.get(b"secret/1").unwrap(),
+        Some(Bytes::from_static(b"redacted"))
+    );
+    assert_eq!(
+        storage.get(b"user/1").unwrap(),
+        Some(Bytes::from_static(b"alice"))
+    );
+}
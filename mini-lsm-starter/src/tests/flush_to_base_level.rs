@@ -0,0 +1,90 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use super::harness::put_and_flush;
+use crate::compact::{CompactionOptions, LeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+fn options(flush_to_base_level: bool) -> LsmStorageOptions {
+    LsmStorageOptions {
+        flush_to_base_level,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                base_level_size_mb: 1,
+            },
+        ))
+    }
+}
+
+/// Write keys after all the keys written in the previous rounds and flush them.
+#[test]
+fn test_flush_to_base_level() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(true)).unwrap();
+    // With nothing below L0, the base level is the bottom level
+    for round in [0, 2, 1] {
+        put_and_flush(&storage, round);
+    }
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.l0_sstables.is_empty());
+    assert_eq!(snapshot.levels[2].1.len(), 3);
+    let first_keys = snapshot.levels[2]
+        .1
+        .iter()
+        .map(|id| snapshot.sstables[id].first_key().clone())
+        .collect::<Vec<_>>();
+    assert!(first_keys.is_sorted());
+    assert!(storage.explain_compaction().is_none());
+
+    // A memtable overlapping with the base level goes to L0
+    storage.put(b"key_01_050", b"new_value").unwrap();
+    storage.force_flush_all().unwrap();
+    assert_eq!(storage.state.read().l0_sstables.len(), 1);
+    // And so does the next one, as L0 is not empty
+    put_and_flush(&storage, 3);
+    assert_eq!(storage.state.read().l0_sstables.len(), 2);
+    assert_eq!(
+        storage.get(b"key_01_050").unwrap().as_deref(),
+        Some(&b"new_value"[..])
+    );
+
+    let levels = storage.state.read().levels.clone();
+    drop(storage);
+    let storage = LsmStorageInner::open(&dir, options(true)).unwrap();
+    assert_eq!(storage.state.read().levels, levels);
+    assert_eq!(storage.state.read().l0_sstables.len(), 2);
+    assert!(storage.get(b"key_02_099").unwrap().is_some());
+}
+
+#[test]
+fn test_flush_to_l0_while_compacting() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(true)).unwrap();
+    {
+        let _compaction_lock = storage.compaction_lock.lock();
+        put_and_flush(&storage, 0);
+    }
+    assert_eq!(storage.state.read().l0_sstables.len(), 1);
+    drop(storage);
+
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options(false)).unwrap();
+    put_and_flush(&storage, 0);
+    assert_eq!(storage.state.read().l0_sstables.len(), 1);
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Golden files written by each on-disk format version, which the current build must read.
+//!
+//! The fixtures of the current version are written by `generate_format_fixtures`:
+//!
+//! ```text
+//! cargo test -p mini-lsm-starter generate_format_fixtures -- --ignored
+//! ```
+//!
+//! Run it once after bumping `FORMAT_VERSION`, and never change the workload below, as the
+//! fixtures of the older versions were written with it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm};
+use crate::manifest::{Manifest, ManifestRecord, live_ssts};
+use crate::options_file::{
+    FORMAT_VERSION, MIN_READABLE_FORMAT_VERSION, OPTIONS_FILE, StoredOptions,
+    report_min_readable_version,
+};
+use crate::table::SsTable;
+
+/// A key and its latest value with its metadata byte, or `None` for a delete.
+type Entries = BTreeMap<Bytes, Option<(Bytes, u8)>>;
+
+fn fixture_dir(version: u32) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/fixtures")
+        .join(format!("format_v{}", version))
+}
+
+/// Write the fixture workload into `storage`, returning the entries it leaves in the SSTs: a
+/// compacted SST with values of each size class, and a flushed SST with a delete and an empty
+/// value on top of it.
+fn write_workload(storage: &MiniLsm) -> Entries {
+    let mut entries = Entries::new();
+    for idx in 0..100 {
+        let value = Bytes::from(format!("value_{}", "x".repeat(idx)));
+        storage.put(&key_of(idx), &value).unwrap();
+        entries.insert(key_of(idx), Some((value, 0)));
+    }
+    storage.delete(&key_of(5)).unwrap();
+    entries.remove(&key_of(5));
+    storage.force_flush().unwrap();
+    for idx in 50..150 {
+        let value = Bytes::from(format!("v2_{:05}", idx));
+        let meta = (idx % 4) as u8;
+        storage.put_with_meta(&key_of(idx), &value, meta).unwrap();
+        entries.insert(key_of(idx), Some((value, meta)));
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    storage.delete(&key_of(20)).unwrap();
+    entries.insert(key_of(20), None);
+    storage.put(&key_of(200), b"").unwrap();
+    entries.insert(key_of(200), Some((Bytes::new(), 0)));
+    storage.force_flush().unwrap();
+    entries
+}
+
+#[test]
+#[ignore = "writes the fixtures of the current format version"]
+fn generate_format_fixtures() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    write_workload(&storage);
+    storage.inner.compact_manifest().unwrap();
+    drop(storage);
+
+    let fixture_dir = fixture_dir(FORMAT_VERSION);
+    if fixture_dir.exists() {
+        std::fs::remove_dir_all(&fixture_dir).unwrap();
+    }
+    std::fs::create_dir_all(&fixture_dir).unwrap();
+    for file in std::fs::read_dir(&dir).unwrap() {
+        let file = file.unwrap();
+        let name = file.file_name().into_string().unwrap();
+        if name == "MANIFEST" || name == OPTIONS_FILE || name.ends_with(".sst") {
+            std::fs::copy(file.path(), fixture_dir.join(name)).unwrap();
+        }
+    }
+}
+
+/// Read the SSTs in the manifest of a fixture, and merge their entries from the oldest to the
+/// newest.
+fn read_fixture(fixture_dir: &Path) -> Entries {
+    // Recovering appends to the manifest, so read a copy
+    let dir = tempdir().unwrap();
+    std::fs::copy(fixture_dir.join("MANIFEST"), dir.path().join("MANIFEST")).unwrap();
+    let (_, records) = Manifest::recover(dir.path().join("MANIFEST")).unwrap();
+    let live = live_ssts(&records);
+    assert_eq!(live.len(), 2);
+    // The SSTs in the order they were written, as compaction outputs may have greater ids than
+    // the memtables flushed after them
+    let ssts = records.iter().flat_map(|entry| match &entry.record {
+        ManifestRecord::Flush(sst_id) => vec![*sst_id],
+        ManifestRecord::Compaction(_, output) => output.clone(),
+        // From the bottom level up to the latest L0 SST
+        ManifestRecord::Snapshot(snapshot) => snapshot
+            .levels
+            .iter()
+            .rev()
+            .flat_map(|(_, level)| level.iter().copied())
+            .chain(snapshot.l0_sstables.iter().rev().copied())
+            .collect(),
+        _ => vec![],
+    });
+
+    let mut entries = Entries::new();
+    for sst_id in ssts.filter(|sst_id| live.contains_key(sst_id)) {
+        let epoch = live[&sst_id];
+        let path = LsmStorageInner::path_of_sst_static(fixture_dir, epoch, sst_id);
+        let sst = Arc::new(SsTable::open_path(&path).unwrap());
+        let mut iter = sst.iter().unwrap();
+        let mut num_entries = 0;
+        while iter.is_valid() {
+            let key = Bytes::copy_from_slice(iter.key().key_ref());
+            let entry = match iter.is_deleted() {
+                true => None,
+                false => Some((Bytes::copy_from_slice(iter.value()), iter.value_meta())),
+            };
+            entries.insert(key, entry);
+            num_entries += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(sst.properties().num_entries, num_entries);
+    }
+    entries
+}
+
+#[test]
+fn test_read_format_fixtures() {
+    assert_eq!(report_min_readable_version(), MIN_READABLE_FORMAT_VERSION);
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let expected = write_workload(&storage);
+    for version in MIN_READABLE_FORMAT_VERSION..=FORMAT_VERSION {
+        let fixture_dir = fixture_dir(version);
+        assert!(
+            fixture_dir.exists(),
+            "no fixtures for format version {}",
+            version
+        );
+        let stored = StoredOptions::read(&fixture_dir).unwrap().unwrap();
+        assert_eq!(stored.format_version, version);
+        stored.check_format_version().unwrap();
+        assert_eq!(
+            read_fixture(&fixture_dir),
+            expected,
+            "format version {}",
+            version
+        );
+    }
+}
+
+#[test]
+fn test_unreadable_format_version() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week1_test();
+    drop(MiniLsm::open(&dir, options.clone()).unwrap());
+    let mut stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.format_version, FORMAT_VERSION);
+    stored.format_version = FORMAT_VERSION + 1;
+    stored.write(dir.path()).unwrap();
+    let err = MiniLsm::open(&dir, options).err().unwrap().to_string();
+    assert!(err.contains("format version"), "{}", err);
+}
@@ -0,0 +1,191 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::format_migration::{MIGRATION_FILE, MigrationProgress, migrate_format};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm};
+use crate::manifest::{Manifest, live_ssts};
+use crate::options_file::{FORMAT_VERSION, StoredOptions};
+use crate::prefix_extractor::FixedPrefix;
+use crate::table::SsTable;
+
+fn sst_entries(path: &Path) -> Vec<(Bytes, Bytes, u8, bool)> {
+    let sst = Arc::new(SsTable::open_path(path).unwrap());
+    let mut iter = sst.iter().unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key().key_ref()),
+            Bytes::copy_from_slice(iter.value()),
+            iter.value_meta(),
+            iter.is_deleted(),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_migrate_format() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week1_test();
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..3 {
+        for idx in 0..100 {
+            let key = format!("key_{:05}", idx * 3 + round);
+            storage
+                .put_with_meta(key.as_bytes(), b"value", round as u8)
+                .unwrap();
+        }
+        storage.delete(b"key_00000").unwrap();
+        storage.force_flush().unwrap();
+    }
+    drop(storage);
+
+    let records = Manifest::read(dir.path().join("MANIFEST")).unwrap();
+    let mut ssts = live_ssts(&records)
+        .into_iter()
+        .map(|(sst_id, epoch)| LsmStorageInner::path_of_sst_static(&dir, epoch, sst_id))
+        .collect::<Vec<_>>();
+    ssts.sort();
+    assert_eq!(ssts.len(), 3);
+    let entries = ssts
+        .iter()
+        .map(|path| sst_entries(path))
+        .collect::<Vec<_>>();
+    let inode = |path: &Path| std::fs::metadata(path).unwrap().ino();
+    let inodes = ssts.iter().map(|path| inode(path)).collect::<Vec<_>>();
+
+    // Resume a migration that was interrupted after the first SST, and while writing the line
+    // of the second
+    let first_sst_id = live_ssts(&records).keys().copied().min().unwrap();
+    std::fs::write(
+        dir.path().join(MIGRATION_FILE),
+        format!("{}\n{}", first_sst_id, first_sst_id + 1),
+    )
+    .unwrap();
+    let mut reports = Vec::new();
+    migrate_format(&dir, &options, |progress| reports.push(progress)).unwrap();
+    assert_eq!(
+        reports,
+        (1..=3)
+            .map(|migrated_ssts| MigrationProgress {
+                from_version: FORMAT_VERSION,
+                migrated_ssts,
+                total_ssts: 3,
+            })
+            .collect::<Vec<_>>()
+    );
+    assert!(!dir.path().join(MIGRATION_FILE).exists());
+    assert_eq!(inode(&ssts[0]), inodes[0]);
+    assert_ne!(inode(&ssts[1]), inodes[1]);
+    assert_ne!(inode(&ssts[2]), inodes[2]);
+    for (path, entries) in ssts.iter().zip(entries) {
+        assert_eq!(sst_entries(path), entries);
+    }
+    let stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.format_version, FORMAT_VERSION);
+    assert_eq!(
+        live_ssts(&Manifest::read(dir.path().join("MANIFEST")).unwrap()),
+        live_ssts(&records)
+    );
+
+    // The storage opens after the migration
+    MiniLsm::open(&dir, options).unwrap();
+}
+
+#[test]
+fn test_migrate_format_v1_fixture() {
+    let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/fixtures/format_v1");
+    let dir = tempdir().unwrap();
+    for file in std::fs::read_dir(&fixture_dir).unwrap() {
+        let file = file.unwrap();
+        std::fs::copy(file.path(), dir.path().join(file.file_name())).unwrap();
+    }
+    let ssts = live_ssts(&Manifest::read(dir.path().join("MANIFEST")).unwrap())
+        .into_iter()
+        .map(|(sst_id, epoch)| LsmStorageInner::path_of_sst_static(&dir, epoch, sst_id))
+        .collect::<Vec<_>>();
+    let entries = ssts
+        .iter()
+        .map(|path| sst_entries(path))
+        .collect::<Vec<_>>();
+    for path in &ssts {
+        assert!(
+            !SsTable::open_path(path)
+                .unwrap()
+                .properties()
+                .block_checksums
+        );
+    }
+
+    let mut from_versions = Vec::new();
+    migrate_format(
+        &dir,
+        &LsmStorageOptions::default_for_week1_test(),
+        |progress| from_versions.push(progress.from_version),
+    )
+    .unwrap();
+    assert_eq!(from_versions, vec![1; ssts.len() + 1]);
+    for (path, entries) in ssts.iter().zip(entries) {
+        assert!(
+            SsTable::open_path(path)
+                .unwrap()
+                .properties()
+                .block_checksums
+        );
+        assert_eq!(sst_entries(path), entries);
+    }
+}
+
+#[test]
+fn test_migrate_format_keeps_range_tombstones() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        prefix_extractor: Some(Arc::new(FixedPrefix(1))),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for key in [b"a1", b"m1", b"m2", b"z1"] {
+        storage.put(key, b"v").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(b"m", b"n").unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(storage.get(b"m1").unwrap(), None);
+    drop(storage);
+
+    migrate_format(&dir, &options, |_| {}).unwrap();
+    let ssts = live_ssts(&Manifest::read(dir.path().join("MANIFEST")).unwrap());
+    for (sst_id, epoch) in ssts {
+        let path = LsmStorageInner::path_of_sst_static(&dir, epoch, sst_id);
+        let sst = SsTable::open_path(path).unwrap();
+        assert_eq!(
+            sst.properties().prefix_extractor.as_deref(),
+            Some("fixed:1")
+        );
+    }
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a1").unwrap(), Some(Bytes::from_static(b"v")));
+    assert_eq!(storage.get(b"m1").unwrap(), None);
+    assert_eq!(storage.get(b"m2").unwrap(), None);
+    assert_eq!(storage.get(b"z1").unwrap(), Some(Bytes::from_static(b"v")));
+}
@@ -65,37 +65,37 @@ impl StorageIterator for MockIterator {
         if self.index < self.data.len() {
             self.index += 1;
         }
-        if let Some(error_when) = self.error_when {
-            if self.index == error_when {
-                bail!("fake error!");
-            }
+        if let Some(error_when) = self.error_when
+            && self.index == error_when
+        {
+            bail!("fake error!");
         }
         Ok(())
     }
 
-    fn key(&self) -> KeySlice {
-        if let Some(error_when) = self.error_when {
-            if self.index >= error_when {
-                panic!("invalid access after next returns an error!");
-            }
+    fn key(&self) -> KeySlice<'_> {
+        if let Some(error_when) = self.error_when
+            && self.index >= error_when
+        {
+            panic!("invalid access after next returns an error!");
         }
         KeySlice::for_testing_from_slice_no_ts(self.data[self.index].0.as_ref())
     }
 
     fn value(&self) -> &[u8] {
-        if let Some(error_when) = self.error_when {
-            if self.index >= error_when {
-                panic!("invalid access after next returns an error!");
-            }
+        if let Some(error_when) = self.error_when
+            && self.index >= error_when
+        {
+            panic!("invalid access after next returns an error!");
         }
         self.data[self.index].1.as_ref()
     }
 
     fn is_valid(&self) -> bool {
-        if let Some(error_when) = self.error_when {
-            if self.index >= error_when {
-                panic!("invalid access after next returns an error!");
-            }
+        if let Some(error_when) = self.error_when
+            && self.index >= error_when
+        {
+            panic!("invalid access after next returns an error!");
         }
         self.index < self.data.len()
     }
@@ -234,6 +234,21 @@ pub fn sync(storage: &LsmStorageInner) {
     storage.force_flush_next_imm_memtable().unwrap();
 }
 
+/// The key of entry `idx` of a test, in the order of the indices.
+pub fn key_of(idx: usize) -> Bytes {
+    Bytes::from(format!("key_{:05}", idx))
+}
+
+/// Write 100 keys after the keys of the previous rounds, and flush them into an SST.
+pub fn put_and_flush(storage: &LsmStorageInner, round: usize) {
+    for i in 0..100 {
+        storage
+            .put(format!("key_{:02}_{:03}", round, i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush_all().unwrap();
+}
+
 pub fn compaction_bench(storage: Arc<MiniLsm>) {
     let mut key_map = BTreeMap::<usize, usize>::new();
     let gen_key = |i| format!("{:010}", i); // 10B
@@ -325,7 +340,7 @@ pub fn check_compaction_ratio(storage: Arc<MiniLsm>) {
         .num_active_iterators();
     let num_memtables = storage.inner.state.read().imm_memtables.len() + 1;
     match compaction_options {
-        CompactionOptions::NoCompaction => unreachable!(),
+        CompactionOptions::NoCompaction | CompactionOptions::Fifo(_) => unreachable!(),
         CompactionOptions::Simple(SimpleLeveledCompactionOptions {
             size_ratio_percent,
             level0_file_num_compaction_trigger,
@@ -425,6 +440,14 @@ pub fn check_compaction_ratio(storage: Arc<MiniLsm>) {
                 "we found {num_iters} iterators in your implementation, (num_memtables={num_memtables}, num_tiers={num_tiers}) did you use concat iterators?"
             );
         }
+        CompactionOptions::LazyLeveled(ref options) => {
+            assert!(l0_sst_num < options.size_ratio);
+            let max_sorted_runs = compaction_options.sorted_runs_before_compaction().unwrap();
+            assert!(
+                num_iters <= num_memtables + max_sorted_runs + extra_iterators,
+                "we found {num_iters} iterators, (num_memtables={num_memtables}, max_sorted_runs={max_sorted_runs})"
+            );
+        }
     }
 }
 
@@ -0,0 +1,99 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::idempotence::{OperationId, is_stale_operation_error};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteOptions};
+use crate::write_batch::WriteBatch;
+
+fn op(client_id: u64, op_id: u64) -> WriteOptions {
+    WriteOptions {
+        operation: Some(OperationId { client_id, op_id }),
+        ..Default::default()
+    }
+}
+
+/// Add `delta` to the counter at `key`, as a producer retrying the increment would.
+fn increment(storage: &MiniLsm, key: &[u8], delta: u64, options: &WriteOptions) {
+    let value = storage.get(key).unwrap().map_or(0, |value| {
+        String::from_utf8(value.to_vec()).unwrap().parse().unwrap()
+    });
+    let mut batch = WriteBatch::new();
+    batch.put(key, (value + delta).to_string().as_bytes());
+    storage.write_batch_with_options(&batch, options).unwrap();
+}
+
+#[test]
+fn test_idempotent_writes() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        idempotent_write_window: 4,
+        internal_keyspace: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    // A retried increment is applied once
+    increment(&storage, b"counter", 5, &op(1, 1));
+    increment(&storage, b"counter", 5, &op(1, 1));
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("5")));
+    // The operation IDs of each client are separate, and writes without one are not deduplicated
+    increment(&storage, b"counter", 1, &op(2, 1));
+    increment(&storage, b"counter", 1, &WriteOptions::default());
+    increment(&storage, b"counter", 1, &WriteOptions::default());
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("8")));
+    storage.put_with_options(b"a", b"1", &op(1, 2)).unwrap();
+    storage.put_with_options(b"a", b"2", &op(1, 2)).unwrap();
+    storage.delete_with_options(b"a", &op(1, 2)).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    drop(storage);
+
+    // The window survives a restart
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    increment(&storage, b"counter", 5, &op(1, 1));
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("8")));
+    // Only the latest 4 operations of a client are remembered, the out-of-order ones included
+    for op_id in [4, 3, 6, 5] {
+        increment(&storage, b"counter", 1, &op(1, op_id));
+    }
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("12")));
+    increment(&storage, b"counter", 1, &op(1, 3));
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("12")));
+    let err = storage
+        .put_with_options(b"counter", b"0", &op(1, 2))
+        .unwrap_err();
+    assert!(is_stale_operation_error(&err), "{}", err);
+    assert_eq!(storage.get(b"counter").unwrap(), Some(Bytes::from("12")));
+    // The window is not visible to the scans
+    let iter = storage
+        .scan(
+            std::ops::Bound::Excluded(b"counter"),
+            std::ops::Bound::Unbounded,
+        )
+        .unwrap();
+    assert!(!crate::iterators::StorageIterator::is_valid(&iter));
+}
+
+#[test]
+fn test_idempotent_writes_need_internal_keyspace() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert!(storage.put_with_options(b"a", b"1", &op(1, 1)).is_err());
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    storage
+        .put_with_options(b"a", b"1", &WriteOptions::default())
+        .unwrap();
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::io_priority::IoPriority;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions, ReadTier};
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        low_priority_read_rate_limit: Some(20_000),
+        ..LsmStorageOptions::default_for_week1_test()
+    }
+}
+
+fn scan_all(storage: &MiniLsm, options: &ReadOptions) -> usize {
+    let mut iter = storage
+        .scan_with_options(Bound::Unbounded, Bound::Unbounded, options)
+        .unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    count
+}
+
+#[test]
+fn test_low_priority_reads() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let value = [b'x'; 996];
+    for idx in 0..40 {
+        storage
+            .put(format!("{:04}", idx).as_bytes(), &value)
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    drop(storage);
+    let low_priority = ReadOptions {
+        priority: IoPriority::Low,
+        ..Default::default()
+    };
+
+    // Interactive reads are not throttled
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let start = Instant::now();
+    assert_eq!(scan_all(&storage, &ReadOptions::default()), 40);
+    assert!(start.elapsed() < Duration::from_millis(400));
+    drop(storage);
+
+    // The first second worth of bytes is read without waiting
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let start = Instant::now();
+    assert_eq!(scan_all(&storage, &low_priority), 40);
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    // The blocks in the block cache are not throttled
+    let start = Instant::now();
+    assert_eq!(scan_all(&storage, &low_priority), 40);
+    assert_eq!(
+        storage.get_with_options(b"0020", &low_priority).unwrap(),
+        Some(value.to_vec().into())
+    );
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    storage.set_low_priority_read_rate_limit(None);
+    assert_eq!(storage.low_priority_read_rate_limit(), None);
+    let options = ReadOptions {
+        read_tier: ReadTier::BlockCacheOnly,
+        ..low_priority
+    };
+    assert!(
+        storage
+            .scan_with_options(Bound::Unbounded, Bound::Unbounded, &options)
+            .is_err()
+    );
+}
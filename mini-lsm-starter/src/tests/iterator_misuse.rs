@@ -0,0 +1,75 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_iterator::is_iterator_misuse_error;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_try_key_value() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(iter.try_key().unwrap(), b"a");
+    assert_eq!(iter.try_value().unwrap(), b"1");
+    assert!(!iter.try_is_deleted().unwrap());
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    assert!(is_iterator_misuse_error(&iter.try_key().unwrap_err()));
+    assert!(is_iterator_misuse_error(&iter.try_value().unwrap_err()));
+    assert!(is_iterator_misuse_error(
+        &iter.try_value_meta().unwrap_err()
+    ));
+    // The `try_` methods leave the iterator usable
+    iter.next().unwrap();
+
+    // Without `iterator_misuse_errors`, reading an invalid iterator panics
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        iter.key();
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_iterator_misuse_errors() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        iterator_misuse_errors: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!((iter.key(), iter.value()), (&b"a"[..], &b"1"[..]));
+    iter.next().unwrap();
+    assert_eq!((iter.key(), iter.value()), (&b""[..], &b""[..]));
+    let err = iter.next().unwrap_err();
+    assert!(is_iterator_misuse_error(&err), "{}", err);
+    assert!(err.to_string().contains("key"), "{}", err);
+    assert!(!iter.is_valid());
+    assert!(is_iterator_misuse_error(&iter.next().unwrap_err()));
+
+    // The iterators of snapshots follow the option as well
+    let snapshot = storage.snapshot().unwrap();
+    let mut iter = snapshot
+        .scan(Bound::Excluded(b"a"), Bound::Unbounded)
+        .unwrap();
+    assert_eq!(iter.value(), b"");
+    assert!(is_iterator_misuse_error(&iter.next().unwrap_err()));
+}
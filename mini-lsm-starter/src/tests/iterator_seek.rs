@@ -0,0 +1,160 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::compact::CompactionOptions;
+use crate::iterators::StorageIterator;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::key::{KeySlice, TS_RANGE_BEGIN};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mem_table::MemTable;
+
+#[test]
+fn test_merge_iterator_seek() {
+    let even = MemTable::create(0);
+    let odd = MemTable::create(1);
+    for idx in 0..10 {
+        let memtable = if idx % 2 == 0 { &even } else { &odd };
+        memtable.for_testing_put_slice(&key_of(idx), b"v").unwrap();
+    }
+    let mut iter = MergeIterator::create(vec![
+        Box::new(even.scan(Bound::Unbounded, Bound::Unbounded)),
+        Box::new(odd.scan(Bound::Unbounded, Bound::Unbounded)),
+    ]);
+    iter.seek(KeySlice::for_testing_from_slice_no_ts(&key_of(9)))
+        .unwrap();
+    assert_eq!(iter.key().for_testing_key_ref(), key_of(9));
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    // Seeking back revives the exhausted iterators
+    iter.seek(KeySlice::for_testing_from_slice_no_ts(&key_of(3)))
+        .unwrap();
+    for idx in 3..10 {
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+fn collect(iter: &mut impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_lsm_iterator_seek() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_size: 64,
+        target_sst_size: 256,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"old").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), b"new").unwrap();
+    }
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(&key_of(30), &key_of(45)).unwrap();
+    for idx in (0..100).step_by(5) {
+        storage.put(&key_of(idx), b"latest").unwrap();
+    }
+
+    let (lower, upper) = (key_of(10), key_of(90));
+    let mut iter = storage
+        .scan(Bound::Included(&lower), Bound::Excluded(&upper))
+        .unwrap();
+    // Forward, back, into a deleted range, onto deleted keys, and past the end
+    for target in [50, 20, 60, 31, 14, 28, 95, 12] {
+        iter.seek(&key_of(target)).unwrap();
+        let expected = collect(
+            &mut storage
+                .scan(Bound::Included(&key_of(target)), Bound::Excluded(&upper))
+                .unwrap(),
+        );
+        match expected.first() {
+            Some((key, value)) => {
+                assert_eq!((iter.key(), iter.value()), (&key[..], &value[..]));
+                iter.next().unwrap();
+                if let Some((key, _)) = expected.get(1) {
+                    assert_eq!(iter.key(), key);
+                }
+            }
+            None => assert!(!iter.is_valid()),
+        }
+    }
+    // The rest of the scan after a seek matches a new scan from there
+    iter.seek(&key_of(40)).unwrap();
+    let expected = collect(
+        &mut storage
+            .scan(Bound::Included(&key_of(40)), Bound::Excluded(&upper))
+            .unwrap(),
+    );
+    assert_eq!(collect(&mut iter), expected);
+
+    // Seeking before the start of the range stops at the start
+    iter.seek(b"a").unwrap();
+    assert_eq!(iter.key(), key_of(10));
+    let mut iter = storage
+        .scan(Bound::Excluded(&lower), Bound::Unbounded)
+        .unwrap();
+    iter.seek(&key_of(5)).unwrap();
+    assert_eq!(iter.key(), key_of(11));
+
+    // The same after a flush, with the SSTs only
+    storage.force_flush().unwrap();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    iter.seek(&key_of(99)).unwrap();
+    iter.seek(&key_of(31)).unwrap();
+    assert_eq!(iter.key(), key_of(35));
+
+    let mut iter = storage
+        .scan_rev(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert!(iter.seek(&key_of(50)).is_err());
+}
+
+#[test]
+fn test_seek_versions() {
+    let memtable = MemTable::create(0);
+    for ts in 1..=3 {
+        memtable
+            .put_entry(KeySlice::from_slice(b"a", ts), Some(b"v"), 0)
+            .unwrap();
+    }
+    let mut iter = memtable.scan(Bound::Unbounded, Bound::Unbounded);
+    iter.seek(KeySlice::from_slice(b"a", 2)).unwrap();
+    assert_eq!(iter.key().ts(), 2);
+    iter.seek(KeySlice::from_slice(b"a", TS_RANGE_BEGIN))
+        .unwrap();
+    assert_eq!(iter.key().ts(), 3);
+}
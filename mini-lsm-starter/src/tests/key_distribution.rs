@@ -0,0 +1,73 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_key_distribution() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..20000 {
+        storage
+            .put(&key_of(idx), format!("value_{}", idx).as_bytes())
+            .unwrap();
+        if idx % 5000 == 4999 {
+            storage.force_flush().unwrap();
+        }
+    }
+
+    let buckets = storage.key_distribution(4).unwrap();
+    assert_eq!(buckets.len(), 4);
+    assert!(buckets[0].lower.is_empty());
+    assert!(buckets[3].upper.is_none());
+    assert!(
+        buckets
+            .windows(2)
+            .all(|pair| pair[0].upper.as_ref() == Some(&pair[1].lower))
+    );
+    assert_eq!(buckets.iter().map(|b| b.entries).sum::<u64>(), 20000);
+    assert!(buckets.iter().all(|b| b.entries > 0 && b.entries < 10000));
+    let bytes = storage
+        .inner
+        .state
+        .read()
+        .sstables
+        .values()
+        .map(|sst| sst.properties().raw_key_size + sst.properties().raw_value_size)
+        .sum::<u64>();
+    assert_eq!(buckets.iter().map(|b| b.bytes).sum::<u64>(), bytes);
+
+    // The counts follow the compaction, the boundaries stay
+    for idx in 0..10000 {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let refreshed = storage.key_distribution(4).unwrap();
+    assert_eq!(refreshed.len(), 4);
+    for (bucket, old) in refreshed.iter().zip(&buckets) {
+        assert_eq!((&bucket.lower, &bucket.upper), (&old.lower, &old.upper));
+        if bucket
+            .upper
+            .as_ref()
+            .is_some_and(|upper| upper[..] <= key_of(10000)[..])
+        {
+            assert_eq!(bucket.entries, 0);
+        }
+    }
+    assert_eq!(refreshed.iter().map(|b| b.entries).sum::<u64>(), 10000);
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::key_range_stats::{KeyRangeStats, jump_consistent_hash, key_hash};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_jump_consistent_hash() {
+    let hashes = (0..10000)
+        .map(|idx| key_hash(format!("key_{:05}", idx).as_bytes()))
+        .collect::<Vec<_>>();
+    let mut sizes = vec![0; 10];
+    for &hash in &hashes {
+        assert_eq!(jump_consistent_hash(hash, 1), 0);
+        sizes[jump_consistent_hash(hash, 10) as usize] += 1;
+        // Adding a bucket only moves keys to the new bucket
+        let before = jump_consistent_hash(hash, 10);
+        let after = jump_consistent_hash(hash, 11);
+        assert!(after == before || after == 10);
+    }
+    assert!(
+        sizes.iter().all(|&size| (800..1200).contains(&size)),
+        "{:?}",
+        sizes
+    );
+    let moved = hashes
+        .iter()
+        .filter(|&&hash| jump_consistent_hash(hash, 11) == 10)
+        .count();
+    assert!((700..1100).contains(&moved), "{}", moved);
+}
+
+#[test]
+fn test_key_range_stats() {
+    let options = LsmStorageOptions {
+        key_range_stats_boundaries: vec![Bytes::from_static(b"d"), Bytes::from_static(b"b")],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        key_range_stats_boundaries: vec![Bytes::from_static(b"b"), Bytes::from_static(b"d")],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a1", b"v").unwrap();
+    storage.put(b"b", b"value").unwrap();
+    storage.put(b"c1", b"v").unwrap();
+    storage.delete(b"c2").unwrap();
+    storage.get(b"a1").unwrap();
+    storage.get(b"zz").unwrap();
+    storage.get(b"zzz").unwrap();
+    storage
+        .scan(Bound::Included(b"bb"), Bound::Unbounded)
+        .unwrap();
+    storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+
+    let range =
+        |lower: &'static [u8], upper: Option<&'static [u8]>, reads, writes, bytes| KeyRangeStats {
+            lower: Bytes::from_static(lower),
+            upper: upper.map(Bytes::from_static),
+            reads,
+            writes,
+            bytes_written: bytes,
+        };
+    assert_eq!(
+        storage.metrics().key_ranges,
+        vec![
+            range(b"", Some(b"b"), 2, 1, 3),
+            range(b"b", Some(b"d"), 1, 3, 6 + 3 + 2),
+            range(b"d", None, 2, 0, 0),
+        ]
+    );
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_sample_keys() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..20000 {
+        storage
+            .put(&key_of(idx), format!("value_{}", idx).as_bytes())
+            .unwrap();
+        if idx % 5000 == 4999 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.put(b"in the memtable", b"").unwrap();
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let keys = storage
+        .inner
+        .sample_keys_with_rng(50, Bound::Unbounded, Bound::Unbounded, &mut rng)
+        .unwrap();
+    // The SSTs do not overlap, so each sampled block gives a distinct key
+    assert_eq!(keys.len(), 50);
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(keys.iter().all(|key| key.starts_with(b"key_")));
+    let first_quarter = keys.iter().filter(|key| key[..] < key_of(5000)[..]).count();
+    let expected = keys.len() / 4;
+    assert!(
+        first_quarter > expected / 2 && first_quarter < expected * 2,
+        "{} of {} keys in the first quarter",
+        first_quarter,
+        keys.len()
+    );
+
+    let (lower, upper) = (key_of(1234), key_of(1300));
+    let keys = storage
+        .inner
+        .sample_keys_with_rng(
+            10,
+            Bound::Excluded(&lower),
+            Bound::Included(&upper),
+            &mut rng,
+        )
+        .unwrap();
+    assert!(!keys.is_empty());
+    assert!(
+        keys.iter()
+            .all(|key| key[..] > lower[..] && key[..] <= upper[..])
+    );
+
+    assert!(
+        storage
+            .sample_keys(10, Bound::Included(b"zzz"), Bound::Unbounded)
+            .unwrap()
+            .is_empty()
+    );
+}
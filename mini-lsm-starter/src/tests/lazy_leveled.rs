@@ -0,0 +1,126 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use super::harness::put_and_flush;
+use crate::compact::{CompactionOptions, CompactionTask, LazyLeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+/// Write a round with `put_and_flush`, along with the round as the value of `latest`.
+fn put_round(storage: &LsmStorageInner, round: usize) {
+    storage
+        .put(b"latest", format!("round_{}", round).as_bytes())
+        .unwrap();
+    put_and_flush(storage, round);
+}
+
+fn run_sizes(storage: &LsmStorageInner) -> Vec<usize> {
+    let snapshot = storage.state.read();
+    snapshot
+        .levels
+        .iter()
+        .map(|(_, sst_ids)| {
+            sst_ids
+                .iter()
+                .map(|id| snapshot.sstables[id].properties().num_entries as usize)
+                .sum()
+        })
+        .collect()
+}
+
+#[test]
+fn test_lazy_leveled_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::LazyLeveled(
+        LazyLeveledCompactionOptions {
+            size_ratio: 2,
+            max_levels: 3,
+        },
+    ));
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    put_round(&storage, 0);
+    assert!(storage.explain_compaction().is_none());
+
+    // The first L0 SSTs become the last level
+    put_round(&storage, 1);
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::LazyLeveled(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.l0_sstables.len(), 2);
+    assert!(task.bottom_run_included);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(!storage.trigger_compaction().unwrap());
+    assert_eq!(run_sizes(&storage), vec![201]);
+
+    // The next L0 SSTs form a run in the tiered level above the last one, without rewriting
+    // the last level
+    put_round(&storage, 2);
+    put_round(&storage, 3);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(!storage.trigger_compaction().unwrap());
+    assert_eq!(run_sizes(&storage), vec![201, 201]);
+
+    // Once that level is full, its runs are merged into the last level
+    put_round(&storage, 4);
+    put_round(&storage, 5);
+    assert!(storage.trigger_compaction().unwrap());
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::LazyLeveled(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert!(task.l0_sstables.is_empty());
+    assert_eq!(task.runs.len(), 3);
+    assert!(task.bottom_run_included);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(!storage.trigger_compaction().unwrap());
+    assert_eq!(run_sizes(&storage), vec![601]);
+    assert!(storage.state.read().l0_sstables.is_empty());
+
+    for round in 0..6 {
+        let key = format!("key_{:02}_{:03}", round, 99);
+        assert!(storage.get(key.as_bytes()).unwrap().is_some());
+    }
+    assert_eq!(
+        storage.get(b"latest").unwrap().as_deref(),
+        Some(&b"round_5"[..])
+    );
+}
+
+#[test]
+fn test_lazy_leveled_recovery() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::LazyLeveled(
+        LazyLeveledCompactionOptions {
+            size_ratio: 2,
+            max_levels: 3,
+        },
+    ));
+    let storage = LsmStorageInner::open(&dir, options.clone()).unwrap();
+    for round in 0..4 {
+        put_round(&storage, round);
+        while storage.trigger_compaction().unwrap() {}
+    }
+    let levels = storage.state.read().levels.clone();
+    assert_eq!(levels.len(), 2);
+    drop(storage);
+
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    assert_eq!(storage.state.read().levels, levels);
+    assert_eq!(
+        storage.get(b"latest").unwrap().as_deref(),
+        Some(&b"round_3"[..])
+    );
+}
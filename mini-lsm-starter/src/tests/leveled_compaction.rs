@@ -0,0 +1,252 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::compact::{LeveledCompactionController, LeveledCompactionOptions};
+use crate::key::KeyBytes;
+use crate::lsm_storage::LsmStorageState;
+use crate::mem_table::MemTable;
+use crate::table::SsTable;
+
+const MB: u64 = 1024 * 1024;
+
+/// An SST of `size_mb` covering the keys from `first` to `last`, without a file.
+fn sst(id: usize, first: &str, last: &str, size_mb: u64) -> Arc<SsTable> {
+    Arc::new(SsTable::create_meta_only(
+        id,
+        size_mb * MB,
+        KeyBytes::for_testing_from_bytes_no_ts(Bytes::copy_from_slice(first.as_bytes())),
+        KeyBytes::for_testing_from_bytes_no_ts(Bytes::copy_from_slice(last.as_bytes())),
+    ))
+}
+
+fn state(l0: Vec<Arc<SsTable>>, levels: Vec<Vec<Arc<SsTable>>>) -> LsmStorageState {
+    let mut state = LsmStorageState {
+        memtable: Arc::new(MemTable::create(0)),
+        imm_memtables: Vec::new(),
+        l0_sstables: l0.iter().map(|sst| sst.sst_id()).collect(),
+        levels: Vec::new(),
+        sstables: Default::default(),
+    };
+    for (idx, level) in levels.iter().enumerate() {
+        state
+            .levels
+            .push((idx + 1, level.iter().map(|sst| sst.sst_id()).collect()));
+    }
+    for sst in l0.into_iter().chain(levels.into_iter().flatten()) {
+        state.sstables.insert(sst.sst_id(), sst);
+    }
+    state
+}
+
+fn controller() -> LeveledCompactionController {
+    LeveledCompactionController::new(LeveledCompactionOptions {
+        level_size_multiplier: 10,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 4,
+        base_level_size_mb: 1,
+    })
+}
+
+#[test]
+fn test_dynamic_level_sizing() {
+    let controller = controller();
+    // With nothing below L0, L0 goes straight to the bottom level
+    let snapshot = state(
+        vec![sst(1, "a", "m", 1), sst(2, "k", "z", 1)],
+        vec![vec![], vec![], vec![], vec![]],
+    );
+    let task = controller.generate_compaction_task(&snapshot).unwrap();
+    assert_eq!(task.upper_level, None);
+    assert_eq!(task.lower_level, 4);
+    assert!(task.is_lower_level_bottom_level);
+
+    // A 5MB bottom level makes L3 the base level, with a target size of 0.5MB. L0 is compacted
+    // with the L3 SSTs its keys overlap with.
+    let snapshot = state(
+        vec![sst(1, "d", "f", 1), sst(2, "e", "g", 1)],
+        vec![
+            vec![],
+            vec![],
+            vec![
+                sst(3, "a", "c", 1),
+                sst(4, "f", "h", 1),
+                sst(5, "x", "z", 1),
+            ],
+            (10..15)
+                .map(|id| sst(id, &format!("{}0", id), &format!("{}9", id), 1))
+                .collect(),
+        ],
+    );
+    let task = controller.generate_compaction_task(&snapshot).unwrap();
+    assert_eq!(task.upper_level, None);
+    assert_eq!(task.lower_level, 3);
+    assert_eq!(task.lower_level_sst_ids, vec![4]);
+
+    // Without L0 SSTs, the oldest SST of the level most over its target goes down, along with
+    // the SSTs of the next level it overlaps with
+    let snapshot = state(
+        vec![],
+        vec![
+            vec![],
+            vec![],
+            vec![sst(5, "x", "z", 1), sst(3, "a", "c", 1)],
+            vec![
+                sst(10, "a", "b", 2),
+                sst(11, "c", "d", 2),
+                sst(12, "e", "z", 2),
+            ],
+        ],
+    );
+    let task = controller.generate_compaction_task(&snapshot).unwrap();
+    assert_eq!(task.upper_level, Some(3));
+    assert_eq!(task.upper_level_sst_ids, vec![3]);
+    assert_eq!(task.lower_level_sst_ids, vec![10, 11]);
+    assert!(task.is_lower_level_bottom_level);
+
+    // The output replaces the compacted SSTs, ordered by key range
+    let mut snapshot = snapshot;
+    for (id, first, last) in [(20, "a", "a"), (21, "b", "d")] {
+        snapshot.sstables.insert(id, sst(id, first, last, 2));
+    }
+    let (new_snapshot, removed) =
+        controller.apply_compaction_result(&snapshot, &task, &[21, 20], false);
+    assert_eq!(removed, vec![3, 10, 11]);
+    assert_eq!(new_snapshot.levels[2].1, vec![5]);
+    assert_eq!(new_snapshot.levels[3].1, vec![20, 21, 12]);
+
+    // Every level is within its target
+    let snapshot = state(
+        vec![sst(1, "a", "b", 1)],
+        vec![
+            vec![],
+            vec![],
+            vec![sst(2, "a", "b", 1)],
+            vec![sst(3, "a", "z", 20)],
+        ],
+    );
+    assert!(controller.generate_compaction_task(&snapshot).is_none());
+}
+
+#[test]
+fn test_disjoint_compaction_tasks() {
+    let controller = controller();
+    // L3 is 2.5MB over its 0.5MB target, and no L3 SST overlaps with the bottom level
+    let snapshot = state(
+        vec![sst(1, "d", "f", 1), sst(2, "e", "g", 1)],
+        vec![
+            vec![],
+            vec![],
+            vec![
+                sst(3, "a", "c", 1),
+                sst(4, "f", "h", 1),
+                sst(5, "x", "z", 1),
+            ],
+            (10..15)
+                .map(|id| sst(id, &format!("{}0", id), &format!("{}9", id), 1))
+                .collect(),
+        ],
+    );
+    let first_task = controller.generate_compaction_task(&snapshot).unwrap();
+    let tasks = controller.generate_compaction_tasks(&snapshot, 4);
+    assert_eq!(tasks.len(), 3);
+    assert_eq!(tasks[0].upper_level_sst_ids, first_task.upper_level_sst_ids);
+    assert_eq!(tasks[0].lower_level_sst_ids, vec![4]);
+    // The L0 compaction takes SST 4, and two more SSTs bring L3 back to its target
+    assert_eq!(tasks[1].upper_level_sst_ids, vec![3]);
+    assert_eq!(tasks[2].upper_level_sst_ids, vec![5]);
+    assert!(tasks[1..].iter().all(|task| task.lower_level == 4));
+    assert_eq!(controller.generate_compaction_tasks(&snapshot, 2).len(), 2);
+
+    // The SSTs being compacted are skipped
+    assert!(snapshot.sstables[&3].try_claim_for_compaction());
+    let tasks = controller.generate_compaction_tasks(&snapshot, 4);
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[1].upper_level_sst_ids, vec![5]);
+    assert!(snapshot.sstables[&1].try_claim_for_compaction());
+    let tasks = controller.generate_compaction_tasks(&snapshot, 4);
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0].upper_level_sst_ids, vec![4]);
+    assert_eq!(tasks[1].upper_level_sst_ids, vec![5]);
+}
+
+/// The SST IDs of L0 and of each level.
+type Layout = (Vec<usize>, Vec<(usize, Vec<usize>)>);
+
+/// The level layout after each of `flushes` flushes of random key ranges generated from `seed`,
+/// compacting until there is no task left after each flush.
+fn simulate(seed: u64, flushes: usize) -> Vec<Layout> {
+    let controller = controller();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut snapshot = state(vec![], vec![vec![], vec![], vec![], vec![]]);
+    let mut next_sst_id = 1;
+    let mut layouts = Vec::new();
+    for _ in 0..flushes {
+        let first = rng.gen_range(0..100_000_000u64);
+        let last = first + rng.gen_range(1..100_000_000u64);
+        let (first, last) = (format!("{:010}", first), format!("{:010}", last));
+        snapshot
+            .sstables
+            .insert(next_sst_id, sst(next_sst_id, &first, &last, 1));
+        snapshot.l0_sstables.insert(0, next_sst_id);
+        next_sst_id += 1;
+
+        while let Some(task) = controller.generate_compaction_task(&snapshot) {
+            let inputs = task
+                .upper_level_sst_ids
+                .iter()
+                .chain(task.lower_level_sst_ids.iter())
+                .copied()
+                .collect::<Vec<_>>();
+            let (first, last) = snapshot.key_range(&inputs).unwrap();
+            let parse = |key: &[u8]| std::str::from_utf8(key).unwrap().parse::<u64>().unwrap();
+            let (first, last) = (parse(first.key_ref()), parse(last.key_ref()));
+            // Split the compacted key range evenly into as many SSTs as the inputs
+            let span = (last - first) / inputs.len() as u64;
+            let mut output = Vec::new();
+            for i in 0..inputs.len() as u64 {
+                let begin = format!("{:010}", first + span * i);
+                let end = format!("{:010}", first + span * (i + 1));
+                snapshot
+                    .sstables
+                    .insert(next_sst_id, sst(next_sst_id, &begin, &end, 1));
+                output.push(next_sst_id);
+                next_sst_id += 1;
+            }
+            let (new_snapshot, removed) =
+                controller.apply_compaction_result(&snapshot, &task, &output, false);
+            snapshot = new_snapshot;
+            for id in removed {
+                snapshot.sstables.remove(&id);
+            }
+        }
+        layouts.push((snapshot.l0_sstables.clone(), snapshot.levels.clone()));
+    }
+    layouts
+}
+
+#[test]
+fn test_seeded_layouts_reproducible() {
+    let layouts = simulate(0, 100);
+    // The workload is large enough to reach the bottom level
+    let (_, levels) = layouts.last().unwrap();
+    assert!(!levels[3].1.is_empty());
+    assert_eq!(simulate(0, 100), layouts);
+    assert_ne!(simulate(1, 100), layouts);
+}
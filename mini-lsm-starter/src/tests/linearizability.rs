@@ -0,0 +1,281 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Concurrent single-key reads and writes with a history recorder and a consistency checker,
+//! to catch reads that miss or resurrect values while memtables are frozen, flushed and
+//! compacted under them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[derive(Debug, Clone, Copy)]
+enum OpKind {
+    /// A put of a value unique across the history.
+    Write(u64),
+    /// A get, with the value it returned.
+    Read(Option<u64>),
+}
+
+/// An operation on one key, with the logical times it was invoked and returned at.
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    key: usize,
+    kind: OpKind,
+    invoke: u64,
+    response: u64,
+}
+
+/// Hands out the logical times of the operations of all threads.
+#[derive(Default)]
+struct HistoryClock(AtomicU64);
+
+impl HistoryClock {
+    fn record<T>(&self, f: impl FnOnce() -> T) -> (T, u64, u64) {
+        let invoke = self.0.fetch_add(1, Ordering::SeqCst);
+        let result = f();
+        let response = self.0.fetch_add(1, Ordering::SeqCst);
+        (result, invoke, response)
+    }
+}
+
+/// Check the history of a register per key that starts empty and is only written with unique
+/// values. This checks the conditions each read of a linearizable register meets, which catches
+/// stale, lost and future reads, but does not search for a total order of the operations:
+///
+/// - A read returns a value whose write was invoked before the read returned.
+/// - A read does not return a value overwritten by a write that completed before the read was
+///   invoked, and does not return nothing after a write completed.
+/// - A read does not return a value older than one returned by a read that completed before it
+///   was invoked.
+fn check_history(history: &[Op]) -> Result<(), String> {
+    let mut writes = HashMap::new();
+    let mut reads_of_key = HashMap::<usize, Vec<Op>>::new();
+    let mut writes_of_key = HashMap::<usize, Vec<Op>>::new();
+    for op in history {
+        match op.kind {
+            OpKind::Write(value) => {
+                writes.insert(value, *op);
+                writes_of_key.entry(op.key).or_default().push(*op);
+            }
+            OpKind::Read(_) => reads_of_key.entry(op.key).or_default().push(*op),
+        }
+    }
+    for (key, mut reads) in reads_of_key {
+        let mut key_writes = writes_of_key.remove(&key).unwrap_or_default();
+        key_writes.sort_by_key(|op| op.response);
+        let mut completed_reads = reads.clone();
+        completed_reads.sort_by_key(|op| op.response);
+        reads.sort_by_key(|op| op.invoke);
+        // Sweep the reads in invocation order, tracking the latest invoked write among the
+        // writes completed before the read, and the same among the writes returned by the reads
+        // completed before it
+        let (mut write_idx, mut read_idx) = (0, 0);
+        let mut latest_write: Option<Op> = None;
+        let mut latest_seen: Option<(Op, Op)> = None;
+        for read in reads {
+            while let Some(write) = key_writes
+                .get(write_idx)
+                .filter(|w| w.response < read.invoke)
+            {
+                if latest_write.is_none_or(|latest| latest.invoke < write.invoke) {
+                    latest_write = Some(*write);
+                }
+                write_idx += 1;
+            }
+            while let Some(earlier) = completed_reads
+                .get(read_idx)
+                .filter(|r| r.response < read.invoke)
+            {
+                if let OpKind::Read(Some(value)) = earlier.kind {
+                    let seen = writes[&value];
+                    if latest_seen.is_none_or(|(_, latest)| latest.invoke < seen.invoke) {
+                        latest_seen = Some((*earlier, seen));
+                    }
+                }
+                read_idx += 1;
+            }
+
+            let OpKind::Read(value) = read.kind else {
+                unreachable!()
+            };
+            let Some(value) = value else {
+                if let Some(write) = latest_write {
+                    return Err(format!("{:?} returned nothing after {:?}", read, write));
+                }
+                if let Some((earlier, _)) = latest_seen {
+                    return Err(format!("{:?} returned nothing after {:?}", read, earlier));
+                }
+                continue;
+            };
+            let write = writes
+                .get(&value)
+                .filter(|w| w.key == read.key)
+                .ok_or_else(|| format!("{:?} returned a value never written to the key", read))?;
+            if read.response < write.invoke {
+                return Err(format!(
+                    "{:?} returned the value of a later {:?}",
+                    read, write
+                ));
+            }
+            if let Some(newer) = latest_write.filter(|newer| write.response < newer.invoke) {
+                return Err(format!(
+                    "{:?} returned the value of {:?}, overwritten by {:?}",
+                    read, write, newer
+                ));
+            }
+            if let Some((earlier, _)) = latest_seen.filter(|(_, seen)| write.response < seen.invoke)
+            {
+                return Err(format!(
+                    "{:?} returned an older value than {:?} before it",
+                    read, earlier
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_history() {
+    let write = |key, value, invoke, response| Op {
+        key,
+        kind: OpKind::Write(value),
+        invoke,
+        response,
+    };
+    let read = |key, value, invoke, response| Op {
+        key,
+        kind: OpKind::Read(value),
+        invoke,
+        response,
+    };
+    let w1 = write(0, 1, 0, 1);
+    let w2 = write(0, 2, 2, 5);
+    // Reads concurrent with the second write may return either value
+    check_history(&[w1, w2, read(0, Some(1), 3, 4), read(0, Some(2), 3, 4)]).unwrap();
+    assert!(check_history(&[w1, w2, read(0, None, 2, 3)]).is_err());
+    assert!(check_history(&[w1, w2, read(0, Some(1), 6, 7)]).is_err());
+    assert!(check_history(&[w1, w2, read(0, Some(3), 6, 7), write(0, 3, 8, 9)]).is_err());
+    assert!(check_history(&[w1, w2, read(1, Some(1), 6, 7)]).is_err());
+    // Once a read saw the second write, a later read cannot see the first
+    assert!(check_history(&[w1, w2, read(0, Some(2), 3, 4), read(0, Some(1), 4, 5)]).is_ok());
+    assert!(check_history(&[w1, w2, read(0, Some(2), 3, 4), read(0, Some(1), 5, 6)]).is_err());
+}
+
+#[test]
+fn test_concurrent_get_consistency() {
+    const NUM_KEYS: usize = 16;
+    const NUM_WRITERS: u64 = 4;
+    const NUM_READERS: usize = 4;
+    const WRITES_PER_WRITER: u64 = 1000;
+
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        // Freeze a memtable every few dozen writes, and flush them in the background
+        target_sst_size: 4096,
+        num_memtable_limit: 3,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let clock = Arc::new(HistoryClock::default());
+    let done = Arc::new(AtomicBool::new(false));
+    let key_of = |key: usize| format!("key_{:03}", key);
+    let padding = [b'x'; 100];
+
+    let history = std::thread::scope(|s| {
+        let writers = (0..NUM_WRITERS)
+            .map(|writer| {
+                let (storage, clock) = (&storage, &clock);
+                s.spawn(move || {
+                    let mut history = Vec::new();
+                    for seq in 0..WRITES_PER_WRITER {
+                        let value = writer * WRITES_PER_WRITER + seq;
+                        let key = (value as usize * 7) % NUM_KEYS;
+                        let mut data = value.to_be_bytes().to_vec();
+                        data.extend_from_slice(&padding);
+                        let (result, invoke, response) =
+                            clock.record(|| storage.put(key_of(key).as_bytes(), &data));
+                        result.unwrap();
+                        history.push(Op {
+                            key,
+                            kind: OpKind::Write(value),
+                            invoke,
+                            response,
+                        });
+                    }
+                    history
+                })
+            })
+            .collect::<Vec<_>>();
+        let readers = (0..NUM_READERS)
+            .map(|reader| {
+                let (storage, clock, done) = (&storage, &clock, &done);
+                s.spawn(move || {
+                    let mut history = Vec::new();
+                    let mut key = reader;
+                    while !done.load(Ordering::Relaxed) {
+                        key = (key + 1) % NUM_KEYS;
+                        let (result, invoke, response) =
+                            clock.record(|| storage.get(key_of(key).as_bytes()));
+                        let value = result
+                            .unwrap()
+                            .map(|value: Bytes| u64::from_be_bytes(value[..8].try_into().unwrap()));
+                        history.push(Op {
+                            key,
+                            kind: OpKind::Read(value),
+                            invoke,
+                            response,
+                        });
+                    }
+                    history
+                })
+            })
+            .collect::<Vec<_>>();
+        // Swap the state under the readers with flushes and full compactions too
+        let chaos = s.spawn(|| {
+            let mut round = 0;
+            while !done.load(Ordering::Relaxed) {
+                storage.force_flush().unwrap();
+                if round % 4 == 0 {
+                    storage.force_full_compaction().unwrap();
+                }
+                round += 1;
+            }
+        });
+
+        let mut history = Vec::new();
+        for writer in writers {
+            history.extend(writer.join().unwrap());
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            history.extend(reader.join().unwrap());
+        }
+        chaos.join().unwrap();
+        history
+    });
+
+    let num_reads = history
+        .iter()
+        .filter(|op| matches!(op.kind, OpKind::Read(_)))
+        .count();
+    assert!(num_reads > 0);
+    check_history(&history).unwrap();
+}
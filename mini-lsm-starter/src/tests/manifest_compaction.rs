@@ -0,0 +1,99 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::manifest::{Manifest, ManifestRecord};
+
+#[test]
+fn test_manifest_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        max_manifest_size: 4096,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let manifest_size = || {
+        std::fs::metadata(dir.path().join("MANIFEST"))
+            .unwrap()
+            .len()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"1").unwrap();
+        storage.force_flush().unwrap();
+        if idx % 20 == 19 {
+            storage.force_full_compaction().unwrap();
+        }
+    }
+    storage.put(&key_of(100), b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete(&key_of(0)).unwrap();
+    // Grown past the limit, the compaction thread compacts the manifest
+    storage.inner.compact_manifest().unwrap();
+    let compacted_size = manifest_size();
+    assert!(compacted_size < 4096, "{} bytes", compacted_size);
+    let records = Manifest::read(dir.path().join("MANIFEST")).unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(matches!(records[1].record, ManifestRecord::Snapshot(_)));
+    // Appended after the snapshot
+    storage.put(b"unflushed", b"1").unwrap();
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    {
+        let snapshot = storage.inner.state.read();
+        assert_eq!(snapshot.levels[0].1.len(), 1);
+        assert_eq!(snapshot.l0_sstables.len(), 1);
+        assert_eq!(snapshot.imm_memtables.len(), 1);
+    }
+    assert_eq!(storage.get(&key_of(0)).unwrap(), None);
+    for idx in 1..=100 {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from_static(b"1"))
+        );
+    }
+    assert_eq!(
+        storage.get(b"unflushed").unwrap(),
+        Some(Bytes::from_static(b"1"))
+    );
+}
+
+#[test]
+fn test_manifest_compaction_fenced() {
+    let dir = tempdir().unwrap();
+    let zombie = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    zombie.put(b"key", b"1").unwrap();
+    zombie.force_flush().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"key", b"2").unwrap();
+    storage.force_flush().unwrap();
+
+    // The records of the newer epoch would be lost
+    let err = zombie.inner.compact_manifest().unwrap_err().to_string();
+    assert!(err.contains("fenced"), "{}", err);
+    storage.inner.compact_manifest().unwrap();
+    drop(zombie);
+    drop(storage);
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from_static(b"2")));
+}
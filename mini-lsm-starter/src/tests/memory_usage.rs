@@ -0,0 +1,72 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MemoryUsage, MiniLsm};
+
+#[test]
+fn test_memory_usage() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_cache_capacity: Some(1 << 20),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.memory_usage(), MemoryUsage::default());
+
+    for i in 0..1000 {
+        storage
+            .put(format!("key_{:05}", i).as_bytes(), &[b'x'; 100])
+            .unwrap();
+    }
+    let usage = storage.memory_usage();
+    assert!(usage.active_memtable >= 1000 * 109, "{:?}", usage);
+    assert_eq!(usage.total(), usage.active_memtable);
+
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    let frozen = storage.memory_usage();
+    assert_eq!(frozen.active_memtable, 0);
+    assert_eq!(frozen.immutable_memtables, usage.active_memtable);
+
+    storage.force_flush().unwrap();
+    let flushed = storage.memory_usage();
+    assert_eq!(flushed.immutable_memtables, 0);
+    assert!(flushed.table_metadata > 0);
+    assert!(flushed.bloom_filters > 0);
+
+    // The scan pins the block it reads, which stays cached after the iterator is dropped
+    let iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(iter.is_valid());
+    let scanning = storage.memory_usage();
+    assert!(scanning.pinned_blocks > 0);
+    assert!(scanning.block_cache > 0);
+    drop(iter);
+    assert_eq!(storage.memory_usage().pinned_blocks, 0);
+
+    // The buffered writes of a transaction count until it is dropped
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"key", b"value");
+    txn.put(b"key", b"longer value");
+    txn.delete(b"other");
+    assert_eq!(storage.memory_usage().txn_write_sets, 3 + 12 + 5);
+    drop(txn);
+    assert_eq!(storage.memory_usage().txn_write_sets, 0);
+}
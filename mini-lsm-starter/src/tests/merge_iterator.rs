@@ -0,0 +1,90 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::harness::{MockIterator, check_iter_result_by_key};
+use crate::iterators::{StorageIterator, merge_iterator::MergeIterator};
+
+type Run = Vec<(Bytes, Bytes)>;
+
+/// Random sorted runs, each value naming its run, and the merge expected from them where the
+/// run with the smaller index wins a duplicated key.
+fn random_runs(rng: &mut StdRng, num_runs: usize) -> (Vec<Run>, Run) {
+    let mut expected = BTreeMap::new();
+    let runs = (0..num_runs)
+        .map(|run| {
+            let num_keys = rng.gen_range(0..50);
+            let keys = (0..num_keys)
+                .map(|_| rng.gen_range(0..200))
+                .collect::<BTreeSet<_>>();
+            keys.into_iter()
+                .map(|key| {
+                    let key = Bytes::from(format!("key_{:03}", key));
+                    let value = Bytes::from(format!("run_{}", run));
+                    expected.entry(key.clone()).or_insert(value.clone());
+                    (key, value)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    (runs, expected.into_iter().collect())
+}
+
+#[test]
+fn test_merge_many_runs() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for num_runs in [0, 1, 2, 3, 5, 8, 13, 64] {
+        let (runs, expected) = random_runs(&mut rng, num_runs);
+        let iters = runs
+            .iter()
+            .map(|run| Box::new(MockIterator::new(run.clone())))
+            .collect();
+        let mut iter = MergeIterator::create(iters);
+        check_iter_result_by_key(&mut iter, expected.clone());
+
+        let iters = runs
+            .into_iter()
+            .map(|run| Box::new(MockIterator::new(run.into_iter().rev().collect())))
+            .collect();
+        let mut iter = MergeIterator::create_rev(iters);
+        check_iter_result_by_key(&mut iter, expected.into_iter().rev().collect());
+    }
+}
+
+#[test]
+fn test_merge_active_iterators() {
+    let run = |keys: &[&'static str]| {
+        let data = keys
+            .iter()
+            .map(|key| (Bytes::from(*key), Bytes::from("v")))
+            .collect();
+        Box::new(MockIterator::new(data))
+    };
+    let mut iter = MergeIterator::create(vec![run(&["a", "b"]), run(&[]), run(&["b", "c"])]);
+    assert_eq!(iter.num_active_iterators(), 2);
+    iter.next().unwrap();
+    assert_eq!(iter.key().for_testing_key_ref(), b"b");
+    iter.next().unwrap();
+    // Both runs moved past `b`
+    assert_eq!(iter.key().for_testing_key_ref(), b"c");
+    assert_eq!(iter.num_active_iterators(), 1);
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    assert_eq!(iter.num_active_iterators(), 0);
+}
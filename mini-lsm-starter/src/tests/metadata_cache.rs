@@ -0,0 +1,105 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use super::harness::check_lsm_iter_result_by_key;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key(i: usize) -> String {
+    format!("key_{:05}", i)
+}
+
+#[test]
+fn test_metadata_cache() {
+    let dir = tempdir().unwrap();
+    let capacity = 1024;
+    let options = LsmStorageOptions {
+        metadata_cache_capacity: Some(capacity),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..10 {
+        for i in (round * 100)..(round * 100 + 100) {
+            storage.put(key(i).as_bytes(), &[b'x'; 100]).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+
+    // The SSTs leave their metadata to the pool, which only holds a few of them
+    let state = storage.inner.state.read().clone();
+    assert_eq!(state.l0_sstables.len(), 10);
+    for sst in state.sstables.values() {
+        assert!(sst.block_meta.is_empty() && sst.bloom.is_none());
+        assert!(sst.num_of_blocks() > 1);
+        assert_eq!(sst.metadata_size(), 0);
+    }
+    let (index, bloom) = storage.inner.block_cache.pooled_metadata_size();
+    assert!(index > 0 && bloom > 0);
+    assert!(index + bloom <= capacity, "{} + {}", index, bloom);
+    let usage = storage.memory_usage();
+    assert_eq!((usage.table_metadata, usage.bloom_filters), (index, bloom));
+
+    // The evicted metadata is read back from the SSTs
+    for i in (0..1000).step_by(37) {
+        assert_eq!(
+            storage.get(key(i).as_bytes()).unwrap().as_deref(),
+            Some(&[b'x'; 100][..])
+        );
+    }
+    assert_eq!(storage.get(b"key_00100x").unwrap(), None);
+    let expected = (250..350)
+        .map(|i| (key(i).into(), [b'x'; 100][..].into()))
+        .collect::<Vec<_>>();
+    check_lsm_iter_result_by_key(
+        &mut storage
+            .scan(
+                Bound::Included(key(250).as_bytes()),
+                Bound::Excluded(key(350).as_bytes()),
+            )
+            .unwrap(),
+        expected,
+    );
+
+    // Compactions read the metadata of their inputs, and drop that of the deleted SSTs
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        storage.get(key(999).as_bytes()).unwrap().as_deref(),
+        Some(&[b'x'; 100][..])
+    );
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.get(key(500).as_bytes()).unwrap().as_deref(),
+        Some(&[b'x'; 100][..])
+    );
+}
+
+#[test]
+fn test_metadata_cache_capacity_validated() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        metadata_cache_capacity: Some(0),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let err = MiniLsm::open(&dir, options).err().unwrap();
+    assert!(
+        err.to_string().contains("metadata_cache_capacity"),
+        "{}",
+        err
+    );
+}
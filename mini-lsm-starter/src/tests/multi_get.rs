@@ -0,0 +1,122 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions};
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:05}", i).into_bytes()
+}
+
+#[test]
+fn test_multi_get_matches_get() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    // A level, an L0 SST and the memtable, each overwriting or deleting some of the keys
+    for i in 0..300 {
+        storage.put(&key(i), b"level").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for i in (0..300).step_by(3) {
+        storage.put(&key(i), b"l0").unwrap();
+    }
+    storage.delete_range(&key(100), &key(120)).unwrap();
+    storage.force_flush().unwrap();
+    for i in (0..300).step_by(5) {
+        storage.delete(&key(i)).unwrap();
+    }
+    for i in (0..300).step_by(7) {
+        storage.put(&key(i), b"memtable").unwrap();
+    }
+    let read_ts = storage.latest_commit_ts();
+    storage.put(&key(1), b"later").unwrap();
+
+    // Unsorted keys, with duplicates and missing ones
+    let keys = (0..320).rev().chain([7, 7, 1]).map(key).collect::<Vec<_>>();
+    let values = storage.multi_get(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(
+            *value,
+            storage.get(key).unwrap(),
+            "{:?}",
+            Bytes::from(key.clone())
+        );
+    }
+    assert_eq!(values[keys.len() - 1], Some(Bytes::from_static(b"later")));
+    assert_eq!(values[0], None);
+
+    // An older snapshot
+    let options = ReadOptions {
+        read_ts: Some(read_ts),
+        ..Default::default()
+    };
+    let values = storage.multi_get_with_options(&keys, &options).unwrap();
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(*value, storage.get_with_ts(key, read_ts).unwrap());
+    }
+    assert_eq!(values[keys.len() - 1], Some(Bytes::from_static(b"level")));
+    assert!(storage.multi_get::<&[u8]>(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_multi_get_shares_block_reads() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_cache_capacity: Some(1 << 20),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..1000 {
+        storage.put(&key(i), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let num_blocks = {
+        let snapshot = storage.inner.state.read();
+        snapshot.levels[0]
+            .1
+            .iter()
+            .map(|id| snapshot.sstables[id].num_of_blocks())
+            .sum::<usize>()
+    };
+    let block_reads = || {
+        let stats = storage.block_cache_stats();
+        stats
+            .iter()
+            .map(|stats| stats.hits + stats.misses)
+            .sum::<u64>() as usize
+    };
+
+    // Each block is read once for all the keys in it, whatever the order of the keys
+    let keys = (0..1000).rev().map(key).collect::<Vec<_>>();
+    let before = block_reads();
+    let values = storage.multi_get(&keys).unwrap();
+    assert!(
+        values
+            .iter()
+            .all(|value| value.as_deref() == Some(b"value"))
+    );
+    assert!(num_blocks < 1000 / 4, "{}", num_blocks);
+    assert_eq!(block_reads() - before, num_blocks);
+
+    let before = block_reads();
+    for key in &keys {
+        storage.get(key).unwrap();
+    }
+    assert!(block_reads() - before >= 1000);
+}
@@ -0,0 +1,247 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::check_lsm_iter_result_by_key;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mvcc::watermark::Watermark;
+
+fn scan_at(storage: &MiniLsm, read_ts: u64) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage
+        .scan_with_ts(Bound::Unbounded, Bound::Unbounded, read_ts)
+        .unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+fn entries(pairs: &[(&'static str, &'static str)]) -> Vec<(Bytes, Bytes)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (Bytes::from(*key), Bytes::from(*value)))
+        .collect()
+}
+
+#[test]
+fn test_write_timestamps() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let ts = storage.latest_commit_ts();
+    storage.put(b"a", b"1").unwrap();
+    assert_eq!(storage.latest_commit_ts(), ts + 1);
+    storage.delete(b"a").unwrap();
+    storage.delete_range(b"a", b"b").unwrap();
+    assert_eq!(storage.latest_commit_ts(), ts + 3);
+}
+
+#[test]
+fn test_snapshot_reads() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let ts1 = storage.latest_commit_ts();
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.put(b"c", b"2").unwrap();
+    let ts2 = storage.latest_commit_ts();
+    storage.delete_range(b"a", b"c").unwrap();
+    storage.put(b"d", b"3").unwrap();
+    let ts3 = storage.latest_commit_ts();
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(storage.get_with_ts(b"a", ts1).unwrap().unwrap(), "1");
+        assert_eq!(storage.get_with_ts(b"a", ts2).unwrap().unwrap(), "2");
+        assert_eq!(storage.get_with_ts(b"a", ts3).unwrap(), None);
+        assert_eq!(storage.get_with_ts(b"b", ts1).unwrap().unwrap(), "1");
+        assert_eq!(storage.get_with_ts(b"b", ts2).unwrap(), None);
+        assert_eq!(storage.get_with_ts(b"c", ts1).unwrap(), None);
+        assert_eq!(scan_at(storage, ts1), entries(&[("a", "1"), ("b", "1")]));
+        assert_eq!(scan_at(storage, ts2), entries(&[("a", "2"), ("c", "2")]));
+        assert_eq!(scan_at(storage, ts3), entries(&[("c", "2"), ("d", "3")]));
+        check_lsm_iter_result_by_key(
+            &mut storage
+                .scan_with_ts(Bound::Excluded(b"a"), Bound::Included(b"c"), ts2)
+                .unwrap(),
+            entries(&[("c", "2")]),
+        );
+    };
+    check(&storage);
+    // The snapshot of the memtable is kept when flushed
+    let snapshot = storage.snapshot().unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(snapshot.read_ts(), ts3);
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"d").unwrap().unwrap(), "3");
+}
+
+#[test]
+fn test_snapshot_reads_across_ssts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let mut read_ts = Vec::new();
+    for version in 0..3 {
+        for key in ["a", "b", "c"] {
+            storage
+                .put(key.as_bytes(), format!("{}{}", key, version).as_bytes())
+                .unwrap();
+        }
+        read_ts.push(storage.latest_commit_ts());
+        storage.force_flush().unwrap();
+    }
+    storage.delete(b"b").unwrap();
+    for (version, read_ts) in read_ts.into_iter().enumerate() {
+        for key in ["a", "b", "c"] {
+            let expected = format!("{}{}", key, version);
+            assert_eq!(
+                storage
+                    .get_with_ts(key.as_bytes(), read_ts)
+                    .unwrap()
+                    .unwrap(),
+                expected
+            );
+        }
+    }
+    assert_eq!(storage.get(b"b").unwrap(), None);
+
+    // Without readers, the compaction only keeps the latest versions
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let latest = storage.latest_commit_ts();
+    assert_eq!(
+        scan_at(&storage, latest),
+        entries(&[("a", "a2"), ("c", "c2")])
+    );
+    assert_eq!(scan_at(&storage, 1), Vec::new());
+}
+
+#[test]
+fn test_commit_ts_recovery() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    let ts1 = storage.latest_commit_ts();
+    storage.put(b"a", b"2").unwrap();
+    let ts2 = storage.latest_commit_ts();
+    drop(storage);
+
+    // The timestamps of the WAL and of the SST are recovered
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.latest_commit_ts(), ts2);
+    assert_eq!(storage.get_with_ts(b"a", ts1).unwrap().unwrap(), "1");
+    assert_eq!(storage.get(b"a").unwrap().unwrap(), "2");
+    storage.put(b"a", b"3").unwrap();
+    assert_eq!(storage.latest_commit_ts(), ts2 + 1);
+    assert_eq!(storage.get_with_ts(b"a", ts2).unwrap().unwrap(), "2");
+}
+
+#[test]
+fn test_watermark() {
+    let mut watermark = Watermark::new();
+    assert_eq!(watermark.watermark(), None);
+    watermark.add_reader(3);
+    watermark.add_reader(2);
+    watermark.add_reader(2);
+    assert_eq!(watermark.watermark(), Some(2));
+    watermark.remove_reader(2);
+    assert_eq!(watermark.watermark(), Some(2));
+    watermark.remove_reader(2);
+    assert_eq!(watermark.watermark(), Some(3));
+    assert_eq!(watermark.num_retained_snapshots(), 1);
+    watermark.remove_reader(3);
+    assert_eq!(watermark.watermark(), None);
+}
+
+#[test]
+fn test_snapshot_keeps_versions() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let snapshot = storage.snapshot().unwrap();
+    let read_ts = snapshot.read_ts();
+    let cloned = snapshot.clone();
+    drop(snapshot);
+    assert_eq!(storage.inner.mvcc().watermark(), read_ts);
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.force_flush_all().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.get_with_ts(b"a", read_ts).unwrap().unwrap(), "1");
+    assert_eq!(storage.get_with_ts(b"b", read_ts).unwrap().unwrap(), "1");
+    assert_eq!(storage.get(b"a").unwrap().unwrap(), "2");
+    assert_eq!(storage.get(b"b").unwrap(), None);
+
+    // Once the snapshot is dropped, the compaction only keeps the latest versions
+    drop(cloned);
+    assert_eq!(storage.inner.mvcc().watermark(), storage.latest_commit_ts());
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.get_with_ts(b"a", read_ts).unwrap(), None);
+    assert_eq!(storage.get_with_ts(b"b", read_ts).unwrap(), None);
+    assert_eq!(
+        scan_at(&storage, storage.latest_commit_ts()),
+        entries(&[("a", "2")])
+    );
+}
+
+#[test]
+fn test_range_delete_above_watermark() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        verify_bottom_level_compaction: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let snapshot = storage.snapshot().unwrap();
+    storage.delete_range(b"b", b"d").unwrap();
+    storage.put(b"c", b"2").unwrap();
+    storage.delete_range(b"c", b"e").unwrap();
+    storage.force_flush().unwrap();
+
+    // The tombstones are merged with the versions they delete, which stay deleted
+    let latest = storage.latest_commit_ts();
+    for _ in 0..2 {
+        storage.force_full_compaction().unwrap();
+        assert_eq!(scan_at(&storage, latest), entries(&[("a", "1")]));
+        assert_eq!(
+            scan_at(&storage, snapshot.read_ts()),
+            entries(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")])
+        );
+        assert_eq!(storage.get(b"c").unwrap(), None);
+        assert_eq!(storage.get_with_ts(b"c", latest - 1).unwrap().unwrap(), "2");
+    }
+    drop(snapshot);
+    storage.force_full_compaction().unwrap();
+    assert_eq!(scan_at(&storage, latest), entries(&[("a", "1")]));
+}
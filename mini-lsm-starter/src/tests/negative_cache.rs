@@ -0,0 +1,116 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord};
+use crate::negative_cache::{NegativeCache, NegativeCacheStats};
+
+fn open(dir: &tempfile::TempDir) -> Arc<MiniLsm> {
+    let options = LsmStorageOptions {
+        negative_cache_capacity: Some(1000),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    MiniLsm::open(dir, options).unwrap()
+}
+
+#[test]
+fn test_negative_cache() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    let hits = |storage: &MiniLsm| storage.metrics().negative_cache.unwrap().hits;
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(hits(&storage), 1);
+
+    storage.put(b"a", b"1").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    storage.delete(b"a").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    storage.force_flush().unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(hits(&storage), 2);
+
+    storage
+        .write_batch(&[WriteBatchRecord::Put(b"a", b"2")])
+        .unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(
+        storage.metrics().negative_cache,
+        Some(NegativeCacheStats {
+            hits: 2,
+            entries: 0
+        })
+    );
+
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.metrics().negative_cache, None);
+}
+
+#[test]
+fn test_negative_cache_raced_write() {
+    let cache = NegativeCache::new(100);
+    // A get starts at sequence 5, and a write to the key is applied before the get caches that
+    // the key is absent
+    cache.invalidate(b"a", 6);
+    cache.insert(b"a", 5);
+    assert!(!cache.contains(b"a"));
+    cache.insert(b"a", 6);
+    assert!(cache.contains(b"a"));
+    cache.invalidate_all(7);
+    assert!(!cache.contains(b"a"));
+    cache.insert(b"b", 6);
+    assert!(!cache.contains(b"b"));
+}
+
+#[test]
+fn test_negative_cache_concurrent_writes() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    let written = Arc::new(AtomicUsize::new(0));
+    let readers = (0..4)
+        .map(|_| {
+            let storage = storage.clone();
+            let written = written.clone();
+            std::thread::spawn(move || {
+                while written.load(Ordering::Acquire) < 2000 {
+                    let written = written.load(Ordering::Acquire);
+                    // Probe the key being written, and check one already written
+                    storage.get(&key_of(written)).unwrap();
+                    if written > 0 {
+                        let idx = written - 1;
+                        assert!(storage.get(&key_of(idx)).unwrap().is_some(), "key {}", idx);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for idx in 0..2000 {
+        storage.put(&key_of(idx), b"value").unwrap();
+        written.store(idx + 1, Ordering::Release);
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    for idx in 0..2000 {
+        assert!(storage.get(&key_of(idx)).unwrap().is_some(), "key {}", idx);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::compact::{
+    CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions,
+    TieredCompactionOptions,
+};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::options_file::StoredOptions;
+
+fn leveled() -> CompactionOptions {
+    CompactionOptions::Leveled(LeveledCompactionOptions {
+        level_size_multiplier: 2,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 4,
+        base_level_size_mb: 1,
+    })
+}
+
+fn tiered(min_merge_width: usize) -> CompactionOptions {
+    CompactionOptions::Tiered(TieredCompactionOptions {
+        num_tiers: 3,
+        max_size_amplification_percent: 200,
+        size_ratio: 1,
+        min_merge_width,
+        max_merge_width: None,
+    })
+}
+
+fn open_err(options: LsmStorageOptions) -> String {
+    let dir = tempdir().unwrap();
+    MiniLsm::open(&dir, options).err().unwrap().to_string()
+}
+
+#[test]
+fn test_invalid_options() {
+    let err = open_err(LsmStorageOptions::default_for_week2_test(tiered(1)));
+    assert!(err.contains("min_merge_width"), "{}", err);
+    let err = open_err(LsmStorageOptions {
+        seek_compaction_threshold: Some(10),
+        ..LsmStorageOptions::default_for_week2_test(tiered(2))
+    });
+    assert!(
+        err.contains("seek_compaction_threshold is not supported with tiered compaction"),
+        "{}",
+        err
+    );
+    let err = open_err(LsmStorageOptions {
+        strict_capacity_limit: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    });
+    assert!(err.contains("requires block_cache_capacity"), "{}", err);
+}
+
+#[test]
+fn test_compaction_layout_migration() {
+    let dir = tempdir().unwrap();
+    let storage =
+        MiniLsm::open(&dir, LsmStorageOptions::default_for_week2_test(leveled())).unwrap();
+    drop(storage);
+    let stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.compaction_options.style(), "leveled");
+
+    let err = MiniLsm::open(&dir, LsmStorageOptions::default_for_week2_test(tiered(2)))
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        err.contains("compaction style changed from leveled to tiered"),
+        "{}",
+        err
+    );
+    // The failed open leaves the OPTIONS file as it was
+    let stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.compaction_options.style(), "leveled");
+
+    let options = LsmStorageOptions {
+        migrate_compaction_layout: true,
+        ..LsmStorageOptions::default_for_week2_test(tiered(2))
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    drop(storage);
+    let stored = StoredOptions::read(dir.path()).unwrap().unwrap();
+    assert_eq!(stored.compaction_options.style(), "tiered");
+    let storage =
+        MiniLsm::open(&dir, LsmStorageOptions::default_for_week2_test(tiered(2))).unwrap();
+    drop(storage);
+}
+
+#[test]
+fn test_compact_into_layout() {
+    let dir = tempdir().unwrap();
+    let compaction_options = CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger: 100,
+        max_levels: 3,
+    });
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(compaction_options.clone()),
+    )
+    .unwrap();
+    for i in 0..4 {
+        storage.put(format!("key_{}", i).as_bytes(), b"1").unwrap();
+        storage.put(b"key_0", format!("{}", i).as_bytes()).unwrap();
+        storage.force_flush().unwrap();
+    }
+    storage.delete(b"key_1").unwrap();
+    storage.force_flush().unwrap();
+    storage
+        .inner
+        .compact_into_layout(&storage.inner.compaction_lock.lock(), &compaction_options)
+        .unwrap();
+
+    let snapshot = storage.inner.state.read().clone();
+    assert!(snapshot.l0_sstables.is_empty());
+    assert!(snapshot.levels[0].1.is_empty() && snapshot.levels[1].1.is_empty());
+    assert_eq!(snapshot.levels[2].1.len(), 1);
+    assert_eq!(snapshot.sstables.len(), 1);
+    assert_eq!(&storage.get(b"key_0").unwrap().unwrap()[..], b"3");
+    assert_eq!(storage.get(b"key_1").unwrap(), None);
+    assert_eq!(&storage.get(b"key_3").unwrap().unwrap()[..], b"1");
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_prefetch_cancelled_at_end_bound() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        scan_readahead: 8,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = |i: usize| format!("key_{:05}", i);
+    for i in 0..1000 {
+        storage.put(key(i).as_bytes(), &[b'x'; 100]).unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert!(
+        storage
+            .inner
+            .state
+            .read()
+            .sstables
+            .values()
+            .next()
+            .unwrap()
+            .num_of_blocks()
+            > 9
+    );
+
+    // The scan ends in the first block, so the 8 blocks queued after it are not needed
+    let mut iter = storage
+        .scan(
+            Bound::Included(key(0).as_bytes()),
+            Bound::Included(key(5).as_bytes()),
+        )
+        .unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 6);
+    let start = Instant::now();
+    loop {
+        let stats = storage.prefetch_stats().unwrap();
+        assert!(stats.wasted_bytes <= stats.prefetched_bytes);
+        if stats.cancelled_blocks > 0 || stats.wasted_bytes > 0 {
+            break;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    drop(iter);
+
+    // A full scan reads everything it prefetched. Halfway through, it has queued blocks that the
+    // first scan did not load, so wait for the worker to prefetch them before reading on.
+    let prefetched_bytes = storage.prefetch_stats().unwrap().prefetched_bytes;
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        if count == 500 {
+            let start = Instant::now();
+            while storage.prefetch_stats().unwrap().prefetched_bytes == prefetched_bytes {
+                assert!(start.elapsed() < Duration::from_secs(5));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+
+    let storage = MiniLsm::open(
+        dir.path().join("no_readahead"),
+        LsmStorageOptions::default_for_week1_test(),
+    )
+    .unwrap();
+    assert_eq!(storage.prefetch_stats(), None);
+}
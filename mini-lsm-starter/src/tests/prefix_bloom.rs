@@ -0,0 +1,114 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::prefix_extractor::{FixedPrefix, PrefixExtractor};
+use crate::table::{SsTable, SsTableBuilder};
+
+fn collect(storage: &MiniLsm, prefix: &[u8]) -> Vec<Bytes> {
+    let mut iter = storage.scan_prefix(prefix).unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    keys
+}
+
+#[test]
+fn test_sst_prefix_bloom() {
+    let dir = tempdir().unwrap();
+    let extractor: Arc<dyn PrefixExtractor> = Arc::new(FixedPrefix(4));
+    let mut builder = SsTableBuilder::new(128).with_prefix_extractor(extractor.clone());
+    for tenant in ["aaaa", "bbbb", "dddd"] {
+        for idx in 0..50 {
+            builder.add(
+                crate::key::KeySlice::for_testing_from_slice_no_ts(
+                    format!("{}{:03}", tenant, idx).as_bytes(),
+                ),
+                b"value",
+            );
+        }
+    }
+    builder.add(
+        crate::key::KeySlice::for_testing_from_slice_no_ts(b"e"),
+        b"no prefix",
+    );
+    let path = dir.path().join("1.sst");
+    drop(builder.build_for_test(&path).unwrap());
+
+    let sst = SsTable::open_path(&path).unwrap();
+    assert_eq!(
+        sst.properties().prefix_extractor.as_deref(),
+        Some("fixed:4")
+    );
+    for prefix in ["aaaa", "bbbb", "dddd"] {
+        assert!(sst.may_contain_prefix(prefix.as_bytes(), extractor.as_ref()));
+    }
+    let absent = (0..100)
+        .filter(|idx| {
+            !sst.may_contain_prefix(format!("c{:03}", idx).as_bytes(), extractor.as_ref())
+        })
+        .count();
+    assert!(absent > 90, "{} absent prefixes ruled out", absent);
+    // Another extractor cannot rule out any prefix
+    assert!(sst.may_contain_prefix(b"cc", &FixedPrefix(2)));
+}
+
+#[test]
+fn test_scan_prefix() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.prefix_extractor = Some(Arc::new(FixedPrefix(4)));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // One SST for each tenant
+    for tenant in ["aaaa", "bbbb", "cccc", "dddd"] {
+        for idx in 0..20 {
+            storage
+                .put(format!("{}{:02}", tenant, idx).as_bytes(), b"value")
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.put(b"bbbb99", b"in the memtable").unwrap();
+    storage.delete(b"cccc05").unwrap();
+    storage.put(b"b", b"shorter than the prefix").unwrap();
+
+    let keys = collect(&storage, b"bbbb");
+    assert_eq!(keys.len(), 21);
+    assert!(keys.iter().all(|key| key.starts_with(b"bbbb")));
+    assert_eq!(collect(&storage, b"cccc").len(), 19);
+    assert_eq!(collect(&storage, b"bbbb1").len(), 10);
+    assert_eq!(collect(&storage, b"b").len(), 22);
+    assert!(collect(&storage, b"eeee").is_empty());
+    assert_eq!(collect(&storage, b"").len(), 81);
+
+    let state = storage.inner.state.read().clone();
+    let extractor = FixedPrefix(4);
+    let ssts_with = |prefix: &[u8]| {
+        state
+            .l0_sstables
+            .iter()
+            .filter(|id| state.sstables[*id].may_contain_prefix(prefix, &extractor))
+            .count()
+    };
+    assert_eq!(ssts_with(b"bbbb"), 1);
+    assert_eq!(ssts_with(b"aaab"), 0);
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::quota::{QuotaExceeded, TenantQuota, TenantUsage};
+
+#[test]
+fn test_tenant_quotas() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        tenant_quotas: vec![
+            TenantQuota {
+                prefix: Bytes::from_static(b"a/"),
+                limit: Some(100),
+            },
+            TenantQuota {
+                prefix: Bytes::from_static(b"a/b/"),
+                limit: None,
+            },
+        ],
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let value = [b'x'; 50];
+    storage.put(b"a/1", &value).unwrap();
+    storage.put(b"a/2", &value).unwrap();
+    storage.put(b"a/b/1", &value).unwrap();
+    storage.put(b"c/1", &value).unwrap();
+    assert_eq!(
+        storage.tenant_usage(b"a/"),
+        Some(TenantUsage {
+            bytes_written: 106,
+            bytes_stored: 106
+        })
+    );
+    assert_eq!(storage.tenant_usage(b"a/b/").unwrap().bytes_stored, 55);
+    assert_eq!(storage.tenant_usage(b"c/"), None);
+
+    let err = storage.put(b"a/3", &value).unwrap_err();
+    let err = err.downcast_ref::<QuotaExceeded>().unwrap();
+    assert_eq!(err.limit, 100);
+    assert_eq!(err.bytes_stored, 106);
+    // Other tenants and deletes are not affected
+    storage.put(b"a/b/2", &value).unwrap();
+    storage.delete(b"a/1").unwrap();
+    assert_eq!(storage.get(b"a/3").unwrap(), None);
+
+    // Only the latest version of a/1 is flushed
+    storage.force_flush().unwrap();
+    let usage = storage.tenant_usage(b"a/").unwrap();
+    assert_eq!(usage.bytes_written, 109);
+    assert_eq!(usage.bytes_stored, 56);
+    storage.put(b"a/3", &value).unwrap();
+
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.tenant_usage(b"a/").unwrap().bytes_stored, 106);
+    assert_eq!(storage.tenant_usage(b"a/b/").unwrap().bytes_stored, 110);
+    assert_eq!(storage.tenant_usages().len(), 2);
+}
@@ -0,0 +1,157 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::range_export::RangeExport;
+
+fn value_of(idx: usize, version: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}_{}", idx, version))
+}
+
+#[test]
+fn test_export_import_range() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        dir.path().join("source"),
+        LsmStorageOptions::default_for_week1_test(),
+    )
+    .unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 100..200 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (50..250).step_by(3) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.delete(&key_of(101)).unwrap();
+    storage.force_flush().unwrap();
+    // Left in the memtable
+    storage.put(&key_of(102), &value_of(102, 2)).unwrap();
+    let expected = |idx: usize| match idx {
+        101 => None,
+        102 => Some(value_of(102, 2)),
+        _ if (50..250).contains(&idx) && (idx - 50).is_multiple_of(3) => Some(value_of(idx, 1)),
+        _ if idx < 200 => Some(value_of(idx, 0)),
+        _ => None,
+    };
+
+    // Split the keys into two shards at key 100
+    let export = storage
+        .export_range(&key_of(100), &key_of(1000), dir.path().join("upper"))
+        .unwrap();
+    assert_eq!(export, RangeExport::read(dir.path().join("upper")).unwrap());
+    // Only the SST with 50..250 crosses a bound, the flushed memtable and the SST with 100..200
+    // are copied
+    assert_eq!(
+        export
+            .ssts
+            .iter()
+            .map(|sst| sst.rewritten)
+            .collect::<Vec<_>>(),
+        vec![false, true, false]
+    );
+    storage
+        .export_range(&key_of(0), &key_of(100), dir.path().join("lower"))
+        .unwrap();
+    assert!(
+        storage
+            .export_range(&key_of(0), &key_of(100), dir.path().join("lower"))
+            .is_err()
+    );
+
+    let upper = MiniLsm::open(
+        dir.path().join("upper_shard"),
+        LsmStorageOptions::default_for_week1_test(),
+    )
+    .unwrap();
+    upper.import_range(dir.path().join("upper")).unwrap();
+    for idx in 0..300 {
+        let expected = if idx >= 100 { expected(idx) } else { None };
+        assert_eq!(upper.get(&key_of(idx)).unwrap(), expected, "key {}", idx);
+    }
+    // Importing the same range twice would shadow the writes made after the first import
+    upper.put(&key_of(150), b"new").unwrap();
+    assert!(upper.import_range(dir.path().join("upper")).is_err());
+
+    // Merge the two shards back
+    upper.import_range(dir.path().join("lower")).unwrap();
+    for idx in 0..300 {
+        let expected = if idx == 150 {
+            Some(Bytes::from_static(b"new"))
+        } else {
+            expected(idx)
+        };
+        assert_eq!(upper.get(&key_of(idx)).unwrap(), expected, "key {}", idx);
+    }
+    assert!(upper.import_range(dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_export_range_keeps_range_tombstones() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        dir.path().join("source"),
+        LsmStorageOptions::default_for_week1_test(),
+    )
+    .unwrap();
+    for key in [b"a1", b"m1", b"m2", b"z1"] {
+        storage.put(key, b"v").unwrap();
+    }
+    storage.force_flush().unwrap();
+    // Crosses both bounds of the export, and is rewritten
+    storage.delete_range(b"l", b"o").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"m2", b"w").unwrap();
+    // Inside the range, and copied whole
+    storage.delete_range(b"m3", b"m4").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"m3", b"v").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete_range(b"m3", b"m4").unwrap();
+    storage.force_flush().unwrap();
+
+    let export = storage
+        .export_range(b"m", b"n", dir.path().join("export"))
+        .unwrap();
+    assert!(export.ssts.iter().any(|sst| sst.rewritten));
+    assert!(export.ssts.iter().any(|sst| !sst.rewritten));
+    let destination = MiniLsm::open(
+        dir.path().join("destination"),
+        LsmStorageOptions::default_for_week1_test(),
+    )
+    .unwrap();
+    for key in [b"l1", b"n1"] {
+        destination.put(key, b"x").unwrap();
+    }
+    destination.import_range(dir.path().join("export")).unwrap();
+    let v = |value: &'static [u8]| Some(Bytes::from_static(value));
+    for _ in 0..2 {
+        assert_eq!(destination.get(b"m1").unwrap(), None);
+        assert_eq!(destination.get(b"m2").unwrap(), v(b"w"));
+        assert_eq!(destination.get(b"m3").unwrap(), None);
+        // The tombstones do not reach past the range
+        assert_eq!(destination.get(b"l1").unwrap(), v(b"x"));
+        assert_eq!(destination.get(b"n1").unwrap(), v(b"x"));
+        destination.force_flush().unwrap();
+        destination.force_full_compaction().unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteOptions};
+
+#[test]
+fn test_write_rate_limit() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        write_rate_limit: Some(20_000),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let value = [b'x'; 996];
+    let rate_limited = WriteOptions {
+        rate_limited: true,
+        ..Default::default()
+    };
+
+    // The first second worth of bytes is written without waiting
+    let start = Instant::now();
+    for idx in 0..30 {
+        let key = format!("{:04}", idx);
+        storage
+            .put_with_options(key.as_bytes(), &value, &rate_limited)
+            .unwrap();
+    }
+    assert!(start.elapsed() >= Duration::from_millis(400));
+
+    // Other writers are not throttled
+    let start = Instant::now();
+    for idx in 0..100 {
+        let key = format!("{:04}", idx);
+        storage.put(key.as_bytes(), &value).unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    storage.set_write_rate_limit(None);
+    assert_eq!(storage.write_rate_limit(), None);
+    let start = Instant::now();
+    for idx in 0..100 {
+        let key = format!("{:04}", idx);
+        storage
+            .put_with_options(key.as_bytes(), &value, &rate_limited)
+            .unwrap();
+    }
+    storage.delete_with_options(b"0000", &rate_limited).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert_eq!(storage.get(b"0000").unwrap(), None);
+}
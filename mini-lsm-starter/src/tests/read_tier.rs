@@ -0,0 +1,87 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{
+    LsmStorageInner, LsmStorageOptions, ReadOptions, ReadTier, is_would_block,
+};
+
+fn read_options(read_tier: ReadTier) -> ReadOptions {
+    ReadOptions {
+        read_tier,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_memtable_only_read() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"3", b"2333").unwrap();
+    storage
+        .force_freeze_memtable(&storage.state_lock.lock())
+        .unwrap();
+    storage.put(b"2", b"23").unwrap();
+
+    let memtable_only = read_options(ReadTier::MemtableOnly);
+    assert_eq!(
+        storage.get_with_options(b"1", &memtable_only).unwrap(),
+        Some(Bytes::from_static(b"233"))
+    );
+    assert_eq!(
+        storage.get_with_options(b"2", &memtable_only).unwrap(),
+        Some(Bytes::from_static(b"23"))
+    );
+
+    storage.force_flush_next_imm_memtable().unwrap();
+    let err = storage.get_with_options(b"1", &memtable_only).unwrap_err();
+    assert!(is_would_block(&err));
+    // keys outside of the SST key range can still be answered
+    assert_eq!(
+        storage.get_with_options(b"4", &memtable_only).unwrap(),
+        None
+    );
+    assert_eq!(storage.get(b"1").unwrap(), Some(Bytes::from_static(b"233")));
+}
+
+#[test]
+fn test_block_cache_only_read() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"3", b"2333").unwrap();
+    storage.delete(b"2").unwrap();
+    storage
+        .force_freeze_memtable(&storage.state_lock.lock())
+        .unwrap();
+    storage.force_flush_next_imm_memtable().unwrap();
+
+    let cache_only = read_options(ReadTier::BlockCacheOnly);
+    let err = storage.get_with_options(b"3", &cache_only).unwrap_err();
+    assert!(is_would_block(&err));
+
+    // a normal read populates the block cache
+    assert_eq!(
+        storage.get(b"3").unwrap(),
+        Some(Bytes::from_static(b"2333"))
+    );
+    assert_eq!(
+        storage.get_with_options(b"3", &cache_only).unwrap(),
+        Some(Bytes::from_static(b"2333"))
+    );
+    assert_eq!(storage.get_with_options(b"2", &cache_only).unwrap(), None);
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::key_of;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::scan_chunks::{decode_chunk, frame_size};
+
+#[test]
+fn test_scan_chunks() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let value_of = |idx: usize| Bytes::from(vec![b'v'; idx % 50]);
+    for idx in 0..300 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(&key_of(1000), &vec![b'x'; 1000]).unwrap();
+    storage.delete(&key_of(7)).unwrap();
+    let mut expected = (0..300)
+        .filter(|&idx| idx != 7)
+        .map(|idx| (key_of(idx), value_of(idx)))
+        .collect::<Vec<_>>();
+    expected.push((key_of(1000), Bytes::from(vec![b'x'; 1000])));
+
+    let mut entries = Vec::new();
+    let mut chunks = 0;
+    for chunk in storage
+        .scan_chunks(Bound::Unbounded, Bound::Unbounded, 256)
+        .unwrap()
+    {
+        let chunk = chunk.unwrap();
+        let decoded = decode_chunk(&chunk).unwrap();
+        assert!(!decoded.is_empty());
+        // Only an entry larger than the limit gets a larger chunk
+        assert!(chunk.len() <= 256 || decoded.len() == 1);
+        // Each chunk holds as many entries as fit
+        if let Some((key, value)) = expected.get(entries.len() + decoded.len()) {
+            assert!(chunk.len() + frame_size(key, value) > 256);
+        }
+        entries.extend(decoded);
+        chunks += 1;
+    }
+    assert_eq!(entries, expected);
+    assert!(chunks > 10);
+
+    let chunks = storage
+        .scan_chunks(
+            Bound::Excluded(b"key_00005"),
+            Bound::Included(b"key_00009"),
+            1 << 20,
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 1);
+    let chunk = chunks[0].as_ref().unwrap();
+    assert_eq!(decode_chunk(chunk).unwrap(), expected[6..9].to_vec());
+    assert!(decode_chunk(&chunk.slice(..chunk.len() - 1)).is_err());
+    let empty = storage.scan_chunks(Bound::Excluded(b"z"), Bound::Unbounded, 256);
+    assert_eq!(empty.unwrap().count(), 0);
+}
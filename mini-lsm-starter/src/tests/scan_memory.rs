@@ -0,0 +1,104 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::scan_memory::{ScanBudgetAction, ScanMemoryBudget};
+
+fn open_with_budget(
+    dir: &std::path::Path,
+    budget: Option<ScanMemoryBudget>,
+) -> std::sync::Arc<MiniLsm> {
+    let options = LsmStorageOptions {
+        target_sst_size: 8192,
+        scan_memory_budget: budget,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(dir, options).unwrap();
+    let key = |i: usize| format!("key_{:05}", i);
+    for i in 0..1000 {
+        storage.put(key(i).as_bytes(), &[b'x'; 100]).unwrap();
+        if i % 100 == 99 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.force_full_compaction().unwrap();
+    // Newer versions in L0 take precedence over the level
+    storage.put(key(500).as_bytes(), b"new").unwrap();
+    storage.force_flush().unwrap();
+    storage
+}
+
+#[test]
+fn test_scan_memory_budget_one_block_per_level() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_budget(
+        dir.path(),
+        Some(ScanMemoryBudget {
+            bytes: 3 * 4096,
+            on_exceeded: ScanBudgetAction::OneBlockPerLevel,
+        }),
+    );
+    assert!(storage.inner.state.read().levels[0].1.len() > 3);
+
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        if iter.key() == b"key_00500" {
+            assert_eq!(iter.value(), b"new");
+        }
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+    // One block of the L0 SST and one of the level
+    let memory = iter.memory().unwrap();
+    assert!(memory.peak() <= 2 * 4096, "{}", memory.peak());
+    drop(iter);
+
+    let mut iter = storage
+        .scan(Bound::Excluded(b"key_00300"), Bound::Included(b"key_00302"))
+        .unwrap();
+    assert_eq!(iter.key(), b"key_00301");
+    iter.next().unwrap();
+    assert_eq!(iter.key(), b"key_00302");
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_scan_memory_budget_fail() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_budget(
+        dir.path(),
+        Some(ScanMemoryBudget {
+            bytes: 4096,
+            on_exceeded: ScanBudgetAction::Fail,
+        }),
+    );
+    let err = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("memory budget"), "{}", err);
+    // A narrow scan only reads a few SSTs
+    let iter = storage
+        .scan(Bound::Included(b"key_00300"), Bound::Included(b"key_00302"))
+        .unwrap();
+    assert_eq!(iter.key(), b"key_00300");
+}
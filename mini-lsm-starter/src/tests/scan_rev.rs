@@ -0,0 +1,176 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::{generate_sst, key_of};
+use crate::block::{BlockBuilder, BlockIterator};
+use crate::compact::CompactionOptions;
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::table::SsTableIterator;
+
+#[test]
+fn test_block_and_sst_prev() {
+    let mut builder = BlockBuilder::new(10000);
+    for idx in 0..100 {
+        assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(&key_of(idx)), b"v"));
+    }
+    let mut iter = BlockIterator::create_and_seek_to_last(Arc::new(builder.build()));
+    for idx in (0..100).rev() {
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx));
+        iter.prev();
+    }
+    assert!(!iter.is_valid());
+
+    let dir = tempdir().unwrap();
+    let data = (0..100)
+        .map(|idx| (key_of(idx), Bytes::from(format!("value_{}", idx))))
+        .collect();
+    let sst = Arc::new(generate_sst(1, dir.path().join("1.sst"), data, None));
+    assert!(sst.num_of_blocks() > 1);
+    let mut iter = SsTableIterator::create_and_seek_to_last(sst.clone()).unwrap();
+    for idx in (0..100).rev() {
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx));
+        assert_eq!(iter.value(), format!("value_{}", idx).as_bytes());
+        iter.prev().unwrap();
+    }
+    assert!(!iter.is_valid());
+
+    // Seeking backwards lands on the last key at or before the target
+    let iter = SsTableIterator::create_and_seek_to_key_rev(
+        sst.clone(),
+        KeySlice::for_testing_from_slice_no_ts(&[&key_of(50)[..], b"a"].concat()),
+    )
+    .unwrap();
+    assert_eq!(iter.key().for_testing_key_ref(), key_of(50));
+    let iter = SsTableIterator::create_and_seek_to_key_rev(
+        sst,
+        KeySlice::for_testing_from_slice_no_ts(b"a"),
+    )
+    .unwrap();
+    assert!(!iter.is_valid());
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+/// Check that the reverse scan of every range is the forward scan reversed.
+fn check_scan_rev(storage: &MiniLsm) {
+    let bounds = |idx: usize| {
+        let key = key_of(idx);
+        [
+            Bound::Included(key.clone()),
+            Bound::Excluded(key),
+            Bound::Unbounded,
+        ]
+    };
+    for (lower_idx, upper_idx) in [(0, 99), (10, 90), (25, 26), (40, 40), (60, 20)] {
+        for lower in bounds(lower_idx) {
+            for upper in bounds(upper_idx) {
+                let lower = lower.as_ref().map(|key| &key[..]);
+                let upper = upper.as_ref().map(|key| &key[..]);
+                let mut expected = collect(storage.scan(lower, upper).unwrap());
+                expected.reverse();
+                let actual = collect(storage.scan_rev(lower, upper).unwrap());
+                assert_eq!(actual, expected, "{:?} {:?}", lower, upper);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_scan_rev() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        block_size: 64,
+        target_sst_size: 256,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"old").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), b"new").unwrap();
+    }
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(&key_of(30), &key_of(45)).unwrap();
+    for idx in (0..100).step_by(5) {
+        storage.put(&key_of(idx), b"latest").unwrap();
+    }
+
+    let entries = collect(
+        storage
+            .scan_rev(Bound::Included(&key_of(28)), Bound::Excluded(&key_of(47)))
+            .unwrap(),
+    );
+    let keys = entries
+        .iter()
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, [46, 45, 40, 35, 30, 29].map(key_of));
+    assert_eq!(entries[1].1, "latest");
+    assert_eq!(entries[5].1, "old");
+    check_scan_rev(&storage);
+
+    storage.force_flush().unwrap();
+    check_scan_rev(&storage);
+    storage.force_full_compaction().unwrap();
+    check_scan_rev(&storage);
+}
+
+#[test]
+fn test_scan_rev_sequential_writes() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        sequential_writes: true,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // The keys in order go to the append log, the overwrites after them to the skipmap
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"log").unwrap();
+    }
+    for idx in (0..100).step_by(4) {
+        storage.put(&key_of(idx), b"map").unwrap();
+    }
+    let entries = collect(
+        storage
+            .scan_rev(Bound::Unbounded, Bound::Unbounded)
+            .unwrap(),
+    );
+    assert_eq!(entries.len(), 100);
+    assert_eq!(entries[0], (key_of(99), Bytes::from("log")));
+    assert_eq!(entries[3], (key_of(96), Bytes::from("map")));
+    check_scan_rev(&storage);
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::ops::Bound;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures_core::Stream;
+use tempfile::tempdir;
+
+use crate::lsm_iterator::LsmStream;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+async fn collect<S>(mut stream: S) -> Vec<(Bytes, Bytes)>
+where
+    S: Stream<Item = anyhow::Result<(Bytes, Bytes)>> + Unpin,
+{
+    let mut entries = Vec::new();
+    while let Some(entry) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        entries.push(entry.unwrap());
+    }
+    entries
+}
+
+#[test]
+fn test_scan_stream() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let key_of = |idx: usize| Bytes::from(format!("key_{:05}", idx));
+    for idx in 0..500 {
+        storage.put(&key_of(idx), &key_of(idx * 2)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..500).step_by(3) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    let expected = (0..500)
+        .filter(|idx| idx % 3 != 0)
+        .map(|idx| (key_of(idx), key_of(idx * 2)))
+        .collect::<Vec<_>>();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let scan = storage
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .into_stream();
+    assert_eq!(runtime.block_on(collect(scan)), expected);
+    let scan = storage.scan(Bound::Included(b"key_00100"), Bound::Excluded(b"key_00200"));
+    let scan = LsmStream::new(scan.unwrap()).with_batch_size(7);
+    assert_eq!(runtime.block_on(collect(scan)), expected[66..133].to_vec());
+    let scan = storage.scan(Bound::Excluded(b"key_00499"), Bound::Unbounded);
+    assert!(
+        runtime
+            .block_on(collect(scan.unwrap().into_stream()))
+            .is_empty()
+    );
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::compact::{
+    CompactionOptions, CompactionPlan, CompactionReason, SimpleLeveledCompactionOptions,
+};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+/// Flush the memtable and move the SST to `level`.
+fn flush_to_level(storage: &LsmStorageInner, level: usize) -> usize {
+    storage.force_flush_all().unwrap();
+    let _state_lock = storage.state_lock.lock();
+    let mut state = storage.state.write();
+    let mut snapshot = state.as_ref().clone();
+    let sst_id = snapshot.l0_sstables.pop().unwrap();
+    snapshot.levels[level - 1].1.push(sst_id);
+    *state = snapshot.into();
+    sst_id
+}
+
+#[test]
+fn test_seek_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        seek_compaction_threshold: Some(3),
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+            SimpleLeveledCompactionOptions {
+                size_ratio_percent: 200,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 2,
+            },
+        ))
+    };
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    let key = |i: usize| format!("key_{:05}", i);
+    for i in (0..2000).step_by(2) {
+        storage.put(key(i).as_bytes(), b"upper").unwrap();
+    }
+    let upper_sst_id = flush_to_level(&storage, 1);
+    for i in (1..2000).step_by(2) {
+        storage.put(key(i).as_bytes(), b"lower").unwrap();
+    }
+    flush_to_level(&storage, 2);
+
+    assert!(!storage.trigger_seek_compaction().unwrap());
+    // The odd keys are all in L2, but the bloom filter of the L1 SST lets some of the lookups
+    // through
+    for i in (1..2000).step_by(2) {
+        assert_eq!(
+            storage.get(key(i).as_bytes()).unwrap(),
+            Some(Bytes::from_static(b"lower"))
+        );
+    }
+    assert!(storage.state.read().sstables[&upper_sst_id].useless_probes() >= 3);
+    assert_eq!(*storage.seek_compaction_sst.lock(), Some(upper_sst_id));
+
+    // Explaining the compaction does not consume the trigger
+    let snapshot = storage.state.read().clone();
+    let (task, reason) = storage.triggered_compaction_task(&snapshot).unwrap();
+    assert_eq!(reason, CompactionReason::SeekMisses);
+    let plan = CompactionPlan::new(&snapshot, task, reason);
+    assert_eq!(plan.input_sst_ids.len(), 2);
+    assert_eq!(plan.input_sst_ids[0], upper_sst_id);
+    assert_eq!(
+        plan.input_size,
+        plan.levels
+            .iter()
+            .map(|level| level.size_bytes)
+            .sum::<u64>()
+    );
+    assert_eq!(
+        plan.levels
+            .iter()
+            .map(|level| level.num_ssts)
+            .collect::<Vec<_>>(),
+        vec![0, 1, 1]
+    );
+    assert_eq!(*storage.seek_compaction_sst.lock(), Some(upper_sst_id));
+
+    assert!(storage.trigger_seek_compaction().unwrap());
+    assert!(!storage.trigger_seek_compaction().unwrap());
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.levels[0].1.is_empty());
+    assert!(!snapshot.levels[1].1.is_empty());
+    for i in 0..2000 {
+        let expected = if i % 2 == 0 { "upper" } else { "lower" };
+        assert_eq!(
+            storage.get(key(i).as_bytes()).unwrap(),
+            Some(Bytes::from(expected))
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use super::harness::put_and_flush;
+use crate::compact::{CompactionOptions, LeveledCompactionOptions};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::mem_table::{MemTable, MemTableIterator};
+
+fn collect(mut iter: MemTableIterator) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            String::from_utf8(iter.key().key_ref().to_vec()).unwrap(),
+            String::from_utf8(iter.value().to_vec()).unwrap(),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_append_log_memtable() {
+    let memtable = MemTable::create(0).with_append_log(true);
+    memtable.put(b"a", b"1").unwrap();
+    memtable.put(b"b", b"1").unwrap();
+    memtable.put(b"c", b"1").unwrap();
+    assert_eq!(memtable.get(b"b").as_deref(), Some(&b"1"[..]));
+    assert!(memtable.get(b"bb").is_none());
+
+    // Writes out of order go to the skipmap, and win over the appended entries
+    memtable.put(b"b", b"2").unwrap();
+    memtable.put(b"aa", b"2").unwrap();
+    memtable.delete(b"c").unwrap();
+    memtable.put(b"d", b"2").unwrap();
+    assert_eq!(memtable.get(b"b").as_deref(), Some(&b"2"[..]));
+    assert_eq!(memtable.get_entry(b"c"), Some(None));
+    assert_eq!(
+        collect(memtable.scan(Bound::Unbounded, Bound::Unbounded)),
+        vec![
+            ("a".to_string(), "1".to_string()),
+            ("aa".to_string(), "2".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("c".to_string(), "".to_string()),
+            ("d".to_string(), "2".to_string()),
+        ]
+    );
+    assert_eq!(
+        collect(memtable.scan(Bound::Excluded(b"a"), Bound::Included(b"b"))),
+        vec![
+            ("aa".to_string(), "2".to_string()),
+            ("b".to_string(), "2".to_string()),
+        ]
+    );
+    assert_eq!(
+        collect(memtable.scan(Bound::Included(b"c"), Bound::Excluded(b"d"))),
+        vec![("c".to_string(), "".to_string())]
+    );
+}
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        sequential_writes: true,
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                base_level_size_mb: 1,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_trivial_move() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorageInner::open(&dir, options()).unwrap();
+    put_and_flush(&storage, 0);
+    put_and_flush(&storage, 1);
+    let l0_sstables = storage.state.read().l0_sstables.clone();
+    drop(storage);
+
+    // The SSTs written before the restart are moved down as they are, and stay readable after
+    // the next restart
+    let storage = LsmStorageInner::open(&dir, options()).unwrap();
+    assert!(storage.trigger_compaction().unwrap());
+    let snapshot = storage.state.read().clone();
+    assert!(snapshot.l0_sstables.is_empty());
+    let mut moved = snapshot.levels[2].1.clone();
+    moved.sort();
+    let mut expected = l0_sstables.clone();
+    expected.sort();
+    assert_eq!(moved, expected);
+    drop(storage);
+
+    let storage = LsmStorageInner::open(&dir, options()).unwrap();
+    assert_eq!(storage.state.read().levels[2].1, snapshot.levels[2].1);
+    assert!(storage.get(b"key_00_000").unwrap().is_some());
+    assert!(storage.get(b"key_01_099").unwrap().is_some());
+
+    // An SST overlapping with the next compaction is merged as usual, the other one stays
+    for key in [b"key_00_050", b"key_00_060"] {
+        storage.put(key, b"new_value").unwrap();
+        storage.force_flush_all().unwrap();
+    }
+    assert!(storage.trigger_compaction().unwrap());
+    let bottom_level = storage.state.read().levels[2].1.clone();
+    // L0 lists the SSTs from the newest, the one of round 0 is last
+    assert!(!bottom_level.contains(&l0_sstables[1]));
+    assert!(bottom_level.contains(&l0_sstables[0]));
+    assert_eq!(
+        storage.get(b"key_00_050").unwrap().as_deref(),
+        Some(&b"new_value"[..])
+    );
+    assert!(storage.get(b"key_01_000").unwrap().is_some());
+}
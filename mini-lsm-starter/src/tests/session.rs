@@ -0,0 +1,69 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions};
+use crate::session::SessionToken;
+
+#[test]
+fn test_read_your_writes_on_replica() {
+    let primary_dir = tempdir().unwrap();
+    let replica_dir = tempdir().unwrap();
+    let primary = MiniLsm::open(&primary_dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let replica = MiniLsm::open(&replica_dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+
+    primary.put(b"1", b"233").unwrap();
+    primary.delete(b"2").unwrap();
+    let token = primary.session_token();
+    assert_eq!(token, SessionToken { sequence: 2 });
+    assert_eq!(SessionToken::decode(token.encode()), token);
+
+    let options = ReadOptions {
+        session: Some(token),
+        session_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let err = replica.get_with_options(b"1", &options).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::TimedOut
+    );
+
+    replica.apply_replicated(1, b"1", Some(b"233")).unwrap();
+    let shipper = {
+        let replica = replica.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            replica.apply_replicated(2, b"2", None).unwrap();
+        })
+    };
+    let options = ReadOptions {
+        session_timeout: Duration::from_secs(10),
+        ..options
+    };
+    assert_eq!(
+        replica.get_with_options(b"1", &options).unwrap(),
+        Some(Bytes::from_static(b"233"))
+    );
+    shipper.join().unwrap();
+    assert_eq!(replica.session_token(), token);
+    replica
+        .wait_for_session(token, Duration::from_millis(0))
+        .unwrap();
+    assert_eq!(replica.get_with_options(b"2", &options).unwrap(), None);
+}
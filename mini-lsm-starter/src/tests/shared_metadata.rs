@@ -0,0 +1,121 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::table::{FileObject, SharedMetadata, SsTable};
+
+#[test]
+fn test_shared_metadata() {
+    let dir = tempdir().unwrap();
+    let namespace = format!(
+        "mini-lsm-test-{}-{}",
+        std::process::id(),
+        dir.path().file_name().unwrap().to_string_lossy()
+    );
+    let options = LsmStorageOptions {
+        shared_metadata_namespace: Some(namespace.clone()),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = |i: usize| format!("key_{:03}", i);
+    for i in 0..100 {
+        storage.put(key(i).as_bytes(), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let generation = storage.inner.state.read().sstables[&sst_id].metadata_generation();
+
+    // A reader maps the metadata published by the writer
+    let shared_metadata = SharedMetadata::new(namespace);
+    let open_reader = |sst_id: usize| {
+        SsTable::open_with_shared_metadata(
+            sst_id,
+            None,
+            FileObject::open(&storage.inner.path_of_sst(sst_id)).unwrap(),
+            &shared_metadata,
+        )
+        .unwrap()
+    };
+    let reader = open_reader(sst_id);
+    assert!(reader.has_shared_metadata());
+    assert_eq!(reader.num_of_blocks(), 1);
+    assert!(reader.may_contain_key(b"key_042"));
+    assert!(!reader.may_contain_key(b"key_042x"));
+
+    // The metadata is removed with the SST, readers that mapped it keep working
+    storage.put(b"key_500", b"value").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert!(shared_metadata.open(sst_id, generation).unwrap().is_none());
+    assert!(reader.may_contain_key(b"key_042"));
+
+    // The first reader of an SST without published metadata publishes it
+    let sst_id = storage.inner.state.read().levels[0].1[0];
+    shared_metadata.remove(sst_id).unwrap();
+    let reader = open_reader(sst_id);
+    assert!(reader.has_shared_metadata());
+    assert!(reader.may_contain_key(b"key_500"));
+    drop(storage);
+    shared_metadata.remove(sst_id).unwrap();
+}
+
+#[test]
+fn test_shared_metadata_checks_region() {
+    let dir = tempdir().unwrap();
+    let namespace = format!(
+        "mini-lsm-test-{}-{}",
+        std::process::id(),
+        dir.path().file_name().unwrap().to_string_lossy()
+    );
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    let sst = storage
+        .inner
+        .state
+        .read()
+        .sstables
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+    let shared_metadata = SharedMetadata::new(namespace.clone());
+    sst.publish_metadata(&shared_metadata).unwrap();
+    let generation = sst.metadata_generation();
+    assert!(
+        shared_metadata
+            .open(sst.sst_id(), generation)
+            .unwrap()
+            .is_some()
+    );
+
+    // The metadata of another SST with the same id is not used
+    assert!(
+        shared_metadata
+            .open(sst.sst_id(), generation ^ 1)
+            .unwrap()
+            .is_none()
+    );
+
+    // Nor is a region whose lengths point past its end
+    let path = format!("/dev/shm/{}-{}-{:08x}", namespace, sst.sst_id(), generation);
+    let mut region = std::fs::read(&path).unwrap();
+    region[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+    std::fs::write(&path, &region).unwrap();
+    assert!(shared_metadata.open(sst.sst_id(), generation).is_err());
+    shared_metadata.remove(sst.sst_id()).unwrap();
+    assert!(!std::path::Path::new(&path).exists());
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, CompactionTask, SimpleLeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+
+fn put_and_flush(storage: &LsmStorageInner, round: usize) {
+    for i in 0..100 {
+        let value = format!("value_{}_{}", i, round);
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), value.as_bytes())
+            .unwrap();
+    }
+    storage.force_flush_all().unwrap();
+}
+
+fn level_ssts(storage: &LsmStorageInner) -> Vec<usize> {
+    let snapshot = storage.state.read();
+    let mut sizes = vec![snapshot.l0_sstables.len()];
+    sizes.extend(snapshot.levels.iter().map(|(_, sst_ids)| sst_ids.len()));
+    sizes
+}
+
+#[test]
+fn test_simple_leveled_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    put_and_flush(&storage, 0);
+    assert!(storage.explain_compaction().is_none());
+    put_and_flush(&storage, 1);
+
+    // L0 reached its trigger and goes into L1
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Simple(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.upper_level, None);
+    assert_eq!(task.lower_level, 1);
+    assert!(!task.is_lower_level_bottom_level);
+    let obsolete = task.upper_level_sst_ids.clone();
+    assert!(storage.trigger_compaction().unwrap());
+
+    // The levels below are empty, so the SSTs keep going down until the bottom level
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Simple(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.upper_level, Some(1));
+    assert_eq!(task.lower_level, 2);
+    assert!(!task.is_lower_level_bottom_level);
+    assert!(storage.trigger_compaction().unwrap());
+    let plan = storage.explain_compaction().unwrap();
+    let CompactionTask::Simple(task) = &plan.task else {
+        panic!("unexpected task {:?}", plan.task);
+    };
+    assert_eq!(task.upper_level, Some(2));
+    assert!(task.is_lower_level_bottom_level);
+    assert!(storage.trigger_compaction().unwrap());
+    assert!(!storage.trigger_compaction().unwrap());
+    assert_eq!(level_ssts(&storage), vec![0, 0, 0, 1]);
+
+    // The compacted SSTs are gone from the state and the disk
+    let snapshot = storage.state.read().clone();
+    for sst_id in obsolete {
+        assert!(!snapshot.sstables.contains_key(&sst_id));
+        assert!(!storage.path_of_sst(sst_id).exists());
+    }
+    for i in 0..100 {
+        let value = storage.get(format!("key_{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.unwrap(), format!("value_{}_1", i).as_bytes());
+    }
+}
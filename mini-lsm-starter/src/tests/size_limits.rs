@@ -0,0 +1,111 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{
+    LsmStorageOptions, MAX_KEY_SIZE, MAX_VALUE_SIZE, MiniLsm, WriteBatchRecord,
+};
+
+fn is_invalid_input(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::InvalidInput)
+}
+
+#[test]
+fn test_key_value_size_limits() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        max_key_size: 16,
+        max_value_size: 1024,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let long_key = [b'k'; 17];
+    let long_value = [b'v'; 1025];
+    storage.put(&[b'k'; 16], &[b'v'; 1024]).unwrap();
+    assert!(is_invalid_input(&storage.put(&long_key, b"v").unwrap_err()));
+    assert!(is_invalid_input(
+        &storage.put(b"k", &long_value).unwrap_err()
+    ));
+    assert!(is_invalid_input(&storage.delete(&long_key).unwrap_err()));
+
+    // Nothing in a batch is written if any record is too large
+    let err = storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"k"[..], &b"v"[..]),
+            WriteBatchRecord::Put(&b"k2"[..], &long_value[..]),
+        ])
+        .unwrap_err();
+    assert!(is_invalid_input(&err));
+    assert_eq!(storage.get(b"k").unwrap(), None);
+    storage.force_flush().unwrap();
+    assert!(storage.get(&[b'k'; 16]).unwrap().is_some());
+
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        max_key_size: MAX_KEY_SIZE + 1,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(MiniLsm::open(&dir, options).is_err());
+
+    // The largest entries still fit into a block
+    let options = LsmStorageOptions::default_for_week1_test();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = vec![b'k'; MAX_KEY_SIZE];
+    storage.put(&key, &vec![b'v'; MAX_VALUE_SIZE]).unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(storage.get(&key).unwrap().unwrap().len(), MAX_VALUE_SIZE);
+}
+
+#[test]
+fn test_values_over_64kb() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let key_of = |idx: usize| format!("key_{:03}", idx).into_bytes();
+    let value_of = |idx: usize| {
+        vec![
+            idx as u8;
+            if idx.is_multiple_of(3) {
+                100 << 10
+            } else {
+                idx
+            }
+        ]
+    };
+    for idx in 0..30 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 15..45 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for idx in 0..45 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap().unwrap(), value_of(idx));
+    }
+    let mut iter = storage
+        .scan(Bound::Included(&key_of(10)), Bound::Unbounded)
+        .unwrap();
+    for idx in 10..45 {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
@@ -0,0 +1,81 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::snapshot::DiffIterator;
+
+fn collect_diff(mut iter: DiffIterator) -> Vec<(Bytes, Bytes, Bytes)> {
+    let mut diff = Vec::new();
+    while iter.is_valid() {
+        diff.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.old_value()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    diff
+}
+
+fn diff_entry(key: &'static str, old: &'static str, new: &'static str) -> (Bytes, Bytes, Bytes) {
+    (
+        Bytes::from_static(key.as_bytes()),
+        Bytes::from_static(old.as_bytes()),
+        Bytes::from_static(new.as_bytes()),
+    )
+}
+
+#[test]
+fn test_diff_scan() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(b"e", b"1").unwrap();
+    let old = storage.snapshot().unwrap();
+
+    storage.put(b"a", b"2").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.delete(b"c").unwrap();
+    storage.put(b"f", b"2").unwrap();
+    let new = storage.snapshot().unwrap();
+    // Writes and compactions after the snapshots do not change them
+    storage.put(b"d", b"3").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    let diff = storage
+        .diff_scan(&old, &new, Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert_eq!(
+        collect_diff(diff),
+        vec![
+            diff_entry("a", "1", "2"),
+            diff_entry("c", "1", ""),
+            diff_entry("f", "", "2"),
+        ]
+    );
+    let diff = storage
+        .diff_scan(&new, &old, Bound::Excluded(b"a"), Bound::Included(b"e"))
+        .unwrap();
+    assert_eq!(collect_diff(diff), vec![diff_entry("c", "", "1")]);
+}
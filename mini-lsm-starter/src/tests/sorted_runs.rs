@@ -0,0 +1,89 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, SimpleLeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions};
+use crate::sorted_runs::num_sorted_runs;
+
+fn options(max_sorted_runs: usize) -> LsmStorageOptions {
+    LsmStorageOptions {
+        max_sorted_runs: Some(max_sorted_runs),
+        ..LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+            SimpleLeveledCompactionOptions {
+                size_ratio_percent: 200,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 1,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_max_sorted_runs_stall() {
+    let dir = tempdir().unwrap();
+    // Without the background threads, so that the test decides when to flush and compact
+    let storage = LsmStorageInner::open(&dir, options(3)).unwrap();
+    let put_and_flush = |key: &str| {
+        storage.put(key.as_bytes(), b"value").unwrap();
+        storage.force_flush_all().unwrap();
+    };
+    put_and_flush("a");
+    put_and_flush("b");
+    assert!(storage.trigger_compaction().unwrap());
+    put_and_flush("c");
+    put_and_flush("d");
+    assert_eq!(num_sorted_runs(&storage.state.read()), 3);
+
+    // Another flush would make a fourth sorted run, so the memtables are held back
+    for key in ["e", "f"] {
+        storage.put(key.as_bytes(), b"value").unwrap();
+        storage
+            .force_freeze_memtable(&storage.state_lock.lock())
+            .unwrap();
+    }
+    assert!(!storage.trigger_flush().unwrap());
+    assert_eq!(storage.state.read().imm_memtables.len(), 2);
+
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(|| storage.put(b"g", b"value"));
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!writer.is_finished());
+        assert_eq!(storage.get(b"g").unwrap(), None);
+
+        assert!(storage.trigger_compaction().unwrap());
+        assert!(storage.trigger_flush().unwrap());
+        writer.join().unwrap().unwrap();
+    });
+    assert_eq!(storage.get(b"g").unwrap().as_deref(), Some(&b"value"[..]));
+    assert!(num_sorted_runs(&storage.state.read()) <= 3);
+    let stats = storage.sorted_run_limiter.as_ref().unwrap().stats();
+    assert_eq!(stats.stalls, 1);
+    assert!(stats.stall_time >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_max_sorted_runs_validation() {
+    let dir = tempdir().unwrap();
+    // L0 compactions start at 2 SSTs, on top of the run of L1
+    assert!(LsmStorageInner::open(&dir, options(2)).is_err());
+    let no_compaction = LsmStorageOptions {
+        max_sorted_runs: Some(10),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(LsmStorageInner::open(&dir, no_compaction).is_err());
+}
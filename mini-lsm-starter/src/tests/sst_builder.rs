@@ -0,0 +1,353 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::{MockIterator, check_iter_result_by_key, key_of};
+use crate::{
+    iterators::StorageIterator,
+    key::{KeySlice, TS_DEFAULT},
+    lsm_storage::{LsmStorageInner, LsmStorageOptions, MAX_VALUE_SIZE, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator},
+};
+
+fn value_of(idx: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}", idx))
+}
+
+#[test]
+fn test_add_sorted_entries() {
+    let dir = tempdir().unwrap();
+    let data = (0..1000)
+        .map(|idx| (key_of(idx), value_of(idx)))
+        .collect::<Vec<_>>();
+    let mut iter = MockIterator::new(data.clone());
+    let mut builder = SsTableBuilder::new(128);
+    builder.add_sorted_entries(&mut iter, 4096, false).unwrap();
+    assert!(iter.is_valid());
+    assert!(builder.estimated_size() >= 4096);
+    let mut remaining = SsTableBuilder::new(128);
+    remaining
+        .add_sorted_entries(&mut iter, usize::MAX, false)
+        .unwrap();
+    assert!(!iter.is_valid());
+
+    // Copy the blocks of the first SST into a new one
+    let sst = Arc::new(builder.build_for_test(dir.path().join("1.sst")).unwrap());
+    let num_of_blocks = sst.num_of_blocks();
+    let mut copied = SsTableBuilder::new(128);
+    for block_idx in 0..sst.num_of_blocks() {
+        let (block, encoded) = sst.read_block_for_copy(block_idx).unwrap();
+        copied.add_encoded_block(block, &encoded);
+    }
+    let copied = Arc::new(copied.build_for_test(dir.path().join("2.sst")).unwrap());
+    assert_eq!(copied.num_of_blocks(), num_of_blocks);
+    assert_eq!(copied.block_meta, sst.block_meta);
+    let num_of_entries = iter_len(SsTableIterator::create_and_seek_to_first(sst).unwrap());
+    let mut copied_iter = SsTableIterator::create_and_seek_to_first(copied).unwrap();
+    check_iter_result_by_key(&mut copied_iter, data[..num_of_entries].to_vec());
+}
+
+fn iter_len(mut iter: SsTableIterator) -> usize {
+    let mut cnt = 0;
+    while iter.is_valid() {
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    cnt
+}
+
+#[test]
+fn test_full_compaction_with_non_overlapping_ssts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 100..200 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.delete(&key_of(150)).unwrap();
+    storage.force_flush().unwrap();
+    for idx in 150..160 {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.put(&key_of(151), &value_of(151)).unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    assert!(storage.inner.state.read().l0_sstables.is_empty());
+    for idx in 0..200 {
+        let expected = match idx {
+            151 => Some(value_of(idx)),
+            150..160 => None,
+            _ => Some(value_of(idx)),
+        };
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_auto_block_size() {
+    let dir = tempdir().unwrap();
+    let large_value = Bytes::from(vec![b'x'; 1000]);
+    let mut builder = SsTableBuilder::new(256).with_max_block_size(16384);
+    for idx in 0..100 {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &large_value,
+        );
+    }
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let properties = sst.properties().clone();
+    assert_eq!(properties.num_entries, 100);
+    assert_eq!(properties.raw_value_size, 100 * 1000);
+    assert_eq!(properties.block_size, 16384);
+    // Only the first block is built with the minimum size
+    assert!(sst.num_of_blocks() < 20);
+
+    let sst = SsTable::open(
+        0,
+        None,
+        FileObject::open(&dir.path().join("1.sst")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(sst.properties(), &properties);
+
+    let mut builder = SsTableBuilder::new(256).with_max_block_size(16384);
+    for idx in 0..100 {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx),
+        );
+    }
+    let sst = builder.build_for_test(dir.path().join("2.sst")).unwrap();
+    // 20-byte entries
+    assert_eq!(sst.properties().block_size, 20 * 32);
+}
+
+#[test]
+fn test_checked_builder() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(128);
+    assert!(
+        SsTableBuilder::new(128)
+            .finish(dir.path().join("0.sst"))
+            .is_err()
+    );
+    for idx in 0..100 {
+        builder.try_add(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    builder.try_delete(&key_of(100)).unwrap();
+    // Rejected entries leave the builder unchanged
+    let err = builder.try_add(&key_of(100), b"v").unwrap_err();
+    assert!(err.to_string().contains("duplicate key"), "{}", err);
+    let err = builder.try_add(&key_of(5), b"v").unwrap_err();
+    assert!(err.to_string().contains("after the greater key"), "{}", err);
+    assert!(builder.try_add(b"", b"v").is_err());
+    assert!(
+        builder
+            .try_add(&key_of(101), &vec![0; MAX_VALUE_SIZE + 1])
+            .is_err()
+    );
+
+    let path = dir.path().join("1.sst");
+    builder.finish(&path).unwrap();
+    let sst = Arc::new(SsTable::open_path(&path).unwrap());
+    assert_eq!(sst.get_entry(&key_of(100)).unwrap(), Some(None));
+    assert_eq!(iter_len(sst.iter().unwrap()), 101);
+}
+
+#[test]
+fn test_key_order_violation() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(128).with_key_order_check();
+    for idx in [0, 2, 1] {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx),
+        );
+    }
+    let path = dir.path().join("1.sst");
+    let Err(err) = builder.build_for_test(&path) else {
+        panic!("built an SST out of order");
+    };
+    assert!(
+        format!("{:#}", err).contains("after the greater key"),
+        "{:#}",
+        err
+    );
+    assert!(!path.exists());
+
+    // Ties are violations too, e.g., a merge yielding the same key twice
+    let data = [0, 1, 1, 2]
+        .into_iter()
+        .map(|idx| (key_of(idx), value_of(idx)))
+        .collect::<Vec<_>>();
+    let mut builder = SsTableBuilder::new(128).with_key_order_check();
+    builder
+        .add_sorted_entries(&mut MockIterator::new(data), usize::MAX, false)
+        .unwrap();
+    let Err(err) = builder.build_for_test(&path) else {
+        panic!("built an SST out of order");
+    };
+    assert!(format!("{:#}", err).contains("duplicate key"), "{:#}", err);
+
+    // A copied block must start after the keys added before
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..10 {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx),
+        );
+    }
+    let sst = builder.build_for_test(dir.path().join("2.sst")).unwrap();
+    let mut builder = SsTableBuilder::new(128).with_key_order_check();
+    builder.add(
+        KeySlice::for_testing_from_slice_no_ts(&key_of(5)),
+        &value_of(5),
+    );
+    let (block, encoded) = sst.read_block_for_copy(0).unwrap();
+    builder.add_encoded_block(block, &encoded);
+    assert!(builder.build_for_test(&path).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_verify_bottom_level_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        verify_bottom_level_compaction: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 50..150 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.delete(&key_of(10)).unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.get(&key_of(10)).unwrap(), None);
+
+    let build = |name: &str, keys: &[usize], delete: Option<usize>| {
+        let mut builder = SsTableBuilder::new(128);
+        for &idx in keys {
+            let value = if Some(idx) == delete {
+                Bytes::new()
+            } else {
+                value_of(idx)
+            };
+            builder.add(KeySlice::for_testing_from_slice_no_ts(&key_of(idx)), &value);
+        }
+        Arc::new(builder.build_for_test(dir.path().join(name)).unwrap())
+    };
+    let first = build("a.sst", &[0, 1, 2], None);
+    let overlapping = build("b.sst", &[2, 3], None);
+    let with_tombstone = build("c.sst", &[3, 4], Some(4));
+    let last = build("d.sst", &[3, 4], None);
+    LsmStorageInner::verify_bottom_level_output(&[first.clone(), last], TS_DEFAULT).unwrap();
+    let err =
+        LsmStorageInner::verify_bottom_level_output(&[first.clone(), overlapping], TS_DEFAULT)
+            .unwrap_err();
+    assert!(err.to_string().contains("more than once"), "{}", err);
+    let err = LsmStorageInner::verify_bottom_level_output(&[first, with_tombstone], TS_DEFAULT)
+        .unwrap_err();
+    assert!(err.to_string().contains("tombstone"), "{}", err);
+}
+
+#[test]
+fn test_chunked_sync() {
+    let dir = tempdir().unwrap();
+    let data = (0..1000).map(|idx| idx as u8).collect::<Vec<_>>();
+    let path = dir.path().join("chunked");
+    let file = FileObject::create_chunked(&path, data.clone(), 64).unwrap();
+    file.sync().unwrap();
+    assert_eq!(file.size(), 1000);
+    assert_eq!(file.read(0, 1000).unwrap(), data);
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+
+    let options = LsmStorageOptions {
+        sst_sync_chunk_size: Some(0),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+    let options = LsmStorageOptions {
+        sst_sync_chunk_size: Some(256),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(dir.path().join("storage"), options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 50..150 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for idx in 0..150 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx)));
+    }
+}
+
+#[test]
+fn test_bloom_bits_per_key() {
+    let dir = tempdir().unwrap();
+    let false_positives = |bits_per_key: Option<usize>| {
+        let mut builder = SsTableBuilder::new(4096);
+        if let Some(bits_per_key) = bits_per_key {
+            builder = builder.with_bloom_bits_per_key(bits_per_key);
+        }
+        for idx in 0..1000 {
+            builder.add(
+                KeySlice::for_testing_from_slice_no_ts(&key_of(idx * 2)),
+                &value_of(idx),
+            );
+        }
+        let path = dir.path().join(format!("{:?}.sst", bits_per_key));
+        let sst = builder.build_for_test(&path).unwrap();
+        assert!((0..1000).all(|idx| sst.may_contain_key(&key_of(idx * 2))));
+        let sst = SsTable::open(0, None, FileObject::open(&path).unwrap()).unwrap();
+        (0..1000)
+            .filter(|idx| sst.may_contain_key(&key_of(idx * 2 + 1)))
+            .count()
+    };
+    let (sparse, default, dense) = (
+        false_positives(Some(2)),
+        false_positives(None),
+        false_positives(Some(20)),
+    );
+    assert!(
+        sparse > default && default > dense,
+        "{} {} {}",
+        sparse,
+        default,
+        dense
+    );
+    assert!(default < 30, "{}", default);
+
+    let options = LsmStorageOptions {
+        bloom_bits_per_key: Some(0),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    assert!(options.validate().is_err());
+}
@@ -0,0 +1,61 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::{SsTable, StorageIterator};
+
+#[test]
+fn test_read_sst_by_path() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..100 {
+        storage
+            .put(
+                format!("key_{:03}", i).as_bytes(),
+                format!("value_{}", i).as_bytes(),
+            )
+            .unwrap();
+    }
+    storage.delete(b"key_050").unwrap();
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let path = storage.inner.path_of_sst(sst_id);
+    drop(storage);
+
+    let table = Arc::new(SsTable::open_path(&path).unwrap());
+    assert_eq!(table.first_key().key_ref(), b"key_000");
+    assert_eq!(table.last_key().key_ref(), b"key_099");
+    assert_eq!(
+        table.get_entry(b"key_007").unwrap(),
+        Some(Some("value_7".into()))
+    );
+    assert_eq!(table.get_entry(b"key_050").unwrap(), Some(None));
+    assert_eq!(table.get_entry(b"key_100").unwrap(), None);
+
+    let mut iter = table.iter().unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key().key_ref(), format!("key_{:03}", count).as_bytes());
+        assert_eq!(iter.is_deleted(), count == 50);
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 100);
+
+    assert!(SsTable::open_path(dir.path().join("missing.sst")).is_err());
+}
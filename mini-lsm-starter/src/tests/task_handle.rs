@@ -0,0 +1,137 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, SimpleLeveledCompactionOptions};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_scheduled_flush_and_full_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.schedule_flush().wait().unwrap();
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 1);
+
+    storage.put(b"2", b"23333").unwrap();
+    storage.delete(b"1").unwrap();
+    storage.schedule_flush().wait().unwrap();
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 2);
+
+    storage.schedule_full_compaction().wait().unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(state.l0_sstables.is_empty());
+        assert_eq!(state.levels[0].1.len(), 1);
+        assert_eq!(state.sstables.len(), 1);
+    }
+    assert_eq!(storage.get(b"1").unwrap(), None);
+    assert_eq!(
+        storage.get(b"2").unwrap(),
+        Some(Bytes::from_static(b"23333"))
+    );
+}
+
+#[test]
+fn test_notify_next_flush() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_day6_test()).unwrap();
+    let handle = storage.notify_next_flush();
+    assert!(handle.wait_timeout(Duration::from_millis(200)).is_none());
+    for i in 0..3 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    handle
+        .wait_timeout(Duration::from_secs(5))
+        .expect("flush thread did not flush")
+        .unwrap();
+    assert!(!storage.inner.state.read().l0_sstables.is_empty());
+}
+
+#[test]
+fn test_force_flush_all() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..3 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    storage.put(b"3", b"value").unwrap();
+    storage.force_flush_all().unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(state.memtable.is_empty());
+        assert!(state.imm_memtables.is_empty());
+        assert_eq!(state.l0_sstables.len(), 4);
+    }
+    storage
+        .wait_for_compactions(Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(
+        storage.get(b"3").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+}
+
+#[test]
+fn test_close() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for i in 0..4 {
+        storage.put(format!("{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    storage.put(b"4", b"value").unwrap();
+    storage.close().unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(state.memtable.is_empty());
+        assert!(state.imm_memtables.is_empty());
+        assert!(state.l0_sstables.len() < 2);
+    }
+    assert!(storage.flush_thread.lock().is_none());
+    assert!(storage.compaction_thread.lock().is_none());
+    drop(storage);
+
+    // Nothing was left in the memtables, which have no WAL
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..5 {
+        assert_eq!(
+            storage.get(format!("{i}").as_bytes()).unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+    }
+}
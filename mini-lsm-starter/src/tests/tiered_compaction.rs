@@ -0,0 +1,144 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, TieredCompactionController, TieredCompactionOptions};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, LsmStorageState, MiniLsm};
+use crate::mem_table::MemTable;
+
+use super::harness::{check_compaction_ratio, compaction_bench};
+
+fn options(max_merge_width: Option<usize>) -> TieredCompactionOptions {
+    TieredCompactionOptions {
+        num_tiers: 3,
+        max_size_amplification_percent: 400,
+        size_ratio: 1,
+        min_merge_width: 2,
+        max_merge_width,
+    }
+}
+
+/// Tiers of the given numbers of SSTs, from the newest, with the ids counting down from 100.
+fn state(tier_sizes: &[usize]) -> LsmStorageState {
+    let mut next_id = 100;
+    let levels = tier_sizes
+        .iter()
+        .map(|&size| {
+            let sst_ids = (0..size).map(|idx| next_id - idx).collect::<Vec<_>>();
+            next_id -= size;
+            (sst_ids[0], sst_ids)
+        })
+        .collect();
+    LsmStorageState {
+        memtable: Arc::new(MemTable::create(0)),
+        imm_memtables: Vec::new(),
+        l0_sstables: Vec::new(),
+        levels,
+        sstables: Default::default(),
+    }
+}
+
+fn task_tiers(
+    controller: &TieredCompactionController,
+    tier_sizes: &[usize],
+) -> Option<(usize, bool)> {
+    let task = controller.generate_compaction_task(&state(tier_sizes))?;
+    Some((task.tiers.len(), task.bottom_tier_included))
+}
+
+#[test]
+fn test_tiered_compaction_tasks() {
+    let controller = TieredCompactionController::new(options(Some(2)));
+    assert_eq!(task_tiers(&controller, &[1, 1]), None);
+    // Space amplification
+    assert_eq!(task_tiers(&controller, &[2, 2, 1]), Some((3, true)));
+    // Size ratio: the third tier is larger than the two above together
+    assert_eq!(task_tiers(&controller, &[1, 1, 4, 8]), Some((2, false)));
+    // ... but the first tier alone is too few to merge
+    assert_eq!(task_tiers(&controller, &[1, 4, 8, 8]), Some((2, false)));
+    // Reducing the number of tiers, up to the max merge width
+    assert_eq!(task_tiers(&controller, &[1, 1, 1, 1]), Some((2, false)));
+    let controller = TieredCompactionController::new(options(None));
+    assert_eq!(task_tiers(&controller, &[1, 1, 1, 1]), Some((4, true)));
+
+    // A tier flushed while compacting stays on top of the merged tier
+    let controller = TieredCompactionController::new(options(Some(2)));
+    let snapshot = state(&[1, 1, 4, 8]);
+    let task = controller.generate_compaction_task(&snapshot).unwrap();
+    let mut snapshot = state(&[1, 1, 4, 8]);
+    snapshot.levels.insert(0, (101, vec![101]));
+    let (new_snapshot, removed) = controller.apply_compaction_result(&snapshot, &task, &[200]);
+    assert_eq!(removed, vec![100, 99]);
+    let tiers = new_snapshot
+        .levels
+        .iter()
+        .map(|(tier_id, sst_ids)| (*tier_id, sst_ids.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(tiers, vec![(101, 1), (200, 1), (98, 4), (94, 8)]);
+    // The tiers go away if nothing is left of them
+    let (new_snapshot, _) = controller.apply_compaction_result(&snapshot, &task, &[]);
+    assert_eq!(new_snapshot.levels.len(), 3);
+}
+
+#[test]
+fn test_tiered_compaction_in_background() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(
+            TieredCompactionOptions {
+                num_tiers: 3,
+                max_size_amplification_percent: 200,
+                size_ratio: 1,
+                min_merge_width: 2,
+                max_merge_width: None,
+            },
+        )),
+    )
+    .unwrap();
+    compaction_bench(storage.clone());
+    check_compaction_ratio(storage.clone());
+    assert!(!storage.inner.has_background_error());
+}
+
+#[test]
+fn test_tiered_compaction_recovery() {
+    let dir = tempdir().unwrap();
+    let options =
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(options(Some(2))));
+    let storage = LsmStorageInner::open(&dir, options.clone()).unwrap();
+    for round in 0..5 {
+        for i in 0..100 {
+            let value = format!("value_{}_{}", i, round);
+            storage
+                .put(format!("key_{:03}", i).as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        storage.force_flush_all().unwrap();
+        // Each flush adds a tier
+        assert!(storage.state.read().l0_sstables.is_empty());
+        while storage.trigger_compaction().unwrap() {}
+    }
+    let levels = storage.state.read().levels.clone();
+    assert_eq!(levels.len(), 2);
+    drop(storage);
+
+    let storage = LsmStorageInner::open(&dir, options).unwrap();
+    assert_eq!(storage.state.read().levels, levels);
+    let value = storage.get(b"key_042").unwrap().unwrap();
+    assert_eq!(value, "value_42_4".as_bytes());
+}
@@ -0,0 +1,54 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::sst_file_manager::TRASH_DIR;
+
+#[test]
+fn test_trash_deletion() {
+    let dir = tempdir().unwrap();
+    // Left over from a previous process
+    std::fs::create_dir(dir.path().join(TRASH_DIR)).unwrap();
+    std::fs::write(dir.path().join(TRASH_DIR).join("00100.sst"), [0; 1000]).unwrap();
+
+    let options = LsmStorageOptions {
+        sst_delete_rate: Some(256 << 10),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = |i: usize| format!("key_{:05}", i);
+    for i in 0..1000 {
+        storage.put(key(i).as_bytes(), &[b'x'; 100]).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let sst_size = storage.inner.state.read().sstables[&sst_id].table_size();
+    storage.force_full_compaction().unwrap();
+
+    // The input SST is moved to trash, then deleted in the background
+    assert!(!storage.inner.path_of_sst(sst_id).exists());
+    assert!(storage.trash_size() > 0 && storage.trash_size() <= sst_size + 1000);
+    let start = Instant::now();
+    while storage.trash_size() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let trash = std::fs::read_dir(dir.path().join(TRASH_DIR)).unwrap();
+    assert_eq!(trash.count(), 0);
+    assert_eq!(storage.get(b"key_00999").unwrap().unwrap().len(), 100);
+}
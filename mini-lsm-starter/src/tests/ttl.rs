@@ -0,0 +1,129 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::idempotence::OperationId;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteOptions};
+use crate::ttl::{Clock, ManualClock, TtlOptions};
+
+#[test]
+fn test_ttl_compaction() {
+    let dir = tempdir().unwrap();
+    let clock = Arc::new(ManualClock::new(1 << 40));
+    let options = LsmStorageOptions {
+        ttl: Some(TtlOptions {
+            ttl: Duration::from_secs(60),
+            compaction_interval: Duration::ZERO,
+            clock: clock.clone(),
+        }),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = |i: usize| format!("key_{:03}", i);
+    for i in 0..100 {
+        storage.put(key(i).as_bytes(), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    let sst_id = snapshot.levels[0].1[0];
+    let histogram = &snapshot.sstables[&sst_id].properties().expiry_histogram;
+    assert_eq!(histogram.buckets.iter().map(|(_, n)| n).sum::<u64>(), 100);
+    assert_eq!(histogram.min_expiry, clock.now_millis() + 60_000);
+
+    // Nothing has expired yet
+    assert!(!storage.inner.trigger_ttl_compaction().unwrap());
+    assert_eq!(
+        storage.get(b"key_000").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(iter.value(), b"value");
+    iter.next().unwrap();
+    assert_eq!(iter.key(), b"key_001");
+
+    clock.advance(Duration::from_secs(60));
+    storage.put(b"key_500", b"value").unwrap();
+    assert_eq!(storage.get(b"key_000").unwrap(), None);
+    let iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(iter.key(), b"key_500");
+
+    // The compaction thread compacts the expired SST, also without a compaction controller
+    let start = Instant::now();
+    while storage.inner.state.read().sstables.contains_key(&sst_id) {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(storage.inner.state.read().levels[0].1.is_empty());
+    assert_eq!(
+        storage.get(b"key_500").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+}
+
+#[test]
+fn test_ttl_keeps_internal_keys() {
+    let dir = tempdir().unwrap();
+    let clock = Arc::new(ManualClock::new(1 << 40));
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        internal_keyspace: true,
+        ttl: Some(TtlOptions {
+            ttl: Duration::from_secs(60),
+            compaction_interval: Duration::ZERO,
+            clock: clock.clone(),
+        }),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let retry = WriteOptions {
+        operation: Some(OperationId {
+            client_id: 1,
+            op_id: 1,
+        }),
+        ..Default::default()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let users = storage.create_cf("users").unwrap();
+    storage.put_cf(&users, b"a", b"user a").unwrap();
+    storage.put_with_options(b"a", b"1", &retry).unwrap();
+
+    // The data expires, but neither the registry nor the window of the client
+    clock.advance(Duration::from_secs(120));
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get_cf(&users, b"a").unwrap(), None);
+    storage.put_with_options(b"a", b"2", &retry).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.list_cfs(), vec!["users"]);
+    storage.put_with_options(b"a", b"3", &retry).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    let orders = storage.create_cf("orders").unwrap();
+    assert_ne!(storage.cf("users").unwrap(), orders);
+    storage.put_cf(&orders, b"a", b"order a").unwrap();
+    assert_eq!(
+        storage.get_cf(&orders, b"a").unwrap(),
+        Some(Bytes::from_static(b"order a"))
+    );
+}
@@ -0,0 +1,231 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use super::harness::check_lsm_iter_result_by_key;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mvcc::txn::is_conflict_error;
+
+fn wal_options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    }
+}
+
+#[test]
+fn test_txn_reads() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.put(b"c", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"2");
+    txn.put(b"d", b"");
+    txn.delete(b"c");
+    storage.put(b"a", b"2").unwrap();
+    storage.put(b"e", b"1").unwrap();
+
+    // The transaction sees its own writes over the storage as of its start
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(txn.get(b"b").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(txn.get(b"c").unwrap(), None);
+    assert_eq!(txn.get(b"d").unwrap(), Some(Bytes::new()));
+    assert_eq!(txn.get(b"e").unwrap(), None);
+    check_lsm_iter_result_by_key(
+        &mut txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("2")),
+            (Bytes::from("d"), Bytes::new()),
+        ],
+    );
+    check_lsm_iter_result_by_key(
+        &mut txn
+            .scan(Bound::Excluded(b"a"), Bound::Included(b"c"))
+            .unwrap(),
+        vec![(Bytes::from("b"), Bytes::from("2"))],
+    );
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from_static(b"1")));
+}
+
+#[test]
+fn test_txn_commit() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, wal_options()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    txn.put(b"a", b"2");
+    txn.delete(b"b");
+    txn.put(b"c", b"2");
+    let ts = storage.latest_commit_ts();
+    txn.commit().unwrap();
+    // The writes are committed at once
+    assert_eq!(storage.latest_commit_ts(), ts + 1);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from_static(b"2")));
+    drop(txn);
+
+    // Dropped without flushing the memtable, as in a crash
+    drop(storage);
+    let storage = MiniLsm::open(&dir, wal_options()).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from_static(b"2")));
+}
+
+#[test]
+fn test_txn_conflicts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+
+    // A write to a key the transaction writes
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.put(b"a", b"2");
+    txn2.put(b"a", b"3");
+    txn1.commit().unwrap();
+    assert!(is_conflict_error(&txn2.commit().unwrap_err()));
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+
+    // A write to a key the transaction read, either with a get or a scan
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get(b"a").unwrap();
+    txn1.put(b"b", b"1");
+    check_lsm_iter_result_by_key(
+        &mut txn2.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![(Bytes::from("a"), Bytes::from("2"))],
+    );
+    txn2.put(b"c", b"1");
+    storage.put(b"a", b"3").unwrap();
+    assert!(is_conflict_error(&txn1.commit().unwrap_err()));
+    assert!(is_conflict_error(&txn2.commit().unwrap_err()));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), None);
+
+    // A range delete over a key the transaction writes
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"1");
+    storage.delete_range(b"a", b"c").unwrap();
+    assert!(is_conflict_error(&txn.commit().unwrap_err()));
+
+    // A read-only transaction and writes to other keys do not conflict
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get(b"a").unwrap();
+    txn2.get(b"b").unwrap();
+    txn2.put(b"b", b"1");
+    storage.put(b"a", b"4").unwrap();
+    txn1.commit().unwrap();
+    txn2.commit().unwrap();
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from_static(b"1")));
+}
+
+#[test]
+fn test_txn_committed_writes_dropped() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let committed_writes = || storage.inner.mvcc().committed_txns.lock().writes.len();
+    // No transaction needs the writes
+    storage.put(b"a", b"1").unwrap();
+    assert_eq!(committed_writes(), 0);
+
+    let txn1 = storage.new_txn().unwrap();
+    storage.put(b"a", b"2").unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    storage.put(b"a", b"3").unwrap();
+    assert_eq!(committed_writes(), 2);
+    drop(txn1);
+    assert_eq!(committed_writes(), 1);
+    drop(txn2);
+    assert_eq!(committed_writes(), 0);
+}
+
+#[test]
+fn test_serializable_txn() {
+    let phantom_commits = |serializable: bool| {
+        let dir = tempdir().unwrap();
+        let options = LsmStorageOptions {
+            serializable,
+            ..LsmStorageOptions::default_for_week1_test()
+        };
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        storage.put(b"a", b"1").unwrap();
+        let txn = storage.new_txn().unwrap();
+        check_lsm_iter_result_by_key(
+            &mut txn
+                .scan(Bound::Included(b"a"), Bound::Excluded(b"c"))
+                .unwrap(),
+            vec![(Bytes::from("a"), Bytes::from("1"))],
+        );
+        txn.put(b"count", b"1");
+        // A key added to the scanned range
+        storage.put(b"b", b"1").unwrap();
+        txn.commit()
+    };
+    phantom_commits(false).unwrap();
+    assert!(is_conflict_error(&phantom_commits(true).unwrap_err()));
+
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        serializable: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // Each transaction writes into the range the other scanned, only one of them can commit
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    assert!(
+        !txn1
+            .scan(Bound::Included(b"x"), Bound::Excluded(b"y"))
+            .unwrap()
+            .is_valid()
+    );
+    assert!(
+        !txn2
+            .scan(Bound::Included(b"y"), Bound::Excluded(b"z"))
+            .unwrap()
+            .is_valid()
+    );
+    txn1.put(b"y1", b"1");
+    txn2.put(b"x1", b"1");
+    txn1.commit().unwrap();
+    assert!(is_conflict_error(&txn2.commit().unwrap_err()));
+
+    // A range delete overlapping a scanned range, but not writes around it
+    let txn = storage.new_txn().unwrap();
+    txn.scan(Bound::Excluded(b"b"), Bound::Included(b"d"))
+        .unwrap();
+    txn.put(b"e", b"1");
+    storage.put(b"b", b"1").unwrap();
+    storage.delete_range(b"d1", b"d5").unwrap();
+    txn.commit().unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.scan(Bound::Excluded(b"b"), Bound::Included(b"d"))
+        .unwrap();
+    txn.put(b"e", b"2");
+    storage.delete_range(b"a", b"c").unwrap();
+    assert!(is_conflict_error(&txn.commit().unwrap_err()));
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn check_value_meta(storage: &MiniLsm, expected: &[(&[u8], &[u8], u8)]) {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for (key, value, meta) in expected {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), *key);
+        assert_eq!(iter.value(), *value);
+        assert_eq!(iter.value_meta(), *meta);
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_value_meta() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put_with_meta(b"1", b"233", 1).unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.put_with_meta(b"3", b"", 3).unwrap();
+    storage.put_with_meta(b"4", b"23333", 4).unwrap();
+    storage.put_with_meta(b"5", b"233333", 5).unwrap();
+    storage.delete(b"5").unwrap();
+    let expected: &[(&[u8], &[u8], u8)] = &[
+        (b"1", b"233", 1),
+        (b"2", b"2333", 0),
+        (b"3", b"", 3),
+        (b"4", b"23333", 4),
+    ];
+    check_value_meta(&storage, expected);
+    assert_eq!(storage.get(b"1").unwrap().unwrap().as_ref(), b"233");
+    assert_eq!(storage.get(b"5").unwrap(), None);
+
+    storage.force_flush().unwrap();
+    check_value_meta(&storage, expected);
+    storage.put_with_meta(b"2", b"2333", 2).unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    check_value_meta(
+        &storage,
+        &[
+            (b"1", b"233", 1),
+            (b"2", b"2333", 2),
+            (b"3", b"", 3),
+            (b"4", b"23333", 4),
+        ],
+    );
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Write;
+
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+use tempfile::tempdir;
+
+use crate::key::{KeyBytes, KeySlice};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::wal::Wal;
+
+fn wal_options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    }
+}
+
+#[test]
+fn test_wal_recovery() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, wal_options()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    storage.put_with_meta(b"d", b"", 7).unwrap();
+    storage.put(b"e", b"1").unwrap();
+    // Dropped without flushing the memtables, as in a crash
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, wal_options()).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert_eq!(snapshot.levels[0].1.len(), 1);
+    assert_eq!(snapshot.imm_memtables.len(), 2);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"d").unwrap(), Some(Bytes::new()));
+    assert_eq!(storage.get(b"e").unwrap(), Some(Bytes::from_static(b"1")));
+
+    // The WALs are deleted once their memtables are flushed
+    storage.force_flush_all().unwrap();
+    let wal_files = || {
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|file| {
+                let path = file.as_ref().unwrap().path();
+                path.extension().is_some_and(|ext| ext == "wal")
+            })
+            .count()
+    };
+    assert_eq!(wal_files(), 1);
+    drop(storage);
+    let storage = MiniLsm::open(&dir, wal_options()).unwrap();
+    assert_eq!(wal_files(), 1);
+    assert!(storage.inner.state.read().imm_memtables.is_empty());
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+}
+
+#[test]
+fn test_wal_torn_frame() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("0.wal");
+    let wal = Wal::create(&path).unwrap();
+    wal.put(KeySlice::for_testing_from_slice_no_ts(b"a"), b"1")
+        .unwrap();
+    wal.put(KeySlice::for_testing_from_slice_no_ts(b"b"), b"2")
+        .unwrap();
+    drop(wal);
+    // A crash in the middle of appending the second frame
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(len - 3)
+        .unwrap();
+
+    let map = SkipMap::new();
+    let wal = Wal::recover(&path, &map).unwrap();
+    assert_eq!(map.len(), 1);
+    assert_eq!(
+        map.get(&KeyBytes::for_testing_from_bytes_no_ts(Bytes::from_static(
+            b"a"
+        )))
+        .unwrap()
+        .value(),
+        b"1\0".as_slice()
+    );
+    // The torn frame is cut off before appending
+    wal.put(KeySlice::for_testing_from_slice_no_ts(b"c"), b"3")
+        .unwrap();
+    drop(wal);
+    let map = SkipMap::new();
+    Wal::recover(&path, &map).unwrap();
+    let keys = map
+        .iter()
+        .map(|entry| entry.key().clone().into_inner())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["a", "c"]);
+
+    // A corrupt frame followed by others is not a torn write
+    let bytes = std::fs::read(&path).unwrap();
+    let corrupt = |offset: usize| {
+        let mut corrupted = bytes.clone();
+        corrupted[offset] ^= 1 << 4;
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(&corrupted)
+            .unwrap();
+    };
+    corrupt(10);
+    assert!(Wal::recover(&path, &SkipMap::new()).is_err());
+    // Nor is a corrupt length that points past the end of the file, which is not truncated
+    corrupt(2);
+    assert!(Wal::recover(&path, &SkipMap::new()).is_err());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), bytes.len() as u64);
+
+    // Space allocated but never written at the end is a torn frame
+    let mut zeroed = bytes.clone();
+    zeroed.extend([0; 16]);
+    std::fs::File::create(&path)
+        .unwrap()
+        .write_all(&zeroed)
+        .unwrap();
+    let map = SkipMap::new();
+    Wal::recover(&path, &map).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), bytes.len() as u64);
+}
+
+#[test]
+fn test_wal_put_batch_empty_value() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("0.wal");
+    let wal = Wal::create(&path).unwrap();
+    wal.put_batch(&[
+        (KeySlice::for_testing_from_slice_no_ts(b"a"), b"".as_slice()),
+        (KeySlice::for_testing_from_slice_no_ts(b"b"), b"1"),
+    ])
+    .unwrap();
+    wal.delete(KeySlice::for_testing_from_slice_no_ts(b"c"))
+        .unwrap();
+    drop(wal);
+
+    // The empty value is replayed as a put with the metadata byte, not as a delete
+    let map = SkipMap::new();
+    Wal::recover(&path, &map).unwrap();
+    let entries = map
+        .iter()
+        .map(|entry| (entry.key().clone().into_inner(), entry.value().clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            (Bytes::from_static(b"a"), Bytes::from_static(b"\0")),
+            (Bytes::from_static(b"b"), Bytes::from_static(b"1\0")),
+            (Bytes::from_static(b"c"), Bytes::new()),
+        ]
+    );
+}
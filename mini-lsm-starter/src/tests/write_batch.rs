@@ -0,0 +1,251 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mem_table::MemTable;
+use crate::wal::Wal;
+use crate::write_batch::{WriteBatch, WriteBatchBuilder};
+
+#[test]
+fn test_write_batch_recovery() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"b", b"0").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1").delete(b"b").put(b"c", b"");
+    storage.write_batch(&batch).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::new()));
+}
+
+#[test]
+fn test_write_batch_torn_frame() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("0.wal");
+    let wal = Wal::create(&path).unwrap();
+    wal.put(KeySlice::for_testing_from_slice_no_ts(b"a"), b"1")
+        .unwrap();
+    wal.write_batch(&[(b"b", Some(b"2")), (b"a", None)], TS_DEFAULT)
+        .unwrap();
+    drop(wal);
+    // The last record of the batch is lost in a crash, which drops all of the batch
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(len - 6)
+        .unwrap();
+
+    let map = SkipMap::new();
+    Wal::recover(&path, &map).unwrap();
+    assert_eq!(map.len(), 1);
+    assert_eq!(
+        map.get(&KeyBytes::for_testing_from_bytes_no_ts(Bytes::from_static(
+            b"a"
+        )))
+        .unwrap()
+        .value(),
+        b"1\0".as_slice()
+    );
+}
+
+#[test]
+fn test_write_batch_snapshot_is_atomic() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        target_sst_size: 1 << 10,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let writer = {
+        let storage = storage.clone();
+        std::thread::spawn(move || {
+            for idx in 0..1000u32 {
+                let value = idx.to_be_bytes();
+                let mut batch = WriteBatch::new();
+                batch.put(b"a", &value).put(b"b", &value).put(b"c", &value);
+                storage.write_batch(&batch).unwrap();
+            }
+        })
+    };
+    while !writer.is_finished() {
+        let snapshot = storage.snapshot().unwrap();
+        let mut iter = snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+        let mut values = Vec::new();
+        while iter.is_valid() {
+            values.push(Bytes::copy_from_slice(iter.value()));
+            iter.next().unwrap();
+        }
+        assert!(
+            values.is_empty()
+                || values == [values[0].clone(), values[0].clone(), values[0].clone()]
+        );
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn test_write_batch_builder_split() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        enable_wal: true,
+        target_sst_size: 1 << 20,
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let wal_bytes = || {
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wal"))
+            .map(|path| std::fs::metadata(path).unwrap().len() as usize)
+            .sum::<usize>()
+    };
+    let add_records = |builder: &mut WriteBatchBuilder| {
+        for i in 0..1000 {
+            let key = format!("key_{:04}", i);
+            match i % 10 {
+                0 => builder.delete(key.as_bytes()),
+                _ => builder.put(key.as_bytes(), b"value"),
+            };
+        }
+    };
+
+    let mut builder = WriteBatchBuilder::new().with_max_batch_size(4096);
+    add_records(&mut builder);
+    assert_eq!(builder.len(), 1000);
+    assert!(builder.num_batches() > 1);
+    let estimated_wal_bytes = builder.estimated_wal_bytes();
+    let batches = builder.build().unwrap();
+    assert_eq!(batches.iter().map(WriteBatch::len).sum::<usize>(), 1000);
+    assert!(
+        batches
+            .iter()
+            .all(|batch| batch.estimated_wal_bytes() <= 4096)
+    );
+    let before = wal_bytes();
+    for batch in &batches {
+        storage.write_batch(batch).unwrap();
+    }
+    assert_eq!(wal_bytes() - before, estimated_wal_bytes);
+    assert_eq!(
+        storage.get(b"key_0001").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+    assert_eq!(storage.get(b"key_0010").unwrap(), None);
+
+    // An atomic builder fails instead of splitting
+    let mut builder = WriteBatchBuilder::new()
+        .with_max_batch_size(4096)
+        .atomic(true);
+    add_records(&mut builder);
+    assert_eq!(builder.num_batches(), 1);
+    let err = builder.build().err().unwrap();
+    assert!(err.to_string().contains("max batch size"), "{}", err);
+    let mut builder = WriteBatchBuilder::new()
+        .with_max_batch_size(4096)
+        .atomic(true);
+    builder.put(b"a", b"1").delete(b"b");
+    assert_eq!(builder.build().unwrap().len(), 1);
+
+    // A record larger than the limit gets a batch of its own
+    let mut builder = WriteBatchBuilder::new().with_max_batch_size(64);
+    builder
+        .put(b"a", b"1")
+        .put(b"b", &[b'x'; 100])
+        .put(b"c", b"1");
+    assert_eq!(
+        builder
+            .build()
+            .unwrap()
+            .iter()
+            .map(WriteBatch::len)
+            .collect::<Vec<_>>(),
+        vec![1, 1, 1]
+    );
+
+    // The builder of the storage splits at the memtable size
+    let mut builder = storage.write_batch_builder();
+    for i in 0..20000 {
+        builder.put(format!("key_{:05}", i).as_bytes(), &[b'x'; 100]);
+    }
+    assert!(builder.num_batches() > 1);
+    assert!(
+        builder
+            .build()
+            .unwrap()
+            .iter()
+            .all(|batch| batch.estimated_wal_bytes() <= 1 << 20)
+    );
+}
+
+#[test]
+fn test_memtable_put_batch() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("0.wal");
+    let memtable = MemTable::create_with_wal(0, &path).unwrap();
+    memtable
+        .put_batch(&[
+            (KeySlice::from_slice(b"a", 5), b"1".as_slice()),
+            (KeySlice::from_slice(b"b", 5), b""),
+        ])
+        .unwrap();
+    assert!(
+        memtable
+            .put_batch(&[
+                (KeySlice::from_slice(b"c", 6), b"1".as_slice()),
+                (KeySlice::from_slice(b"d", 7), b"1"),
+            ])
+            .is_err()
+    );
+    assert_eq!(
+        memtable.get_entry_with_ts(b"a", 5),
+        Some(Some(Bytes::from_static(b"1")))
+    );
+    assert_eq!(
+        memtable.get_entry_with_ts(b"b", 5),
+        Some(Some(Bytes::new()))
+    );
+    drop(memtable);
+
+    let memtable = MemTable::recover_from_wal(0, &path).unwrap();
+    assert_eq!(
+        memtable.get_entry_with_ts(b"a", 5),
+        Some(Some(Bytes::from_static(b"1")))
+    );
+    assert_eq!(
+        memtable.get_entry_with_ts(b"b", 5),
+        Some(Some(Bytes::new()))
+    );
+    assert_eq!(memtable.get_entry_with_ts(b"a", 4), None);
+}
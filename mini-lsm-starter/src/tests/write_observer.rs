@@ -0,0 +1,108 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::wal::Wal;
+use crate::write_observer::{FileKind, WriteObserver, WriteObservers};
+
+/// The number of writes and bytes of each file kind.
+#[derive(Default)]
+struct Recorder(Mutex<HashMap<FileKind, (u64, u64)>>);
+
+impl WriteObserver for Arc<Recorder> {
+    fn on_write(&self, file_kind: FileKind, bytes: u64) {
+        let mut writes = self.0.lock();
+        let (count, total) = writes.entry(file_kind).or_default();
+        *count += 1;
+        *total += bytes;
+    }
+}
+
+impl Recorder {
+    fn get(&self, file_kind: FileKind) -> (u64, u64) {
+        self.0.lock().get(&file_kind).copied().unwrap_or_default()
+    }
+}
+
+fn dir_size(dir: &std::path::Path, extension: &str) -> u64 {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .map(|path| path.metadata().unwrap().len())
+        .sum()
+}
+
+#[test]
+fn test_write_observer() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        sst_sync_chunk_size: Some(256),
+        ..LsmStorageOptions::default_for_week1_test()
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let manifest_size = std::fs::metadata(dir.path().join("MANIFEST"))
+        .unwrap()
+        .len();
+    let recorder = Arc::new(Recorder::default());
+    storage.add_write_observer(Box::new(recorder.clone()));
+    for idx in 0..100 {
+        let key = format!("key_{:05}", idx);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    let sst_size = dir_size(dir.path(), "sst");
+    let (sst_writes, sst_bytes) = recorder.get(FileKind::Sst);
+    assert_eq!(sst_bytes, sst_size);
+    // One notification per chunk
+    assert_eq!(sst_writes, sst_size.div_ceil(256));
+    let (_, manifest_bytes) = recorder.get(FileKind::Manifest);
+    let new_manifest_size = std::fs::metadata(dir.path().join("MANIFEST"))
+        .unwrap()
+        .len();
+    assert_eq!(manifest_bytes, new_manifest_size - manifest_size);
+    assert!(manifest_bytes > 0);
+    assert_eq!(recorder.get(FileKind::Wal), (0, 0));
+}
+
+#[test]
+fn test_wal_write_observer() {
+    let dir = tempdir().unwrap();
+    let observers = Arc::new(WriteObservers::default());
+    let recorder = Arc::new(Recorder::default());
+    observers.add(Box::new(recorder.clone()));
+    let path = dir.path().join("0.wal");
+    let wal = Wal::create(&path).unwrap().with_write_observers(observers);
+    wal.put(KeySlice::for_testing_from_slice_no_ts(b"key"), b"value")
+        .unwrap();
+    wal.delete(KeySlice::for_testing_from_slice_no_ts(b"key"))
+        .unwrap();
+    wal.put_batch(&[
+        (KeySlice::for_testing_from_slice_no_ts(b"a"), b"1"),
+        (KeySlice::for_testing_from_slice_no_ts(b"b"), b""),
+    ])
+    .unwrap();
+    wal.sync().unwrap();
+    let (writes, bytes) = recorder.get(FileKind::Wal);
+    assert_eq!(writes, 3);
+    assert_eq!(bytes, std::fs::metadata(&path).unwrap().len());
+}
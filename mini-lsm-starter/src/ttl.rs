@@ -0,0 +1,248 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expiry of the entries written while a TTL is configured. The expiry time is appended to each
+//! value as milliseconds since the UNIX epoch. Reads hide expired entries, and compactions turn
+//! them into deletes.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use bytes::{Buf, BufMut};
+
+use crate::iterators::StorageIterator;
+
+#[derive(Debug, Clone)]
+pub struct TtlOptions {
+    /// How long an entry lives after it is written.
+    pub ttl: Duration,
+    /// How often the compaction thread looks for SSTs that are mostly expired.
+    pub compaction_interval: Duration,
+    /// The time the expiry times are set and checked against.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl TtlOptions {
+    /// Expire the entries `ttl` after they are written, by the system time.
+    pub fn new(ttl: Duration, compaction_interval: Duration) -> Self {
+        Self {
+            ttl,
+            compaction_interval,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub(crate) fn now_millis(&self) -> u64 {
+        self.clock.now_millis()
+    }
+}
+
+/// The current time of the expiry, in milliseconds since the UNIX epoch.
+pub trait Clock: Debug + Send + Sync {
+    fn now_millis(&self) -> u64;
+}
+
+/// The system time.
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+}
+
+/// A clock that only moves when it is advanced, e.g., to expire entries in tests without
+/// waiting.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now_millis: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now_millis),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// The length of the expiry time appended to a value.
+pub(crate) const EXPIRY_LEN: usize = 8;
+
+/// The number of buckets of the expiry histogram of an SST.
+const EXPIRY_HISTOGRAM_BUCKETS: usize = 8;
+
+/// Append the expiry time to a value. Deletes are stored as-is.
+pub(crate) fn append_expiry(value: &[u8], expiry: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(value.len() + EXPIRY_LEN);
+    buf.extend_from_slice(value);
+    buf.put_u64(expiry);
+    buf
+}
+
+/// Split a stored value into the value and its expiry time. Deletes never expire.
+pub(crate) fn split_expiry(value: &[u8]) -> (&[u8], u64) {
+    if value.len() < EXPIRY_LEN {
+        return (value, u64::MAX);
+    }
+    let (value, mut expiry) = value.split_at(value.len() - EXPIRY_LEN);
+    (value, expiry.get_u64())
+}
+
+/// Whether a stored value has expired at `now`.
+pub(crate) fn is_expired(value: &[u8], now: u64) -> bool {
+    split_expiry(value).1 <= now
+}
+
+/// An equi-depth histogram of the expiry times of the entries in an SST, so that the SSTs that
+/// are mostly expired can be found without reading them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExpiryHistogram {
+    /// The earliest expiry time.
+    pub min_expiry: u64,
+    /// The latest expiry time and the number of entries of each bucket, in ascending order.
+    pub buckets: Vec<(u64, u64)>,
+}
+
+impl ExpiryHistogram {
+    pub(crate) fn build(mut expiries: Vec<u64>) -> Self {
+        if expiries.is_empty() {
+            return Self::default();
+        }
+        expiries.sort_unstable();
+        let bucket_len = expiries.len().div_ceil(EXPIRY_HISTOGRAM_BUCKETS);
+        Self {
+            min_expiry: expiries[0],
+            buckets: expiries
+                .chunks(bucket_len)
+                .map(|chunk| (*chunk.last().unwrap(), chunk.len() as u64))
+                .collect(),
+        }
+    }
+
+    /// A lower bound of the number of entries expired at `now`.
+    pub fn expired_entries(&self, now: u64) -> u64 {
+        self.buckets
+            .iter()
+            .take_while(|(max_expiry, _)| *max_expiry <= now)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Whether any entry has expired at `now`.
+    pub fn has_expired(&self, now: u64) -> bool {
+        !self.buckets.is_empty() && self.min_expiry <= now
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u64(self.min_expiry);
+        buf.put_u32(self.buckets.len() as u32);
+        for (max_expiry, count) in &self.buckets {
+            buf.put_u64(*max_expiry);
+            buf.put_u64(*count);
+        }
+    }
+
+    pub fn decode(buf: &mut impl Buf) -> Result<Self> {
+        if buf.remaining() < 12 {
+            bail!("expiry histogram is truncated");
+        }
+        let min_expiry = buf.get_u64();
+        let num_buckets = buf.get_u32() as usize;
+        if buf.remaining() < num_buckets * 16 {
+            bail!("expiry histogram is truncated");
+        }
+        let buckets = (0..num_buckets)
+            .map(|_| (buf.get_u64(), buf.get_u64()))
+            .collect();
+        Ok(Self {
+            min_expiry,
+            buckets,
+        })
+    }
+}
+
+/// Presents the entries expired at `now` as deletes, so that a compaction drops them from the
+/// bottom level and shadows the older versions of the keys elsewhere. Passes all entries through
+/// if `now` is `None`, i.e., no TTL is configured.
+pub(crate) struct ExpiryFilterIterator<I> {
+    iter: I,
+    now: Option<u64>,
+}
+
+impl<I> ExpiryFilterIterator<I> {
+    pub(crate) fn new(iter: I, now: Option<u64>) -> Self {
+        Self { iter, now }
+    }
+}
+
+impl<I: StorageIterator> StorageIterator for ExpiryFilterIterator<I> {
+    type KeyType<'a>
+        = I::KeyType<'a>
+    where
+        Self: 'a;
+
+    fn key(&self) -> Self::KeyType<'_> {
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        let value = self.iter.value();
+        if self.now.is_some_and(|now| is_expired(value, now)) {
+            &[]
+        } else {
+            value
+        }
+    }
+
+    fn value_meta(&self) -> u8 {
+        self.iter.value_meta()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.iter.is_deleted()
+            || self
+                .now
+                .is_some_and(|now| is_expired(self.iter.value(), now))
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.iter.num_active_iterators()
+    }
+}
@@ -1,4 +1,3 @@
-// REMOVE THIS LINE after fully implementing this functionality
 // Copyright (c) 2022-2025 Alex Chi Z
 //
 // Licensed under the Apache License, Version 2.0 (the "License");
@@ -12,43 +11,433 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-#![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
-#![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
-use anyhow::Result;
-use bytes::Bytes;
+use anyhow::{Context, Result, bail, ensure};
+use bytes::{Buf, BufMut, Bytes};
 use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, IoSlice, Read, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::key::KeySlice;
+use crate::block::EMPTY_VALUE_FLAG;
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT, TS_RANGE_BEGIN};
+use crate::range_tombstone::RangeTombstones;
+use crate::write_observer::{FileKind, WriteObservers};
 
+/*
+----------------------------------------------------------------------------
+|                          Frame                                     | ... |
+----------------------------------------------------------------------------
+| payload_len (4B) | checksum (4B) of payload_len | ts (8B) | record | ... | checksum (4B) of the payload | ... |
+----------------------------------------------------------------------------
+
+-------------------------------------------------------------------------------
+|                        Record                                               |
+-------------------------------------------------------------------------------
+| key_len (2B) | key (keylen) | value_len (4B) | value (varlen) | meta (1B) |
+-------------------------------------------------------------------------------
+
+Each write appends one frame, a batch one frame for all its records, so that it is replayed
+entirely or not at all. The records of a frame share its timestamp. The top bit of the payload
+length is set if the timestamp is there, the frames of older WALs have none and are replayed at
+`TS_DEFAULT`. The next bit is set if the checksum of the payload length follows it, which
+tells a torn frame at the end of the WAL from a corrupt length; the frames of older WALs have
+none and their lengths are trusted. The top bit of the key length is set for a put of an empty value, as in
+the blocks. A record with an empty value without it is a delete. The top bit of the value length
+is set for a range delete, whose key and value are the start and the exclusive end of the range.
+*/
 pub struct Wal {
-    file: Arc<Mutex<BufWriter<File>>>,
+    file: Arc<Mutex<WalWriter>>,
+    path: PathBuf,
+    /// Notified of the bytes of each frame as it is appended.
+    observers: Option<Arc<WriteObservers>>,
+}
+
+struct WalWriter {
+    file: File,
+    /// Encode buffer reused across frames, so that the write path does not allocate.
+    buf: Vec<u8>,
 }
 
+/// Set in the value length of a range delete record.
+const RANGE_DELETE_FLAG: u32 = 1 << 31;
+/// Set in the payload length of a frame starting with a timestamp.
+const TIMESTAMP_FLAG: u32 = 1 << 31;
+/// Set in the payload length of a frame whose length is followed by its checksum.
+const LENGTH_CHECKSUM_FLAG: u32 = 1 << 30;
+
+const FRAME_LENGTH_SIZE: usize = 4;
+const FRAME_HEADER_SIZE: usize = FRAME_LENGTH_SIZE + 4;
+const FRAME_CHECKSUM_SIZE: usize = 4;
+
 impl Wal {
-    pub fn create(_path: impl AsRef<Path>) -> Result<Self> {
-        unimplemented!()
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .context("failed to create WAL")?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(WalWriter {
+                file,
+                buf: Vec::new(),
+            })),
+            path: path.as_ref().to_path_buf(),
+            observers: None,
+        })
+    }
+
+    /// Notify `observers` of the writes to the WAL.
+    pub fn with_write_observers(mut self, observers: Arc<WriteObservers>) -> Self {
+        self.observers = Some(observers);
+        self
+    }
+
+    /// The path of the WAL file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn notify(&self, bytes: usize) {
+        if let Some(observers) = &self.observers {
+            observers.notify(FileKind::Wal, bytes as u64);
+        }
+    }
+
+    /// Replay the frames of a WAL into `skiplist`, in the encoding of the memtables: each value
+    /// followed by its metadata byte, and deletes as empty values. A frame cut short at the end
+    /// of the file, as left by a crash while appending it, was never acknowledged and is
+    /// truncated away, as are the zeros of space allocated for it but never written. A corrupt
+    /// frame followed by more data fails the recovery, as does a corrupt length.
+    pub fn recover(path: impl AsRef<Path>, skiplist: &SkipMap<KeyBytes, Bytes>) -> Result<Self> {
+        Self::recover_with_range_deletes(path, skiplist, &mut RangeTombstones::new())
+    }
+
+    /// Replay a WAL like `recover`, collecting the ranges of the range deletes into `tombstones`.
+    /// A range delete also deletes the keys of the range replayed before it.
+    pub fn recover_with_range_deletes(
+        path: impl AsRef<Path>,
+        skiplist: &SkipMap<KeyBytes, Bytes>,
+        tombstones: &mut RangeTombstones,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(path)
+            .context("failed to recover from WAL")?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let mut rbuf = &buf[..];
+        while rbuf.len() >= FRAME_LENGTH_SIZE {
+            if rbuf.iter().all(|byte| *byte == 0) {
+                // Space allocated but never written by a crash while appending
+                break;
+            }
+            let offset = buf.len() - rbuf.len();
+            let payload_len_flags = (&rbuf[..FRAME_LENGTH_SIZE]).get_u32();
+            let header_size = match payload_len_flags & LENGTH_CHECKSUM_FLAG != 0 {
+                true => FRAME_HEADER_SIZE,
+                false => FRAME_LENGTH_SIZE,
+            };
+            if rbuf.len() < header_size {
+                break;
+            }
+            if header_size == FRAME_HEADER_SIZE
+                && crc32fast::hash(&rbuf[..FRAME_LENGTH_SIZE])
+                    != (&rbuf[FRAME_LENGTH_SIZE..FRAME_HEADER_SIZE]).get_u32()
+            {
+                // The end of the frame is unknown, so it cannot be told from a torn one
+                bail!(
+                    "checksum mismatch in the length of the frame at offset {} of {}",
+                    offset,
+                    path.display()
+                );
+            }
+            let payload_len =
+                (payload_len_flags & !(TIMESTAMP_FLAG | LENGTH_CHECKSUM_FLAG)) as usize;
+            let frame_len = header_size + payload_len + FRAME_CHECKSUM_SIZE;
+            if rbuf.len() < frame_len {
+                break;
+            }
+            let payload = &rbuf[header_size..header_size + payload_len];
+            let checksum = (&rbuf[header_size + payload_len..frame_len]).get_u32();
+            if crc32fast::hash(payload) != checksum {
+                if rbuf.len() == frame_len {
+                    break;
+                }
+                bail!(
+                    "checksum mismatch in the frame at offset {} of {}",
+                    offset,
+                    path.display()
+                );
+            }
+            decode_frame(
+                payload,
+                payload_len_flags & TIMESTAMP_FLAG != 0,
+                skiplist,
+                tombstones,
+            )
+            .with_context(|| format!("corrupt WAL {}", path.display()))?;
+            rbuf = &rbuf[frame_len..];
+        }
+        if !rbuf.is_empty() {
+            // Cut the torn frame off, so that the frames appended from now on follow the last
+            // complete one.
+            file.set_len((buf.len() - rbuf.len()) as u64)?;
+        }
+        Ok(Self {
+            file: Arc::new(Mutex::new(WalWriter {
+                file,
+                buf: Vec::new(),
+            })),
+            path: path.to_path_buf(),
+            observers: None,
+        })
+    }
+
+    pub fn put(&self, key: KeySlice, value: &[u8]) -> Result<()> {
+        self.put_with_meta(key, value, 0)
+    }
+
+    pub fn put_with_meta(&self, key: KeySlice, value: &[u8], meta: u8) -> Result<()> {
+        self.write_records(&[(key.key_ref(), Some(value))], meta, key.ts())
+    }
+
+    pub fn delete(&self, key: KeySlice) -> Result<()> {
+        self.write_records(&[(key.key_ref(), None)], 0, key.ts())
     }
 
-    pub fn recover(_path: impl AsRef<Path>, _skiplist: &SkipMap<Bytes, Bytes>) -> Result<Self> {
-        unimplemented!()
+    /// Append a range delete of `[start, end)` at timestamp `ts`.
+    pub fn delete_range(&self, start: &[u8], end: &[u8], ts: u64) -> Result<()> {
+        let mut writer = self.file.lock();
+        let WalWriter { file, buf } = &mut *writer;
+        buf.clear();
+        buf.put_u32(0);
+        buf.put_u32(0);
+        buf.put_u64(ts);
+        buf.put_u16(start.len() as u16);
+        buf.put_slice(start);
+        buf.put_u32(end.len() as u32 | RANGE_DELETE_FLAG);
+        buf.put_slice(end);
+        buf.put_u8(0);
+        seal_frame(buf);
+        write_frame(file, buf)?;
+        self.notify(buf.len());
+        Ok(())
     }
 
-    pub fn put(&self, _key: &[u8], _value: &[u8]) -> Result<()> {
-        unimplemented!()
+    /// Append the puts and deletes (with a `None` value) of a write batch at timestamp `ts` as a
+    /// single frame.
+    ///
+    /// Only the frame header, the length fields, the metadata bytes and the checksum are encoded
+    /// into the reused buffer. Keys and values are written from the caller's memory with a single
+    /// vectored write to the file.
+    pub fn write_batch(&self, records: &[(&[u8], Option<&[u8]>)], ts: u64) -> Result<()> {
+        let mut writer = self.file.lock();
+        let WalWriter { file, buf } = &mut *writer;
+        buf.clear();
+        let payload_len = 8 + records
+            .iter()
+            .map(|(key, value)| record_size(key.len(), value.map_or(0, <[u8]>::len)))
+            .sum::<usize>();
+        buf.put_u32(payload_len as u32 | TIMESTAMP_FLAG | LENGTH_CHECKSUM_FLAG);
+        buf.put_u32(crc32fast::hash(&buf[..FRAME_LENGTH_SIZE]));
+        buf.put_u64(ts);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&buf[FRAME_HEADER_SIZE..]);
+        for (key, value) in records {
+            let (key_len_flags, value, meta) = record_parts(*value, 0);
+            let mut header = [0; 7];
+            (&mut header[..2]).put_u16(key.len() as u16 | key_len_flags);
+            (&mut header[2..6]).put_u32(value.len() as u32);
+            header[6] = meta;
+            hasher.update(&header[..2]);
+            hasher.update(key);
+            hasher.update(&header[2..6]);
+            hasher.update(value);
+            hasher.update(&header[6..]);
+            buf.put_slice(&header);
+        }
+        buf.put_u32(hasher.finalize());
+        let (frame_header, rest) = buf.split_at(FRAME_HEADER_SIZE + 8);
+        let (headers, checksum) = rest.split_at(rest.len() - FRAME_CHECKSUM_SIZE);
+        let mut slices = Vec::with_capacity(records.len() * 5 + 2);
+        slices.push(IoSlice::new(frame_header));
+        for ((key, value), header) in records.iter().zip(headers.chunks_exact(7)) {
+            slices.push(IoSlice::new(&header[..2]));
+            slices.push(IoSlice::new(key));
+            slices.push(IoSlice::new(&header[2..6]));
+            slices.push(IoSlice::new(value.unwrap_or_default()));
+            slices.push(IoSlice::new(&header[6..]));
+        }
+        slices.push(IoSlice::new(checksum));
+        let bytes = slices.iter().map(|slice| slice.len()).sum();
+        write_all_vectored(file, &mut slices)?;
+        self.notify(bytes);
+        Ok(())
     }
 
-    /// Implement this in week 3, day 5; if you want to implement this earlier, use `&[u8]` as the key type.
-    pub fn put_batch(&self, _data: &[(KeySlice, &[u8])]) -> Result<()> {
-        unimplemented!()
+    fn write_records(&self, records: &[(&[u8], Option<&[u8]>)], meta: u8, ts: u64) -> Result<()> {
+        let mut writer = self.file.lock();
+        let WalWriter { file, buf } = &mut *writer;
+        buf.clear();
+        buf.put_u32(0);
+        buf.put_u32(0);
+        buf.put_u64(ts);
+        for (key, value) in records {
+            encode_record(buf, key, *value, meta);
+        }
+        seal_frame(buf);
+        write_frame(file, buf)?;
+        self.notify(buf.len());
+        Ok(())
+    }
+
+    /// Append the puts of a batch as a single frame, see `write_batch`. The keys of a batch must
+    /// have the same timestamp, that of the frame.
+    pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
+        let ts = data.first().map_or(TS_DEFAULT, |(key, _)| key.ts());
+        ensure!(
+            data.iter().all(|(key, _)| key.ts() == ts),
+            "the keys of a WAL batch must have the same timestamp"
+        );
+        let records = data
+            .iter()
+            .map(|(key, value)| (key.key_ref(), Some(*value)))
+            .collect::<Vec<_>>();
+        self.write_batch(&records, ts)
     }
 
     pub fn sync(&self) -> Result<()> {
-        unimplemented!()
+        self.file.lock().file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// The encoded size of a record with a value of `value_len` bytes, 0 for a delete.
+pub(crate) fn record_size(key_len: usize, value_len: usize) -> usize {
+    2 + key_len + 4 + value_len + 1
+}
+
+/// The size of the frame of a batch whose records take `records_size` bytes.
+pub(crate) fn frame_size(records_size: usize) -> usize {
+    FRAME_HEADER_SIZE + 8 + records_size + FRAME_CHECKSUM_SIZE
+}
+
+/// The flags of the key length, the value and the metadata byte of a put, or of a delete if
+/// `value` is `None`.
+fn record_parts(value: Option<&[u8]>, meta: u8) -> (u16, &[u8], u8) {
+    match value {
+        Some([]) => (EMPTY_VALUE_FLAG, &[][..], meta),
+        Some(value) => (0, value, meta),
+        None => (0, &[][..], 0),
+    }
+}
+
+/// Encode a put, or a delete if `value` is `None`.
+fn encode_record(buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>, meta: u8) {
+    let (key_len_flags, value, meta) = record_parts(value, meta);
+    buf.put_u16(key.len() as u16 | key_len_flags);
+    buf.put_slice(key);
+    buf.put_u32(value.len() as u32);
+    buf.put_slice(value);
+    buf.put_u8(meta);
+}
+
+/// Fill in the payload length and its checksum at the start of a frame holding its timestamp and
+/// records, and append their checksum.
+fn seal_frame(buf: &mut Vec<u8>) {
+    let payload_len = buf.len() - FRAME_HEADER_SIZE;
+    (&mut buf[..FRAME_LENGTH_SIZE])
+        .put_u32(payload_len as u32 | TIMESTAMP_FLAG | LENGTH_CHECKSUM_FLAG);
+    let length_checksum = crc32fast::hash(&buf[..FRAME_LENGTH_SIZE]);
+    (&mut buf[FRAME_LENGTH_SIZE..FRAME_HEADER_SIZE]).put_u32(length_checksum);
+    let checksum = crc32fast::hash(&buf[FRAME_HEADER_SIZE..]);
+    buf.put_u32(checksum);
+}
+
+/// Append a frame and hand it to the OS, so that the write survives a crash of the process once
+/// it is acknowledged. `Wal::sync` makes it survive a crash of the machine.
+fn write_frame(file: &mut File, frame: &[u8]) -> Result<()> {
+    file.write_all(frame).context("failed to write to WAL")
+}
+
+/// Insert the records of a frame into a memtable skiplist and its range tombstones, at the
+/// timestamp the payload starts with if `timestamped` is set.
+fn decode_frame(
+    mut payload: &[u8],
+    timestamped: bool,
+    skiplist: &SkipMap<KeyBytes, Bytes>,
+    tombstones: &mut RangeTombstones,
+) -> Result<()> {
+    let ts = match timestamped {
+        true if payload.remaining() < 8 => bail!("frame timestamp is truncated"),
+        true => payload.get_u64(),
+        false => TS_DEFAULT,
+    };
+    while payload.has_remaining() {
+        if payload.remaining() < 2 {
+            bail!("record header is truncated");
+        }
+        let key_len_flags = payload.get_u16();
+        let key_len = (key_len_flags & !EMPTY_VALUE_FLAG) as usize;
+        if payload.remaining() < key_len + 4 {
+            bail!("record key is truncated");
+        }
+        let key = Bytes::copy_from_slice(&payload[..key_len]);
+        payload.advance(key_len);
+        let value_len_flags = payload.get_u32();
+        let value_len = (value_len_flags & !RANGE_DELETE_FLAG) as usize;
+        if payload.remaining() < value_len + 1 {
+            bail!("record value is truncated");
+        }
+        let value = &payload[..value_len];
+        payload.advance(value_len);
+        let meta = payload.get_u8();
+        if value_len_flags & RANGE_DELETE_FLAG != 0 {
+            let lower = KeyBytes::from_bytes_with_ts(key.clone(), TS_RANGE_BEGIN);
+            let upper = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(value), TS_RANGE_BEGIN);
+            let mut deleted = skiplist
+                .range((Bound::Included(lower), Bound::Excluded(upper)))
+                .map(|entry| entry.key().key_ref().to_vec())
+                .collect::<Vec<_>>();
+            deleted.dedup();
+            for deleted_key in deleted {
+                skiplist.insert(
+                    KeyBytes::from_bytes_with_ts(deleted_key.into(), ts),
+                    Bytes::new(),
+                );
+            }
+            tombstones.insert(&key, value, ts);
+        } else if value.is_empty() && key_len_flags & EMPTY_VALUE_FLAG == 0 {
+            skiplist.insert(KeyBytes::from_bytes_with_ts(key, ts), Bytes::new());
+        } else {
+            let mut stored = Vec::with_capacity(value_len + 1);
+            stored.extend_from_slice(value);
+            stored.push(meta);
+            skiplist.insert(KeyBytes::from_bytes_with_ts(key, ts), stored.into());
+        }
+    }
+    Ok(())
+}
+
+/// Write all slices, retrying on partial writes. `Write::write_all_vectored` is not stable yet.
+fn write_all_vectored(writer: &mut impl Write, mut slices: &mut [IoSlice<'_>]) -> Result<()> {
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => {
+                return Err(std::io::Error::from(ErrorKind::WriteZero))
+                    .context("failed to write to WAL");
+            }
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e).context("failed to write to WAL"),
+        }
     }
+    Ok(())
 }
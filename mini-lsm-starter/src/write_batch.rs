@@ -0,0 +1,234 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use crate::column_family::ColumnFamily;
+use crate::lsm_storage::WriteBatchRecord;
+use crate::wal;
+
+/// A condition on the current value of a key, checked when a `WriteBatch` is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// The key does not exist.
+    Absent(Bytes),
+    /// The key exists with the given value.
+    ValueMatches(Bytes, Bytes),
+}
+
+/// A batch of puts and deletes applied atomically with `MiniLsm::write_batch`, or with
+/// `MiniLsm::write_conditional` only if all of its preconditions hold.
+#[derive(Default)]
+pub struct WriteBatch {
+    records: Vec<WriteBatchRecord<Bytes>>,
+    preconditions: Vec<Precondition>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.records.push(WriteBatchRecord::Put(
+            Bytes::copy_from_slice(key),
+            Bytes::copy_from_slice(value),
+        ));
+        self
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.records
+            .push(WriteBatchRecord::Del(Bytes::copy_from_slice(key)));
+        self
+    }
+
+    /// Put a key into a column family. A batch over several column families is atomic too.
+    pub fn put_cf(&mut self, cf: &ColumnFamily, key: &[u8], value: &[u8]) -> &mut Self {
+        self.put(&cf.key(key), value)
+    }
+
+    pub fn delete_cf(&mut self, cf: &ColumnFamily, key: &[u8]) -> &mut Self {
+        self.delete(&cf.key(key))
+    }
+
+    /// Put the key only if it does not exist.
+    pub fn put_if_absent(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.preconditions
+            .push(Precondition::Absent(Bytes::copy_from_slice(key)));
+        self.put(key, value)
+    }
+
+    /// Delete the key only if its value is `expected`.
+    pub fn delete_if_value_matches(&mut self, key: &[u8], expected: &[u8]) -> &mut Self {
+        self.preconditions.push(Precondition::ValueMatches(
+            Bytes::copy_from_slice(key),
+            Bytes::copy_from_slice(expected),
+        ));
+        self.delete(key)
+    }
+
+    pub fn records(&self) -> &[WriteBatchRecord<Bytes>] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The bytes of the WAL frame the batch is logged as.
+    pub fn estimated_wal_bytes(&self) -> usize {
+        wal::frame_size(self.records.iter().map(encoded_size).sum())
+    }
+
+    /// The preconditions in the order they were added, which is the order of the indices
+    /// returned for the failed ones.
+    pub fn preconditions(&self) -> &[Precondition] {
+        &self.preconditions
+    }
+}
+
+impl AsRef<[WriteBatchRecord<Bytes>]> for WriteBatch {
+    fn as_ref(&self) -> &[WriteBatchRecord<Bytes>] {
+        &self.records
+    }
+}
+
+fn encoded_size(record: &WriteBatchRecord<Bytes>) -> usize {
+    match record {
+        WriteBatchRecord::Put(key, value) => wal::record_size(key.len(), value.len()),
+        WriteBatchRecord::Del(key) => wal::record_size(key.len(), 0),
+    }
+}
+
+/// Builds the puts and deletes of a large write into `WriteBatch`es of at most
+/// `max_batch_bytes` of WAL each, so that no single batch grows the memtable far past the size it
+/// is frozen at. Each batch is atomic on its own, but a crash may leave only the first ones
+/// applied, unless the builder is `atomic`, in which case it fails instead of splitting.
+pub struct WriteBatchBuilder {
+    max_batch_bytes: Option<usize>,
+    atomic: bool,
+    batches: Vec<WriteBatch>,
+    current: WriteBatch,
+    /// The WAL bytes of the records of `current`, without the frame.
+    current_bytes: usize,
+}
+
+impl Default for WriteBatchBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteBatchBuilder {
+    /// A builder of a single batch of any size.
+    pub fn new() -> Self {
+        Self {
+            max_batch_bytes: None,
+            atomic: false,
+            batches: Vec::new(),
+            current: WriteBatch::new(),
+            current_bytes: 0,
+        }
+    }
+
+    /// Start a new batch before a record would take the current one past `bytes` of WAL. A
+    /// record larger than that on its own gets a batch of its own.
+    pub fn with_max_batch_size(mut self, bytes: usize) -> Self {
+        self.max_batch_bytes = Some(bytes);
+        self
+    }
+
+    /// Require all the records to be written in one atomic batch: `build` fails if they do not
+    /// fit into `max_batch_size`.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.split_before(wal::record_size(key.len(), value.len()));
+        self.current.put(key, value);
+        self
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.split_before(wal::record_size(key.len(), 0));
+        self.current.delete(key);
+        self
+    }
+
+    fn split_before(&mut self, record_bytes: usize) {
+        if let Some(max_batch_bytes) = self.max_batch_bytes
+            && !self.atomic
+            && !self.current.is_empty()
+            && wal::frame_size(self.current_bytes + record_bytes) > max_batch_bytes
+        {
+            self.batches.push(std::mem::take(&mut self.current));
+            self.current_bytes = 0;
+        }
+        self.current_bytes += record_bytes;
+    }
+
+    /// The number of records added so far.
+    pub fn len(&self) -> usize {
+        self.batches.iter().map(WriteBatch::len).sum::<usize>() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of batches the records are split into so far.
+    pub fn num_batches(&self) -> usize {
+        self.batches.len() + usize::from(!self.current.is_empty())
+    }
+
+    /// The bytes of the WAL frames of all the batches.
+    pub fn estimated_wal_bytes(&self) -> usize {
+        self.batches
+            .iter()
+            .map(WriteBatch::estimated_wal_bytes)
+            .sum::<usize>()
+            + match self.current.is_empty() {
+                true => 0,
+                false => wal::frame_size(self.current_bytes),
+            }
+    }
+
+    /// The batches to write in order with `MiniLsm::write_batch`. Fails if the builder is
+    /// `atomic` and the records do not fit into one batch.
+    pub fn build(mut self) -> Result<Vec<WriteBatch>> {
+        let bytes = self.estimated_wal_bytes();
+        if let Some(max_batch_bytes) = self.max_batch_bytes
+            && self.atomic
+            && bytes > max_batch_bytes
+        {
+            bail!(
+                "atomic write batch of {} bytes exceeds the max batch size of {} bytes",
+                bytes,
+                max_batch_bytes
+            );
+        }
+        if !self.current.is_empty() {
+            self.batches.push(self.current);
+        }
+        Ok(self.batches)
+    }
+}
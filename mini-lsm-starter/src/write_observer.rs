@@ -0,0 +1,49 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notifications of the bytes written to the files of a storage, e.g., to charge them to an I/O
+//! controller such as a cgroup or a QoS policy. See `MiniLsm::add_write_observer`.
+
+use parking_lot::RwLock;
+
+/// The kind of file a write went to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Wal,
+    Sst,
+    Manifest,
+}
+
+/// Receives a notification for every write to the files of a storage. It is called from the
+/// thread doing the write, including the flush and compaction threads, so it should return
+/// quickly.
+pub trait WriteObserver: Send + Sync {
+    fn on_write(&self, file_kind: FileKind, bytes: u64);
+}
+
+/// The observers of a storage, shared by the writers of its files.
+#[derive(Default)]
+pub struct WriteObservers(RwLock<Vec<Box<dyn WriteObserver>>>);
+
+impl WriteObservers {
+    pub fn add(&self, observer: Box<dyn WriteObserver>) {
+        self.0.write().push(observer);
+    }
+
+    pub(crate) fn notify(&self, file_kind: FileKind, bytes: u64) {
+        for observer in self.0.read().iter() {
+            observer.on_write(file_kind, bytes);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Allocation-count regression tests for the hot loops of compaction and scans. They live in a
+//! test binary of their own, as the counting allocator replaces the global allocator of the whole
+//! binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use mini_lsm_starter::block::{BlockBuilder, BlockIterator};
+use mini_lsm_starter::iterators::merge_iterator::MergeIterator;
+use mini_lsm_starter::key::KeySlice;
+use mini_lsm_starter::table::{SsTable, SsTableBuilder, SsTableIterator};
+use mini_lsm_starter::wal::Wal;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|cnt| cnt.set(cnt.get() + 1));
+        ALLOCATED_BYTES.with(|cnt| cnt.set(cnt.get() + layout.size()));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|cnt| cnt.set(cnt.get() + 1));
+        ALLOCATED_BYTES.with(|cnt| cnt.set(cnt.get() + new_size));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Count the allocations made by the current thread when running `f`.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|cnt| cnt.get());
+    let result = f();
+    let after = ALLOCATIONS.with(|cnt| cnt.get());
+    (result, after - before)
+}
+
+/// Count the bytes allocated by the current thread when running `f`.
+fn count_allocated_bytes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED_BYTES.with(|cnt| cnt.get());
+    let result = f();
+    let after = ALLOCATED_BYTES.with(|cnt| cnt.get());
+    (result, after - before)
+}
+
+const NUM_OF_KEYS: usize = 20000;
+
+fn build_sst(
+    path: impl AsRef<std::path::Path>,
+    id: usize,
+    range: impl Iterator<Item = usize>,
+) -> Arc<SsTable> {
+    let mut builder = SsTableBuilder::new(4096);
+    for idx in range {
+        let key = format!("key_{:010}", idx);
+        let value = format!("value_{:010}_{}", idx, id);
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(key.as_bytes()),
+            value.as_bytes(),
+        );
+    }
+    Arc::new(
+        builder
+            .build(id, None, path.as_ref().join(format!("{id}.sst")))
+            .unwrap(),
+    )
+}
+
+#[test]
+fn test_compaction_allocations() {
+    let dir = tempdir().unwrap();
+    let sst1 = build_sst(&dir, 1, (0..NUM_OF_KEYS).step_by(2));
+    let sst2 = build_sst(&dir, 2, 0..NUM_OF_KEYS);
+
+    let (sst, allocations) = count_allocations(|| {
+        let iters = vec![
+            Box::new(SsTableIterator::create_and_seek_to_first(sst1.clone()).unwrap()),
+            Box::new(SsTableIterator::create_and_seek_to_first(sst2.clone()).unwrap()),
+        ];
+        let mut iter = MergeIterator::create(iters);
+        let mut builder = SsTableBuilder::new(4096);
+        builder
+            .add_sorted_entries(&mut iter, usize::MAX, false)
+            .unwrap();
+        builder.build(3, None, dir.path().join("3.sst")).unwrap()
+    });
+    let num_of_blocks = sst.num_of_blocks() + sst1.num_of_blocks() + sst2.num_of_blocks();
+    println!("{allocations} allocations for {NUM_OF_KEYS} keys in {num_of_blocks} blocks");
+    // Only a constant number of allocations per block, and none per entry
+    assert!(
+        allocations < num_of_blocks * 16,
+        "too many allocations: {allocations}"
+    );
+}
+
+#[test]
+fn test_block_seek_allocations() {
+    let mut builder = BlockBuilder::new(65536);
+    for idx in 0..1000 {
+        let key = format!("key_{:05}", idx * 2);
+        assert!(builder.add(
+            KeySlice::for_testing_from_slice_no_ts(key.as_bytes()),
+            b"value"
+        ));
+    }
+    let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(builder.build()));
+    let keys = (0..2000)
+        .map(|idx| format!("key_{:05}", idx))
+        .collect::<Vec<_>>();
+    let (_, allocations) = count_allocations(|| {
+        for key in &keys {
+            iter.seek_to_key(KeySlice::for_testing_from_slice_no_ts(key.as_bytes()));
+            assert!(iter.is_valid() || key.as_str() > "key_01998");
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_wal_batch_allocations() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("0.wal");
+    let wal = Wal::create(&path).unwrap();
+    let value = vec![b'v'; 256 << 10];
+    let keys = (0..4).map(|idx| format!("key_{idx}")).collect::<Vec<_>>();
+    let records = keys
+        .iter()
+        .map(|key| (key.as_bytes(), Some(value.as_slice())))
+        .chain([(b"deleted".as_slice(), None)])
+        .collect::<Vec<_>>();
+    let (_, bytes) = count_allocated_bytes(|| wal.write_batch(&records, 1).unwrap());
+    // Only the headers of the records are encoded, the keys and values are written in place
+    assert!(bytes < 4096, "too many bytes allocated: {bytes}");
+    assert!(std::fs::metadata(&path).unwrap().len() > 4 * value.len() as u64);
+}
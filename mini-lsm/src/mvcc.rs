@@ -1 +0,0 @@
-../../mini-lsm-starter/src/mvcc.rs
\ No newline at end of file
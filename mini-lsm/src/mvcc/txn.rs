@@ -1 +0,0 @@
-../../../mini-lsm-starter/src/mvcc/txn.rs
\ No newline at end of file
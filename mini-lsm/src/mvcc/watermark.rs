@@ -1 +0,0 @@
-../../../mini-lsm-starter/src/mvcc/watermark.rs
\ No newline at end of file